@@ -0,0 +1,351 @@
+//! macOS Accessibility (AX) queries against the focused text element.
+//!
+//! Used to read the text surrounding the caret in whatever app the user is
+//! dictating into, so post-processing can make the transcript fit in, and
+//! to find where the caret and the focused window are so the pill can
+//! appear next to them.
+//!
+//! # Architecture Decision
+//!
+//! AX calls are synchronous IPC into the target application and can hang when
+//! that app is busy. Every query therefore runs on a worker thread with a hard
+//! deadline, and a missing/late answer is treated the same as "no AX access".
+//! Callers must always have a context-free fallback.
+//!
+//! # Platform Support
+//!
+//! macOS only. Other platforms report no context.
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::pill::placement::Rect;
+use crate::postprocess::context::TextContext;
+
+/// Maximum number of characters of surrounding text captured on each side of
+/// the caret. Only the immediate neighborhood matters for formatting.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+const CONTEXT_CHARS: usize = 200;
+
+/// Reads the text around the caret of the focused element, giving up after
+/// `budget`.
+///
+/// Returns `None` when Accessibility permission is missing, the focused
+/// element isn't a text field, or the target app doesn't answer in time.
+pub fn focused_text_context(budget: Duration) -> Option<TextContext> {
+    with_deadline(budget, read_focused_text_context)
+}
+
+/// Returns the frame of the frontmost app's focused window in global screen
+/// points (origin at the top left of the primary display), giving up after
+/// `budget`.
+pub fn focused_window_frame(budget: Duration) -> Option<Rect> {
+    with_deadline(budget, read_focused_window_frame)
+}
+
+/// Returns the on-screen bounds of the caret (or selection) in the focused
+/// text element, in global screen points, giving up after `budget`.
+///
+/// Returns `None` when Accessibility permission is missing, nothing editable
+/// has focus, or the app doesn't report text bounds.
+pub fn focused_caret_bounds(budget: Duration) -> Option<Rect> {
+    with_deadline(budget, read_focused_caret_bounds)
+}
+
+/// Whether the app has Accessibility permission, which pasting and reading
+/// the focused field need.
+#[cfg(target_os = "macos")]
+pub fn is_trusted() -> bool {
+    macos::is_trusted()
+}
+
+/// Whether the app has Accessibility permission. Always `false` off macOS.
+#[cfg(not(target_os = "macos"))]
+pub fn is_trusted() -> bool {
+    false
+}
+
+/// Runs `query` on a worker thread and waits at most `budget` for it.
+fn with_deadline<T: Send + 'static>(
+    budget: Duration,
+    query: fn(Duration) -> Option<T>,
+) -> Option<T> {
+    let (tx, rx) = mpsc::channel();
+
+    // The worker may outlive the deadline if the target app hangs; its result
+    // is simply dropped in that case
+    std::thread::spawn(move || {
+        let _ = tx.send(query(budget));
+    });
+
+    rx.recv_timeout(budget).ok().flatten()
+}
+
+#[cfg(target_os = "macos")]
+fn read_focused_text_context(budget: Duration) -> Option<TextContext> {
+    macos::read_focused_text_context(budget)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn read_focused_text_context(_budget: Duration) -> Option<TextContext> {
+    None
+}
+
+#[cfg(target_os = "macos")]
+fn read_focused_caret_bounds(budget: Duration) -> Option<Rect> {
+    macos::read_focused_caret_bounds(budget)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn read_focused_caret_bounds(_budget: Duration) -> Option<Rect> {
+    None
+}
+
+#[cfg(target_os = "macos")]
+fn read_focused_window_frame(budget: Duration) -> Option<Rect> {
+    macos::read_focused_window_frame(budget)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn read_focused_window_frame(_budget: Duration) -> Option<Rect> {
+    None
+}
+
+/// Splits `value` around a caret selection expressed in UTF-16 code units (as
+/// AX reports it), keeping at most [`CONTEXT_CHARS`] on each side.
+///
+/// Selected text is excluded from both sides because the paste replaces it.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn split_at_utf16_range(value: &str, location: usize, length: usize) -> Option<TextContext> {
+    let to_byte_index = |utf16_offset: usize| {
+        let mut units = 0;
+        for (index, c) in value.char_indices() {
+            if units >= utf16_offset {
+                return Some(index);
+            }
+            units += c.len_utf16();
+        }
+        (units >= utf16_offset).then_some(value.len())
+    };
+
+    let start = to_byte_index(location)?;
+    let end = to_byte_index(location + length)?;
+
+    let before: String = {
+        let chars: Vec<char> = value[..start].chars().rev().take(CONTEXT_CHARS).collect();
+        chars.into_iter().rev().collect()
+    };
+    let after: String = value[end..].chars().take(CONTEXT_CHARS).collect();
+
+    Some(TextContext { before, after })
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::ffi::c_void;
+    use std::time::Duration;
+
+    use core_foundation::base::{CFGetTypeID, CFRelease, CFTypeRef, TCFType};
+    use core_foundation::string::{CFString, CFStringRef};
+
+    use super::split_at_utf16_range;
+    use crate::pill::placement::Rect;
+    use crate::postprocess::context::TextContext;
+
+    type AXUIElementRef = CFTypeRef;
+    type AXError = i32;
+
+    const K_AX_ERROR_SUCCESS: AXError = 0;
+    const K_AX_VALUE_CG_POINT_TYPE: u32 = 1;
+    const K_AX_VALUE_CG_SIZE_TYPE: u32 = 2;
+    const K_AX_VALUE_CG_RECT_TYPE: u32 = 3;
+    const K_AX_VALUE_CF_RANGE_TYPE: u32 = 4;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CFRange {
+        location: isize,
+        length: isize,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CGPoint {
+        x: f64,
+        y: f64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CGSize {
+        width: f64,
+        height: f64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CGRect {
+        origin: CGPoint,
+        size: CGSize,
+    }
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXIsProcessTrusted() -> bool;
+        fn AXUIElementCreateSystemWide() -> AXUIElementRef;
+        fn AXUIElementSetMessagingTimeout(element: AXUIElementRef, timeout: f32) -> AXError;
+        fn AXUIElementCopyAttributeValue(
+            element: AXUIElementRef,
+            attribute: CFStringRef,
+            value: *mut CFTypeRef,
+        ) -> AXError;
+        fn AXUIElementCopyParameterizedAttributeValue(
+            element: AXUIElementRef,
+            attribute: CFStringRef,
+            parameter: CFTypeRef,
+            value: *mut CFTypeRef,
+        ) -> AXError;
+        fn AXValueGetValue(value: CFTypeRef, value_type: u32, value_ptr: *mut c_void) -> bool;
+    }
+
+    pub fn is_trusted() -> bool {
+        // SAFETY: plain C call without preconditions
+        unsafe { AXIsProcessTrusted() }
+    }
+
+    /// Owned CF object released on drop.
+    struct Owned(CFTypeRef);
+
+    impl Drop for Owned {
+        fn drop(&mut self) {
+            // SAFETY: every `Owned` comes from a Create/Copy function, so we
+            // hold exactly one reference to release
+            unsafe { CFRelease(self.0) }
+        }
+    }
+
+    fn copy_attribute(element: CFTypeRef, name: &str) -> Option<Owned> {
+        let attribute = CFString::new(name);
+        let mut value: CFTypeRef = std::ptr::null();
+        // SAFETY: `element` is a valid AXUIElement and `value` is a valid out
+        // pointer; on success we own the returned reference
+        let error = unsafe {
+            AXUIElementCopyAttributeValue(element, attribute.as_concrete_TypeRef(), &mut value)
+        };
+        (error == K_AX_ERROR_SUCCESS && !value.is_null()).then(|| Owned(value))
+    }
+
+    fn copy_parameterized_attribute(
+        element: CFTypeRef,
+        name: &str,
+        parameter: &Owned,
+    ) -> Option<Owned> {
+        let attribute = CFString::new(name);
+        let mut value: CFTypeRef = std::ptr::null();
+        // SAFETY: as in `copy_attribute`; `parameter` is a live CF object
+        let error = unsafe {
+            AXUIElementCopyParameterizedAttributeValue(
+                element,
+                attribute.as_concrete_TypeRef(),
+                parameter.0,
+                &mut value,
+            )
+        };
+        (error == K_AX_ERROR_SUCCESS && !value.is_null()).then(|| Owned(value))
+    }
+
+    /// Reads an `AXValue` of `value_type` into a `T` of the matching layout.
+    fn value_of<T: Default>(value: &Owned, value_type: u32) -> Option<T> {
+        let mut out = T::default();
+        // SAFETY: callers pair each value type with the struct of its layout
+        let ok = unsafe { AXValueGetValue(value.0, value_type, &mut out as *mut T as *mut c_void) };
+        ok.then_some(out)
+    }
+
+    /// The system-wide element, or `None` without Accessibility permission.
+    fn system_wide() -> Option<Owned> {
+        // SAFETY: plain C calls without preconditions
+        if !unsafe { AXIsProcessTrusted() } {
+            return None;
+        }
+
+        // SAFETY: returns a new reference which `Owned` releases
+        Some(Owned(unsafe { AXUIElementCreateSystemWide() }))
+    }
+
+    /// The element in attribute `name` of `element`, with a messaging
+    /// timeout of `budget` so a slow app fails fast instead of hanging the
+    /// worker. Set on the target element only: set on the system-wide one,
+    /// it would apply to every app we talk to, for the life of the process.
+    fn copy_element(element: CFTypeRef, name: &str, budget: Duration) -> Option<Owned> {
+        let target = copy_attribute(element, name)?;
+        // SAFETY: `target.0` is a valid AXUIElement we own
+        unsafe { AXUIElementSetMessagingTimeout(target.0, budget.as_secs_f32()) };
+        Some(target)
+    }
+
+    pub(super) fn read_focused_window_frame(budget: Duration) -> Option<Rect> {
+        let system = system_wide()?;
+        let app = copy_element(system.0, "AXFocusedApplication", budget)?;
+        let window = copy_element(app.0, "AXFocusedWindow", budget)?;
+        let position: CGPoint = value_of(
+            &copy_attribute(window.0, "AXPosition")?,
+            K_AX_VALUE_CG_POINT_TYPE,
+        )?;
+        let size: CGSize = value_of(
+            &copy_attribute(window.0, "AXSize")?,
+            K_AX_VALUE_CG_SIZE_TYPE,
+        )?;
+        Some(Rect {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+        })
+    }
+
+    pub(super) fn read_focused_caret_bounds(budget: Duration) -> Option<Rect> {
+        let system = system_wide()?;
+        let focused = copy_element(system.0, "AXFocusedUIElement", budget)?;
+        let range = copy_attribute(focused.0, "AXSelectedTextRange")?;
+        let bounds = copy_parameterized_attribute(focused.0, "AXBoundsForRange", &range)?;
+        let rect: CGRect = value_of(&bounds, K_AX_VALUE_CG_RECT_TYPE)?;
+
+        // Some apps answer with an empty rect at the origin instead of an
+        // error
+        if rect.origin.x == 0.0 && rect.origin.y == 0.0 && rect.size.height == 0.0 {
+            return None;
+        }
+        Some(Rect {
+            x: rect.origin.x,
+            y: rect.origin.y,
+            width: rect.size.width,
+            height: rect.size.height,
+        })
+    }
+
+    pub(super) fn read_focused_text_context(budget: Duration) -> Option<TextContext> {
+        let system = system_wide()?;
+        let focused = copy_element(system.0, "AXFocusedUIElement", budget)?;
+        let value = copy_attribute(focused.0, "AXValue")?;
+        let range_value = copy_attribute(focused.0, "AXSelectedTextRange")?;
+
+        // SAFETY: both calls only inspect the CF objects we own
+        if unsafe { CFGetTypeID(value.0) } != CFString::type_id() {
+            return None;
+        }
+        let text = {
+            // SAFETY: type checked above; `wrap_under_get_rule` retains, so
+            // `value` keeps its own reference to release
+            let string = unsafe { CFString::wrap_under_get_rule(value.0 as CFStringRef) };
+            string.to_string()
+        };
+
+        let range: CFRange = value_of(&range_value, K_AX_VALUE_CF_RANGE_TYPE)?;
+        if range.location < 0 || range.length < 0 {
+            return None;
+        }
+
+        split_at_utf16_range(&text, range.location as usize, range.length as usize)
+    }
+}
@@ -0,0 +1,401 @@
+//! Live transcript stream for captions (`GET /live`, a WebSocket).
+//!
+//! Every connected client receives the transcript of the dictation in
+//! progress as it changes, as JSON [`LiveMessage`]s. The same messages are
+//! emitted to the app's own windows as `transcript:live` events, so there is
+//! one schema for both.
+//!
+//! A client first gets a [`LiveMessage::Snapshot`] of the current session,
+//! then `session_started`, `transcript` and `session_ended` messages. The
+//! text of a `transcript` message is the whole transcript so far; `stable`
+//! tells whether it is final or a partial that may still change. Sessions
+//! are identified by the dictation's session id (see [`crate::app_state`]),
+//! the same as in `state:changed` and history, and transcripts published
+//! for another dictation than the current one are dropped.
+//!
+//! # Architecture Decision
+//!
+//! Slow clients must never stall dictation or grow memory without bound, but
+//! must never miss final text either. Each client has its own queue in which
+//! a newer partial replaces a queued one and a final drops the partial it
+//! supersedes, so a client that reads slowly skips intermediate partials and
+//! still sees every final, in order.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+use tokio::sync::Notify;
+
+use crate::AppHandle;
+
+/// A transcript update within a session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LiveTranscript {
+    pub session_id: String,
+    /// Everything transcribed in this session so far.
+    pub text: String,
+    /// `true` when `text` is final, `false` for a partial that may change.
+    pub stable: bool,
+}
+
+/// A message on the live stream (and payload of `transcript:live` events).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveMessage {
+    /// First message after connecting: the session in progress, if any, and
+    /// its latest transcript.
+    Snapshot {
+        session_id: Option<String>,
+        text: String,
+        stable: bool,
+    },
+    /// A dictation started.
+    SessionStarted {
+        session_id: String,
+    },
+    Transcript(LiveTranscript),
+    /// The dictation was pasted or cancelled.
+    SessionEnded {
+        session_id: String,
+    },
+}
+
+impl LiveMessage {
+    fn is_partial(&self) -> bool {
+        matches!(self, Self::Transcript(transcript) if !transcript.stable)
+    }
+}
+
+/// Messages waiting to be written to one client.
+#[derive(Default)]
+struct ClientQueue {
+    /// Messages that are always delivered, in order.
+    messages: VecDeque<LiveMessage>,
+    /// The newest partial, sent after `messages`.
+    partial: Option<LiveMessage>,
+}
+
+/// One connected client.
+#[derive(Default)]
+struct Client {
+    queue: Mutex<ClientQueue>,
+    ready: Notify,
+}
+
+impl Client {
+    fn push(&self, message: LiveMessage) {
+        let mut queue = self.queue.lock().unwrap();
+        if message.is_partial() {
+            queue.partial = Some(message);
+        } else {
+            // A final or a session change supersedes any pending partial
+            queue.partial = None;
+            queue.messages.push_back(message);
+        }
+        drop(queue);
+        self.ready.notify_one();
+    }
+
+    fn drain(&self) -> Vec<LiveMessage> {
+        let mut queue = self.queue.lock().unwrap();
+        let partial = queue.partial.take();
+        queue.messages.drain(..).chain(partial).collect()
+    }
+}
+
+/// The session being dictated, as last published.
+#[derive(Default)]
+struct Session {
+    current: Option<LiveTranscript>,
+}
+
+impl Session {
+    /// Starts the session `session_id`, returning the messages announcing
+    /// it: the end of the previous session, if any, then its start.
+    fn begin(&mut self, session_id: &str) -> Vec<LiveMessage> {
+        let mut messages: Vec<LiveMessage> = self.end().into_iter().collect();
+        self.current = Some(LiveTranscript {
+            session_id: session_id.to_string(),
+            text: String::new(),
+            stable: true,
+        });
+        messages.push(LiveMessage::SessionStarted {
+            session_id: session_id.to_string(),
+        });
+        messages
+    }
+
+    /// Ends the current session, returning the message announcing it.
+    fn end(&mut self) -> Option<LiveMessage> {
+        let ended = self.current.take()?;
+        Some(LiveMessage::SessionEnded {
+            session_id: ended.session_id,
+        })
+    }
+
+    /// Sets the transcript of the session `session_id`, returning the
+    /// message announcing it; `None` when it isn't the current session.
+    fn update(&mut self, session_id: &str, text: String, stable: bool) -> Option<LiveMessage> {
+        let current = self
+            .current
+            .as_mut()
+            .filter(|current| current.session_id == session_id)?;
+        current.text = text;
+        current.stable = stable;
+        Some(LiveMessage::Transcript(current.clone()))
+    }
+
+    fn snapshot(&self) -> LiveMessage {
+        match &self.current {
+            Some(current) => LiveMessage::Snapshot {
+                session_id: Some(current.session_id.clone()),
+                text: current.text.clone(),
+                stable: current.stable,
+            },
+            None => LiveMessage::Snapshot {
+                session_id: None,
+                text: String::new(),
+                stable: true,
+            },
+        }
+    }
+}
+
+/// Managed state fanning transcript updates out to live clients.
+#[derive(Default)]
+pub struct LiveHub {
+    session: Mutex<Session>,
+    clients: Mutex<Vec<Arc<Client>>>,
+}
+
+impl LiveHub {
+    /// Starts the session of the dictation `session_id` (ending any
+    /// previous one).
+    pub fn begin_session(&self, app: &AppHandle, session_id: &str) {
+        let messages = self.session.lock().unwrap().begin(session_id);
+        for message in messages {
+            self.publish(app, message);
+        }
+    }
+
+    /// Ends the current session, if any.
+    pub fn end_session(&self, app: &AppHandle) {
+        let ended = self.session.lock().unwrap().end();
+        if let Some(ended) = ended {
+            self.publish(app, ended);
+        }
+    }
+
+    /// Publishes the transcript of the dictation `session_id`. Ignored
+    /// unless it is the current session.
+    pub fn update(&self, app: &AppHandle, session_id: &str, text: String, stable: bool) {
+        let message = self
+            .session
+            .lock()
+            .unwrap()
+            .update(session_id, text, stable);
+        match message {
+            Some(message) => self.publish(app, message),
+            None => tracing::debug!(session_id, "Ignoring the transcript of another dictation"),
+        }
+    }
+
+    fn publish(&self, app: &AppHandle, message: LiveMessage) {
+        for client in self.clients.lock().unwrap().iter() {
+            client.push(message.clone());
+        }
+        let _ = app.emit("transcript:live", message);
+    }
+
+    fn subscribe(&self) -> Arc<Client> {
+        let client = Arc::new(Client::default());
+        let snapshot = self.session.lock().unwrap().snapshot();
+        client.push(snapshot);
+        self.clients.lock().unwrap().push(client.clone());
+        client
+    }
+
+    fn unsubscribe(&self, client: &Arc<Client>) {
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|other| !Arc::ptr_eq(other, client));
+    }
+}
+
+/// `GET /live`: upgrades to a WebSocket streaming [`LiveMessage`]s. The
+/// token is checked before the upgrade, so a client without it never gets a
+/// socket.
+pub(super) async fn live(State(app): State<AppHandle>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| stream(app, socket))
+}
+
+async fn stream(app: AppHandle, mut socket: WebSocket) {
+    let hub = app.state::<LiveHub>();
+    let client = hub.subscribe();
+
+    'connection: loop {
+        tokio::select! {
+            () = client.ready.notified() => {
+                for message in client.drain() {
+                    let Ok(json) = serde_json::to_string(&message) else {
+                        continue;
+                    };
+                    if socket.send(Message::Text(json)).await.is_err() {
+                        break 'connection;
+                    }
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                // Clients have nothing to say; pings are answered by axum
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    hub.unsubscribe(&client);
+}
+
+/// Publishes the transcript of the dictation `session_id` to live clients.
+///
+/// Called by the frontend for every partial (`stable: false`) and final
+/// (`stable: true`) transcript it receives. Transcripts of a dictation that
+/// ended are dropped.
+#[tauri::command]
+pub fn publish_live_transcript(app: AppHandle, session_id: String, text: String, stable: bool) {
+    app.state::<LiveHub>()
+        .update(&app, &session_id, text, stable);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript(session_id: &str, text: &str, stable: bool) -> LiveMessage {
+        LiveMessage::Transcript(LiveTranscript {
+            session_id: session_id.to_string(),
+            text: text.to_string(),
+            stable,
+        })
+    }
+
+    const FIRST: &str = "5b0a4f8e-6c1d-4a57-9d1e-2f3b8c7a9e10";
+    const SECOND: &str = "0c6e2d4a-1b3f-4e8d-a9c7-5f2e1d0b3a64";
+
+    #[test]
+    fn sessions_carry_the_dictation_id() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.begin(FIRST),
+            [LiveMessage::SessionStarted {
+                session_id: FIRST.to_string()
+            }]
+        );
+        assert_eq!(
+            session.update(FIRST, "Hello".to_string(), false),
+            Some(transcript(FIRST, "Hello", false))
+        );
+        assert_eq!(
+            session.end(),
+            Some(LiveMessage::SessionEnded {
+                session_id: FIRST.to_string()
+            })
+        );
+        assert_eq!(session.end(), None);
+    }
+
+    #[test]
+    fn starting_a_session_ends_the_previous_one() {
+        let mut session = Session::default();
+        session.begin(FIRST);
+        assert_eq!(
+            session.begin(SECOND),
+            [
+                LiveMessage::SessionEnded {
+                    session_id: FIRST.to_string()
+                },
+                LiveMessage::SessionStarted {
+                    session_id: SECOND.to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn transcripts_of_a_stale_session_are_dropped() {
+        let mut session = Session::default();
+        session.begin(FIRST);
+        session.update(FIRST, "First".to_string(), true);
+        session.begin(SECOND);
+        // A late partial of the cancelled dictation
+        assert_eq!(
+            session.update(FIRST, "First, again".to_string(), false),
+            None
+        );
+        assert_eq!(
+            session.snapshot(),
+            LiveMessage::Snapshot {
+                session_id: Some(SECOND.to_string()),
+                text: String::new(),
+                stable: true,
+            }
+        );
+
+        session.end();
+        assert_eq!(session.update(SECOND, "Late".to_string(), true), None);
+        assert_eq!(
+            session.snapshot(),
+            LiveMessage::Snapshot {
+                session_id: None,
+                text: String::new(),
+                stable: true,
+            }
+        );
+    }
+
+    #[test]
+    fn messages_use_the_session_id_string() {
+        let json = serde_json::to_value(transcript(FIRST, "Hi", true)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "transcript",
+                "session_id": FIRST,
+                "text": "Hi",
+                "stable": true,
+            })
+        );
+        let json = serde_json::to_value(LiveMessage::SessionEnded {
+            session_id: FIRST.to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "type": "session_ended", "session_id": FIRST })
+        );
+    }
+
+    #[test]
+    fn slow_clients_skip_partials_but_not_finals() {
+        let client = Client::default();
+        client.push(transcript(FIRST, "He", false));
+        client.push(transcript(FIRST, "Hello", false));
+        client.push(transcript(FIRST, "Hello.", true));
+        client.push(transcript(FIRST, "Hello. W", false));
+        client.push(transcript(FIRST, "Hello. Wor", false));
+        assert_eq!(
+            client.drain(),
+            [
+                transcript(FIRST, "Hello.", true),
+                transcript(FIRST, "Hello. Wor", false),
+            ]
+        );
+        assert!(client.drain().is_empty());
+    }
+}
@@ -0,0 +1,425 @@
+//! Local HTTP API for scripts and Stream Deck plugins.
+//!
+//! An optional server on `127.0.0.1` exposes the core actions:
+//!
+//! - `POST /record/start`: start a dictation (like pressing the hotkey)
+//! - `POST /record/stop`: finish the dictation in progress and paste it
+//! - `GET /history/latest`: the most recent history entry, as JSON
+//! - `GET /status`: whether a dictation is in progress
+//! - `GET /live`: WebSocket streaming the live transcript (see [`live`])
+//!
+//! Every request must carry the per-install token as `Authorization: Bearer
+//! <token>`. The token is generated the first time the server is enabled and
+//! kept in the keychain (see [`crate::secrets`]), never in the settings, so
+//! settings files, bundles and diagnostics can't leak it; [`get_api_token`]
+//! reads it for the user. The server starts, stops and moves ports as the
+//! `api` settings change.
+//!
+//! # Architecture Decision
+//!
+//! The listener is always bound to the loopback address; there is no setting
+//! for the interface, so the API can't be exposed to the network by
+//! accident. The token still matters on loopback: any local process, and any
+//! web page through the browser, can reach `127.0.0.1`. It is only accepted
+//! in the header: a query parameter ends up in logs and browser history.
+//!
+//! # Submodules
+//!
+//! - [`live`]: Live transcript WebSocket for captions
+
+pub mod live;
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use tokio::sync::oneshot;
+
+use crate::dictation::DictationState;
+use crate::external::{self, ExternalAction};
+use crate::history::HistoryState;
+use crate::secrets;
+use crate::AppHandle;
+
+/// Lowest port the server may use; lower ones need elevated privileges.
+const MIN_PORT: u16 = 1024;
+
+/// Attempts to bind the port, for restarts where the previous listener is
+/// still shutting down.
+const BIND_ATTEMPTS: u32 = 5;
+const BIND_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Configuration of the local API server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiSettings {
+    /// Whether the server runs.
+    pub enabled: bool,
+    /// Port on `127.0.0.1`.
+    pub port: u16,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 47_823,
+        }
+    }
+}
+
+impl ApiSettings {
+    /// Validates a configuration supplied by the frontend.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.port < MIN_PORT {
+            return Err(format!("port must be between {} and 65535", MIN_PORT));
+        }
+        Ok(())
+    }
+}
+
+/// A server that is running (or binding).
+struct RunningServer {
+    port: u16,
+    shutdown: oneshot::Sender<()>,
+}
+
+/// Managed state holding the running server, if any.
+#[derive(Default)]
+pub struct ApiState {
+    server: Mutex<Option<RunningServer>>,
+}
+
+/// Body of `GET /status`.
+#[derive(Debug, Clone, Serialize)]
+struct Status {
+    version: String,
+    /// A dictation has started and not been pasted yet.
+    dictating: bool,
+    /// The history database is open.
+    history_available: bool,
+}
+
+/// Body of every error response.
+#[derive(Debug, Clone, Serialize)]
+struct ApiError {
+    error: String,
+}
+
+/// Payload of the `api:error` event.
+#[derive(Debug, Clone, Serialize)]
+struct ServerError {
+    port: u16,
+    message: String,
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(ApiError {
+            error: message.into(),
+        }),
+    )
+        .into_response()
+}
+
+/// Where the token every request must present is read from, for each
+/// request: the keychain, or a fixed token in tests. `None` when there is
+/// none, which rejects every request.
+type TokenSource = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+
+/// Starts, stops or moves the server to match `settings`.
+pub fn apply(app: &AppHandle, settings: &ApiSettings) {
+    if settings.enabled {
+        ensure_token();
+    }
+
+    let state = app.state::<ApiState>();
+    let mut server = state.server.lock().unwrap();
+    let wanted = settings.enabled.then_some(settings.port);
+    if server.as_ref().map(|server| server.port) == wanted {
+        return;
+    }
+
+    if let Some(running) = server.take() {
+        let _ = running.shutdown.send(());
+        tracing::info!("Local API stopped");
+    }
+    if let Some(port) = wanted {
+        let (shutdown, stopped) = oneshot::channel();
+        tauri::async_runtime::spawn(serve(app.clone(), port, stopped));
+        *server = Some(RunningServer { port, shutdown });
+    }
+}
+
+/// Stops the server, if it is running.
+pub fn stop(app: &AppHandle) {
+    if let Some(running) = app.state::<ApiState>().server.lock().unwrap().take() {
+        let _ = running.shutdown.send(());
+        tracing::info!("Local API stopped");
+    }
+}
+
+/// Generates and stores a token when there is none yet.
+fn ensure_token() {
+    let result = secrets::get(secrets::API_TOKEN).and_then(|token| match token {
+        Some(_) => Ok(()),
+        None => secrets::set(secrets::API_TOKEN, &generate_token()?),
+    });
+    if let Err(e) = result {
+        tracing::warn!("Failed to create local API token: {}", e);
+    }
+}
+
+/// Reads the token from the keychain.
+fn keychain_token() -> Option<String> {
+    secrets::get(secrets::API_TOKEN).unwrap_or_else(|e| {
+        tracing::warn!("Failed to read the local API token: {}", e);
+        None
+    })
+}
+
+/// Binds `127.0.0.1:port` and serves until `stopped` fires.
+async fn serve(app: AppHandle, port: u16, stopped: oneshot::Receiver<()>) {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+
+    let mut attempt = 1;
+    let listener = loop {
+        match tokio::net::TcpListener::bind(address).await {
+            Ok(listener) => break listener,
+            Err(_) if attempt < BIND_ATTEMPTS => {
+                attempt += 1;
+                tokio::time::sleep(BIND_RETRY_DELAY).await;
+            }
+            Err(e) => {
+                tracing::warn!("Local API could not listen on {}: {}", address, e);
+                let _ = app.emit(
+                    "api:error",
+                    ServerError {
+                        port,
+                        message: e.to_string(),
+                    },
+                );
+                return;
+            }
+        }
+    };
+
+    tracing::info!("Local API listening on http://{}", address);
+    let result = axum::serve(listener, router(app))
+        .with_graceful_shutdown(async {
+            let _ = stopped.await;
+        })
+        .await;
+    if let Err(e) = result {
+        tracing::warn!("Local API stopped: {}", e);
+    }
+}
+
+fn router(app: AppHandle) -> Router {
+    let token: TokenSource = Arc::new(keychain_token);
+    Router::new()
+        .route("/record/start", post(record_start))
+        .route("/record/stop", post(record_stop))
+        .route("/history/latest", get(history_latest))
+        .route("/status", get(status))
+        .route("/live", get(live::live))
+        .layer(middleware::from_fn_with_state(token, require_token))
+        .with_state(app)
+}
+
+/// Rejects requests without the current token.
+async fn require_token(State(token): State<TokenSource>, request: Request, next: Next) -> Response {
+    // The keychain can block
+    let expected = tauri::async_runtime::spawn_blocking(move || token())
+        .await
+        .ok()
+        .flatten();
+    match authorize(request.headers(), expected.as_deref()) {
+        Ok(()) => next.run(request).await,
+        Err(message) => error(StatusCode::UNAUTHORIZED, message),
+    }
+}
+
+/// Checks the bearer token in `headers` against `expected`.
+fn authorize(headers: &HeaderMap, expected: Option<&str>) -> Result<(), &'static str> {
+    match (bearer_token(headers), expected) {
+        (None, _) => Err("missing token"),
+        (Some(token), Some(expected))
+            if !expected.is_empty() && constant_time_eq(&token, expected) =>
+        {
+            Ok(())
+        }
+        (Some(_), _) => Err("invalid token"),
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_string())
+}
+
+/// Compares without leaking the position of the first difference through
+/// timing.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+async fn record_start(State(app): State<AppHandle>) -> Response {
+    external::dispatch(&app, ExternalAction::Start);
+    StatusCode::ACCEPTED.into_response()
+}
+
+async fn record_stop(State(app): State<AppHandle>) -> Response {
+    external::dispatch(&app, ExternalAction::Stop);
+    StatusCode::ACCEPTED.into_response()
+}
+
+async fn history_latest(State(app): State<AppHandle>) -> Response {
+    match app.state::<HistoryState>().latest() {
+        Ok(Some(item)) => Json(item).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, "no transcripts yet"),
+        Err(e) => error(StatusCode::SERVICE_UNAVAILABLE, e),
+    }
+}
+
+async fn status(State(app): State<AppHandle>) -> Json<Status> {
+    let dictating = app.state::<DictationState>().0.lock().unwrap().is_some();
+    let history_available = app.state::<HistoryState>().latest().is_ok();
+    Json(Status {
+        version: app.package_info().version.to_string(),
+        dictating,
+        history_available,
+    })
+}
+
+fn generate_token() -> Result<String, String> {
+    let mut bytes = [0u8; 24];
+    getrandom::getrandom(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Returns the API token, or `None` before the server was first enabled.
+///
+/// # Errors
+///
+/// Returns an error if the keychain can't be read.
+#[tauri::command]
+pub fn get_api_token() -> Result<Option<String>, String> {
+    secrets::get(secrets::API_TOKEN)
+}
+
+/// Replaces the API token, invalidating the old one, and returns the new
+/// one.
+///
+/// # Errors
+///
+/// Returns an error if no random token can be generated or the keychain
+/// rejects it.
+#[tauri::command]
+pub fn regenerate_api_token() -> Result<String, String> {
+    let token = generate_token()?;
+    secrets::set(secrets::API_TOKEN, &token)?;
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let value = HeaderValue::from_str(&format!("Bearer {}", token)).unwrap();
+        headers.insert(header::AUTHORIZATION, value);
+        headers
+    }
+
+    #[test]
+    fn authorize_checks_the_bearer_token() {
+        assert_eq!(authorize(&bearer("secret"), Some("secret")), Ok(()));
+        assert_eq!(
+            authorize(&bearer("wrong"), Some("secret")),
+            Err("invalid token")
+        );
+        assert_eq!(
+            authorize(&HeaderMap::new(), Some("secret")),
+            Err("missing token")
+        );
+    }
+
+    #[test]
+    fn authorize_rejects_everything_without_a_token() {
+        assert_eq!(authorize(&bearer("secret"), None), Err("invalid token"));
+        assert_eq!(authorize(&bearer(""), Some("")), Err("invalid token"));
+    }
+
+    #[test]
+    fn generated_tokens_differ() {
+        let token = generate_token().unwrap();
+        assert_eq!(token.len(), 48);
+        assert_ne!(token, generate_token().unwrap());
+    }
+
+    /// Serves `/status` behind [`require_token`], expecting `expected`, and
+    /// returns the status of a request to `path` with `token` as bearer.
+    fn request(expected: Option<&'static str>, path: &str, token: Option<&str>) -> StatusCode {
+        tauri::async_runtime::block_on(async {
+            let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+                .await
+                .unwrap();
+            let address = listener.local_addr().unwrap();
+            let source: TokenSource = Arc::new(move || expected.map(str::to_string));
+            let router = Router::new()
+                .route("/status", get(|| async { "ok" }))
+                .layer(middleware::from_fn_with_state(source, require_token));
+            tauri::async_runtime::spawn(async move { axum::serve(listener, router).await });
+
+            let mut request = reqwest::Client::new().get(format!("http://{}{}", address, path));
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            let status = request.send().await.unwrap().status().as_u16();
+            StatusCode::from_u16(status).unwrap()
+        })
+    }
+
+    #[test]
+    fn server_accepts_the_token() {
+        assert_eq!(
+            request(Some("secret"), "/status", Some("secret")),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn server_rejects_a_missing_or_wrong_token() {
+        let rejected = StatusCode::UNAUTHORIZED;
+        assert_eq!(request(Some("secret"), "/status", None), rejected);
+        assert_eq!(request(Some("secret"), "/status", Some("wrong")), rejected);
+        assert_eq!(request(None, "/status", Some("secret")), rejected);
+    }
+
+    #[test]
+    fn server_ignores_a_token_in_the_query() {
+        let status = request(Some("secret"), "/status?token=secret", None);
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,322 @@
+//! Pasting per app: a different paste keystroke, and what to do with line
+//! breaks.
+//!
+//! Pasting into a terminal with plain Cmd+V types every line break as
+//! Return, so a transcript that spans lines runs its first lines as
+//! commands before the user has read them. Terminals (see [`TERMINALS`])
+//! therefore get their line breaks turned into spaces by default, and
+//! `paste_apps.apps` sets, for any app by bundle id (window class on
+//! Linux), the keystroke that
+//! pastes into it (`Cmd+Shift+V`, in accelerator syntax like voice
+//! commands) and what happens to line breaks ([`Newlines`]). A rule of its
+//! own replaces an app's terminal default completely, so
+//! `{"newlines": "keep"}` turns it off for one terminal, and
+//! `paste_apps.terminal_defaults` turns it off for all of them.
+//!
+//! The rule is picked by [`rule_for`] once focus is back in the app pasted
+//! into, so it is the app the text lands in that decides, even for a
+//! history entry pasted somewhere else than it was dictated.
+//!
+//! # Platform Support
+//!
+//! The app in front is known on macOS and Linux (see [`crate::frontmost`]).
+//! On Linux it is the focused window's class, which X11 apps report in
+//! varying case (`Alacritty`, `kitty`), so [`TERMINALS`] are matched
+//! ignoring case. Where it isn't known, on Windows or a Wayland compositor
+//! that doesn't say, every paste uses the platform keystroke and keeps its
+//! line breaks.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::keystroke::Chord;
+
+/// Bundle ids (and, on Linux, window classes) of the terminals that get
+/// [`Newlines::Space`] unless they have a rule of their own.
+pub const TERMINALS: &[&str] = &[
+    "com.apple.Terminal",
+    "com.googlecode.iterm2",
+    "dev.warp.Warp-Stable",
+    "net.kovidgoyal.kitty",
+    "org.alacritty",
+    "com.github.wez.wezterm",
+    "com.mitchellh.ghostty",
+    "co.zeit.hyper",
+    "org.tabby",
+    // Linux
+    "org.gnome.Terminal",
+    "gnome-terminal",
+    "gnome-terminal-server",
+    "org.gnome.Console",
+    "kgx",
+    "org.kde.konsole",
+    "konsole",
+    "kitty",
+    "alacritty",
+    "foot",
+    "footclient",
+    "org.wezfurlong.wezterm",
+    "xterm",
+    "urxvt",
+    "xfce4-terminal",
+    "terminator",
+    "com.gexperts.Tilix",
+    "tilix",
+    "dev.warp.Warp",
+];
+
+/// What happens to the line breaks of text pasted into an app.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Newlines {
+    /// Pasted as they are.
+    #[default]
+    Keep,
+    /// Each run of them becomes one space; those at the start go, and the
+    /// end is trimmed, so nothing runs before the user presses Return.
+    Space,
+    /// Each one is removed.
+    Strip,
+    /// Each one is preceded by a backslash, which continues the command on
+    /// the next line in a shell instead of running it.
+    Escape,
+}
+
+impl Newlines {
+    /// `text` with its line breaks (`\r\n`, `\n` or `\r`) handled.
+    pub fn apply(self, text: &str) -> String {
+        let normalized = || text.replace("\r\n", "\n").replace('\r', "\n");
+        match self {
+            Newlines::Keep => text.to_string(),
+            Newlines::Space => {
+                let text = normalized();
+                let lines: Vec<&str> = text.split('\n').filter(|line| !line.is_empty()).collect();
+                lines.join(" ").trim_end().to_string()
+            }
+            Newlines::Strip => normalized().replace('\n', ""),
+            Newlines::Escape => normalized().replace('\n', "\\\n"),
+        }
+    }
+}
+
+/// How to paste into one app, as set in `paste_apps.apps`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppPasteRule {
+    /// The chord that pastes, like `Cmd+Shift+V`; the platform's paste
+    /// shortcut if `None`.
+    pub keystroke: Option<String>,
+    pub newlines: Newlines,
+}
+
+/// Per-app paste settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppPasteSettings {
+    /// Paste into [`TERMINALS`] with [`Newlines::Space`].
+    pub terminal_defaults: bool,
+    /// Rules by bundle id, over the terminal defaults.
+    pub apps: BTreeMap<String, AppPasteRule>,
+}
+
+impl Default for AppPasteSettings {
+    fn default() -> Self {
+        Self {
+            terminal_defaults: true,
+            apps: BTreeMap::new(),
+        }
+    }
+}
+
+impl AppPasteSettings {
+    /// Checks that every rule names an app and a keystroke that can be
+    /// pressed.
+    pub fn validate(&self) -> Result<(), String> {
+        for (app, rule) in &self.apps {
+            if app.trim().is_empty() {
+                return Err("apps must not contain empty names".to_string());
+            }
+            if let Some(keystroke) = &rule.keystroke {
+                keystroke
+                    .parse::<Chord>()
+                    .map_err(|e| format!("apps: \"{}\": {}", app, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a paste goes into the app it is for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PasteRule {
+    /// `None` for the platform's paste shortcut.
+    pub keystroke: Option<Chord>,
+    pub newlines: Newlines,
+}
+
+/// The rule for pasting into the app `bundle_id`: its own from
+/// `paste_apps.apps`, else the terminal default if it is a terminal, else
+/// the platform keystroke with the line breaks kept.
+pub fn rule_for(settings: &AppPasteSettings, bundle_id: Option<&str>) -> PasteRule {
+    let Some(bundle_id) = bundle_id else {
+        return PasteRule::default();
+    };
+    if let Some(rule) = settings.apps.get(bundle_id) {
+        return PasteRule {
+            // Checked when the settings were saved
+            keystroke: rule.keystroke.as_deref().and_then(|k| k.parse().ok()),
+            newlines: rule.newlines,
+        };
+    }
+    let terminal = TERMINALS
+        .iter()
+        .any(|terminal| terminal.eq_ignore_ascii_case(bundle_id));
+    if settings.terminal_defaults && terminal {
+        return PasteRule {
+            keystroke: None,
+            newlines: Newlines::Space,
+        };
+    }
+    PasteRule::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(apps: &[(&str, AppPasteRule)]) -> AppPasteSettings {
+        AppPasteSettings {
+            apps: apps
+                .iter()
+                .map(|(app, rule)| (app.to_string(), rule.clone()))
+                .collect(),
+            ..AppPasteSettings::default()
+        }
+    }
+
+    fn space() -> PasteRule {
+        PasteRule {
+            keystroke: None,
+            newlines: Newlines::Space,
+        }
+    }
+
+    #[test]
+    fn terminals_get_spaces_on_every_platform() {
+        let settings = settings(&[]);
+        for terminal in ["com.apple.Terminal", "org.gnome.Terminal", "kitty"] {
+            assert_eq!(rule_for(&settings, Some(terminal)), space(), "{}", terminal);
+        }
+        // X11 classes come in any case
+        for class in ["Alacritty", "XTerm", "Gnome-terminal", "KITTY"] {
+            assert_eq!(rule_for(&settings, Some(class)), space(), "{}", class);
+        }
+        for app in ["com.apple.mail", "firefox", "kitty-launcher"] {
+            assert_eq!(
+                rule_for(&settings, Some(app)),
+                PasteRule::default(),
+                "{}",
+                app
+            );
+        }
+        assert_eq!(rule_for(&settings, None), PasteRule::default());
+    }
+
+    #[test]
+    fn a_rule_of_its_own_replaces_the_terminal_default() {
+        let settings = settings(&[
+            (
+                "com.googlecode.iterm2",
+                AppPasteRule {
+                    keystroke: None,
+                    newlines: Newlines::Keep,
+                },
+            ),
+            (
+                "kitty",
+                AppPasteRule {
+                    keystroke: Some("Ctrl+Shift+V".to_string()),
+                    newlines: Newlines::Escape,
+                },
+            ),
+            (
+                "com.apple.mail",
+                AppPasteRule {
+                    keystroke: None,
+                    newlines: Newlines::Strip,
+                },
+            ),
+        ]);
+
+        assert_eq!(
+            rule_for(&settings, Some("com.googlecode.iterm2")),
+            PasteRule::default()
+        );
+        assert_eq!(
+            rule_for(&settings, Some("kitty")),
+            PasteRule {
+                keystroke: Some("Ctrl+Shift+V".parse().unwrap()),
+                newlines: Newlines::Escape,
+            }
+        );
+        assert_eq!(
+            rule_for(&settings, Some("com.apple.mail")).newlines,
+            Newlines::Strip
+        );
+        // Other terminals keep the default
+        assert_eq!(rule_for(&settings, Some("com.apple.Terminal")), space());
+    }
+
+    #[test]
+    fn terminal_defaults_can_be_turned_off() {
+        let settings = AppPasteSettings {
+            terminal_defaults: false,
+            ..settings(&[])
+        };
+        assert_eq!(
+            rule_for(&settings, Some("com.apple.Terminal")),
+            PasteRule::default()
+        );
+    }
+
+    #[test]
+    fn line_breaks_are_handled_as_set() {
+        let text = "ls -la\r\ncd src\rmake\n";
+        assert_eq!(Newlines::Keep.apply(text), text);
+        assert_eq!(Newlines::Strip.apply(text), "ls -lacd srcmake");
+        assert_eq!(Newlines::Escape.apply(text), "ls -la\\\ncd src\\\nmake\\\n");
+        assert_eq!(Newlines::Space.apply(text), "ls -la cd src make");
+    }
+
+    #[test]
+    fn runs_of_line_breaks_become_one_space() {
+        assert_eq!(Newlines::Space.apply("a\n\nb"), "a b");
+        assert_eq!(Newlines::Space.apply("a\r\n\r\n\r\nb\nc"), "a b c");
+        // Nothing is left at either end to run a command
+        assert_eq!(Newlines::Space.apply("\n\na\nb\n\n"), "a b");
+        assert_eq!(Newlines::Space.apply("echo hi \n"), "echo hi");
+        assert_eq!(Newlines::Space.apply("\n"), "");
+        assert_eq!(Newlines::Space.apply("one line"), "one line");
+    }
+
+    #[test]
+    fn rules_are_checked_when_saved() {
+        assert_eq!(AppPasteSettings::default().validate(), Ok(()));
+        let rule = |keystroke: &str| AppPasteRule {
+            keystroke: Some(keystroke.to_string()),
+            newlines: Newlines::Keep,
+        };
+        assert_eq!(
+            settings(&[("kitty", rule("Ctrl+Shift+V"))]).validate(),
+            Ok(())
+        );
+        assert!(settings(&[("kitty", rule("Ctrl+Shift+Nope"))])
+            .validate()
+            .unwrap_err()
+            .starts_with("apps: \"kitty\": "));
+        assert!(settings(&[(" ", AppPasteRule::default())])
+            .validate()
+            .is_err());
+    }
+}
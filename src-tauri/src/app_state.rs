@@ -0,0 +1,859 @@
+//! The dictation state machine.
+//!
+//! What the app is doing is kept in one place, [`AppState`], instead of
+//! being inferred from the frontend's flags and the pill's visibility. Every
+//! command that starts, advances or ends a dictation asks for a transition
+//! here, which checks it against the transition table and rejects the rest:
+//! the hotkey can't start a recording while a paste is in flight, and a
+//! history entry can't be pasted into the middle of a dictation.
+//!
+//! A dictation goes Idle → Recording → Transcribing → Pasting → Idle.
+//! Besides that:
+//!
+//! - A new recording may start over a cancelled or failed one, and restart a
+//!   recording in progress.
+//! - Recording, transcribing and pasting may fail (`Error`).
+//! - Cancelling (`hide_recording_pill` during a dictation) passes through
+//!   `Cancelled` on its way back to `Idle`, stopping the work still under
+//!   way for the dictation (see [`crate::cancellation`]).
+//! - Pastes that aren't dictations (history entries, recovered transcripts)
+//!   go straight from `Idle` or `Error` to `Pasting`.
+//! - A dictation that must be confirmed before pasting (see
+//!   [`crate::confirm`]) waits in `AwaitingConfirmation` between
+//!   `Transcribing` and `Pasting`, and goes to `Cancelled` if it isn't.
+//!
+//! Presses of the dictation hotkey go through [`press`], which decides from
+//! the phase what a press does, and ignores presses that follow the previous
+//! one within `hotkeys.debounce_ms`: a held or bouncing key would otherwise
+//! start, restart and hide the pill several times in a burst. A press while
+//! recording, pasting or awaiting confirmation is ignored; while
+//! transcribing it is ignored too, unless
+//! `hotkeys.restart_while_transcribing` makes it cancel the dictation and
+//! start a new one.
+//!
+//! Every transition is announced as `state:changed` with the old and new
+//! phase and the reason. `get_app_state` returns the current state, for the
+//! frontend to resync after a reload.
+//!
+//! # Sessions
+//!
+//! Each dictation gets a session id (a UUID) when recording starts. It is
+//! part of the state until the app is idle again, of every recording event,
+//! and of the history entry. Commands about a dictation take its id and are
+//! rejected with [`SessionError::StaleSession`] when it isn't the current
+//! one, so a webview reloaded mid-dictation (or a late retry) can't stop,
+//! cancel or paste the wrong dictation, nor paste one that was cancelled.
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use tauri::{Emitter, Manager};
+
+use crate::clock::Clock;
+use crate::errors::{self, ErrorCode};
+use crate::paste::Progress;
+use crate::settings::HotkeySettings;
+use crate::AppHandle;
+
+/// What the app is doing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Waiting for the hotkey.
+    #[default]
+    Idle,
+    /// The mic is open.
+    Recording,
+    /// The recording ended; the transcript is being processed.
+    Transcribing,
+    /// The transcript is shown, waiting for the user to confirm the paste.
+    AwaitingConfirmation,
+    /// Text is being pasted into the target app.
+    Pasting,
+    /// The user cancelled the dictation.
+    Cancelled,
+    /// A recording, transcription or paste failed.
+    Error,
+}
+
+impl Phase {
+    /// Whether the state machine may go from `self` to `to`.
+    pub fn allows(self, to: Phase) -> bool {
+        use Phase::*;
+        matches!(
+            (self, to),
+            (Idle | Recording | Cancelled | Error, Recording)
+                | (Recording, Transcribing)
+                | (Transcribing, AwaitingConfirmation)
+                | (Idle | Transcribing | AwaitingConfirmation | Error, Pasting)
+                | (Recording | Transcribing | AwaitingConfirmation, Cancelled)
+                | (Recording | Transcribing | Pasting, Error)
+                | (Pasting | Cancelled | Error, Idle)
+        )
+    }
+}
+
+/// The state, managed as `Mutex<AppState>`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AppState {
+    pub phase: Phase,
+    /// Why the app entered `phase`; `None` before the first transition.
+    pub reason: Option<&'static str>,
+    /// The dictation under way; `None` while idle and for pastes that
+    /// aren't dictations.
+    pub session_id: Option<String>,
+    /// When the dictation hotkey was last pressed.
+    #[serde(skip)]
+    pub last_press: Option<Instant>,
+}
+
+/// What a press of the dictation hotkey does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Press {
+    /// Starts a dictation.
+    Start,
+    /// Cancels the dictation being transcribed and starts a new one.
+    Restart,
+    /// Nothing, for `reason` (`debounced`, or the phase).
+    Ignore { reason: &'static str },
+}
+
+/// How presses of the dictation hotkey are handled, from
+/// [`HotkeySettings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PressPolicy {
+    /// Presses this soon after the previous one are ignored.
+    pub debounce: Duration,
+    pub restart_while_transcribing: bool,
+}
+
+impl From<&HotkeySettings> for PressPolicy {
+    fn from(settings: &HotkeySettings) -> Self {
+        Self {
+            debounce: Duration::from_millis(settings.debounce_ms),
+            restart_while_transcribing: settings.restart_while_transcribing,
+        }
+    }
+}
+
+impl AppState {
+    /// What a press of the dictation hotkey at `now` does.
+    pub fn press(&self, now: Instant, policy: PressPolicy) -> Press {
+        if self
+            .last_press
+            .is_some_and(|at| now.saturating_duration_since(at) < policy.debounce)
+        {
+            return Press::Ignore {
+                reason: "debounced",
+            };
+        }
+        match self.phase {
+            Phase::Idle | Phase::Cancelled | Phase::Error => Press::Start,
+            Phase::Transcribing if policy.restart_while_transcribing => Press::Restart,
+            Phase::Recording => Press::Ignore {
+                reason: "recording",
+            },
+            Phase::Transcribing => Press::Ignore {
+                reason: "transcribing",
+            },
+            Phase::AwaitingConfirmation => Press::Ignore {
+                reason: "awaiting_confirmation",
+            },
+            Phase::Pasting => Press::Ignore { reason: "pasting" },
+        }
+    }
+
+    /// Checks the session (when given) and the transition to `to`, and
+    /// applies it, returning the phase it came from: `new_session` replaces
+    /// the session, going idle ends it, and anything else keeps it. Stays
+    /// put on error.
+    fn advance(
+        &mut self,
+        session_id: Option<&str>,
+        new_session: Option<String>,
+        to: Phase,
+        reason: &'static str,
+    ) -> Result<Phase, SessionError> {
+        if let Some(session_id) = session_id {
+            check(self, session_id)?;
+        }
+        let from = self.phase;
+        if !from.allows(to) {
+            return Err(format!("can't go from {:?} to {:?} ({})", from, to, reason).into());
+        }
+        let session_id = match (new_session, to) {
+            (Some(session_id), _) => Some(session_id),
+            (None, Phase::Idle) => None,
+            (None, _) => self.session_id.take(),
+        };
+        *self = AppState {
+            phase: to,
+            reason: Some(reason),
+            session_id,
+            last_press: self.last_press,
+        };
+        Ok(from)
+    }
+}
+
+/// Payload of the `state:changed` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct StateChanged {
+    pub from: Phase,
+    pub to: Phase,
+    pub reason: &'static str,
+    pub session_id: Option<String>,
+}
+
+/// Payload of the `recording:started` and `recording:stopped` events.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionEvent {
+    pub session_id: Option<String>,
+}
+
+/// Error returned by commands about a dictation, serialized with its
+/// `kind`, its [`code`](SessionError::code) and its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionError {
+    /// `session_id` isn't the dictation under way (`current`, if any): it
+    /// ended, was cancelled or belongs to a previous page load.
+    StaleSession {
+        session_id: String,
+        current: Option<String>,
+    },
+    /// Another paste is in progress, and this one was asked not to wait.
+    Busy,
+    /// The paste failed, for the reason `code`. For a paste in chunks,
+    /// `partial` is how much of the text was pasted; when the clipboard
+    /// didn't hold the text, `mismatch_at` is the grapheme it first differed
+    /// at.
+    PasteFailed {
+        code: ErrorCode,
+        message: String,
+        partial: Option<Progress>,
+        mismatch_at: Option<usize>,
+    },
+    /// The recording never got above the silent input level (its peak was
+    /// `peak_dbfs`), so it wasn't transcribed.
+    EmptyRecording { peak_dbfs: i32 },
+    /// The paste had to be confirmed and was cancelled instead, or not
+    /// answered in time (`timed_out`). The text is on the clipboard.
+    NotConfirmed { timed_out: bool },
+    /// A dictation in command mode (see [`crate::command_mode`]) said
+    /// `text`, which isn't a voice command; nothing was pressed.
+    UnrecognizedCommand { text: String },
+    /// The voice command `phrase` has keys that can't be pressed
+    /// (`message` says which); nothing was pressed.
+    InvalidCommand { phrase: String, message: String },
+    /// Any other failure (the transition isn't allowed).
+    Failed { message: String },
+}
+
+impl SessionError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::StaleSession { .. } => ErrorCode::SessionStale,
+            Self::Busy => ErrorCode::PasteBusy,
+            Self::PasteFailed { code, .. } => *code,
+            Self::EmptyRecording { .. } => ErrorCode::EmptyRecording,
+            Self::NotConfirmed { .. } => ErrorCode::PasteNotConfirmed,
+            Self::UnrecognizedCommand { .. } => ErrorCode::UnrecognizedCommand,
+            Self::InvalidCommand { .. } => ErrorCode::InvalidCommand,
+            Self::Failed { .. } => ErrorCode::Internal,
+        }
+    }
+}
+
+impl Serialize for SessionError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (kind, fields) = match self {
+            Self::StaleSession {
+                session_id,
+                current,
+            } => (
+                "stale_session",
+                json!({ "session_id": session_id, "current": current }),
+            ),
+            Self::Busy => ("busy", json!({})),
+            Self::PasteFailed {
+                message,
+                partial,
+                mismatch_at,
+                ..
+            } => (
+                "paste_failed",
+                json!({ "message": message, "partial": partial, "mismatch_at": mismatch_at }),
+            ),
+            Self::EmptyRecording { peak_dbfs } => {
+                ("empty_recording", json!({ "peak_dbfs": peak_dbfs }))
+            }
+            Self::NotConfirmed { timed_out } => {
+                ("not_confirmed", json!({ "timed_out": timed_out }))
+            }
+            Self::UnrecognizedCommand { text } => ("unrecognized_command", json!({ "text": text })),
+            Self::InvalidCommand { phrase, message } => (
+                "invalid_command",
+                json!({ "phrase": phrase, "message": message }),
+            ),
+            Self::Failed { message } => ("failed", json!({ "message": message })),
+        };
+        errors::serialize_error(serializer, kind, self.code(), fields)
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StaleSession { session_id, .. } => {
+                write!(f, "dictation {} is no longer current", session_id)
+            }
+            Self::Busy => f.write_str("another paste is in progress"),
+            Self::EmptyRecording { peak_dbfs } => {
+                write!(f, "the recording is silent (peak {} dBFS)", peak_dbfs)
+            }
+            Self::NotConfirmed { timed_out: true } => {
+                f.write_str("the paste wasn't confirmed in time")
+            }
+            Self::NotConfirmed { timed_out: false } => f.write_str("the paste was cancelled"),
+            Self::UnrecognizedCommand { text } => write!(f, "\"{}\" isn't a voice command", text),
+            Self::InvalidCommand { phrase, message } => {
+                write!(
+                    f,
+                    "the voice command \"{}\" can't be run: {}",
+                    phrase, message
+                )
+            }
+            Self::PasteFailed { message, .. } | Self::Failed { message } => f.write_str(message),
+        }
+    }
+}
+
+impl From<String> for SessionError {
+    fn from(message: String) -> Self {
+        Self::Failed { message }
+    }
+}
+
+/// The current phase.
+pub fn phase(app: &AppHandle) -> Phase {
+    app.state::<Mutex<AppState>>().lock().unwrap().phase
+}
+
+/// The dictation under way, if any.
+pub fn session_id(app: &AppHandle) -> Option<String> {
+    app.state::<Mutex<AppState>>()
+        .lock()
+        .unwrap()
+        .session_id
+        .clone()
+}
+
+/// Starts recording a new dictation, returning its session id.
+///
+/// # Errors
+///
+/// Returns an error, and stays put, if a recording can't start now.
+pub fn start_session(app: &AppHandle, reason: &'static str) -> Result<String, String> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    advance(
+        app,
+        None,
+        Some(session_id.clone()),
+        Phase::Recording,
+        reason,
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(session_id)
+}
+
+/// Moves to `to`, for `reason`, returning the phase it came from. Going
+/// idle ends the session.
+///
+/// # Errors
+///
+/// Returns an error, and stays put, if the transition isn't allowed.
+pub fn transition(app: &AppHandle, to: Phase, reason: &'static str) -> Result<Phase, String> {
+    advance(app, None, None, to, reason).map_err(|e| e.to_string())
+}
+
+/// Like [`transition`], for the dictation `session_id` only.
+///
+/// # Errors
+///
+/// Returns [`SessionError::StaleSession`] if `session_id` isn't current,
+/// and stays put.
+pub fn transition_session(
+    app: &AppHandle,
+    session_id: &str,
+    to: Phase,
+    reason: &'static str,
+) -> Result<Phase, SessionError> {
+    advance(app, Some(session_id), None, to, reason)
+}
+
+/// Checks that `session_id` is the dictation under way.
+///
+/// # Errors
+///
+/// Returns [`SessionError::StaleSession`] if it isn't.
+pub fn check_session(app: &AppHandle, session_id: &str) -> Result<(), SessionError> {
+    let state = app.state::<Mutex<AppState>>();
+    let state = state.lock().unwrap();
+    check(&state, session_id)
+}
+
+fn check(state: &AppState, session_id: &str) -> Result<(), SessionError> {
+    if state.session_id.as_deref() == Some(session_id) {
+        Ok(())
+    } else {
+        Err(SessionError::StaleSession {
+            session_id: session_id.to_string(),
+            current: state.session_id.clone(),
+        })
+    }
+}
+
+/// Applies the transition (see [`AppState::advance`]) and announces it.
+fn advance(
+    app: &AppHandle,
+    session_id: Option<&str>,
+    new_session: Option<String>,
+    to: Phase,
+    reason: &'static str,
+) -> Result<Phase, SessionError> {
+    advance_with(app, session_id, new_session, |_| to, reason)
+}
+
+/// Like [`advance`], to the phase `target` picks from the current one
+/// under the same lock.
+fn advance_with(
+    app: &AppHandle,
+    session_id: Option<&str>,
+    new_session: Option<String>,
+    target: impl FnOnce(Phase) -> Phase,
+    reason: &'static str,
+) -> Result<Phase, SessionError> {
+    let (from, to, session_id) = {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().unwrap();
+        let to = target(state.phase);
+        let from = state.advance(session_id, new_session, to, reason)?;
+        (from, to, state.session_id.clone())
+    };
+    tracing::debug!(?from, ?to, reason, session_id = ?session_id, "State changed");
+    if let (Phase::Cancelled, Some(session_id)) = (to, &session_id) {
+        crate::cancellation::cancel(app, session_id, from, reason);
+    }
+    if to == Phase::Recording {
+        crate::pill::cancel_auto_hide(app);
+    }
+    if from == Phase::Recording || to == Phase::Recording {
+        crate::indicator::sync(app);
+        crate::focus::sync(app);
+    }
+    let _ = app.emit(
+        "state:changed",
+        StateChanged {
+            from,
+            to,
+            reason,
+            session_id,
+        },
+    );
+    Ok(from)
+}
+
+/// Takes a press of the dictation hotkey at `clock`'s now and returns what
+/// it does; acting on it is up to the caller. Every press counts for the
+/// debounce, ignored or not, so a held key stays ignored until released.
+pub fn press(app: &AppHandle, clock: &impl Clock, policy: PressPolicy) -> Press {
+    let state = app.state::<Mutex<AppState>>();
+    let mut state = state.lock().unwrap();
+    let now = clock.now();
+    let press = state.press(now, policy);
+    state.last_press = Some(now);
+    press
+}
+
+/// Ends whatever dictation is in progress, going back to `Idle` through
+/// `Cancelled` when it hadn't finished; a dictation awaiting confirmation
+/// is cancelled (see [`crate::confirm`]). Does nothing while idle or
+/// pasting, which ends by itself.
+pub fn dismiss(app: &AppHandle, reason: &'static str) {
+    let phase = phase(app);
+    // Losing a race with another transition just means there is nothing
+    // left to dismiss
+    match phase {
+        Phase::Recording | Phase::Transcribing => {
+            let _ = transition(app, Phase::Cancelled, reason);
+            let _ = transition(app, Phase::Idle, reason);
+        }
+        Phase::AwaitingConfirmation => {
+            let _ = transition(app, Phase::Cancelled, reason);
+            let _ = transition(app, Phase::Idle, reason);
+            crate::confirm::cancel(app);
+        }
+        Phase::Cancelled | Phase::Error => {
+            let _ = transition(app, Phase::Idle, reason);
+        }
+        Phase::Idle | Phase::Pasting => {}
+    }
+}
+
+/// Cancels the dictation `session_id` like [`dismiss`] ends the current
+/// one, but only while it is current: the session is checked and the
+/// dictation moved on under one lock, so one that started in the meantime
+/// is left alone. Returns the phase it was in.
+///
+/// # Errors
+///
+/// Returns [`SessionError::StaleSession`] if `session_id` isn't current,
+/// and an error while it is being pasted, which can't be taken back.
+pub fn cancel_session(
+    app: &AppHandle,
+    session_id: &str,
+    reason: &'static str,
+) -> Result<Phase, SessionError> {
+    let from = advance_with(app, Some(session_id), None, cancel_target, reason)?;
+    if cancel_target(from) == Phase::Cancelled {
+        // Only fails if a new dictation replaced it in between
+        let _ = transition_session(app, session_id, Phase::Idle, reason);
+    }
+    if from == Phase::AwaitingConfirmation {
+        crate::confirm::cancel(app);
+    }
+    Ok(from)
+}
+
+/// Where cancelling a dictation in `phase` goes: a failed or cancelled one
+/// has nothing left to stop.
+fn cancel_target(phase: Phase) -> Phase {
+    match phase {
+        Phase::Cancelled | Phase::Error => Phase::Idle,
+        _ => Phase::Cancelled,
+    }
+}
+
+/// Returns the current state, for the frontend to resync after a reload.
+#[tauri::command]
+pub fn get_app_state(app: AppHandle) -> AppState {
+    app.state::<Mutex<AppState>>().lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    /// Every phase, in declaration order.
+    fn phases() -> [Phase; 7] {
+        // Fails to build once a phase is added, until it is added here and
+        // to the tables below
+        let _exhaustive = |phase: Phase| match phase {
+            Phase::Idle
+            | Phase::Recording
+            | Phase::Transcribing
+            | Phase::AwaitingConfirmation
+            | Phase::Pasting
+            | Phase::Cancelled
+            | Phase::Error => {}
+        };
+        [
+            Phase::Idle,
+            Phase::Recording,
+            Phase::Transcribing,
+            Phase::AwaitingConfirmation,
+            Phase::Pasting,
+            Phase::Cancelled,
+            Phase::Error,
+        ]
+    }
+
+    /// Which transitions are allowed: a row for each phase it goes from, a
+    /// column for each phase it goes to, both in [`phases`] order.
+    #[rustfmt::skip]
+    const ALLOWED: [[bool; 7]; 7] = {
+        const N: bool = false;
+        const Y: bool = true;
+        [
+            //            Idle Rec Trans Await Paste Cancel Error
+            /* Idle */   [N,   Y,  N,    N,    Y,    N,     N],
+            /* Rec */    [N,   Y,  Y,    N,    N,    Y,     Y],
+            /* Trans */  [N,   N,  N,    Y,    Y,    Y,     Y],
+            /* Await */  [N,   N,  N,    N,    Y,    Y,     N],
+            /* Paste */  [Y,   N,  N,    N,    N,    N,     Y],
+            /* Cancel */ [Y,   Y,  N,    N,    N,    N,     N],
+            /* Error */  [Y,   Y,  N,    N,    Y,    N,     N],
+        ]
+    };
+
+    fn in_phase(phase: Phase) -> AppState {
+        AppState {
+            phase,
+            reason: Some("test"),
+            session_id: (phase != Phase::Idle).then(|| "current".to_string()),
+            last_press: None,
+        }
+    }
+
+    #[test]
+    fn transition_table() {
+        for (row, from) in phases().into_iter().enumerate() {
+            for (column, to) in phases().into_iter().enumerate() {
+                let allowed = ALLOWED[row][column];
+                assert_eq!(from.allows(to), allowed, "{:?} -> {:?}", from, to);
+
+                let mut state = in_phase(from);
+                let outcome = state.advance(None, None, to, "tested");
+                if allowed {
+                    assert_eq!(outcome, Ok(from), "{:?} -> {:?}", from, to);
+                    assert_eq!(state.phase, to);
+                    assert_eq!(state.reason, Some("tested"));
+                } else {
+                    assert!(
+                        matches!(outcome, Err(SessionError::Failed { .. })),
+                        "{:?} -> {:?}: {:?}",
+                        from,
+                        to,
+                        outcome
+                    );
+                    // Rejected transitions stay put
+                    assert_eq!(state, in_phase(from), "{:?} -> {:?}", from, to);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn going_idle_ends_the_session() {
+        for from in [Phase::Pasting, Phase::Cancelled, Phase::Error] {
+            let mut state = in_phase(from);
+            state.advance(None, None, Phase::Idle, "done").unwrap();
+            assert_eq!(state.session_id, None);
+        }
+    }
+
+    #[test]
+    fn other_transitions_keep_the_session() {
+        let mut state = in_phase(Phase::Recording);
+        for to in [Phase::Transcribing, Phase::Pasting, Phase::Error] {
+            state.advance(Some("current"), None, to, "next").unwrap();
+            assert_eq!(state.session_id.as_deref(), Some("current"));
+        }
+    }
+
+    #[test]
+    fn new_recordings_replace_the_session() {
+        let now = Instant::now();
+        for from in [
+            Phase::Idle,
+            Phase::Recording,
+            Phase::Cancelled,
+            Phase::Error,
+        ] {
+            let mut state = AppState {
+                last_press: Some(now),
+                ..in_phase(from)
+            };
+            let new = Some("new".to_string());
+            state
+                .advance(None, new, Phase::Recording, "hotkey")
+                .unwrap();
+            assert_eq!(state.session_id.as_deref(), Some("new"));
+            // Kept for the debounce
+            assert_eq!(state.last_press, Some(now));
+        }
+    }
+
+    #[test]
+    fn stale_sessions_are_rejected() {
+        for from in phases() {
+            let mut state = in_phase(from);
+            for to in phases() {
+                let outcome = state.advance(Some("previous"), None, to, "late");
+                assert_eq!(
+                    outcome,
+                    Err(SessionError::StaleSession {
+                        session_id: "previous".to_string(),
+                        current: in_phase(from).session_id,
+                    })
+                );
+                assert_eq!(state, in_phase(from));
+            }
+        }
+    }
+
+    #[test]
+    fn dictations_can_be_cancelled_until_they_are_pasted() {
+        for from in phases() {
+            let mut state = in_phase(from);
+            let outcome = state.advance(Some("current"), None, cancel_target(from), "cancelled");
+            match from {
+                Phase::Idle => assert!(matches!(outcome, Err(SessionError::StaleSession { .. }))),
+                Phase::Pasting => {
+                    assert!(matches!(outcome, Err(SessionError::Failed { .. })));
+                    assert_eq!(state, in_phase(from));
+                }
+                _ => assert_eq!(outcome, Ok(from), "{:?}", from),
+            }
+        }
+    }
+
+    #[test]
+    fn cancelling_a_stale_session_leaves_the_new_one_alone() {
+        // Cancelled, then replaced by a new recording before the cancel of
+        // the old one reached the state
+        let mut state = AppState {
+            session_id: Some("new".to_string()),
+            ..in_phase(Phase::Recording)
+        };
+        let before = state.clone();
+        let outcome = state.advance(Some("old"), None, cancel_target(state.phase), "cancelled");
+        assert_eq!(
+            outcome,
+            Err(SessionError::StaleSession {
+                session_id: "old".to_string(),
+                current: Some("new".to_string()),
+            })
+        );
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn presses_by_phase() {
+        let ignore = |reason| Press::Ignore { reason };
+        // What a press does in each phase, without and with
+        // restart_while_transcribing
+        let table = [
+            (Phase::Idle, Press::Start, Press::Start),
+            (Phase::Recording, ignore("recording"), ignore("recording")),
+            (Phase::Transcribing, ignore("transcribing"), Press::Restart),
+            (
+                Phase::AwaitingConfirmation,
+                ignore("awaiting_confirmation"),
+                ignore("awaiting_confirmation"),
+            ),
+            (Phase::Pasting, ignore("pasting"), ignore("pasting")),
+            (Phase::Cancelled, Press::Start, Press::Start),
+            (Phase::Error, Press::Start, Press::Start),
+        ];
+        assert_eq!(table.map(|(phase, ..)| phase), phases());
+        let now = Instant::now();
+        for (phase, plain, restarting) in table {
+            for (restart_while_transcribing, expected) in [(false, plain), (true, restarting)] {
+                let policy = PressPolicy {
+                    debounce: Duration::ZERO,
+                    restart_while_transcribing,
+                };
+                assert_eq!(in_phase(phase).press(now, policy), expected, "{:?}", phase);
+            }
+        }
+    }
+
+    #[test]
+    fn presses_within_the_debounce_are_ignored() {
+        let policy = PressPolicy {
+            debounce: Duration::from_millis(300),
+            restart_while_transcribing: true,
+        };
+        let start = Instant::now();
+        let state = AppState {
+            last_press: Some(start),
+            ..AppState::default()
+        };
+        let debounced = Press::Ignore {
+            reason: "debounced",
+        };
+        assert_eq!(state.press(start, policy), debounced);
+        assert_eq!(
+            state.press(start + Duration::from_millis(299), policy),
+            debounced
+        );
+        assert_eq!(
+            state.press(start + Duration::from_millis(300), policy),
+            Press::Start
+        );
+        // In every phase
+        for phase in phases() {
+            let state = AppState {
+                last_press: Some(start),
+                ..in_phase(phase)
+            };
+            let soon = start + Duration::from_millis(100);
+            assert_eq!(state.press(soon, policy), debounced, "{:?}", phase);
+        }
+    }
+
+    /// An app holding just the dictation state, `state`.
+    fn app_with(state: AppState) -> tauri::App<crate::Runtime> {
+        let app = tauri::test::mock_app();
+        app.manage(Mutex::new(state));
+        app
+    }
+
+    #[test]
+    fn the_debounce_runs_on_the_clock() {
+        let app = app_with(AppState::default());
+        let clock = MockClock::default();
+        let policy = PressPolicy::from(&HotkeySettings {
+            debounce_ms: 250,
+            ..HotkeySettings::default()
+        });
+        let debounced = Press::Ignore {
+            reason: "debounced",
+        };
+        assert_eq!(press(app.handle(), &clock, policy), Press::Start);
+        clock.advance(Duration::from_millis(249));
+        assert_eq!(press(app.handle(), &clock, policy), debounced);
+        // The ignored press counted: a held key repeating every 200ms stays
+        // ignored, well past 250ms from the first press
+        for _ in 0..5 {
+            clock.advance(Duration::from_millis(200));
+            assert_eq!(press(app.handle(), &clock, policy), debounced);
+        }
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(press(app.handle(), &clock, policy), Press::Start);
+    }
+
+    #[test]
+    fn a_press_after_the_debounce_goes_by_the_phase() {
+        let app = app_with(in_phase(Phase::Transcribing));
+        let clock = MockClock::default();
+        let mut policy = PressPolicy {
+            debounce: Duration::from_millis(250),
+            restart_while_transcribing: false,
+        };
+        let transcribing = Press::Ignore {
+            reason: "transcribing",
+        };
+        assert_eq!(press(app.handle(), &clock, policy), transcribing);
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(press(app.handle(), &clock, policy), transcribing);
+        policy.restart_while_transcribing = true;
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(
+            press(app.handle(), &clock, policy),
+            Press::Ignore {
+                reason: "debounced",
+            }
+        );
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(press(app.handle(), &clock, policy), Press::Restart);
+    }
+
+    #[test]
+    fn no_debounce_takes_every_press() {
+        let app = app_with(AppState::default());
+        let clock = MockClock::default();
+        let policy = PressPolicy::from(&HotkeySettings {
+            debounce_ms: 0,
+            ..HotkeySettings::default()
+        });
+        for _ in 0..3 {
+            assert_eq!(press(app.handle(), &clock, policy), Press::Start);
+        }
+    }
+}
@@ -0,0 +1,737 @@
+//! Audio files given to transcribe: checking and decoding them.
+//!
+//! An audio file (from `--transcribe`, a `wispr://transcribe` link or the
+//! benchmark) is read by [`crate::external::read_audio_file`], but first
+//! probed with Symphonia, which reads mp3, m4a/aac/alac, flac, ogg
+//! (vorbis and opus), webm and wav. Probing finds the audio track and how
+//! long it is ([`probe_audio_file`]), and decodes its first packet and its
+//! last seconds, so a file the provider can't use fails up front with an
+//! [`AudioFileError`] instead of after the upload: one in a format
+//! Symphonia doesn't know, one that is DRM protected (iTunes purchases),
+//! or one cut short, like a voice memo whose recording was interrupted
+//! before its index was written.
+//!
+//! # Architecture Decision
+//!
+//! The file is then decoded to PCM in the dictation capture quality
+//! ([`decode`]), through the same channel mixing and resampling as native
+//! capture ([`crate::capture::Converter`]), so a file reaches the provider
+//! like a recording does, whatever its rate and channels. The PCM is
+//! streamed to the webview in chunks as it is decoded, so an hour-long
+//! file is never in memory at once before it is sent. Probing only reads
+//! the container, the first packet and the last seconds, so it takes the
+//! same time for an hour-long file as for a short one. Opus has no
+//! Symphonia decoder, so an opus file is checked as far as its container
+//! and streamed as it is, for the provider to decode.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use symphonia::core::audio::{SampleBuffer, SignalSpec};
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+use symphonia::core::errors::{Error as SymphoniaError, SeekErrorKind};
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo, Track};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
+
+use crate::capture::{CaptureQuality, Converter};
+use crate::errors::{self, ErrorCode};
+use crate::external;
+
+/// Packets skipped looking for the audio track's first one.
+const MAX_SKIPPED_PACKETS: usize = 1_000;
+
+/// Protection scheme box of encrypted MP4 tracks.
+const PROTECTION_BOX: &[u8] = b"sinf";
+
+/// Read at a time when looking for [`PROTECTION_BOX`].
+const SCAN_CHUNK_BYTES: usize = 64 * 1024;
+
+/// End of the track [`probe`] decodes, to tell it isn't cut short.
+const END_CHECKED_MS: u64 = 2_000;
+
+/// How far before where its container says the audio may stop: the last
+/// packet's duration isn't always exact.
+const END_TOLERANCE_MS: u64 = 500;
+
+/// Audio in each chunk [`decode`] streams.
+const CHUNK_MS: u64 = 10_000;
+
+/// Bytes in each chunk of a file streamed as it is.
+const CHUNK_BYTES: usize = 256 * 1024;
+
+/// What probing an audio file found.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AudioFileInfo {
+    pub path: PathBuf,
+    /// Codec of the audio track, such as `mp3` or `aac`.
+    pub codec: String,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<usize>,
+    /// `None` when the container doesn't say (mp3 without a header for it).
+    pub duration_ms: Option<u64>,
+}
+
+/// Why an audio file can't be transcribed. Serialized with its `kind`, its
+/// [`code`](AudioFileError::code) and its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioFileError {
+    /// Missing, too large, or without an audio extension.
+    Invalid { message: String },
+    /// Not a format or codec that can be read.
+    Unsupported { message: String },
+    /// DRM protected.
+    Protected,
+    /// Cut short or damaged.
+    Truncated { message: String },
+    /// It couldn't be read.
+    Unreadable { message: String },
+}
+
+impl AudioFileError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Invalid { .. } | Self::Unsupported { .. } => ErrorCode::AudioUnsupported,
+            Self::Protected => ErrorCode::AudioProtected,
+            Self::Truncated { .. } => ErrorCode::AudioTruncated,
+            Self::Unreadable { .. } => ErrorCode::AudioUnreadable,
+        }
+    }
+
+    /// The error for Symphonia's `error` reading the file.
+    fn of(error: SymphoniaError) -> Self {
+        match error {
+            SymphoniaError::IoError(e) if e.kind() == ErrorKind::UnexpectedEof => Self::Truncated {
+                message: "the file ends before its audio does".to_string(),
+            },
+            SymphoniaError::IoError(e) => Self::Unreadable {
+                message: e.to_string(),
+            },
+            SymphoniaError::Unsupported(what) => Self::Unsupported {
+                message: what.to_string(),
+            },
+            SymphoniaError::DecodeError(what) => Self::Truncated {
+                message: what.to_string(),
+            },
+            other => Self::Unreadable {
+                message: other.to_string(),
+            },
+        }
+    }
+}
+
+impl Serialize for AudioFileError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (kind, fields) = match self {
+            Self::Invalid { message } => ("invalid", json!({ "message": message })),
+            Self::Unsupported { message } => ("unsupported", json!({ "message": message })),
+            Self::Protected => ("protected", json!({})),
+            Self::Truncated { message } => ("truncated", json!({ "message": message })),
+            Self::Unreadable { message } => ("unreadable", json!({ "message": message })),
+        };
+        errors::serialize_error(serializer, kind, self.code(), fields)
+    }
+}
+
+impl fmt::Display for AudioFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid { message } => f.write_str(message),
+            Self::Unsupported { message } => write!(f, "unsupported audio: {}", message),
+            Self::Protected => f.write_str("the audio is DRM protected"),
+            Self::Truncated { message } => write!(f, "the audio is damaged: {}", message),
+            Self::Unreadable { message } => write!(f, "the audio can't be read: {}", message),
+        }
+    }
+}
+
+/// Checks that `path` is an audio file that can be transcribed, returning
+/// its canonical path and what is in it.
+///
+/// # Errors
+///
+/// Returns an [`AudioFileError`] saying why it can't be.
+pub fn probe(path: &Path) -> Result<AudioFileInfo, AudioFileError> {
+    let (info, mut format, track) = open(path)?;
+    let params = &track.codec_params;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(params, &DecoderOptions::default())
+        .ok();
+
+    // Decode the first packet, where the codec has a decoder here
+    if let Some(decoder) = decoder.as_mut() {
+        for _ in 0..MAX_SKIPPED_PACKETS {
+            let packet = format.next_packet().map_err(AudioFileError::of)?;
+            if packet.track_id() != track.id {
+                continue;
+            }
+            decoder.decode(&packet).map_err(AudioFileError::of)?;
+            break;
+        }
+    }
+    check_end(format.as_mut(), &track, decoder.as_mut())?;
+    tracing::debug!(
+        codec = %info.codec,
+        duration_ms = ?info.duration_ms,
+        "Probed audio file"
+    );
+    Ok(info)
+}
+
+/// Opens the audio file at `path`, returning what is in it, its reader and
+/// its audio track.
+fn open(path: &Path) -> Result<(AudioFileInfo, Box<dyn FormatReader>, Track), AudioFileError> {
+    let path = external::audio_file(path).map_err(|message| AudioFileError::Invalid { message })?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    let mp4 = matches!(extension.as_str(), "m4a" | "mp4" | "aac");
+
+    let file = File::open(&path).map_err(|e| AudioFileError::Unreadable {
+        message: e.to_string(),
+    })?;
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension(&extension);
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        source,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    );
+    let format = match probed {
+        Ok(probed) => probed.format,
+        // An encrypted track is as good as no track
+        Err(_) if mp4 && is_protected(&path) => return Err(AudioFileError::Protected),
+        Err(e) => return Err(AudioFileError::of(e)),
+    };
+
+    let Some(track) = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .cloned()
+    else {
+        if mp4 && is_protected(&path) {
+            return Err(AudioFileError::Protected);
+        }
+        return Err(AudioFileError::Unsupported {
+            message: "there is no audio track".to_string(),
+        });
+    };
+    let params = &track.codec_params;
+    let codec = match symphonia::default::get_codecs().get_codec(params.codec) {
+        Some(descriptor) => descriptor.short_name.to_string(),
+        None if params.codec == CODEC_TYPE_OPUS => "opus".to_string(),
+        None if mp4 && is_protected(&path) => return Err(AudioFileError::Protected),
+        None => {
+            return Err(AudioFileError::Unsupported {
+                message: "unknown codec".to_string(),
+            })
+        }
+    };
+    let duration_ms = params
+        .n_frames
+        .and_then(|frames| millis(params.time_base, frames));
+    let info = AudioFileInfo {
+        codec,
+        sample_rate: params.sample_rate,
+        channels: params.channels.map(|channels| channels.count()),
+        duration_ms,
+        path,
+    };
+    Ok((info, format, track))
+}
+
+/// `ts` in milliseconds, if the time base is known.
+fn millis(time_base: Option<TimeBase>, ts: u64) -> Option<u64> {
+    let time = time_base?.calc_time(ts);
+    Some(time.seconds * 1_000 + (time.frac * 1_000.0) as u64)
+}
+
+/// Where the track ends, in its time base, by the container.
+fn track_end(track: &Track) -> Option<u64> {
+    let params = &track.codec_params;
+    params.n_frames.map(|frames| params.start_ts + frames)
+}
+
+/// Fails with [`AudioFileError::Truncated`] if the track's audio stops at
+/// `decoded` (in its time base) well before the container says it ends.
+fn check_complete(track: &Track, decoded: u64) -> Result<(), AudioFileError> {
+    let params = &track.codec_params;
+    let (Some(end), Some(time_base)) = (track_end(track), params.time_base) else {
+        return Ok(());
+    };
+    let tolerance = ticks(time_base, END_TOLERANCE_MS);
+    if decoded + tolerance >= end {
+        return Ok(());
+    }
+    let at = |ts: u64| millis(Some(time_base), ts.saturating_sub(params.start_ts)).unwrap_or(0);
+    Err(AudioFileError::Truncated {
+        message: format!("the audio stops at {} ms of {} ms", at(decoded), at(end)),
+    })
+}
+
+/// `ms` in the time base.
+fn ticks(time_base: TimeBase, ms: u64) -> u64 {
+    ms * u64::from(time_base.denom) / (1_000 * u64::from(time_base.numer))
+}
+
+/// Checks that the last [`END_CHECKED_MS`] of the track are there: a file
+/// cut short still has its first packets, and its container may still say
+/// how long it was meant to be. Seeks there and reads the packets to the
+/// end, decoding them with `decoder` if there is one.
+fn check_end(
+    format: &mut dyn FormatReader,
+    track: &Track,
+    mut decoder: Option<&mut Box<dyn Decoder>>,
+) -> Result<(), AudioFileError> {
+    let params = &track.codec_params;
+    let (Some(end), Some(time_base)) = (track_end(track), params.time_base) else {
+        // Nothing says where it should end
+        return Ok(());
+    };
+    let from = end
+        .saturating_sub(ticks(time_base, END_CHECKED_MS))
+        .max(params.start_ts);
+    let to = SeekTo::TimeStamp {
+        ts: from,
+        track_id: track.id,
+    };
+    let mut decoded = match format.seek(SeekMode::Coarse, to) {
+        Ok(seeked) => seeked.actual_ts,
+        // Only decoding it all would tell
+        Err(SymphoniaError::SeekError(SeekErrorKind::Unseekable | SeekErrorKind::ForwardOnly)) => {
+            return Ok(())
+        }
+        // Where it should end isn't in the file
+        Err(SymphoniaError::SeekError(_)) => params.start_ts,
+        Err(e) => return Err(AudioFileError::of(e)),
+    };
+    if let Some(decoder) = decoder.as_mut() {
+        decoder.reset();
+    }
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(AudioFileError::of(e)),
+        };
+        if packet.track_id() != track.id {
+            continue;
+        }
+        if let Some(decoder) = decoder.as_mut() {
+            decoder.decode(&packet).map_err(AudioFileError::of)?;
+        }
+        decoded = decoded.max(packet.ts() + packet.dur());
+    }
+    check_complete(track, decoded)
+}
+
+/// What [`decode`] streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// 16-bit little-endian PCM, interleaved, in the capture quality asked
+    /// for.
+    Pcm,
+    /// The file's own bytes, for a codec without a decoder here (opus),
+    /// which the provider decodes.
+    File,
+}
+
+/// An audio file, as [`decode`] streamed it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedAudio {
+    pub info: AudioFileInfo,
+    pub encoding: Encoding,
+    /// Rate and channels of [`Encoding::Pcm`].
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Bytes streamed, in how many chunks.
+    pub bytes: u64,
+    pub chunks: usize,
+}
+
+/// How far [`decode`] got: sent with each chunk, and as
+/// `audio:file-progress`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DecodeProgress {
+    pub path: PathBuf,
+    /// Audio decoded so far.
+    pub decoded_ms: u64,
+    /// As probed; `None` when the container doesn't say.
+    pub duration_ms: Option<u64>,
+}
+
+/// Decodes the audio file at `path` to PCM in `quality` (see
+/// [`capture::Converter`]), handing it to `on_chunk` in chunks of about
+/// [`CHUNK_MS`], so an hour-long file never is in memory at once. The file
+/// is [`probe`]d first, so a file that can't be transcribed fails before
+/// anything is streamed. A codec without a decoder here is streamed as it
+/// is ([`Encoding::File`]).
+///
+/// # Errors
+///
+/// Returns an [`AudioFileError`] if the file can't be transcribed, turns
+/// out to be damaged or cut short partway, or `on_chunk` fails.
+pub fn decode(
+    path: &Path,
+    quality: &CaptureQuality,
+    mut on_chunk: impl FnMut(Vec<u8>, &DecodeProgress) -> Result<(), String>,
+) -> Result<DecodedAudio, AudioFileError> {
+    let info = probe(path)?;
+    let (_, mut format, track) = open(&info.path)?;
+    let mut progress = DecodeProgress {
+        path: info.path.clone(),
+        decoded_ms: 0,
+        duration_ms: info.duration_ms,
+    };
+    let mut decoded = DecodedAudio {
+        info,
+        encoding: Encoding::Pcm,
+        sample_rate: quality.sample_rate,
+        channels: quality.channels,
+        bytes: 0,
+        chunks: 0,
+    };
+    let mut send = |decoded: &mut DecodedAudio, chunk: Vec<u8>, progress: &DecodeProgress| {
+        decoded.bytes += chunk.len() as u64;
+        decoded.chunks += 1;
+        on_chunk(chunk, progress).map_err(|message| AudioFileError::Unreadable { message })
+    };
+
+    let params = track.codec_params.clone();
+    let Ok(mut decoder) =
+        symphonia::default::get_codecs().make(&params, &DecoderOptions::default())
+    else {
+        decoded.encoding = Encoding::File;
+        stream_file(&mut decoded, &mut progress, &mut send)?;
+        return Ok(decoded);
+    };
+
+    let chunk_samples =
+        quality.sample_rate as usize * usize::from(quality.channels) * CHUNK_MS as usize / 1_000;
+    let mut converter: Option<(SignalSpec, Converter)> = None;
+    let mut samples: Option<SampleBuffer<f32>> = None;
+    let mut converted = Vec::with_capacity(chunk_samples);
+    let mut end = params.start_ts;
+    let mut frames = 0;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(AudioFileError::of(e)),
+        };
+        if packet.track_id() != track.id {
+            continue;
+        }
+        let audio = decoder.decode(&packet).map_err(AudioFileError::of)?;
+        let spec = *audio.spec();
+        let (converting, converter) = match &mut converter {
+            Some(converter) => converter,
+            slot @ None => {
+                let created = Converter::new(spec.rate, spec.channels.count(), quality)
+                    .map_err(|message| AudioFileError::Unsupported { message })?;
+                slot.insert((spec, created))
+            }
+        };
+        if spec != *converting {
+            return Err(AudioFileError::Unsupported {
+                message: "the audio changes format partway".to_string(),
+            });
+        }
+        let needed = audio.capacity() * spec.channels.count();
+        if !samples
+            .as_ref()
+            .is_some_and(|samples| samples.capacity() >= needed)
+        {
+            samples = None;
+        }
+        let samples =
+            samples.get_or_insert_with(|| SampleBuffer::new(audio.capacity() as u64, spec));
+        samples.copy_interleaved_ref(audio);
+        frames += (samples.samples().len() / spec.channels.count()) as u64;
+        converter
+            .push(samples.samples(), &mut converted)
+            .map_err(|message| AudioFileError::Unreadable { message })?;
+        end = end.max(packet.ts() + packet.dur());
+
+        if converted.len() >= chunk_samples {
+            progress.decoded_ms = millis(params.time_base, end - params.start_ts)
+                .unwrap_or(frames * 1_000 / u64::from(spec.rate.max(1)));
+            send(&mut decoded, pcm(&converted), &progress)?;
+            converted.clear();
+        }
+    }
+    if let Some((_, converter)) = converter {
+        converter
+            .finish(&mut converted)
+            .map_err(|message| AudioFileError::Unreadable { message })?;
+    }
+    check_complete(&track, end)?;
+    if !converted.is_empty() {
+        progress.decoded_ms = progress.duration_ms.unwrap_or(progress.decoded_ms);
+        send(&mut decoded, pcm(&converted), &progress)?;
+    }
+    tracing::debug!(
+        codec = %decoded.info.codec,
+        bytes = decoded.bytes,
+        chunks = decoded.chunks,
+        "Decoded audio file"
+    );
+    Ok(decoded)
+}
+
+/// `samples` as 16-bit little-endian PCM.
+fn pcm(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|&sample| ((sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16).to_le_bytes())
+        .collect()
+}
+
+/// Streams the file of `decoded` as it is, in chunks of [`CHUNK_BYTES`].
+fn stream_file(
+    decoded: &mut DecodedAudio,
+    progress: &mut DecodeProgress,
+    send: &mut impl FnMut(&mut DecodedAudio, Vec<u8>, &DecodeProgress) -> Result<(), AudioFileError>,
+) -> Result<(), AudioFileError> {
+    let unreadable = |e: std::io::Error| AudioFileError::Unreadable {
+        message: e.to_string(),
+    };
+    let mut file = File::open(&decoded.info.path).map_err(unreadable)?;
+    let size = file.metadata().map_err(unreadable)?.len().max(1);
+    loop {
+        let mut chunk = Vec::with_capacity(CHUNK_BYTES);
+        let read = (&mut file)
+            .take(CHUNK_BYTES as u64)
+            .read_to_end(&mut chunk)
+            .map_err(unreadable)?;
+        if read == 0 {
+            return Ok(());
+        }
+        if let Some(duration_ms) = progress.duration_ms {
+            progress.decoded_ms = duration_ms * (decoded.bytes + read as u64) / size;
+        }
+        send(decoded, chunk, progress)?;
+    }
+}
+
+/// Whether the MP4 file at `path` has an encrypted track. Reads it in
+/// chunks, since the index may be at the end.
+fn is_protected(path: &Path) -> bool {
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+    let overlap = PROTECTION_BOX.len() - 1;
+    let mut buffer = vec![0; SCAN_CHUNK_BYTES + overlap];
+    let mut kept = 0;
+    loop {
+        let read = match file.read(&mut buffer[kept..]) {
+            Ok(0) | Err(_) => return false,
+            Ok(read) => read,
+        };
+        let filled = kept + read;
+        if buffer[..filled]
+            .windows(PROTECTION_BOX.len())
+            .any(|window| window == PROTECTION_BOX)
+        {
+            return true;
+        }
+        // Keep the tail, in case the box name spans two reads
+        kept = overlap.min(filled);
+        buffer.copy_within(filled - kept..filled, 0);
+    }
+}
+
+/// Probes the audio file at `path` (see [`probe`]): its codec, format and
+/// duration.
+///
+/// # Errors
+///
+/// Returns an [`AudioFileError`] if it can't be transcribed.
+#[tauri::command]
+pub async fn probe_audio_file(path: PathBuf) -> Result<AudioFileInfo, AudioFileError> {
+    tauri::async_runtime::spawn_blocking(move || probe(&path))
+        .await
+        .map_err(|e| AudioFileError::Unreadable {
+            message: e.to_string(),
+        })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/audio")
+            .join(name)
+    }
+
+    fn dictation() -> CaptureQuality {
+        CaptureQuality {
+            sample_rate: 16_000,
+            channels: 1,
+            bitrate_kbps: 32,
+        }
+    }
+
+    /// Decodes `name` in [`dictation`], returning the chunks and the
+    /// progress sent with each.
+    fn decode_fixture(
+        name: &str,
+    ) -> Result<(DecodedAudio, Vec<Vec<u8>>, Vec<DecodeProgress>), AudioFileError> {
+        let mut chunks = Vec::new();
+        let mut progress = Vec::new();
+        let decoded = decode(&fixture(name), &dictation(), |chunk, sent| {
+            chunks.push(chunk);
+            progress.push(sent.clone());
+            Ok(())
+        })?;
+        Ok((decoded, chunks, progress))
+    }
+
+    #[test]
+    fn every_format_is_probed_with_its_codec_and_duration() {
+        for (name, codec, duration_ms) in [
+            ("silence.wav", "pcm_s16le", 500),
+            ("silence.flac", "flac", 3_000),
+            ("silence.mp3", "mp3", 1_200),
+            ("silence.m4a", "aac", 3_200),
+            ("silence.ogg", "vorbis", 3_000),
+            ("silence.opus.ogg", "opus", 3_000),
+        ] {
+            let info = probe(&fixture(name)).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(info.codec, codec, "{}", name);
+            assert_eq!(info.duration_ms, Some(duration_ms), "{}", name);
+            assert_eq!(
+                info.channels,
+                Some(if name == "silence.wav" { 2 } else { 1 })
+            );
+            assert_eq!(info.path, fixture(name).canonicalize().unwrap());
+        }
+    }
+
+    #[test]
+    fn a_drm_protected_file_is_told_apart() {
+        let error = probe(&fixture("protected.m4a")).unwrap_err();
+        assert_eq!(error, AudioFileError::Protected);
+        assert_eq!(error.code(), ErrorCode::AudioProtected);
+    }
+
+    #[test]
+    fn a_file_cut_short_is_truncated_though_it_starts_fine() {
+        // Its first frames are all there; the second half isn't
+        let error = probe(&fixture("truncated.flac")).unwrap_err();
+        let AudioFileError::Truncated { message } = &error else {
+            panic!("{:?}", error);
+        };
+        assert_eq!(message, "the audio stops at 1500 ms of 3000 ms");
+        assert_eq!(error.code(), ErrorCode::AudioTruncated);
+        assert!(matches!(
+            decode_fixture("truncated.flac"),
+            Err(AudioFileError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn a_file_that_is_not_audio_is_invalid() {
+        let error = probe(&fixture("generate.py")).unwrap_err();
+        assert!(
+            matches!(error, AudioFileError::Invalid { .. }),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn files_are_decoded_to_the_dictation_quality() {
+        // 8 kHz stereo, resampled and mixed to 16 kHz mono
+        let (decoded, chunks, progress) = decode_fixture("silence.wav").unwrap();
+        assert_eq!(decoded.encoding, Encoding::Pcm);
+        assert_eq!((decoded.sample_rate, decoded.channels), (16_000, 1));
+        assert_eq!(decoded.bytes, 16_000);
+        assert_eq!(decoded.chunks, chunks.len());
+        assert_eq!(chunks.concat().len(), 16_000);
+        assert!(chunks.concat().iter().all(|&byte| byte == 0));
+        assert_eq!(progress.last().unwrap().decoded_ms, 500);
+
+        // 3 s of 8 kHz mono flac
+        let (decoded, chunks, _) = decode_fixture("silence.flac").unwrap();
+        assert_eq!(decoded.bytes, 3 * 16_000 * 2);
+        assert_eq!(chunks.iter().map(Vec::len).sum::<usize>(), 3 * 16_000 * 2);
+    }
+
+    #[test]
+    fn compressed_files_are_decoded_too() {
+        for (name, duration_ms) in [
+            ("silence.mp3", 1_200),
+            ("silence.m4a", 3_200),
+            ("silence.ogg", 3_000),
+        ] {
+            let (decoded, _, progress) =
+                decode_fixture(name).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(decoded.encoding, Encoding::Pcm, "{}", name);
+            let expected = duration_ms * 16 * 2;
+            assert!(
+                decoded.bytes.abs_diff(expected) <= 4_096,
+                "{}: {}",
+                name,
+                decoded.bytes
+            );
+            assert_eq!(progress.last().unwrap().decoded_ms, duration_ms, "{}", name);
+        }
+    }
+
+    #[test]
+    fn opus_is_streamed_as_it_is() {
+        let (decoded, chunks, _) = decode_fixture("silence.opus.ogg").unwrap();
+        assert_eq!(decoded.encoding, Encoding::File);
+        assert_eq!(
+            chunks.concat(),
+            std::fs::read(fixture("silence.opus.ogg")).unwrap()
+        );
+        assert_eq!(decoded.bytes, chunks.concat().len() as u64);
+    }
+
+    #[test]
+    fn a_chunk_that_cant_be_sent_stops_decoding() {
+        let mut sent = 0;
+        let result = decode(&fixture("silence.flac"), &dictation(), |_, _| {
+            sent += 1;
+            Err("the window closed".to_string())
+        });
+        assert_eq!(
+            result,
+            Err(AudioFileError::Unreadable {
+                message: "the window closed".to_string()
+            })
+        );
+        assert_eq!(sent, 1);
+    }
+
+    #[test]
+    fn errors_serialize_with_their_kind_and_code() {
+        let error = AudioFileError::Truncated {
+            message: "the audio stops at 1500 ms of 3000 ms".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({
+                "kind": "truncated",
+                "code": "E_AUDIO_TRUNCATED",
+                "message": "the audio stops at 1500 ms of 3000 ms",
+            })
+        );
+    }
+}
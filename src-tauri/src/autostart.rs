@@ -0,0 +1,278 @@
+//! Launch at login.
+//!
+//! The OS login item is the source of truth: the user can remove it in
+//! System Settings (or the Windows startup apps list) without the app
+//! noticing, so the stored `startup.launch_at_login` preference is
+//! reconciled with the real state at every startup.
+//!
+//! # Platform Support
+//!
+//! - macOS 13+: the main app is registered with `SMAppService`, which shows
+//!   up under System Settings > General > Login Items and may need the
+//!   user's approval there.
+//! - Older macOS: a LaunchAgent plist, via `tauri-plugin-autostart`.
+//! - Windows and Linux: the registry Run key and an XDG autostart entry,
+//!   via `tauri-plugin-autostart`.
+
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use tauri_plugin_autostart::ManagerExt;
+
+use crate::errors::{self, ErrorCode};
+use crate::settings;
+use crate::AppHandle;
+
+/// Why the login item could not be changed, so the settings UI can tell the
+/// user what to do. Serialized with its `kind`, its
+/// [`code`](AutostartError::code) and its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AutostartError {
+    /// Registered, but the user has to allow it in System Settings > General
+    /// > Login Items before it takes effect (macOS).
+    RequiresApproval,
+    /// The OS refused the change (sandboxing, policy or file permissions).
+    PermissionDenied { message: String },
+    /// Any other failure.
+    Failed { message: String },
+}
+
+impl AutostartError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::RequiresApproval => ErrorCode::AutostartApproval,
+            Self::PermissionDenied { .. } => ErrorCode::AutostartDenied,
+            Self::Failed { .. } => ErrorCode::Autostart,
+        }
+    }
+}
+
+impl Serialize for AutostartError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (kind, fields) = match self {
+            Self::RequiresApproval => ("requires_approval", json!({})),
+            Self::PermissionDenied { message } => {
+                ("permission_denied", json!({ "message": message }))
+            }
+            Self::Failed { message } => ("failed", json!({ "message": message })),
+        };
+        errors::serialize_error(serializer, kind, self.code(), fields)
+    }
+}
+
+impl fmt::Display for AutostartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RequiresApproval => f.write_str(
+                "launch at login must be allowed in System Settings > General > Login Items",
+            ),
+            Self::PermissionDenied { message } => {
+                write!(f, "not allowed to change login items: {}", message)
+            }
+            Self::Failed { message } => f.write_str(message),
+        }
+    }
+}
+
+impl From<String> for AutostartError {
+    fn from(message: String) -> Self {
+        Self::Failed { message }
+    }
+}
+
+impl From<tauri_plugin_autostart::Error> for AutostartError {
+    fn from(error: tauri_plugin_autostart::Error) -> Self {
+        let message = error.to_string();
+        // The plugin only surfaces errors as text; file and registry
+        // permission failures are the ones worth telling apart
+        let lowercase = message.to_lowercase();
+        if lowercase.contains("permission denied") || lowercase.contains("access is denied") {
+            Self::PermissionDenied { message }
+        } else {
+            Self::Failed { message }
+        }
+    }
+}
+
+/// Whether the app is registered to launch at login.
+///
+/// A macOS registration still awaiting the user's approval counts as
+/// enabled: the user asked for it and only has to confirm.
+pub fn is_enabled(app: &AppHandle) -> Result<bool, AutostartError> {
+    #[cfg(target_os = "macos")]
+    if let Some(enabled) = service::is_enabled() {
+        return Ok(enabled);
+    }
+
+    Ok(app.autolaunch().is_enabled()?)
+}
+
+/// Registers or unregisters the app as a login item.
+///
+/// # Errors
+///
+/// Returns [`AutostartError::RequiresApproval`] when macOS registered the
+/// app but needs the user to allow it; the registration itself succeeded.
+pub fn set_enabled(app: &AppHandle, enabled: bool) -> Result<(), AutostartError> {
+    #[cfg(target_os = "macos")]
+    if let Some(result) = service::set_enabled(enabled) {
+        return result;
+    }
+
+    let launcher = app.autolaunch();
+    if enabled {
+        launcher.enable()?;
+    } else {
+        launcher.disable()?;
+    }
+    Ok(())
+}
+
+/// Brings the stored preference in line with the OS login item, which wins:
+/// if the user removed the login item outside the app, the preference is
+/// turned off rather than the item silently re-added.
+pub fn reconcile(app: &AppHandle) {
+    let enabled = match is_enabled(app) {
+        Ok(enabled) => enabled,
+        Err(e) => {
+            tracing::warn!("Could not read launch-at-login state: {}", e);
+            return;
+        }
+    };
+
+    let result = settings::update(app, |settings| {
+        if settings.startup.launch_at_login != enabled {
+            tracing::info!(
+                "Launch at login was {} outside the app; updating the preference",
+                if enabled { "enabled" } else { "disabled" }
+            );
+            settings.startup.launch_at_login = enabled;
+        }
+        Ok(())
+    });
+    if let Err(e) = result {
+        tracing::warn!("Failed to save launch-at-login state: {}", e);
+    }
+}
+
+/// Returns whether the app launches at login, as the OS reports it.
+///
+/// # Errors
+///
+/// Returns an error if the login item state cannot be read.
+#[tauri::command]
+pub fn get_autostart_enabled(app: AppHandle) -> Result<bool, AutostartError> {
+    is_enabled(&app)
+}
+
+/// Turns launch at login on or off, and whether a login launch starts
+/// hidden.
+///
+/// The preference is saved only once the OS accepted the change. A macOS
+/// registration awaiting the user's approval is saved and reported as
+/// [`AutostartError::RequiresApproval`] so the UI can point the user to
+/// System Settings.
+///
+/// # Errors
+///
+/// Returns a typed [`AutostartError`] if the login item cannot be changed,
+/// or if the preference cannot be saved (the login item is reverted then).
+#[tauri::command]
+pub fn set_autostart(
+    app: AppHandle,
+    enabled: bool,
+    start_hidden: bool,
+) -> Result<(), AutostartError> {
+    let registered = set_enabled(&app, enabled);
+    if matches!(&registered, Err(e) if *e != AutostartError::RequiresApproval) {
+        return registered;
+    }
+
+    let saved = settings::update(&app, |settings| {
+        settings.startup.launch_at_login = enabled;
+        settings.startup.start_hidden = start_hidden;
+        Ok(())
+    });
+    if let Err(e) = saved {
+        let _ = set_enabled(&app, !enabled);
+        return Err(e.into());
+    }
+
+    registered
+}
+
+/// `SMAppService` registration of the main app (macOS 13+).
+///
+/// Every function returns `None` when `SMAppService` doesn't exist on this
+/// system, so callers fall back to the LaunchAgent.
+#[cfg(target_os = "macos")]
+mod service {
+    use objc2::runtime::AnyClass;
+    use objc2_foundation::NSError;
+    use objc2_service_management::{SMAppService, SMAppServiceStatus};
+
+    use super::AutostartError;
+
+    /// `kSMErrorJobNotFound`: unregistering something that isn't registered.
+    const JOB_NOT_FOUND: isize = 6;
+    /// `kSMErrorAuthorizationFailure`.
+    const AUTHORIZATION_FAILURE: isize = 4;
+    /// `kSMErrorLaunchDeniedByUser`.
+    const LAUNCH_DENIED_BY_USER: isize = 11;
+    /// `kSMErrorAlreadyRegistered`.
+    const ALREADY_REGISTERED: isize = 12;
+
+    fn available() -> bool {
+        AnyClass::get("SMAppService").is_some()
+    }
+
+    pub fn is_enabled() -> Option<bool> {
+        if !available() {
+            return None;
+        }
+        let status = unsafe { SMAppService::mainAppService().status() };
+        Some(
+            status == SMAppServiceStatus::Enabled || status == SMAppServiceStatus::RequiresApproval,
+        )
+    }
+
+    pub fn set_enabled(enabled: bool) -> Option<Result<(), AutostartError>> {
+        if !available() {
+            return None;
+        }
+
+        let service = unsafe { SMAppService::mainAppService() };
+        let result = if enabled {
+            unsafe { service.registerAndReturnError() }
+        } else {
+            unsafe { service.unregisterAndReturnError() }
+        };
+
+        let result = match result {
+            Ok(()) => Ok(()),
+            Err(error) => match error.code() {
+                ALREADY_REGISTERED if enabled => Ok(()),
+                JOB_NOT_FOUND if !enabled => Ok(()),
+                AUTHORIZATION_FAILURE | LAUNCH_DENIED_BY_USER => {
+                    Err(AutostartError::PermissionDenied {
+                        message: describe(&error),
+                    })
+                }
+                _ => Err(AutostartError::Failed {
+                    message: describe(&error),
+                }),
+            },
+        };
+
+        let status = unsafe { service.status() };
+        if result.is_ok() && enabled && status == SMAppServiceStatus::RequiresApproval {
+            return Some(Err(AutostartError::RequiresApproval));
+        }
+        Some(result)
+    }
+
+    fn describe(error: &NSError) -> String {
+        error.localizedDescription().to_string()
+    }
+}
@@ -0,0 +1,338 @@
+//! Transcription benchmarks: how fast each provider is on this machine.
+//!
+//! [`run_benchmark`] transcribes the same clip several times with one
+//! provider and model and reports the latencies: median, 90th percentile,
+//! fastest and slowest, and the realtime factor (median latency over the
+//! clip's length, below 1 when transcription is faster than speech). Every
+//! result is kept in `benchmarks.json` in the app data directory with the
+//! machine it ran on, so [`list_benchmarks`] can show everything tried side
+//! by side.
+//!
+//! The app ships no audio, so the clip is one of the user's own retained
+//! recordings (see [`crate::history::audio`]): the one asked for, or the
+//! most recent. Results say which, since only runs on the same clip compare.
+//!
+//! Transcription happens in the main window, like dictation: the backend
+//! emits `benchmark:run` with the clip, the window transcribes it once per
+//! run and reports each timing back with [`report_benchmark_run`], and
+//! `benchmark:progress` is emitted after each. A benchmark isn't started
+//! while a dictation or meeting is under way, and only one runs at a time.
+//!
+//! Every provider is a cloud service or the mock, so there is no local model
+//! whose memory could be measured or that would need unloading afterwards.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager, State};
+use tokio::sync::oneshot;
+
+use crate::app_state::{self, Phase};
+use crate::history::HistoryState;
+use crate::meeting::MeetingState;
+use crate::transcription;
+use crate::AppHandle;
+
+/// File under the app data directory holding the results.
+const BENCHMARKS_FILE: &str = "benchmarks.json";
+
+/// Results kept; older ones are dropped.
+const MAX_RESULTS: usize = 100;
+
+/// Runs when none are asked for, and the most allowed.
+const DEFAULT_RUNS: u32 = 5;
+const MAX_RUNS: u32 = 20;
+
+/// How long one run may take before the benchmark is given up.
+const RUN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Managed state: the results so far, and the benchmark under way.
+pub struct BenchmarkState {
+    path: PathBuf,
+    active: Mutex<Option<Active>>,
+}
+
+/// A benchmark waiting for the main window's timings.
+struct Active {
+    id: String,
+    runs: u32,
+    latencies_ms: Vec<u64>,
+    errors: Vec<String>,
+    done: Option<oneshot::Sender<()>>,
+}
+
+/// Payload of `benchmark:run`: what the main window transcribes.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkRequest {
+    pub id: String,
+    pub provider: String,
+    /// The provider's default model when `None`.
+    pub model: Option<String>,
+    /// The clip, read with `read_audio_file`.
+    pub path: PathBuf,
+    pub runs: u32,
+}
+
+/// Payload of `benchmark:progress`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkProgress {
+    pub id: String,
+    /// Runs finished, and asked for.
+    pub completed: u32,
+    pub runs: u32,
+    /// How long the last one took, if it succeeded.
+    pub latency_ms: Option<u64>,
+}
+
+/// The machine a benchmark ran on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hardware {
+    pub os: String,
+    pub arch: String,
+    /// Logical CPUs.
+    pub cpus: u32,
+    pub app_version: String,
+}
+
+/// The outcome of one benchmark.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    pub id: String,
+    pub created_at_ms: i64,
+    pub provider: String,
+    pub model: Option<String>,
+    /// History entry whose recording was transcribed, and its length.
+    pub clip_history_id: i64,
+    pub clip_duration_ms: u64,
+    pub runs: u32,
+    /// Runs that failed, and the first error.
+    pub failures: u32,
+    pub error: Option<String>,
+    /// Over the runs that succeeded; `None` if none did.
+    pub median_ms: Option<u64>,
+    pub p90_ms: Option<u64>,
+    pub min_ms: Option<u64>,
+    pub max_ms: Option<u64>,
+    /// Median latency over clip length.
+    pub realtime_factor: Option<f64>,
+    pub hardware: Hardware,
+}
+
+impl BenchmarkState {
+    /// Results are kept under `data_dir`.
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join(BENCHMARKS_FILE),
+            active: Mutex::new(None),
+        }
+    }
+
+    fn results(&self) -> Vec<BenchmarkResult> {
+        match fs::read(&self.path) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable {}: {}", self.path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn save(&self, results: &[BenchmarkResult]) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(results).map_err(|e| e.to_string())?;
+        fs::write(&self.path, json).map_err(|e| e.to_string())
+    }
+}
+
+fn hardware(app: &AppHandle) -> Hardware {
+    Hardware {
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        cpus: std::thread::available_parallelism().map_or(1, |n| n.get() as u32),
+        app_version: app.package_info().version.to_string(),
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// The `percent`th percentile of sorted `values`, by nearest rank.
+fn percentile(sorted: &[u64], percent: usize) -> Option<u64> {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted.get(rank - 1).copied()
+}
+
+/// Clears the benchmark under way, if it is `id`.
+fn finish(state: &BenchmarkState, id: &str) -> Option<Active> {
+    let mut active = state.active.lock().unwrap();
+    if active.as_ref().is_some_and(|active| active.id == id) {
+        active.take()
+    } else {
+        None
+    }
+}
+
+/// Transcribes a retained recording `runs` times (5 by default, at most 20)
+/// with `provider` and `model`, and returns and keeps the timings. The clip
+/// is history entry `history_id`'s recording, or the most recent one.
+///
+/// # Errors
+///
+/// Returns an error if the provider is unknown, a dictation, meeting or
+/// other benchmark is under way, there is no recording to transcribe, a run
+/// takes longer than a minute or the result cannot be saved.
+#[tauri::command]
+pub async fn run_benchmark(
+    app: AppHandle,
+    provider: String,
+    model: Option<String>,
+    runs: Option<u32>,
+    history_id: Option<i64>,
+) -> Result<BenchmarkResult, String> {
+    if !transcription::list_providers(app.clone())
+        .iter()
+        .any(|info| info.id == provider)
+    {
+        return Err(format!("unknown provider \"{}\"", provider));
+    }
+    let dictating = matches!(
+        app_state::phase(&app),
+        Phase::Recording | Phase::Transcribing | Phase::AwaitingConfirmation | Phase::Pasting
+    );
+    if dictating || app.state::<MeetingState>().recording() {
+        return Err("a dictation is in progress".to_string());
+    }
+    let runs = runs.unwrap_or(DEFAULT_RUNS).clamp(1, MAX_RUNS);
+    let (clip_history_id, path, clip_duration_ms) = app
+        .state::<HistoryState>()
+        .recording(history_id)?
+        .ok_or("no retained recording to benchmark with; set retention.audio to keep some")?;
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let (done, finished) = oneshot::channel();
+    let state = app.state::<BenchmarkState>();
+    {
+        let mut active = state.active.lock().unwrap();
+        if active.is_some() {
+            return Err("a benchmark is already running".to_string());
+        }
+        *active = Some(Active {
+            id: id.clone(),
+            runs,
+            latencies_ms: Vec::new(),
+            errors: Vec::new(),
+            done: Some(done),
+        });
+    }
+
+    tracing::info!(%provider, ?model, runs, "Benchmark started");
+    let request = BenchmarkRequest {
+        id: id.clone(),
+        provider: provider.clone(),
+        model: model.clone(),
+        path,
+        runs,
+    };
+    let _ = app.emit("benchmark:run", request);
+    let timeout = RUN_TIMEOUT * runs;
+    let waited = tokio::time::timeout(timeout, finished).await;
+    let active = finish(&state, &id).ok_or("the benchmark was interrupted")?;
+    if waited.is_err() {
+        return Err("the benchmark timed out".to_string());
+    }
+
+    let mut latencies = active.latencies_ms;
+    latencies.sort_unstable();
+    let median_ms = percentile(&latencies, 50);
+    let result = BenchmarkResult {
+        id,
+        created_at_ms: now_ms(),
+        provider,
+        model,
+        clip_history_id,
+        clip_duration_ms,
+        runs,
+        failures: active.errors.len() as u32,
+        error: active.errors.into_iter().next(),
+        median_ms,
+        p90_ms: percentile(&latencies, 90),
+        min_ms: latencies.first().copied(),
+        max_ms: latencies.last().copied(),
+        realtime_factor: median_ms.map(|ms| ms as f64 / clip_duration_ms as f64),
+        hardware: hardware(&app),
+    };
+    tracing::info!(median_ms = ?result.median_ms, failures = result.failures, "Benchmark finished");
+
+    let mut results = state.results();
+    results.push(result.clone());
+    let excess = results.len().saturating_sub(MAX_RESULTS);
+    results.drain(..excess);
+    state.save(&results)?;
+    Ok(result)
+}
+
+/// Reports one run of benchmark `id`: how long transcribing the clip took,
+/// or why it failed. Called by the main window.
+///
+/// # Errors
+///
+/// Returns an error if `id` isn't the benchmark under way.
+#[tauri::command]
+pub fn report_benchmark_run(
+    app: AppHandle,
+    state: State<'_, BenchmarkState>,
+    id: String,
+    latency_ms: Option<u64>,
+    error: Option<String>,
+) -> Result<(), String> {
+    let mut guard = state.active.lock().unwrap();
+    let active = guard
+        .as_mut()
+        .filter(|active| active.id == id)
+        .ok_or("no such benchmark")?;
+    match latency_ms {
+        Some(ms) => active.latencies_ms.push(ms),
+        None => active
+            .errors
+            .push(error.unwrap_or_else(|| "transcription failed".to_string())),
+    }
+
+    let completed = (active.latencies_ms.len() + active.errors.len()) as u32;
+    let _ = app.emit(
+        "benchmark:progress",
+        BenchmarkProgress {
+            id,
+            completed,
+            runs: active.runs,
+            latency_ms,
+        },
+    );
+    if completed >= active.runs {
+        if let Some(done) = active.done.take() {
+            let _ = done.send(());
+        }
+    }
+    Ok(())
+}
+
+/// Returns the results kept, oldest first.
+#[tauri::command]
+pub fn list_benchmarks(state: State<'_, BenchmarkState>) -> Vec<BenchmarkResult> {
+    state.results()
+}
+
+/// Deletes the results kept.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written.
+#[tauri::command]
+pub fn clear_benchmarks(state: State<'_, BenchmarkState>) -> Result<(), String> {
+    state.save(&[])
+}
@@ -0,0 +1,152 @@
+//! Stopping the work still under way for a cancelled dictation.
+//!
+//! Cancelling a dictation (Escape, `cancel_transcription`, a click outside
+//! the pill, a restart from the hotkey) ends its session, so every later
+//! command about it fails with `stale_session`: nothing is pasted, and
+//! nothing is added to history, which is only written after a paste. What
+//! was already running for it is stopped as well. Each session owns a
+//! [`CancelToken`], tripped when the dictation goes to `Cancelled`; the
+//! stages after the recording take it with [`token`] and check it between
+//! steps ([`CancelToken::check`]), and requests such as the rewrite run
+//! under [`CancelToken::run`], which drops them, aborting the connection,
+//! as soon as it trips.
+//!
+//! Its crash checkpoint is cleared too (see [`crate::recovery`]), so a
+//! cancelled dictation is never offered back at the next start; only a
+//! recording ended by sleep keeps it, to be offered on wake (see
+//! [`crate::power`]). Every other cancelled dictation is announced as
+//! `transcription:cancelled`, whatever phase it was in, so the webview
+//! stops a live transcription too.
+//!
+//! # Architecture Decision
+//!
+//! The transcription itself runs in the webview, which stops the provider
+//! (closing its connection, or ignoring the result of a file upload the
+//! SDK can't abort) when it sees `transcription:cancelled`. There is no
+//! local model to stop in the backend.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tauri::{Emitter, Manager};
+use tokio::sync::Notify;
+
+use crate::app_state::{self, Phase, SessionError, SessionEvent};
+use crate::recovery::RecoveryState;
+use crate::AppHandle;
+
+/// Tripped once the dictation `session_id` is cancelled.
+#[derive(Debug)]
+pub struct CancelToken {
+    session_id: String,
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelToken {
+    fn new(session_id: &str) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            cancelled: AtomicBool::new(false),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Completes once the token trips.
+    pub async fn cancelled(&self) {
+        loop {
+            // Created before the check, so a trip in between still wakes it
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Runs `work` to completion, or until the token trips: then `work` is
+    /// dropped and `None` returned.
+    pub async fn run<F: Future>(&self, work: F) -> Option<F::Output> {
+        tokio::select! {
+            output = work => Some(output),
+            () = self.cancelled() => None,
+        }
+    }
+
+    /// Checks, between two stages, that the dictation still goes on.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SessionError::StaleSession`] if it was cancelled or has
+    /// ended otherwise.
+    pub fn check(&self, app: &AppHandle) -> Result<(), SessionError> {
+        app_state::check_session(app, &self.session_id)?;
+        if self.is_cancelled() {
+            // Cancelled, but not back to idle yet
+            return Err(SessionError::StaleSession {
+                session_id: self.session_id.clone(),
+                current: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Managed state: the token of the latest session that asked for one or
+/// was cancelled.
+#[derive(Default)]
+pub struct CancelState(Mutex<Option<Arc<CancelToken>>>);
+
+/// The token of the dictation `session_id`.
+///
+/// # Errors
+///
+/// Returns [`SessionError::StaleSession`] if it isn't the dictation under
+/// way.
+pub fn token(app: &AppHandle, session_id: &str) -> Result<Arc<CancelToken>, SessionError> {
+    app_state::check_session(app, session_id)?;
+    let state = app.state::<CancelState>();
+    let mut current = state.0.lock().unwrap();
+    Ok(for_session(&mut current, session_id))
+}
+
+/// The token in `current` if it is the one of `session_id`, else a new one
+/// replacing it.
+fn for_session(current: &mut Option<Arc<CancelToken>>, session_id: &str) -> Arc<CancelToken> {
+    match current {
+        Some(token) if token.session_id == session_id => token.clone(),
+        _ => current
+            .insert(Arc::new(CancelToken::new(session_id)))
+            .clone(),
+    }
+}
+
+/// Trips the token of the dictation `session_id`, which was just cancelled
+/// for `reason` while it was `from`. Called by the state machine.
+pub(crate) fn cancel(app: &AppHandle, session_id: &str, from: Phase, reason: &str) {
+    // Created if nothing asked for it yet, so whatever does gets it tripped
+    let state = app.state::<CancelState>();
+    for_session(&mut state.0.lock().unwrap(), session_id).cancel();
+    // A recording ended by sleep keeps its checkpoint, and is announced as
+    // `recording:interrupted`
+    if reason != crate::power::SLEEP {
+        app.state::<RecoveryState>().clear_active();
+        tracing::info!(session_id, ?from, "Transcription cancelled");
+        let _ = app.emit(
+            "transcription:cancelled",
+            SessionEvent {
+                session_id: Some(session_id.to_string()),
+            },
+        );
+    }
+}
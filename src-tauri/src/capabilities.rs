@@ -0,0 +1,174 @@
+//! What pasting can do on this system.
+//!
+//! [`get_paste_capabilities`] tells the frontend which insertion methods
+//! work here, so it can adapt: not offer direct insertion where there is
+//! none, tell a Wayland user that auto-paste needs `wtype` or `ydotool`.
+//! It is built from the same lookups the paste itself uses
+//! ([`keystroke::injector`], [`accessibility::is_trusted`]) and recomputed on
+//! every call, since tools get installed and permissions granted while the
+//! app runs. On Linux it also names the clipboard manager running, if any
+//! (see [`clipboard_manager`]), since one can get in the way of a paste.
+
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::accessibility;
+use crate::clipboard_manager::{self, ClipboardManager};
+use crate::keystroke::{self, SessionType, Tool};
+
+/// A way of getting text into the focused app.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InsertionMethod {
+    /// Setting the focused field's value through the accessibility API.
+    AxInsert,
+    /// Writing the clipboard and sending the paste shortcut.
+    ClipboardPaste,
+    /// Typing the text key by key.
+    KeystrokeTyping,
+}
+
+/// A permission an insertion method or capture needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// macOS Accessibility access.
+    Accessibility,
+    /// Access to `/dev/uinput`, which `ydotool`'s daemon needs.
+    Uinput,
+    /// macOS Screen Recording access, which capturing system audio needs
+    /// (see [`crate::loopback`]).
+    ScreenRecording,
+}
+
+/// A permission and whether it is granted; `None` when that can't be told.
+#[derive(Debug, Clone, Serialize)]
+pub struct PermissionStatus {
+    pub permission: Permission,
+    pub granted: Option<bool>,
+}
+
+/// Whether an insertion method can be used.
+#[derive(Debug, Clone, Serialize)]
+pub struct MethodSupport {
+    pub method: InsertionMethod,
+    /// Whether it works right now: supported, with its tool installed and
+    /// its permissions granted.
+    pub available: bool,
+    /// Why not, or how it works, for the user.
+    pub detail: String,
+    /// The permissions it needs.
+    pub permissions: Vec<PermissionStatus>,
+}
+
+/// A keystroke tool and where it was found.
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectedTool {
+    pub tool: Tool,
+    /// `None` when it isn't installed.
+    pub path: Option<PathBuf>,
+}
+
+/// The result of [`get_paste_capabilities`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PasteCapabilities {
+    /// `macos`, `windows`, `linux`, ...
+    pub platform: &'static str,
+    pub session_type: SessionType,
+    pub methods: Vec<MethodSupport>,
+    /// The keystroke tools the app can use on this platform.
+    pub tools: Vec<DetectedTool>,
+    /// The clipboard manager running, on Linux.
+    pub clipboard_manager: Option<ClipboardManager>,
+}
+
+/// What the paste shortcut needs and whether it has it.
+fn clipboard_paste(session: SessionType, injector: Option<Tool>) -> MethodSupport {
+    let mut permissions = Vec::new();
+    let (available, detail) = match injector {
+        Some(Tool::Osascript) => {
+            let trusted = accessibility::is_trusted();
+            permissions.push(PermissionStatus {
+                permission: Permission::Accessibility,
+                granted: Some(trusted),
+            });
+            if trusted {
+                (true, "Pastes with Cmd+V through System Events".to_string())
+            } else {
+                (
+                    false,
+                    "Grant accessibility access in System Settings to paste".to_string(),
+                )
+            }
+        }
+        Some(Tool::Ydotool) => {
+            permissions.push(PermissionStatus {
+                permission: Permission::Uinput,
+                granted: None,
+            });
+            (
+                true,
+                "Pastes with Ctrl+V through ydotool; its daemon (ydotoold) must be running"
+                    .to_string(),
+            )
+        }
+        Some(tool) => (
+            true,
+            format!("Pastes with Ctrl+V through {}", tool.program()),
+        ),
+        None if session == SessionType::Wayland => (
+            false,
+            "Install wtype or ydotool to paste automatically; until then paste with Ctrl+V"
+                .to_string(),
+        ),
+        None if session == SessionType::X11 => (
+            false,
+            "Install xdotool to paste automatically; until then paste with Ctrl+V".to_string(),
+        ),
+        None => (
+            false,
+            "Text is copied to the clipboard; paste it yourself".to_string(),
+        ),
+    };
+    MethodSupport {
+        method: InsertionMethod::ClipboardPaste,
+        available,
+        detail,
+        permissions,
+    }
+}
+
+/// A method the app doesn't implement yet.
+fn unsupported(method: InsertionMethod) -> MethodSupport {
+    MethodSupport {
+        method,
+        available: false,
+        detail: "Not supported yet".to_string(),
+        permissions: Vec::new(),
+    }
+}
+
+/// Reports what pasting can do on this system, freshly detected.
+#[tauri::command]
+pub fn get_paste_capabilities() -> PasteCapabilities {
+    let session_type = keystroke::session_type();
+    let tools = Tool::all()
+        .iter()
+        .map(|&tool| DetectedTool {
+            tool,
+            path: tool.find(),
+        })
+        .collect();
+    PasteCapabilities {
+        platform: std::env::consts::OS,
+        session_type,
+        methods: vec![
+            unsupported(InsertionMethod::AxInsert),
+            clipboard_paste(session_type, keystroke::injector()),
+            unsupported(InsertionMethod::KeystrokeTyping),
+        ],
+        tools,
+        clipboard_manager: clipboard_manager::detect(),
+    }
+}
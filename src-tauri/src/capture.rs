@@ -0,0 +1,507 @@
+//! Capture quality: the format recordings are made in.
+//!
+//! Dictations and meetings each have their own [`CaptureQuality`]:
+//! `capture.dictation` (16 kHz mono by default, which is all transcription
+//! needs) and `capture.meeting` (48 kHz mono, for recordings worth keeping).
+//! The frontend asks the microphone for that sample rate and channel count,
+//! and the browser opens it with the nearest configuration the device
+//! supports. Whatever the device gives, the recording's audio graph runs at
+//! the requested rate and mixes to the requested channels, so the recording
+//! always comes out as asked; only the microphone side may differ. The
+//! frontend reports both with [`report_capture_format`], which records them
+//! on the dictation, logs them and emits `audio:capture-format`.
+//!
+//! Recordings are WebM with Opus, which has no bit depth: `bitrate_kbps` is
+//! what sets how much detail is kept. Transcription takes the recording as
+//! it is, whatever its rate, and history retention keeps it that way.
+//!
+//! Audio decoded natively is brought to a capture quality here, the way the
+//! audio graph does it in the webview: [`mix_channels`] mixes channels by
+//! the same rules, and a [`Converter`] resamples after it. Audio files given
+//! to transcribe come out in `capture.dictation` (see [`crate::audio_file`]),
+//! and system audio is mixed down to mono (see [`crate::loopback`]).
+//!
+//! # Architecture Decision
+//!
+//! The quality that can be asked for is checked when the settings are
+//! saved: rates Opus handles, one or two channels, and a bitrate Opus can
+//! encode that many channels at. Which configurations a microphone supports
+//! is only known once it is opened, and the browser never fails to open one
+//! over a rate it doesn't support, so the device side is reported rather
+//! than checked.
+
+use rubato::{FftFixedIn, Resampler};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use crate::dictation::DictationState;
+use crate::AppHandle;
+
+/// Sample rates recordings can be made at.
+pub const SAMPLE_RATES: &[u32] = &[8_000, 12_000, 16_000, 24_000, 48_000];
+
+/// Bounds for `bitrate_kbps`, the range Opus encodes.
+const MIN_BITRATE_KBPS: u32 = 6;
+const MAX_BITRATE_KBPS: u32 = 510;
+
+/// Lowest bitrate per channel for stereo to be worth recording.
+const MIN_STEREO_KBPS_PER_CHANNEL: u32 = 16;
+
+/// Frames a [`Converter`] resamples at a time.
+const RESAMPLE_CHUNK_FRAMES: usize = 1024;
+
+/// The format of a recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureQuality {
+    /// One of [`SAMPLE_RATES`].
+    pub sample_rate: u32,
+    /// 1 (mono) or 2 (stereo).
+    pub channels: u16,
+    /// Opus bitrate of the recording.
+    pub bitrate_kbps: u32,
+}
+
+impl CaptureQuality {
+    /// Checks that the rate, channels and bitrate can be recorded together.
+    pub fn validate(&self) -> Result<(), String> {
+        if !SAMPLE_RATES.contains(&self.sample_rate) {
+            let rates: Vec<String> = SAMPLE_RATES.iter().map(u32::to_string).collect();
+            return Err(format!("sample_rate must be one of: {}", rates.join(", ")));
+        }
+        if !(1..=2).contains(&self.channels) {
+            return Err("channels must be 1 or 2".to_string());
+        }
+        if !(MIN_BITRATE_KBPS..=MAX_BITRATE_KBPS).contains(&self.bitrate_kbps) {
+            return Err(format!(
+                "bitrate_kbps must be between {} and {}",
+                MIN_BITRATE_KBPS, MAX_BITRATE_KBPS
+            ));
+        }
+        if self.channels == 2 && self.bitrate_kbps < 2 * MIN_STEREO_KBPS_PER_CHANNEL {
+            return Err(format!(
+                "stereo needs a bitrate_kbps of at least {}",
+                2 * MIN_STEREO_KBPS_PER_CHANNEL
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Capture quality of each kind of recording.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureSettings {
+    pub dictation: CaptureQuality,
+    pub meeting: CaptureQuality,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            dictation: CaptureQuality {
+                sample_rate: 16_000,
+                channels: 1,
+                bitrate_kbps: 32,
+            },
+            meeting: CaptureQuality {
+                sample_rate: 48_000,
+                channels: 1,
+                bitrate_kbps: 64,
+            },
+        }
+    }
+}
+
+/// What a recording was actually made in, as the frontend reports it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureFormat {
+    /// The recording's format, the audio graph's.
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// What the microphone was opened with, when the browser says.
+    pub device_sample_rate: Option<u32>,
+    pub device_channels: Option<u16>,
+}
+
+/// Payload of `audio:capture-format`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureFormatReported {
+    /// The dictation's session id, or the meeting's id.
+    pub id: String,
+    #[serde(flatten)]
+    pub format: CaptureFormat,
+}
+
+/// Records the format the dictation or meeting `id` is recorded in: kept
+/// with the dictation's metadata, logged and announced as
+/// `audio:capture-format`.
+#[tauri::command]
+pub fn report_capture_format(app: AppHandle, id: String, format: CaptureFormat) {
+    if let Some(dictation) = app
+        .state::<DictationState>()
+        .0
+        .lock()
+        .unwrap()
+        .as_mut()
+        .filter(|dictation| dictation.session_id == id)
+    {
+        dictation.capture = Some(format.clone());
+    }
+    tracing::info!(
+        %id,
+        sample_rate = format.sample_rate,
+        channels = format.channels,
+        device_sample_rate = ?format.device_sample_rate,
+        device_channels = ?format.device_channels,
+        "Capture format"
+    );
+    let _ = app.emit("audio:capture-format", CaptureFormatReported { id, format });
+}
+
+/// Appends interleaved `samples` of `from` channels to `out`, mixed to
+/// `to` channels by the rules of the webview's audio graph (Web Audio's
+/// "speakers" mixing): mono is copied to every channel, anything mixed down
+/// to mono is averaged, and otherwise the first `to` channels are kept, any
+/// missing one silent.
+pub fn mix_channels(samples: &[f32], from: usize, to: usize, out: &mut Vec<f32>) {
+    let from = from.max(1);
+    out.reserve(samples.len() / from * to);
+    for frame in samples.chunks_exact(from) {
+        if from == to {
+            out.extend_from_slice(frame);
+        } else if to == 1 {
+            out.push(frame.iter().sum::<f32>() / from as f32);
+        } else if from == 1 {
+            out.resize(out.len() + to, frame[0]);
+        } else {
+            out.extend((0..to).map(|channel| frame.get(channel).copied().unwrap_or(0.0)));
+        }
+    }
+}
+
+/// Converts interleaved audio to a [`CaptureQuality`]'s channels (see
+/// [`mix_channels`]) and rate, in pieces of any size: what doesn't fill a
+/// chunk of the resampler is held until more comes, or [`finish`] is
+/// called.
+///
+/// [`finish`]: Converter::finish
+pub struct Converter {
+    channels_in: usize,
+    channels: usize,
+    rate_in: u32,
+    rate: u32,
+    /// `None` when the rate stays the same.
+    resampler: Option<FftFixedIn<f32>>,
+    /// Mixed samples waiting for a whole chunk, one `Vec` per channel.
+    pending: Vec<Vec<f32>>,
+    /// Resampled frames still to drop: the resampler's delay.
+    delay: usize,
+    frames_in: u64,
+    frames_out: u64,
+}
+
+impl Converter {
+    /// A converter of audio at `rate_in` with `channels_in` to `quality`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rates can't be converted between.
+    pub fn new(rate_in: u32, channels_in: usize, quality: &CaptureQuality) -> Result<Self, String> {
+        let channels = usize::from(quality.channels);
+        let resampler = if rate_in == quality.sample_rate {
+            None
+        } else {
+            let resampler = FftFixedIn::new(
+                rate_in as usize,
+                quality.sample_rate as usize,
+                RESAMPLE_CHUNK_FRAMES,
+                2,
+                channels,
+            )
+            .map_err(|e| format!("resampling from {} Hz: {}", rate_in, e))?;
+            Some(resampler)
+        };
+        Ok(Self {
+            channels_in,
+            channels,
+            rate_in,
+            rate: quality.sample_rate,
+            delay: resampler
+                .as_ref()
+                .map_or(0, |resampler| resampler.output_delay()),
+            resampler,
+            pending: vec![Vec::new(); channels],
+            frames_in: 0,
+            frames_out: 0,
+        })
+    }
+
+    /// Converts `samples`, appending what is converted so far to `out`,
+    /// interleaved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if resampling fails.
+    pub fn push(&mut self, samples: &[f32], out: &mut Vec<f32>) -> Result<(), String> {
+        let mut mixed = Vec::new();
+        mix_channels(samples, self.channels_in, self.channels, &mut mixed);
+        let frames = (mixed.len() / self.channels) as u64;
+        self.frames_in += frames;
+        let Some(resampler) = self.resampler.as_mut() else {
+            self.frames_out += frames;
+            out.extend_from_slice(&mixed);
+            return Ok(());
+        };
+        for frame in mixed.chunks_exact(self.channels) {
+            for (pending, &sample) in self.pending.iter_mut().zip(frame) {
+                pending.push(sample);
+            }
+        }
+        while self.pending[0].len() >= resampler.input_frames_next() {
+            let needed = resampler.input_frames_next();
+            let chunk: Vec<Vec<f32>> = self
+                .pending
+                .iter_mut()
+                .map(|pending| pending.drain(..needed).collect())
+                .collect();
+            let resampled = resampler.process(&chunk, None).map_err(|e| e.to_string())?;
+            interleave(
+                &resampled,
+                &mut self.delay,
+                &mut self.frames_out,
+                u64::MAX,
+                out,
+            );
+        }
+        Ok(())
+    }
+
+    /// Converts what is held back, once all the audio was pushed, appending
+    /// it to `out`. The audio converted in all is as long as was pushed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if resampling fails.
+    pub fn finish(mut self, out: &mut Vec<f32>) -> Result<(), String> {
+        let Some(resampler) = self.resampler.as_mut() else {
+            return Ok(());
+        };
+        let expected = self.frames_in * u64::from(self.rate) / u64::from(self.rate_in);
+        let mut rest = Some(std::mem::take(&mut self.pending));
+        while self.frames_out < expected {
+            let resampled = resampler
+                .process_partial(rest.take().as_deref(), None)
+                .map_err(|e| e.to_string())?;
+            if resampled.iter().all(Vec::is_empty) {
+                break;
+            }
+            interleave(
+                &resampled,
+                &mut self.delay,
+                &mut self.frames_out,
+                expected,
+                out,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Appends `resampled`, one `Vec` per channel, to `out`, interleaved:
+/// after dropping the first `delay` frames, and until `frames_out` reaches
+/// `limit`.
+fn interleave(
+    resampled: &[Vec<f32>],
+    delay: &mut usize,
+    frames_out: &mut u64,
+    limit: u64,
+    out: &mut Vec<f32>,
+) {
+    let frames = resampled.first().map_or(0, Vec::len);
+    let skipped = (*delay).min(frames);
+    *delay -= skipped;
+    for index in skipped..frames {
+        if *frames_out >= limit {
+            break;
+        }
+        out.extend(resampled.iter().map(|channel| channel[index]));
+        *frames_out += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::harness::Harness;
+
+    fn quality(sample_rate: u32, channels: u16, bitrate_kbps: u32) -> CaptureQuality {
+        CaptureQuality {
+            sample_rate,
+            channels,
+            bitrate_kbps,
+        }
+    }
+
+    #[test]
+    fn only_rates_opus_handles_are_accepted() {
+        for &rate in SAMPLE_RATES {
+            assert_eq!(quality(rate, 1, 32).validate(), Ok(()), "{}", rate);
+        }
+        for rate in [0, 11_025, 22_050, 44_100, 96_000] {
+            let error = quality(rate, 1, 32).validate().unwrap_err();
+            assert!(error.starts_with("sample_rate must be one of"), "{}", rate);
+        }
+    }
+
+    #[test]
+    fn stereo_needs_the_bitrate_for_two_channels() {
+        assert_eq!(quality(48_000, 2, 32).validate(), Ok(()));
+        assert!(quality(48_000, 2, 31).validate().is_err());
+        assert!(quality(48_000, 3, 64).validate().is_err());
+        assert!(quality(48_000, 1, 5).validate().is_err());
+        assert!(quality(48_000, 1, 511).validate().is_err());
+        let defaults = CaptureSettings::default();
+        assert_eq!(defaults.dictation.validate(), Ok(()));
+        assert_eq!(defaults.meeting.validate(), Ok(()));
+    }
+
+    #[test]
+    fn a_device_at_another_rate_is_recorded_with_the_dictation() {
+        let harness = Harness::new("capture-format");
+        let session_id = harness.press_hotkey();
+        // A 44.1 kHz stereo headset, recorded at the dictation's 16 kHz mono
+        let format = CaptureFormat {
+            sample_rate: 16_000,
+            channels: 1,
+            device_sample_rate: Some(44_100),
+            device_channels: Some(2),
+        };
+        let report = json!({ "id": session_id, "format": format });
+        harness.invoke("report_capture_format", report).unwrap();
+
+        let dictation = harness.handle().state::<DictationState>();
+        let capture = dictation
+            .0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .capture
+            .clone();
+        assert_eq!(capture, Some(format));
+        assert_eq!(
+            harness.events("audio:capture-format"),
+            [json!({
+                "id": session_id,
+                "sample_rate": 16_000,
+                "channels": 1,
+                "device_sample_rate": 44_100,
+                "device_channels": 2,
+            })]
+        );
+    }
+
+    #[test]
+    fn a_format_for_another_recording_is_only_announced() {
+        let harness = Harness::new("capture-format-other");
+        harness.press_hotkey();
+        let format = CaptureFormat {
+            sample_rate: 48_000,
+            channels: 1,
+            device_sample_rate: None,
+            device_channels: None,
+        };
+        // A meeting's, by its id
+        let report = json!({ "id": "meeting-1", "format": format });
+        harness.invoke("report_capture_format", report).unwrap();
+
+        let dictation = harness.handle().state::<DictationState>();
+        assert_eq!(dictation.0.lock().unwrap().as_ref().unwrap().capture, None);
+        assert_eq!(harness.events("audio:capture-format").len(), 1);
+    }
+
+    #[test]
+    fn channels_are_mixed_like_the_audio_graph() {
+        let stereo = [0.25, 0.75, -1.0, 0.0];
+        let mut out = Vec::new();
+        mix_channels(&stereo, 2, 1, &mut out);
+        assert_eq!(out, [0.5, -0.5]);
+
+        out.clear();
+        mix_channels(&[0.5, -0.5], 1, 2, &mut out);
+        assert_eq!(out, [0.5, 0.5, -0.5, -0.5]);
+
+        out.clear();
+        mix_channels(&stereo, 2, 2, &mut out);
+        assert_eq!(out, stereo);
+
+        // 5.1 to stereo keeps the front left and right
+        out.clear();
+        mix_channels(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 6, 2, &mut out);
+        assert_eq!(out, [0.1, 0.2]);
+    }
+
+    /// `frames` of a 440 Hz tone at `rate` in `channels`, interleaved.
+    fn tone(rate: u32, channels: usize, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|frame| {
+                let t = frame as f32 / rate as f32;
+                let sample = (t * 440.0 * std::f32::consts::TAU).sin() * 0.5;
+                std::iter::repeat_n(sample, channels)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn audio_at_the_quality_is_passed_through() {
+        let mut converter = Converter::new(16_000, 1, &quality(16_000, 1, 32)).unwrap();
+        let samples = tone(16_000, 1, 1_000);
+        let mut out = Vec::new();
+        converter.push(&samples, &mut out).unwrap();
+        converter.finish(&mut out).unwrap();
+        assert_eq!(out, samples);
+    }
+
+    #[test]
+    fn resampled_audio_is_as_long_as_was_pushed() {
+        // 44.1 kHz stereo in uneven pieces, to 16 kHz mono
+        let mut converter = Converter::new(44_100, 2, &quality(16_000, 1, 32)).unwrap();
+        let samples = tone(44_100, 2, 44_100);
+        let mut out = Vec::new();
+        for piece in samples.chunks(2 * 777) {
+            converter.push(piece, &mut out).unwrap();
+        }
+        converter.finish(&mut out).unwrap();
+        assert_eq!(out.len(), 16_000);
+    }
+
+    #[test]
+    fn the_resamplers_delay_is_dropped() {
+        // Half a second of silence, then half a second at 0.5
+        let mut samples = vec![0.0; 24_000];
+        samples.resize(48_000, 0.5);
+        let mut converter = Converter::new(48_000, 1, &quality(16_000, 1, 32)).unwrap();
+        let mut out = Vec::new();
+        converter.push(&samples, &mut out).unwrap();
+        converter.finish(&mut out).unwrap();
+        assert_eq!(out.len(), 16_000);
+        // The step is where it was, not the resampler's delay later
+        assert!(out[..7_950].iter().all(|sample| sample.abs() < 0.05));
+        assert!(out[8_050..15_000]
+            .iter()
+            .all(|sample| (sample - 0.5).abs() < 0.05));
+    }
+
+    #[test]
+    fn audio_shorter_than_a_chunk_is_resampled_on_finish() {
+        let mut converter = Converter::new(48_000, 1, &quality(16_000, 1, 32)).unwrap();
+        let mut out = Vec::new();
+        converter.push(&tone(48_000, 1, 300), &mut out).unwrap();
+        assert!(out.is_empty());
+        converter.finish(&mut out).unwrap();
+        assert_eq!(out.len(), 100);
+    }
+}
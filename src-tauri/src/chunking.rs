@@ -0,0 +1,388 @@
+//! Splitting very large pastes into chunks.
+//!
+//! A long meeting transcript is one paste like any other, but some apps
+//! stall or drop text when tens of thousands of characters arrive at once.
+//! Above `paste_chunking.threshold_chars`, [`crate::paste::run`] pastes the
+//! text in chunks of at most `paste_chunking.chunk_chars`, one after the
+//! other, waiting `paste_chunking.delay_ms` between them for the app to
+//! catch up.
+//!
+//! [`split`] cuts where the text would be cut by hand: at the last
+//! paragraph break that fits, or failing that the last line break, word
+//! boundary or, for a paragraph without spaces, grapheme boundary. Only a
+//! cut in the second half of the chunk is preferred that way, so a short
+//! paragraph doesn't become a chunk of its own. A chunk never ends in the
+//! middle of a grapheme cluster (an emoji sequence or a letter with its
+//! accents), and never inside a markdown code fence: a fenced block longer
+//! than `chunk_chars` becomes a chunk of its own rather than being cut.
+//!
+//! # Architecture Decision
+//!
+//! Sizes are counted in `char`s, like the other text limits in the
+//! settings. The chunks are slices of the text, so pasting them all in order
+//! inserts exactly the text, with nothing added or lost at the cuts.
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Bounds for `paste_chunking.chunk_chars`.
+const MIN_CHUNK_CHARS: usize = 500;
+const MAX_CHUNK_CHARS: usize = 100_000;
+
+/// Longest `paste_chunking.delay_ms`.
+const MAX_DELAY_MS: u64 = 5_000;
+
+/// Chunked paste settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChunkingSettings {
+    /// Texts longer than this are pasted in chunks. `0` never chunks.
+    pub threshold_chars: usize,
+    /// Longest chunk, unless a code fence is longer.
+    pub chunk_chars: usize,
+    /// Wait between two chunks.
+    pub delay_ms: u64,
+}
+
+impl Default for ChunkingSettings {
+    fn default() -> Self {
+        Self {
+            threshold_chars: 20_000,
+            chunk_chars: 4_000,
+            delay_ms: 150,
+        }
+    }
+}
+
+impl ChunkingSettings {
+    /// Checks the chunk size against the threshold, and the delay.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_CHUNK_CHARS..=MAX_CHUNK_CHARS).contains(&self.chunk_chars) {
+            return Err(format!(
+                "chunk_chars must be between {} and {}",
+                MIN_CHUNK_CHARS, MAX_CHUNK_CHARS
+            ));
+        }
+        if self.threshold_chars != 0 && self.threshold_chars < self.chunk_chars {
+            return Err("threshold_chars must be 0 or at least chunk_chars".to_string());
+        }
+        if self.delay_ms > MAX_DELAY_MS {
+            return Err(format!("delay_ms must be at most {}", MAX_DELAY_MS));
+        }
+        Ok(())
+    }
+
+    /// Whether `text` is long enough to be pasted in chunks.
+    pub fn applies_to(&self, text: &str) -> bool {
+        self.threshold_chars != 0 && text.chars().count() > self.threshold_chars
+    }
+}
+
+/// Where a chunk may end, from the best place to cut to the worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Cut {
+    Grapheme,
+    Word,
+    Line,
+    Paragraph,
+}
+
+/// Splits `text` into chunks of at most `chunk_chars` characters (see the
+/// module docs). Joined, the chunks are `text`.
+pub fn split(text: &str, chunk_chars: usize) -> Vec<&str> {
+    let cuts = cuts(text);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut next = 0;
+    while start < text.len() {
+        // Byte offset `chunk_chars` characters on, or the end
+        let limit = text[start..]
+            .char_indices()
+            .nth(chunk_chars)
+            .map_or(text.len(), |(offset, _)| start + offset);
+        if limit == text.len() {
+            chunks.push(&text[start..]);
+            break;
+        }
+        // Byte offset half a chunk on
+        let half = text[start..]
+            .char_indices()
+            .nth(chunk_chars / 2)
+            .map_or(limit, |(offset, _)| start + offset);
+        while next < cuts.len() && cuts[next].0 <= start {
+            next += 1;
+        }
+        let fitting = &cuts[next..][..cuts[next..].partition_point(|&(at, _)| at <= limit)];
+        // The best kind of cut that fits from `from`, the last of its kind
+        let best = |from: usize| {
+            fitting
+                .iter()
+                .filter(|&&(at, _)| at >= from)
+                .max_by_key(|&&(at, cut)| (cut, at))
+        };
+        let end = match best(half).or_else(|| best(start)) {
+            Some(&(at, _)) => at,
+            // Inside a code fence: the fence goes whole
+            None => cuts.get(next).map_or(text.len(), |&(at, _)| at),
+        };
+        chunks.push(&text[start..end]);
+        start = end;
+    }
+    chunks
+}
+
+/// Every offset a chunk may end at, in order, with how good a place it is.
+/// Nothing inside a code fence.
+fn cuts(text: &str) -> Vec<(usize, Cut)> {
+    let fences = fences(text);
+    let in_fence = |at: usize| fences.iter().any(|&(from, to)| from < at && at < to);
+
+    let lines = text.split_inclusive('\n').scan(0, |end, line| {
+        *end += line.len();
+        Some((*end, line))
+    });
+    let mut cuts: Vec<(usize, Cut)> = text
+        .grapheme_indices(true)
+        .map(|(at, _)| (at, Cut::Grapheme))
+        .chain(
+            text.split_word_bound_indices()
+                .map(|(at, _)| (at, Cut::Word)),
+        )
+        .chain(lines.map(|(end, line)| {
+            // After a blank line, a new paragraph starts
+            let blank = line.trim().is_empty();
+            (end, if blank { Cut::Paragraph } else { Cut::Line })
+        }))
+        .filter(|&(at, _)| at > 0 && !in_fence(at))
+        .collect();
+    // The best cut at each offset, once
+    cuts.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    cuts.dedup_by_key(|cut| cut.0);
+    cuts
+}
+
+/// The code fences of `text`, each from the start of its opening line to
+/// the end of its closing line (or of the text, if it is never closed).
+fn fences(text: &str) -> Vec<(usize, usize)> {
+    let mut fences = Vec::new();
+    let mut open: Option<(usize, char, usize)> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim_start();
+        let marker = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'));
+        let length = marker.map_or(0, |c| trimmed.chars().take_while(|&m| m == c).count());
+        match open {
+            None if length >= 3 => open = Some((start, marker.unwrap_or('`'), length)),
+            // A closing fence is at least as long as the opening one, with
+            // nothing after it
+            Some((from, c, opening))
+                if marker == Some(c)
+                    && length >= opening
+                    && trimmed[length * c.len_utf8()..].trim().is_empty() =>
+            {
+                fences.push((from, offset));
+                open = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((from, _, _)) = open {
+        fences.push((from, text.len()));
+    }
+    fences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits `text`, checking what holds for any text: the chunks join
+    /// back to it, none is longer than `chunk_chars` unless it is a fence
+    /// or a grapheme that doesn't fit, and no cut is inside a grapheme or
+    /// a fence.
+    fn split_checked(text: &str, chunk_chars: usize) -> Vec<&str> {
+        let chunks = split(text, chunk_chars);
+        assert_eq!(chunks.concat(), text);
+        assert!(chunks.iter().all(|chunk| !chunk.is_empty()));
+
+        let graphemes: Vec<usize> = text.grapheme_indices(true).map(|(at, _)| at).collect();
+        let fences = fences(text);
+        let mut at = 0;
+        for chunk in &chunks[..chunks.len().saturating_sub(1)] {
+            at += chunk.len();
+            assert!(graphemes.contains(&at), "cut inside a grapheme at {}", at);
+            assert!(
+                !fences.iter().any(|&(from, to)| from < at && at < to),
+                "cut inside a fence at {}",
+                at
+            );
+        }
+        for chunk in &chunks {
+            let fenced = fences.iter().any(|&(from, to)| to - from > chunk_chars);
+            let whole = chunk.graphemes(true).count() == 1;
+            assert!(
+                chunk.chars().count() <= chunk_chars || fenced || whole,
+                "{} chars: {:?}",
+                chunk.chars().count(),
+                chunk
+            );
+        }
+        chunks
+    }
+
+    /// `count` words of a few letters, a sentence every few words.
+    fn prose(count: usize) -> String {
+        let words = [
+            "the", "meeting", "ran", "long", "again", "so", "we", "moved", "on.",
+        ];
+        (0..count)
+            .map(|index| words[index % words.len()])
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn short_text_is_one_chunk() {
+        assert_eq!(split_checked("hello world", 100), ["hello world"]);
+        assert!(split("", 100).is_empty());
+    }
+
+    #[test]
+    fn chunks_join_back_and_fit_the_limit() {
+        let text = format!(
+            "{}\n\n{}\n{}\n\n\n{}",
+            prose(80),
+            prose(3),
+            prose(120),
+            prose(40)
+        );
+        for chunk_chars in [50, 64, 100, 333, 1_000] {
+            split_checked(&text, chunk_chars);
+        }
+    }
+
+    #[test]
+    fn paragraphs_are_cut_between_first() {
+        let first = prose(30);
+        let second = prose(30);
+        let text = format!("{}\n\n{}", first, second);
+        let limit = first.chars().count() + 20;
+        let chunks = split_checked(&text, limit);
+        assert_eq!(chunks, [format!("{}\n\n", first).as_str(), second.as_str()]);
+    }
+
+    #[test]
+    fn a_short_paragraph_is_not_a_chunk_of_its_own() {
+        // A paragraph break early in each chunk, and words everywhere
+        let text = format!("Hi.\n\n{}\n\nOk.\n\n{}", prose(200), prose(200));
+        let chunks = split_checked(&text, 200);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.chars().count() >= 100, "{:?}", chunk);
+        }
+    }
+
+    #[test]
+    fn a_line_is_cut_at_a_word() {
+        let text = prose(100);
+        let chunks = split_checked(&text, 60);
+        let mut at = 0;
+        for chunk in &chunks[..chunks.len() - 1] {
+            // Next to a space, never in a word
+            at += chunk.len();
+            assert!(
+                text[..at].ends_with(' ') || text[at..].starts_with(' '),
+                "{:?}",
+                chunk
+            );
+        }
+    }
+
+    #[test]
+    fn emoji_sequences_are_never_split() {
+        // A family is 7 chars joined by ZWJs; a flag is two regional
+        // indicators; neither has a word boundary inside
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+        let flag = "\u{1F1EB}\u{1F1F7}";
+        let text = format!("{}{}", family, flag).repeat(30);
+        for chunk_chars in [8, 10, 13, 50] {
+            for chunk in split_checked(&text, chunk_chars) {
+                assert!(chunk.starts_with(family) || chunk.starts_with(flag));
+            }
+        }
+        // A grapheme longer than the limit goes whole
+        assert_eq!(split_checked(family, 3), [family]);
+    }
+
+    #[test]
+    fn combining_marks_stay_with_their_letter() {
+        // "é" as e and a combining acute, then a stack of marks on "a"
+        let text = "e\u{301}a\u{308}\u{323}".repeat(50);
+        for chunk_chars in [4, 5, 7, 11] {
+            for chunk in split_checked(&text, chunk_chars) {
+                assert!(chunk.starts_with('e') || chunk.starts_with('a'));
+                assert!(!chunk.ends_with('e') && !chunk.ends_with('a'));
+            }
+        }
+    }
+
+    #[test]
+    fn a_fence_is_never_cut() {
+        let code = (0..20)
+            .map(|line| format!("let x{} = {};\n", line, line))
+            .collect::<Vec<_>>()
+            .concat();
+        let fence = format!("```rust\n{}```\n", code);
+        let text = format!("{}\n\n{}\n{}", prose(30), fence, prose(30));
+        let chunks = split_checked(&text, 120);
+        // Longer than the limit, it is a chunk of its own
+        assert!(fence.chars().count() > 120);
+        assert!(chunks.contains(&fence.as_str()), "{:#?}", chunks);
+
+        // Shorter, it goes whole with what is around it
+        for chunk in split_checked(&text, 400) {
+            assert!(
+                !chunk.contains("```") || chunk.contains(&fence),
+                "{:?}",
+                chunk
+            );
+        }
+    }
+
+    #[test]
+    fn a_fence_never_closed_runs_to_the_end() {
+        let text = format!("{}\n~~~\n{}", prose(20), "code\n".repeat(40));
+        let chunks = split_checked(&text, 60);
+        assert!(chunks.last().unwrap().starts_with("~~~"), "{:#?}", chunks);
+        // A shorter closing fence doesn't close it
+        let text = format!("````\n{}```\n{}", "code\n".repeat(40), prose(20));
+        assert_eq!(split_checked(&text, 60), [text.as_str()]);
+    }
+
+    #[test]
+    fn settings_are_checked() {
+        assert_eq!(ChunkingSettings::default().validate(), Ok(()));
+        let settings = |threshold_chars, chunk_chars, delay_ms| ChunkingSettings {
+            threshold_chars,
+            chunk_chars,
+            delay_ms,
+        };
+        assert!(settings(20_000, 499, 0).validate().is_err());
+        assert!(settings(20_000, 100_001, 0).validate().is_err());
+        assert!(settings(1_000, 4_000, 0).validate().is_err());
+        assert_eq!(settings(0, 4_000, 0).validate(), Ok(()));
+        assert!(settings(20_000, 4_000, 5_001).validate().is_err());
+
+        let settings = settings(10, 500, 0);
+        assert!(!settings.applies_to("0123456789"));
+        // Counted in chars, not bytes
+        assert!(!settings.applies_to(&"é".repeat(10)));
+        assert!(settings.applies_to("0123456789a"));
+        assert!(!ChunkingSettings {
+            threshold_chars: 0,
+            ..settings
+        }
+        .applies_to(&"a".repeat(100_000)));
+    }
+}
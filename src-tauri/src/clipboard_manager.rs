@@ -0,0 +1,156 @@
+//! Clipboard managers on Linux: Klipper (KDE) and GPaste (GNOME).
+//!
+//! A clipboard manager takes ownership of the clipboard as soon as an app
+//! sets it, to keep a copy in its history, and for a moment the clipboard
+//! may hold its previous entry or nothing. A paste sent then pastes stale
+//! text. Three things cover it:
+//!
+//! - every write is read back, and polled with a growing wait until the
+//!   text shows up, before the keystroke is sent (see [`crate::paste`]);
+//! - with `clipboard_manager.use_store`, the text is handed to the manager
+//!   itself over D-Bus ([`store`]), which then owns the clipboard with our
+//!   text instead of taking it over afterwards. The text goes on the
+//!   command line of `dbus-send`, where other processes of the user can
+//!   read it (`/proc/<pid>/cmdline`) while it runs, so privacy mode never
+//!   does this (see [`crate::privacy`]);
+//! - with a manager running, the text is written once more after the paste,
+//!   so what the manager ends up with is the text pasted. Nothing restores
+//!   the previous clipboard, so this never overwrites something the user
+//!   wanted back.
+//!
+//! A manager is found by asking the session bus whether its service has an
+//! owner ([`detect`]), through `dbus-send`; `get_paste_capabilities`
+//! reports the one found.
+//!
+//! # Platform Support
+//!
+//! Linux only; [`detect`] is `None` elsewhere.
+
+use serde::{Deserialize, Serialize};
+
+/// Longest text handed to a manager, which takes it as a command line
+/// argument of `dbus-send`; longer ones are written to the clipboard
+/// directly.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const MAX_STORE_BYTES: usize = 100 * 1024;
+
+/// How long to wait for the session bus to answer.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const DBUS_TIMEOUT_MS: u32 = 500;
+
+/// A clipboard manager we know how to talk to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipboardManager {
+    Klipper,
+    Gpaste,
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+impl ClipboardManager {
+    const ALL: &'static [ClipboardManager] = &[ClipboardManager::Klipper, ClipboardManager::Gpaste];
+
+    /// Its D-Bus service name.
+    fn service(self) -> &'static str {
+        match self {
+            ClipboardManager::Klipper => "org.kde.klipper",
+            ClipboardManager::Gpaste => "org.gnome.GPaste",
+        }
+    }
+
+    /// Object path and method that make `text` the clipboard.
+    fn store_method(self) -> (&'static str, &'static str) {
+        match self {
+            ClipboardManager::Klipper => {
+                ("/klipper", "org.kde.klipper.klipper.setClipboardContents")
+            }
+            ClipboardManager::Gpaste => ("/org/gnome/GPaste", "org.gnome.GPaste2.Add"),
+        }
+    }
+}
+
+/// Clipboard manager settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipboardManagerSettings {
+    /// Hand pasted text to the clipboard manager instead of writing the
+    /// clipboard, when one is running.
+    pub use_store: bool,
+}
+
+/// The clipboard manager running in this session, if any.
+pub fn detect() -> Option<ClipboardManager> {
+    #[cfg(target_os = "linux")]
+    {
+        ClipboardManager::ALL
+            .iter()
+            .copied()
+            .find(|manager| has_owner(manager.service()))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Whether `service` is on the session bus.
+#[cfg(target_os = "linux")]
+fn has_owner(service: &str) -> bool {
+    dbus_send(&[
+        "--dest=org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus.NameHasOwner",
+        &format!("string:{}", service),
+    ])
+    .is_ok_and(|reply| reply.contains("boolean true"))
+}
+
+/// Makes `text` the clipboard through `manager`. The text is visible on
+/// `dbus-send`'s command line while it runs, so this is never called in
+/// privacy mode.
+///
+/// # Errors
+///
+/// Returns an error if the text is too long to hand over, or the manager
+/// didn't take it.
+pub fn store(manager: ClipboardManager, text: &str) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    {
+        if text.len() > MAX_STORE_BYTES {
+            return Err("the text is too long to hand to the clipboard manager".to_string());
+        }
+        let (path, method) = manager.store_method();
+        dbus_send(&[
+            &format!("--dest={}", manager.service()),
+            path,
+            method,
+            &format!("string:{}", text),
+        ])
+        .map(|_| ())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (manager, text);
+        Err("clipboard managers are only supported on Linux".to_string())
+    }
+}
+
+/// Calls a method on the session bus, returning the printed reply.
+#[cfg(target_os = "linux")]
+fn dbus_send(args: &[&str]) -> Result<String, String> {
+    let output = std::process::Command::new("dbus-send")
+        .args([
+            "--session",
+            "--print-reply",
+            &format!("--reply-timeout={}", DBUS_TIMEOUT_MS),
+        ])
+        .args(args)
+        .output()
+        .map_err(|e| format!("dbus-send: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,62 @@
+//! Time, behind a trait.
+//!
+//! Code that waits or compares timestamps (the paste sequence, the hotkey
+//! debounce) takes a [`Clock`] instead of calling [`Instant::now`], so it
+//! can be driven with made-up time.
+
+use std::time::{Duration, Instant};
+
+/// Time, for delays and timestamps.
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The wall clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Made-up time, for tests: sleeping moves it on at once.
+#[cfg(test)]
+pub struct MockClock {
+    now: std::sync::Mutex<Instant>,
+}
+
+#[cfg(test)]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self {
+            now: std::sync::Mutex::new(Instant::now()),
+        }
+    }
+}
+
+#[cfg(test)]
+impl MockClock {
+    /// Moves time on by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+        // Lets other threads run, as a real sleep would
+        std::thread::yield_now();
+    }
+}
@@ -0,0 +1,541 @@
+//! Command mode: dictations that press keys instead of typing text.
+//!
+//! A dictation is in command mode when it was started with the command
+//! hotkey ([`HotkeyAction::Command`](crate::dictation::HotkeyAction)), or
+//! when its transcript starts with `command_mode.switch_word` ("command
+//! mode" by default; empty turns the spoken switch off). Its transcript
+//! skips post-processing and is matched against `command_mode.commands`,
+//! each a spoken phrase and the keys it presses, and `copy_and_paste_text`
+//! presses those keys (see [`crate::keystroke`]) instead of pasting.
+//!
+//! "select all and copy" runs two commands: the transcript is read left to
+//! right, taking the longest phrase that matches at each point, and "and",
+//! "then" and "please" between them are skipped. Matching ignores case and
+//! punctuation, so "Select all." is "select all".
+//!
+//! # Architecture Decision
+//!
+//! A wrong match presses keys in the user's apps, so matching is strict:
+//! every word said must belong to a command, and if one doesn't, nothing
+//! runs. What wasn't recognized is reported (`command:unrecognized`, and
+//! [`SessionError::UnrecognizedCommand`]), and never typed as text.
+//! Commands marked `confirm` are shown first and only run once confirmed,
+//! the same way as `confirm_before_paste` (see [`crate::confirm`]), within
+//! its `timeout_ms`.
+//!
+//! The keys are pressed through the [`PasteQueue`](crate::PasteQueue), so
+//! they never interleave with a paste, while the app is `Pasting`, and with
+//! the paste backend, so they are stopped by secure input the same way.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use crate::app_state::{self, Phase, SessionError};
+use crate::confirm::{self, ConfirmKind};
+use crate::keystroke::Chord;
+use crate::logging;
+use crate::paste::{self, PasteBackendState, PasteError};
+use crate::pill::{self, PillState};
+use crate::settings::SettingsState;
+use crate::timings::Stage;
+use crate::{AppHandle, WhenBusy};
+
+/// Words skipped between commands.
+const FILLER_WORDS: &[&str] = &["and", "then", "please"];
+
+/// Command mode settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandModeSettings {
+    /// Said first, switches a dictation to command mode. Empty leaves only
+    /// the command hotkey.
+    pub switch_word: String,
+    pub commands: Vec<VoiceCommand>,
+}
+
+/// A spoken phrase and the keys it presses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VoiceCommand {
+    pub phrase: String,
+    /// Chords pressed in order, like `CmdOrCtrl+A` (see [`Chord`]).
+    pub keys: Vec<String>,
+    /// Ask before pressing them, for commands that destroy something.
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+impl VoiceCommand {
+    fn new(phrase: &str, keys: &[&str], confirm: bool) -> Self {
+        Self {
+            phrase: phrase.to_string(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            confirm,
+        }
+    }
+
+    fn chords(&self) -> Result<Vec<Chord>, String> {
+        self.keys.iter().map(|key| key.parse()).collect()
+    }
+}
+
+impl Default for CommandModeSettings {
+    fn default() -> Self {
+        Self {
+            switch_word: "command mode".to_string(),
+            commands: vec![
+                VoiceCommand::new("new line", &["Shift+Enter"], false),
+                VoiceCommand::new("new paragraph", &["Shift+Enter", "Shift+Enter"], false),
+                VoiceCommand::new("send", &["Enter"], false),
+                VoiceCommand::new("tab", &["Tab"], false),
+                VoiceCommand::new("escape", &["Escape"], false),
+                VoiceCommand::new("select all", &["CmdOrCtrl+A"], false),
+                VoiceCommand::new("copy", &["CmdOrCtrl+C"], false),
+                VoiceCommand::new("cut", &["CmdOrCtrl+X"], false),
+                VoiceCommand::new("paste", &["CmdOrCtrl+V"], false),
+                VoiceCommand::new("undo", &["CmdOrCtrl+Z"], false),
+                VoiceCommand::new("redo", &["CmdOrCtrl+Shift+Z"], false),
+                VoiceCommand::new("delete all", &["CmdOrCtrl+A", "Backspace"], true),
+            ],
+        }
+    }
+}
+
+impl CommandModeSettings {
+    /// Checks that every command has a phrase of its own and keys that can
+    /// be pressed.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut phrases = Vec::new();
+        for command in &self.commands {
+            let phrase = words(&command.phrase);
+            if phrase.is_empty() {
+                return Err("commands: a phrase must have words".to_string());
+            }
+            if phrases.contains(&phrase) {
+                return Err(format!("commands: \"{}\" is listed twice", command.phrase));
+            }
+            if command.keys.is_empty() {
+                return Err(format!("commands: \"{}\" has no keys", command.phrase));
+            }
+            command
+                .chords()
+                .map_err(|e| format!("commands: \"{}\": {}", command.phrase, e))?;
+            phrases.push(phrase);
+        }
+        Ok(())
+    }
+}
+
+/// Payload of `command:ran`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandsRan {
+    pub session_id: String,
+    /// Phrases of the commands run, in order.
+    pub commands: Vec<String>,
+}
+
+/// Payload of `command:unrecognized`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Unrecognized {
+    pub session_id: String,
+    /// What was said from the first word that isn't a command.
+    pub text: String,
+}
+
+/// `text` in lowercase words, without punctuation.
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The rest of `text` when it starts with `switch_word`.
+pub fn strip_switch_word(text: &str, switch_word: &str) -> Option<String> {
+    let switch = words(switch_word);
+    let said = words(text);
+    if switch.is_empty() || !said.starts_with(&switch) {
+        return None;
+    }
+    Some(said[switch.len()..].join(" "))
+}
+
+/// Matches `text` against `commands` and returns the commands said, in
+/// order.
+///
+/// # Errors
+///
+/// Returns what was said from the first word that isn't part of a command,
+/// or all of it if no command was said.
+pub fn parse<'a>(
+    text: &str,
+    commands: &'a [VoiceCommand],
+) -> Result<Vec<&'a VoiceCommand>, String> {
+    let phrases: Vec<(Vec<String>, &VoiceCommand)> = commands
+        .iter()
+        .map(|command| (words(&command.phrase), command))
+        .filter(|(phrase, _)| !phrase.is_empty())
+        .collect();
+    let said = words(text);
+    let mut matched = Vec::new();
+    let mut rest = &said[..];
+    while let Some(first) = rest.first() {
+        let longest = phrases
+            .iter()
+            .filter(|(phrase, _)| rest.starts_with(phrase))
+            .max_by_key(|(phrase, _)| phrase.len());
+        match longest {
+            Some((phrase, command)) => {
+                matched.push(*command);
+                rest = &rest[phrase.len()..];
+            }
+            None if FILLER_WORDS.contains(&first.as_str()) => rest = &rest[1..],
+            None => return Err(rest.join(" ")),
+        }
+    }
+    if matched.is_empty() {
+        return Err(said.join(" "));
+    }
+    Ok(matched)
+}
+
+/// Runs the voice commands of the dictation `session_id`, which is being
+/// transcribed: asks first if one of them is marked `confirm`, then waits
+/// for the turn to paste (see [`PasteQueue`], and `when_busy`), hands focus
+/// back to the target app and presses their keys.
+///
+/// # Errors
+///
+/// Returns [`SessionError::UnrecognizedCommand`] if `text` isn't made of
+/// commands, [`SessionError::InvalidCommand`] if one of them has keys that
+/// can't be pressed, [`SessionError::NotConfirmed`] if they weren't
+/// confirmed, [`SessionError::Busy`] if another paste has the turn and
+/// `when_busy` says not to wait, [`SessionError::StaleSession`] if the
+/// dictation isn't the current one, and [`SessionError::PasteFailed`] if
+/// the keys couldn't be pressed.
+///
+/// [`PasteQueue`]: crate::PasteQueue
+pub async fn run(
+    app: &AppHandle,
+    session_id: &str,
+    text: &str,
+    when_busy: WhenBusy,
+) -> Result<(), SessionError> {
+    let settings = app.state::<SettingsState>().get();
+    let commands = match parse(text, &settings.command_mode.commands) {
+        Ok(commands) => commands,
+        Err(unrecognized) => {
+            tracing::info!(
+                session_id,
+                text = %logging::transcript(text),
+                "No voice command recognized"
+            );
+            app_state::transition_session(app, session_id, Phase::Error, "unrecognized_command")?;
+            let _ = app.emit(
+                "command:unrecognized",
+                Unrecognized {
+                    session_id: session_id.to_string(),
+                    text: unrecognized.clone(),
+                },
+            );
+            return Err(SessionError::UnrecognizedCommand { text: unrecognized });
+        }
+    };
+    let phrases: Vec<String> = commands
+        .iter()
+        .map(|command| command.phrase.clone())
+        .collect();
+    // Checked when the settings were saved, but a settings file edited by
+    // hand isn't
+    let mut chords = Vec::new();
+    for command in &commands {
+        match command.chords() {
+            Ok(keys) => chords.extend(keys),
+            Err(message) => {
+                tracing::warn!(
+                    session_id,
+                    phrase = %command.phrase,
+                    error = %message,
+                    "Invalid voice command"
+                );
+                app_state::transition_session(app, session_id, Phase::Error, "invalid_command")?;
+                return Err(SessionError::InvalidCommand {
+                    phrase: command.phrase.clone(),
+                    message,
+                });
+            }
+        }
+    }
+    if commands.iter().any(|command| command.confirm) {
+        let timeout_ms = settings.confirm_before_paste.timeout_ms;
+        confirm::ask(
+            app,
+            session_id,
+            ConfirmKind::Commands,
+            &phrases.join(", "),
+            timeout_ms,
+        )
+        .await?;
+    }
+
+    let queue = app.state::<crate::PasteQueue>();
+    let _turn = queue.turn(when_busy).await?;
+    app_state::transition_session(app, session_id, Phase::Pasting, "commands_started")?;
+    let panel = pill::is_panel(app);
+    let pressed = {
+        let app = app.clone();
+        let delay = Duration::from_millis(settings.paste_delay_ms);
+        tauri::async_runtime::spawn_blocking(move || press(&app, &chords, !panel, delay))
+            .await
+            .unwrap_or_else(|e| Err(PasteError::new(Stage::PasteQueue, e.to_string())))
+    };
+    if panel {
+        pill::set_state(app, PillState::Hidden);
+    }
+
+    match pressed {
+        Ok(()) => {
+            tracing::info!(session_id, commands = ?phrases, "Voice commands run");
+            let _ = app_state::transition(app, Phase::Idle, "commands_run");
+            let _ = app.emit(
+                "command:ran",
+                CommandsRan {
+                    session_id: session_id.to_string(),
+                    commands: phrases,
+                },
+            );
+            Ok(())
+        }
+        Err(error) => {
+            tracing::warn!(
+                session_id,
+                code = %error.code(),
+                error = %error,
+                "Voice commands failed"
+            );
+            let _ = app_state::transition(app, Phase::Error, "commands_failed");
+            Err(SessionError::PasteFailed {
+                code: error.code(),
+                message: error.message,
+                partial: None,
+                mismatch_at: None,
+            })
+        }
+    }
+}
+
+/// Presses `chords` with the paste backend (see [`PasteBackendState`]),
+/// handing focus back first if `return_focus`, like a paste does. Blocks
+/// for `delay` then, so it must not run on the async runtime.
+fn press(
+    app: &AppHandle,
+    chords: &[Chord],
+    return_focus: bool,
+    delay: Duration,
+) -> Result<(), PasteError> {
+    let platform = paste::PlatformBackend { app, store: None };
+    let backend = app.state::<PasteBackendState>();
+    let backend = backend.or(&platform);
+    if return_focus {
+        backend
+            .activate_app()
+            .map_err(|e| PasteError::new(Stage::FocusWait, e))?;
+        std::thread::sleep(delay);
+    }
+    // The keys wouldn't arrive, and nothing would say so
+    if backend.secure_input() {
+        let message = "secure input is on, so the keys would be dropped";
+        return Err(PasteError {
+            secure_input: true,
+            ..PasteError::new(Stage::Keystroke, message.to_string())
+        });
+    }
+    backend
+        .send_chords(chords)
+        .map_err(|e| PasteError::new(Stage::Keystroke, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::errors::ErrorCode;
+    use crate::harness::Harness;
+    use crate::paste::mock::Call;
+
+    fn commands() -> Vec<VoiceCommand> {
+        vec![
+            VoiceCommand::new("select all", &["CmdOrCtrl+A"], false),
+            VoiceCommand::new("copy", &["CmdOrCtrl+C"], false),
+            VoiceCommand::new("delete", &["Backspace"], false),
+            VoiceCommand::new("delete all", &["CmdOrCtrl+A", "Backspace"], true),
+        ]
+    }
+
+    fn phrases(text: &str) -> Result<Vec<String>, String> {
+        let commands = commands();
+        parse(text, &commands).map(|matched| matched.iter().map(|c| c.phrase.clone()).collect())
+    }
+
+    /// The app, with a transcript that switches to command mode.
+    fn harness(test: &str) -> Harness {
+        let mock = json!({ "text": "Command mode: select all, then copy." });
+        Harness::with_settings(test, json!({ "transcription": { "mock": mock } }))
+    }
+
+    fn chords(keys: &[&str]) -> Vec<Chord> {
+        keys.iter().map(|key| key.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn commands_are_read_left_to_right_skipping_filler() {
+        assert_eq!(
+            phrases("select all and copy").unwrap(),
+            ["select all", "copy"]
+        );
+        assert_eq!(
+            phrases("please copy, then select all").unwrap(),
+            ["copy", "select all"]
+        );
+        assert_eq!(phrases("copy copy").unwrap(), ["copy", "copy"]);
+    }
+
+    #[test]
+    fn the_longest_phrase_wins() {
+        assert_eq!(phrases("delete all").unwrap(), ["delete all"]);
+        assert_eq!(phrases("delete and delete").unwrap(), ["delete", "delete"]);
+    }
+
+    #[test]
+    fn case_punctuation_and_spacing_are_ignored() {
+        assert_eq!(phrases("Select all.").unwrap(), ["select all"]);
+        assert_eq!(
+            phrases("  SELECT   all,\nthen Copy!  ").unwrap(),
+            ["select all", "copy"]
+        );
+    }
+
+    #[test]
+    fn a_word_that_isnt_a_command_runs_nothing() {
+        assert_eq!(phrases("select all and fly away"), Err("fly away".into()));
+        assert_eq!(
+            phrases("select everything"),
+            Err("select everything".into())
+        );
+        assert_eq!(phrases("and then please"), Err("and then please".into()));
+        assert_eq!(phrases(""), Err(String::new()));
+    }
+
+    #[test]
+    fn the_switch_word_has_to_come_first() {
+        assert_eq!(
+            strip_switch_word("Command mode, select all.", "command mode"),
+            Some("select all".into())
+        );
+        assert_eq!(
+            strip_switch_word("COMMAND MODE copy", "Command-Mode"),
+            Some("copy".into())
+        );
+        assert_eq!(
+            strip_switch_word("command mode", "command mode"),
+            Some(String::new())
+        );
+        assert_eq!(
+            strip_switch_word("command modes copy", "command mode"),
+            None
+        );
+        assert_eq!(
+            strip_switch_word("copy in command mode", "command mode"),
+            None
+        );
+        assert_eq!(strip_switch_word("command mode copy", ""), None);
+    }
+
+    #[test]
+    fn validation_rejects_duplicates_and_bad_keys() {
+        assert_eq!(CommandModeSettings::default().validate(), Ok(()));
+        let mut settings = CommandModeSettings {
+            switch_word: String::new(),
+            commands: commands(),
+        };
+        settings
+            .commands
+            .push(VoiceCommand::new("Select All!", &["Tab"], false));
+        assert!(settings.validate().unwrap_err().contains("listed twice"));
+        settings.commands.pop();
+        settings
+            .commands
+            .push(VoiceCommand::new("fly", &["Ctrl+F13"], false));
+        let error = settings.validate().unwrap_err();
+        assert!(error.contains("\"fly\""), "{}", error);
+        assert!(error.contains("unknown key"), "{}", error);
+    }
+
+    #[test]
+    fn a_command_mode_dictation_presses_keys_and_pastes_no_text() {
+        let harness = harness("command-mode");
+        let app = harness.handle();
+
+        let session_id = harness.press_hotkey();
+        let text = harness.transcribe(&session_id).unwrap();
+        assert_eq!(text, "select all then copy");
+        harness.paste(&session_id, &text).unwrap();
+
+        let pressed = chords(&["CmdOrCtrl+A", "CmdOrCtrl+C"]);
+        assert_eq!(
+            harness.backend.calls(),
+            [Call::ActivateApp, Call::Chords(pressed)]
+        );
+        assert!(harness.backend.pasted().is_empty());
+        assert_eq!(harness.backend.clipboard(), "");
+        assert_eq!(app_state::phase(app), Phase::Idle);
+        let ran = harness.events("command:ran");
+        assert_eq!(ran.len(), 1);
+        assert_eq!(ran[0]["session_id"], session_id);
+        assert_eq!(ran[0]["commands"], json!(["select all", "copy"]));
+        assert_eq!(harness.events("recording:stopped").len(), 1);
+        assert!(harness.history().is_empty());
+    }
+
+    #[test]
+    fn secure_input_stops_the_keys() {
+        let harness = harness("command-mode-secure-input");
+        harness.backend.set_secure_input(true);
+
+        let session_id = harness.press_hotkey();
+        let text = harness.transcribe(&session_id).unwrap();
+        let error = harness.paste(&session_id, &text).unwrap_err();
+
+        assert_eq!(error["kind"], "paste_failed");
+        assert_eq!(error["code"], json!(ErrorCode::SecureInput));
+        assert_eq!(harness.backend.calls(), [Call::ActivateApp]);
+        assert_eq!(app_state::phase(harness.handle()), Phase::Error);
+        assert!(harness.events("command:ran").is_empty());
+    }
+
+    #[test]
+    fn busy_commands_can_be_rejected() {
+        let harness = harness("command-mode-busy");
+        let app = harness.handle();
+
+        let session_id = harness.press_hotkey();
+        let text = harness.transcribe(&session_id).unwrap();
+        let queue = app.state::<crate::PasteQueue>();
+        let turn = tauri::async_runtime::block_on(queue.turn(WhenBusy::Queue)).unwrap();
+        let error = harness
+            .invoke(
+                "copy_and_paste_text",
+                json!({ "text": text, "sessionId": session_id, "whenBusy": "reject" }),
+            )
+            .unwrap_err();
+        drop(turn);
+
+        assert_eq!(error["kind"], "busy");
+        assert!(harness.backend.calls().is_empty());
+        assert!(harness.events("command:ran").is_empty());
+    }
+}
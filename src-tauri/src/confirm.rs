@@ -0,0 +1,322 @@
+//! Preview and confirm a dictation before it is pasted.
+//!
+//! With `confirm_before_paste.enabled`, or when the app in front is one of
+//! `confirm_before_paste.apps`, a dictation isn't pasted as soon as its
+//! transcript is processed. `copy_and_paste_text` stops there instead: the
+//! pill shows the text and `paste:awaiting-confirmation` is emitted with
+//! the session id. Enter, or [`confirm_paste`], pastes it the usual way;
+//! Escape, [`cancel_paste`] or `confirm_before_paste.timeout_ms` (20s by
+//! default) without an answer cancel the dictation, leaving the text on the
+//! clipboard.
+//!
+//! The app is `AwaitingConfirmation` meanwhile (see [`crate::app_state`]),
+//! and Enter and Escape are global shortcuts so they work whichever app has
+//! focus. An answer is about one dictation: confirming one that was
+//! cancelled or replaced is rejected as stale, and dismissing the pill
+//! cancels the one waiting.
+//!
+//! There are no per-app profiles, so `confirm_before_paste.apps` (bundle
+//! ids on macOS, process names elsewhere) is how confirmation is turned on
+//! for some apps only.
+//!
+//! Voice commands marked `confirm` are asked about the same way before they
+//! run (see [`crate::command_mode`]), with `kind` telling the two apart; a
+//! cancelled command leaves nothing on the clipboard.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcut, GlobalShortcutExt, ShortcutState};
+use tokio::sync::oneshot;
+
+use crate::app_state::{self, Phase, SessionError};
+use crate::frontmost;
+use crate::paste;
+use crate::pill::{self, PillState};
+use crate::settings::SettingsState;
+use crate::snippets::CursorPlacement;
+use crate::{AppHandle, Runtime};
+
+/// Bounds for `confirm_before_paste.timeout_ms`.
+const MIN_TIMEOUT_MS: u64 = 1_000;
+const MAX_TIMEOUT_MS: u64 = 300_000;
+
+/// Keys answering while a dictation waits, registered only meanwhile.
+const CONFIRM_KEY: &str = "Enter";
+const CANCEL_KEY: &str = "Escape";
+
+/// Characters per line of the transcript pill, to size it to the text.
+const CHARS_PER_LINE: usize = 48;
+
+/// When to confirm a dictation before pasting it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfirmSettings {
+    /// Confirm every dictation.
+    pub enabled: bool,
+    /// Confirm dictations into these apps even when not `enabled`.
+    pub apps: Vec<String>,
+    /// How long to wait for an answer before cancelling.
+    pub timeout_ms: u64,
+}
+
+impl Default for ConfirmSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            apps: Vec::new(),
+            timeout_ms: 20_000,
+        }
+    }
+}
+
+impl ConfirmSettings {
+    /// Checks the timeout and app list.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_TIMEOUT_MS..=MAX_TIMEOUT_MS).contains(&self.timeout_ms) {
+            return Err(format!(
+                "timeout_ms must be between {} and {}",
+                MIN_TIMEOUT_MS, MAX_TIMEOUT_MS
+            ));
+        }
+        if self.apps.iter().any(|app| app.trim().is_empty()) {
+            return Err("apps must not contain empty names".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether a dictation needs confirming. Only looks up the app in front
+    /// when that could change the answer.
+    fn applies(&self) -> bool {
+        self.enabled
+            || (!self.apps.is_empty()
+                && frontmost::frontmost_bundle_id().is_some_and(|id| self.apps.contains(&id)))
+    }
+}
+
+/// What a dictation does once confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmKind {
+    /// Pastes its text.
+    Paste,
+    /// Runs its voice commands.
+    Commands,
+}
+
+/// Payload of `paste:awaiting-confirmation`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AwaitingConfirmation {
+    pub session_id: String,
+    pub kind: ConfirmKind,
+    /// The text to paste, or the commands to run.
+    pub text: String,
+    pub timeout_ms: u64,
+}
+
+/// Managed state: the dictation waiting for an answer, if any.
+#[derive(Default)]
+pub struct ConfirmState(Mutex<Option<Pending>>);
+
+struct Pending {
+    session_id: String,
+    /// Sends whether to paste.
+    answer: oneshot::Sender<bool>,
+}
+
+/// Waits for the dictation `session_id` to be confirmed if the settings ask
+/// for it, and returns at once otherwise. Called by `copy_and_paste_text`
+/// before pasting `text`.
+///
+/// # Errors
+///
+/// Returns [`SessionError::NotConfirmed`] if the dictation was cancelled or
+/// nobody answered in time, with `text` left on the clipboard, and
+/// [`SessionError::StaleSession`] if it isn't the current one.
+pub async fn await_confirmation(
+    app: &AppHandle,
+    session_id: &str,
+    text: &str,
+) -> Result<(), SessionError> {
+    let settings = app.state::<SettingsState>().get().confirm_before_paste;
+    if !settings.applies() {
+        return Ok(());
+    }
+    let text = CursorPlacement::from_body(text).text;
+    ask(
+        app,
+        session_id,
+        ConfirmKind::Paste,
+        &text,
+        settings.timeout_ms,
+    )
+    .await
+}
+
+/// Shows `text` for the dictation `session_id`, which is being transcribed,
+/// and waits up to `timeout_ms` for it to be confirmed. A dictation that
+/// isn't is dismissed, and a paste's `text` is left on the clipboard.
+///
+/// # Errors
+///
+/// The same as [`await_confirmation`].
+pub(crate) async fn ask(
+    app: &AppHandle,
+    session_id: &str,
+    kind: ConfirmKind,
+    text: &str,
+    timeout_ms: u64,
+) -> Result<(), SessionError> {
+    app_state::transition_session(
+        app,
+        session_id,
+        Phase::AwaitingConfirmation,
+        "awaiting_confirmation",
+    )?;
+
+    let (answer, answered) = oneshot::channel();
+    *app.state::<ConfirmState>().0.lock().unwrap() = Some(Pending {
+        session_id: session_id.to_string(),
+        answer,
+    });
+    let estimated_lines = (text.chars().count() / CHARS_PER_LINE + 1) as u32;
+    pill::set_state(app, PillState::Transcript { estimated_lines });
+    register_keys(app);
+    tracing::info!(session_id, ?kind, "Waiting for confirmation");
+    let _ = app.emit(
+        "paste:awaiting-confirmation",
+        AwaitingConfirmation {
+            session_id: session_id.to_string(),
+            kind,
+            text: text.to_string(),
+            timeout_ms,
+        },
+    );
+
+    let timeout = Duration::from_millis(timeout_ms);
+    let answer = tokio::time::timeout(timeout, answered).await;
+    unregister_keys(app);
+    {
+        let mut pending = app.state::<ConfirmState>().0.lock().unwrap();
+        if pending.as_ref().is_some_and(|p| p.session_id == session_id) {
+            *pending = None;
+        }
+    }
+    let timed_out = answer.is_err();
+    if matches!(answer, Ok(Ok(true))) {
+        return Ok(());
+    }
+
+    tracing::info!(session_id, ?kind, timed_out, "Not confirmed");
+    if kind == ConfirmKind::Paste {
+        paste::copy_fallback(app, text);
+    }
+    app_state::dismiss(
+        app,
+        if timed_out {
+            "confirmation_timed_out"
+        } else {
+            "confirmation_cancelled"
+        },
+    );
+    pill::set_state(app, PillState::Hidden);
+    Err(SessionError::NotConfirmed { timed_out })
+}
+
+/// Answers the dictation waiting, if any, and if it is `session_id` when
+/// given. Returns whether there was one to answer.
+fn answer(app: &AppHandle, session_id: Option<&str>, paste: bool) -> bool {
+    let pending = {
+        let mut pending = app.state::<ConfirmState>().0.lock().unwrap();
+        if session_id.is_some_and(|id| pending.as_ref().is_some_and(|p| p.session_id != id)) {
+            return false;
+        }
+        pending.take()
+    };
+    match pending {
+        Some(pending) => pending.answer.send(paste).is_ok(),
+        None => false,
+    }
+}
+
+/// Cancels the dictation waiting for confirmation, if any. Used when the
+/// dictation is dismissed.
+pub fn cancel(app: &AppHandle) {
+    answer(app, None, false);
+}
+
+/// Whether there are global shortcuts to register: not in the test
+/// harness, which runs without plugins (see `harness`).
+fn has_shortcuts(app: &AppHandle) -> bool {
+    app.try_state::<GlobalShortcut<Runtime>>().is_some()
+}
+
+fn register_keys(app: &AppHandle) {
+    if !has_shortcuts(app) {
+        return;
+    }
+    for (key, paste) in [(CONFIRM_KEY, true), (CANCEL_KEY, false)] {
+        let registered = app
+            .global_shortcut()
+            .on_shortcut(key, move |app, _, event| {
+                if event.state == ShortcutState::Pressed {
+                    answer(app, None, paste);
+                }
+            });
+        if let Err(e) = registered {
+            tracing::warn!("Failed to register {} to answer the paste: {}", key, e);
+        }
+    }
+}
+
+fn unregister_keys(app: &AppHandle) {
+    if !has_shortcuts(app) {
+        return;
+    }
+    for key in [CONFIRM_KEY, CANCEL_KEY] {
+        if app.global_shortcut().is_registered(key) {
+            let _ = app.global_shortcut().unregister(key);
+        }
+    }
+}
+
+/// Pastes the dictation `session_id`, which is waiting for confirmation.
+///
+/// # Errors
+///
+/// Returns [`SessionError::StaleSession`] if it isn't the dictation
+/// waiting: it was cancelled, timed out or replaced.
+#[tauri::command]
+pub fn confirm_paste(app: AppHandle, session_id: String) -> Result<(), SessionError> {
+    app_state::check_session(&app, &session_id)?;
+    if app_state::phase(&app) != Phase::AwaitingConfirmation
+        || !answer(&app, Some(&session_id), true)
+    {
+        return Err(SessionError::StaleSession {
+            session_id,
+            current: app_state::session_id(&app),
+        });
+    }
+    Ok(())
+}
+
+/// Cancels the dictation `session_id`, which is waiting for confirmation;
+/// its text stays on the clipboard.
+///
+/// # Errors
+///
+/// Returns [`SessionError::StaleSession`] if it isn't the dictation
+/// waiting.
+#[tauri::command]
+pub fn cancel_paste(app: AppHandle, session_id: String) -> Result<(), SessionError> {
+    app_state::check_session(&app, &session_id)?;
+    if !answer(&app, Some(&session_id), false) {
+        return Err(SessionError::StaleSession {
+            session_id,
+            current: app_state::session_id(&app),
+        });
+    }
+    Ok(())
+}
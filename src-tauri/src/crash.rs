@@ -0,0 +1,316 @@
+//! Crash reporting and supervised background threads.
+//!
+//! [`install`] runs first thing in `run()` and adds a panic hook that logs
+//! the panic with a backtrace and writes it to a crash marker file,
+//! `crash.json` in the app data directory, then lets the default hook run.
+//! Panics before the data directory is known (very early startup) are only
+//! logged.
+//!
+//! The hook can't tell whether the app survives the panic (one in a
+//! supervised thread or an async command doesn't end it), so the marker is
+//! removed again when the app exits cleanly ([`clean_exit`]): what is left at
+//! the next start is a panic that ended the process.
+//!
+//! On the next start [`open`] picks the marker up: the report is kept for
+//! [`get_last_crash`] and the diagnostics bundle, `crash:detected` is
+//! emitted, and the settings window opens so the user can export a bundle
+//! for support.
+//!
+//! Background threads that should outlive a panic run under [`supervise`]:
+//! a panic (logged by the hook) restarts the thread after a pause, and one
+//! that keeps panicking is given up on and reported as degraded
+//! ([`degraded`]) instead of silently dying.
+
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager, State};
+
+use crate::AppHandle;
+
+/// File name of the crash marker inside the app data directory.
+const CRASH_FILE: &str = "crash.json";
+
+/// Restarts of a supervised thread before it is given up on.
+const MAX_RESTARTS: u32 = 3;
+
+/// Pause before restarting a supervised thread, so a thread that panics
+/// right away doesn't spin.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Where the panic hook writes the crash marker; set by [`open`].
+static MARKER: OnceLock<PathBuf> = OnceLock::new();
+
+/// A panic, as written to the crash marker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    /// Unix milliseconds.
+    pub at_ms: i64,
+    pub app_version: String,
+    pub message: String,
+    /// `file:line:column` of the panic.
+    pub location: Option<String>,
+    /// Name of the thread that panicked.
+    pub thread: Option<String>,
+    pub backtrace: String,
+}
+
+/// A background subsystem that is no longer running as it should.
+#[derive(Debug, Clone, Serialize)]
+pub struct Degraded {
+    /// Times its thread panicked.
+    pub panics: u32,
+    /// Whether it was given up on and is no longer running.
+    pub stopped: bool,
+}
+
+/// Crash state, managed for the life of the app.
+#[derive(Default)]
+pub struct CrashState {
+    /// The crash of the previous run, if it crashed.
+    last_crash: Mutex<Option<CrashReport>>,
+    /// Supervised threads that panicked, by name.
+    degraded: Mutex<BTreeMap<&'static str, Degraded>>,
+}
+
+/// Installs the panic hook. Call once, before anything else.
+pub fn install() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let report = CrashReport {
+            at_ms: now_ms(),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            message,
+            location: info.location().map(|l| l.to_string()),
+            thread: std::thread::current().name().map(str::to_string),
+            backtrace: Backtrace::force_capture().to_string(),
+        };
+        tracing::error!(
+            thread = ?report.thread,
+            location = ?report.location,
+            backtrace = %report.backtrace,
+            "Panic: {}",
+            report.message
+        );
+        if let Some(marker) = MARKER.get() {
+            if let Ok(json) = serde_json::to_vec_pretty(&report) {
+                let _ = fs::write(marker, json);
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+/// Arms the crash marker in `data_dir` and reports the crash of the
+/// previous run, if any. Call from `setup`, once the windows exist.
+pub fn open(app: &AppHandle, data_dir: &Path) {
+    let marker = data_dir.join(CRASH_FILE);
+    let previous = take_marker(&marker);
+    let _ = MARKER.set(marker);
+
+    let Some(crash) = previous else {
+        return;
+    };
+    tracing::warn!(
+        at_ms = crash.at_ms,
+        "The previous run crashed: {}",
+        crash.message
+    );
+    *app.state::<CrashState>().last_crash.lock().unwrap() = Some(crash.clone());
+    let _ = app.emit("crash:detected", crash);
+    if let Err(e) = crate::windows::open_settings_window(app.clone()) {
+        tracing::warn!(error = %e, "Failed to offer the diagnostics export");
+    }
+}
+
+/// Removes the crash marker, once the app is known to have survived the
+/// panics it records. Call when the app exits cleanly.
+pub fn clean_exit() {
+    if let Some(marker) = MARKER.get() {
+        clear_marker(marker);
+    }
+}
+
+/// The report in `marker`, removing it, so a crash is reported once.
+fn take_marker(marker: &Path) -> Option<CrashReport> {
+    let json = fs::read(marker).ok()?;
+    clear_marker(marker);
+    serde_json::from_slice(&json).ok()
+}
+
+fn clear_marker(marker: &Path) {
+    if let Err(e) = fs::remove_file(marker) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!(error = %e, "Failed to remove the crash marker");
+        }
+    }
+}
+
+/// Runs `body` on a thread called `name`, restarting it if it panics. After
+/// [`MAX_RESTARTS`] restarts it is given up on. Either way it is reported in
+/// [`degraded`].
+///
+/// # Errors
+///
+/// Returns an error if the thread can't be started.
+pub fn supervise(
+    app: &AppHandle,
+    name: &'static str,
+    mut body: impl FnMut() + Send + 'static,
+) -> std::io::Result<()> {
+    let app = app.clone();
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            let mut panics = 0;
+            // The hook has logged the panic already
+            while panic::catch_unwind(AssertUnwindSafe(&mut body)).is_err() {
+                // The app survived it, so it isn't a crash of this run
+                if let Some(marker) = MARKER.get() {
+                    clear_marker(marker);
+                }
+                panics += 1;
+                let stopped = panics > MAX_RESTARTS;
+                app.state::<CrashState>()
+                    .degraded
+                    .lock()
+                    .unwrap()
+                    .insert(name, Degraded { panics, stopped });
+                if stopped {
+                    tracing::error!(thread = name, panics, "Giving up on a background thread");
+                    return;
+                }
+                tracing::warn!(thread = name, panics, "Restarting a background thread");
+                std::thread::sleep(RESTART_DELAY);
+            }
+        })
+        .map(|_| ())
+}
+
+/// Supervised threads that have panicked, by name.
+pub fn degraded(app: &AppHandle) -> BTreeMap<&'static str, Degraded> {
+    app.state::<CrashState>().degraded.lock().unwrap().clone()
+}
+
+/// The crash of the previous run, if it crashed.
+pub fn last_crash(app: &AppHandle) -> Option<CrashReport> {
+    app.state::<CrashState>().last_crash.lock().unwrap().clone()
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Returns the crash of the previous run, if it crashed.
+#[tauri::command]
+pub fn get_last_crash(state: State<'_, CrashState>) -> Option<CrashReport> {
+    state.last_crash.lock().unwrap().clone()
+}
+
+/// Forgets the crash of the previous run, once the user has seen it.
+#[tauri::command]
+pub fn dismiss_crash(state: State<'_, CrashState>) {
+    state.last_crash.lock().unwrap().take();
+}
+
+/// Panics, to exercise crash reporting: on the calling thread, or with
+/// `background` in a supervised thread, which is restarted and eventually
+/// reported degraded. Debug builds only.
+///
+/// # Errors
+///
+/// Returns an error in release builds, without panicking.
+#[tauri::command]
+pub fn trigger_test_panic(app: AppHandle, background: Option<bool>) -> Result<(), String> {
+    if !cfg!(debug_assertions) {
+        return Err("test panics are only available in debug builds".to_string());
+    }
+    if background.unwrap_or(false) {
+        return supervise(&app, "test-panic", || panic!("test panic (background)"))
+            .map_err(|e| e.to_string());
+    }
+    panic!("test panic");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("wispr-crash-{}-{}", test, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn report(message: &str) -> CrashReport {
+        CrashReport {
+            at_ms: 1_700_000_000_000,
+            app_version: "1.2.3".to_string(),
+            message: message.to_string(),
+            location: Some("src/lib.rs:1:1".to_string()),
+            thread: Some("main".to_string()),
+            backtrace: String::new(),
+        }
+    }
+
+    #[test]
+    fn a_crash_is_reported_once() {
+        let dir = TempDir::new("once");
+        let marker = dir.0.join(CRASH_FILE);
+        fs::write(&marker, serde_json::to_vec(&report("boom")).unwrap()).unwrap();
+
+        assert_eq!(take_marker(&marker).unwrap().message, "boom");
+        assert!(!marker.exists());
+        assert!(take_marker(&marker).is_none());
+    }
+
+    #[test]
+    fn a_panic_survived_until_a_clean_exit_is_not_a_crash() {
+        let dir = TempDir::new("survived");
+        let marker = dir.0.join(CRASH_FILE);
+        fs::write(&marker, serde_json::to_vec(&report("caught")).unwrap()).unwrap();
+
+        clear_marker(&marker);
+        assert!(take_marker(&marker).is_none());
+        // Nothing to clear is fine too
+        clear_marker(&marker);
+    }
+
+    #[test]
+    fn an_unreadable_marker_is_dropped() {
+        let dir = TempDir::new("unreadable");
+        let marker = dir.0.join(CRASH_FILE);
+        fs::write(&marker, "{ not json").unwrap();
+
+        assert!(take_marker(&marker).is_none());
+        assert!(!marker.exists());
+    }
+}
@@ -0,0 +1,125 @@
+//! Bookkeeping for the dictation currently in flight.
+//!
+//! A dictation spans several commands (`show_recording_pill` →
+//! `process_transcript` → `copy_and_paste_text`), each of which knows a
+//! different piece of what ends up in history. This module collects those
+//! pieces in managed state until the paste completes.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::capture::CaptureFormat;
+
+/// Which hotkey started a dictation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HotkeyAction {
+    /// `hotkeys.dictation`, or a dictation not started by a hotkey.
+    #[default]
+    Dictation,
+    /// `hotkeys.rewrite`.
+    Rewrite,
+    /// `hotkeys.command`, which starts a dictation in command mode (see
+    /// [`crate::command_mode`]).
+    Command,
+}
+
+/// Metadata gathered over the lifetime of one dictation.
+#[derive(Debug, Clone)]
+pub struct Dictation {
+    /// The dictation's session id (see [`crate::app_state`]).
+    pub session_id: String,
+    /// The hotkey that started it.
+    pub action: HotkeyAction,
+    /// Whether its transcript is voice commands (see
+    /// [`crate::command_mode`]) rather than text to paste.
+    pub command_mode: bool,
+    /// When the recording pill was shown.
+    pub started_at: Instant,
+    /// When the recording ended.
+    pub stopped_at: Option<Instant>,
+    /// Audio captured so far, as reported by the frontend (see
+    /// [`crate::ticks`]).
+    pub audio_ms: u64,
+    /// Whether the frontend has heard speech yet (see [`crate::ticks`]).
+    pub speech_detected: bool,
+    /// Highest level of the captured audio, 0 to 1, as reported by the
+    /// frontend.
+    pub peak_level: f32,
+    /// Whether the input was reported silent (see [`crate::ticks`]).
+    pub silent_input_reported: bool,
+    /// Transcript as returned by the provider, before post-processing.
+    pub raw_text: Option<String>,
+    /// Name of the transcription provider that produced `raw_text`.
+    pub provider: Option<String>,
+    /// Language code it is transcribed in: picked at recording start (see
+    /// [`crate::transcription::language_for_dictation`]), then as the
+    /// frontend reports it.
+    pub language: Option<String>,
+    /// Time from recording start until the final transcript arrived.
+    pub duration_ms: Option<u64>,
+    /// Recording staged by the frontend, pending the audio retention policy.
+    pub audio_path: Option<PathBuf>,
+    /// Format it was recorded in, as the frontend reports it (see
+    /// [`crate::capture`]).
+    pub capture: Option<CaptureFormat>,
+}
+
+impl Dictation {
+    /// Starts tracking the new dictation `session_id`.
+    pub fn start(session_id: String) -> Self {
+        Self {
+            session_id,
+            action: HotkeyAction::default(),
+            command_mode: false,
+            started_at: Instant::now(),
+            stopped_at: None,
+            audio_ms: 0,
+            speech_detected: false,
+            peak_level: 0.0,
+            silent_input_reported: false,
+            raw_text: None,
+            provider: None,
+            language: None,
+            duration_ms: None,
+            audio_path: None,
+            capture: None,
+        }
+    }
+
+    /// Marks the end of the recording, returning how long it was.
+    pub fn stop_recording(&mut self) -> Duration {
+        let stopped_at = *self.stopped_at.get_or_insert_with(Instant::now);
+        stopped_at - self.started_at
+    }
+
+    /// Records the provider's final transcript and stops the duration clock.
+    pub fn finish_transcription(
+        &mut self,
+        raw_text: &str,
+        provider: Option<String>,
+        language: Option<String>,
+    ) {
+        self.raw_text = Some(raw_text.to_string());
+        self.provider = provider;
+        if language.is_some() {
+            self.language = language;
+        }
+        self.duration_ms = Some(self.started_at.elapsed().as_millis() as u64);
+    }
+
+    /// Deletes the staged recording, if any. Used when a dictation is
+    /// abandoned or its audio replaced before reaching history.
+    pub fn discard_audio(&mut self) {
+        if let Some(path) = self.audio_path.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Managed state holding the dictation in flight, if any.
+#[derive(Default)]
+pub struct DictationState(pub Mutex<Option<Dictation>>);
@@ -0,0 +1,451 @@
+//! Dictation log: every dictation as a line of JSON, for auditing and
+//! personal analytics.
+//!
+//! With `dictation_log.enabled`, each pasted dictation is appended to
+//! `dictation_log.path` as one JSON [`LogEntry`] per line (JSON Lines):
+//! when it finished, the text, the app it was pasted into, how long it took,
+//! and the provider and language it was transcribed with. The log has
+//! nothing to do with history: clearing or pruning history leaves it alone,
+//! and it is written even when `retention.save_transcripts` is off.
+//!
+//! Before a line would take the file past `dictation_log.max_bytes`, the
+//! file is rotated: `dictations.jsonl` becomes `dictations.jsonl.1`, the
+//! old `.1` becomes `.2`, and so on, keeping `dictation_log.keep_files` of
+//! them. A line is never split between two files, so one longer than
+//! `max_bytes` gets a file of its own.
+//!
+//! `dictation_log.sync` decides when a line reaches the disk: `every_entry`
+//! syncs each one before the next is written, so a power cut loses at most
+//! the dictation being written, while `never` leaves it to the OS.
+//!
+//! # Architecture Decision
+//!
+//! Lines are written by one thread, in the order the dictations were
+//! pasted, so two dictations finishing close together never swap places or
+//! interleave. Each line is a single `write` on a file opened for appending,
+//! so a reader following the file (`tail -f`) never sees half of one.
+//!
+//! Like the journal, the log is an output sink (see [`crate::sinks`]):
+//! writing happens after the paste and off its path, and a failure is only
+//! logged. In privacy mode nothing is written (see [`crate::privacy`]).
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::dictation::Dictation;
+use crate::frontmost;
+use crate::privacy;
+use crate::settings::SettingsState;
+use crate::sinks::OutputSink;
+use crate::AppHandle;
+
+/// Bounds for `dictation_log.max_bytes`.
+const MIN_MAX_BYTES: u64 = 64 * 1024;
+const MAX_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Most rotated files `dictation_log.keep_files` may keep.
+const MAX_KEEP_FILES: u32 = 100;
+
+/// Dictation log settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DictationLogSettings {
+    /// Append dictations to the log.
+    pub enabled: bool,
+    /// The log file. Required when enabled.
+    pub path: Option<PathBuf>,
+    /// Size the file is rotated at.
+    pub max_bytes: u64,
+    /// Rotated files kept besides the current one.
+    pub keep_files: u32,
+    pub sync: SyncPolicy,
+}
+
+impl Default for DictationLogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: None,
+            max_bytes: 10 * 1024 * 1024,
+            keep_files: 5,
+            sync: SyncPolicy::default(),
+        }
+    }
+}
+
+/// When lines are synced to the disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPolicy {
+    /// After every line.
+    #[default]
+    EveryEntry,
+    /// Whenever the OS writes it out.
+    Never,
+}
+
+impl DictationLogSettings {
+    /// Checks the path and rotation bounds.
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.path {
+            Some(path) if !path.is_absolute() => {
+                return Err("path must be an absolute path".to_string());
+            }
+            Some(path) if path.file_name().is_none() => {
+                return Err("path must name a file".to_string());
+            }
+            None if self.enabled => {
+                return Err("path must be set to enable the dictation log".to_string());
+            }
+            _ => {}
+        }
+        if !(MIN_MAX_BYTES..=MAX_MAX_BYTES).contains(&self.max_bytes) {
+            return Err(format!(
+                "max_bytes must be between {} and {}",
+                MIN_MAX_BYTES, MAX_MAX_BYTES
+            ));
+        }
+        if self.keep_files > MAX_KEEP_FILES {
+            return Err(format!("keep_files must be at most {}", MAX_KEEP_FILES));
+        }
+        Ok(())
+    }
+}
+
+/// One line of the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogEntry {
+    /// When the paste completed, in RFC 3339 with the local offset.
+    pub timestamp: String,
+    pub session_id: String,
+    /// The text as pasted.
+    pub text: String,
+    /// Bundle id (or process name) of the app pasted into, when known.
+    pub app: Option<String>,
+    /// From recording start until the final transcript arrived.
+    pub duration_ms: Option<u64>,
+    pub provider: Option<String>,
+    pub language: Option<String>,
+}
+
+/// A line waiting for the writer, with the settings it was taken under.
+struct Job {
+    settings: DictationLogSettings,
+    path: PathBuf,
+    entry: LogEntry,
+}
+
+/// Managed state: where lines are sent to be written, once the writer runs.
+#[derive(Default)]
+pub struct DictationLogState(Mutex<Option<Sender<Job>>>);
+
+/// The dictation log as an output sink: appends each pasted dictation if
+/// the settings say so.
+pub struct DictationLog;
+
+impl OutputSink for DictationLog {
+    fn accept(&self, app: &AppHandle, dictation: &Dictation, pasted: &str) {
+        let settings = app.state::<SettingsState>().get().dictation_log;
+        if !settings.enabled || !privacy::policy().dictation_log {
+            return;
+        }
+        let Some(path) = settings.path.clone() else {
+            return;
+        };
+        let job = Job {
+            settings,
+            path,
+            entry: LogEntry {
+                timestamp: Local::now().to_rfc3339(),
+                session_id: dictation.session_id.clone(),
+                text: pasted.to_string(),
+                app: None,
+                duration_ms: dictation.duration_ms,
+                provider: dictation.provider.clone(),
+                language: dictation.language.clone(),
+            },
+        };
+        let sender = app.state::<DictationLogState>().0.lock().unwrap().clone();
+        let sent = sender.is_some_and(|sender| sender.send(job).is_ok());
+        if !sent {
+            tracing::warn!("Dictation log writer isn't running; dictation not logged");
+        }
+    }
+}
+
+/// Starts the thread that writes the log.
+pub fn spawn_writer(app: &AppHandle) {
+    let (sender, receiver) = mpsc::channel();
+    let spawned = crate::crash::supervise(app, "dictation-log", move || write_all(&receiver));
+    match spawned {
+        Ok(()) => *app.state::<DictationLogState>().0.lock().unwrap() = Some(sender),
+        Err(e) => tracing::warn!("Failed to start the dictation log writer: {}", e),
+    }
+}
+
+/// Writes lines as they come, until the app exits.
+fn write_all(receiver: &Receiver<Job>) {
+    while let Ok(mut job) = receiver.recv() {
+        // Looked up here so the paste never waits for it
+        job.entry.app = frontmost::frontmost_bundle_id();
+        if let Err(e) = append(&job) {
+            tracing::warn!("Failed to append to the dictation log: {}", e);
+        }
+    }
+}
+
+/// Appends the job's line, rotating the file first if it would get too big.
+fn append(job: &Job) -> Result<(), String> {
+    let mut line = serde_json::to_string(&job.entry).map_err(|e| e.to_string())?;
+    line.push('\n');
+    let path = &job.path;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.to_string()),
+    };
+    if size > 0 && size + line.len() as u64 > job.settings.max_bytes {
+        rotate(path, job.settings.keep_files)
+            .map_err(|e| format!("rotating {}: {}", path.display(), e))?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    // One write, so readers never see part of a line
+    file.write_all(line.as_bytes())
+        .and_then(|()| match job.settings.sync {
+            SyncPolicy::EveryEntry => file.sync_data(),
+            SyncPolicy::Never => Ok(()),
+        })
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// `path` with `.{n}` after its file name.
+fn rotated(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", n));
+    path.with_file_name(name)
+}
+
+/// Moves `path` to `path.1`, and each older file one number up, dropping
+/// what would go past `keep_files`. With none to keep, `path` is deleted.
+fn rotate(path: &Path, keep_files: u32) -> std::io::Result<()> {
+    if keep_files == 0 {
+        return fs::remove_file(path);
+    }
+    for n in (1..keep_files).rev() {
+        match fs::rename(rotated(path, n), rotated(path, n + 1)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    fs::rename(path, rotated(path, 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::harness::{wait_for, Harness};
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "wispr-dictation-log-{}-{}",
+                test,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn log(&self) -> PathBuf {
+            self.0.join("dictations.jsonl")
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A line for `session_id`, the same length for every two-letter id.
+    fn job(path: &Path, session_id: &str, max_bytes: u64, keep_files: u32) -> Job {
+        Job {
+            settings: DictationLogSettings {
+                enabled: true,
+                path: Some(path.to_path_buf()),
+                max_bytes,
+                keep_files,
+                sync: SyncPolicy::Never,
+            },
+            path: path.to_path_buf(),
+            entry: LogEntry {
+                timestamp: "2026-03-01T09:30:00.000+01:00".to_string(),
+                session_id: session_id.to_string(),
+                text: "Hello world".to_string(),
+                app: None,
+                duration_ms: Some(1200),
+                provider: Some("mock".to_string()),
+                language: Some("en".to_string()),
+            },
+        }
+    }
+
+    /// Bytes each line of [`job`] takes.
+    fn line_len() -> u64 {
+        let entry = job(Path::new("/log"), "s1", 0, 0).entry;
+        serde_json::to_string(&entry).unwrap().len() as u64 + 1
+    }
+
+    /// Session ids of the lines in `path`, in order; none if it is missing.
+    fn sessions(path: &Path) -> Vec<String> {
+        let Ok(content) = fs::read_to_string(path) else {
+            return Vec::new();
+        };
+        content
+            .lines()
+            .map(|line| {
+                let entry: Value = serde_json::from_str(line).unwrap();
+                entry["session_id"].as_str().unwrap().to_string()
+            })
+            .collect()
+    }
+
+    fn logging_to(log: &Path) -> Value {
+        json!({ "dictation_log": { "enabled": true, "path": log } })
+    }
+
+    #[test]
+    fn a_line_that_just_reaches_max_bytes_stays_in_the_file() {
+        let dir = TempDir::new("exact");
+        let log = dir.log();
+        let max_bytes = 2 * line_len();
+
+        append(&job(&log, "s1", max_bytes, 5)).unwrap();
+        append(&job(&log, "s2", max_bytes, 5)).unwrap();
+        assert_eq!(fs::metadata(&log).unwrap().len(), max_bytes);
+        assert_eq!(sessions(&log), ["s1", "s2"]);
+        assert!(!rotated(&log, 1).exists());
+
+        append(&job(&log, "s3", max_bytes, 5)).unwrap();
+        assert_eq!(sessions(&log), ["s3"]);
+        assert_eq!(sessions(&rotated(&log, 1)), ["s1", "s2"]);
+    }
+
+    #[test]
+    fn a_line_one_byte_over_max_bytes_rotates_first() {
+        let dir = TempDir::new("over");
+        let log = dir.log();
+        let max_bytes = 2 * line_len() - 1;
+
+        append(&job(&log, "s1", max_bytes, 5)).unwrap();
+        append(&job(&log, "s2", max_bytes, 5)).unwrap();
+        assert_eq!(sessions(&log), ["s2"]);
+        assert_eq!(sessions(&rotated(&log, 1)), ["s1"]);
+    }
+
+    #[test]
+    fn a_line_longer_than_max_bytes_gets_a_file_of_its_own() {
+        let dir = TempDir::new("long");
+        let log = dir.log();
+
+        append(&job(&log, "s1", 1, 5)).unwrap();
+        append(&job(&log, "s2", 1, 5)).unwrap();
+        assert_eq!(sessions(&log), ["s2"]);
+        assert_eq!(sessions(&rotated(&log, 1)), ["s1"]);
+    }
+
+    #[test]
+    fn rotation_keeps_keep_files_dropping_the_oldest() {
+        let dir = TempDir::new("keep");
+        let log = dir.log();
+        for session_id in ["s1", "s2", "s3", "s4"] {
+            append(&job(&log, session_id, line_len(), 2)).unwrap();
+        }
+
+        assert_eq!(sessions(&log), ["s4"]);
+        assert_eq!(sessions(&rotated(&log, 1)), ["s3"]);
+        assert_eq!(sessions(&rotated(&log, 2)), ["s2"]);
+        assert!(!rotated(&log, 3).exists());
+    }
+
+    #[test]
+    fn keeping_no_files_deletes_the_full_one() {
+        let dir = TempDir::new("keep-none");
+        let log = dir.log();
+        append(&job(&log, "s1", line_len(), 0)).unwrap();
+        append(&job(&log, "s2", line_len(), 0)).unwrap();
+
+        assert_eq!(sessions(&log), ["s2"]);
+        assert!(!rotated(&log, 1).exists());
+    }
+
+    #[test]
+    fn concurrent_dictations_are_logged_in_the_order_they_were_sent() {
+        const DICTATIONS: usize = 32;
+        let dir = TempDir::new("concurrent");
+        let log = dir.log();
+        let harness = Harness::with_settings("dictation-log-concurrent", logging_to(&log));
+        let app = harness.handle();
+        spawn_writer(app);
+
+        let sent = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for index in 0..DICTATIONS {
+                let sent = &sent;
+                scope.spawn(move || {
+                    let dictation = Dictation::start(format!("session-{}", index));
+                    // Sent holding the lock, so `sent` is the order the
+                    // writer got them in
+                    let mut sent = sent.lock().unwrap();
+                    DictationLog.accept(app, &dictation, &format!("dictation {}", index));
+                    sent.push(dictation.session_id);
+                });
+            }
+        });
+
+        wait_for("every line", || sessions(&log).len() >= DICTATIONS);
+        assert_eq!(sessions(&log), sent.into_inner().unwrap());
+    }
+
+    #[test]
+    fn nothing_is_logged_in_privacy_mode() {
+        let dir = TempDir::new("privacy");
+        let log = dir.log();
+        let mut settings = logging_to(&log);
+        settings["privacy"] = json!({ "enabled": true });
+        let harness = Harness::with_settings("dictation-log-privacy", settings);
+        let app = harness.handle();
+        spawn_writer(app);
+
+        DictationLog.accept(app, &Dictation::start("private".into()), "a secret");
+        // Once privacy mode is off, the next dictation is logged, and only it
+        let mut current = app.state::<SettingsState>().get();
+        current.privacy.enabled = false;
+        privacy::apply(&current);
+        DictationLog.accept(app, &Dictation::start("public".into()), "no secret");
+
+        wait_for("the line", || !sessions(&log).is_empty());
+        assert_eq!(sessions(&log), ["public"]);
+        assert!(!fs::read_to_string(&log).unwrap().contains("a secret"));
+    }
+}
@@ -0,0 +1,87 @@
+//! Dock icon visibility.
+//!
+//! With the tray icon as its permanent presence, the app doesn't need a Dock
+//! slot or a Cmd+Tab entry, so by default it runs as an accessory app. The
+//! `dock.visible` setting switches it back to a regular app at runtime.
+//!
+//! # Platform Support
+//!
+//! - macOS: the activation policy switches between Regular and Accessory.
+//!   Accessory apps can still show windows, so the pill keeps appearing on
+//!   top when the hotkey fires.
+//! - Windows and Linux: there is no Dock; the setting is stored but has no
+//!   effect.
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings;
+use crate::AppHandle;
+
+/// Dock icon settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DockSettings {
+    /// Show the app in the Dock and the app switcher.
+    pub visible: bool,
+}
+
+/// Applies the Dock icon setting.
+///
+/// Called from the setup hook before any window exists, so a hidden Dock
+/// icon never flashes up at launch.
+pub fn apply(app: &AppHandle, dock: &DockSettings) {
+    #[cfg(target_os = "macos")]
+    {
+        let policy = if dock.visible {
+            tauri::ActivationPolicy::Regular
+        } else {
+            tauri::ActivationPolicy::Accessory
+        };
+        if let Err(e) = app.set_activation_policy(policy) {
+            tracing::warn!("Failed to change the Dock icon: {}", e);
+            return;
+        }
+        if !dock.visible {
+            refocus_windows(app);
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    let _ = (app, dock);
+}
+
+/// Becoming an accessory app deactivates it and sends its windows behind
+/// the active app's. Any open window other than the pill (e.g. the settings
+/// window the user just flipped the switch in) is brought back to the
+/// front.
+#[cfg(target_os = "macos")]
+fn refocus_windows(app: &AppHandle) {
+    use tauri::Manager;
+
+    let windows: Vec<_> = app
+        .webview_windows()
+        .into_values()
+        .filter(|window| window.label() != crate::pill::MAIN_WINDOW)
+        .filter(|window| window.is_visible().unwrap_or(false))
+        .collect();
+    if windows.is_empty() {
+        return;
+    }
+    let _ = app.show();
+    for window in windows {
+        let _ = window.set_focus();
+    }
+}
+
+/// Shows or hides the app's Dock icon and saves the preference.
+///
+/// # Errors
+///
+/// Returns an error if the settings cannot be saved.
+#[tauri::command]
+pub fn set_dock_icon_visible(app: AppHandle, visible: bool) -> Result<(), String> {
+    settings::update(&app, |settings| {
+        settings.dock.visible = visible;
+        Ok(())
+    })
+    .map(|_| ())
+}
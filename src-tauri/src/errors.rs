@@ -0,0 +1,560 @@
+//! Stable error codes and what to tell the user about them.
+//!
+//! Every error the frontend or the user gets to see carries an
+//! [`ErrorCode`]: as `code` in the typed command errors ([`SessionError`],
+//! [`HistoryError`], [`AutostartError`], [`WindowError`], [`UpdateError`],
+//! [`CaptureError`], [`AudioFileError`]), in `paste:failed`
+//! and `transcription:failed`, and in notifications. The code is the part to
+//! match on; the prose next to it may change.
+//!
+//! [`message_for`] maps each code to a default message and a suggested
+//! action, so notifications and the frontend ([`get_error_messages`]) share
+//! one copy of the text. It and the errors' `code()` match without a
+//! wildcard, so a new code or error variant doesn't compile until it has a
+//! code and a message.
+//!
+//! [`SessionError`]: crate::app_state::SessionError
+//! [`HistoryError`]: crate::history::HistoryError
+//! [`AutostartError`]: crate::autostart::AutostartError
+//! [`WindowError`]: crate::windows::WindowError
+//! [`UpdateError`]: crate::updater::UpdateError
+//! [`CaptureError`]: crate::shortcut_capture::CaptureError
+//! [`AudioFileError`]: crate::audio_file::AudioFileError
+
+use std::fmt;
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+/// A stable identifier of an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The dictation ended, was cancelled or isn't the current one.
+    SessionStale,
+    /// Another paste is in progress.
+    PasteBusy,
+    /// The paste had to be confirmed, and wasn't.
+    PasteNotConfirmed,
+    /// What was said in command mode isn't a voice command.
+    UnrecognizedCommand,
+    /// A voice command's keys can't be pressed.
+    InvalidCommand,
+    /// The clipboard couldn't be written.
+    Clipboard,
+    /// The clipboard read back differs from the text written to it.
+    ClipboardMismatch,
+    /// Our windows couldn't be hidden to hand focus back.
+    FocusReturn,
+    /// The paste keystroke was refused for lack of Accessibility access.
+    PermAccessibility,
+    /// The paste keystroke couldn't be sent.
+    PasteKeystroke,
+    /// Secure input is on, so the paste keystroke would be dropped.
+    SecureInput,
+    /// The caret couldn't be moved to the `{cursor}` marker.
+    Caret,
+    /// A paste in chunks stopped partway: it was stopped, or focus moved to
+    /// another app.
+    PasteStopped,
+    /// The microphone is denied or missing.
+    PermMicrophone,
+    /// The recording is silent, so it wasn't transcribed.
+    EmptyRecording,
+    /// The provider rejected the API key.
+    ProviderAuth,
+    /// The provider is rate limiting us.
+    ProviderRateLimit,
+    /// The provider couldn't be reached.
+    ProviderUnreachable,
+    /// Any other transcription failure.
+    Transcription,
+    /// The audio file isn't in a format that can be transcribed.
+    AudioUnsupported,
+    /// The audio file is DRM protected.
+    AudioProtected,
+    /// The audio file is cut short or damaged.
+    AudioTruncated,
+    /// The audio file couldn't be read.
+    AudioUnreadable,
+    /// The history entry doesn't exist.
+    HistoryNotFound,
+    /// The history entry's audio wasn't kept.
+    HistoryNotRetained,
+    /// Any other history failure.
+    History,
+    /// The login item waits for the user's approval.
+    AutostartApproval,
+    /// The login item can't be changed without permission.
+    AutostartDenied,
+    /// Any other login item failure.
+    Autostart,
+    /// A window couldn't be opened or closed.
+    Window,
+    /// The recorded shortcut is the system's.
+    ShortcutReserved,
+    /// No shortcut was recorded.
+    ShortcutCapture,
+    /// The update server couldn't be reached.
+    UpdateNetwork,
+    /// An update didn't match its signature.
+    UpdateSignature,
+    /// An update couldn't be written to disk.
+    UpdateDisk,
+    /// Any other update failure.
+    Update,
+    /// Anything else.
+    Internal,
+}
+
+impl ErrorCode {
+    /// Every code, for [`get_error_messages`].
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::SessionStale,
+        ErrorCode::PasteBusy,
+        ErrorCode::PasteNotConfirmed,
+        ErrorCode::UnrecognizedCommand,
+        ErrorCode::InvalidCommand,
+        ErrorCode::Clipboard,
+        ErrorCode::ClipboardMismatch,
+        ErrorCode::FocusReturn,
+        ErrorCode::PermAccessibility,
+        ErrorCode::PasteKeystroke,
+        ErrorCode::SecureInput,
+        ErrorCode::Caret,
+        ErrorCode::PasteStopped,
+        ErrorCode::PermMicrophone,
+        ErrorCode::EmptyRecording,
+        ErrorCode::ProviderAuth,
+        ErrorCode::ProviderRateLimit,
+        ErrorCode::ProviderUnreachable,
+        ErrorCode::Transcription,
+        ErrorCode::AudioUnsupported,
+        ErrorCode::AudioProtected,
+        ErrorCode::AudioTruncated,
+        ErrorCode::AudioUnreadable,
+        ErrorCode::HistoryNotFound,
+        ErrorCode::HistoryNotRetained,
+        ErrorCode::History,
+        ErrorCode::AutostartApproval,
+        ErrorCode::AutostartDenied,
+        ErrorCode::Autostart,
+        ErrorCode::Window,
+        ErrorCode::ShortcutReserved,
+        ErrorCode::ShortcutCapture,
+        ErrorCode::UpdateNetwork,
+        ErrorCode::UpdateSignature,
+        ErrorCode::UpdateDisk,
+        ErrorCode::Update,
+        ErrorCode::Internal,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::SessionStale => "E_SESSION_STALE",
+            ErrorCode::PasteBusy => "E_PASTE_BUSY",
+            ErrorCode::PasteNotConfirmed => "E_PASTE_NOT_CONFIRMED",
+            ErrorCode::UnrecognizedCommand => "E_UNRECOGNIZED_COMMAND",
+            ErrorCode::InvalidCommand => "E_INVALID_COMMAND",
+            ErrorCode::Clipboard => "E_CLIPBOARD",
+            ErrorCode::ClipboardMismatch => "E_CLIPBOARD_MISMATCH",
+            ErrorCode::FocusReturn => "E_FOCUS_RETURN",
+            ErrorCode::PermAccessibility => "E_PERM_ACCESSIBILITY",
+            ErrorCode::PasteKeystroke => "E_PASTE_KEYSTROKE",
+            ErrorCode::SecureInput => "E_SECURE_INPUT",
+            ErrorCode::Caret => "E_CARET",
+            ErrorCode::PasteStopped => "E_PASTE_STOPPED",
+            ErrorCode::PermMicrophone => "E_PERM_MICROPHONE",
+            ErrorCode::EmptyRecording => "E_EMPTY_RECORDING",
+            ErrorCode::ProviderAuth => "E_PROVIDER_AUTH",
+            ErrorCode::ProviderRateLimit => "E_PROVIDER_RATE_LIMIT",
+            ErrorCode::ProviderUnreachable => "E_PROVIDER_UNREACHABLE",
+            ErrorCode::Transcription => "E_TRANSCRIPTION",
+            ErrorCode::AudioUnsupported => "E_AUDIO_UNSUPPORTED",
+            ErrorCode::AudioProtected => "E_AUDIO_PROTECTED",
+            ErrorCode::AudioTruncated => "E_AUDIO_TRUNCATED",
+            ErrorCode::AudioUnreadable => "E_AUDIO_UNREADABLE",
+            ErrorCode::HistoryNotFound => "E_HISTORY_NOT_FOUND",
+            ErrorCode::HistoryNotRetained => "E_HISTORY_AUDIO_NOT_RETAINED",
+            ErrorCode::History => "E_HISTORY",
+            ErrorCode::AutostartApproval => "E_AUTOSTART_APPROVAL",
+            ErrorCode::AutostartDenied => "E_AUTOSTART_DENIED",
+            ErrorCode::Autostart => "E_AUTOSTART",
+            ErrorCode::Window => "E_WINDOW",
+            ErrorCode::ShortcutReserved => "E_SHORTCUT_RESERVED",
+            ErrorCode::ShortcutCapture => "E_SHORTCUT_CAPTURE",
+            ErrorCode::UpdateNetwork => "E_UPDATE_NETWORK",
+            ErrorCode::UpdateSignature => "E_UPDATE_SIGNATURE",
+            ErrorCode::UpdateDisk => "E_UPDATE_DISK",
+            ErrorCode::Update => "E_UPDATE",
+            ErrorCode::Internal => "E_INTERNAL",
+        }
+    }
+
+    /// The code of a transcription failure the webview reported, from its
+    /// message (the provider SDK and the browser only give prose).
+    pub fn of_transcription_failure(message: &str) -> Self {
+        let message = message.to_lowercase();
+        let mentions = |words: &[&str]| words.iter().any(|word| message.contains(word));
+        if mentions(&[
+            "notallowederror",
+            "notfounderror",
+            "microphone",
+            "permission denied",
+        ]) {
+            ErrorCode::PermMicrophone
+        } else if mentions(&["401", "403", "unauthorized", "forbidden", "api key"]) {
+            ErrorCode::ProviderAuth
+        } else if mentions(&["429", "rate limit", "too many requests"]) {
+            ErrorCode::ProviderRateLimit
+        } else if mentions(&["network", "websocket", "timed out", "timeout", "offline"]) {
+            ErrorCode::ProviderUnreachable
+        } else {
+            ErrorCode::Transcription
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Serializes an error as `{ "kind": kind, "code": code, ...fields }`, the
+/// shape of every typed command error.
+pub fn serialize_error<S: Serializer>(
+    serializer: S,
+    kind: &str,
+    code: ErrorCode,
+    fields: Value,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(None)?;
+    map.serialize_entry("kind", kind)?;
+    map.serialize_entry("code", &code)?;
+    if let Value::Object(fields) = fields {
+        for (name, value) in &fields {
+            map.serialize_entry(name, value)?;
+        }
+    }
+    map.end()
+}
+
+/// What to tell the user about an error.
+#[derive(Debug, Clone, Serialize)]
+pub struct UserMessage {
+    pub code: ErrorCode,
+    /// What went wrong.
+    pub message: &'static str,
+    /// What the user can do about it, if anything.
+    pub action: Option<&'static str>,
+}
+
+/// The default message and suggested action for `code`.
+pub fn message_for(code: ErrorCode) -> UserMessage {
+    let (message, action) = match code {
+        ErrorCode::SessionStale => ("That dictation already ended", None),
+        ErrorCode::PasteBusy => (
+            "Another paste is in progress",
+            Some("Try again in a moment"),
+        ),
+        ErrorCode::PasteNotConfirmed => (
+            "The dictation wasn't pasted",
+            Some("It is on the clipboard"),
+        ),
+        ErrorCode::UnrecognizedCommand => (
+            "That isn't a voice command",
+            Some("Nothing was pressed; try one from the command list"),
+        ),
+        ErrorCode::InvalidCommand => (
+            "A voice command has keys that can't be pressed",
+            Some("Fix its keys in the command mode settings"),
+        ),
+        ErrorCode::Clipboard => (
+            "Couldn't write to the clipboard",
+            Some("Copy the text from history instead"),
+        ),
+        ErrorCode::ClipboardMismatch => (
+            "The clipboard didn't keep the text as written",
+            Some("Copy the text from history instead"),
+        ),
+        ErrorCode::FocusReturn => (
+            "Couldn't switch back to the app you were typing in",
+            Some("Click into it and paste by hand"),
+        ),
+        ErrorCode::PermAccessibility => (
+            "Pasting needs Accessibility access",
+            Some("Allow Wispr Clone in System Settings › Privacy & Security › Accessibility"),
+        ),
+        ErrorCode::PasteKeystroke => (
+            "Couldn't send the paste shortcut",
+            Some("Paste by hand; the text is on the clipboard"),
+        ),
+        ErrorCode::SecureInput => (
+            "A password field or Secure Keyboard Entry is blocking the paste",
+            Some("Paste by hand; the text is on the clipboard"),
+        ),
+        ErrorCode::Caret => ("Couldn't move the cursor to the snippet's marker", None),
+        ErrorCode::PasteStopped => (
+            "The paste stopped before the end of the text",
+            Some("The rest of the text is on the clipboard"),
+        ),
+        ErrorCode::PermMicrophone => (
+            "The microphone is unavailable",
+            Some("Allow microphone access, or connect a microphone"),
+        ),
+        ErrorCode::EmptyRecording => (
+            "We're not hearing anything",
+            Some("Check that the microphone is connected and not muted"),
+        ),
+        ErrorCode::ProviderAuth => (
+            "The transcription service rejected the API key",
+            Some("Check the API key"),
+        ),
+        ErrorCode::ProviderRateLimit => (
+            "The transcription service is busy",
+            Some("Wait a moment, then dictate again"),
+        ),
+        ErrorCode::ProviderUnreachable => (
+            "Couldn't reach the transcription service",
+            Some("Check the internet connection"),
+        ),
+        ErrorCode::Transcription => ("Transcription failed", Some("Dictate again")),
+        ErrorCode::AudioUnsupported => (
+            "That audio file can't be transcribed",
+            Some("Use an mp3, m4a, flac, ogg, webm or wav file"),
+        ),
+        ErrorCode::AudioProtected => (
+            "That audio file is copy protected",
+            Some("Use a copy without DRM"),
+        ),
+        ErrorCode::AudioTruncated => ("That audio file is incomplete or damaged", None),
+        ErrorCode::AudioUnreadable => (
+            "Couldn't read that audio file",
+            Some("Check that it still exists and can be opened"),
+        ),
+        ErrorCode::HistoryNotFound => ("That history entry no longer exists", None),
+        ErrorCode::HistoryNotRetained => (
+            "The audio of that dictation wasn't kept",
+            Some("Turn on audio retention in the settings to keep future recordings"),
+        ),
+        ErrorCode::History => (
+            "Couldn't read or update the history",
+            Some("Restart the app if it keeps happening"),
+        ),
+        ErrorCode::AutostartApproval => (
+            "Starting at login needs your approval",
+            Some("Allow Wispr Clone in System Settings › General › Login Items"),
+        ),
+        ErrorCode::AutostartDenied => (
+            "Not allowed to change the login items",
+            Some("Check the permissions of your startup folder"),
+        ),
+        ErrorCode::Autostart => ("Couldn't change starting at login", None),
+        ErrorCode::Window => ("Couldn't open or close the window", Some("Try again")),
+        ErrorCode::ShortcutReserved => (
+            "That shortcut is reserved by the system",
+            Some("Pick another one"),
+        ),
+        ErrorCode::ShortcutCapture => ("No shortcut was recorded", Some("Try again")),
+        ErrorCode::UpdateNetwork => (
+            "Couldn't reach the update server",
+            Some("Check the internet connection"),
+        ),
+        ErrorCode::UpdateSignature => (
+            "The update couldn't be verified, so it wasn't installed",
+            Some("Download the latest version from the website"),
+        ),
+        ErrorCode::UpdateDisk => (
+            "Couldn't save the update",
+            Some("Free up some disk space, then try again"),
+        ),
+        ErrorCode::Update => ("Couldn't update the app", None),
+        ErrorCode::Internal => (
+            "Something went wrong",
+            Some("Export diagnostics from the settings if it keeps happening"),
+        ),
+    };
+    UserMessage {
+        code,
+        message,
+        action,
+    }
+}
+
+/// Returns the default message and suggested action of every error code.
+#[tauri::command]
+pub fn get_error_messages() -> Vec<UserMessage> {
+    ErrorCode::ALL
+        .iter()
+        .map(|&code| message_for(code))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// Every code, in declaration order.
+    fn codes() -> Vec<ErrorCode> {
+        // Fails to build once a code is added, until it is added here; the
+        // tests below then fail until it is in `ALL`
+        let _exhaustive = |code: ErrorCode| match code {
+            ErrorCode::SessionStale
+            | ErrorCode::PasteBusy
+            | ErrorCode::PasteNotConfirmed
+            | ErrorCode::UnrecognizedCommand
+            | ErrorCode::InvalidCommand
+            | ErrorCode::Clipboard
+            | ErrorCode::ClipboardMismatch
+            | ErrorCode::FocusReturn
+            | ErrorCode::PermAccessibility
+            | ErrorCode::PasteKeystroke
+            | ErrorCode::SecureInput
+            | ErrorCode::Caret
+            | ErrorCode::PasteStopped
+            | ErrorCode::PermMicrophone
+            | ErrorCode::EmptyRecording
+            | ErrorCode::ProviderAuth
+            | ErrorCode::ProviderRateLimit
+            | ErrorCode::ProviderUnreachable
+            | ErrorCode::Transcription
+            | ErrorCode::AudioUnsupported
+            | ErrorCode::AudioProtected
+            | ErrorCode::AudioTruncated
+            | ErrorCode::AudioUnreadable
+            | ErrorCode::HistoryNotFound
+            | ErrorCode::HistoryNotRetained
+            | ErrorCode::History
+            | ErrorCode::AutostartApproval
+            | ErrorCode::AutostartDenied
+            | ErrorCode::Autostart
+            | ErrorCode::Window
+            | ErrorCode::ShortcutReserved
+            | ErrorCode::ShortcutCapture
+            | ErrorCode::UpdateNetwork
+            | ErrorCode::UpdateSignature
+            | ErrorCode::UpdateDisk
+            | ErrorCode::Update
+            | ErrorCode::Internal => {}
+        };
+        vec![
+            ErrorCode::SessionStale,
+            ErrorCode::PasteBusy,
+            ErrorCode::PasteNotConfirmed,
+            ErrorCode::UnrecognizedCommand,
+            ErrorCode::InvalidCommand,
+            ErrorCode::Clipboard,
+            ErrorCode::ClipboardMismatch,
+            ErrorCode::FocusReturn,
+            ErrorCode::PermAccessibility,
+            ErrorCode::PasteKeystroke,
+            ErrorCode::SecureInput,
+            ErrorCode::Caret,
+            ErrorCode::PasteStopped,
+            ErrorCode::PermMicrophone,
+            ErrorCode::EmptyRecording,
+            ErrorCode::ProviderAuth,
+            ErrorCode::ProviderRateLimit,
+            ErrorCode::ProviderUnreachable,
+            ErrorCode::Transcription,
+            ErrorCode::AudioUnsupported,
+            ErrorCode::AudioProtected,
+            ErrorCode::AudioTruncated,
+            ErrorCode::AudioUnreadable,
+            ErrorCode::HistoryNotFound,
+            ErrorCode::HistoryNotRetained,
+            ErrorCode::History,
+            ErrorCode::AutostartApproval,
+            ErrorCode::AutostartDenied,
+            ErrorCode::Autostart,
+            ErrorCode::Window,
+            ErrorCode::ShortcutReserved,
+            ErrorCode::ShortcutCapture,
+            ErrorCode::UpdateNetwork,
+            ErrorCode::UpdateSignature,
+            ErrorCode::UpdateDisk,
+            ErrorCode::Update,
+            ErrorCode::Internal,
+        ]
+    }
+
+    #[test]
+    fn every_code_is_listed() {
+        assert_eq!(ErrorCode::ALL, codes());
+    }
+
+    #[test]
+    fn codes_are_unique_and_serialized_as_their_string() {
+        let mut seen = HashSet::new();
+        for code in codes() {
+            let string = code.as_str();
+            assert!(seen.insert(string), "{} is used twice", string);
+            assert!(string.starts_with("E_"), "{}", string);
+            assert!(
+                string[2..]
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c == '_'),
+                "{}",
+                string
+            );
+            assert_eq!(serde_json::to_value(code).unwrap(), string);
+            assert_eq!(code.to_string(), string);
+        }
+    }
+
+    #[test]
+    fn every_code_has_a_message() {
+        let messages = get_error_messages();
+        assert_eq!(messages.len(), codes().len());
+        for (message, code) in messages.iter().zip(codes()) {
+            assert_eq!(message.code, code);
+            // Notifications put the full stops in
+            for text in std::iter::once(message.message).chain(message.action) {
+                assert!(!text.trim().is_empty(), "{} has an empty message", code);
+                assert!(!text.ends_with('.'), "{}: {:?}", code, text);
+            }
+        }
+    }
+
+    #[test]
+    fn transcription_failures_are_told_apart() {
+        let cases = [
+            (
+                "NotAllowedError: Permission denied",
+                ErrorCode::PermMicrophone,
+            ),
+            (
+                "NotFoundError: Requested device not found",
+                ErrorCode::PermMicrophone,
+            ),
+            (
+                "Deepgram returned 401 Unauthorized",
+                ErrorCode::ProviderAuth,
+            ),
+            ("429 Too Many Requests", ErrorCode::ProviderRateLimit),
+            (
+                "WebSocket closed before the transcript",
+                ErrorCode::ProviderUnreachable,
+            ),
+            ("The request timed out", ErrorCode::ProviderUnreachable),
+            ("Mock provider error", ErrorCode::Transcription),
+        ];
+        for (message, code) in cases {
+            assert_eq!(
+                ErrorCode::of_transcription_failure(message),
+                code,
+                "{}",
+                message
+            );
+        }
+    }
+}
@@ -0,0 +1,656 @@
+//! Actions triggered from outside the app.
+//!
+//! Two kinds of external trigger end up here:
+//!
+//! - Launching the app again (`open -a wispr-clone --args --transcribe
+//!   memo.m4a`): the single instance plugin hands the arguments to the
+//!   instance that is already running.
+//! - `wispr://` links, e.g. from Raycast or Keyboard Maestro:
+//!   - `wispr://toggle`: start a dictation, or stop the one in progress
+//!   - `wispr://paste-last`: paste the most recent transcript again
+//!   - `wispr://toggle-meeting`: start recording a meeting, or stop the one
+//!     in progress
+//!   - `wispr://transcribe?path=/absolute/path/memo.m4a`: transcribe an
+//!     audio file and paste the result
+//!   - `wispr://get-last?callback=shortcuts://...`: open the callback with
+//!     the most recent transcript as its percent-encoded `text` parameter
+//!     (x-callback-url's `x-success` and `x-error` work too). Callback
+//!     schemes must be in the `automation.callback_schemes` allowlist, so a
+//!     web page can't have transcripts sent to itself
+//!
+//! Both are parsed into an [`ExternalAction`] and go through [`dispatch`].
+//! Recording and transcription live in the pill window's webview, so most
+//! actions are events the frontend acts on:
+//!
+//! - `external:toggle`: start a dictation, or stop the one in progress
+//! - `external:start` / `external:stop`: the same, one direction only (from
+//!   the local HTTP API)
+//! - `external:toggle-meeting`: start recording a meeting, or stop the one
+//!   in progress (see [`crate::meeting`])
+//! - `external:transcribe` (`{ "path": ... }`): transcribe an audio file and
+//!   paste the result
+//! - `external:error` (`{ "message": ... }`): a request that couldn't be
+//!   carried out
+//! - `deeplink:error` (`{ "url": ..., "message": ... }`): a link that is
+//!   unknown or malformed
+//!
+//! # Architecture Decision
+//!
+//! Actions can arrive before the webview has loaded (arguments or the link
+//! that started the app) and would be lost as events. They are queued until
+//! the frontend calls [`take_pending_external_events`], after which they are
+//! emitted directly.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use tauri::ipc::{Channel, InvokeResponseBody};
+use tauri::{Emitter, Manager, State, Url};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::audio_file::{self, AudioFileError, DecodedAudio};
+use crate::history::{manage, HistoryState};
+use crate::settings::SettingsState;
+use crate::AppHandle;
+
+/// URL scheme registered for deep links.
+pub const URL_SCHEME: &str = "wispr";
+
+/// Characters left unescaped in a URI component, as by JavaScript's
+/// `encodeURIComponent` minus its few sub-delimiters.
+const URI_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Schemes a callback can never use: `wispr` would loop back into us, the
+/// others run or read things locally.
+const FORBIDDEN_CALLBACK_SCHEMES: &[&str] = &[URL_SCHEME, "file", "javascript", "data"];
+
+/// Extensions accepted for file transcription.
+const AUDIO_EXTENSIONS: &[&str] = &["m4a", "mp3", "mp4", "wav", "webm", "ogg", "flac", "aac"];
+
+/// Largest audio file read for transcription.
+const MAX_AUDIO_FILE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Something an external trigger asked the app to do.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ExternalAction {
+    /// Start a dictation, or stop the one in progress.
+    Toggle,
+    /// Start a dictation unless one is in progress.
+    Start,
+    /// Finish the dictation in progress and paste it.
+    Stop,
+    /// Paste the most recent transcript again.
+    PasteLast,
+    /// Start recording a meeting, or stop the one in progress.
+    ToggleMeeting,
+    /// Transcribe an audio file and paste the transcript.
+    Transcribe { path: PathBuf },
+    /// Open `callback` with the most recent transcript as its `text`
+    /// parameter, or `error_callback` with an `errorMessage`.
+    GetLast {
+        #[serde(skip)]
+        callback: Url,
+        #[serde(skip)]
+        error_callback: Option<Url>,
+    },
+}
+
+/// Settings for automation from other apps.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutomationSettings {
+    /// URL schemes `wispr://get-last` may send transcripts to. Anything that
+    /// can open a link can ask for the last transcript, so only apps the
+    /// user trusts belong here.
+    pub callback_schemes: Vec<String>,
+}
+
+impl Default for AutomationSettings {
+    fn default() -> Self {
+        Self {
+            callback_schemes: vec![
+                "shortcuts".to_string(),
+                "raycast".to_string(),
+                "kmtrigger".to_string(),
+            ],
+        }
+    }
+}
+
+impl AutomationSettings {
+    /// Validates a configuration supplied by the frontend.
+    pub fn validate(&self) -> Result<(), String> {
+        for scheme in &self.callback_schemes {
+            let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+            if !valid {
+                return Err(format!("\"{}\" is not a URL scheme", scheme));
+            }
+            if FORBIDDEN_CALLBACK_SCHEMES
+                .iter()
+                .any(|forbidden| forbidden.eq_ignore_ascii_case(scheme))
+            {
+                return Err(format!("\"{}\" can't be used for callbacks", scheme));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Payload of the `external:error` event.
+#[derive(Debug, Clone, Serialize)]
+struct ExternalError {
+    message: String,
+}
+
+/// An event held back until the frontend is listening.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingEvent {
+    pub event: &'static str,
+    pub payload: serde_json::Value,
+}
+
+/// Payload of the `deeplink:error` event.
+#[derive(Debug, Clone, Serialize)]
+struct DeepLinkError {
+    url: String,
+    message: String,
+}
+
+/// Managed state queueing events until the frontend is listening.
+pub struct ExternalState {
+    /// `None` once the frontend has taken the queue; events are emitted
+    /// directly from then on.
+    queue: Mutex<Option<Vec<PendingEvent>>>,
+}
+
+impl Default for ExternalState {
+    fn default() -> Self {
+        Self {
+            queue: Mutex::new(Some(Vec::new())),
+        }
+    }
+}
+
+/// Parses launch arguments (without the program name) relative to `cwd`.
+///
+/// Returns `Ok(None)` when the arguments don't ask for anything; flags that
+/// only matter at startup (like `--hidden`) are ignored.
+///
+/// # Errors
+///
+/// Returns an error for a `--transcribe` without a usable audio file.
+pub fn parse_args(args: &[String], cwd: &Path) -> Result<Option<ExternalAction>, String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let path = match arg.as_str() {
+            "--toggle" => return Ok(Some(ExternalAction::Toggle)),
+            "--transcribe" => args
+                .next()
+                .ok_or_else(|| "--transcribe needs a file path".to_string())?,
+            _ => match arg.strip_prefix("--transcribe=") {
+                Some(path) => path,
+                None => continue,
+            },
+        };
+        let path = audio_file(&cwd.join(path))?;
+        return Ok(Some(ExternalAction::Transcribe { path }));
+    }
+    Ok(None)
+}
+
+/// Checks that `path` is a readable audio file, returning its absolute path.
+///
+/// # Errors
+///
+/// Returns an error if the file doesn't exist, isn't a regular file, is too
+/// large or doesn't have an audio extension.
+pub fn audio_file(path: &Path) -> Result<PathBuf, String> {
+    let path = path
+        .canonicalize()
+        .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    let metadata = std::fs::metadata(&path).map_err(|e| e.to_string())?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    if metadata.len() > MAX_AUDIO_FILE_BYTES {
+        return Err(format!(
+            "{} is larger than {} MB",
+            path.display(),
+            MAX_AUDIO_FILE_BYTES / 1024 / 1024
+        ));
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    match extension {
+        Some(extension) if AUDIO_EXTENSIONS.contains(&extension.as_str()) => Ok(path),
+        _ => Err(format!(
+            "{} is not a supported audio file (expected one of: {})",
+            path.display(),
+            AUDIO_EXTENSIONS.join(", ")
+        )),
+    }
+}
+
+/// Parses a `wispr://` link. Callback URLs must use one of
+/// `callback_schemes`.
+///
+/// # Errors
+///
+/// Returns an error for another scheme, an unknown action, unexpected,
+/// repeated or missing parameters, a `transcribe` path that isn't an
+/// absolute path to a supported audio file, and a callback whose scheme
+/// isn't allowed.
+pub fn parse_url(url: &Url, callback_schemes: &[String]) -> Result<ExternalAction, String> {
+    if url.scheme() != URL_SCHEME {
+        return Err(format!("unsupported scheme \"{}\"", url.scheme()));
+    }
+
+    // `wispr://toggle` puts the action in the host, `wispr:toggle` in the path
+    let url_path = url.path().trim_matches('/');
+    let action = match url.host_str().filter(|host| !host.is_empty()) {
+        Some(host) if url_path.is_empty() => host,
+        Some(_) => return Err(format!("unexpected path \"{}\"", url.path())),
+        None => url_path,
+    };
+
+    let allowed: &[&str] = match action {
+        "toggle" | "paste-last" | "toggle-meeting" => &[],
+        "transcribe" => &["path"],
+        "get-last" => &["callback", "x-success", "x-error"],
+        "" => return Err("missing action".to_string()),
+        other => return Err(format!("unknown action \"{}\"", other)),
+    };
+
+    // Query values are percent-decoded here
+    let mut params = BTreeMap::new();
+    for (key, value) in url.query_pairs() {
+        if !allowed.contains(&key.as_ref()) {
+            return Err(format!("unexpected parameter \"{}\"", key));
+        }
+        if params.insert(key.to_string(), value.into_owned()).is_some() {
+            return Err(format!("parameter \"{}\" given more than once", key));
+        }
+    }
+
+    match action {
+        "toggle" => Ok(ExternalAction::Toggle),
+        "paste-last" => Ok(ExternalAction::PasteLast),
+        "toggle-meeting" => Ok(ExternalAction::ToggleMeeting),
+        "transcribe" => {
+            let path = params
+                .get("path")
+                .ok_or_else(|| "transcribe needs a path parameter".to_string())?;
+            let path = Path::new(path);
+            // There is no working directory to resolve against
+            if !path.is_absolute() {
+                return Err(format!("path must be absolute: {}", path.display()));
+            }
+            Ok(ExternalAction::Transcribe {
+                path: audio_file(path)?,
+            })
+        }
+        _ => {
+            // `callback` is the short form of x-callback-url's `x-success`
+            let callback = params
+                .get("callback")
+                .or_else(|| params.get("x-success"))
+                .ok_or_else(|| "get-last needs a callback parameter".to_string())?;
+            Ok(ExternalAction::GetLast {
+                callback: callback_url(callback, callback_schemes)?,
+                error_callback: params
+                    .get("x-error")
+                    .map(|url| callback_url(url, callback_schemes))
+                    .transpose()?,
+            })
+        }
+    }
+}
+
+/// Checks a callback URL against the allowed schemes.
+fn callback_url(url: &str, callback_schemes: &[String]) -> Result<Url, String> {
+    let url = Url::parse(url).map_err(|e| format!("invalid callback URL: {}", e))?;
+    // Parsing lowercases the scheme
+    if !callback_schemes
+        .iter()
+        .any(|scheme| scheme.eq_ignore_ascii_case(url.scheme()))
+    {
+        return Err(format!(
+            "callback scheme \"{}\" is not allowed (allowed: {})",
+            url.scheme(),
+            callback_schemes.join(", ")
+        ));
+    }
+    Ok(url)
+}
+
+/// Appends `name=value` to the query of `url`, percent-encoding `value` as a
+/// URI component (spaces become `%20`, not `+`).
+fn with_query_param(url: &Url, name: &str, value: &str) -> Url {
+    let value = utf8_percent_encode(value, URI_COMPONENT);
+    let query = match url.query().filter(|query| !query.is_empty()) {
+        Some(query) => format!("{}&{}={}", query, name, value),
+        None => format!("{}={}", name, value),
+    };
+    let mut url = url.clone();
+    url.set_query(Some(&query));
+    url
+}
+
+/// Handles `wispr://` links, whether they started the app or were opened
+/// while it was running.
+pub fn handle_urls(app: &AppHandle, urls: Vec<Url>) {
+    let callback_schemes = app
+        .state::<SettingsState>()
+        .get()
+        .automation
+        .callback_schemes;
+    for url in urls {
+        match parse_url(&url, &callback_schemes) {
+            Ok(action) => dispatch(app, action),
+            Err(message) => {
+                tracing::warn!("Ignoring deep link {}: {}", url, message);
+                emit(
+                    app,
+                    "deeplink:error",
+                    DeepLinkError {
+                        url: url.to_string(),
+                        message,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Handles the arguments of a second launch, forwarded by the single
+/// instance plugin. A bare relaunch starts a dictation, the same as the
+/// hotkey.
+pub fn handle_second_launch(app: &AppHandle, args: Vec<String>, cwd: String) {
+    // The first argument is the program path
+    let args = args.get(1..).unwrap_or_default();
+    // Links forwarded this way reach the deep link handler on their own
+    let scheme = format!("{}:", URL_SCHEME);
+    if args.iter().any(|arg| arg.starts_with(&scheme)) {
+        return;
+    }
+    match parse_args(args, Path::new(&cwd)) {
+        Ok(action) => dispatch(app, action.unwrap_or(ExternalAction::Toggle)),
+        Err(message) => report_error(app, message),
+    }
+}
+
+/// Sends `action` to the frontend, or queues it until the frontend is ready.
+pub fn dispatch(app: &AppHandle, action: ExternalAction) {
+    tracing::info!("External action: {:?}", action);
+    match &action {
+        ExternalAction::Toggle => emit(app, "external:toggle", ()),
+        ExternalAction::Start => emit(app, "external:start", ()),
+        ExternalAction::Stop => emit(app, "external:stop", ()),
+        ExternalAction::PasteLast => paste_last(app.clone()),
+        ExternalAction::ToggleMeeting => emit(app, "external:toggle-meeting", ()),
+        ExternalAction::GetLast {
+            callback,
+            error_callback,
+        } => send_last(app.clone(), callback.clone(), error_callback.clone()),
+        ExternalAction::Transcribe { .. } => emit(app, "external:transcribe", &action),
+    }
+}
+
+/// Re-pastes the most recent history entry, off the calling thread (deep
+/// links are delivered on the main thread, and pasting waits for focus).
+fn paste_last(app: AppHandle) {
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = app
+            .state::<HistoryState>()
+            .latest()
+            .and_then(|item| item.ok_or_else(|| "no transcript to paste yet".to_string()))
+            .and_then(|item| {
+                tauri::async_runtime::block_on(manage::paste_item(&app, item.id))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(message) = result {
+            report_error(&app, message);
+        }
+    });
+}
+
+/// Opens `callback` with the most recent transcript, or `error_callback`
+/// with the reason there is none.
+fn send_last(app: AppHandle, callback: Url, error_callback: Option<Url>) {
+    tauri::async_runtime::spawn_blocking(move || {
+        let latest = app
+            .state::<HistoryState>()
+            .latest()
+            .and_then(|item| item.ok_or_else(|| "no transcript yet".to_string()));
+        let target = match latest {
+            Ok(item) => Some(with_query_param(&callback, "text", &item.processed_text)),
+            Err(message) => {
+                let target =
+                    error_callback.map(|url| with_query_param(&url, "errorMessage", &message));
+                report_error(&app, message);
+                target
+            }
+        };
+
+        if let Some(target) = target {
+            if let Err(e) = app.opener().open_url(target.as_str(), None::<&str>) {
+                report_error(&app, format!("failed to open callback: {}", e));
+            }
+        }
+    });
+}
+
+/// Reports a request that couldn't be handled.
+pub fn report_error(app: &AppHandle, message: String) {
+    tracing::warn!("Ignoring external request: {}", message);
+    emit(app, "external:error", ExternalError { message });
+}
+
+/// Emits `event`, or queues it while the frontend isn't listening yet.
+fn emit(app: &AppHandle, event: &'static str, payload: impl Serialize) {
+    let state = app.state::<ExternalState>();
+    let mut queue = state.queue.lock().unwrap();
+    match queue.as_mut() {
+        Some(queue) => queue.push(PendingEvent {
+            event,
+            payload: serde_json::to_value(payload).unwrap_or_default(),
+        }),
+        None => {
+            let _ = app.emit(event, payload);
+        }
+    }
+}
+
+/// Returns the events that arrived before the frontend was listening, in
+/// order; later ones are emitted directly. Called once by the frontend after
+/// it registered its listeners.
+#[tauri::command]
+pub fn take_pending_external_events(state: State<'_, ExternalState>) -> Vec<PendingEvent> {
+    state.queue.lock().unwrap().take().unwrap_or_default()
+}
+
+/// Reads an audio file for transcription once it has been probed (see
+/// [`audio_file::decode`]), sending its audio to `channel` in chunks as it
+/// is decoded and emitting `audio:file-progress` with each. Returns what was
+/// sent once the last chunk is.
+///
+/// # Errors
+///
+/// Returns an [`AudioFileError`] if `path` isn't an audio file that can be
+/// transcribed or can't be read.
+#[tauri::command]
+pub async fn read_audio_file(
+    app: AppHandle,
+    path: PathBuf,
+    channel: Channel<InvokeResponseBody>,
+) -> Result<DecodedAudio, AudioFileError> {
+    let quality = app.state::<SettingsState>().get().capture.dictation;
+    tauri::async_runtime::spawn_blocking(move || {
+        audio_file::decode(&path, &quality, |chunk, progress| {
+            channel
+                .send(InvokeResponseBody::Raw(chunk))
+                .map_err(|e| e.to_string())?;
+            let _ = app.emit("audio:file-progress", progress);
+            Ok(())
+        })
+    })
+    .await
+    .map_err(|e| AudioFileError::Unreadable {
+        message: e.to_string(),
+    })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schemes() -> Vec<String> {
+        AutomationSettings::default().callback_schemes
+    }
+
+    fn parse(url: &str) -> Result<ExternalAction, String> {
+        parse_url(&Url::parse(url).unwrap(), &schemes())
+    }
+
+    /// The `name` parameter of `url`, decoded.
+    fn param(url: &Url, name: &str) -> Option<String> {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    #[test]
+    fn text_is_encoded_as_a_uri_component() {
+        let url = Url::parse("shortcuts://x-callback-url/run-shortcut").unwrap();
+        let url = with_query_param(&url, "text", "a b+c&d=e%f#g/h?i");
+        assert_eq!(url.query(), Some("text=a%20b%2Bc%26d%3De%25f%23g%2Fh%3Fi"));
+        assert_eq!(url.fragment(), None);
+    }
+
+    #[test]
+    fn unicode_and_newlines_are_encoded_as_utf8() {
+        let url = Url::parse("raycast://extensions/x").unwrap();
+        let url = with_query_param(&url, "text", "évé 👋\nok");
+        assert_eq!(url.query(), Some("text=%C3%A9v%C3%A9%20%F0%9F%91%8B%0Aok"));
+    }
+
+    #[test]
+    fn unreserved_characters_stay_as_they_are() {
+        let url = Url::parse("kmtrigger://macro").unwrap();
+        let url = with_query_param(&url, "text", "Az09-._~");
+        assert_eq!(url.query(), Some("text=Az09-._~"));
+    }
+
+    #[test]
+    fn text_is_appended_to_the_callback_query() {
+        let url = Url::parse("shortcuts://x-callback-url/run-shortcut?name=Paste%20It").unwrap();
+        let url = with_query_param(&url, "text", "hello world");
+        assert_eq!(url.query(), Some("name=Paste%20It&text=hello%20world"));
+
+        let empty = Url::parse("shortcuts://run?").unwrap();
+        assert_eq!(
+            with_query_param(&empty, "text", "x").query(),
+            Some("text=x")
+        );
+    }
+
+    #[test]
+    fn encoded_text_decodes_back() {
+        let text = "Dear Sam,\n\n50% off & free + fast? Yes = 👍 #deal";
+        let url = Url::parse("shortcuts://x-callback-url/run-shortcut?name=Reply").unwrap();
+        let url = with_query_param(&url, "text", text);
+        assert_eq!(param(&url, "text").as_deref(), Some(text));
+        assert_eq!(param(&url, "name").as_deref(), Some("Reply"));
+    }
+
+    #[test]
+    fn get_last_decodes_its_callback() {
+        let action = parse(
+            "wispr://get-last?callback=shortcuts%3A%2F%2Fx-callback-url%2Frun-shortcut%3Fname%3DPaste%2520It",
+        )
+        .unwrap();
+        let (callback, error_callback) = match action {
+            ExternalAction::GetLast {
+                callback,
+                error_callback,
+            } => (callback, error_callback),
+            other => panic!("not get-last: {:?}", other),
+        };
+        assert_eq!(callback.scheme(), "shortcuts");
+        assert_eq!(param(&callback, "name").as_deref(), Some("Paste It"));
+        assert_eq!(error_callback, None);
+    }
+
+    #[test]
+    fn get_last_takes_x_callback_parameters() {
+        let action =
+            parse("wispr://get-last?x-success=raycast%3A%2F%2Fok&x-error=raycast%3A%2F%2Ffailed")
+                .unwrap();
+        assert_eq!(
+            action,
+            ExternalAction::GetLast {
+                callback: Url::parse("raycast://ok").unwrap(),
+                error_callback: Some(Url::parse("raycast://failed").unwrap()),
+            }
+        );
+    }
+
+    #[test]
+    fn callbacks_must_use_an_allowed_scheme() {
+        let error = parse("wispr://get-last?callback=https%3A%2F%2Fexample.com").unwrap_err();
+        assert!(error.contains("not allowed"), "{}", error);
+        let error =
+            parse("wispr://get-last?x-success=shortcuts%3A%2F%2Fok&x-error=wispr%3A%2F%2Ftoggle")
+                .unwrap_err();
+        assert!(error.contains("not allowed"), "{}", error);
+        assert!(parse("wispr://get-last?callback=SHORTCUTS%3A%2F%2Fok").is_ok());
+    }
+
+    #[test]
+    fn get_last_needs_a_single_valid_callback() {
+        assert!(parse("wispr://get-last").is_err());
+        assert!(parse("wispr://get-last?callback=not%20a%20url").is_err());
+        assert!(parse(
+            "wispr://get-last?callback=shortcuts%3A%2F%2Fa&callback=shortcuts%3A%2F%2Fb"
+        )
+        .is_err());
+        assert!(parse("wispr://get-last?callback=shortcuts%3A%2F%2Fa&text=x").is_err());
+    }
+
+    #[test]
+    fn actions_parse_in_both_forms() {
+        assert_eq!(parse("wispr://toggle"), Ok(ExternalAction::Toggle));
+        assert_eq!(parse("wispr:paste-last"), Ok(ExternalAction::PasteLast));
+        assert_eq!(
+            parse("wispr://toggle-meeting/"),
+            Ok(ExternalAction::ToggleMeeting)
+        );
+        assert!(parse("wispr://record").is_err());
+        assert!(parse("https://toggle").is_err());
+    }
+
+    #[test]
+    fn callback_schemes_that_loop_back_or_run_locally_are_refused() {
+        for scheme in ["wispr", "file", "JavaScript", "data", "1abc", "a b"] {
+            let settings = AutomationSettings {
+                callback_schemes: vec![scheme.to_string()],
+            };
+            assert!(settings.validate().is_err(), "{}", scheme);
+        }
+        assert!(AutomationSettings::default().validate().is_ok());
+    }
+}
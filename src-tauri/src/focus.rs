@@ -0,0 +1,342 @@
+//! Focus (Do Not Disturb) while recording, so notification sounds stay out
+//! of dictations and meetings.
+//!
+//! With `focus.enabled`, Focus is turned on when a dictation starts
+//! recording or a meeting starts, and off again when the mic closes.
+//! [`sync`] is called wherever the recording indicator's is (see
+//! [`crate::indicator`]) and does the work on a thread of its own, since
+//! the mechanisms run other programs.
+//!
+//! Focus is only turned off if we turned it on: when it was on already, it
+//! is left alone throughout. When the user turns it off during a recording,
+//! it stays off, and at the end nothing is done. The app quitting while it
+//! is ours turns it off ([`restore`]). Failures are logged and never stop a
+//! recording.
+//!
+//! # Platform Support
+//!
+//! macOS only, through a [`FocusMechanism`] that depends on its version:
+//!
+//! - macOS 12 and later have no API for Focus. Shortcuts can set it, so
+//!   the user creates two shortcuts with the "Set Focus" action, named
+//!   `focus.on_shortcut` and `focus.off_shortcut`, which are run with the
+//!   `shortcuts` tool. Whether Focus is on is read from the Do Not Disturb
+//!   database, which only records Focus turned on by hand or by a
+//!   shortcut, not by a schedule.
+//! - macOS 11 and earlier: the legacy Do Not Disturb preference of
+//!   Notification Center, set with `defaults`.
+//!
+//! [`get_focus_support`] says which one is used and whether it can work.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::indicator;
+use crate::settings::SettingsState;
+use crate::AppHandle;
+
+/// Focus settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FocusSettings {
+    /// Turn Focus on while recording.
+    pub enabled: bool,
+    /// Shortcut that turns Focus on, on macOS 12 and later.
+    pub on_shortcut: String,
+    /// Shortcut that turns Focus off, on macOS 12 and later.
+    pub off_shortcut: String,
+}
+
+impl Default for FocusSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_shortcut: "Wispr Focus On".to_string(),
+            off_shortcut: "Wispr Focus Off".to_string(),
+        }
+    }
+}
+
+impl FocusSettings {
+    /// Checks the shortcut names.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.on_shortcut.trim().is_empty() || self.off_shortcut.trim().is_empty() {
+            return Err("on_shortcut and off_shortcut must not be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Which mechanism sets Focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusKind {
+    /// The user's shortcuts, macOS 12 and later.
+    Shortcuts,
+    /// Notification Center's Do Not Disturb, macOS 11 and earlier.
+    LegacyDnd,
+}
+
+/// A way of turning Focus on and off.
+pub trait FocusMechanism {
+    fn kind(&self) -> FocusKind;
+    /// Why it can't be used, if it can't.
+    fn check(&self) -> Result<(), String>;
+    /// Whether Focus is on; `None` when that can't be told.
+    fn is_on(&self) -> Option<bool>;
+    fn turn_on(&self) -> Result<(), String>;
+    fn turn_off(&self) -> Result<(), String>;
+}
+
+/// Runs the user's shortcuts.
+pub struct Shortcuts {
+    pub on: String,
+    pub off: String,
+}
+
+impl Shortcuts {
+    fn run(name: &str) -> Result<(), String> {
+        run("shortcuts", &["run", name]).map(|_| ())
+    }
+}
+
+impl FocusMechanism for Shortcuts {
+    fn kind(&self) -> FocusKind {
+        FocusKind::Shortcuts
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let listed = run("shortcuts", &["list"])?;
+        for name in [&self.on, &self.off] {
+            if !listed.lines().any(|line| line.trim() == name.as_str()) {
+                return Err(format!("there is no shortcut named \"{}\"", name));
+            }
+        }
+        Ok(())
+    }
+
+    fn is_on(&self) -> Option<bool> {
+        let path = PathBuf::from(std::env::var_os("HOME")?)
+            .join("Library/DoNotDisturb/DB/Assertions.json");
+        let assertions: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+        let records = assertions
+            .get("data")?
+            .get(0)?
+            .get("storeAssertionRecords")
+            .and_then(|records| records.as_array())
+            .map_or(0, Vec::len);
+        Some(records > 0)
+    }
+
+    fn turn_on(&self) -> Result<(), String> {
+        Self::run(&self.on)
+    }
+
+    fn turn_off(&self) -> Result<(), String> {
+        Self::run(&self.off)
+    }
+}
+
+/// Sets Notification Center's Do Not Disturb preference.
+pub struct LegacyDnd;
+
+impl LegacyDnd {
+    /// The preference's domain and key.
+    const DOMAIN: &'static str = "com.apple.notificationcenterui";
+    const KEY: &'static str = "doNotDisturb";
+
+    fn set(on: bool) -> Result<(), String> {
+        let value = if on { "true" } else { "false" };
+        let args = [
+            "-currentHost",
+            "write",
+            Self::DOMAIN,
+            Self::KEY,
+            "-boolean",
+            value,
+        ];
+        run("defaults", &args)?;
+        // Notification Center only reads it when it starts
+        run("killall", &["NotificationCenter"]).map(|_| ())
+    }
+}
+
+impl FocusMechanism for LegacyDnd {
+    fn kind(&self) -> FocusKind {
+        FocusKind::LegacyDnd
+    }
+
+    fn check(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn is_on(&self) -> Option<bool> {
+        match run(
+            "defaults",
+            &["-currentHost", "read", Self::DOMAIN, Self::KEY],
+        ) {
+            Ok(value) => Some(value.trim() == "1"),
+            // Never set
+            Err(_) => Some(false),
+        }
+    }
+
+    fn turn_on(&self) -> Result<(), String> {
+        Self::set(true)
+    }
+
+    fn turn_off(&self) -> Result<(), String> {
+        Self::set(false)
+    }
+}
+
+/// The mechanism for this system, if it has one.
+pub fn mechanism(settings: &FocusSettings) -> Option<Box<dyn FocusMechanism>> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+    let version = run("sw_vers", &["-productVersion"]).ok()?;
+    let major: u32 = version.trim().split('.').next()?.parse().ok()?;
+    if major >= 12 {
+        Some(Box::new(Shortcuts {
+            on: settings.on_shortcut.clone(),
+            off: settings.off_shortcut.clone(),
+        }))
+    } else {
+        Some(Box::new(LegacyDnd))
+    }
+}
+
+/// Managed state: whether Focus is on because we turned it on. Locked for
+/// as long as Focus is being changed, so changes never overlap.
+#[derive(Default)]
+pub struct FocusState(Mutex<bool>);
+
+/// Turns Focus on or off to match the recording state and the settings,
+/// in the background. Cheap, and safe to call from any thread.
+pub fn sync(app: &AppHandle) {
+    let handle = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("focus".to_string())
+        .spawn(move || apply(&handle));
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to change Focus: {}", e);
+    }
+}
+
+/// Turns Focus on if it is wanted and off, or off if we turned it on and
+/// it is no longer wanted.
+fn apply(app: &AppHandle) {
+    let state = app.state::<FocusState>();
+    let mut ours = state.0.lock().unwrap();
+    let settings = app.state::<SettingsState>().get().focus;
+    // Looked at now, not when asked, so the latest state wins
+    let wanted = settings.enabled && indicator::recording(app);
+    if wanted == *ours {
+        return;
+    }
+    let Some(mechanism) = mechanism(&settings) else {
+        return;
+    };
+    if wanted {
+        if mechanism.is_on() == Some(true) {
+            tracing::debug!("Focus is on already; leaving it alone");
+            return;
+        }
+        match mechanism.turn_on() {
+            Ok(()) => {
+                tracing::info!(kind = ?mechanism.kind(), "Turned Focus on for the recording");
+                *ours = true;
+            }
+            Err(e) => tracing::warn!("Failed to turn Focus on: {}", e),
+        }
+    } else {
+        *ours = false;
+        turn_off(mechanism.as_ref());
+    }
+}
+
+/// Turns Focus off, unless the user did already.
+fn turn_off(mechanism: &dyn FocusMechanism) {
+    if mechanism.is_on() == Some(false) {
+        tracing::info!("Focus was turned off during the recording; leaving it off");
+        return;
+    }
+    match mechanism.turn_off() {
+        Ok(()) => tracing::info!("Turned Focus off after the recording"),
+        Err(e) => tracing::warn!("Failed to turn Focus off: {}", e),
+    }
+}
+
+/// Turns Focus off if we turned it on. Called when the app exits.
+pub fn restore(app: &AppHandle) {
+    let state = app.state::<FocusState>();
+    let mut ours = state.0.lock().unwrap();
+    if !*ours {
+        return;
+    }
+    *ours = false;
+    let settings = app.state::<SettingsState>().get().focus;
+    if let Some(mechanism) = mechanism(&settings) {
+        turn_off(mechanism.as_ref());
+    }
+}
+
+/// What [`get_focus_support`] returns.
+#[derive(Debug, Clone, Serialize)]
+pub struct FocusSupport {
+    /// `None` where Focus can't be changed.
+    pub kind: Option<FocusKind>,
+    /// Whether it can work as configured.
+    pub available: bool,
+    /// Why not, for the user.
+    pub detail: Option<String>,
+}
+
+/// Reports how Focus is set on this system, and whether it can be.
+#[tauri::command]
+pub async fn get_focus_support(app: AppHandle) -> FocusSupport {
+    let settings = app.state::<SettingsState>().get().focus;
+    tauri::async_runtime::spawn_blocking(move || match mechanism(&settings) {
+        None => FocusSupport {
+            kind: None,
+            available: false,
+            detail: Some("Focus can only be turned on on macOS".to_string()),
+        },
+        Some(mechanism) => {
+            let checked = mechanism.check();
+            FocusSupport {
+                kind: Some(mechanism.kind()),
+                available: checked.is_ok(),
+                detail: checked.err(),
+            }
+        }
+    })
+    .await
+    .unwrap_or(FocusSupport {
+        kind: None,
+        available: false,
+        detail: Some("Focus support couldn't be checked".to_string()),
+    })
+}
+
+/// Runs `program` with `args`, returning its output.
+fn run(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("{}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{}: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,174 @@
+//! Frontmost application detection.
+//!
+//! Several behaviors depend on which application will receive the paste
+//! (caret positioning, per-app quirks). This module answers "who has focus
+//! right now?" in terms of a stable identifier.
+//!
+//! # Platform Support
+//!
+//! macOS returns the bundle identifier (e.g. `com.apple.Terminal`) via System
+//! Events, and can bring an application back to the front by it. Linux
+//! returns the focused window's class instead (e.g. `kitty` or
+//! `org.gnome.Terminal`): the `app_id` from the compositor on Sway
+//! (`swaymsg`) and Hyprland (`hyprctl`), otherwise the X11 class from
+//! `xdotool`, which on other Wayland compositors only sees XWayland windows.
+//! Linux can't activate anything, and Windows returns `None`.
+
+#[cfg(any(target_os = "linux", test))]
+use serde_json::Value;
+
+/// Returns the bundle identifier of the frontmost application, if it can be
+/// determined.
+///
+/// This is best-effort: failures (no Accessibility permission, `osascript`
+/// missing) are reported as `None` rather than an error because callers only
+/// use it to tune behavior, never to decide whether to paste at all.
+pub fn frontmost_bundle_id() -> Option<String> {
+    #[cfg(target_os = "macos")]
+    {
+        use std::process::Command;
+
+        let output = Command::new("osascript")
+            .arg("-e")
+            .arg(
+                r#"tell application "System Events" to get bundle identifier of first application process whose frontmost is true"#,
+            )
+            .output()
+            .ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+
+        let bundle_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if bundle_id.is_empty() || bundle_id == "missing value" {
+            return None;
+        }
+
+        Some(bundle_id)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        focused_window_class()
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    {
+        None
+    }
+}
+
+/// The class of the focused window on Linux (see the module docs).
+#[cfg(target_os = "linux")]
+fn focused_window_class() -> Option<String> {
+    use std::process::Command;
+
+    let run = |program: &str, args: &[&str]| -> Option<String> {
+        let output = Command::new(program).args(args).output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    if std::env::var_os("SWAYSOCK").is_some() {
+        let tree = run("swaymsg", &["-t", "get_tree"])?;
+        return sway_focused_class(&serde_json::from_str(&tree).ok()?);
+    }
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        let window = run("hyprctl", &["activewindow", "-j"])?;
+        let window: Value = serde_json::from_str(&window).ok()?;
+        return non_empty(window["class"].as_str()?);
+    }
+    non_empty(&run("xdotool", &["getactivewindow", "getwindowclassname"])?)
+}
+
+/// The `app_id` of the focused window in the Sway tree `node`, or its X11
+/// class for an XWayland one.
+#[cfg(any(target_os = "linux", test))]
+fn sway_focused_class(node: &Value) -> Option<String> {
+    if node["focused"] == true {
+        let class = node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str());
+        return class.and_then(non_empty);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|children| node[children].as_array())
+        .flatten()
+        .find_map(sway_focused_class)
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn non_empty(class: &str) -> Option<String> {
+    let class = class.trim();
+    (!class.is_empty()).then(|| class.to_string())
+}
+
+/// Brings the application with `bundle_id` to the front, returning whether
+/// it worked.
+///
+/// Best-effort like [`frontmost_bundle_id`]: used to hand focus back to the
+/// app a window was opened from.
+pub fn activate(bundle_id: &str) -> bool {
+    #[cfg(target_os = "macos")]
+    {
+        use std::process::Command;
+
+        // The id is interpolated into AppleScript; real bundle ids never
+        // contain quotes or backslashes
+        if bundle_id.contains(['"', '\\']) {
+            return false;
+        }
+
+        Command::new("osascript")
+            .arg("-e")
+            .arg(format!(
+                r#"tell application id "{}" to activate"#,
+                bundle_id
+            ))
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = bundle_id;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn window(app_id: Value, focused: bool) -> Value {
+        json!({ "app_id": app_id, "focused": focused, "nodes": [] })
+    }
+
+    #[test]
+    fn the_focused_sway_window_is_found_at_any_depth() {
+        let tree = json!({
+            "focused": false,
+            "nodes": [{
+                "focused": false,
+                "nodes": [window(json!("firefox"), false)],
+                "floating_nodes": [window(json!("kitty"), true)],
+            }],
+        });
+        assert_eq!(sway_focused_class(&tree).as_deref(), Some("kitty"));
+
+        // An XWayland window has a class instead
+        let mut xwayland = window(Value::Null, true);
+        xwayland["window_properties"] = json!({ "class": "XTerm" });
+        let tree = json!({ "focused": false, "nodes": [xwayland] });
+        assert_eq!(sway_focused_class(&tree).as_deref(), Some("XTerm"));
+
+        // A focused workspace has no window
+        let tree = json!({ "focused": true, "nodes": [] });
+        assert_eq!(sway_focused_class(&tree), None);
+    }
+}
@@ -0,0 +1,288 @@
+//! The app on Tauri's mock runtime, for tests that drive whole dictations
+//! through the real command handlers.
+//!
+//! [`Harness::new`] builds the app with the state and commands [`run`]
+//! registers (see `with_commands`), its data dir in a directory of its own,
+//! with an unencrypted history database. Dictations are transcribed with the mock provider
+//! and pasted with a [`MockPasteBackend`] (see [`PasteBackendState`]), so
+//! they need no microphone, API key or GUI session. Commands are invoked
+//! through the IPC, as the webview does, from a webview of the harness's
+//! own, so the app's windows are only the ones it creates.
+//!
+//! The plugins aren't registered, so notifications are turned off, and
+//! nothing [`settings::apply`] starts (the local API, the hotkeys) runs.
+//!
+//! [`run`]: crate::run
+//! [`settings::apply`]: crate::settings::apply
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use tauri::test::{mock_builder, mock_context, noop_assets, INVOKE_KEY};
+use tauri::webview::InvokeRequest;
+use tauri::{App, Listener, Manager, WebviewUrl, WebviewWindowBuilder};
+
+use crate::benchmark::BenchmarkState;
+use crate::history::summary::SummaryState;
+use crate::history::HistoryState;
+use crate::meeting::MeetingState;
+use crate::paste::mock::MockPasteBackend;
+use crate::paste::PasteBackendState;
+use crate::recovery::RecoveryState;
+use crate::settings::migrate::SCHEMA_VERSION;
+use crate::settings::SettingsState;
+use crate::webhook::WebhookState;
+use crate::window_state::WindowStore;
+use crate::{pill, privacy, AppHandle, Runtime, WebviewWindow};
+
+/// Label of the webview commands are invoked from.
+const IPC_WEBVIEW: &str = "harness";
+
+/// Events recorded for [`Harness::events`].
+const EVENTS: &[&str] = &[
+    "paste:failed",
+    "transcription:failed",
+    "history:changed",
+    "recording:stopped",
+    "recording:interrupted",
+    "power:woke",
+    "audio:device-changed",
+    "audio:capture-format",
+    "command:ran",
+    "transcription:cancelled",
+];
+
+/// How long [`wait_for`] waits.
+const WAIT: Duration = Duration::from_secs(5);
+
+/// Held by every harness: the storage policy is global (see
+/// [`crate::privacy`]), so two apps can't run at once.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// A running app, removing its data dir when dropped.
+pub struct Harness {
+    app: App<Runtime>,
+    pub backend: Arc<MockPasteBackend>,
+    webview: WebviewWindow,
+    events: Arc<Mutex<Vec<(String, Value)>>>,
+    dir: PathBuf,
+    _serial: MutexGuard<'static, ()>,
+}
+
+impl Harness {
+    /// The app for `test`, with its pill window created as at startup.
+    pub fn new(test: &str) -> Self {
+        Self::start(test, json!({}), true)
+    }
+
+    /// Like [`new`](Self::new), with `settings` over the harness's own.
+    pub fn with_settings(test: &str, settings: Value) -> Self {
+        Self::start(test, settings, true)
+    }
+
+    /// Like [`new`](Self::new), before the pill window was created.
+    pub fn without_pill_window(test: &str) -> Self {
+        Self::start(test, json!({}), false)
+    }
+
+    fn start(test: &str, overrides: Value, pill_window: bool) -> Self {
+        let serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir =
+            std::env::temp_dir().join(format!("wispr-harness-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut settings = json!({
+            "schema_version": SCHEMA_VERSION,
+            "transcription": {
+                "provider": "mock",
+                "mock": { "enabled": true, "delay_ms": 0 },
+            },
+            "notifications": { "enabled": false },
+            // The ticks run on the clock; a slow test mustn't be cancelled
+            "recording": { "no_speech_timeout_ms": 0 },
+        });
+        merge(&mut settings, overrides);
+        fs::write(dir.join("settings.json"), settings.to_string()).unwrap();
+
+        let backend = Arc::new(MockPasteBackend::default());
+        let app = crate::with_commands(mock_builder())
+            .manage(PasteBackendState::new(backend.clone()))
+            .build(mock_context(noop_assets()))
+            .unwrap();
+
+        // What setup manages from the data dir
+        let settings = SettingsState::load(&dir);
+        assert_eq!(settings.meta().warnings, Vec::<String>::new());
+        let current = settings.get();
+        app.manage(settings);
+        app.manage(WindowStore::open(&dir));
+        app.manage(HistoryState::open_plaintext(&dir));
+        app.manage(SummaryState::new(&dir));
+        app.manage(BenchmarkState::new(&dir));
+        app.manage(WebhookState::new(&dir));
+        app.manage(MeetingState::new(&dir));
+        app.manage(RecoveryState::open(&dir, &current.recovery));
+        privacy::apply(&current);
+        if pill_window {
+            pill::ensure_main_window(app.handle()).unwrap();
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        for &name in EVENTS {
+            let events = events.clone();
+            app.listen_any(name, move |event| {
+                let payload = serde_json::from_str(event.payload()).unwrap();
+                events.lock().unwrap().push((name.to_string(), payload));
+            });
+        }
+        let webview = WebviewWindowBuilder::new(&app, IPC_WEBVIEW, WebviewUrl::default())
+            .visible(false)
+            .build()
+            .unwrap();
+
+        Self {
+            app,
+            backend,
+            webview,
+            events,
+            dir,
+            _serial: serial,
+        }
+    }
+
+    pub fn handle(&self) -> &AppHandle {
+        self.app.handle()
+    }
+
+    /// The app's data dir.
+    pub fn data_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Invokes the command `cmd` with `args`, as the webview does: the
+    /// arguments are camelCase. Returns what it returned, or its error.
+    pub fn invoke(&self, cmd: &str, args: Value) -> Result<Value, Value> {
+        invoke(&self.webview, cmd, args)
+    }
+
+    /// Presses the dictation hotkey, and returns the session id of the
+    /// dictation it started.
+    pub fn press_hotkey(&self) -> String {
+        let outcome = self.invoke("press_dictation_hotkey", json!({})).unwrap();
+        assert_eq!(outcome["action"], "started", "{}", outcome);
+        outcome["session_id"].as_str().unwrap().to_string()
+    }
+
+    /// Plays the webview's part once the user stops the dictation
+    /// `session_id`: stops the recording, transcribes it with the mock
+    /// provider as `get_transcription_config` describes it, and processes
+    /// the transcript. Returns the text to paste, or `None` when the
+    /// provider failed, which is reported as the webview does.
+    pub fn transcribe(&self, session_id: &str) -> Option<String> {
+        self.invoke("stop_recording", json!({ "sessionId": session_id }))
+            .unwrap();
+        let config = self.invoke("get_transcription_config", json!({})).unwrap();
+        assert_eq!(config["provider"], "mock");
+        if config["mock"]["fail"] == true {
+            let failure = json!({ "message": "Mock provider error", "sessionId": session_id });
+            self.invoke("report_recording_error", failure).unwrap();
+            return None;
+        }
+        let processed = self
+            .invoke(
+                "process_transcript",
+                json!({
+                    "text": config["mock"]["text"],
+                    "provider": config["provider"],
+                    "language": config["language"],
+                    "sessionId": session_id,
+                }),
+            )
+            .unwrap();
+        Some(processed["text"].as_str().unwrap().to_string())
+    }
+
+    /// Pastes `text` as the dictation `session_id`.
+    pub fn paste(&self, session_id: &str, text: &str) -> Result<Value, Value> {
+        self.invoke(
+            "copy_and_paste_text",
+            json!({ "text": text, "sessionId": session_id }),
+        )
+    }
+
+    /// Pastes `text` as the dictation `session_id` on a thread of its own,
+    /// for a paste that waits (its turn, a confirmation) while the test
+    /// goes on.
+    pub fn spawn_paste(&self, session_id: &str, text: &str) -> JoinHandle<Result<Value, Value>> {
+        let webview = self.webview.clone();
+        let args = json!({ "text": text, "sessionId": session_id });
+        std::thread::spawn(move || invoke(&webview, "copy_and_paste_text", args))
+    }
+
+    /// Every entry in history, newest first.
+    pub fn history(&self) -> Vec<Value> {
+        match self.invoke("list_history", json!({})).unwrap() {
+            Value::Array(items) => items,
+            other => panic!("list_history returned {}", other),
+        }
+    }
+
+    /// The payloads of the event `name` emitted so far, in order.
+    pub fn events(&self, name: &str) -> Vec<Value> {
+        assert!(EVENTS.contains(&name), "{} isn't recorded", name);
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .filter(|(event, _)| event == name)
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Invokes the command `cmd` with `args` from `webview`; see
+/// [`Harness::invoke`].
+fn invoke(webview: &WebviewWindow, cmd: &str, args: Value) -> Result<Value, Value> {
+    let request = InvokeRequest {
+        cmd: cmd.to_string(),
+        callback: tauri::ipc::CallbackFn(0),
+        error: tauri::ipc::CallbackFn(1),
+        url: "tauri://localhost".parse().unwrap(),
+        body: tauri::ipc::InvokeBody::Json(args),
+        headers: Default::default(),
+        invoke_key: INVOKE_KEY.to_string(),
+    };
+    tauri::test::get_ipc_response(webview, request).map(|body| body.deserialize::<Value>().unwrap())
+}
+
+/// Waits until `done`, for work the app does in the background, failing
+/// the test once [`WAIT`] is up.
+pub fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + WAIT;
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Merges the objects in `over` into those in `base`; anything else in
+/// `over` replaces what `base` has.
+fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Object(base), Value::Object(over)) => {
+            for (key, value) in over {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
@@ -0,0 +1,875 @@
+//! "Is everything working?" for the settings window.
+//!
+//! [`health_check`] reports one [`HealthItem`] per subsystem: the dictation
+//! hotkey, the microphone, permissions, the transcription provider and its
+//! model, the history database, the tray icon, the background threads,
+//! updates and system audio capture for meetings.
+//! Each has a status, a `code` for the UI to act on and a `detail` for the
+//! user to read.
+//!
+//! Checks only look at state the app already has, so the command answers
+//! right away. The one check that needs the network, whether the provider
+//! can be reached, is a TCP connection made on a background thread; its
+//! result is cached for [`REACHABILITY_TTL`] and the first check after that
+//! reports the previous result (or `provider_unchecked`) while a new one
+//! runs.
+//!
+//! The microphone is the webview's, so the frontend passes what it knows
+//! about it (see [`MicrophoneReport`]).
+//!
+//! Every check is a plain function of what it looks at, so each can be
+//! exercised on its own.
+
+use std::collections::BTreeMap;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use tauri_plugin_global_shortcut::GlobalShortcutExt;
+
+use crate::crash::{self, Degraded};
+use crate::history::HistoryState;
+use crate::loopback;
+use crate::settings::{SettingsState, KNOWN_PROVIDERS};
+use crate::transcription::{self, MOCK_PROVIDER};
+use crate::tray;
+use crate::updater::{self, LastCheck, UpdateError};
+use crate::AppHandle;
+
+/// How long a reachability result is reused.
+pub const REACHABILITY_TTL: Duration = Duration::from_secs(60);
+
+/// How long connecting to a provider may take before it counts as
+/// unreachable.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How healthy a subsystem is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Ok,
+    /// Working, but something needs attention.
+    Warn,
+    /// Not working.
+    Error,
+}
+
+/// How one subsystem is doing.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthItem {
+    pub subsystem: &'static str,
+    pub status: HealthStatus,
+    /// Machine-readable reason, e.g. `hotkey_not_registered`.
+    pub code: &'static str,
+    /// For the user to read.
+    pub detail: String,
+}
+
+impl HealthItem {
+    fn new(
+        subsystem: &'static str,
+        status: HealthStatus,
+        code: &'static str,
+        detail: impl Into<String>,
+    ) -> Self {
+        Self {
+            subsystem,
+            status,
+            code,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// The result of [`health_check`].
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    /// The worst status of the items.
+    pub status: HealthStatus,
+    pub items: Vec<HealthItem>,
+}
+
+impl HealthReport {
+    fn new(items: Vec<HealthItem>) -> Self {
+        let status = items
+            .iter()
+            .map(|item| item.status)
+            .max()
+            .unwrap_or(HealthStatus::Ok);
+        Self { status, items }
+    }
+}
+
+/// What the frontend knows about the microphone.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MicrophoneReport {
+    /// Label of the default input device; `None` if there is none.
+    pub device: Option<String>,
+    /// The microphone permission: `granted`, `denied` or `prompt`.
+    pub permission: Option<String>,
+}
+
+/// The outcome of connecting to a provider.
+#[derive(Debug, Clone)]
+pub struct Reachability {
+    pub checked: Instant,
+    pub result: Result<(), String>,
+}
+
+/// Cached provider reachability, managed for the life of the app.
+#[derive(Default)]
+pub struct HealthState {
+    reachability: Mutex<BTreeMap<String, Reachability>>,
+    /// Whether a connection attempt is running.
+    probing: AtomicBool,
+}
+
+/// Whether the dictation hotkey `accelerator` is registered.
+pub fn hotkey(accelerator: &str, registered: bool) -> HealthItem {
+    if registered {
+        HealthItem::new(
+            "hotkey",
+            HealthStatus::Ok,
+            "hotkey_registered",
+            format!("{} starts a dictation", accelerator),
+        )
+    } else {
+        HealthItem::new(
+            "hotkey",
+            HealthStatus::Error,
+            "hotkey_not_registered",
+            format!(
+                "{} is not registered; another app may be using it",
+                accelerator
+            ),
+        )
+    }
+}
+
+/// Whether there is a microphone to record from, per the frontend's report.
+pub fn microphone(report: Option<&MicrophoneReport>) -> HealthItem {
+    let Some(report) = report else {
+        return HealthItem::new(
+            "microphone",
+            HealthStatus::Warn,
+            "microphone_unknown",
+            "The microphone wasn't checked",
+        );
+    };
+    match (report.permission.as_deref(), report.device.as_deref()) {
+        (Some("denied"), _) => HealthItem::new(
+            "microphone",
+            HealthStatus::Error,
+            "microphone_denied",
+            "Microphone access is denied",
+        ),
+        (_, None) => HealthItem::new(
+            "microphone",
+            HealthStatus::Error,
+            "microphone_missing",
+            "No microphone found",
+        ),
+        (Some("granted"), Some(device)) => HealthItem::new(
+            "microphone",
+            HealthStatus::Ok,
+            "microphone_available",
+            device,
+        ),
+        (_, Some(device)) => HealthItem::new(
+            "microphone",
+            HealthStatus::Warn,
+            "microphone_permission_pending",
+            format!(
+                "{}; access will be asked for on the first dictation",
+                device
+            ),
+        ),
+    }
+}
+
+/// Whether pasting can send keystrokes: `trusted` is `None` where no
+/// permission is needed.
+pub fn accessibility(trusted: Option<bool>) -> HealthItem {
+    match trusted {
+        None => HealthItem::new(
+            "accessibility",
+            HealthStatus::Ok,
+            "accessibility_not_required",
+            "No permission needed to paste",
+        ),
+        Some(true) => HealthItem::new(
+            "accessibility",
+            HealthStatus::Ok,
+            "accessibility_granted",
+            "Accessibility access is granted",
+        ),
+        Some(false) => HealthItem::new(
+            "accessibility",
+            HealthStatus::Error,
+            "accessibility_denied",
+            "Grant accessibility access in System Settings to paste",
+        ),
+    }
+}
+
+/// Whether `provider` can be reached, from the last connection attempt.
+pub fn provider(provider: &str, reachability: Option<&Reachability>) -> HealthItem {
+    if provider == MOCK_PROVIDER {
+        return HealthItem::new(
+            "provider",
+            HealthStatus::Warn,
+            "provider_mock",
+            "Dictations get the mock provider's canned text",
+        );
+    }
+    if !KNOWN_PROVIDERS.contains(&provider) {
+        return HealthItem::new(
+            "provider",
+            HealthStatus::Error,
+            "provider_unknown",
+            format!("Unknown transcription provider {}", provider),
+        );
+    }
+    match reachability.map(|reachability| &reachability.result) {
+        None => HealthItem::new(
+            "provider",
+            HealthStatus::Warn,
+            "provider_unchecked",
+            format!("Checking whether {} can be reached", provider),
+        ),
+        Some(Ok(())) => HealthItem::new(
+            "provider",
+            HealthStatus::Ok,
+            "provider_reachable",
+            format!("{} can be reached", provider),
+        ),
+        Some(Err(e)) => HealthItem::new(
+            "provider",
+            HealthStatus::Error,
+            "provider_unreachable",
+            format!("{} can't be reached: {}", provider, e),
+        ),
+    }
+}
+
+/// Whether `provider` has its model. Every provider so far is a cloud
+/// service (or the mock one), with nothing to install.
+pub fn model(provider: &str) -> HealthItem {
+    if provider == MOCK_PROVIDER {
+        HealthItem::new("model", HealthStatus::Ok, "model_mock", "No model needed")
+    } else if KNOWN_PROVIDERS.contains(&provider) {
+        HealthItem::new(
+            "model",
+            HealthStatus::Ok,
+            "model_cloud",
+            format!("{} transcribes in the cloud; no model to install", provider),
+        )
+    } else {
+        HealthItem::new(
+            "model",
+            HealthStatus::Error,
+            "model_unknown",
+            format!("No model for the unknown provider {}", provider),
+        )
+    }
+}
+
+/// Whether the history database is open.
+pub fn history(availability: Result<(), String>) -> HealthItem {
+    match availability {
+        Ok(()) => HealthItem::new(
+            "history",
+            HealthStatus::Ok,
+            "history_open",
+            "History is saved",
+        ),
+        Err(e) => HealthItem::new(
+            "history",
+            HealthStatus::Error,
+            "history_unavailable",
+            format!("History can't be opened: {}", e),
+        ),
+    }
+}
+
+/// When updates were last looked for, and what was found.
+pub fn updates(last_check: Option<&LastCheck>) -> HealthItem {
+    let Some(last_check) = last_check else {
+        return HealthItem::new(
+            "updates",
+            HealthStatus::Ok,
+            "updates_unchecked",
+            "Updates haven't been checked for yet",
+        );
+    };
+    let checked = chrono::DateTime::from_timestamp_millis(last_check.at_ms)
+        .map(|at| {
+            at.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default();
+    match (&last_check.error, &last_check.available_version) {
+        (Some(UpdateError::NotConfigured), _) => HealthItem::new(
+            "updates",
+            HealthStatus::Ok,
+            "updates_not_configured",
+            "This build doesn't update itself",
+        ),
+        (Some(e), _) => HealthItem::new(
+            "updates",
+            HealthStatus::Warn,
+            "update_check_failed",
+            format!("Checking for updates failed at {}: {}", checked, e),
+        ),
+        (None, Some(version)) => HealthItem::new(
+            "updates",
+            HealthStatus::Warn,
+            "update_available",
+            format!("Version {} is available (checked {})", version, checked),
+        ),
+        (None, None) => match &last_check.behind_version {
+            Some(version) => HealthItem::new(
+                "updates",
+                HealthStatus::Ok,
+                "updates_channel_behind",
+                format!(
+                    "This build is newer than the channel's {}; staying on it (checked {})",
+                    version, checked
+                ),
+            ),
+            None => HealthItem::new(
+                "updates",
+                HealthStatus::Ok,
+                "updates_current",
+                format!("Up to date (checked {})", checked),
+            ),
+        },
+    }
+}
+
+/// Whether the tray icon exists.
+pub fn tray(present: bool) -> HealthItem {
+    if present {
+        HealthItem::new(
+            "tray",
+            HealthStatus::Ok,
+            "tray_present",
+            "The tray icon is shown",
+        )
+    } else {
+        HealthItem::new(
+            "tray",
+            HealthStatus::Warn,
+            "tray_missing",
+            "The tray icon couldn't be created",
+        )
+    }
+}
+
+/// Whether meetings can record system audio (see [`crate::loopback`]):
+/// `support` is whether it can be captured here, `selected` whether
+/// meetings are set to, and `permission` whether the permission it needs
+/// is granted, `None` where there is none.
+pub fn system_audio(
+    support: Result<(), String>,
+    selected: bool,
+    permission: Option<bool>,
+) -> HealthItem {
+    match (support, permission) {
+        (Err(e), _) => HealthItem::new(
+            "system_audio",
+            if selected {
+                HealthStatus::Warn
+            } else {
+                HealthStatus::Ok
+            },
+            "system_audio_unsupported",
+            format!("Meetings can't record system audio: {}", e),
+        ),
+        _ if !selected => HealthItem::new(
+            "system_audio",
+            HealthStatus::Ok,
+            "system_audio_off",
+            "Meetings record the microphone",
+        ),
+        (Ok(()), Some(false)) => HealthItem::new(
+            "system_audio",
+            HealthStatus::Warn,
+            "system_audio_permission",
+            "Grant Screen Recording access in System Settings to record system audio",
+        ),
+        (Ok(()), _) => HealthItem::new(
+            "system_audio",
+            HealthStatus::Ok,
+            "system_audio_ready",
+            "Meetings record system audio",
+        ),
+    }
+}
+
+/// Whether the supervised background threads are running.
+pub fn background(degraded: &BTreeMap<&'static str, Degraded>) -> HealthItem {
+    let stopped: Vec<_> = degraded
+        .iter()
+        .filter(|(_, degraded)| degraded.stopped)
+        .map(|(name, _)| *name)
+        .collect();
+    if !stopped.is_empty() {
+        return HealthItem::new(
+            "background",
+            HealthStatus::Error,
+            "background_stopped",
+            format!("Stopped after repeated crashes: {}", stopped.join(", ")),
+        );
+    }
+    if !degraded.is_empty() {
+        let restarted: Vec<_> = degraded.keys().copied().collect();
+        return HealthItem::new(
+            "background",
+            HealthStatus::Warn,
+            "background_restarted",
+            format!("Restarted after a crash: {}", restarted.join(", ")),
+        );
+    }
+    HealthItem::new(
+        "background",
+        HealthStatus::Ok,
+        "background_running",
+        "Background tasks are running",
+    )
+}
+
+/// Host the provider's API is reached at.
+fn provider_address(provider: &str) -> Option<&'static str> {
+    match provider {
+        "deepgram" => Some("api.deepgram.com:443"),
+        _ => None,
+    }
+}
+
+/// The cached reachability of `provider`, starting a new connection attempt
+/// in the background when it is missing or older than
+/// [`REACHABILITY_TTL`].
+fn reachability(app: &AppHandle, provider: &str) -> Option<Reachability> {
+    let state = app.state::<HealthState>();
+    let cached = state.reachability.lock().unwrap().get(provider).cloned();
+    let fresh = cached
+        .as_ref()
+        .is_some_and(|cached| cached.checked.elapsed() < REACHABILITY_TTL);
+    if !fresh && !state.probing.swap(true, Ordering::SeqCst) {
+        if let Some(address) = provider_address(provider) {
+            let app = app.clone();
+            let provider = provider.to_string();
+            let spawned = std::thread::Builder::new()
+                .name("health-probe".to_string())
+                .spawn(move || {
+                    let result = connect(address);
+                    if let Err(e) = &result {
+                        tracing::warn!(provider, error = %e, "Provider unreachable");
+                    }
+                    let state = app.state::<HealthState>();
+                    state.reachability.lock().unwrap().insert(
+                        provider,
+                        Reachability {
+                            checked: Instant::now(),
+                            result,
+                        },
+                    );
+                    state.probing.store(false, Ordering::SeqCst);
+                });
+            if spawned.is_err() {
+                state.probing.store(false, Ordering::SeqCst);
+            }
+        } else {
+            state.probing.store(false, Ordering::SeqCst);
+        }
+    }
+    cached
+}
+
+/// Opens (and drops) a TCP connection to `address`.
+fn connect(address: &str) -> Result<(), String> {
+    let addresses = address.to_socket_addrs().map_err(|e| e.to_string())?;
+    let mut last_error = format!("{} has no address", address);
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(_) => return Ok(()),
+            Err(e) => last_error = e.to_string(),
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(target_os = "macos")]
+fn accessibility_trusted() -> Option<bool> {
+    Some(crate::accessibility::is_trusted())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn accessibility_trusted() -> Option<bool> {
+    None
+}
+
+/// Reports how each subsystem is doing, from cached state: it never waits
+/// on the network or the database.
+///
+/// `microphone` is the frontend's view of the microphone; without it the
+/// microphone is reported unchecked.
+#[tauri::command]
+pub fn health_check(app: AppHandle, microphone: Option<MicrophoneReport>) -> HealthReport {
+    let settings = app.state::<SettingsState>().get();
+    let accelerator = settings.hotkeys.dictation.as_str();
+    let provider_name = transcription::active_provider(&app);
+    let provider_name = provider_name.as_str();
+    let reachability = reachability(&app, provider_name);
+
+    HealthReport::new(vec![
+        hotkey(
+            accelerator,
+            app.global_shortcut().is_registered(accelerator),
+        ),
+        self::microphone(microphone.as_ref()),
+        accessibility(accessibility_trusted()),
+        provider(provider_name, reachability.as_ref()),
+        model(provider_name),
+        history(app.state::<HistoryState>().availability()),
+        tray(tray::is_present(&app)),
+        background(&crash::degraded(&app)),
+        updates(updater::last_check(&app).as_ref()),
+        system_audio(
+            loopback::support(),
+            settings.meeting.input.records_system_audio(),
+            loopback::permission_granted(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn mic(device: Option<&str>, permission: Option<&str>) -> MicrophoneReport {
+        MicrophoneReport {
+            device: device.map(str::to_string),
+            permission: permission.map(str::to_string),
+        }
+    }
+
+    fn checked(result: Result<(), String>) -> Reachability {
+        Reachability {
+            checked: Instant::now(),
+            result,
+        }
+    }
+
+    fn last_check(
+        error: Option<UpdateError>,
+        available: Option<&str>,
+        behind: Option<&str>,
+    ) -> LastCheck {
+        LastCheck {
+            at_ms: 1_700_000_000_000,
+            channel: updater::UpdateChannel::Stable,
+            available_version: available.map(str::to_string),
+            behind_version: behind.map(str::to_string),
+            error,
+        }
+    }
+
+    fn status_and_code(item: &HealthItem) -> (HealthStatus, &'static str) {
+        (item.status, item.code)
+    }
+
+    #[test]
+    fn the_report_has_the_worst_status() {
+        assert_eq!(HealthReport::new(Vec::new()).status, HealthStatus::Ok);
+        let report = HealthReport::new(vec![tray(true), tray(false), history(Ok(()))]);
+        assert_eq!(report.status, HealthStatus::Warn);
+        let report = HealthReport::new(vec![tray(false), hotkey("Alt+Space", false)]);
+        assert_eq!(report.status, HealthStatus::Error);
+    }
+
+    #[test]
+    fn hotkey_names_the_accelerator() {
+        let item = hotkey("Alt+Space", true);
+        assert_eq!(
+            status_and_code(&item),
+            (HealthStatus::Ok, "hotkey_registered")
+        );
+        assert!(item.detail.contains("Alt+Space"));
+        let item = hotkey("Alt+Space", false);
+        assert_eq!(
+            status_and_code(&item),
+            (HealthStatus::Error, "hotkey_not_registered")
+        );
+        assert!(item.detail.contains("Alt+Space"));
+    }
+
+    #[test]
+    fn microphone_by_device_and_permission() {
+        let cases = [
+            (None, (HealthStatus::Warn, "microphone_unknown")),
+            (
+                Some(mic(Some("MacBook Pro Microphone"), Some("denied"))),
+                (HealthStatus::Error, "microphone_denied"),
+            ),
+            (
+                Some(mic(None, Some("granted"))),
+                (HealthStatus::Error, "microphone_missing"),
+            ),
+            (
+                Some(mic(Some("MacBook Pro Microphone"), Some("granted"))),
+                (HealthStatus::Ok, "microphone_available"),
+            ),
+            (
+                Some(mic(Some("MacBook Pro Microphone"), Some("prompt"))),
+                (HealthStatus::Warn, "microphone_permission_pending"),
+            ),
+            (
+                Some(mic(Some("MacBook Pro Microphone"), None)),
+                (HealthStatus::Warn, "microphone_permission_pending"),
+            ),
+        ];
+        for (report, expected) in cases {
+            let item = microphone(report.as_ref());
+            assert_eq!(status_and_code(&item), expected, "{:?}", report);
+            assert_eq!(item.subsystem, "microphone");
+        }
+        assert_eq!(
+            microphone(Some(&mic(Some("USB Mic"), Some("granted")))).detail,
+            "USB Mic"
+        );
+    }
+
+    #[test]
+    fn accessibility_when_it_is_needed() {
+        assert_eq!(
+            status_and_code(&accessibility(None)),
+            (HealthStatus::Ok, "accessibility_not_required")
+        );
+        assert_eq!(
+            status_and_code(&accessibility(Some(true))),
+            (HealthStatus::Ok, "accessibility_granted")
+        );
+        assert_eq!(
+            status_and_code(&accessibility(Some(false))),
+            (HealthStatus::Error, "accessibility_denied")
+        );
+    }
+
+    #[test]
+    fn provider_by_reachability() {
+        assert_eq!(
+            status_and_code(&provider("deepgram", None)),
+            (HealthStatus::Warn, "provider_unchecked")
+        );
+        assert_eq!(
+            status_and_code(&provider("deepgram", Some(&checked(Ok(()))))),
+            (HealthStatus::Ok, "provider_reachable")
+        );
+        let item = provider(
+            "deepgram",
+            Some(&checked(Err("connection refused".to_string()))),
+        );
+        assert_eq!(
+            status_and_code(&item),
+            (HealthStatus::Error, "provider_unreachable")
+        );
+        assert!(item.detail.contains("connection refused"));
+        // Neither is ever connected to
+        assert_eq!(
+            status_and_code(&provider(MOCK_PROVIDER, None)),
+            (HealthStatus::Warn, "provider_mock")
+        );
+        assert_eq!(
+            status_and_code(&provider("whisper", Some(&checked(Ok(()))))),
+            (HealthStatus::Error, "provider_unknown")
+        );
+    }
+
+    #[test]
+    fn model_by_provider() {
+        assert_eq!(
+            status_and_code(&model("deepgram")),
+            (HealthStatus::Ok, "model_cloud")
+        );
+        assert_eq!(
+            status_and_code(&model(MOCK_PROVIDER)),
+            (HealthStatus::Ok, "model_mock")
+        );
+        assert_eq!(
+            status_and_code(&model("whisper")),
+            (HealthStatus::Error, "model_unknown")
+        );
+    }
+
+    #[test]
+    fn history_and_tray() {
+        assert_eq!(
+            status_and_code(&history(Ok(()))),
+            (HealthStatus::Ok, "history_open")
+        );
+        let item = history(Err("database is locked".to_string()));
+        assert_eq!(
+            status_and_code(&item),
+            (HealthStatus::Error, "history_unavailable")
+        );
+        assert!(item.detail.contains("database is locked"));
+        assert_eq!(
+            status_and_code(&tray(true)),
+            (HealthStatus::Ok, "tray_present")
+        );
+        assert_eq!(
+            status_and_code(&tray(false)),
+            (HealthStatus::Warn, "tray_missing")
+        );
+    }
+
+    #[test]
+    fn updates_by_last_check() {
+        let network = || UpdateError::Network {
+            message: "timed out".to_string(),
+        };
+        let cases = [
+            (None, (HealthStatus::Ok, "updates_unchecked")),
+            (
+                Some(last_check(Some(UpdateError::NotConfigured), None, None)),
+                (HealthStatus::Ok, "updates_not_configured"),
+            ),
+            (
+                Some(last_check(Some(network()), Some("2.0.0"), None)),
+                (HealthStatus::Warn, "update_check_failed"),
+            ),
+            (
+                Some(last_check(None, Some("2.0.0"), None)),
+                (HealthStatus::Warn, "update_available"),
+            ),
+            (
+                Some(last_check(None, None, Some("0.9.0"))),
+                (HealthStatus::Ok, "updates_channel_behind"),
+            ),
+            (
+                Some(last_check(None, None, None)),
+                (HealthStatus::Ok, "updates_current"),
+            ),
+        ];
+        for (last_check, expected) in cases {
+            let item = updates(last_check.as_ref());
+            assert_eq!(status_and_code(&item), expected, "{:?}", last_check);
+        }
+        assert!(updates(Some(&last_check(None, Some("2.0.0"), None)))
+            .detail
+            .contains("2.0.0"));
+    }
+
+    #[test]
+    fn system_audio_by_support_selection_and_permission() {
+        let unsupported = || Err("needs macOS 13".to_string());
+        let cases = [
+            (
+                unsupported(),
+                true,
+                None,
+                (HealthStatus::Warn, "system_audio_unsupported"),
+            ),
+            (
+                unsupported(),
+                false,
+                None,
+                (HealthStatus::Ok, "system_audio_unsupported"),
+            ),
+            (
+                Ok(()),
+                false,
+                Some(false),
+                (HealthStatus::Ok, "system_audio_off"),
+            ),
+            (
+                Ok(()),
+                true,
+                Some(false),
+                (HealthStatus::Warn, "system_audio_permission"),
+            ),
+            (
+                Ok(()),
+                true,
+                Some(true),
+                (HealthStatus::Ok, "system_audio_ready"),
+            ),
+            (Ok(()), true, None, (HealthStatus::Ok, "system_audio_ready")),
+        ];
+        for (support, selected, permission, expected) in cases {
+            let item = system_audio(support.clone(), selected, permission);
+            assert_eq!(
+                status_and_code(&item),
+                expected,
+                "{:?}, selected {}, permission {:?}",
+                support,
+                selected,
+                permission
+            );
+        }
+    }
+
+    #[test]
+    fn background_threads_by_how_they_did() {
+        let mut degraded = BTreeMap::new();
+        assert_eq!(
+            status_and_code(&background(&degraded)),
+            (HealthStatus::Ok, "background_running")
+        );
+        degraded.insert(
+            "webhook",
+            Degraded {
+                panics: 1,
+                stopped: false,
+            },
+        );
+        let item = background(&degraded);
+        assert_eq!(
+            status_and_code(&item),
+            (HealthStatus::Warn, "background_restarted")
+        );
+        assert!(item.detail.contains("webhook"));
+        degraded.insert(
+            "metrics-flusher",
+            Degraded {
+                panics: 4,
+                stopped: true,
+            },
+        );
+        let item = background(&degraded);
+        assert_eq!(
+            status_and_code(&item),
+            (HealthStatus::Error, "background_stopped")
+        );
+        assert!(item.detail.contains("metrics-flusher"));
+        assert!(!item.detail.contains("webhook"));
+    }
+
+    #[test]
+    fn connecting_reports_whether_anything_listens() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        assert_eq!(connect(&address), Ok(()));
+
+        drop(listener);
+        assert!(connect(&address).is_err());
+        assert!(connect("not a host name").is_err());
+    }
+
+    #[test]
+    fn only_known_providers_are_probed() {
+        for provider in KNOWN_PROVIDERS {
+            assert!(provider_address(provider).is_some(), "{}", provider);
+        }
+        assert_eq!(provider_address(MOCK_PROVIDER), None);
+    }
+}
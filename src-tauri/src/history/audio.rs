@@ -0,0 +1,401 @@
+//! Retention of dictation recordings.
+//!
+//! The frontend stages each dictation's audio in the cache directory with
+//! [`stage_dictation_audio`]. Once the transcript is recorded in history the
+//! staged file is either deleted (the default) or moved to
+//! `recordings/<history id>.<ext>` in the app data directory, depending on
+//! the [`AudioRetention`] policy. The pruning task later enforces the policy
+//! on older recordings.
+//!
+//! Pinned entries keep their recordings regardless of policy. Every deletion
+//! tolerates the file already being gone.
+//!
+//! The recording is only sent when the policy keeps it, as raw bytes, and
+//! the paste doesn't wait for it: staged after the dictation was pasted, it
+//! is kept for the dictation's entry once that is recorded (see
+//! [`LateAudioState`]).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use tauri::ipc::{InvokeBody, Request};
+use tauri::{Manager, State};
+
+use super::manage::remove_audio_files;
+use super::{now_ms, HistoryError, HistoryState};
+use crate::dictation::DictationState;
+use crate::privacy;
+use crate::AppHandle;
+
+/// Directory under the app data dir holding retained recordings.
+const RECORDINGS_DIR: &str = "recordings";
+
+const MS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// How long recordings are kept after a successful transcription.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "policy", content = "value", rename_all = "snake_case")]
+pub enum AudioRetention {
+    /// Delete audio as soon as the transcript is saved.
+    #[default]
+    None,
+    /// Keep the most recent N recordings.
+    LastN(u32),
+    /// Keep recordings for N days.
+    Days(u32),
+}
+
+impl HistoryState {
+    /// Directory where retained recordings are stored.
+    fn recordings_dir(&self) -> PathBuf {
+        self.path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(RECORDINGS_DIR)
+    }
+
+    /// Keeps or deletes the staged recording of entry `id` according to
+    /// `policy`.
+    pub fn retain_audio(
+        &self,
+        id: i64,
+        staged: &Path,
+        policy: AudioRetention,
+    ) -> Result<(), String> {
+        if policy == AudioRetention::None {
+            remove_audio_files(vec![staged.to_path_buf()]);
+            return Ok(());
+        }
+
+        let dir = self.recordings_dir();
+        let mut target = dir.join(id.to_string());
+        if let Some(extension) = staged.extension() {
+            target.set_extension(extension);
+        }
+
+        let moved = fs::create_dir_all(&dir).and_then(|()| move_file(staged, &target));
+        if let Err(e) = moved {
+            remove_audio_files(vec![staged.to_path_buf()]);
+            return Err(format!("failed to keep recording: {}", e));
+        }
+
+        self.with_connection(|connection| {
+            connection.execute(
+                "UPDATE history SET audio_path = ?1 WHERE id = ?2",
+                params![target.to_string_lossy(), id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Deletes recordings the policy no longer allows, keeping the entries.
+    /// Returns the number of recordings removed.
+    pub fn apply_audio_retention(&self, policy: AudioRetention) -> Result<u64, String> {
+        let cutoff_ms = match policy {
+            AudioRetention::Days(days) => Some(now_ms() - i64::from(days) * MS_PER_DAY),
+            _ => None,
+        };
+
+        let expired = self.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            let mut expired = Vec::new();
+            {
+                let mut statement = transaction.prepare(
+                    "SELECT id, created_at_ms, audio_path FROM history
+                     WHERE audio_path IS NOT NULL AND pinned = 0
+                     ORDER BY created_at_ms DESC, id DESC",
+                )?;
+                let mut rows = statement.query([])?;
+                let mut index = 0;
+                while let Some(row) = rows.next()? {
+                    let created_at_ms: i64 = row.get(1)?;
+                    let keep = match policy {
+                        AudioRetention::None => false,
+                        AudioRetention::LastN(count) => index < count,
+                        AudioRetention::Days(_) => cutoff_ms.is_some_and(|c| created_at_ms >= c),
+                    };
+                    if !keep {
+                        expired.push((row.get::<_, i64>(0)?, row.get::<_, String>(2)?));
+                    }
+                    index += 1;
+                }
+            }
+
+            let mut clear =
+                transaction.prepare("UPDATE history SET audio_path = NULL WHERE id = ?1")?;
+            for (id, _) in &expired {
+                clear.execute(params![id])?;
+            }
+            drop(clear);
+            transaction.commit()?;
+            Ok(expired)
+        })?;
+
+        let count = expired.len() as u64;
+        remove_audio_files(expired.into_iter().map(|(_, path)| path.into()).collect());
+        Ok(count)
+    }
+
+    /// Returns the retained recording of entry `id`.
+    pub fn audio_path(&self, id: i64) -> Result<PathBuf, HistoryError> {
+        let path: Option<Option<String>> = self.with_connection(|connection| {
+            connection
+                .query_row(
+                    "SELECT audio_path FROM history WHERE id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .map(Some)
+                .or_else(|e| match e {
+                    rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                    e => Err(e),
+                })
+        })?;
+
+        match path {
+            None => Err(HistoryError::NotFound { id }),
+            Some(Some(path)) if Path::new(&path).exists() => Ok(path.into()),
+            Some(_) => Err(HistoryError::NotRetained { id }),
+        }
+    }
+
+    /// Returns entry `id`'s retained recording and its duration in
+    /// milliseconds, or the most recent entry's with both, as `(id, path,
+    /// duration)`.
+    pub fn recording(&self, id: Option<i64>) -> Result<Option<(i64, PathBuf, u64)>, String> {
+        let found: Option<(i64, String, i64)> = self.with_connection(|connection| {
+            connection
+                .query_row(
+                    "SELECT id, audio_path, duration_ms FROM history
+                     WHERE audio_path IS NOT NULL AND duration_ms > 0
+                       AND (?1 IS NULL OR id = ?1)
+                     ORDER BY created_at_ms DESC LIMIT 1",
+                    params![id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .map(Some)
+                .or_else(|e| match e {
+                    rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                    e => Err(e),
+                })
+        })?;
+
+        Ok(found
+            .filter(|(_, path, _)| Path::new(path).exists())
+            .map(|(id, path, duration_ms)| (id, path.into(), duration_ms as u64)))
+    }
+}
+
+/// One half of a dictation whose recording was staged after its paste.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Late {
+    /// The recording, staged once the dictation was no longer in progress.
+    Audio(PathBuf),
+    /// The dictation's entry, recorded without a recording.
+    Recorded(i64),
+}
+
+/// Managed state: whichever of a finished dictation's recording and its
+/// history entry came first, waiting for the other. Dictations finish one
+/// at a time, so it holds one.
+#[derive(Default)]
+pub struct LateAudioState(Mutex<Option<(String, Late)>>);
+
+/// Puts `arrived`, of dictation `session_id`, in `slot`. Returns the entry
+/// and staged recording to keep, once both are there, and a recording that
+/// no entry will come for any more, to delete.
+fn meet(
+    slot: &mut Option<(String, Late)>,
+    session_id: &str,
+    arrived: Late,
+) -> (Option<(i64, PathBuf)>, Option<PathBuf>) {
+    match (slot.take(), arrived) {
+        (Some((id, Late::Recorded(entry))), Late::Audio(path))
+        | (Some((id, Late::Audio(path))), Late::Recorded(entry))
+            if id == session_id =>
+        {
+            (Some((entry, path)), None)
+        }
+        (previous, arrived) => {
+            *slot = Some((session_id.to_string(), arrived));
+            let stale = match previous {
+                Some((_, Late::Audio(path))) => Some(path),
+                _ => None,
+            };
+            (None, stale)
+        }
+    }
+}
+
+/// Hands `arrived` of the finished dictation `session_id` over, keeping its
+/// recording as `policy` says once both halves are there.
+fn arrive(app: &AppHandle, session_id: &str, arrived: Late, policy: AudioRetention) {
+    let (ready, stale) = meet(
+        &mut app.state::<LateAudioState>().0.lock().unwrap(),
+        session_id,
+        arrived,
+    );
+    remove_audio_files(stale.into_iter().collect());
+    if let Some((id, staged)) = ready {
+        let history = app.state::<HistoryState>();
+        if let Err(e) = history.retain_audio(id, &staged, policy) {
+            tracing::warn!("{}", e);
+        }
+    }
+}
+
+/// Called once the entry `id` of dictation `session_id` is recorded before
+/// its recording was staged.
+pub(super) fn recorded_without_audio(
+    app: &AppHandle,
+    session_id: &str,
+    id: i64,
+    policy: AudioRetention,
+) {
+    if policy != AudioRetention::None {
+        arrive(app, session_id, Late::Recorded(id), policy);
+    }
+}
+
+/// Moves a file, falling back to copy and delete across file systems (the
+/// cache and data directories aren't guaranteed to share one).
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::rename(from, to).or_else(|_| {
+        fs::copy(from, to)?;
+        fs::remove_file(from)
+    })
+}
+
+/// Stages the audio of dictation `session_id` until its transcript is
+/// recorded.
+///
+/// The body is the recording, as raw bytes; the `x-session-id` header names
+/// the dictation, and `x-audio-extension` its container format (e.g.
+/// `webm`). Staging again replaces the previous file. Nothing is written
+/// when the storage policy doesn't keep recordings.
+///
+/// # Errors
+///
+/// Returns an error if the body isn't raw bytes, a header is missing, the
+/// extension is invalid or the file cannot be written.
+#[tauri::command]
+pub fn stage_dictation_audio(
+    app: AppHandle,
+    dictation: State<'_, DictationState>,
+    request: Request<'_>,
+) -> Result<(), String> {
+    let InvokeBody::Raw(audio) = request.body() else {
+        return Err("the recording must be sent as raw bytes".to_string());
+    };
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| format!("missing {} header", name))
+    };
+    let session_id = header("x-session-id")?;
+    let extension = header("x-audio-extension")?;
+    if extension.is_empty()
+        || extension.len() > 5
+        || !extension.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return Err(format!("invalid audio extension \"{}\"", extension));
+    }
+
+    let policy = privacy::policy().audio;
+    if policy == AudioRetention::None {
+        // Would be deleted as soon as the transcript is saved
+        return Ok(());
+    }
+
+    let dir = app.path().app_cache_dir().map_err(|e| e.to_string())?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join(format!("dictation-{}.{}", now_ms(), extension));
+    fs::write(&path, audio).map_err(|e| e.to_string())?;
+
+    let mut guard = dictation.0.lock().unwrap();
+    let current = guard
+        .as_mut()
+        .filter(|current| current.session_id == session_id);
+    match current {
+        Some(current) => {
+            current.discard_audio();
+            current.audio_path = Some(path);
+        }
+        None => {
+            drop(guard);
+            arrive(&app, session_id, Late::Audio(path), policy);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the path of a history entry's retained recording, for exporting
+/// or re-transcribing it.
+///
+/// # Errors
+///
+/// Returns [`HistoryError::NotFound`] if no entry has the given id and
+/// [`HistoryError::NotRetained`] if its audio was never kept or has been
+/// deleted.
+#[tauri::command]
+pub fn get_audio_for_history_item(
+    state: State<'_, HistoryState>,
+    id: i64,
+) -> Result<PathBuf, HistoryError> {
+    state.audio_path(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audio(name: &str) -> Late {
+        Late::Audio(PathBuf::from(name))
+    }
+
+    #[test]
+    fn recording_staged_after_the_entry_is_kept_for_it() {
+        let mut slot = None;
+        assert_eq!(meet(&mut slot, "a", Late::Recorded(7)), (None, None));
+        assert_eq!(
+            meet(&mut slot, "a", audio("a.webm")),
+            (Some((7, PathBuf::from("a.webm"))), None)
+        );
+        assert_eq!(slot, None);
+    }
+
+    #[test]
+    fn entry_recorded_after_the_recording_keeps_it() {
+        let mut slot = None;
+        assert_eq!(meet(&mut slot, "a", audio("a.webm")), (None, None));
+        assert_eq!(
+            meet(&mut slot, "a", Late::Recorded(7)),
+            (Some((7, PathBuf::from("a.webm"))), None)
+        );
+        assert_eq!(slot, None);
+    }
+
+    #[test]
+    fn recording_of_an_unrecorded_dictation_is_deleted_by_the_next() {
+        let mut slot = None;
+        meet(&mut slot, "a", audio("a.webm"));
+        assert_eq!(
+            meet(&mut slot, "b", Late::Recorded(8)),
+            (None, Some(PathBuf::from("a.webm")))
+        );
+        assert_eq!(slot, Some(("b".to_string(), Late::Recorded(8))));
+    }
+
+    #[test]
+    fn halves_of_different_dictations_never_meet() {
+        let mut slot = None;
+        meet(&mut slot, "a", Late::Recorded(7));
+        assert_eq!(meet(&mut slot, "b", audio("b.webm")), (None, None));
+        assert_eq!(slot, Some(("b".to_string(), audio("b.webm"))));
+    }
+}
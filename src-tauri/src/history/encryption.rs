@@ -0,0 +1,329 @@
+//! Optional encryption of the history database at rest.
+//!
+//! # Architecture Decision
+//!
+//! The whole database file is encrypted with SQLCipher rather than
+//! encrypting individual columns. Column-level encryption would leave the
+//! FTS5 index (which must hold searchable plaintext tokens) readable, or
+//! force search to decrypt every row. With SQLCipher every page, including
+//! the index and its shadow tables, is encrypted on disk while queries see
+//! plaintext, so search keeps working unchanged.
+//!
+//! The 256-bit key is random, stored only in the OS keychain and applied as
+//! a raw key (no passphrase derivation). Encryption is on exactly when the
+//! keychain holds a key. If the key goes missing, the database is not
+//! touched: history reports itself as locked until the key is restored.
+//!
+//! The key is only stored once the encrypted copy has been verified, and
+//! the plaintext database is kept as a backup until the next start unlocks
+//! the encrypted one with the key read back from the keychain. A keychain
+//! that didn't really keep the key would otherwise lose all of history; at
+//! that start the backup is put back instead.
+//!
+//! Retained audio files live outside the database and are not covered.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, ErrorCode};
+use tauri::Manager;
+
+use super::{open_database, HistoryState};
+use crate::secrets;
+use crate::AppHandle;
+
+/// Error reported when the database is encrypted but can't be unlocked.
+const LOCKED: &str =
+    "history locked: the database is encrypted and its key is missing from the keychain";
+const WRONG_KEY: &str = "history locked: the keychain key does not match the database";
+
+/// Reads the database key from the keychain. Keychain failures are logged
+/// and treated as "no key", which surfaces as a locked history if the
+/// database turns out to be encrypted.
+pub(super) fn database_key() -> Option<String> {
+    secrets::get(secrets::HISTORY_DATABASE_KEY).unwrap_or_else(|e| {
+        tracing::warn!("Failed to read history key from keychain: {}", e);
+        None
+    })
+}
+
+/// Opens the database at `path`, with the keychain key if there is one.
+pub(super) fn open(path: &Path) -> Result<Connection, String> {
+    open_with(path, database_key().as_deref())
+}
+
+/// Opens the database at `path` with `key`, and settles the plaintext
+/// backup an encryption left: deleted once the key has unlocked the
+/// database, put back if there is no key to unlock it with.
+fn open_with(path: &Path, key: Option<&str>) -> Result<Connection, String> {
+    let opened = open_database(path, key);
+    let backup_path = backup_path(path);
+    if !backup_path.exists() {
+        return opened;
+    }
+    let locked = matches!(&opened, Err(e) if e == LOCKED);
+    if opened.is_ok() && key.is_some() {
+        if let Err(e) = fs::remove_file(&backup_path) {
+            tracing::warn!("Failed to remove the unencrypted history backup: {}", e);
+        }
+    } else if locked && key.is_none() {
+        tracing::warn!("History was encrypted but its key is gone; restoring the backup");
+        fs::rename(&backup_path, path).map_err(|e| e.to_string())?;
+        return open_database(path, None);
+    }
+    opened
+}
+
+/// Where the plaintext database is kept while it is being encrypted.
+fn backup_path(path: &Path) -> PathBuf {
+    path.with_extension("sqlite3.backup")
+}
+
+/// Unlocks a freshly opened connection. Must run before any other statement.
+pub(super) fn apply_key(connection: &Connection, key: &str) -> rusqlite::Result<()> {
+    // The key is hex we generated, so it is safe to inline
+    connection.execute_batch(&format!("PRAGMA key = \"x'{}'\";", key))
+}
+
+/// Fails with a "locked" error if the database can't be read, which is how
+/// SQLCipher reports a missing or wrong key.
+pub(super) fn check_readable(connection: &Connection, keyed: bool) -> Result<(), String> {
+    match connection.query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(())) {
+        Ok(()) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::NotADatabase => {
+            Err(if keyed { WRONG_KEY } else { LOCKED }.to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl HistoryState {
+    /// Encrypts a plaintext database in place.
+    ///
+    /// An encrypted copy is written next to the database and verified, the
+    /// key stored in the keychain, and the copy then replaces the original.
+    /// The original is restored if anything fails, and kept as a backup
+    /// otherwise, until the next start (see [`open_with`]).
+    pub fn encrypt(&self) -> Result<(), String> {
+        if database_key().is_some() {
+            return Err("history is already encrypted".to_string());
+        }
+
+        let mut guard = self.connection.lock().unwrap();
+        let connection = guard.as_ref().map_err(Clone::clone)?;
+        let encrypted_path = self.path.with_extension("sqlite3.encrypting");
+        let backup_path = backup_path(&self.path);
+        let key = generate_key()?;
+
+        // Step 1: Write and verify the encrypted copy
+        let _ = fs::remove_file(&encrypted_path);
+        if let Err(e) = export_encrypted(connection, &encrypted_path, &key) {
+            let _ = fs::remove_file(&encrypted_path);
+            return Err(format!("failed to encrypt history: {}", e));
+        }
+
+        // Step 2: Store the key, now that it unlocks something. A key left
+        // in the keychain would lock the plaintext database, so it goes
+        // again if anything after this fails
+        if let Err(e) = secrets::set(secrets::HISTORY_DATABASE_KEY, &key) {
+            let _ = fs::remove_file(&encrypted_path);
+            let _ = secrets::delete(secrets::HISTORY_DATABASE_KEY);
+            return Err(format!("failed to store the history key: {}", e));
+        }
+
+        // Step 3: Swap the files. Dropping the connection closes the
+        // plaintext database so it can be moved
+        *guard = Err("history is being encrypted".to_string());
+        let swapped = fs::rename(&self.path, &backup_path).and_then(|()| {
+            fs::rename(&encrypted_path, &self.path).inspect_err(|_| {
+                let _ = fs::rename(&backup_path, &self.path);
+            })
+        });
+
+        // Step 4: Reopen with the key; fall back to the original on failure
+        let reopened = swapped
+            .map_err(|e| e.to_string())
+            .and_then(|()| open_database(&self.path, Some(&key)));
+        match reopened {
+            Ok(connection) => {
+                *guard = Ok(connection);
+                tracing::info!("History database encrypted; keeping the backup until next start");
+                Ok(())
+            }
+            Err(e) => {
+                if backup_path.exists() {
+                    let _ = fs::rename(&backup_path, &self.path);
+                }
+                let _ = fs::remove_file(&encrypted_path);
+                let _ = secrets::delete(secrets::HISTORY_DATABASE_KEY);
+                *guard = open_database(&self.path, None);
+                Err(format!("failed to encrypt history: {}", e))
+            }
+        }
+    }
+}
+
+/// Copies the database behind `connection` into a new encrypted file, and
+/// checks that the copy opens with `key` and holds every entry.
+fn export_encrypted(connection: &Connection, path: &Path, key: &str) -> Result<(), String> {
+    write_encrypted(connection, path, key).map_err(|e| e.to_string())?;
+
+    let copy = Connection::open(path).map_err(|e| e.to_string())?;
+    apply_key(&copy, key).map_err(|e| e.to_string())?;
+    check_readable(&copy, true)?;
+    let entries = |connection: &Connection| {
+        connection
+            .query_row("SELECT COUNT(*) FROM history", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map_err(|e| e.to_string())
+    };
+    let (copied, original) = (entries(&copy)?, entries(connection)?);
+    if copied != original {
+        return Err(format!(
+            "the encrypted copy holds {} of {} entries",
+            copied, original
+        ));
+    }
+    copy.execute_batch("INSERT INTO history_fts (history_fts) VALUES ('integrity-check');")
+        .map_err(|e| e.to_string())
+}
+
+/// [`export_encrypted`] without the checks.
+fn write_encrypted(connection: &Connection, path: &Path, key: &str) -> rusqlite::Result<()> {
+    let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    connection.execute(
+        "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+        params![path.to_string_lossy(), format!("x'{}'", key)],
+    )?;
+    let exported = connection
+        .query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))
+        .and_then(|()| {
+            connection.execute_batch(&format!("PRAGMA encrypted.user_version = {};", version))
+        });
+    connection.execute_batch("DETACH DATABASE encrypted;")?;
+    exported?;
+
+    // Rebuild the full-text index from the copied rows rather than trusting
+    // the exported shadow tables
+    let copy = Connection::open(path)?;
+    apply_key(&copy, key)?;
+    copy.execute_batch("INSERT INTO history_fts (history_fts) VALUES ('rebuild');")?;
+    Ok(())
+}
+
+/// Generates a random 256-bit key, hex encoded.
+fn generate_key() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Encrypts the history database in place with a new keychain-held key.
+///
+/// # Errors
+///
+/// Returns an error if history is already encrypted or unavailable, or if any
+/// step fails; the plaintext database is left as it was in that case.
+#[tauri::command]
+pub async fn encrypt_history(app: AppHandle) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<HistoryState>().encrypt())
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Returns whether history is encrypted at rest.
+#[tauri::command]
+pub fn is_history_encrypted() -> bool {
+    database_key().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::NewHistoryEntry;
+    use super::*;
+
+    /// A directory of its own for `test`, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("wispr-history-{}-{}", test, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn entries(connection: &Connection) -> i64 {
+        connection
+            .query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    /// A plaintext database with two entries at `path`.
+    fn plaintext(path: &Path) -> Connection {
+        let history = HistoryState {
+            path: path.to_path_buf(),
+            connection: std::sync::Mutex::new(open_database(path, None)),
+        };
+        history
+            .insert(&NewHistoryEntry::with_text("first"))
+            .unwrap();
+        history
+            .insert(&NewHistoryEntry::with_text("second"))
+            .unwrap();
+        history.connection.into_inner().unwrap().unwrap()
+    }
+
+    #[test]
+    fn encrypted_copy_opens_with_its_key_only() {
+        let dir = TempDir::new("export");
+        let source = plaintext(&dir.0.join("history.sqlite3"));
+        let copy = dir.0.join("copy.sqlite3");
+        let key = generate_key().unwrap();
+
+        export_encrypted(&source, &copy, &key).unwrap();
+        assert_eq!(entries(&open_database(&copy, Some(&key)).unwrap()), 2);
+        assert_eq!(open_database(&copy, None).err().as_deref(), Some(LOCKED));
+        let other = generate_key().unwrap();
+        assert_eq!(
+            open_database(&copy, Some(&other)).err().as_deref(),
+            Some(WRONG_KEY)
+        );
+    }
+
+    #[test]
+    fn backup_is_removed_once_the_key_unlocks() {
+        let dir = TempDir::new("unlocked");
+        let path = dir.0.join("history.sqlite3");
+        let source = plaintext(&dir.0.join("plain.sqlite3"));
+        let key = generate_key().unwrap();
+        export_encrypted(&source, &path, &key).unwrap();
+        fs::copy(dir.0.join("plain.sqlite3"), backup_path(&path)).unwrap();
+
+        assert_eq!(entries(&open_with(&path, Some(&key)).unwrap()), 2);
+        assert!(!backup_path(&path).exists());
+    }
+
+    #[test]
+    fn backup_is_restored_without_a_key() {
+        let dir = TempDir::new("restored");
+        let path = dir.0.join("history.sqlite3");
+        let source = plaintext(&dir.0.join("plain.sqlite3"));
+        export_encrypted(&source, &path, &generate_key().unwrap()).unwrap();
+        fs::copy(dir.0.join("plain.sqlite3"), backup_path(&path)).unwrap();
+
+        assert_eq!(entries(&open_with(&path, None).unwrap()), 2);
+        assert!(!backup_path(&path).exists());
+        assert_eq!(entries(&open_database(&path, None).unwrap()), 2);
+    }
+}
@@ -0,0 +1,394 @@
+//! Export of transcript history to JSON, CSV and Markdown files.
+//!
+//! Exports honor the same [`HistoryFilter`] as search and list entries
+//! oldest first.
+//!
+//! # Architecture Decision
+//!
+//! Rows are streamed from SQLite straight into a buffered file writer, one
+//! entry at a time, so exporting years of history never builds the whole
+//! document in memory. JSON arrays are written incrementally for the same
+//! reason rather than serializing a `Vec`.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, TimeZone};
+use rusqlite::params_from_iter;
+use serde::Deserialize;
+use tauri::Manager;
+
+use super::search::HistoryFilter;
+use super::{HistoryItem, HistoryState, ITEM_COLUMNS};
+
+use crate::AppHandle;
+
+/// Output format of [`export_history`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// An array of full history items.
+    Json,
+    /// One row per entry with a header line (RFC 4180 quoting).
+    Csv,
+    /// Entries grouped by local date, transcripts as blockquotes.
+    Markdown,
+}
+
+/// CSV column headers, in the order written by [`write_csv_row`].
+const CSV_HEADER: &[&str] = &[
+    "id",
+    "created_at",
+    "target_app",
+    "provider",
+    "language",
+    "duration_ms",
+    "word_count",
+    "pinned",
+    "raw_text",
+    "processed_text",
+];
+
+impl HistoryState {
+    /// Calls `f` with every entry matching `filter`, oldest first, stopping
+    /// at the first error. Returns the number of entries visited.
+    pub fn for_each_filtered(
+        &self,
+        filter: &HistoryFilter,
+        mut f: impl FnMut(HistoryItem) -> Result<(), String>,
+    ) -> Result<u64, String> {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        filter.append_sql(&mut conditions, &mut values);
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let sql = format!(
+            "SELECT {} FROM history h {} ORDER BY h.created_at_ms ASC, h.id ASC",
+            ITEM_COLUMNS, where_clause
+        );
+
+        self.with_connection(|connection| {
+            let mut statement = connection.prepare(&sql)?;
+            let mut rows = statement.query(params_from_iter(values.iter()))?;
+            let mut count = 0;
+            while let Some(row) = rows.next()? {
+                if let Err(e) = f(HistoryItem::from_row(row)?) {
+                    return Ok(Err(e));
+                }
+                count += 1;
+            }
+            Ok(Ok(count))
+        })?
+    }
+
+    /// Writes the entries matching `filter` to `path` in `format`, returning
+    /// the number of entries exported.
+    ///
+    /// A partially written file is removed if the export fails.
+    pub fn export(
+        &self,
+        format: ExportFormat,
+        path: &Path,
+        filter: &HistoryFilter,
+    ) -> Result<u64, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+        let result = self.write_export(Exporter::new(format, BufWriter::new(file)), filter);
+
+        if result.is_err() {
+            let _ = std::fs::remove_file(path);
+        }
+        result
+    }
+
+    fn write_export(
+        &self,
+        mut exporter: Exporter<impl Write>,
+        filter: &HistoryFilter,
+    ) -> Result<u64, String> {
+        exporter.start().map_err(|e| e.to_string())?;
+        let count = self.for_each_filtered(filter, |item| {
+            exporter.write_item(&item).map_err(|e| e.to_string())
+        })?;
+        exporter.finish().map_err(|e| e.to_string())?;
+        Ok(count)
+    }
+}
+
+/// Incremental writer for one export format.
+struct Exporter<W: Write> {
+    format: ExportFormat,
+    out: W,
+    /// Whether an entry has been written (JSON separators).
+    wrote_item: bool,
+    /// Local date of the current Markdown section.
+    current_date: Option<String>,
+}
+
+impl<W: Write> Exporter<W> {
+    fn new(format: ExportFormat, out: W) -> Self {
+        Self {
+            format,
+            out,
+            wrote_item: false,
+            current_date: None,
+        }
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        match self.format {
+            ExportFormat::Json => self.out.write_all(b"["),
+            ExportFormat::Csv => write_csv_record(&mut self.out, CSV_HEADER.iter().copied()),
+            ExportFormat::Markdown => self.out.write_all(b"# Dictation History\n"),
+        }
+    }
+
+    fn write_item(&mut self, item: &HistoryItem) -> io::Result<()> {
+        match self.format {
+            ExportFormat::Json => {
+                self.out
+                    .write_all(if self.wrote_item { b",\n  " } else { b"\n  " })?;
+                serde_json::to_writer(&mut self.out, item)?;
+            }
+            ExportFormat::Csv => write_csv_row(&mut self.out, item)?,
+            ExportFormat::Markdown => {
+                let created_at = Local.timestamp_millis_opt(item.created_at_ms).single();
+                let date = created_at.map_or_else(
+                    || "Unknown date".to_string(),
+                    |t| t.format("%Y-%m-%d").to_string(),
+                );
+                if self.current_date.as_deref() != Some(date.as_str()) {
+                    write!(self.out, "\n## {}\n", date)?;
+                    self.current_date = Some(date);
+                }
+
+                let time = created_at.map_or_else(String::new, |t| t.format("%H:%M").to_string());
+                write!(self.out, "\n**{}**", time)?;
+                if let Some(app) = &item.target_app {
+                    write!(self.out, " · {}", app)?;
+                }
+                self.out.write_all(b"\n\n")?;
+                write_blockquote(&mut self.out, &item.processed_text)?;
+            }
+        }
+        self.wrote_item = true;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        if self.format == ExportFormat::Json {
+            self.out
+                .write_all(if self.wrote_item { b"\n]\n" } else { b"]\n" })?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Writes one history entry as a CSV record.
+fn write_csv_row(out: &mut impl Write, item: &HistoryItem) -> io::Result<()> {
+    let created_at = Local
+        .timestamp_millis_opt(item.created_at_ms)
+        .single()
+        .map_or_else(|| item.created_at_ms.to_string(), |t| t.to_rfc3339());
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+
+    let fields = [
+        item.id.to_string(),
+        created_at,
+        optional(&item.target_app),
+        optional(&item.provider),
+        optional(&item.language),
+        item.duration_ms
+            .map(|ms| ms.to_string())
+            .unwrap_or_default(),
+        item.word_count.to_string(),
+        item.pinned.to_string(),
+        item.raw_text.clone(),
+        item.processed_text.clone(),
+    ];
+    write_csv_record(out, fields.iter().map(String::as_str))
+}
+
+/// Writes one CSV record terminated by CRLF.
+///
+/// Fields containing commas, quotes or line breaks are quoted with embedded
+/// quotes doubled (RFC 4180), so multi-line transcripts survive a round trip
+/// through spreadsheet apps.
+fn write_csv_record<'a>(
+    out: &mut impl Write,
+    fields: impl IntoIterator<Item = &'a str>,
+) -> io::Result<()> {
+    for (index, field) in fields.into_iter().enumerate() {
+        if index > 0 {
+            out.write_all(b",")?;
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            write!(out, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            out.write_all(field.as_bytes())?;
+        }
+    }
+    out.write_all(b"\r\n")
+}
+
+/// Writes `text` as a Markdown blockquote, one `>` line per line of text.
+fn write_blockquote(out: &mut impl Write, text: &str) -> io::Result<()> {
+    for line in text.lines() {
+        if line.is_empty() {
+            out.write_all(b">\n")?;
+        } else {
+            writeln!(out, "> {}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Exports history matching `filter` to the file at `path`.
+///
+/// Returns the number of entries exported. Runs on a blocking worker so large
+/// exports don't stall the UI.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written, the history database is
+/// unavailable or the query fails. No partial file is left behind.
+#[tauri::command]
+pub async fn export_history(
+    app: AppHandle,
+    format: ExportFormat,
+    path: PathBuf,
+    filter: Option<HistoryFilter>,
+) -> Result<u64, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        app.state::<HistoryState>()
+            .export(format, &path, &filter.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::NewHistoryEntry;
+    use super::*;
+
+    /// Reads back RFC 4180 records, as a spreadsheet app would.
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let mut records = Vec::new();
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' if quoted => quoted = false,
+                '"' if field.is_empty() => quoted = true,
+                ',' if !quoted => record.push(std::mem::take(&mut field)),
+                '\r' if !quoted && chars.peek() == Some(&'\n') => {
+                    chars.next();
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                _ => field.push(c),
+            }
+        }
+        assert!(field.is_empty() && record.is_empty(), "unterminated record");
+        records
+    }
+
+    const AWKWARD: &[&str] = &[
+        "plain",
+        "",
+        "a, b",
+        "she said \"hi\"",
+        "\"quoted\"",
+        "two\nlines",
+        "crlf\r\nline",
+        "lone\rreturn",
+        "trailing,",
+        "😀, ünïcödé",
+    ];
+
+    #[test]
+    fn csv_records_round_trip() {
+        let mut out = Vec::new();
+        write_csv_record(&mut out, AWKWARD.iter().copied()).unwrap();
+        let records = parse_csv(&String::from_utf8(out).unwrap());
+        assert_eq!(records, vec![AWKWARD.to_vec()]);
+    }
+
+    #[test]
+    fn plain_fields_are_not_quoted() {
+        let mut out = Vec::new();
+        write_csv_record(&mut out, ["a", "b c", "d"]).unwrap();
+        assert_eq!(out, b"a,b c,d\r\n");
+    }
+
+    fn export(history: &HistoryState, format: ExportFormat) -> String {
+        let mut out = Vec::new();
+        let count = history
+            .write_export(Exporter::new(format, &mut out), &HistoryFilter::default())
+            .unwrap();
+        assert_eq!(count, AWKWARD.len() as u64);
+        String::from_utf8(out).unwrap()
+    }
+
+    fn history() -> HistoryState {
+        let history = HistoryState::in_memory();
+        for text in AWKWARD {
+            history.insert(&NewHistoryEntry::with_text(text)).unwrap();
+        }
+        history
+    }
+
+    #[test]
+    fn csv_export_round_trips_transcripts() {
+        let records = parse_csv(&export(&history(), ExportFormat::Csv));
+
+        assert_eq!(records[0], CSV_HEADER.to_vec());
+        let texts: Vec<&str> = records[1..].iter().map(|r| r[8].as_str()).collect();
+        assert_eq!(texts, AWKWARD.to_vec());
+        assert!(records.iter().all(|r| r.len() == CSV_HEADER.len()));
+        assert!(records[1..].iter().all(|r| r[8] == r[9]));
+    }
+
+    #[test]
+    fn json_export_is_one_array() {
+        let json: serde_json::Value =
+            serde_json::from_str(&export(&history(), ExportFormat::Json)).unwrap();
+        let texts: Vec<&str> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["processed_text"].as_str().unwrap())
+            .collect();
+        assert_eq!(texts, AWKWARD.to_vec());
+    }
+
+    #[test]
+    fn empty_json_export_is_an_empty_array() {
+        let mut out = Vec::new();
+        HistoryState::in_memory()
+            .write_export(
+                Exporter::new(ExportFormat::Json, &mut out),
+                &HistoryFilter::default(),
+            )
+            .unwrap();
+        assert_eq!(out, b"[]\n");
+    }
+
+    #[test]
+    fn blockquotes_quote_every_line() {
+        let mut out = Vec::new();
+        write_blockquote(&mut out, "first\n\nthird").unwrap();
+        assert_eq!(out, b"> first\n>\n> third\n");
+    }
+}
@@ -0,0 +1,236 @@
+//! History item management: pinning, deletion, clearing and re-pasting.
+//!
+//! # Architecture Decision
+//!
+//! Entries may own a retained audio file. Rows are deleted inside a
+//! transaction that first collects their audio paths, and the files are
+//! removed only after the commit succeeds: a failed transaction deletes
+//! nothing, and a file that is already gone is not an error.
+
+use std::path::PathBuf;
+
+use rusqlite::{params, Connection};
+use tauri::{Manager, State};
+
+use crate::windows;
+use crate::AppHandle;
+
+use super::{emit_changed, now_ms, HistoryChange, HistoryError, HistoryState};
+
+impl HistoryState {
+    /// Sets the pinned flag of an entry. Returns `false` if no entry has `id`.
+    pub fn set_pinned(&self, id: i64, pinned: bool) -> Result<bool, String> {
+        self.with_connection(|connection| {
+            let updated = connection.execute(
+                "UPDATE history SET pinned = ?1 WHERE id = ?2",
+                params![pinned, id],
+            )?;
+            Ok(updated > 0)
+        })
+    }
+
+    /// Records that an entry was just re-pasted. Returns `false` if no entry
+    /// has `id`.
+    pub fn mark_used(&self, id: i64) -> Result<bool, String> {
+        self.with_connection(|connection| {
+            let updated = connection.execute(
+                "UPDATE history SET last_used_at_ms = ?1 WHERE id = ?2",
+                params![now_ms(), id],
+            )?;
+            Ok(updated > 0)
+        })
+    }
+
+    /// Deletes an entry and its retained audio. Returns `false` if no entry
+    /// has `id`.
+    pub fn delete(&self, id: i64) -> Result<bool, String> {
+        let audio =
+            self.with_connection(|connection| delete_where(connection, "id = ?1", params![id]))?;
+
+        let found = audio.is_some();
+        remove_audio_files(audio.unwrap_or_default());
+        Ok(found)
+    }
+
+    /// Deletes every entry (or every unpinned entry) and their audio, in a
+    /// single transaction. Returns the number of deleted entries.
+    pub fn clear(&self, keep_pinned: bool) -> Result<usize, String> {
+        let condition = if keep_pinned { "pinned = 0" } else { "1" };
+        let (count, audio) = self.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            let audio = collect_audio_paths(&transaction, condition, params![])?;
+            let count =
+                transaction.execute(&format!("DELETE FROM history WHERE {}", condition), [])?;
+            transaction.commit()?;
+            Ok((count, audio))
+        })?;
+
+        remove_audio_files(audio);
+        Ok(count)
+    }
+}
+
+/// Deletes the rows matching `condition` in one transaction, returning their
+/// audio paths, or `None` when nothing matched.
+fn delete_where(
+    connection: &Connection,
+    condition: &str,
+    params: impl rusqlite::Params + Copy,
+) -> rusqlite::Result<Option<Vec<PathBuf>>> {
+    let transaction = connection.unchecked_transaction()?;
+    let audio = collect_audio_paths(&transaction, condition, params)?;
+    let deleted =
+        transaction.execute(&format!("DELETE FROM history WHERE {}", condition), params)?;
+    transaction.commit()?;
+
+    Ok((deleted > 0).then_some(audio))
+}
+
+/// Returns the retained audio paths of the rows matching `condition`.
+fn collect_audio_paths(
+    connection: &Connection,
+    condition: &str,
+    params: impl rusqlite::Params,
+) -> rusqlite::Result<Vec<PathBuf>> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT audio_path FROM history WHERE audio_path IS NOT NULL AND {}",
+        condition
+    ))?;
+    let paths = statement
+        .query_map(params, |row| row.get::<_, String>(0))?
+        .map(|path| path.map(PathBuf::from))
+        .collect();
+    paths
+}
+
+/// Removes audio files after their rows are gone. Files that no longer exist
+/// are skipped; other failures are logged.
+pub(crate) fn remove_audio_files(paths: Vec<PathBuf>) {
+    for path in paths {
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("Failed to remove audio {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Pins a history entry so it is listed first and never pruned.
+///
+/// # Errors
+///
+/// Returns an error if no entry has the given id or the database fails.
+#[tauri::command]
+pub fn pin_history_item(
+    app: AppHandle,
+    state: State<'_, HistoryState>,
+    id: i64,
+) -> Result<(), String> {
+    set_pinned(&app, &state, id, true)
+}
+
+/// Unpins a history entry.
+///
+/// # Errors
+///
+/// Returns an error if no entry has the given id or the database fails.
+#[tauri::command]
+pub fn unpin_history_item(
+    app: AppHandle,
+    state: State<'_, HistoryState>,
+    id: i64,
+) -> Result<(), String> {
+    set_pinned(&app, &state, id, false)
+}
+
+/// Deletes a history entry together with its retained audio.
+///
+/// # Errors
+///
+/// Returns an error if no entry has the given id or the database fails.
+#[tauri::command]
+pub fn delete_history_item(
+    app: AppHandle,
+    state: State<'_, HistoryState>,
+    id: i64,
+) -> Result<(), String> {
+    if !state.delete(id)? {
+        return Err(format!("history item {} not found", id));
+    }
+    emit_changed(&app, HistoryChange::Deleted, vec![id]);
+    Ok(())
+}
+
+/// Deletes all history, optionally keeping pinned entries. Returns the
+/// number of deleted entries.
+///
+/// # Errors
+///
+/// Returns an error if the database fails; nothing is deleted in that case.
+#[tauri::command]
+pub fn clear_history(
+    app: AppHandle,
+    state: State<'_, HistoryState>,
+    keep_pinned: bool,
+) -> Result<usize, String> {
+    let count = state.clear(keep_pinned)?;
+    emit_changed(&app, HistoryChange::Cleared, Vec::new());
+    Ok(count)
+}
+
+/// Pastes a history entry's processed text into the app under the cursor.
+///
+/// Uses the same insertion path as a fresh dictation ([`crate::paste_text`]),
+/// including hiding our windows first so focus returns to the target app.
+/// The entry itself is not re-recorded; only its `last_used_at_ms` is bumped.
+///
+/// When pasting from the history window, the window is hidden first and the
+/// app that was in front when it opened is activated again, so the paste
+/// lands there.
+///
+/// # Errors
+///
+/// Returns [`HistoryError::NotFound`] if no entry has the given id, and
+/// [`HistoryError::Failed`] if the database or the paste fails.
+#[tauri::command]
+pub async fn paste_history_item(app: AppHandle, id: i64) -> Result<(), HistoryError> {
+    windows::leave_history_window(&app);
+    paste_item(&app, id).await
+}
+
+/// Pastes the entry with `id` and marks it used. Shared by every way of
+/// re-pasting history (the command, `wispr://paste-last`).
+pub(crate) async fn paste_item(app: &AppHandle, id: i64) -> Result<(), HistoryError> {
+    let state = app.state::<HistoryState>();
+    let item = state.get(id)?.ok_or(HistoryError::NotFound { id })?;
+
+    crate::paste_text(
+        app,
+        &item.processed_text,
+        None,
+        crate::WhenBusy::Queue,
+        true,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    // The entry may have been deleted while we were pasting; the paste
+    // already happened, so that's not worth reporting
+    if state.mark_used(id)? {
+        emit_changed(app, HistoryChange::Used, vec![id]);
+    }
+    Ok(())
+}
+
+fn set_pinned(app: &AppHandle, state: &HistoryState, id: i64, pinned: bool) -> Result<(), String> {
+    if !state.set_pinned(id, pinned)? {
+        return Err(format!("history item {} not found", id));
+    }
+    let change = if pinned {
+        HistoryChange::Pinned
+    } else {
+        HistoryChange::Unpinned
+    };
+    emit_changed(app, change, vec![id]);
+    Ok(())
+}
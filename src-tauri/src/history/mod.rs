@@ -0,0 +1,642 @@
+//! Transcript history backed by SQLite.
+//!
+//! Every successful dictation is recorded in `history.sqlite3` in the app
+//! data directory so past transcripts can be listed and reused.
+//!
+//! # Architecture Decision
+//!
+//! Schema changes are embedded as an ordered list of migrations and tracked
+//! with SQLite's `user_version` pragma. Opening the database applies any
+//! migrations newer than the file's version, so databases created by older
+//! builds pick up new columns instead of breaking.
+//!
+//! Writes happen on a blocking worker spawned after the paste completes; the
+//! paste path never waits on disk I/O.
+//!
+//! # Submodules
+//!
+//! - [`search`]: Full-text search over the FTS5 index
+//! - [`manage`]: Pinning, deletion and clearing
+//! - [`prune`]: Retention limits enforced by a background pruner
+//! - [`export`]: Streaming export to JSON, CSV and Markdown
+//! - [`stats`]: Usage statistics from per-day counters
+//! - [`summary`]: Weekly summaries, and their announcement
+//! - [`encryption`]: Optional SQLCipher encryption at rest
+//! - [`audio`]: Retention of dictation recordings
+//! - [`speakers`]: Speaker labels of meeting transcripts
+//!
+//! Every mutation emits a `history:changed` event so an open history window
+//! can refresh without polling.
+
+pub mod audio;
+pub mod encryption;
+pub mod export;
+pub mod manage;
+pub mod prune;
+pub mod search;
+pub mod speakers;
+pub mod stats;
+pub mod summary;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, TryLockError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use tauri::{Emitter, Manager, State};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::errors::{self, ErrorCode};
+use crate::frontmost;
+use crate::shutdown::PendingWork;
+use crate::AppHandle;
+
+/// File name of the database inside the app data directory.
+const DATABASE_FILE: &str = "history.sqlite3";
+
+/// Default and maximum page sizes for [`list_history`].
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+/// Schema migrations, applied in order. Entry `i` upgrades a database from
+/// `user_version = i` to `i + 1`. Never edit a released entry; append a new
+/// one instead.
+const MIGRATIONS: &[&str] = &[
+    // v1: initial schema
+    "CREATE TABLE history (
+        id             INTEGER PRIMARY KEY AUTOINCREMENT,
+        created_at_ms  INTEGER NOT NULL,
+        raw_text       TEXT    NOT NULL,
+        processed_text TEXT    NOT NULL,
+        target_app     TEXT,
+        provider       TEXT,
+        duration_ms    INTEGER,
+        word_count     INTEGER NOT NULL
+    );
+    CREATE INDEX history_created_at ON history (created_at_ms DESC);",
+    // v2: transcript language and full-text index. The FTS table uses the
+    // history table as external content and is kept in sync by triggers, so
+    // every insert/update/delete path (including pruning) maintains it
+    "ALTER TABLE history ADD COLUMN language TEXT;
+    CREATE VIRTUAL TABLE history_fts USING fts5(
+        processed_text,
+        raw_text,
+        content = 'history',
+        content_rowid = 'id',
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+        INSERT INTO history_fts (rowid, processed_text, raw_text)
+        VALUES (new.id, new.processed_text, new.raw_text);
+    END;
+    CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+        INSERT INTO history_fts (history_fts, rowid, processed_text, raw_text)
+        VALUES ('delete', old.id, old.processed_text, old.raw_text);
+    END;
+    CREATE TRIGGER history_fts_update AFTER UPDATE OF processed_text, raw_text ON history BEGIN
+        INSERT INTO history_fts (history_fts, rowid, processed_text, raw_text)
+        VALUES ('delete', old.id, old.processed_text, old.raw_text);
+        INSERT INTO history_fts (rowid, processed_text, raw_text)
+        VALUES (new.id, new.processed_text, new.raw_text);
+    END;
+    INSERT INTO history_fts (history_fts) VALUES ('rebuild');",
+    // v3: pinning and retained audio
+    "ALTER TABLE history ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE history ADD COLUMN audio_path TEXT;",
+    // v4: re-paste tracking
+    "ALTER TABLE history ADD COLUMN last_used_at_ms INTEGER;",
+    // v5: per-day usage counters behind the stats screen, backfilled from
+    // existing history
+    "CREATE TABLE daily_usage (
+        day         TEXT PRIMARY KEY,
+        dictations  INTEGER NOT NULL DEFAULT 0,
+        words       INTEGER NOT NULL DEFAULT 0,
+        timed_words INTEGER NOT NULL DEFAULT 0,
+        speech_ms   INTEGER NOT NULL DEFAULT 0
+    );
+    INSERT INTO daily_usage (day, dictations, words, timed_words, speech_ms)
+    SELECT date(created_at_ms / 1000, 'unixepoch', 'localtime'),
+           COUNT(*),
+           SUM(word_count),
+           SUM(CASE WHEN duration_ms IS NULL THEN 0 ELSE word_count END),
+           COALESCE(SUM(duration_ms), 0)
+    FROM history
+    GROUP BY 1;",
+    // v6: the dictation's session id
+    "ALTER TABLE history ADD COLUMN session_id TEXT;",
+    // v7: input level of the recording
+    "ALTER TABLE history ADD COLUMN peak_level REAL;",
+    // v8: transcript by speaker of diarized meetings, as JSON
+    "ALTER TABLE history ADD COLUMN speakers TEXT;",
+    // v9: the settings profile dictated with
+    "ALTER TABLE history ADD COLUMN profile TEXT;",
+];
+
+/// Columns selected for [`HistoryItem`], in field order. Queries alias the
+/// history table as `h`.
+const ITEM_COLUMNS: &str = "h.id, h.created_at_ms, h.raw_text, h.processed_text, h.target_app, \
+     h.provider, h.duration_ms, h.word_count, h.language, h.pinned, h.last_used_at_ms, \
+     h.session_id, h.peak_level, h.speakers, h.profile";
+
+/// Number of columns in [`ITEM_COLUMNS`]; extra selected columns start here.
+const ITEM_COLUMN_COUNT: usize = 15;
+
+/// A stored dictation.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryItem {
+    pub id: i64,
+    /// Unix timestamp in milliseconds.
+    pub created_at_ms: i64,
+    pub raw_text: String,
+    pub processed_text: String,
+    /// Bundle identifier of the app the text was pasted into.
+    pub target_app: Option<String>,
+    pub provider: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub word_count: i64,
+    /// Language code the transcript was dictated in (e.g. `en-US`).
+    pub language: Option<String>,
+    /// Pinned items are listed first and never pruned automatically.
+    pub pinned: bool,
+    /// When the entry was last re-pasted, Unix milliseconds.
+    pub last_used_at_ms: Option<i64>,
+    /// Session id of the dictation; `None` for entries from before they
+    /// were recorded.
+    pub session_id: Option<String>,
+    /// Highest level of the recording, 0 to 1; `None` for entries that
+    /// weren't recorded, or from before it was measured.
+    pub peak_level: Option<f64>,
+    /// The transcript by speaker, for meetings transcribed with speaker
+    /// labels (see [`speakers`]).
+    pub speakers: Option<speakers::SpeakerTranscript>,
+    /// The settings profile that was active (see
+    /// [`crate::settings::profiles`]).
+    pub profile: Option<String>,
+}
+
+impl HistoryItem {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            created_at_ms: row.get(1)?,
+            raw_text: row.get(2)?,
+            processed_text: row.get(3)?,
+            target_app: row.get(4)?,
+            provider: row.get(5)?,
+            duration_ms: row.get(6)?,
+            word_count: row.get(7)?,
+            language: row.get(8)?,
+            pinned: row.get(9)?,
+            last_used_at_ms: row.get(10)?,
+            session_id: row.get(11)?,
+            peak_level: row.get(12)?,
+            speakers: row
+                .get::<_, Option<String>>(13)?
+                .and_then(|json| serde_json::from_str(&json).ok()),
+            profile: row.get(14)?,
+        })
+    }
+}
+
+/// A dictation to be recorded.
+#[derive(Debug, Clone)]
+pub struct NewHistoryEntry {
+    pub raw_text: String,
+    pub processed_text: String,
+    pub target_app: Option<String>,
+    pub provider: Option<String>,
+    pub duration_ms: Option<u64>,
+    pub language: Option<String>,
+    /// Staged recording of the dictation, kept or deleted per the audio
+    /// retention policy once the entry is saved.
+    pub audio_path: Option<PathBuf>,
+    pub session_id: Option<String>,
+    /// Highest level of the recording, 0 to 1, if it was measured.
+    pub peak_level: Option<f32>,
+    /// The active settings profile, if any.
+    pub profile: Option<String>,
+}
+
+#[cfg(test)]
+impl NewHistoryEntry {
+    /// An entry with only its text, for tests.
+    pub(crate) fn with_text(text: &str) -> Self {
+        Self {
+            raw_text: text.to_string(),
+            processed_text: text.to_string(),
+            target_app: None,
+            provider: None,
+            duration_ms: None,
+            language: None,
+            audio_path: None,
+            session_id: None,
+            peak_level: None,
+            profile: None,
+        }
+    }
+}
+
+/// Error returned by history commands that callers need to tell apart,
+/// serialized with its `kind`, its [`code`](HistoryError::code) and its
+/// fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryError {
+    /// No entry has this id: it was never recorded or has been deleted.
+    NotFound { id: i64 },
+    /// The entry exists but its recording was not kept.
+    NotRetained { id: i64 },
+    /// Any other failure (database unavailable, query or paste failed).
+    Failed { message: String },
+}
+
+impl HistoryError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::NotFound { .. } => ErrorCode::HistoryNotFound,
+            Self::NotRetained { .. } => ErrorCode::HistoryNotRetained,
+            Self::Failed { .. } => ErrorCode::History,
+        }
+    }
+}
+
+impl Serialize for HistoryError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (kind, fields) = match self {
+            Self::NotFound { id } => ("not_found", json!({ "id": id })),
+            Self::NotRetained { id } => ("not_retained", json!({ "id": id })),
+            Self::Failed { message } => ("failed", json!({ "message": message })),
+        };
+        errors::serialize_error(serializer, kind, self.code(), fields)
+    }
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound { id } => write!(f, "history item {} not found", id),
+            Self::NotRetained { id } => write!(f, "audio for history item {} was not retained", id),
+            Self::Failed { message } => f.write_str(message),
+        }
+    }
+}
+
+impl From<String> for HistoryError {
+    fn from(message: String) -> Self {
+        Self::Failed { message }
+    }
+}
+
+/// Managed state wrapping the history database.
+///
+/// The connection is an error when the database couldn't be opened at
+/// startup (or is locked); dictation keeps working, only history commands
+/// fail, with that error.
+pub struct HistoryState {
+    /// Database file, kept so it can be reopened after encryption.
+    path: PathBuf,
+    connection: Mutex<Result<Connection, String>>,
+}
+
+impl HistoryState {
+    /// Opens (creating if needed) and migrates the database in `data_dir`,
+    /// unlocking it with the keychain key if it is encrypted.
+    ///
+    /// Failures are logged and produce an unavailable history rather than
+    /// aborting startup.
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(DATABASE_FILE);
+        let connection = std::fs::create_dir_all(data_dir)
+            .map_err(|e| e.to_string())
+            .and_then(|()| encryption::open(&path));
+
+        if let Err(e) = &connection {
+            tracing::warn!("History unavailable: {}", e);
+        }
+        Self {
+            path,
+            connection: Mutex::new(connection),
+        }
+    }
+
+    /// The history in `data_dir`, as [`open`](Self::open) would open it
+    /// unencrypted, without asking the keychain, for tests.
+    #[cfg(test)]
+    pub(crate) fn open_plaintext(data_dir: &Path) -> Self {
+        let path = data_dir.join(DATABASE_FILE);
+        let connection = open_database(&path, None).unwrap();
+        Self {
+            path,
+            connection: Mutex::new(Ok(connection)),
+        }
+    }
+
+    /// An empty, migrated history in memory, for tests.
+    #[cfg(test)]
+    pub(crate) fn in_memory() -> Self {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        Self {
+            path: PathBuf::new(),
+            connection: Mutex::new(Ok(connection)),
+        }
+    }
+
+    /// Whether the database is open, without waiting for it: a connection
+    /// busy with another query counts as open.
+    pub fn availability(&self) -> Result<(), String> {
+        match self.connection.try_lock() {
+            Ok(guard) => guard.as_ref().map(|_| ()).map_err(Clone::clone),
+            Err(TryLockError::WouldBlock) => Ok(()),
+            Err(TryLockError::Poisoned(_)) => Err("the database lock is poisoned".to_string()),
+        }
+    }
+
+    /// Runs `f` with the open connection.
+    fn with_connection<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, String> {
+        let guard = self.connection.lock().unwrap();
+        let connection = guard.as_ref().map_err(Clone::clone)?;
+        f(connection).map_err(|e| e.to_string())
+    }
+
+    /// Inserts a new entry and counts it in the usage stats, returning its
+    /// id.
+    pub fn insert(&self, entry: &NewHistoryEntry) -> Result<i64, String> {
+        let word_count = entry.processed_text.unicode_words().count() as i64;
+        let created_at_ms = now_ms();
+
+        self.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            transaction.execute(
+                "INSERT INTO history
+                    (created_at_ms, raw_text, processed_text, target_app, provider,
+                     duration_ms, word_count, language, session_id, peak_level, profile)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    created_at_ms,
+                    entry.raw_text,
+                    entry.processed_text,
+                    entry.target_app,
+                    entry.provider,
+                    entry.duration_ms.map(|ms| ms as i64),
+                    word_count,
+                    entry.language,
+                    entry.session_id,
+                    entry.peak_level.map(f64::from),
+                    entry.profile,
+                ],
+            )?;
+            let id = transaction.last_insert_rowid();
+            stats::record_usage(&transaction, created_at_ms, word_count, entry.duration_ms)?;
+            transaction.commit()?;
+            Ok(id)
+        })
+    }
+
+    /// Replaces the text and duration of entry `id`, one that keeps growing
+    /// after it is recorded (a meeting transcript). The words and time
+    /// added count towards today's usage, without another dictation.
+    pub fn update_text(&self, id: i64, text: &str, duration_ms: Option<u64>) -> Result<(), String> {
+        self.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            replace_text(&transaction, id, text, duration_ms)?;
+            transaction.commit()
+        })
+    }
+
+    /// Returns one page of entries, pinned first, then newest first.
+    pub fn list(&self, limit: u32, offset: u32) -> Result<Vec<HistoryItem>, String> {
+        self.with_connection(|connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM history h
+                 ORDER BY h.pinned DESC, h.created_at_ms DESC, h.id DESC
+                 LIMIT ?1 OFFSET ?2",
+                ITEM_COLUMNS
+            ))?;
+            let items = statement
+                .query_map(params![limit, offset], HistoryItem::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(items)
+        })
+    }
+
+    /// Returns a single entry by id.
+    pub fn get(&self, id: i64) -> Result<Option<HistoryItem>, String> {
+        self.with_connection(|connection| {
+            connection
+                .query_row(
+                    &format!("SELECT {} FROM history h WHERE h.id = ?1", ITEM_COLUMNS),
+                    params![id],
+                    HistoryItem::from_row,
+                )
+                .optional()
+        })
+    }
+
+    /// How many entries there are, and how many have a retained recording.
+    pub fn counts(&self) -> Result<(u64, u64), String> {
+        self.with_connection(|connection| {
+            connection.query_row(
+                "SELECT COUNT(*), COUNT(audio_path) FROM history",
+                [],
+                |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+            )
+        })
+    }
+
+    /// Returns the most recently recorded entry, pinned or not.
+    pub fn latest(&self) -> Result<Option<HistoryItem>, String> {
+        self.with_connection(|connection| {
+            connection
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM history h ORDER BY h.created_at_ms DESC, h.id DESC LIMIT 1",
+                        ITEM_COLUMNS
+                    ),
+                    [],
+                    HistoryItem::from_row,
+                )
+                .optional()
+        })
+    }
+}
+
+/// [`HistoryState::update_text`] within a transaction.
+fn replace_text(
+    connection: &Connection,
+    id: i64,
+    text: &str,
+    duration_ms: Option<u64>,
+) -> rusqlite::Result<()> {
+    let word_count = text.unicode_words().count() as i64;
+    let (old_words, old_duration_ms): (i64, Option<i64>) = connection.query_row(
+        "SELECT word_count, duration_ms FROM history WHERE id = ?1",
+        params![id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    connection.execute(
+        "UPDATE history
+         SET raw_text = ?2, processed_text = ?2, word_count = ?3, duration_ms = ?4
+         WHERE id = ?1",
+        params![id, text, word_count, duration_ms.map(|ms| ms as i64)],
+    )?;
+    let added_ms = duration_ms.map(|ms| (ms as i64 - old_duration_ms.unwrap_or(0)).max(0) as u64);
+    stats::add_usage(
+        connection,
+        now_ms(),
+        (word_count - old_words).max(0),
+        added_ms,
+    )
+}
+
+/// Records a dictation in the background.
+///
+/// Called after the paste has completed. When `entry.target_app` is unset it
+/// is resolved on the worker: right after the paste, the frontmost app is the
+/// one that received the text. Failures are logged; history must never
+/// surface as a dictation error.
+///
+/// When the storage policy keeps no transcripts (transcript saving or
+/// privacy mode turned off, see [`crate::privacy`]), only the usage
+/// counters are updated and any staged recording is deleted.
+pub fn spawn_record(app: AppHandle, mut entry: NewHistoryEntry) {
+    // Quitting waits for the write
+    let pending = PendingWork::start(&app);
+    tauri::async_runtime::spawn_blocking(move || {
+        let _pending = pending;
+        let history = app.state::<HistoryState>();
+        let policy = crate::privacy::policy();
+        if !policy.history {
+            manage::remove_audio_files(entry.audio_path.take().into_iter().collect());
+            if let Err(e) = history.record_usage_only(&entry) {
+                tracing::warn!("Failed to record usage: {}", e);
+            }
+            return;
+        }
+
+        if entry.target_app.is_none() {
+            entry.target_app = frontmost::frontmost_bundle_id();
+        }
+        let id = match history.insert(&entry) {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::warn!("Failed to record history: {}", e);
+                manage::remove_audio_files(entry.audio_path.into_iter().collect());
+                return;
+            }
+        };
+        match (&entry.audio_path, &entry.session_id) {
+            (Some(staged), _) => {
+                if let Err(e) = history.retain_audio(id, staged, policy.audio) {
+                    tracing::warn!("{}", e);
+                }
+            }
+            // Its recording may still be on its way (see `audio`)
+            (None, Some(session_id)) => {
+                audio::recorded_without_audio(&app, session_id, id, policy.audio)
+            }
+            (None, None) => {}
+        }
+        emit_changed(&app, HistoryChange::Added, vec![id]);
+    });
+}
+
+/// What happened to history, carried by the `history:changed` event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryChange {
+    Added,
+    Pinned,
+    Unpinned,
+    /// An entry's text changed (a meeting transcript growing).
+    Updated,
+    Deleted,
+    Cleared,
+    Pruned,
+    Used,
+}
+
+/// Payload of the `history:changed` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryChangedPayload {
+    pub change: HistoryChange,
+    /// Affected entry ids (empty for bulk changes like clearing).
+    pub ids: Vec<i64>,
+}
+
+/// Notifies the frontend that history changed.
+pub(crate) fn emit_changed(app: &AppHandle, change: HistoryChange, ids: Vec<i64>) {
+    let _ = app.emit("history:changed", HistoryChangedPayload { change, ids });
+}
+
+/// Lists history entries, pinned first, then newest first.
+///
+/// `limit` defaults to 50 and is capped at 500.
+///
+/// # Errors
+///
+/// Returns an error if the history database is unavailable or the query
+/// fails.
+#[tauri::command]
+pub fn list_history(
+    state: State<'_, HistoryState>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<Vec<HistoryItem>, String> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    state.list(limit, offset.unwrap_or(0))
+}
+
+/// Returns a single history entry.
+///
+/// # Errors
+///
+/// Returns an error if no entry has the given id, the history database is
+/// unavailable or the query fails.
+#[tauri::command]
+pub fn get_history_item(state: State<'_, HistoryState>, id: i64) -> Result<HistoryItem, String> {
+    state
+        .get(id)?
+        .ok_or_else(|| format!("history item {} not found", id))
+}
+
+/// Opens the database file, unlocks it with `key` if given, and brings its
+/// schema up to date.
+fn open_database(path: &Path, key: Option<&str>) -> Result<Connection, String> {
+    let mut connection = Connection::open(path).map_err(|e| e.to_string())?;
+    if let Some(key) = key {
+        encryption::apply_key(&connection, key).map_err(|e| e.to_string())?;
+    }
+    encryption::check_readable(&connection, key.is_some())?;
+    migrate(&mut connection).map_err(|e| e.to_string())?;
+    Ok(connection)
+}
+
+/// Applies every migration newer than the database's `user_version`.
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        // Each step commits together with its version bump, so a crash
+        // mid-upgrade never leaves a half-applied schema
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", (index + 1) as i64)?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Current time as Unix milliseconds.
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
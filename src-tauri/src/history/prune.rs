@@ -0,0 +1,299 @@
+//! Automatic history pruning by age, item count and audio size.
+//!
+//! A background thread prunes at startup and then once a day; the settings
+//! UI can also trigger a run with [`prune_history_now`]. Each run deletes the
+//! oldest unpinned entries (and their audio) until history is back within
+//! every configured limit.
+//!
+//! # Architecture Decision
+//!
+//! Pinned entries are never pruned. When pinned content alone exceeds a
+//! limit, the run emits a `history:retention_warning` event and leaves it in
+//! place: the user asked to keep those entries, so deleting them would be
+//! worse than going over a soft limit.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager, State};
+
+use super::audio::AudioRetention;
+use super::manage::remove_audio_files;
+use super::{emit_changed, now_ms, HistoryChange, HistoryState};
+use crate::settings;
+use crate::AppHandle;
+
+/// How often the background pruner runs after the startup pass.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const MS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// Retention limits. `None` disables a limit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Whether transcripts are stored at all. When off, dictations only
+    /// update the aggregate usage counters.
+    pub save_transcripts: bool,
+    /// Entries older than this many days are deleted.
+    pub max_age_days: Option<u32>,
+    /// Maximum number of entries kept.
+    pub max_items: Option<u64>,
+    /// Maximum total size of retained audio files.
+    pub max_audio_bytes: Option<u64>,
+    /// How long recordings are kept after transcription.
+    pub audio: AudioRetention,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            save_transcripts: true,
+            max_age_days: Some(90),
+            max_items: Some(10_000),
+            max_audio_bytes: Some(1024 * 1024 * 1024),
+            audio: AudioRetention::None,
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// Rejects zero limits, which would delete all unpinned history. Use
+    /// `None` to disable a limit instead.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_age_days == Some(0) {
+            return Err("max_age_days must be at least 1 (use null to disable)".to_string());
+        }
+        if self.max_items == Some(0) {
+            return Err("max_items must be at least 1 (use null to disable)".to_string());
+        }
+        if self.max_audio_bytes == Some(0) {
+            return Err("max_audio_bytes must be at least 1 (use null to disable)".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Managed state holding the retention limits.
+#[derive(Default)]
+pub struct RetentionState(pub Mutex<RetentionConfig>);
+
+/// Outcome of one pruning run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PruneReport {
+    /// Recordings deleted by the audio retention policy (their entries are
+    /// kept).
+    pub expired_recordings: u64,
+    /// Ids of the deleted entries.
+    pub removed_ids: Vec<i64>,
+    /// Total size of the deleted entries' audio files.
+    pub freed_audio_bytes: u64,
+    pub remaining_items: u64,
+    pub remaining_audio_bytes: u64,
+    /// Whether pinned entries alone still exceed the item limit.
+    pub pinned_over_item_limit: bool,
+    /// Whether pinned entries alone still exceed the audio size limit.
+    pub pinned_over_audio_limit: bool,
+}
+
+/// Payload of the `history:retention_warning` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionWarning {
+    pub over_item_limit: bool,
+    pub over_audio_limit: bool,
+    pub remaining_items: u64,
+    pub remaining_audio_bytes: u64,
+}
+
+/// An unpinned entry that may be pruned.
+struct Candidate {
+    id: i64,
+    created_at_ms: i64,
+    audio_path: Option<PathBuf>,
+    audio_bytes: u64,
+}
+
+impl HistoryState {
+    /// Deletes the oldest unpinned entries until history is within `config`.
+    ///
+    /// Rows are deleted in one transaction; audio files are removed after it
+    /// commits.
+    pub fn prune(&self, config: &RetentionConfig) -> Result<PruneReport, String> {
+        let cutoff_ms = config
+            .max_age_days
+            .map(|days| now_ms() - i64::from(days) * MS_PER_DAY);
+
+        let (report, audio) = self.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+
+            let count: i64 =
+                transaction.query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))?;
+            let mut remaining_items = count as u64;
+            let mut remaining_audio_bytes = 0;
+            let mut candidates = Vec::new();
+            {
+                let mut statement = transaction.prepare(
+                    "SELECT id, created_at_ms, audio_path, pinned FROM history
+                     ORDER BY created_at_ms ASC, id ASC",
+                )?;
+                let mut rows = statement.query([])?;
+                while let Some(row) = rows.next()? {
+                    let audio_path: Option<PathBuf> =
+                        row.get::<_, Option<String>>(2)?.map(PathBuf::from);
+                    let audio_bytes = audio_path.as_deref().map_or(0, file_size);
+                    remaining_audio_bytes += audio_bytes;
+
+                    let pinned: bool = row.get(3)?;
+                    if !pinned {
+                        candidates.push(Candidate {
+                            id: row.get(0)?,
+                            created_at_ms: row.get(1)?,
+                            audio_path,
+                            audio_bytes,
+                        });
+                    }
+                }
+            }
+
+            let mut report = PruneReport::default();
+            let mut audio = Vec::new();
+            {
+                let mut delete = transaction.prepare("DELETE FROM history WHERE id = ?1")?;
+                for candidate in candidates {
+                    let expired = cutoff_ms.is_some_and(|cutoff| candidate.created_at_ms < cutoff);
+                    let over_items = config.max_items.is_some_and(|max| remaining_items > max);
+                    let over_audio = config
+                        .max_audio_bytes
+                        .is_some_and(|max| remaining_audio_bytes > max);
+
+                    if !expired && !over_items && !over_audio {
+                        // Candidates are oldest first and totals only shrink,
+                        // so nothing later can be over a limit either
+                        break;
+                    }
+                    // Entries without audio don't help with the size limit
+                    if !expired && !over_items && candidate.audio_bytes == 0 {
+                        continue;
+                    }
+
+                    delete.execute(params![candidate.id])?;
+                    remaining_items -= 1;
+                    remaining_audio_bytes -= candidate.audio_bytes;
+                    report.freed_audio_bytes += candidate.audio_bytes;
+                    report.removed_ids.push(candidate.id);
+                    audio.extend(candidate.audio_path);
+                }
+            }
+            transaction.commit()?;
+
+            report.remaining_items = remaining_items;
+            report.remaining_audio_bytes = remaining_audio_bytes;
+            report.pinned_over_item_limit =
+                config.max_items.is_some_and(|max| remaining_items > max);
+            report.pinned_over_audio_limit = config
+                .max_audio_bytes
+                .is_some_and(|max| remaining_audio_bytes > max);
+            Ok((report, audio))
+        })?;
+
+        remove_audio_files(audio);
+        Ok(report)
+    }
+}
+
+/// Starts the background pruner: one pass now, then one per day.
+///
+/// Runs on its own thread so startup and the UI never wait on it; a panic
+/// restarts it (see [`crate::crash::supervise`]).
+pub fn spawn_pruner(app: AppHandle) {
+    let spawned = crate::crash::supervise(&app.clone(), "history-pruner", move || loop {
+        if let Err(e) = run_prune(&app) {
+            tracing::warn!("History pruning failed: {}", e);
+        }
+        std::thread::sleep(PRUNE_INTERVAL);
+    });
+
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to start history pruner: {}", e);
+    }
+}
+
+/// Prunes with the current limits, then logs and emits the outcome.
+fn run_prune(app: &AppHandle) -> Result<PruneReport, String> {
+    let config = app.state::<RetentionState>().0.lock().unwrap().clone();
+    let history = app.state::<HistoryState>();
+    // Expire recordings first so the size limit only deletes entries when
+    // the audio policy alone wasn't enough. Privacy mode overrides it
+    let expired_recordings = history.apply_audio_retention(crate::privacy::policy().audio)?;
+    let mut report = history.prune(&config)?;
+    report.expired_recordings = expired_recordings;
+
+    tracing::info!("History pruned: removed {} entries and {} recordings, freed {} audio bytes ({} entries, {} audio bytes kept)", report.removed_ids.len(),
+        report.expired_recordings,
+        report.freed_audio_bytes,
+        report.remaining_items,
+        report.remaining_audio_bytes
+    );
+
+    if !report.removed_ids.is_empty() {
+        emit_changed(app, HistoryChange::Pruned, report.removed_ids.clone());
+    }
+
+    if report.pinned_over_item_limit || report.pinned_over_audio_limit {
+        tracing::warn!(
+            "Pinned history alone exceeds the retention limits; nothing pinned was deleted"
+        );
+        let _ = app.emit(
+            "history:retention_warning",
+            RetentionWarning {
+                over_item_limit: report.pinned_over_item_limit,
+                over_audio_limit: report.pinned_over_audio_limit,
+                remaining_items: report.remaining_items,
+                remaining_audio_bytes: report.remaining_audio_bytes,
+            },
+        );
+    }
+
+    Ok(report)
+}
+
+/// Size of a retained audio file, or 0 if it no longer exists.
+fn file_size(path: &std::path::Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+/// Returns the current retention limits.
+#[tauri::command]
+pub fn get_retention_config(state: State<'_, RetentionState>) -> RetentionConfig {
+    state.0.lock().unwrap().clone()
+}
+
+/// Replaces the retention limits. They take effect on the next pruning run.
+///
+/// # Errors
+///
+/// Returns an error if a limit is zero or the settings cannot be saved.
+#[tauri::command]
+pub fn set_retention_config(app: AppHandle, config: RetentionConfig) -> Result<(), String> {
+    settings::update(&app, |settings| {
+        settings.retention = config;
+        Ok(())
+    })
+    .map(|_| ())
+}
+
+/// Prunes history immediately with the current limits.
+///
+/// # Errors
+///
+/// Returns an error if the history database is unavailable or the deletion
+/// fails; nothing is deleted in that case.
+#[tauri::command]
+pub async fn prune_history_now(app: AppHandle) -> Result<PruneReport, String> {
+    tauri::async_runtime::spawn_blocking(move || run_prune(&app))
+        .await
+        .map_err(|e| e.to_string())?
+}
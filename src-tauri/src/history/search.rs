@@ -0,0 +1,342 @@
+//! Full-text search over transcript history.
+//!
+//! Queries run against the `history_fts` FTS5 index (kept in sync with the
+//! history table by triggers) and can be narrowed by date range, target app
+//! and language. Results are ranked by BM25 relevance with newer entries
+//! winning ties.
+//!
+//! # Architecture Decision
+//!
+//! User input is never passed to FTS5 as query syntax. Each whitespace
+//! separated term is quoted as a literal phrase, so input like `Q3 "budget`
+//! or `NOT AND` searches for those words instead of producing a syntax error.
+//! The last term is a prefix match so results update while typing.
+
+use rusqlite::params_from_iter;
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::{
+    HistoryItem, HistoryState, DEFAULT_PAGE_SIZE, ITEM_COLUMNS, ITEM_COLUMN_COUNT, MAX_PAGE_SIZE,
+};
+
+/// Markers wrapped around matches by FTS5's `snippet()`. Control characters
+/// never appear in transcripts, so they can be stripped unambiguously.
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+/// Maximum number of tokens in a result snippet.
+const SNIPPET_TOKENS: u32 = 24;
+
+/// Optional filters shared by search (and anything else that selects a
+/// subset of history).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HistoryFilter {
+    /// Inclusive lower bound, Unix milliseconds.
+    pub from_ms: Option<i64>,
+    /// Exclusive upper bound, Unix milliseconds.
+    pub to_ms: Option<i64>,
+    /// Exact bundle identifier of the target app.
+    pub target_app: Option<String>,
+    /// Exact language code (e.g. `en-US`).
+    pub language: Option<String>,
+}
+
+impl HistoryFilter {
+    /// Appends SQL conditions for the set filters to `conditions`, pushing
+    /// their values onto `values`. Columns are referenced via the `h` alias.
+    pub(crate) fn append_sql(&self, conditions: &mut Vec<String>, values: &mut Vec<Value>) {
+        if let Some(from_ms) = self.from_ms {
+            values.push(Value::Integer(from_ms));
+            conditions.push(format!("h.created_at_ms >= ?{}", values.len()));
+        }
+        if let Some(to_ms) = self.to_ms {
+            values.push(Value::Integer(to_ms));
+            conditions.push(format!("h.created_at_ms < ?{}", values.len()));
+        }
+        if let Some(target_app) = &self.target_app {
+            values.push(Value::Text(target_app.clone()));
+            conditions.push(format!("h.target_app = ?{}", values.len()));
+        }
+        if let Some(language) = &self.language {
+            values.push(Value::Text(language.clone()));
+            conditions.push(format!("h.language = ?{}", values.len()));
+        }
+    }
+}
+
+/// A highlighted match inside [`SearchHit::snippet`], as a half-open range
+/// of UTF-16 code units so the frontend can slice the JS string directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct HighlightRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// One search result.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub item: HistoryItem,
+    /// Excerpt of the processed text around the matches.
+    pub snippet: String,
+    pub highlights: Vec<HighlightRange>,
+}
+
+impl HistoryState {
+    /// Searches history. An empty query returns the filtered entries, newest
+    /// first, without highlights.
+    pub fn search(
+        &self,
+        query: &str,
+        filter: &HistoryFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<SearchHit>, String> {
+        let match_expression = to_match_expression(query);
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+
+        let sql = match &match_expression {
+            Some(expression) => {
+                values.push(Value::Text(expression.clone()));
+                conditions.push(format!("history_fts MATCH ?{}", values.len()));
+                filter.append_sql(&mut conditions, &mut values);
+                format!(
+                    "SELECT {}, snippet(history_fts, 0, '{}', '{}', '…', {})
+                     FROM history_fts
+                     JOIN history h ON h.id = history_fts.rowid
+                     WHERE {}
+                     ORDER BY bm25(history_fts), h.created_at_ms DESC",
+                    ITEM_COLUMNS,
+                    MATCH_START,
+                    MATCH_END,
+                    SNIPPET_TOKENS,
+                    conditions.join(" AND ")
+                )
+            }
+            None => {
+                filter.append_sql(&mut conditions, &mut values);
+                let where_clause = if conditions.is_empty() {
+                    String::new()
+                } else {
+                    format!("WHERE {}", conditions.join(" AND "))
+                };
+                format!(
+                    "SELECT {}, h.processed_text FROM history h {}
+                     ORDER BY h.created_at_ms DESC, h.id DESC",
+                    ITEM_COLUMNS, where_clause
+                )
+            }
+        };
+
+        values.push(Value::Integer(limit.into()));
+        values.push(Value::Integer(offset.into()));
+        let sql = format!(
+            "{} LIMIT ?{} OFFSET ?{}",
+            sql,
+            values.len() - 1,
+            values.len()
+        );
+
+        self.with_connection(|connection| {
+            let mut statement = connection.prepare(&sql)?;
+            let hits = statement
+                .query_map(params_from_iter(values.iter()), |row| {
+                    let item = HistoryItem::from_row(row)?;
+                    let marked: String = row.get(ITEM_COLUMN_COUNT)?;
+                    let (snippet, highlights) = strip_match_markers(&marked);
+                    Ok(SearchHit {
+                        item,
+                        snippet,
+                        highlights,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(hits)
+        })
+    }
+}
+
+/// Searches history by free text plus optional filters.
+///
+/// Input is treated as literal terms (all must match); the last term also
+/// matches as a prefix. An empty query lists the filtered entries.
+///
+/// # Errors
+///
+/// Returns an error if the history database is unavailable or the query
+/// fails.
+#[tauri::command]
+pub fn search_history(
+    state: State<'_, HistoryState>,
+    query: String,
+    filters: Option<HistoryFilter>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<Vec<SearchHit>, String> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    state.search(
+        &query,
+        &filters.unwrap_or_default(),
+        limit,
+        offset.unwrap_or(0),
+    )
+}
+
+/// Converts user input into a safe FTS5 match expression, or `None` when the
+/// input has no searchable terms.
+///
+/// Every term becomes a quoted phrase with embedded quotes doubled, which
+/// disables all FTS5 operators (`AND`, `NEAR`, `*`, `:` column filters...).
+fn to_match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .filter(|quoted| quoted.trim_matches('"').chars().any(char::is_alphanumeric))
+        .collect();
+
+    if terms.is_empty() {
+        return None;
+    }
+
+    Some(format!("{}*", terms.join(" ")))
+}
+
+/// Removes the snippet match markers, returning the clean text and the
+/// highlighted ranges in UTF-16 code units.
+fn strip_match_markers(marked: &str) -> (String, Vec<HighlightRange>) {
+    let mut text = String::with_capacity(marked.len());
+    let mut highlights = Vec::new();
+    let mut position = 0;
+    let mut open = None;
+
+    for c in marked.chars() {
+        match c {
+            MATCH_START => open = Some(position),
+            MATCH_END => {
+                if let Some(start) = open.take() {
+                    highlights.push(HighlightRange {
+                        start,
+                        end: position,
+                    });
+                }
+            }
+            _ => {
+                text.push(c);
+                position += c.len_utf16();
+            }
+        }
+    }
+
+    (text, highlights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prune::RetentionConfig;
+    use super::super::NewHistoryEntry;
+    use super::*;
+
+    fn history(texts: &[&str]) -> (HistoryState, Vec<i64>) {
+        let history = HistoryState::in_memory();
+        let ids = texts
+            .iter()
+            .map(|text| history.insert(&NewHistoryEntry::with_text(text)).unwrap())
+            .collect();
+        (history, ids)
+    }
+
+    fn found(history: &HistoryState, query: &str) -> Vec<i64> {
+        history
+            .search(query, &HistoryFilter::default(), 50, 0)
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.item.id)
+            .collect()
+    }
+
+    /// Fails if the index doesn't match the history table.
+    fn assert_index_in_sync(history: &HistoryState) {
+        history
+            .with_connection(|connection| {
+                connection.execute(
+                    "INSERT INTO history_fts (history_fts, rank) VALUES ('integrity-check', 1)",
+                    [],
+                )
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn deleted_entries_leave_the_index() {
+        let (history, ids) = history(&["quarterly budget review", "budget for lunch"]);
+        assert_eq!(found(&history, "budget").len(), 2);
+
+        assert!(history.delete(ids[0]).unwrap());
+        assert_eq!(found(&history, "budget"), vec![ids[1]]);
+        assert!(found(&history, "quarterly").is_empty());
+        assert_index_in_sync(&history);
+    }
+
+    #[test]
+    fn cleared_entries_leave_the_index() {
+        let (history, ids) = history(&["pinned budget", "unpinned budget"]);
+        history.set_pinned(ids[0], true).unwrap();
+
+        assert_eq!(history.clear(true).unwrap(), 1);
+        assert_eq!(found(&history, "budget"), vec![ids[0]]);
+        assert_index_in_sync(&history);
+    }
+
+    #[test]
+    fn pruned_entries_leave_the_index() {
+        let (history, ids) = history(&["oldest budget", "middle budget", "newest budget"]);
+        let config = RetentionConfig {
+            max_items: Some(1),
+            ..RetentionConfig::default()
+        };
+
+        let report = history.prune(&config).unwrap();
+        assert_eq!(report.removed_ids, vec![ids[0], ids[1]]);
+        assert_eq!(found(&history, "budget"), vec![ids[2]]);
+        assert!(found(&history, "oldest").is_empty());
+        assert_index_in_sync(&history);
+    }
+
+    #[test]
+    fn updated_text_is_reindexed() {
+        let (history, ids) = history(&["first draft"]);
+
+        history.update_text(ids[0], "final version", None).unwrap();
+        assert!(found(&history, "draft").is_empty());
+        assert_eq!(found(&history, "final"), vec![ids[0]]);
+        assert_index_in_sync(&history);
+    }
+
+    #[test]
+    fn query_syntax_is_searched_literally() {
+        let (history, ids) = history(&["Q3 \"budget\" is NOT final"]);
+
+        assert_eq!(found(&history, "Q3 \"budget"), vec![ids[0]]);
+        assert_eq!(found(&history, "NOT AND"), Vec::<i64>::new());
+        assert_eq!(found(&history, "fin"), vec![ids[0]]);
+    }
+
+    #[test]
+    fn match_expressions_quote_every_term() {
+        assert_eq!(
+            to_match_expression("say \"hi\" NEAR"),
+            Some("\"say\" \"\"\"hi\"\"\" \"NEAR\"*".to_string())
+        );
+        assert_eq!(to_match_expression("  \" * "), None);
+    }
+
+    #[test]
+    fn match_markers_become_utf16_ranges() {
+        let marked = format!("😀 {}budget{} review", MATCH_START, MATCH_END);
+        let (text, highlights) = strip_match_markers(&marked);
+        assert_eq!(text, "😀 budget review");
+        assert_eq!(highlights, vec![HighlightRange { start: 3, end: 9 }]);
+    }
+}
@@ -0,0 +1,197 @@
+//! Speaker labels of meeting transcripts.
+//!
+//! A meeting transcribed with speaker diarization keeps its transcript by
+//! speaker next to the text, as JSON in the `speakers` column
+//! ([`SpeakerTranscript`]). The text is rendered from it, one `[hh:mm:ss]
+//! Speaker 1: ...` paragraph per turn, so renaming a speaker
+//! ([`update_speaker_label`]) rewrites every paragraph they spoke, and
+//! exports show the names given. Entries without it (dictations, meetings
+//! without diarization) have no speakers to rename.
+
+use std::collections::BTreeMap;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{emit_changed, replace_text, HistoryChange, HistoryError, HistoryState};
+
+use crate::AppHandle;
+
+/// Longest name a speaker can be given, in characters.
+const MAX_NAME_LEN: usize = 100;
+
+/// A transcript by speaker.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpeakerTranscript {
+    /// Paragraph above the others, e.g. the meeting's date and length.
+    pub heading: Option<String>,
+    pub paragraphs: Vec<Paragraph>,
+    /// Names given to speakers, by number; the others are "Speaker n".
+    pub names: BTreeMap<u32, String>,
+}
+
+/// A turn of one speaker, or a stretch nobody was told apart in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Paragraph {
+    /// Where it starts in the recording.
+    pub start_ms: u64,
+    /// Speaker number, from 1; `None` if unlabeled.
+    pub speaker: Option<u32>,
+    pub text: String,
+}
+
+impl SpeakerTranscript {
+    /// Whether any paragraph has a speaker.
+    pub fn has_speakers(&self) -> bool {
+        self.paragraphs
+            .iter()
+            .any(|paragraph| paragraph.speaker.is_some())
+    }
+
+    pub fn label(&self, speaker: u32) -> String {
+        self.names
+            .get(&speaker)
+            .cloned()
+            .unwrap_or_else(|| format!("Speaker {}", speaker))
+    }
+
+    /// The text: the heading, then one `[hh:mm:ss]` paragraph each,
+    /// prefixed with its speaker's label.
+    pub fn render(&self) -> String {
+        let mut paragraphs: Vec<String> = self.heading.iter().cloned().collect();
+        for paragraph in &self.paragraphs {
+            let start = timestamp(paragraph.start_ms);
+            paragraphs.push(match paragraph.speaker {
+                Some(speaker) => format!("[{}] {}: {}", start, self.label(speaker), paragraph.text),
+                None => format!("[{}] {}", start, paragraph.text),
+            });
+        }
+        paragraphs.join("\n\n")
+    }
+}
+
+/// `ms` as `hh:mm:ss`.
+pub fn timestamp(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// The stored transcript of entry `id`: `None` if there is no such entry,
+/// `Some(None)` if it has no speakers.
+fn stored(connection: &Connection, id: i64) -> rusqlite::Result<Option<Option<SpeakerTranscript>>> {
+    let speakers: Option<Option<String>> = connection
+        .query_row(
+            "SELECT speakers FROM history WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(speakers.map(|json| json.and_then(|json| serde_json::from_str(&json).ok())))
+}
+
+impl HistoryState {
+    /// Replaces the text and duration of entry `id` with `transcript`, as
+    /// [`HistoryState::update_text`] does, keeping the names its speakers
+    /// were given. A transcript without speakers is stored as plain text.
+    pub fn update_transcript(
+        &self,
+        id: i64,
+        transcript: &SpeakerTranscript,
+        duration_ms: Option<u64>,
+    ) -> Result<(), String> {
+        self.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            let mut transcript = transcript.clone();
+            if let Some(Some(stored)) = stored(&transaction, id)? {
+                transcript.names.extend(stored.names);
+            }
+            replace_text(&transaction, id, &transcript.render(), duration_ms)?;
+            let json = transcript
+                .has_speakers()
+                .then(|| serde_json::to_string(&transcript).ok())
+                .flatten();
+            transaction.execute(
+                "UPDATE history SET speakers = ?2 WHERE id = ?1",
+                params![id, json],
+            )?;
+            transaction.commit()
+        })
+    }
+
+    /// Names `speaker` of entry `id` `name`, or back to "Speaker n" if it
+    /// is empty, and rewrites the text with it.
+    pub fn rename_speaker(&self, id: i64, speaker: u32, name: &str) -> Result<(), HistoryError> {
+        let name = name.trim();
+        if name.chars().count() > MAX_NAME_LEN {
+            return Err(format!("speaker names are at most {} characters", MAX_NAME_LEN).into());
+        }
+        self.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            let mut transcript = match stored(&transaction, id)? {
+                None => return Ok(Err(HistoryError::NotFound { id })),
+                Some(None) => {
+                    return Ok(Err(
+                        format!("history item {} has no speaker labels", id).into()
+                    ))
+                }
+                Some(Some(transcript)) => transcript,
+            };
+            if !transcript
+                .paragraphs
+                .iter()
+                .any(|paragraph| paragraph.speaker == Some(speaker))
+            {
+                return Ok(Err(format!(
+                    "history item {} has no speaker {}",
+                    id, speaker
+                )
+                .into()));
+            }
+
+            if name.is_empty() {
+                transcript.names.remove(&speaker);
+            } else {
+                transcript.names.insert(speaker, name.to_string());
+            }
+            let text = transcript.render();
+            let json = serde_json::to_string(&transcript).ok();
+            // A relabeling, not new words: usage stays as it is
+            transaction.execute(
+                "UPDATE history
+                 SET raw_text = ?2, processed_text = ?2, word_count = ?3, speakers = ?4
+                 WHERE id = ?1",
+                params![id, text, text.unicode_words().count() as i64, json],
+            )?;
+            transaction.commit()?;
+            Ok(Ok(()))
+        })?
+    }
+}
+
+/// Names `speaker` of the history entry `history_id` `name` (an empty name
+/// resets it to "Speaker n"), rewriting its transcript.
+///
+/// # Errors
+///
+/// Returns [`HistoryError::NotFound`] if no entry has the id, and an error
+/// if it has no such speaker or the database fails.
+#[tauri::command]
+pub fn update_speaker_label(
+    app: AppHandle,
+    state: State<'_, HistoryState>,
+    history_id: i64,
+    speaker: u32,
+    name: String,
+) -> Result<(), HistoryError> {
+    state.rename_speaker(history_id, speaker, &name)?;
+    tracing::info!(history_id, speaker, "Speaker renamed");
+    emit_changed(&app, HistoryChange::Updated, vec![history_id]);
+    Ok(())
+}
@@ -0,0 +1,299 @@
+//! Usage statistics: words dictated, speaking speed, time saved and streaks.
+//!
+//! # Architecture Decision
+//!
+//! Statistics are read from a `daily_usage` table of per-day counters (local
+//! dates) rather than aggregated over the history table on every request.
+//! The counters are bumped in the same transaction that records a dictation,
+//! so opening the stats screen reads at most one row per day.
+//!
+//! The counters also outlive the transcripts they came from: pruning or
+//! deleting history doesn't rewrite past stats, and with transcript saving
+//! turned off only the counters are updated, without storing any text.
+
+use std::sync::Mutex;
+
+use chrono::{Duration, Local, NaiveDate};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::summary::SummarySchedule;
+use super::{now_ms, HistoryState, NewHistoryEntry};
+use crate::settings;
+use crate::AppHandle;
+
+/// Local-date format stored in `daily_usage.day`.
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+/// Configuration for derived statistics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsConfig {
+    /// Typing speed used to estimate time saved, in words per minute.
+    pub baseline_wpm: u32,
+    /// When to announce last week's summary (`summary:ready`); `None`
+    /// never does.
+    pub weekly_summary: Option<SummarySchedule>,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            baseline_wpm: 40,
+            weekly_summary: None,
+        }
+    }
+}
+
+/// Managed state holding the stats configuration.
+#[derive(Default)]
+pub struct StatsState(pub Mutex<StatsConfig>);
+
+/// Time window for [`get_usage_stats`], ending today.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsPeriod {
+    Today,
+    /// The last 7 days, including today.
+    Week,
+    /// The last 30 days, including today.
+    Month,
+    /// The last 365 days, including today.
+    Year,
+    All,
+}
+
+impl StatsPeriod {
+    /// Number of days in the window, or `None` for all time.
+    fn days(self) -> Option<i64> {
+        match self {
+            Self::Today => Some(1),
+            Self::Week => Some(7),
+            Self::Month => Some(30),
+            Self::Year => Some(365),
+            Self::All => None,
+        }
+    }
+}
+
+/// Counters for one local day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DailyUsage {
+    /// Local date, `YYYY-MM-DD`.
+    pub day: String,
+    pub dictations: u64,
+    pub words: u64,
+    pub speech_ms: u64,
+}
+
+/// Summary returned by [`get_usage_stats`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageStats {
+    pub total_words: u64,
+    pub total_dictations: u64,
+    /// Average speaking speed over dictations with a known duration.
+    pub words_per_minute: Option<f64>,
+    /// Estimated typing time at the baseline speed minus time spent speaking.
+    pub time_saved_ms: u64,
+    pub baseline_wpm: u32,
+    /// Consecutive days with at least one dictation, ending today (or
+    /// yesterday, if nothing has been dictated yet today).
+    pub streak_days: u32,
+    /// Per-day breakdown for charting, oldest first. Days without usage are
+    /// omitted.
+    pub days: Vec<DailyUsage>,
+}
+
+impl HistoryState {
+    /// Updates the usage counters for a dictation without storing its text.
+    pub fn record_usage_only(&self, entry: &NewHistoryEntry) -> Result<(), String> {
+        let words = entry.processed_text.unicode_words().count() as i64;
+        self.with_connection(|connection| {
+            record_usage(connection, now_ms(), words, entry.duration_ms)
+        })
+    }
+
+    /// Computes usage statistics for `period`.
+    pub fn usage_stats(
+        &self,
+        period: StatsPeriod,
+        config: &StatsConfig,
+    ) -> Result<UsageStats, String> {
+        let today = Local::now().date_naive();
+        let since = period.days().map(|days| {
+            (today - Duration::days(days - 1))
+                .format(DAY_FORMAT)
+                .to_string()
+        });
+
+        let (totals, days, active_days) = self.with_connection(|connection| {
+            let totals = connection.query_row(
+                "SELECT COALESCE(SUM(dictations), 0), COALESCE(SUM(words), 0),
+                        COALESCE(SUM(timed_words), 0), COALESCE(SUM(speech_ms), 0)
+                 FROM daily_usage WHERE ?1 IS NULL OR day >= ?1",
+                params![since],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)? as u64,
+                        row.get::<_, i64>(1)? as u64,
+                        row.get::<_, i64>(2)? as u64,
+                        row.get::<_, i64>(3)? as u64,
+                    ))
+                },
+            )?;
+
+            let mut statement = connection.prepare(
+                "SELECT day, dictations, words, speech_ms FROM daily_usage
+                 WHERE ?1 IS NULL OR day >= ?1 ORDER BY day ASC",
+            )?;
+            let days = statement
+                .query_map(params![since], |row| {
+                    Ok(DailyUsage {
+                        day: row.get(0)?,
+                        dictations: row.get::<_, i64>(1)? as u64,
+                        words: row.get::<_, i64>(2)? as u64,
+                        speech_ms: row.get::<_, i64>(3)? as u64,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            // Streaks ignore the period: they always count back from today
+            let mut statement = connection
+                .prepare("SELECT day FROM daily_usage WHERE dictations > 0 ORDER BY day DESC")?;
+            let active_days = statement
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok((totals, days, active_days))
+        })?;
+
+        let (dictations, words, timed_words, speech_ms) = totals;
+        let words_per_minute =
+            (speech_ms > 0).then(|| timed_words as f64 / (speech_ms as f64 / 60_000.0));
+        let typing_ms = words * 60_000 / u64::from(config.baseline_wpm.max(1));
+
+        Ok(UsageStats {
+            total_words: words,
+            total_dictations: dictations,
+            words_per_minute,
+            time_saved_ms: typing_ms.saturating_sub(speech_ms),
+            baseline_wpm: config.baseline_wpm,
+            streak_days: streak(today, &active_days),
+            days,
+        })
+    }
+}
+
+/// Bumps the counters for the local day of `at_ms`.
+pub(super) fn record_usage(
+    connection: &Connection,
+    at_ms: i64,
+    words: i64,
+    duration_ms: Option<u64>,
+) -> rusqlite::Result<()> {
+    bump_usage(connection, at_ms, 1, words, duration_ms)
+}
+
+/// Adds words and time to an entry already counted, on the local day of
+/// `at_ms`, without counting another dictation.
+pub(super) fn add_usage(
+    connection: &Connection,
+    at_ms: i64,
+    words: i64,
+    duration_ms: Option<u64>,
+) -> rusqlite::Result<()> {
+    bump_usage(connection, at_ms, 0, words, duration_ms)
+}
+
+fn bump_usage(
+    connection: &Connection,
+    at_ms: i64,
+    dictations: i64,
+    words: i64,
+    duration_ms: Option<u64>,
+) -> rusqlite::Result<()> {
+    // Words only count towards speaking speed when we know how long they took
+    let timed_words = if duration_ms.is_some() { words } else { 0 };
+
+    connection.execute(
+        "INSERT INTO daily_usage (day, dictations, words, timed_words, speech_ms)
+         VALUES (date(?1 / 1000, 'unixepoch', 'localtime'), ?2, ?3, ?4, ?5)
+         ON CONFLICT (day) DO UPDATE SET
+            dictations = dictations + excluded.dictations,
+            words = words + excluded.words,
+            timed_words = timed_words + excluded.timed_words,
+            speech_ms = speech_ms + excluded.speech_ms",
+        params![
+            at_ms,
+            dictations,
+            words,
+            timed_words,
+            duration_ms.unwrap_or(0) as i64
+        ],
+    )?;
+    Ok(())
+}
+
+/// Length of the run of consecutive days in `active_days` (newest first)
+/// ending today or yesterday.
+fn streak(today: NaiveDate, active_days: &[String]) -> u32 {
+    let mut days = active_days
+        .iter()
+        .filter_map(|day| NaiveDate::parse_from_str(day, DAY_FORMAT).ok())
+        .peekable();
+
+    // A streak survives until the end of the day after the last dictation
+    let mut expected = match days.peek() {
+        Some(&first) if first == today || first == today - Duration::days(1) => first,
+        _ => return 0,
+    };
+
+    let mut count = 0;
+    for day in days {
+        if day != expected {
+            break;
+        }
+        count += 1;
+        expected = day - Duration::days(1);
+    }
+    count
+}
+
+/// Returns usage statistics for `period` with a per-day breakdown.
+///
+/// # Errors
+///
+/// Returns an error if the history database is unavailable or the query
+/// fails.
+#[tauri::command]
+pub fn get_usage_stats(
+    history: State<'_, HistoryState>,
+    stats: State<'_, StatsState>,
+    period: StatsPeriod,
+) -> Result<UsageStats, String> {
+    let config = stats.0.lock().unwrap().clone();
+    history.usage_stats(period, &config)
+}
+
+/// Returns the stats configuration.
+#[tauri::command]
+pub fn get_stats_config(state: State<'_, StatsState>) -> StatsConfig {
+    state.0.lock().unwrap().clone()
+}
+
+/// Replaces the stats configuration.
+///
+/// # Errors
+///
+/// Returns an error if the baseline typing speed is zero or the settings
+/// cannot be saved.
+#[tauri::command]
+pub fn set_stats_config(app: AppHandle, config: StatsConfig) -> Result<(), String> {
+    settings::update(&app, |settings| {
+        settings.stats = config;
+        Ok(())
+    })
+    .map(|_| ())
+}
@@ -0,0 +1,404 @@
+//! Weekly summaries: what was dictated in one Monday-to-Sunday week.
+//!
+//! [`get_weekly_summary`] sums up the local week `week_offset` weeks before
+//! the current one (0, the default, is this week so far): words,
+//! dictations, time spoken and saved, the busiest day, the apps dictated
+//! into most, the average dictation, and speaking speed against the week
+//! before. Totals come from the daily usage counters (see [`super::stats`]),
+//! so they include pruned entries and dictations whose text wasn't kept;
+//! the apps come from the entries still in history. A week with little or
+//! nothing in it has the averages it can't compute as `null`, never zero
+//! or infinity.
+//!
+//! Finished weeks don't change, so their summaries are computed once and
+//! cached; the current week's is computed on every call.
+//!
+//! With `stats.weekly_summary` set (e.g. `{"day": "monday", "hour": 9}`),
+//! `summary:ready` carries the summary of the week before once a week, at
+//! that local time or the first time the app runs after it. The last week
+//! announced is kept in `weekly-summary.json` in the app data directory, so
+//! restarting doesn't announce it again.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration as StdDuration;
+
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use super::stats::{DailyUsage, StatsState};
+use super::HistoryState;
+
+use crate::AppHandle;
+
+/// File remembering the last week announced.
+const SUMMARY_FILE: &str = "weekly-summary.json";
+
+/// Local-date format of `daily_usage.day`.
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+/// Apps listed in a summary.
+const TOP_APPS: u32 = 5;
+
+/// How often the schedule is checked.
+const SCHEDULE_POLL: StdDuration = StdDuration::from_secs(10 * 60);
+
+/// A day of the week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// Days after Monday.
+    fn offset(self) -> i64 {
+        self as i64
+    }
+}
+
+/// When `summary:ready` is emitted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SummarySchedule {
+    pub day: Weekday,
+    /// Local hour, 0 to 23.
+    pub hour: u32,
+}
+
+impl SummarySchedule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.hour > 23 {
+            return Err("hour must be between 0 and 23".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// How often an app was dictated into.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AppUsage {
+    pub target_app: String,
+    pub dictations: u64,
+}
+
+/// Returned by [`get_weekly_summary`] and carried by `summary:ready`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WeeklySummary {
+    /// The Monday and Sunday of the week, `YYYY-MM-DD`.
+    pub week_start: String,
+    pub week_end: String,
+    /// Whether the week is over.
+    pub complete: bool,
+    pub words: u64,
+    pub dictations: u64,
+    pub speech_ms: u64,
+    /// Typing the words at `baseline_wpm` minus speaking them.
+    pub time_saved_ms: u64,
+    pub baseline_wpm: u32,
+    /// Days with at least one dictation.
+    pub active_days: u32,
+    /// The day with the most words, if any.
+    pub busiest_day: Option<DailyUsage>,
+    /// Apps dictated into most, most first.
+    pub top_apps: Vec<AppUsage>,
+    /// Speaking time per dictation.
+    pub average_dictation_ms: Option<u64>,
+    /// Speaking speed over dictations with a known duration.
+    pub words_per_minute: Option<f64>,
+    /// The same, the week before.
+    pub previous_words_per_minute: Option<f64>,
+    /// Change of the speaking speed since the week before, in percent.
+    pub words_per_minute_change_percent: Option<f64>,
+}
+
+/// Counters summed over some days.
+#[derive(Debug, Default)]
+struct Totals {
+    dictations: u64,
+    words: u64,
+    timed_words: u64,
+    speech_ms: u64,
+}
+
+impl Totals {
+    fn words_per_minute(&self) -> Option<f64> {
+        (self.speech_ms > 0 && self.timed_words > 0)
+            .then(|| self.timed_words as f64 / (self.speech_ms as f64 / 60_000.0))
+    }
+}
+
+/// Managed state: summaries of finished weeks, and the schedule's memory.
+pub struct SummaryState {
+    path: PathBuf,
+    /// By Monday and baseline typing speed.
+    cache: Mutex<HashMap<(NaiveDate, u32), WeeklySummary>>,
+}
+
+/// Contents of [`SUMMARY_FILE`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Announced {
+    /// Monday of the week whose `summary:ready` was last emitted.
+    week_start: Option<String>,
+}
+
+impl SummaryState {
+    /// The schedule's memory goes under `data_dir`.
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join(SUMMARY_FILE),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn announced(&self) -> Announced {
+        fs::read(&self.path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn set_announced(&self, week_start: NaiveDate) {
+        let announced = Announced {
+            week_start: Some(week_start.format(DAY_FORMAT).to_string()),
+        };
+        let written = serde_json::to_vec(&announced)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&self.path, json).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            tracing::warn!("Failed to save {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Monday of the week `week_offset` weeks before the one `today` is in.
+fn week_start(today: NaiveDate, week_offset: u32) -> NaiveDate {
+    let monday = today - Duration::days(i64::from(today.weekday().num_days_from_monday()));
+    monday - Duration::weeks(i64::from(week_offset))
+}
+
+/// Unix milliseconds of local midnight starting `day`.
+fn midnight_ms(day: NaiveDate) -> i64 {
+    let midnight = day.and_time(NaiveTime::MIN);
+    local_ms(midnight)
+}
+
+fn local_ms(time: NaiveDateTime) -> i64 {
+    // Midnight may not exist on the day clocks go forward
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .unwrap_or_else(|| Local.from_utc_datetime(&time))
+        .timestamp_millis()
+}
+
+fn totals(connection: &Connection, start: &str, end: &str) -> rusqlite::Result<Totals> {
+    connection.query_row(
+        "SELECT COALESCE(SUM(dictations), 0), COALESCE(SUM(words), 0),
+                COALESCE(SUM(timed_words), 0), COALESCE(SUM(speech_ms), 0)
+         FROM daily_usage WHERE day >= ?1 AND day <= ?2",
+        params![start, end],
+        |row| {
+            Ok(Totals {
+                dictations: row.get::<_, i64>(0)? as u64,
+                words: row.get::<_, i64>(1)? as u64,
+                timed_words: row.get::<_, i64>(2)? as u64,
+                speech_ms: row.get::<_, i64>(3)? as u64,
+            })
+        },
+    )
+}
+
+impl HistoryState {
+    /// Sums up the week starting on Monday `start`, as of `today`.
+    fn weekly_summary(
+        &self,
+        start: NaiveDate,
+        today: NaiveDate,
+        baseline_wpm: u32,
+    ) -> Result<WeeklySummary, String> {
+        let end = start + Duration::days(6);
+        let previous = start - Duration::weeks(1);
+        let (start_day, end_day) = (
+            start.format(DAY_FORMAT).to_string(),
+            end.format(DAY_FORMAT).to_string(),
+        );
+
+        let (week, before, days, top_apps) = self.with_connection(|connection| {
+            let week = totals(connection, &start_day, &end_day)?;
+            let before = totals(
+                connection,
+                &previous.format(DAY_FORMAT).to_string(),
+                &(start - Duration::days(1)).format(DAY_FORMAT).to_string(),
+            )?;
+
+            let mut statement = connection.prepare(
+                "SELECT day, dictations, words, speech_ms FROM daily_usage
+                 WHERE day >= ?1 AND day <= ?2 AND dictations > 0 ORDER BY day ASC",
+            )?;
+            let days = statement
+                .query_map(params![start_day, end_day], |row| {
+                    Ok(DailyUsage {
+                        day: row.get(0)?,
+                        dictations: row.get::<_, i64>(1)? as u64,
+                        words: row.get::<_, i64>(2)? as u64,
+                        speech_ms: row.get::<_, i64>(3)? as u64,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            let mut statement = connection.prepare(
+                "SELECT target_app, COUNT(*) FROM history
+                 WHERE target_app IS NOT NULL AND created_at_ms >= ?1 AND created_at_ms < ?2
+                 GROUP BY target_app ORDER BY COUNT(*) DESC, target_app ASC LIMIT ?3",
+            )?;
+            let top_apps = statement
+                .query_map(
+                    params![
+                        midnight_ms(start),
+                        midnight_ms(end + Duration::days(1)),
+                        TOP_APPS
+                    ],
+                    |row| {
+                        Ok(AppUsage {
+                            target_app: row.get(0)?,
+                            dictations: row.get::<_, i64>(1)? as u64,
+                        })
+                    },
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok((week, before, days, top_apps))
+        })?;
+
+        let typing_ms = week.words * 60_000 / u64::from(baseline_wpm.max(1));
+        let words_per_minute = week.words_per_minute();
+        let previous_words_per_minute = before.words_per_minute();
+        let words_per_minute_change_percent = words_per_minute
+            .zip(previous_words_per_minute)
+            .map(|(now, before)| (now - before) / before * 100.0);
+        // The first day with the most words wins a tie
+        let busiest_day = days
+            .iter()
+            .rev()
+            .max_by_key(|day| day.words)
+            .filter(|day| day.words > 0)
+            .cloned();
+
+        Ok(WeeklySummary {
+            week_start: start_day,
+            week_end: end_day,
+            complete: end < today,
+            words: week.words,
+            dictations: week.dictations,
+            speech_ms: week.speech_ms,
+            time_saved_ms: typing_ms.saturating_sub(week.speech_ms),
+            baseline_wpm,
+            active_days: days.len() as u32,
+            busiest_day,
+            top_apps,
+            average_dictation_ms: (week.dictations > 0 && week.speech_ms > 0)
+                .then(|| week.speech_ms / week.dictations),
+            words_per_minute,
+            previous_words_per_minute,
+            words_per_minute_change_percent,
+        })
+    }
+}
+
+/// The summary of the week `week_offset` weeks ago, from the cache for
+/// finished weeks.
+fn summary(app: &AppHandle, week_offset: u32) -> Result<WeeklySummary, String> {
+    let today = Local::now().date_naive();
+    let start = week_start(today, week_offset);
+    let baseline_wpm = app.state::<StatsState>().0.lock().unwrap().baseline_wpm;
+    let state = app.state::<SummaryState>();
+    let key = (start, baseline_wpm);
+    if let Some(cached) = state.cache.lock().unwrap().get(&key) {
+        return Ok(cached.clone());
+    }
+
+    let summary = app
+        .state::<HistoryState>()
+        .weekly_summary(start, today, baseline_wpm)?;
+    if summary.complete {
+        state.cache.lock().unwrap().insert(key, summary.clone());
+    }
+    Ok(summary)
+}
+
+/// Starts the thread emitting `summary:ready` on `stats.weekly_summary`'s
+/// schedule. A panic restarts it (see [`crate::crash::supervise`]).
+pub fn spawn_scheduler(app: AppHandle) {
+    let handle = app.clone();
+    let spawned = crate::crash::supervise(&app, "weekly-summary", move || loop {
+        announce_if_due(&handle);
+        std::thread::sleep(SCHEDULE_POLL);
+    });
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to start the weekly summary schedule: {}", e);
+    }
+}
+
+/// Emits last week's summary if this week's announcement is due and wasn't
+/// made yet.
+fn announce_if_due(app: &AppHandle) {
+    let schedule = app
+        .state::<StatsState>()
+        .0
+        .lock()
+        .unwrap()
+        .weekly_summary
+        .clone();
+    let Some(schedule) = schedule else {
+        return;
+    };
+    let now = Local::now();
+    let this_week = week_start(now.date_naive(), 0);
+    let due = (this_week + Duration::days(schedule.day.offset()))
+        .and_hms_opt(schedule.hour, 0, 0)
+        .map(local_ms);
+    if due.map_or(true, |due| now.timestamp_millis() < due) {
+        return;
+    }
+    let state = app.state::<SummaryState>();
+    let this_week_day = this_week.format(DAY_FORMAT).to_string();
+    if state.announced().week_start.as_deref() == Some(this_week_day.as_str()) {
+        return;
+    }
+
+    match summary(app, 1) {
+        Ok(summary) => {
+            tracing::info!(week_start = %summary.week_start, "Weekly summary ready");
+            state.set_announced(this_week);
+            let _ = app.emit("summary:ready", summary);
+        }
+        Err(e) => tracing::warn!("Failed to compute the weekly summary: {}", e),
+    }
+}
+
+/// Returns the summary of the week `week_offset` weeks before this one
+/// (Monday to Sunday, local time); this week so far by default.
+///
+/// # Errors
+///
+/// Returns an error if the history database is unavailable or the query
+/// fails.
+#[tauri::command]
+pub fn get_weekly_summary(
+    app: AppHandle,
+    week_offset: Option<u32>,
+) -> Result<WeeklySummary, String> {
+    summary(&app, week_offset.unwrap_or(0))
+}
@@ -0,0 +1,259 @@
+//! The recording indicator: a dot on every display while the mic is open.
+//!
+//! The pill only shows on one monitor, so someone looking at another one
+//! can't tell that they are being recorded. With `indicator.enabled`, a
+//! small red dot sits in the `indicator.corner` of every connected
+//! display's work area for as long as a dictation is recording or a
+//! meeting is in progress.
+//!
+//! Each dot is a tiny window of its own (`indicator-<n>`, loading the
+//! `#/indicator` route), always on top, out of the taskbar, never focused
+//! and ignoring the mouse, so clicks go to whatever is below it. The
+//! backend owns them entirely: [`sync`] is called whenever the dictation
+//! phase, a meeting or the settings change, and starts or stops a watcher
+//! thread that creates the windows. Tauri doesn't report monitors being
+//! connected or disconnected, so while the watcher runs it looks at them
+//! every [`POLL`], adding dots for new displays, moving those whose display
+//! changed and closing the rest.
+//!
+//! # Platform Support
+//!
+//! - macOS: the dots are at the status bar level on every Space, over
+//!   fullscreen apps, and are ordered in without activating the app.
+//! - Windows and Linux: the dots are topmost windows.
+
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Manager, Monitor, PhysicalPosition, PhysicalSize, WebviewUrl, WebviewWindowBuilder};
+
+use crate::app_state::{self, Phase};
+use crate::meeting::MeetingState;
+use crate::settings::SettingsState;
+use crate::{AppHandle, WebviewWindow};
+
+/// Prefix of the indicator windows' labels; the rest is the monitor's
+/// index.
+const LABEL_PREFIX: &str = "indicator-";
+
+/// Diameter of the dot, and its distance from the corner, in logical
+/// pixels.
+const DOT_SIZE: f64 = 14.0;
+const MARGIN: f64 = 10.0;
+
+/// How often the monitors are looked at while recording.
+const POLL: Duration = Duration::from_secs(1);
+
+/// The corner of each display the dot sits in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Corner {
+    TopLeft,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Recording indicator settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IndicatorSettings {
+    /// Show a dot on every display while recording.
+    pub enabled: bool,
+    pub corner: Corner,
+}
+
+/// Managed state: whether the dots should be shown, and the watcher
+/// showing them.
+#[derive(Default)]
+pub struct IndicatorState {
+    watch: Mutex<Watch>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Watch {
+    wanted: bool,
+    /// Whether the watcher thread is running.
+    running: bool,
+}
+
+/// Where one dot goes, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Frame {
+    position: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+}
+
+/// Whether the mic is open, for a dictation or a meeting.
+pub fn recording(app: &AppHandle) -> bool {
+    app_state::phase(app) == Phase::Recording || app.state::<MeetingState>().recording()
+}
+
+/// Shows or hides the dots to match the recording state and the settings.
+/// Cheap, and safe to call from any thread: the windows themselves are
+/// handled by the watcher.
+pub fn sync(app: &AppHandle) {
+    let wanted = app.state::<SettingsState>().get().indicator.enabled && recording(app);
+    let state = app.state::<IndicatorState>();
+    let mut watch = state.watch.lock().unwrap();
+    watch.wanted = wanted;
+    if wanted && !watch.running {
+        let handle = app.clone();
+        let spawned = std::thread::Builder::new()
+            .name("recording-indicator".to_string())
+            .spawn(move || run(&handle));
+        match spawned {
+            Ok(_) => watch.running = true,
+            Err(e) => tracing::warn!("Failed to start the recording indicator: {}", e),
+        }
+    }
+    state.changed.notify_all();
+}
+
+/// The watcher: keeps one dot per monitor until they are no longer wanted,
+/// then closes them.
+fn run(app: &AppHandle) {
+    tracing::debug!("Recording indicator shown");
+    let mut shown: Vec<Frame> = Vec::new();
+    loop {
+        let corner = app.state::<SettingsState>().get().indicator.corner;
+        let frames = match app.available_monitors() {
+            Ok(monitors) => monitors
+                .iter()
+                .map(|monitor| frame(monitor, corner))
+                .collect(),
+            Err(e) => {
+                tracing::warn!("Failed to list monitors for the recording indicator: {}", e);
+                shown.clone()
+            }
+        };
+        reconcile(app, &mut shown, &frames);
+
+        let state = app.state::<IndicatorState>();
+        let watch = state.watch.lock().unwrap();
+        let (mut watch, _) = state
+            .changed
+            .wait_timeout_while(watch, POLL, |watch| watch.wanted)
+            .unwrap();
+        if !watch.wanted {
+            // Closed with the lock held, so a watcher started right after
+            // this one never has its new windows closed
+            reconcile(app, &mut shown, &[]);
+            watch.running = false;
+            tracing::debug!("Recording indicator hidden");
+            return;
+        }
+    }
+}
+
+/// Where the dot goes on `monitor`: `corner` of its work area.
+fn frame(monitor: &Monitor, corner: Corner) -> Frame {
+    let scale = monitor.scale_factor();
+    let area = monitor.work_area();
+    let side = (DOT_SIZE * scale).round() as i32;
+    let margin = (MARGIN * scale).round() as i32;
+    let left = area.position.x + margin;
+    let top = area.position.y + margin;
+    let right = area.position.x + area.size.width as i32 - margin - side;
+    let bottom = area.position.y + area.size.height as i32 - margin - side;
+    let (x, y) = match corner {
+        Corner::TopLeft => (left, top),
+        Corner::TopRight => (right, top),
+        Corner::BottomLeft => (left, bottom),
+        Corner::BottomRight => (right, bottom),
+    };
+    Frame {
+        position: PhysicalPosition::new(x, y),
+        size: PhysicalSize::new(side as u32, side as u32),
+    }
+}
+
+/// Makes the dots match `frames`, one per monitor: opens the missing
+/// windows, moves those whose frame changed and closes the extra ones.
+fn reconcile(app: &AppHandle, shown: &mut Vec<Frame>, frames: &[Frame]) {
+    for (index, frame) in frames.iter().enumerate() {
+        let label = format!("{}{}", LABEL_PREFIX, index);
+        match app.get_webview_window(&label) {
+            Some(_) if shown.get(index) == Some(frame) => {}
+            Some(window) => {
+                let _ = window.set_size(frame.size);
+                let _ = window.set_position(frame.position);
+            }
+            None => {
+                if let Err(e) = open(app, &label, *frame) {
+                    tracing::warn!(%label, "Failed to open a recording indicator: {}", e);
+                }
+            }
+        }
+    }
+    for index in frames.len()..shown.len() {
+        if let Some(window) = app.get_webview_window(&format!("{}{}", LABEL_PREFIX, index)) {
+            let _ = window.destroy();
+        }
+    }
+    *shown = frames.to_vec();
+}
+
+/// Opens the dot window `label` at `frame`.
+fn open(app: &AppHandle, label: &str, frame: Frame) -> Result<(), String> {
+    let url = WebviewUrl::App("index.html#/indicator".into());
+    let builder = WebviewWindowBuilder::new(app, label, url)
+        .title("Recording")
+        .visible(false)
+        .decorations(false)
+        .resizable(false)
+        .shadow(false)
+        .always_on_top(true)
+        .skip_taskbar(true)
+        .visible_on_all_workspaces(true)
+        .focused(false)
+        .focusable(false);
+    // Transparency needs the private API on macOS; the window's background
+    // is cleared natively instead (see `configure`)
+    #[cfg(not(target_os = "macos"))]
+    let builder = builder.transparent(true);
+
+    let window = builder.build().map_err(|e| e.to_string())?;
+    window.set_size(frame.size).map_err(|e| e.to_string())?;
+    window
+        .set_position(frame.position)
+        .map_err(|e| e.to_string())?;
+    // Never in the way of a click
+    window
+        .set_ignore_cursor_events(true)
+        .map_err(|e| e.to_string())?;
+    configure(app, &window)
+}
+
+/// Shows a new dot window, above everything and without activating the
+/// app.
+#[cfg(target_os = "macos")]
+fn configure(app: &AppHandle, window: &WebviewWindow) -> Result<(), String> {
+    use crate::pill::{macos, WindowLevel};
+
+    let window = window.clone();
+    app.run_on_main_thread(move || {
+        let Ok(ns_window) = window.ns_window() else {
+            return;
+        };
+        unsafe {
+            macos::clear_window_background(ns_window);
+            macos::float_over_fullscreen(ns_window);
+            macos::set_level(ns_window, WindowLevel::StatusBar);
+        }
+        let _ = window.with_webview(|webview| unsafe {
+            macos::clear_webview_background(webview.inner());
+        });
+        unsafe { macos::order_front(ns_window) };
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Shows a new dot window.
+#[cfg(not(target_os = "macos"))]
+fn configure(_app: &AppHandle, window: &WebviewWindow) -> Result<(), String> {
+    window.show().map_err(|e| e.to_string())
+}
@@ -0,0 +1,285 @@
+//! Journal: appends every dictation to a daily Markdown note.
+//!
+//! With `journal.enabled`, each pasted dictation is appended to a file in
+//! `journal.directory` named after the day by `journal.filename`
+//! (`{date}.md` by default, which is what Obsidian's daily notes use). A
+//! new file starts with `journal.header`. `journal.actions` picks which
+//! hotkeys' dictations are journaled: plain dictation, rewrite, or both.
+//!
+//! Templates take `{placeholder}`s, replaced in one pass so a transcript
+//! containing braces is written as is:
+//!
+//! - filename and header: `{date}` (`2024-05-31`), `{year}`, `{month}`,
+//!   `{day}`
+//! - entry: all of those, plus `{time}` (`14:05`), `{app}` (the app pasted
+//!   into, or `unknown`) and `{text}`
+//!
+//! # Architecture Decision
+//!
+//! Other tools (the notes app, sync clients) write the same files, so an
+//! entry is never written by rewriting the file: it is appended with a
+//! single `write` on a file opened for appending, then synced. The file is
+//! created with `create_new`, so of two writers creating it at once only
+//! one writes the header.
+//!
+//! The filename template may name subdirectories (`{year}/{date}.md`) but
+//! never leave `journal.directory`: absolute paths and `..` are rejected
+//! when the settings are saved, and the resolved file is checked to still
+//! be inside the directory, symlinks included, before anything is written.
+//!
+//! Like the webhook, the journal is an output sink (see [`crate::sinks`]):
+//! journaling happens after the paste and off its path, and a failure is
+//! only logged. In privacy mode nothing is journaled (see
+//! [`crate::privacy`]).
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::dictation::{Dictation, HotkeyAction};
+use crate::frontmost;
+use crate::privacy;
+use crate::settings::SettingsState;
+use crate::sinks::OutputSink;
+use crate::AppHandle;
+
+/// Placeholders every template can use.
+const DATE_PLACEHOLDERS: &[&str] = &["date", "year", "month", "day"];
+
+/// Placeholders only entries can use.
+const ENTRY_PLACEHOLDERS: &[&str] = &["time", "app", "text"];
+
+/// Journal settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JournalSettings {
+    /// Append dictations to the journal.
+    pub enabled: bool,
+    /// Folder holding the notes. Required when enabled.
+    pub directory: Option<PathBuf>,
+    /// Name of the day's note, relative to `directory`.
+    pub filename: String,
+    /// Written at the top of a new note.
+    pub header: String,
+    /// One dictation. A line break is added if it doesn't end with one.
+    pub entry: String,
+    pub actions: JournalActions,
+}
+
+impl Default for JournalSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: None,
+            filename: "{date}.md".to_string(),
+            header: "# {date}\n\n".to_string(),
+            entry: "- {time} {text}\n".to_string(),
+            actions: JournalActions::default(),
+        }
+    }
+}
+
+/// Which hotkeys' dictations are journaled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JournalActions {
+    pub dictation: bool,
+    pub rewrite: bool,
+}
+
+impl Default for JournalActions {
+    fn default() -> Self {
+        Self {
+            dictation: true,
+            rewrite: true,
+        }
+    }
+}
+
+impl JournalActions {
+    fn includes(&self, action: HotkeyAction) -> bool {
+        match action {
+            HotkeyAction::Dictation => self.dictation,
+            HotkeyAction::Rewrite => self.rewrite,
+            // Voice commands aren't pasted, so there is nothing to journal
+            HotkeyAction::Command => false,
+        }
+    }
+}
+
+impl JournalSettings {
+    /// Checks the directory and templates.
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.directory {
+            Some(directory) if !directory.is_absolute() => {
+                return Err("directory must be an absolute path".to_string());
+            }
+            None if self.enabled => {
+                return Err("directory must be set to enable the journal".to_string());
+            }
+            _ => {}
+        }
+        let now = Local::now();
+        let sample = |name: &str| placeholder(name, &now, "", "");
+        render(&self.filename, DATE_PLACEHOLDERS, sample)
+            .and_then(|filename| relative_path(&filename))
+            .map_err(|e| format!("filename: {}", e))?;
+        render(&self.header, DATE_PLACEHOLDERS, sample).map_err(|e| format!("header: {}", e))?;
+        let entry_placeholders = [DATE_PLACEHOLDERS, ENTRY_PLACEHOLDERS].concat();
+        render(&self.entry, &entry_placeholders, sample).map_err(|e| format!("entry: {}", e))?;
+        Ok(())
+    }
+}
+
+/// Replaces the `{name}`s in `template` with `value(name)`, in one pass.
+/// Only `allowed` names may appear; a `{` that doesn't start one is kept.
+fn render(
+    template: &str,
+    allowed: &[&str],
+    value: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        rendered.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let name = after
+            .find('}')
+            .map(|close| &after[..close])
+            .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase()));
+        match name {
+            Some(name) if allowed.contains(&name) => {
+                rendered.push_str(&value(name).unwrap_or_default());
+                rest = &after[name.len() + 1..];
+            }
+            Some(name) => return Err(format!("unknown placeholder {{{}}}", name)),
+            None => {
+                rendered.push('{');
+                rest = after;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// `path` as a relative path that stays below where it is joined.
+fn relative_path(path: &str) -> Result<PathBuf, String> {
+    let path = Path::new(path);
+    if path.as_os_str().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err("must be a relative path without \"..\"".to_string());
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Values of the date placeholders, and the entry's at `now`.
+fn placeholder(name: &str, now: &DateTime<Local>, app: &str, text: &str) -> Option<String> {
+    Some(match name {
+        "date" => now.format("%Y-%m-%d").to_string(),
+        "year" => now.format("%Y").to_string(),
+        "month" => now.format("%m").to_string(),
+        "day" => now.format("%d").to_string(),
+        "time" => now.format("%H:%M").to_string(),
+        "app" => app.to_string(),
+        "text" => text.to_string(),
+        _ => return None,
+    })
+}
+
+/// The journal as an output sink: appends each pasted dictation to the
+/// day's note if the settings say so.
+pub struct Journal;
+
+impl OutputSink for Journal {
+    fn accept(&self, app: &AppHandle, dictation: &Dictation, pasted: &str) {
+        spawn_append(app, dictation, pasted);
+    }
+}
+
+/// Journals the pasted `dictation` if the settings say so. The writing
+/// happens in the background.
+fn spawn_append(app: &AppHandle, dictation: &Dictation, pasted: &str) {
+    let settings = app.state::<SettingsState>().get().journal;
+    if !settings.enabled
+        || !settings.actions.includes(dictation.action)
+        || !privacy::policy().journal
+    {
+        return;
+    }
+    let Some(directory) = settings.directory.clone() else {
+        return;
+    };
+    let now = Local::now();
+    let text = pasted.to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        let app = frontmost::frontmost_bundle_id().unwrap_or_else(|| "unknown".to_string());
+        if let Err(e) = append(&settings, &directory, &now, &app, &text) {
+            tracing::warn!("Failed to append to the journal: {}", e);
+        }
+    });
+}
+
+/// Appends one entry to the note for `now` in `directory`.
+fn append(
+    settings: &JournalSettings,
+    directory: &Path,
+    now: &DateTime<Local>,
+    app: &str,
+    text: &str,
+) -> Result<(), String> {
+    let value = |name: &str| placeholder(name, now, app, text);
+    let filename = render(&settings.filename, DATE_PLACEHOLDERS, value)?;
+    let path = directory.join(relative_path(&filename)?);
+
+    // Checked once the folders exist, so symlinks among them are resolved
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let root = directory.canonicalize().map_err(|e| e.to_string())?;
+    let parent = path
+        .parent()
+        .unwrap_or(directory)
+        .canonicalize()
+        .map_err(|e| e.to_string())?;
+    if !parent.starts_with(&root) {
+        return Err(format!(
+            "{} is outside the journal directory",
+            path.display()
+        ));
+    }
+
+    let entry_placeholders = [DATE_PLACEHOLDERS, ENTRY_PLACEHOLDERS].concat();
+    let mut entry = render(&settings.entry, &entry_placeholders, value)?;
+    if !entry.ends_with('\n') {
+        entry.push('\n');
+    }
+
+    let (mut file, contents) = match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(file) => {
+            let header = render(&settings.header, DATE_PLACEHOLDERS, value)?;
+            (file, header + &entry)
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            let file = OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .map_err(|e| e.to_string())?;
+            (file, entry)
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    // One write, so other writers' appends land before or after it
+    file.write_all(contents.as_bytes())
+        .and_then(|()| file.sync_all())
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
@@ -0,0 +1,629 @@
+//! Keystroke injection layer.
+//!
+//! Synthesizes keyboard input into whichever application currently has
+//! focus. Everything that needs to "press keys" on the user's behalf (the
+//! paste shortcut, caret positioning after a snippet, voice commands) goes
+//! through here so the platform-specific details live in one place.
+//!
+//! Voice commands name their keys as [`Chord`]s, in the accelerator syntax
+//! of the global-shortcut plugin (`CmdOrCtrl+Shift+Z`, `Enter`), limited to
+//! the keys every tool below can press: letters, digits, and the editing
+//! and navigation keys.
+//!
+//! # Platform Support
+//!
+//! - macOS: System Events, through `osascript`
+//! - Linux: `xdotool` on X11; `wtype` or `ydotool` on Wayland
+//! - Windows: none yet
+//!
+//! Where no injector is found ([`injector`]) these functions are no-ops that
+//! return `Ok(())`, matching the "copy to clipboard, paste manually"
+//! behavior. [`send_chords`] fails instead: a voice command has no manual
+//! fallback, and must not be reported as run. The injector is looked up on
+//! every call, so a tool installed while the app runs is picked up;
+//! `get_paste_capabilities` reports the same lookup.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+/// macOS virtual key code for the Left arrow key.
+const KEY_CODE_LEFT_ARROW: u32 = 123;
+
+/// Linux input event codes for `ydotool`.
+const EV_KEY_LEFTCTRL: u32 = 29;
+const EV_KEY_V: u32 = 47;
+const EV_KEY_LEFT: u32 = 105;
+
+/// Linux input event codes of the letters `a` to `z`, in order.
+const EV_KEY_LETTERS: [u32; 26] = [
+    30, 48, 46, 32, 18, 33, 34, 35, 23, 36, 37, 38, 50, 49, 24, 25, 16, 19, 31, 20, 22, 47, 17, 45,
+    21, 44,
+];
+
+/// Linux input event codes of the digits `1` to `9`; `0` is 11.
+const EV_KEY_DIGIT_1: u32 = 2;
+const EV_KEY_DIGIT_0: u32 = 11;
+
+/// A modifier held for a [`Chord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    /// Cmd on macOS, Super elsewhere.
+    Command,
+    Control,
+    /// Option on macOS.
+    Alt,
+    Shift,
+}
+
+impl Modifier {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "cmd" | "command" | "super" | "meta" => Modifier::Command,
+            "ctrl" | "control" => Modifier::Control,
+            "alt" | "option" => Modifier::Alt,
+            "shift" => Modifier::Shift,
+            "cmdorctrl" | "commandorcontrol" if cfg!(target_os = "macos") => Modifier::Command,
+            "cmdorctrl" | "commandorcontrol" => Modifier::Control,
+            _ => return None,
+        })
+    }
+
+    fn applescript(self) -> &'static str {
+        match self {
+            Modifier::Command => "command down",
+            Modifier::Control => "control down",
+            Modifier::Alt => "option down",
+            Modifier::Shift => "shift down",
+        }
+    }
+
+    /// Name for `xdotool` and `wtype`.
+    fn xkb(self, tool: Tool) -> &'static str {
+        match (self, tool) {
+            (Modifier::Command, Tool::Wtype) => "logo",
+            (Modifier::Command, _) => "super",
+            (Modifier::Control, _) => "ctrl",
+            (Modifier::Alt, _) => "alt",
+            (Modifier::Shift, _) => "shift",
+        }
+    }
+
+    fn event_code(self) -> u32 {
+        match self {
+            Modifier::Command => 125,
+            Modifier::Control => EV_KEY_LEFTCTRL,
+            Modifier::Alt => 56,
+            Modifier::Shift => 42,
+        }
+    }
+}
+
+/// A key pressed by a [`Chord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A lowercase ASCII letter or a digit.
+    Char(char),
+    Enter,
+    Tab,
+    Space,
+    Backspace,
+    /// Forward delete.
+    Delete,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
+impl Key {
+    fn parse(name: &str) -> Option<Self> {
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return c
+                .is_ascii_alphanumeric()
+                .then(|| Key::Char(c.to_ascii_lowercase()));
+        }
+        Some(match name.to_ascii_lowercase().as_str() {
+            "enter" | "return" => Key::Enter,
+            "tab" => Key::Tab,
+            "space" => Key::Space,
+            "backspace" => Key::Backspace,
+            "delete" => Key::Delete,
+            "escape" | "esc" => Key::Escape,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pageup" => Key::PageUp,
+            "pagedown" => Key::PageDown,
+            _ => return None,
+        })
+    }
+
+    /// macOS virtual key code, for the keys System Events can't type.
+    fn mac_key_code(self) -> Option<u32> {
+        Some(match self {
+            Key::Char(_) => return None,
+            Key::Enter => 36,
+            Key::Tab => 48,
+            Key::Space => 49,
+            Key::Backspace => 51,
+            Key::Delete => 117,
+            Key::Escape => 53,
+            Key::Up => 126,
+            Key::Down => 125,
+            Key::Left => KEY_CODE_LEFT_ARROW,
+            Key::Right => 124,
+            Key::Home => 115,
+            Key::End => 119,
+            Key::PageUp => 116,
+            Key::PageDown => 121,
+        })
+    }
+
+    /// X keysym name, for `xdotool` and `wtype`.
+    fn keysym(self) -> String {
+        match self {
+            Key::Char(c) => c.to_string(),
+            Key::Enter => "Return".into(),
+            Key::Tab => "Tab".into(),
+            Key::Space => "space".into(),
+            Key::Backspace => "BackSpace".into(),
+            Key::Delete => "Delete".into(),
+            Key::Escape => "Escape".into(),
+            Key::Up => "Up".into(),
+            Key::Down => "Down".into(),
+            Key::Left => "Left".into(),
+            Key::Right => "Right".into(),
+            Key::Home => "Home".into(),
+            Key::End => "End".into(),
+            Key::PageUp => "Prior".into(),
+            Key::PageDown => "Next".into(),
+        }
+    }
+
+    fn event_code(self) -> u32 {
+        match self {
+            Key::Char('0') => EV_KEY_DIGIT_0,
+            Key::Char(c @ '1'..='9') => EV_KEY_DIGIT_1 + (c as u32 - '1' as u32),
+            Key::Char(c) => EV_KEY_LETTERS[(c as u8 - b'a') as usize],
+            Key::Enter => 28,
+            Key::Tab => 15,
+            Key::Space => 57,
+            Key::Backspace => 14,
+            Key::Delete => 111,
+            Key::Escape => 1,
+            Key::Up => 103,
+            Key::Down => 108,
+            Key::Left => EV_KEY_LEFT,
+            Key::Right => 106,
+            Key::Home => 102,
+            Key::End => 107,
+            Key::PageUp => 104,
+            Key::PageDown => 109,
+        }
+    }
+}
+
+/// One key press, with modifiers held: `CmdOrCtrl+Shift+Z`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chord {
+    pub modifiers: Vec<Modifier>,
+    pub key: Key,
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let (key, modifiers) = parts
+            .split_last()
+            .filter(|(key, _)| !key.is_empty())
+            .ok_or_else(|| format!("\"{}\" has no key", s))?;
+        let mut chord = Chord {
+            modifiers: Vec::new(),
+            key: Key::parse(key).ok_or_else(|| format!("unknown key \"{}\"", key))?,
+        };
+        for name in modifiers {
+            let modifier =
+                Modifier::parse(name).ok_or_else(|| format!("unknown modifier \"{}\"", name))?;
+            if !chord.modifiers.contains(&modifier) {
+                chord.modifiers.push(modifier);
+            }
+        }
+        Ok(chord)
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", modifier.xkb(Tool::Xdotool))?;
+        }
+        f.write_str(&self.key.keysym())
+    }
+}
+
+/// The kind of graphical session on Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionType {
+    X11,
+    Wayland,
+    /// No display server found, or not Linux.
+    Unknown,
+}
+
+/// The graphical session keystrokes go to, from the environment.
+pub fn session_type() -> SessionType {
+    if !cfg!(target_os = "linux") {
+        return SessionType::Unknown;
+    }
+    match std::env::var("XDG_SESSION_TYPE").as_deref() {
+        Ok("wayland") => return SessionType::Wayland,
+        Ok("x11") => return SessionType::X11,
+        _ => {}
+    }
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        SessionType::Wayland
+    } else if std::env::var_os("DISPLAY").is_some() {
+        SessionType::X11
+    } else {
+        SessionType::Unknown
+    }
+}
+
+/// An external tool that sends keystrokes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tool {
+    Osascript,
+    Xdotool,
+    Wtype,
+    Ydotool,
+}
+
+impl Tool {
+    /// Tools that can send keystrokes in `session`, preferred first.
+    pub fn candidates(session: SessionType) -> &'static [Tool] {
+        if cfg!(target_os = "macos") {
+            return &[Tool::Osascript];
+        }
+        if !cfg!(target_os = "linux") {
+            return &[];
+        }
+        match session {
+            SessionType::X11 => &[Tool::Xdotool],
+            // wtype needs a compositor with the virtual keyboard protocol,
+            // ydotool works everywhere but needs its daemon
+            SessionType::Wayland => &[Tool::Wtype, Tool::Ydotool],
+            SessionType::Unknown => &[],
+        }
+    }
+
+    /// Every tool the app can use on this platform, in any session.
+    pub fn all() -> &'static [Tool] {
+        if cfg!(target_os = "macos") {
+            &[Tool::Osascript]
+        } else if cfg!(target_os = "linux") {
+            &[Tool::Xdotool, Tool::Wtype, Tool::Ydotool]
+        } else {
+            &[]
+        }
+    }
+
+    pub fn program(self) -> &'static str {
+        match self {
+            Tool::Osascript => "osascript",
+            Tool::Xdotool => "xdotool",
+            Tool::Wtype => "wtype",
+            Tool::Ydotool => "ydotool",
+        }
+    }
+
+    /// Where the tool is installed, if it is on `PATH`.
+    pub fn find(self) -> Option<PathBuf> {
+        let path = std::env::var_os("PATH")?;
+        std::env::split_paths(&path)
+            .map(|dir| dir.join(self.program()))
+            .find(|candidate| candidate.is_file())
+    }
+}
+
+/// The tool keystrokes are sent with: the first installed candidate for
+/// the current session.
+pub fn injector() -> Option<Tool> {
+    Tool::candidates(session_type())
+        .iter()
+        .copied()
+        .find(|tool| tool.find().is_some())
+}
+
+/// Simulates the platform paste shortcut (Cmd+V on macOS, Ctrl+V on Linux).
+///
+/// # Errors
+///
+/// Returns an error if the tool cannot be spawned or exits with a failure
+/// status (on macOS usually missing Accessibility permission).
+pub fn send_paste() -> Result<(), String> {
+    let Some(tool) = injector() else {
+        return Ok(());
+    };
+    match tool {
+        // System Events is the macOS accessibility framework that handles
+        // keyboard/mouse simulation
+        Tool::Osascript => run_applescript(
+            r#"
+                tell application "System Events"
+                    keystroke "v" using command down
+                end tell
+            "#,
+        ),
+        Tool::Xdotool => run(tool, &["key", "--clearmodifiers", "ctrl+v"]),
+        Tool::Wtype => run(tool, &["-M", "ctrl", "v", "-m", "ctrl"]),
+        Tool::Ydotool => run(
+            tool,
+            &[
+                "key",
+                &format!("{}:1", EV_KEY_LEFTCTRL),
+                &format!("{}:1", EV_KEY_V),
+                &format!("{}:0", EV_KEY_V),
+                &format!("{}:0", EV_KEY_LEFTCTRL),
+            ],
+        ),
+    }
+}
+
+/// Whether secure event input is on: a password field has focus, or an
+/// app such as Terminal has turned on Secure Keyboard Entry. macOS drops
+/// synthesized keystrokes then, without an error. Never on other platforms.
+#[cfg(target_os = "macos")]
+pub fn secure_input_enabled() -> bool {
+    #[link(name = "Carbon", kind = "framework")]
+    extern "C" {
+        fn IsSecureEventInputEnabled() -> u8;
+    }
+    // SAFETY: plain C call without preconditions
+    unsafe { IsSecureEventInputEnabled() != 0 }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn secure_input_enabled() -> bool {
+    false
+}
+
+/// Presses the Left arrow key `count` times in the focused application.
+///
+/// All presses are sent from a single invocation so the caret moves in one
+/// burst rather than paying process startup cost per key.
+///
+/// # Errors
+///
+/// Returns an error if the keystroke simulation fails.
+pub fn send_left_arrows(count: usize) -> Result<(), String> {
+    if count == 0 {
+        return Ok(());
+    }
+    let Some(tool) = injector() else {
+        return Ok(());
+    };
+    match tool {
+        Tool::Osascript => run_applescript(&format!(
+            r#"
+                tell application "System Events"
+                    repeat {count} times
+                        key code {KEY_CODE_LEFT_ARROW}
+                    end repeat
+                end tell
+            "#
+        )),
+        Tool::Xdotool => run(tool, &["key", "--repeat", &count.to_string(), "Left"]),
+        Tool::Wtype => {
+            let args: Vec<&str> = std::iter::repeat(["-k", "Left"])
+                .take(count)
+                .flatten()
+                .collect();
+            run(tool, &args)
+        }
+        Tool::Ydotool => {
+            let press = [format!("{}:1", EV_KEY_LEFT), format!("{}:0", EV_KEY_LEFT)];
+            let mut args = vec!["key"];
+            for _ in 0..count {
+                args.extend(press.iter().map(String::as_str));
+            }
+            run(tool, &args)
+        }
+    }
+}
+
+/// Presses `chords` one after the other in the focused application.
+///
+/// Like [`send_left_arrows`], they are all sent from a single invocation,
+/// so they arrive in order without other input in between.
+///
+/// # Errors
+///
+/// Returns an error if keys can't be pressed on this platform, no injector
+/// is installed, or the keystroke simulation fails.
+pub fn send_chords(chords: &[Chord]) -> Result<(), String> {
+    if chords.is_empty() {
+        return Ok(());
+    }
+    let Some(tool) = injector() else {
+        if Tool::all().is_empty() {
+            return Err("pressing keys is not supported on this platform".to_string());
+        }
+        let tools: Vec<&str> = Tool::candidates(session_type())
+            .iter()
+            .map(|tool| tool.program())
+            .collect();
+        return Err(match tools.as_slice() {
+            [] => "no graphical session to press keys in".to_string(),
+            _ => format!("no tool to press keys with ({})", tools.join(" or ")),
+        });
+    };
+    match tool {
+        Tool::Osascript => {
+            let mut script = String::from("tell application \"System Events\"\n");
+            for chord in chords {
+                let press = match chord.key.mac_key_code() {
+                    Some(code) => format!("key code {}", code),
+                    // Characters, which System Events types
+                    None => format!("keystroke \"{}\"", chord.key.keysym()),
+                };
+                let modifiers: Vec<&str> =
+                    chord.modifiers.iter().map(|m| m.applescript()).collect();
+                if modifiers.is_empty() {
+                    script.push_str(&format!("{}\n", press));
+                } else {
+                    script.push_str(&format!("{} using {{{}}}\n", press, modifiers.join(", ")));
+                }
+            }
+            script.push_str("end tell");
+            run_applescript(&script)
+        }
+        Tool::Xdotool => {
+            let keys: Vec<String> = chords.iter().map(Chord::to_string).collect();
+            let mut args = vec!["key", "--clearmodifiers"];
+            args.extend(keys.iter().map(String::as_str));
+            run(tool, &args)
+        }
+        Tool::Wtype => {
+            let mut args = Vec::new();
+            for chord in chords {
+                for modifier in &chord.modifiers {
+                    args.extend(["-M".to_string(), modifier.xkb(tool).to_string()]);
+                }
+                args.extend(["-k".to_string(), chord.key.keysym()]);
+                for modifier in chord.modifiers.iter().rev() {
+                    args.extend(["-m".to_string(), modifier.xkb(tool).to_string()]);
+                }
+            }
+            run(tool, &args.iter().map(String::as_str).collect::<Vec<_>>())
+        }
+        Tool::Ydotool => {
+            let mut args = vec!["key".to_string()];
+            for chord in chords {
+                let codes: Vec<u32> = chord
+                    .modifiers
+                    .iter()
+                    .map(|m| m.event_code())
+                    .chain([chord.key.event_code()])
+                    .collect();
+                args.extend(codes.iter().map(|code| format!("{}:1", code)));
+                args.extend(codes.iter().rev().map(|code| format!("{}:0", code)));
+            }
+            run(tool, &args.iter().map(String::as_str).collect::<Vec<_>>())
+        }
+    }
+}
+
+/// Runs an AppleScript snippet through `osascript`, surfacing stderr on failure.
+fn run_applescript(script: &str) -> Result<(), String> {
+    run(Tool::Osascript, &["-e", script])
+}
+
+/// Runs `tool` with `args`, surfacing stderr on failure.
+fn run(tool: Tool, args: &[&str]) -> Result<(), String> {
+    let output = Command::new(tool.program())
+        .args(args)
+        .output()
+        .map_err(|e| format!("Process error: {}", e))?;
+
+    // Check if the command succeeded
+    // If not, return the error message from stderr
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `CmdOrCtrl` stands for here.
+    const CMD_OR_CTRL: Modifier = if cfg!(target_os = "macos") {
+        Modifier::Command
+    } else {
+        Modifier::Control
+    };
+
+    fn chord(s: &str) -> Result<Chord, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn chords_hold_their_modifiers_in_order() {
+        assert_eq!(
+            chord("CmdOrCtrl+Shift+Z"),
+            Ok(Chord {
+                modifiers: vec![CMD_OR_CTRL, Modifier::Shift],
+                key: Key::Char('z'),
+            })
+        );
+        assert_eq!(
+            chord("Alt+Super+PageUp"),
+            Ok(Chord {
+                modifiers: vec![Modifier::Alt, Modifier::Command],
+                key: Key::PageUp,
+            })
+        );
+        assert_eq!(
+            chord("Enter"),
+            Ok(Chord {
+                modifiers: Vec::new(),
+                key: Key::Enter,
+            })
+        );
+        // Held once however often it is named
+        assert_eq!(chord("Ctrl+Control+A"), chord("Ctrl+A"));
+    }
+
+    #[test]
+    fn names_ignore_case_and_spaces() {
+        assert_eq!(chord(" ctrl + SHIFT + z "), chord("Ctrl+Shift+Z"));
+        assert_eq!(chord("CMDORCTRL+a"), chord("CmdOrCtrl+A"));
+        assert_eq!(chord("ESC"), chord("Escape"));
+        assert_eq!(chord("return"), chord("Enter"));
+        assert_eq!(chord("7").unwrap().key, Key::Char('7'));
+    }
+
+    #[test]
+    fn unknown_keys_and_modifiers_are_named() {
+        assert_eq!(chord("Ctrl+F13"), Err("unknown key \"F13\"".into()));
+        assert_eq!(chord("Ctrl+é"), Err("unknown key \"é\"".into()));
+        assert_eq!(chord("Ctrl+!"), Err("unknown key \"!\"".into()));
+        assert_eq!(chord("Hyper+A"), Err("unknown modifier \"Hyper\"".into()));
+        assert_eq!(chord("Ctrl+"), Err("\"Ctrl+\" has no key".into()));
+        assert_eq!(chord(""), Err("\"\" has no key".into()));
+    }
+
+    #[test]
+    fn chords_display_as_xdotool_takes_them() {
+        assert_eq!(chord("Ctrl+Shift+Z").unwrap().to_string(), "ctrl+shift+z");
+        assert_eq!(chord("Alt+PageDown").unwrap().to_string(), "alt+Next");
+        assert_eq!(
+            chord("Cmd+Backspace").unwrap().to_string(),
+            "super+BackSpace"
+        );
+    }
+
+    #[test]
+    fn no_chords_press_nothing() {
+        assert_eq!(send_chords(&[]), Ok(()));
+    }
+}
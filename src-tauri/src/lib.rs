@@ -11,16 +11,101 @@
 //!
 //! - Window lifecycle management (show/hide/resize)
 //! - Clipboard operations (copy text)
-//! - System integration (macOS paste simulation)
+//! - System integration (macOS paste simulation, caret positioning)
 //!
 //! # Plugins
 //!
 //! - `tauri-plugin-opener`: For opening URLs/files
 //! - `tauri-plugin-global-shortcut`: For registering global hotkeys
 //! - `tauri-plugin-clipboard-manager`: For clipboard read/write operations
+//! - `tauri-plugin-autostart`: For launching at login
+//! - `tauri-plugin-single-instance`: For forwarding second launches to the running app
+//! - `tauri-plugin-deep-link`: For `wispr://` links from other apps
+//! - `tauri-plugin-notification`: For notifying failures the pill can't show
 
-use tauri::{AppHandle, Manager};
-use tauri_plugin_clipboard_manager::ClipboardExt;
+mod accessibility;
+mod api;
+mod app_paste;
+mod app_state;
+mod audio_file;
+mod autostart;
+mod benchmark;
+mod cancellation;
+mod capabilities;
+mod capture;
+mod chunking;
+mod clipboard_manager;
+mod clock;
+mod command_mode;
+mod confirm;
+mod crash;
+mod diagnostics;
+mod dictation;
+mod dictation_log;
+mod dock;
+mod errors;
+mod external;
+mod focus;
+mod frontmost;
+#[cfg(test)]
+mod harness;
+mod health;
+mod history;
+mod indicator;
+mod journal;
+mod keystroke;
+mod logging;
+mod loopback;
+mod meeting;
+mod metrics;
+mod microphone;
+mod notifications;
+mod paste;
+mod pill;
+mod postprocess;
+mod power;
+mod privacy;
+mod recent;
+mod recovery;
+mod rewrite;
+mod secrets;
+mod settings;
+mod shortcut_capture;
+mod shutdown;
+mod sinks;
+mod snippets;
+mod ticks;
+mod timings;
+mod transcription;
+mod tray;
+mod updater;
+mod webhook;
+mod window_state;
+mod windows;
+
+use std::ops::ControlFlow;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use app_state::{AppState, Phase, Press, SessionError, SessionEvent};
+use dictation::{Dictation, DictationState, HotkeyAction};
+use errors::ErrorCode;
+use history::NewHistoryEntry;
+use pill::PillState;
+use postprocess::PostProcessState;
+use timings::Stage;
+use tauri::{Emitter, Manager, State};
+use tauri_plugin_deep_link::DeepLinkExt;
+
+/// The runtime the app runs on. The tests run it on Tauri's mock runtime,
+/// which has no windows or webview (see `harness`).
+#[cfg(not(test))]
+pub(crate) type Runtime = tauri::Wry;
+#[cfg(test)]
+pub(crate) type Runtime = tauri::test::MockRuntime;
+
+pub(crate) type AppHandle = tauri::AppHandle<Runtime>;
+pub(crate) type WebviewWindow = tauri::WebviewWindow<Runtime>;
 
 /// Test command to verify Tauri communication
 #[tauri::command]
@@ -28,88 +113,524 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
-/// Shows the recording pill window in its initial state.
+/// Time budget for reading the focused field's text via Accessibility before
+/// the pill is shown. Past this we dictate without context.
+const TEXT_CONTEXT_BUDGET: Duration = Duration::from_millis(30);
+
+/// Starts a dictation and shows the recording pill.
 ///
 /// This command is called when the user presses the global hotkey (Alt+Space)
-/// to start recording. The window is resized to 400x100px, centered on screen,
-/// and made visible.
+/// to start recording. The pill is shown in its recording state (see
+/// [`pill::set_pill_state`]), and created first if it doesn't exist yet.
+///
+/// Before showing the window, the text around the caret in the still-focused
+/// target app is captured (when Accessibility access allows) so the transcript
+/// can later be formatted to fit it.
+///
+/// This is the state machine's entry into `Recording` (see [`app_state`]).
+/// Returns the new dictation's session id, which the commands about it
+/// take.
 ///
 /// # Errors
 ///
-/// Returns an error if the main window cannot be found or if any window
-/// operation fails.
+/// Returns an error if a recording can't start now, e.g. while the previous
+/// dictation is still being transcribed or pasted.
 #[tauri::command]
-fn show_recording_pill(app: tauri::AppHandle) -> Result<(), String> {
-    println!("show_recording_pill called!");
-    
-    let window = app
-        .get_webview_window("main")
-        .ok_or_else(|| "main window not found".to_string())?;
+fn show_recording_pill(
+    app: AppHandle,
+    postprocess: State<'_, PostProcessState>,
+    dictation: State<'_, DictationState>,
+) -> Result<String, String> {
+    let session_id = app_state::start_session(&app, "recording_started")?;
 
-    window
-        .set_size(tauri::PhysicalSize::new(400, 100))
-        .map_err(|e| e.to_string())?;
+    // Picked now, while the target app is still in front
+    let mut started = Dictation::start(session_id.clone());
+    started.language = Some(transcription::language_for_dictation(&app));
+    let previous = dictation.0.lock().unwrap().replace(started);
+    // A dictation that never reached the paste leaves its staged audio behind
+    if let Some(mut previous) = previous {
+        previous.discard_audio();
+    }
+    app.state::<recovery::RecoveryState>().begin_dictation();
+    timings::begin(&app, Some(&session_id));
+    app.state::<api::live::LiveHub>()
+        .begin_session(&app, &session_id);
+    let _ = app.emit(
+        "recording:started",
+        SessionEvent {
+            session_id: Some(session_id.clone()),
+        },
+    );
 
-    window.center().map_err(|e| e.to_string())?;
-    window.show().map_err(|e| e.to_string())?;
+    // Must happen before show(): once our window is up, the focused element
+    // may be ours instead of the target app's
+    *postprocess.context.lock().unwrap() =
+        accessibility::focused_text_context(TEXT_CONTEXT_BUDGET);
 
-    println!("Window shown!");
-    Ok(())
+    pill::set_state(&app, PillState::Recording);
+    ticks::start(&app, session_id.clone());
+
+    tracing::info!(%session_id, "Recording started");
+    Ok(session_id)
 }
 
-/// Shows the transcript pill window in its expanded state.
+/// What a press of the dictation hotkey did, the result of
+/// [`press_dictation_hotkey`].
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum HotkeyOutcome {
+    /// A dictation started; `replaced` is the one it cancelled, if any.
+    Started {
+        session_id: String,
+        replaced: Option<String>,
+    },
+    /// The press was ignored, for `reason`.
+    Ignored { reason: &'static str },
+}
+
+/// Payload of `hotkey:ignored`.
+#[derive(Debug, Clone, serde::Serialize)]
+struct HotkeyIgnored {
+    reason: &'static str,
+}
+
+/// Handles a press of a dictation hotkey: starts a dictation like
+/// [`show_recording_pill`], or ignores the press, as the state machine
+/// decides (see [`app_state::press`]). A press ignored because of what the
+/// app is doing is announced as `hotkey:ignored`, for the pill to flash;
+/// debounced ones are dropped silently. `action` says which hotkey it was
+/// (plain dictation when `None`).
 ///
-/// This command is used for testing the transcript UI. The window is resized
-/// to 600x150px to accommodate longer transcript text, centered, and shown.
+/// # Errors
+///
+/// Returns an error if the dictation couldn't start.
+#[tauri::command]
+fn press_dictation_hotkey(
+    app: AppHandle,
+    postprocess: State<'_, PostProcessState>,
+    dictation: State<'_, DictationState>,
+    action: Option<HotkeyAction>,
+) -> Result<HotkeyOutcome, String> {
+    let policy = (&app.state::<settings::SettingsState>().get().hotkeys).into();
+    let replaced = match app_state::press(&app, &clock::SystemClock, policy) {
+        Press::Start => None,
+        Press::Restart => {
+            let replaced = app_state::session_id(&app);
+            tracing::info!(session_id = ?replaced, "Restarting over a dictation being transcribed");
+            app_state::dismiss(&app, "restarted");
+            replaced
+        }
+        Press::Ignore { reason } => {
+            tracing::debug!(reason, "Ignoring a hotkey press");
+            if reason != "debounced" {
+                let _ = app.emit("hotkey:ignored", HotkeyIgnored { reason });
+            }
+            return Ok(HotkeyOutcome::Ignored { reason });
+        }
+    };
+    let session_id = show_recording_pill(app, postprocess, dictation.clone())?;
+    if let Some(current) = dictation.0.lock().unwrap().as_mut() {
+        current.action = action.unwrap_or_default();
+        current.command_mode = current.action == HotkeyAction::Command;
+    }
+    Ok(HotkeyOutcome::Started {
+        session_id,
+        replaced,
+    })
+}
+
+/// Stops recording the dictation `session_id`: the mic is closed and the
+/// transcript is being processed, so the pill shows it is transcribing.
+///
+/// A recording that stayed silent throughout (see [`ticks::is_silent`]) is
+/// not worth transcribing: it fails instead, its audio is dropped and the
+/// pill shows the error.
 ///
 /// # Errors
 ///
-/// Returns an error if the main window cannot be found or if any window
-/// operation fails.
+/// Returns [`SessionError::StaleSession`] if the dictation isn't the
+/// current one, [`SessionError::EmptyRecording`] if it was silent, and an
+/// error if it wasn't recording.
 #[tauri::command]
-fn show_transcript_pill(app: tauri::AppHandle) -> Result<(), String> {
-    let window = app
-        .get_webview_window("main")
-        .ok_or_else(|| "main window not found".to_string())?;
+#[tracing::instrument(skip(app))]
+fn stop_recording(app: AppHandle, session_id: String) -> Result<(), SessionError> {
+    let settings = app.state::<settings::SettingsState>().get().recording;
+    let silent_peak = app
+        .state::<DictationState>()
+        .0
+        .lock()
+        .unwrap()
+        .as_ref()
+        .filter(|d| d.session_id == session_id && ticks::is_silent(d, &settings))
+        .map(|d| d.peak_level);
+    if let Some(peak_level) = silent_peak {
+        app_state::transition_session(&app, &session_id, Phase::Error, "empty_recording")?;
+        tracing::warn!(peak_level, "Not transcribing a silent recording");
+        if let Some(mut dictation) = app.state::<DictationState>().0.lock().unwrap().take() {
+            dictation.discard_audio();
+        }
+        app.state::<recovery::RecoveryState>().clear_active();
+        app.state::<api::live::LiveHub>().end_session(&app);
+        let _ = app.emit(
+            "recording:stopped",
+            SessionEvent {
+                session_id: Some(session_id),
+            },
+        );
+        pill::set_state(&app, PillState::Error);
+        return Err(SessionError::EmptyRecording {
+            peak_dbfs: ticks::dbfs(peak_level),
+        });
+    }
 
-    window
-        .set_size(tauri::PhysicalSize::new(600, 150))
-        .map_err(|e| e.to_string())?;
+    app_state::transition_session(&app, &session_id, Phase::Transcribing, "recording_stopped")?;
+    let recorded = app
+        .state::<DictationState>()
+        .0
+        .lock()
+        .unwrap()
+        .as_mut()
+        .map(Dictation::stop_recording);
+    if let Some(recorded) = recorded {
+        timings::record(&app, Some(&session_id), Stage::Recording, recorded);
+    }
+    let _ = app.emit(
+        "recording:stopped",
+        SessionEvent {
+            session_id: Some(session_id),
+        },
+    );
+    pill::set_state(&app, PillState::Transcribing);
+    Ok(())
+}
 
-    window.center().map_err(|e| e.to_string())?;
-    window.show().map_err(|e| e.to_string())?;
+/// Cancels the dictation `session_id` (Escape, a click outside the pill)
+/// and hides the pill. Nothing is pasted, even if the transcript is already
+/// being processed, and `transcription:cancelled` is emitted.
+///
+/// # Errors
+///
+/// Returns [`SessionError::StaleSession`] if the dictation isn't the
+/// current one; the current dictation is left alone then. Returns an error
+/// if it is being pasted already.
+#[tauri::command]
+#[tracing::instrument(skip(app))]
+fn cancel_recording(app: AppHandle, session_id: String) -> Result<(), SessionError> {
+    app_state::cancel_session(&app, &session_id, "dismissed")?;
+    tracing::info!("Recording cancelled");
+    close_pill(&app, Some(session_id));
+    Ok(())
+}
 
+/// Cancels the dictation `session_id` after its recording stopped (Escape
+/// while it is transcribed, processed or awaiting confirmation) and hides
+/// the pill. The work still under way for it stops (see [`cancellation`]),
+/// `transcription:cancelled` is emitted, and nothing is pasted or added to
+/// history.
+///
+/// # Errors
+///
+/// Returns [`SessionError::StaleSession`] if the dictation isn't the
+/// current one, and an error if it is being pasted already, which can't be
+/// taken back.
+#[tauri::command]
+#[tracing::instrument(skip(app))]
+fn cancel_transcription(app: AppHandle, session_id: String) -> Result<(), SessionError> {
+    app_state::cancel_session(&app, &session_id, "dismissed").map_err(|e| match e {
+        // The one phase a current dictation can't be cancelled in
+        SessionError::Failed { .. } => "the transcript is being pasted already".to_string().into(),
+        e => e,
+    })?;
+    close_pill(&app, Some(session_id));
     Ok(())
 }
 
+/// Shows the transcript pill window in its expanded state.
+///
+/// This command is used for testing the transcript UI. Kept for backward
+/// compatibility; equivalent to `set_pill_state` with a two-line transcript.
+#[tauri::command]
+fn show_transcript_pill(app: AppHandle) {
+    pill::set_state(&app, PillState::Transcript { estimated_lines: 2 });
+}
+
 /// Hides the recording pill window.
 ///
 /// Called after transcription is complete and text has been pasted, or when
 /// the user cancels recording (Escape key). The window is hidden but not
 /// destroyed, allowing it to be shown again quickly.
 ///
-/// # Errors
+/// A dictation still in progress is cancelled, and a failed one dismissed.
+#[tauri::command]
+fn hide_recording_pill(app: AppHandle) {
+    let session_id = app_state::session_id(&app);
+    app_state::dismiss(&app, "dismissed");
+    close_pill(&app, session_id);
+}
+
+/// Stops the live transcript of the dictation `session_id`, which ended,
+/// announces `recording:stopped` and hides the pill.
+fn close_pill(app: &AppHandle, session_id: Option<String>) {
+    // Pasted dictations have ended already; this covers cancellation
+    app.state::<api::live::LiveHub>().end_session(app);
+    let _ = app.emit("recording:stopped", SessionEvent { session_id });
+
+    pill::set_state(app, PillState::Hidden);
+}
+
+/// Reports that a recording failed (microphone or transcription error).
 ///
-/// Returns an error if the main window cannot be found or if hiding fails.
+/// The mic is closed and the dictation won't be pasted, so everything showing
+/// a live recording (the tray animation, live caption clients) is stopped.
+/// The pill stays up in its error state to show the error.
+///
+/// Reports about another dictation than the current one (`session_id`) are
+/// ignored.
 #[tauri::command]
-fn hide_recording_pill(app: tauri::AppHandle) -> Result<(), String> {
-    let window = app
-        .get_webview_window("main")
-        .ok_or_else(|| "main window not found".to_string())?;
+fn report_recording_error(app: AppHandle, message: String, session_id: Option<String>) {
+    tracing::warn!(session_id = ?session_id, "Recording failed: {}", message);
+    let failed = match &session_id {
+        Some(session_id) => {
+            app_state::transition_session(&app, session_id, Phase::Error, "recording_failed")
+        }
+        None => app_state::transition(&app, Phase::Error, "recording_failed").map_err(Into::into),
+    };
+    match failed {
+        Ok(_) => {}
+        Err(e @ SessionError::StaleSession { .. }) => {
+            tracing::warn!(error = %e, "Ignoring the failure of another dictation");
+            return;
+        }
+        Err(e) => tracing::warn!(error = %e, "Failed to record the failure"),
+    }
+    app.state::<api::live::LiveHub>().end_session(&app);
+    notifications::failed(
+        &app,
+        notifications::Failure::Transcription,
+        ErrorCode::of_transcription_failure(&message),
+        session_id.as_deref(),
+        &message,
+        None,
+        notifications::pill_visible(&app),
+    );
+    let _ = app.emit("recording:stopped", SessionEvent { session_id });
+    pill::set_state(&app, PillState::Error);
+}
 
-    window.hide().map_err(|e| e.to_string())?;
-    
-    Ok(())
+/// Lets one paste run at a time.
+///
+/// Two pastes at once would interleave their clipboard writes and
+/// keystrokes: the wrong text lands, or a Cmd+V is sent while our window is
+/// still in front. The whole paste, from the clipboard write to the last
+/// keystroke, runs holding this lock, so nothing a paste does to the
+/// clipboard can touch the next one's text.
+#[derive(Default)]
+pub(crate) struct PasteQueue(tokio::sync::Mutex<()>);
+
+impl PasteQueue {
+    /// Waits for the turn to paste, held until the guard is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SessionError::Busy`] if another paste has the turn and
+    /// `when_busy` says not to wait.
+    pub(crate) async fn turn(
+        &self,
+        when_busy: WhenBusy,
+    ) -> Result<tokio::sync::MutexGuard<'_, ()>, SessionError> {
+        match when_busy {
+            WhenBusy::Queue => Ok(self.0.lock().await),
+            WhenBusy::Reject => self.0.try_lock().map_err(|_| SessionError::Busy),
+        }
+    }
+}
+
+/// What a paste does while another one is in progress.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WhenBusy {
+    /// Waits its turn.
+    #[default]
+    Queue,
+    /// Fails with [`SessionError::Busy`].
+    Reject,
+}
+
+/// Runs steps 1-5 of [`copy_and_paste_text`]: hiding our windows, clipboard
+/// write, paste keystroke and caret placement (see [`paste::run`]).
+/// Returns the text that was pasted (with any `{cursor}` marker removed).
+///
+/// Every insertion path (fresh dictations and re-pasted history items) goes
+/// through here so they behave identically.
+/// Must not run on the main thread: hiding the pill waits for the pill's
+/// window queue, which may need the main thread.
+///
+/// Pastes go through the [`PasteQueue`] one at a time. The app is `Pasting`
+/// meanwhile, and `Idle` (or `Error`) afterwards; a paste is refused in the
+/// middle of a dictation. A dictation's paste (`session_id`) is refused
+/// unless it is the current one, checked once its turn comes, so a queued
+/// paste of a dictation cancelled in the meantime doesn't run.
+///
+/// The steps run on a blocking worker, holding the queue's turn, since
+/// they wait for focus and the clipboard; `restore_clipboard` puts the
+/// clipboard's previous text back afterwards (see [`paste`]).
+pub(crate) async fn paste_text(
+    app: &AppHandle,
+    text: &str,
+    session_id: Option<&str>,
+    when_busy: WhenBusy,
+    restore_clipboard: bool,
+) -> Result<String, SessionError> {
+    let started = std::time::Instant::now();
+    let queue = app.state::<PasteQueue>();
+    let _turn = queue.turn(when_busy).await?;
+
+    match session_id {
+        Some(session_id) => {
+            app_state::transition_session(app, session_id, Phase::Pasting, "paste_started")?
+        }
+        None => app_state::transition(app, Phase::Pasting, "paste_started")?,
+    };
+    let queued = started.elapsed();
+    let queued_ms = queued.as_millis() as u64;
+    if session_id.is_none() {
+        timings::begin(app, None);
+    }
+    timings::record(app, session_id, Stage::PasteQueue, queued);
+    let pasted = {
+        let (app, text) = (app.clone(), text.to_string());
+        let session_id = session_id.map(str::to_string);
+        tauri::async_runtime::spawn_blocking(move || {
+            paste_steps(&app, &text, session_id.as_deref(), restore_clipboard)
+        })
+        .await
+        .unwrap_or_else(|e| Err(paste::PasteError::new(Stage::PasteQueue, e.to_string())))
+    };
+    let (to, reason) = match &pasted {
+        Ok(_) => {
+            tracing::info!(
+                session_id,
+                queued_ms,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                text = %logging::transcript(text),
+                "Pasted"
+            );
+            (Phase::Idle, "pasted")
+        }
+        Err(e) => {
+            tracing::warn!(
+                session_id,
+                queued_ms,
+                stage = ?e.stage,
+                code = %e.code(),
+                error = %e,
+                "Paste failed"
+            );
+            (Phase::Error, "paste_failed")
+        }
+    };
+    let _ = app_state::transition(app, to, reason);
+    pasted.map_err(|e| {
+        let code = e.code();
+        let text = e
+            .unpasted
+            .clone()
+            .unwrap_or_else(|| snippets::CursorPlacement::from_body(text).text);
+        let copied = paste::copy_fallback(app, &text);
+        notifications::failed(
+            app,
+            notifications::Failure::Paste { copied },
+            code,
+            session_id,
+            &e.message,
+            Some(&text),
+            notifications::pill_visible(app),
+        );
+        SessionError::PasteFailed {
+            code,
+            message: e.message,
+            partial: e.partial,
+            mismatch_at: e.mismatch_at,
+        }
+    })
+}
+
+/// Each step is timed as a stage of the dictation `session_id` (see
+/// [`timings`]).
+fn paste_steps(
+    app: &AppHandle,
+    text: &str,
+    session_id: Option<&str>,
+    restore_clipboard: bool,
+) -> Result<String, paste::PasteError> {
+    let panel = pill::is_panel(app);
+    // 150ms by default based on testing; configurable (`paste_delay_ms`)
+    // for slower systems
+    let settings = app.state::<settings::SettingsState>().get();
+    let manager = clipboard_manager::detect();
+    // Never in privacy mode: the text would be on dbus-send's command line
+    let store = manager.filter(|_| privacy::policy().clipboard_manager_store);
+    let options = paste::PasteOptions {
+        return_focus: !panel,
+        delay: Duration::from_millis(settings.paste_delay_ms),
+        chunking: settings.paste_chunking,
+        apps: settings.paste_apps,
+        reassert_clipboard: manager.is_some(),
+        restore_clipboard,
+    };
+    let stop = app.state::<paste::PasteStopState>();
+    stop.reset();
+    let platform = paste::PlatformBackend { app, store };
+    let pasted = paste::run(
+        app.state::<paste::PasteBackendState>().or(&platform),
+        &clock::SystemClock,
+        text,
+        options,
+        |stage, elapsed| timings::record(app, session_id, stage, elapsed),
+        |progress| {
+            tracing::info!(
+                session_id,
+                chunk = progress.pasted_chunks,
+                chunks = progress.chunks,
+                "Pasted chunk"
+            );
+            let _ = app.emit(
+                "paste:progress",
+                paste::PasteProgress {
+                    session_id: session_id.map(str::to_string),
+                    progress,
+                },
+            );
+            if stop.requested() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        },
+    )?;
+
+    // The panel stayed up through the paste; put it away now
+    if panel {
+        pill::set_state(app, PillState::Hidden);
+    }
+
+    Ok(pasted)
 }
 
 /// Copies text to clipboard and automatically pastes it into the active application.
 ///
 /// This is the core "Wispr-style" functionality. The function:
 ///
-/// 1. Writes the transcribed text to the system clipboard
-/// 2. Hides the Tauri window to return focus to the previously active app
-/// 3. Waits 150ms for the OS to register the focus shift
-/// 4. On macOS, simulates Cmd+V keystroke using AppleScript
+/// 1. Hides the pill (and on macOS the app, if other windows are open) to
+///    return focus to the previously active app
+/// 2. Waits briefly (150ms by default) for the OS to register the focus shift
+/// 3. Writes the transcribed text to the system clipboard, with line breaks
+///    as the app in front takes them (see [`app_paste`])
+/// 4. Simulates that app's paste keystroke (see [`keystroke`])
+/// 5. If the text contained a `{cursor}` marker, moves the caret back to it
+/// 6. Records the dictation in history (in the background)
+///
+/// A successful paste also clears the dictation's crash-recovery checkpoint.
 ///
 /// # Architecture Decision
 ///
@@ -118,11 +639,15 @@ fn hide_recording_pill(app: tauri::AppHandle) -> Result<(), String> {
 /// - Keeping our window focused would cause paste to fail
 /// - The 150ms delay ensures macOS completes the focus transition
 ///
+/// When the pill is a non-activating panel (`pill.non_activating`, macOS) it
+/// never had focus, so steps 1 and 2 are skipped and the pill is hidden after
+/// the paste instead.
+///
 /// # Platform Support
 ///
-/// Currently macOS-only. The paste simulation uses `osascript` which is
-/// macOS-specific. Windows/Linux support would require platform-specific
-/// implementations (SendInput API on Windows, xdotool on Linux).
+/// macOS and Linux (with `xdotool`, `wtype` or `ydotool` installed). On
+/// Windows the text is left on the clipboard for the user to paste; Windows
+/// support would require the SendInput API.
 ///
 /// # Errors
 ///
@@ -130,68 +655,316 @@ fn hide_recording_pill(app: tauri::AppHandle) -> Result<(), String> {
 /// - Clipboard write fails
 /// - Window hide fails
 /// - macOS paste simulation fails (on macOS)
+/// - Caret positioning fails after a successful paste (on macOS)
+/// - The dictation `session_id` isn't the current one, e.g. because it was
+///   cancelled while its transcript was processed ([`SessionError::StaleSession`]);
+///   nothing is pasted then
+/// - Another paste is in progress and `when_busy` is `reject`
+///   ([`SessionError::Busy`]); by default the paste waits for it instead
+/// - The paste had to be confirmed and was cancelled or timed out
+///   ([`SessionError::NotConfirmed`], see [`confirm`]); the text is left on
+///   the clipboard
+/// - The dictation is in command mode and `text` isn't voice commands
+///   ([`SessionError::UnrecognizedCommand`]); a dictation in command mode
+///   presses the keys of its commands instead of pasting (see
+///   [`command_mode`])
 #[tauri::command]
-async fn copy_and_paste_text(app: AppHandle, text: String) -> Result<(), String> {
-    // Step 1: Write to clipboard using Tauri's clipboard plugin
-    // The ClipboardExt trait must be imported for the clipboard() method
-    app.clipboard()
-        .write_text(text.clone())
-        .map_err(|e| e.to_string())?;
-
-    // Step 2: Hide the window to return focus to the previous application
-    // This is crucial - the paste keystroke must go to the app that was
-    // focused before our window appeared, not to our window
-    if let Some(window) = app.get_webview_window("main") {
-        window.hide().map_err(|e| e.to_string())?;
-    }
-
-    // Step 3: Small delay to ensure the OS registers the clipboard change
-    // and completes the focus shift. Without this, paste may fail.
-    // Tuned to 150ms based on testing - may need adjustment on slower systems
-    std::thread::sleep(std::time::Duration::from_millis(150));
-
-    // Step 4: Simulate Cmd+V keystroke (macOS only)
-    // Uses AppleScript via osascript command to send keystroke to System Events
-    // This works reliably across all macOS applications
-    #[cfg(target_os = "macos")]
-    {
-        use std::process::Command;
-        
-        // AppleScript to simulate Cmd+V
-        // System Events is the macOS accessibility framework that handles
-        // keyboard/mouse simulation
-        let script = r#"
-            tell application "System Events"
-                keystroke "v" using command down
-            end tell
-        "#;
-        
-        let output = Command::new("osascript")
-            .arg("-e")
-            .arg(script)
-            .output()
-            .map_err(|e| format!("Process error: {}", e))?;
-
-        // Check if osascript command succeeded
-        // If not, return the error message from stderr
-        if !output.status.success() {
-            return Err(String::from_utf8_lossy(&output.stderr).to_string());
+#[tracing::instrument(skip(app, text))]
+async fn copy_and_paste_text(
+    app: AppHandle,
+    text: String,
+    session_id: String,
+    when_busy: Option<WhenBusy>,
+) -> Result<(), SessionError> {
+    cancellation::token(&app, &session_id)?.check(&app)?;
+    let command_mode = app
+        .state::<DictationState>()
+        .0
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|dictation| dictation.session_id == session_id && dictation.command_mode);
+    if command_mode {
+        let when_busy = when_busy.unwrap_or_default();
+        let ran = command_mode::run(&app, &session_id, &text, when_busy).await;
+        if !matches!(ran, Err(SessionError::StaleSession { .. })) {
+            end_unpasted(&app, session_id);
+        }
+        return ran;
+    }
+    if let Err(e) = confirm::await_confirmation(&app, &session_id, &text).await {
+        if let SessionError::NotConfirmed { .. } = e {
+            end_unpasted(&app, session_id);
         }
+        return Err(e);
+    }
+    let when_busy = when_busy.unwrap_or_default();
+    let pasted = paste_text(&app, &text, Some(&session_id), when_busy, false).await?;
+    app.state::<recovery::RecoveryState>().clear_active();
+    timings::complete(&app, &session_id);
+    app.state::<api::live::LiveHub>().end_session(&app);
+    let _ = app.emit(
+        "recording:stopped",
+        SessionEvent {
+            session_id: Some(session_id.clone()),
+        },
+    );
+
+    // Step 6: The paste succeeded, so the dictation is complete. Record it
+    // off the paste path
+    let finished = app.state::<DictationState>().0.lock().unwrap().take();
+    if let Some(dictation) = &finished {
+        sinks::deliver(&app, dictation, &pasted);
+        recent::spawn_push(&app, &pasted);
+    }
+    if let Some(Dictation {
+        session_id,
+        raw_text: Some(raw_text),
+        provider,
+        language,
+        duration_ms,
+        audio_path,
+        audio_ms,
+        peak_level,
+        ..
+    }) = finished
+    {
+        history::spawn_record(
+            app.clone(),
+            NewHistoryEntry {
+                raw_text,
+                processed_text: pasted,
+                target_app: None,
+                provider,
+                duration_ms,
+                language,
+                audio_path,
+                session_id: Some(session_id),
+                // Only known for recordings the frontend captured
+                peak_level: (audio_ms > 0).then_some(peak_level),
+                profile: app.state::<settings::SettingsState>().get().profiles.active,
+            },
+        );
     }
 
     Ok(())
 }
 
+/// Ends the dictation `session_id`, which didn't paste on purpose: it wasn't
+/// confirmed, or was voice commands. There is nothing to recover.
+fn end_unpasted(app: &AppHandle, session_id: String) {
+    app.state::<recovery::RecoveryState>().clear_active();
+    app.state::<api::live::LiveHub>().end_session(app);
+    let _ = app.emit(
+        "recording:stopped",
+        SessionEvent {
+            session_id: Some(session_id),
+        },
+    );
+}
+
+/// Command-line flag that starts the app in the background, like the
+/// `startup.start_hidden` setting.
+const START_HIDDEN_FLAG: &str = "--hidden";
+
+/// Manages the state the commands find from the start, and registers the
+/// commands. Shared with the tests' `harness`, so they drive the real
+/// handlers; the state kept in the app data dir is managed once the app is
+/// built, and the paste backend by the caller.
+fn with_commands(builder: tauri::Builder<Runtime>) -> tauri::Builder<Runtime> {
+    builder
+        .manage(postprocess::PostProcessState::default())
+        .manage(rewrite::RewriteState::default())
+        .manage(DictationState::default())
+        .manage(dictation_log::DictationLogState::default())
+        .manage(Mutex::new(AppState::default()))
+        .manage(crash::CrashState::default())
+        .manage(timings::TimingsState::default())
+        .manage(health::HealthState::default())
+        .manage(notifications::NotificationState::default())
+        .manage(shutdown::ShutdownState::default())
+        .manage(power::PowerState::default())
+        .manage(PasteQueue::default())
+        .manage(paste::PasteStopState::default())
+        .manage(cancellation::CancelState::default())
+        .manage(external::ExternalState::default())
+        .manage(api::ApiState::default())
+        .manage(api::live::LiveHub::default())
+        .manage(tray::TrayState::default())
+        .manage(pill::PillWindowState::default())
+        .manage(windows::WindowState::default())
+        .manage(history::prune::RetentionState::default())
+        .manage(history::audio::LateAudioState::default())
+        .manage(history::stats::StatsState::default())
+        .manage(loopback::LoopbackState::default())
+        .manage(indicator::IndicatorState::default())
+        .manage(focus::FocusState::default())
+        .manage(metrics::MetricsState::default())
+        .manage(updater::UpdaterState::default())
+        .manage(shortcut_capture::CaptureState::default())
+        .manage(recent::RecentState::default())
+        .manage(confirm::ConfirmState::default())
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            app_state::get_app_state,
+            logging::get_log_directory,
+            logging::log_frontend,
+            diagnostics::export_diagnostics,
+            privacy::get_privacy_status,
+            health::health_check,
+            updater::check_for_updates,
+            updater::download_and_install_update,
+            updater::set_update_channel,
+            shortcut_capture::capture_next_shortcut,
+            shortcut_capture::report_shortcut_key,
+            shortcut_capture::cancel_shortcut_capture,
+            capabilities::get_paste_capabilities,
+            focus::get_focus_support,
+            crash::get_last_crash,
+            crash::dismiss_crash,
+            crash::trigger_test_panic,
+            timings::get_last_timings,
+            ticks::report_captured_audio,
+            ticks::report_speech,
+            microphone::report_input_device_change,
+            stop_recording,
+            cancel_recording,
+            cancel_transcription,
+            show_recording_pill,
+            press_dictation_hotkey,
+            show_transcript_pill,
+            hide_recording_pill,
+            pill::set_pill_state,
+            pill::set_pill_window_level,
+            pill::set_pill_appearance,
+            pill::get_pill_appearance,
+            pill::set_pill_hit_region,
+            pill::debug_pill_hit_region,
+            pill::list_monitors,
+            pill::get_monitors,
+            pill::get_cursor_position,
+            pill::set_pill_position,
+            pill::reset_pill_position,
+            pill::start_pill_drag,
+            pill::resize_pill_to_content,
+            pill::pill_interaction,
+            copy_and_paste_text,
+            report_recording_error,
+            transcription::list_providers,
+            errors::get_error_messages,
+            shutdown::shutdown_now,
+            transcription::get_transcription_config,
+            capture::report_capture_format,
+            transcription::set_app_language,
+            transcription::remove_app_language,
+            postprocess::process_transcript,
+            postprocess::get_profanity_filter,
+            postprocess::set_profanity_filter_mode,
+            postprocess::add_profanity_word,
+            postprocess::remove_profanity_word,
+            postprocess::get_emoji_config,
+            postprocess::set_emoji_enabled,
+            postprocess::set_emoji_alias,
+            postprocess::remove_emoji_alias,
+            postprocess::set_emoji_shortcut,
+            postprocess::remove_emoji_shortcut,
+            rewrite::rewrite_text,
+            rewrite::get_rewrite_config,
+            rewrite::set_rewrite_config,
+            rewrite::set_rewrite_api_key,
+            history::list_history,
+            history::get_history_item,
+            history::search::search_history,
+            history::manage::pin_history_item,
+            history::manage::unpin_history_item,
+            history::manage::delete_history_item,
+            history::manage::clear_history,
+            history::manage::paste_history_item,
+            history::prune::get_retention_config,
+            history::prune::set_retention_config,
+            history::prune::prune_history_now,
+            history::export::export_history,
+            history::stats::get_usage_stats,
+            history::stats::get_stats_config,
+            history::stats::set_stats_config,
+            history::summary::get_weekly_summary,
+            metrics::get_metrics,
+            metrics::reset_metrics,
+            benchmark::run_benchmark,
+            benchmark::report_benchmark_run,
+            benchmark::list_benchmarks,
+            benchmark::clear_benchmarks,
+            webhook::get_webhook_status,
+            webhook::set_webhook_token,
+            webhook::clear_webhook_queue,
+            history::encryption::encrypt_history,
+            history::encryption::is_history_encrypted,
+            history::audio::stage_dictation_audio,
+            history::audio::get_audio_for_history_item,
+            history::speakers::update_speaker_label,
+            recovery::checkpoint_recording,
+            recovery::get_pending_recovery,
+            recovery::recover_pending,
+            recovery::discard_pending,
+            power::discard_interrupted_recording,
+            meeting::start_meeting,
+            meeting::add_meeting_segment,
+            meeting::next_meeting_segment,
+            meeting::read_meeting_segment,
+            meeting::complete_meeting_segment,
+            meeting::fail_meeting_segment,
+            meeting::stop_meeting,
+            meeting::report_meeting_source_lost,
+            loopback::get_system_audio_support,
+            loopback::start_system_audio,
+            settings::get_settings,
+            settings::update_settings,
+            settings::get_settings_meta,
+            settings::bundle::export_settings_bundle,
+            settings::bundle::import_settings_bundle,
+            settings::profiles::list_profiles,
+            settings::profiles::create_profile,
+            settings::profiles::switch_profile,
+            settings::profiles::delete_profile,
+            autostart::get_autostart_enabled,
+            autostart::set_autostart,
+            dock::set_dock_icon_visible,
+            external::take_pending_external_events,
+            external::read_audio_file,
+            audio_file::probe_audio_file,
+            api::get_api_token,
+            api::regenerate_api_token,
+            api::live::publish_live_transcript,
+            windows::open_settings_window,
+            windows::close_settings_window,
+            windows::open_history_window,
+            windows::open_recent_window,
+            recent::get_recent_transcripts,
+            recent::paste_recent_transcript,
+            confirm::confirm_paste,
+            confirm::cancel_paste,
+            paste::stop_paste
+        ])
+}
+
 /// Main entry point for the Tauri application.
 ///
 /// Initializes all plugins and registers command handlers. This function
 /// is called by Tauri when the application starts.
 ///
+/// With `--hidden` (or the `startup.start_hidden` setting) nothing is shown
+/// until the user presses the dictation hotkey.
+///
 /// # Plugins
 ///
 /// - `tauri-plugin-opener`: Allows opening URLs/files from the frontend
 /// - `tauri-plugin-global-shortcut`: Enables global hotkey registration
 /// - `tauri-plugin-clipboard-manager`: Provides clipboard read/write capabilities
+/// - `tauri-plugin-autostart`: Registers the app as a login item
+/// - `tauri-plugin-single-instance`: Keeps one running instance; later
+///   launches hand their arguments to it (see [`external`]) and exit
+/// - `tauri-plugin-deep-link`: Delivers `wispr://` links (see [`external`])
+/// - `tauri-plugin-notification`: Notifies failures (see [`notifications`])
 ///
 /// # Panics
 ///
@@ -199,19 +972,487 @@ async fn copy_and_paste_text(app: AppHandle, text: String) -> Result<(), String>
 /// in normal operation and indicates a critical configuration error.
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    println!("🚀 Tauri app starting...");
-    
-    tauri::Builder::default()
+    // Before anything can panic
+    crash::install();
+
+    let builder = tauri::Builder::<Runtime>::new()
+        // Must be registered first: a second launch forwards its arguments
+        // to the running instance and exits before anything else starts.
+        // A crashed instance can't block the next launch: its lock (a named
+        // mutex on Windows, a D-Bus name on Linux) dies with the process, and
+        // a leftover socket on macOS is replaced when nothing answers on it
+        .plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+            external::handle_second_launch(app, args, cwd);
+        }))
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .plugin(tauri_plugin_clipboard_manager::init())
-        .invoke_handler(tauri::generate_handler![
-            greet,
-            show_recording_pill,
-            show_transcript_pill,
-            hide_recording_pill,
-            copy_and_paste_text
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .plugin(tauri_plugin_notification::init())
+        .plugin(updater::plugin())
+        .plugin(tauri_plugin_autostart::init(
+            tauri_plugin_autostart::MacosLauncher::LaunchAgent,
+            None,
+        ))
+        .manage(paste::PasteBackendState::default());
+    with_commands(builder)
+        .setup(|app| {
+            // First, so everything below is logged
+            app.manage(logging::init(app.handle()));
+            tracing::info!(version = env!("CARGO_PKG_VERSION"), "Tauri app starting...");
+
+            // Settings and history live in the app data dir, which is only
+            // known once the app is built
+            let data_dir = app.path().app_data_dir()?;
+            let settings = settings::SettingsState::load(&data_dir);
+            let current = settings.get();
+            // Shown by the settings window, which asks for them
+            // (`get_settings_meta`) once it is up
+            for warning in settings.meta().warnings {
+                tracing::warn!("Settings: {}", warning);
+            }
+            app.manage(settings);
+            app.manage(window_state::WindowStore::open(&data_dir));
+            // Before anything can call a handler that uses them: applying the
+            // settings starts the local API, and launch arguments and links
+            // are handled below
+            app.manage(history::HistoryState::open(&data_dir));
+            app.manage(history::summary::SummaryState::new(&data_dir));
+            app.manage(benchmark::BenchmarkState::new(&data_dir));
+            app.manage(webhook::WebhookState::new(&data_dir));
+            app.manage(meeting::MeetingState::new(&data_dir));
+            // Offer anything an interrupted dictation left behind
+            // (the frontend asks for it with `get_pending_recovery`)
+            app.manage(recovery::RecoveryState::open(&data_dir, &current.recovery));
+
+            settings::apply(app.handle(), &current);
+            settings::watch::spawn_watcher(app.handle().clone());
+            autostart::reconcile(app.handle());
+
+            // Every window decision happens here. The Dock icon policy was
+            // applied with the settings above, and the pill window is
+            // created hidden either way
+            let start_hidden = std::env::args().any(|arg| arg == START_HIDDEN_FLAG)
+                || current.startup.start_hidden;
+            pill::ensure_main_window(app.handle())?;
+            tray::create(app.handle())?;
+            crash::open(app.handle(), &data_dir);
+            metrics::open(app.handle(), &data_dir);
+            if start_hidden {
+                tracing::info!("Started hidden; waiting for the hotkey");
+            }
+
+            history::summary::spawn_scheduler(app.handle().clone());
+            webhook::spawn_dispatcher(app.handle().clone());
+            dictation_log::spawn_writer(app.handle());
+            history::prune::spawn_pruner(app.handle().clone());
+
+            // Ends a recording when the system goes to sleep
+            power::watch(app.handle(), power::PlatformSource { app: app.handle() });
+            updater::spawn_checker(app.handle().clone());
+
+            // Launch arguments of the first instance (`--transcribe <file>`)
+            // go through the same path as those forwarded by later launches
+            let args: Vec<String> = std::env::args().skip(1).collect();
+            let cwd = std::env::current_dir().unwrap_or_default();
+            match external::parse_args(&args, &cwd) {
+                Ok(Some(action)) => external::dispatch(app.handle(), action),
+                Ok(None) => {}
+                Err(message) => external::report_error(app.handle(), message),
+            }
+
+            // wispr:// links: the one that started the app, then any opened
+            // later (forwarded by the single instance plugin on Windows and
+            // Linux, delivered to this process on macOS)
+            #[cfg(any(target_os = "linux", all(debug_assertions, windows)))]
+            if let Err(e) = app.deep_link().register_all() {
+                tracing::warn!("Failed to register wispr:// links: {}", e);
+            }
+            if let Ok(Some(urls)) = app.deep_link().get_current() {
+                external::handle_urls(app.handle(), urls);
+            }
+            let handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                external::handle_urls(&handle, event.urls());
+            });
+
+            Ok(())
+        })
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| match event {
+            // Held off while a dictation or history write is in flight
+            tauri::RunEvent::ExitRequested { api, .. } => shutdown::exit_requested(app, &api),
+            // Moves since the last write would be lost otherwise
+            tauri::RunEvent::Exit => {
+                app.state::<window_state::WindowStore>().flush();
+                metrics::flush(app);
+                focus::restore(app);
+                // Any panic recorded this run was survived
+                crash::clean_exit();
+            }
+            // Clicking a failure notification reopens the app
+            #[cfg(target_os = "macos")]
+            tauri::RunEvent::Reopen { .. } => notifications::recover(app),
+            _ => {}
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::harness::{wait_for, Harness};
+    use crate::paste::mock::{MockPasteBackend, Step};
+
+    /// Re-pastes, putting the clipboard back afterwards.
+    fn options() -> paste::PasteOptions {
+        paste::PasteOptions {
+            return_focus: true,
+            delay: Duration::from_millis(150),
+            chunking: Default::default(),
+            apps: Default::default(),
+            reassert_clipboard: false,
+            restore_clipboard: true,
+        }
+    }
+
+    #[test]
+    fn concurrent_pastes_run_one_at_a_time_in_turn_order() {
+        const PASTES: usize = 64;
+        let queue = Arc::new(PasteQueue::default());
+        let backend = Arc::new(MockPasteBackend::with_clipboard("the user's own"));
+        let turns = Arc::new(Mutex::new(Vec::new()));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most_in_flight = Arc::new(AtomicUsize::new(0));
+
+        tauri::async_runtime::block_on(async {
+            let pastes: Vec<_> = (0..PASTES)
+                .map(|index| {
+                    let queue = queue.clone();
+                    let backend = backend.clone();
+                    let turns = turns.clone();
+                    let in_flight = in_flight.clone();
+                    let most_in_flight = most_in_flight.clone();
+                    tauri::async_runtime::spawn(async move {
+                        // The steps run on a blocking worker, like paste_text's
+                        let _turn = queue.turn(WhenBusy::Queue).await?;
+                        let text = format!("dictation {}", index);
+                        turns.lock().unwrap().push(text.clone());
+                        let pasted = tauri::async_runtime::spawn_blocking(move || {
+                            let running = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                            most_in_flight.fetch_max(running, Ordering::SeqCst);
+                            let pasted = paste::run(
+                                &*backend,
+                                &MockClock::default(),
+                                &text,
+                                options(),
+                                |_, _| {},
+                                |_| ControlFlow::Continue(()),
+                            );
+                            in_flight.fetch_sub(1, Ordering::SeqCst);
+                            pasted
+                        })
+                        .await
+                        .unwrap();
+                        pasted.map_err(|e| SessionError::from(e.message))
+                    })
+                })
+                .collect();
+            for paste in pastes {
+                paste.await.unwrap().unwrap();
+            }
+        });
+
+        assert_eq!(most_in_flight.load(Ordering::SeqCst), 1);
+        let turns = turns.lock().unwrap().clone();
+        assert_eq!(turns.len(), PASTES);
+        // Every keystroke pasted its own text, in the order of the turns
+        assert_eq!(backend.pasted(), turns);
+        // No paste put back another one's text
+        assert_eq!(backend.clipboard(), "the user's own");
+    }
+
+    #[test]
+    fn busy_pastes_can_be_rejected() {
+        let queue = PasteQueue::default();
+        tauri::async_runtime::block_on(async {
+            let turn = queue.turn(WhenBusy::Queue).await.unwrap();
+            assert!(matches!(
+                queue.turn(WhenBusy::Reject).await,
+                Err(SessionError::Busy)
+            ));
+            drop(turn);
+            assert!(queue.turn(WhenBusy::Reject).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn a_dictation_is_recorded_transcribed_pasted_and_kept() {
+        let harness = Harness::new("dictation");
+        let app = harness.handle();
+
+        let session_id = harness.press_hotkey();
+        assert_eq!(app_state::phase(app), Phase::Recording);
+        let text = harness.transcribe(&session_id).unwrap();
+        assert_eq!(app_state::phase(app), Phase::Transcribing);
+        harness.paste(&session_id, &text).unwrap();
+
+        assert_eq!(app_state::phase(app), Phase::Idle);
+        assert_eq!(harness.backend.pasted(), [text.clone()]);
+        assert_eq!(harness.backend.clipboard(), text);
+        // Recorded in the background
+        wait_for("the history entry", || !harness.history().is_empty());
+        let history = harness.history();
+        assert_eq!(history.len(), 1);
+        let mock = app
+            .state::<settings::SettingsState>()
+            .get()
+            .transcription
+            .mock;
+        assert_eq!(history[0]["raw_text"], mock.text);
+        assert_eq!(history[0]["processed_text"], text);
+        assert_eq!(history[0]["provider"], "mock");
+        assert_eq!(history[0]["session_id"], session_id);
+        assert!(harness.events("paste:failed").is_empty());
+    }
+
+    #[test]
+    fn a_denied_microphone_fails_the_dictation_without_pasting() {
+        let harness = Harness::new("microphone-denied");
+        let app = harness.handle();
+
+        let session_id = harness.press_hotkey();
+        // What the webview reports when getUserMedia is refused
+        let failure = json!({
+            "message": "NotAllowedError: Permission denied",
+            "sessionId": session_id,
+        });
+        harness.invoke("report_recording_error", failure).unwrap();
+
+        assert_eq!(app_state::phase(app), Phase::Error);
+        let failed = harness.events("transcription:failed");
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0]["code"], json!(ErrorCode::PermMicrophone));
+        assert_eq!(failed[0]["session_id"], session_id);
+        // Dismissing the error pill ends it
+        harness.invoke("hide_recording_pill", json!({})).unwrap();
+        assert_eq!(app_state::phase(app), Phase::Idle);
+        assert!(harness.backend.calls().is_empty());
+        assert!(harness.history().is_empty());
+    }
+
+    #[test]
+    fn a_provider_failure_fails_the_dictation_without_pasting() {
+        let harness = Harness::with_settings(
+            "provider-failed",
+            json!({ "transcription": { "mock": { "fail": true } } }),
+        );
+        let app = harness.handle();
+
+        let session_id = harness.press_hotkey();
+        assert_eq!(harness.transcribe(&session_id), None);
+
+        assert_eq!(app_state::phase(app), Phase::Error);
+        let failed = harness.events("transcription:failed");
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0]["code"], json!(ErrorCode::Transcription));
+        assert!(harness.backend.calls().is_empty());
+        assert!(harness.history().is_empty());
+    }
+
+    #[test]
+    fn a_refused_keystroke_leaves_the_transcript_on_the_clipboard() {
+        let harness = Harness::new("keystroke-refused");
+        let app = harness.handle();
+        harness.backend.fail(Step::PasteKeystroke);
+
+        let session_id = harness.press_hotkey();
+        let text = harness.transcribe(&session_id).unwrap();
+        let error = harness.paste(&session_id, &text).unwrap_err();
+
+        // Without Accessibility access macOS refuses it as a permission
+        let code = if cfg!(target_os = "macos") && !accessibility::is_trusted() {
+            ErrorCode::PermAccessibility
+        } else {
+            ErrorCode::PasteKeystroke
+        };
+        assert_eq!(error["kind"], "paste_failed");
+        assert_eq!(error["code"], json!(code));
+        assert_eq!(app_state::phase(app), Phase::Error);
+        let failed = harness.events("paste:failed");
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0]["code"], json!(code));
+        assert_eq!(harness.backend.clipboard(), text);
+        // Only written once a paste succeeded
+        assert!(harness.history().is_empty());
+    }
+
+    #[test]
+    fn a_clipboard_that_cuts_the_text_short_says_where_in_the_error() {
+        let harness = Harness::new("clipboard-mismatch");
+        harness
+            .backend
+            .mangle_clipboard(|text| text.chars().take(3).collect());
+
+        let session_id = harness.press_hotkey();
+        let text = harness.transcribe(&session_id).unwrap();
+        let error = harness.paste(&session_id, &text).unwrap_err();
+
+        assert_eq!(error["kind"], "paste_failed");
+        assert_eq!(error["code"], json!(ErrorCode::ClipboardMismatch));
+        assert_eq!(error["mismatch_at"], 3);
+        assert!(error["partial"].is_null());
+        assert!(harness.backend.pasted().is_empty());
+        assert_eq!(harness.events("paste:failed").len(), 1);
+        assert!(harness.history().is_empty());
+    }
+
+    #[test]
+    fn a_dictation_creates_the_pill_window_when_it_is_missing() {
+        let harness = Harness::without_pill_window("pill-missing");
+        let app = harness.handle();
+        assert!(app.get_webview_window(pill::MAIN_WINDOW).is_none());
+
+        let session_id = harness.press_hotkey();
+        // Shown from the pill's window queue
+        wait_for("the pill window", || {
+            app.get_webview_window(pill::MAIN_WINDOW).is_some()
+        });
+        let text = harness.transcribe(&session_id).unwrap();
+        harness.paste(&session_id, &text).unwrap();
+
+        assert_eq!(app_state::phase(app), Phase::Idle);
+        assert_eq!(harness.backend.pasted(), [text]);
+    }
+
+    /// Checks that the dictation `session_id` was cancelled, once, and left
+    /// nothing pasted or kept.
+    fn assert_cancelled(harness: &Harness, session_id: &str) {
+        assert_eq!(app_state::phase(harness.handle()), Phase::Idle);
+        assert!(harness.backend.pasted().is_empty());
+        assert!(harness.history().is_empty());
+        let cancelled = harness.events("transcription:cancelled");
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0]["session_id"], session_id);
+    }
+
+    #[test]
+    fn a_cancelled_dictation_is_neither_pasted_nor_kept() {
+        let harness = Harness::new("cancelled");
+
+        let session_id = harness.press_hotkey();
+        let text = harness.transcribe(&session_id).unwrap();
+        harness
+            .invoke("cancel_transcription", json!({ "sessionId": session_id }))
+            .unwrap();
+        let error = harness.paste(&session_id, &text).unwrap_err();
+
+        assert_eq!(error["kind"], "stale_session");
+        assert!(harness.backend.calls().is_empty());
+        assert_cancelled(&harness, &session_id);
+    }
+
+    #[test]
+    fn a_dictation_cancelled_while_recording_is_neither_pasted_nor_kept() {
+        let harness = Harness::new("cancelled-recording");
+
+        let session_id = harness.press_hotkey();
+        harness
+            .invoke("cancel_recording", json!({ "sessionId": session_id }))
+            .unwrap();
+        let stopped = harness.invoke("stop_recording", json!({ "sessionId": session_id }));
+        let error = harness.paste(&session_id, "Too late").unwrap_err();
+
+        assert_eq!(stopped.unwrap_err()["kind"], "stale_session");
+        assert_eq!(error["kind"], "stale_session");
+        assert_cancelled(&harness, &session_id);
+    }
+
+    #[test]
+    fn a_dictation_cancelled_before_its_transcript_is_processed_is_neither_pasted_nor_kept() {
+        let harness = Harness::new("cancelled-transcribing");
+
+        let session_id = harness.press_hotkey();
+        harness
+            .invoke("stop_recording", json!({ "sessionId": session_id }))
+            .unwrap();
+        harness
+            .invoke("cancel_transcription", json!({ "sessionId": session_id }))
+            .unwrap();
+        let text = "This is a mock transcription.";
+        let processed = harness.invoke(
+            "process_transcript",
+            json!({ "text": text, "sessionId": session_id }),
+        );
+        let error = harness.paste(&session_id, text).unwrap_err();
+
+        assert_eq!(processed.unwrap_err()["kind"], "stale_session");
+        assert_eq!(error["kind"], "stale_session");
+        assert_cancelled(&harness, &session_id);
+    }
+
+    #[test]
+    fn a_dictation_cancelled_while_awaiting_confirmation_is_neither_pasted_nor_kept() {
+        let harness = Harness::with_settings(
+            "cancelled-confirming",
+            json!({ "confirm_before_paste": { "enabled": true } }),
+        );
+        let app = harness.handle();
+
+        let session_id = harness.press_hotkey();
+        let text = harness.transcribe(&session_id).unwrap();
+        let paste = harness.spawn_paste(&session_id, &text);
+        wait_for("the confirmation", || {
+            app_state::phase(app) == Phase::AwaitingConfirmation
+        });
+        harness
+            .invoke("cancel_transcription", json!({ "sessionId": session_id }))
+            .unwrap();
+        let error = paste.join().unwrap().unwrap_err();
+
+        assert_eq!(error["kind"], "not_confirmed");
+        assert_eq!(error["timed_out"], false);
+        assert_cancelled(&harness, &session_id);
+    }
+
+    #[test]
+    fn a_dictation_cancelled_while_its_paste_is_queued_is_neither_pasted_nor_kept() {
+        let harness = Harness::new("cancelled-queued");
+
+        let session_id = harness.press_hotkey();
+        let text = harness.transcribe(&session_id).unwrap();
+        // Another paste has the turn
+        let queue = harness.handle().state::<PasteQueue>();
+        let turn = tauri::async_runtime::block_on(queue.turn(WhenBusy::Queue)).unwrap();
+        let paste = harness.spawn_paste(&session_id, &text);
+        // Time to get in the queue; a paste that didn't yet is refused all
+        // the same, only before it
+        std::thread::sleep(Duration::from_millis(200));
+        harness
+            .invoke("cancel_transcription", json!({ "sessionId": session_id }))
+            .unwrap();
+        drop(turn);
+        let error = paste.join().unwrap().unwrap_err();
+
+        assert_eq!(error["kind"], "stale_session");
+        assert!(harness.backend.calls().is_empty());
+        assert_cancelled(&harness, &session_id);
+    }
+
+    #[test]
+    fn a_press_while_recording_is_ignored() {
+        let harness = Harness::new("press-while-recording");
+        let session_id = harness.press_hotkey();
+
+        let outcome = harness.invoke("press_dictation_hotkey", json!({})).unwrap();
+
+        assert_eq!(outcome["action"], "ignored");
+        assert_eq!(app_state::session_id(harness.handle()), Some(session_id));
+    }
 }
@@ -0,0 +1,279 @@
+//! Diagnostics logging.
+//!
+//! Everything the backend logs goes through `tracing`: to stdout, as
+//! before, and to a log file in the app's log directory that users can send
+//! along with a bug report ([`get_log_directory`]). The file rotates daily
+//! and the last [`KEPT_LOG_FILES`] days are kept.
+//!
+//! The level is `logging.level` (`info` by default) and follows the setting
+//! live. The `WISPR_LOG` environment variable overrides it with a full
+//! filter, e.g. `WISPR_LOG=wispr_clone_lib=debug,tauri=info`.
+//!
+//! Transcripts are the user's words and stay out of the logs: log one with
+//! [`transcript`], which only shows its length unless
+//! `logging.log_transcripts` is on and privacy mode off (the storage
+//! policy in [`crate::privacy`] decides).
+//!
+//! The file also has a line for every span closed (timed stages, commands)
+//! with how long it took.
+//!
+//! The most recent warning or error is also kept in memory ([`last_error`])
+//! for the diagnostics bundle.
+//!
+//! The webview logs into the same file with [`log_frontend`], under the
+//! `frontend` target, held to the same rule about transcripts.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{Manager, State};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::AppHandle;
+
+/// Environment variable overriding `logging.level`.
+const FILTER_VAR: &str = "WISPR_LOG";
+
+/// Log file names: `wispr-clone.<date>.log`.
+const LOG_FILE_PREFIX: &str = "wispr-clone";
+const LOG_FILE_SUFFIX: &str = "log";
+
+/// Days of logs kept.
+const KEPT_LOG_FILES: usize = 7;
+
+/// Target of the webview's messages, under ours so `logging.level`
+/// applies to them.
+const FRONTEND_TARGET: &str = concat!(env!("CARGO_CRATE_NAME"), "::frontend");
+
+/// How much is logged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// Our own events at this level; other crates' warnings and errors only.
+    fn filter(self) -> EnvFilter {
+        let level = match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        };
+        EnvFilter::new(format!("warn,{}={}", env!("CARGO_CRATE_NAME"), level))
+    }
+}
+
+/// What is logged.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingSettings {
+    /// Most detailed level logged; `WISPR_LOG` overrides it.
+    pub level: LogLevel,
+    /// Log the text of transcripts, for debugging. Off, only their length
+    /// is logged.
+    pub log_transcripts: bool,
+}
+
+/// Changes the level of the running subscriber; unset when `WISPR_LOG`
+/// decides it.
+static LEVEL: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// The most recent warning or error, see [`last_error`].
+static LAST_ERROR: Mutex<Option<LoggedError>> = Mutex::new(None);
+
+/// A warning or error that was logged.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoggedError {
+    /// Unix milliseconds.
+    pub at_ms: i64,
+    pub level: String,
+    /// Module that logged it.
+    pub target: String,
+    /// The message, followed by the event's fields.
+    pub message: String,
+}
+
+/// Keeps the most recent warning or error in [`LAST_ERROR`].
+struct LastError;
+
+impl<S: Subscriber> Layer<S> for LastError {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > Level::WARN {
+            return;
+        }
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        let at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        *LAST_ERROR.lock().unwrap() = Some(LoggedError {
+            at_ms,
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: message.0,
+        });
+    }
+}
+
+/// Formats an event as its message followed by `name=value` fields.
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            self.0.push_str(&format!("{:?}", value));
+        } else {
+            self.0.push_str(&format!("{}={:?}", field.name(), value));
+        }
+    }
+}
+
+/// Where the logs are written, managed for the life of the app.
+pub struct LogState {
+    /// The log directory; `None` if the file couldn't be opened.
+    directory: Option<PathBuf>,
+    /// Flushes the file when the app exits.
+    _guard: Option<WorkerGuard>,
+}
+
+impl LogState {
+    /// The log directory; `None` if the app logs to stdout only.
+    pub fn directory(&self) -> Option<&Path> {
+        self.directory.as_deref()
+    }
+}
+
+/// Starts logging, at the default level until the settings are applied.
+/// Call once, before anything logs.
+pub fn init(app: &AppHandle) -> LogState {
+    let from_env = std::env::var(FILTER_VAR)
+        .ok()
+        .and_then(|filter| EnvFilter::try_new(filter).ok());
+    let overridden = from_env.is_some();
+    let (filter, level) =
+        reload::Layer::new(from_env.unwrap_or_else(|| LogLevel::default().filter()));
+
+    let file = app
+        .path()
+        .app_log_dir()
+        .map_err(|e| e.to_string())
+        .and_then(|dir| {
+            RollingFileAppender::builder()
+                .rotation(Rotation::DAILY)
+                .filename_prefix(LOG_FILE_PREFIX)
+                .filename_suffix(LOG_FILE_SUFFIX)
+                .max_log_files(KEPT_LOG_FILES)
+                .build(&dir)
+                .map(|appender| (dir, appender))
+                .map_err(|e| e.to_string())
+        });
+    let (directory, guard, file_layer, no_file) = match file {
+        Ok((dir, appender)) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_span_events(FmtSpan::CLOSE);
+            (Some(dir), Some(guard), Some(layer), None)
+        }
+        Err(e) => (None, None, None, Some(e)),
+    };
+
+    let started = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(file_layer)
+        .with(LastError)
+        .try_init();
+    if started.is_ok() && !overridden {
+        let _ = LEVEL.set(level);
+    }
+    if let Some(e) = no_file {
+        tracing::warn!("Logging to stdout only; no log file: {}", e);
+    }
+    LogState {
+        directory,
+        _guard: guard,
+    }
+}
+
+/// Applies `logging` to the running subscriber.
+pub fn apply(logging: &LoggingSettings) {
+    if let Some(level) = LEVEL.get() {
+        if let Err(e) = level.reload(logging.level.filter()) {
+            tracing::warn!(error = %e, "Failed to change the log level");
+        }
+    }
+}
+
+/// Whether transcripts are logged in full (`logging.log_transcripts`,
+/// never in privacy mode).
+pub fn logs_transcripts() -> bool {
+    crate::privacy::policy().log_transcripts
+}
+
+/// The most recent warning or error logged, if any.
+pub fn last_error() -> Option<LoggedError> {
+    LAST_ERROR.lock().unwrap().clone()
+}
+
+/// A transcript as it may be logged: its text with
+/// `logging.log_transcripts`, otherwise only its length.
+pub fn transcript(text: &str) -> String {
+    if logs_transcripts() {
+        format!("{:?}", text)
+    } else {
+        format!("<{} chars>", text.chars().count())
+    }
+}
+
+/// Returns the directory the log files are written to, for the UI to
+/// reveal.
+///
+/// # Errors
+///
+/// Returns an error if the app logs to stdout only, because the log file
+/// couldn't be created.
+#[tauri::command]
+pub fn get_log_directory(state: State<'_, LogState>) -> Result<PathBuf, String> {
+    state
+        .directory()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| "no log file is being written".to_string())
+}
+
+/// Logs `message` from the webview at `level`. Like the backend's own
+/// messages, it must not hold transcript text, only its length.
+#[tauri::command]
+pub fn log_frontend(level: LogLevel, message: String) {
+    match level {
+        LogLevel::Error => tracing::error!(target: FRONTEND_TARGET, "{}", message),
+        LogLevel::Warn => tracing::warn!(target: FRONTEND_TARGET, "{}", message),
+        LogLevel::Info => tracing::info!(target: FRONTEND_TARGET, "{}", message),
+        LogLevel::Debug => tracing::debug!(target: FRONTEND_TARGET, "{}", message),
+        LogLevel::Trace => tracing::trace!(target: FRONTEND_TARGET, "{}", message),
+    }
+}
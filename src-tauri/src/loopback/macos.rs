@@ -0,0 +1,427 @@
+//! System audio capture with ScreenCaptureKit.
+//!
+//! ScreenCaptureKit only captures audio from macOS 13, so the framework is
+//! loaded at runtime rather than linked, and the app still starts on older
+//! systems. A stream hands its samples to an object implementing
+//! `SCStreamOutput`, registered at runtime like the pill's panel class; it
+//! is also the stream's delegate, to hear when the stream stops on its own.
+//! There is one capture at a time, so the [`Output`] it feeds lives in a
+//! static.
+
+use std::ffi::{c_char, c_void, CString};
+use std::ptr::{null, null_mut};
+use std::sync::{mpsc, Mutex, Once};
+use std::time::Duration;
+
+use block2::RcBlock;
+use core_foundation::base::CFRelease;
+use objc2::declare::ClassBuilder;
+use objc2::runtime::{AnyClass, AnyObject, AnyProtocol, Bool, MessageReceiver, Sel};
+use objc2::{msg_send, sel};
+use objc2_foundation::NSError;
+
+use super::{Capture, Output};
+
+/// Name of the class receiving the stream's samples.
+const OUTPUT_CLASS: &str = "WisprSystemAudioOutput";
+const FRAMEWORK: &str = "/System/Library/Frameworks/ScreenCaptureKit.framework/ScreenCaptureKit";
+/// First macOS version ScreenCaptureKit captures audio on.
+const FIRST_VERSION: u32 = 13;
+/// Rate the stream is asked to deliver.
+const SAMPLE_RATE: u32 = 48_000;
+/// How long ScreenCaptureKit gets to answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const RTLD_NOW: i32 = 2;
+/// `SCStreamOutputTypeAudio`.
+const OUTPUT_TYPE_AUDIO: isize = 1;
+/// `kCMSampleBufferFlag_AudioBufferList_Assure16ByteAlignment`.
+const ASSURE_16_BYTE_ALIGNMENT: u32 = 1;
+/// Buffers a sample buffer is read into: one per channel at most, and the
+/// stream is asked for one.
+const MAX_BUFFERS: usize = 2;
+
+/// Where the samples of the running capture go.
+static OUTPUT: Mutex<Option<Output>> = Mutex::new(None);
+
+#[repr(C)]
+struct AudioBuffer {
+    number_channels: u32,
+    data_byte_size: u32,
+    data: *mut c_void,
+}
+
+#[repr(C)]
+struct AudioBufferList {
+    number_buffers: u32,
+    buffers: [AudioBuffer; MAX_BUFFERS],
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+#[link(name = "CoreMedia", kind = "framework")]
+extern "C" {
+    fn CMSampleBufferGetAudioBufferListWithRetainedBlockBuffer(
+        sample_buffer: *mut c_void,
+        buffer_list_size_needed_out: *mut usize,
+        buffer_list_out: *mut AudioBufferList,
+        buffer_list_size: usize,
+        block_buffer_structure_allocator: *const c_void,
+        block_buffer_block_allocator: *const c_void,
+        flags: u32,
+        block_buffer_out: *mut *mut c_void,
+    ) -> i32;
+}
+
+extern "C" {
+    fn dlopen(path: *const c_char, mode: i32) -> *mut c_void;
+    fn sysctlbyname(
+        name: *const c_char,
+        old: *mut c_void,
+        old_len: *mut usize,
+        new: *mut c_void,
+        new_len: usize,
+    ) -> i32;
+    fn dispatch_queue_create(label: *const c_char, attr: *mut c_void) -> *mut AnyObject;
+}
+
+/// Whether this macOS captures system audio.
+pub fn support() -> Result<(), String> {
+    match major_version() {
+        Some(major) if major >= FIRST_VERSION => Ok(()),
+        Some(major) => Err(format!(
+            "system audio capture needs macOS {} or later (this is macOS {})",
+            FIRST_VERSION, major
+        )),
+        None => Err("couldn't tell which macOS this is".to_string()),
+    }
+}
+
+fn major_version() -> Option<u32> {
+    let name = CString::new("kern.osproductversion").ok()?;
+    let mut version = [0u8; 32];
+    let mut len = version.len();
+    // SAFETY: `version` holds `len` bytes
+    let status = unsafe {
+        sysctlbyname(
+            name.as_ptr(),
+            version.as_mut_ptr().cast(),
+            &mut len,
+            null_mut(),
+            0,
+        )
+    };
+    if status != 0 {
+        return None;
+    }
+    let version = std::str::from_utf8(&version[..len]).ok()?;
+    version
+        .trim_end_matches('\0')
+        .split('.')
+        .next()?
+        .parse()
+        .ok()
+}
+
+pub fn screen_recording_granted() -> bool {
+    unsafe { CGPreflightScreenCaptureAccess() }
+}
+
+/// Shows the system's prompt the first time; after that, only System
+/// Settings can grant it. A grant applies once the app restarts.
+pub fn request_screen_recording() {
+    unsafe {
+        CGRequestScreenCaptureAccess();
+    }
+}
+
+extern "C" fn did_output_sample_buffer(
+    _: &AnyObject,
+    _: Sel,
+    _stream: *mut AnyObject,
+    sample_buffer: *mut c_void,
+    kind: isize,
+) {
+    if kind != OUTPUT_TYPE_AUDIO {
+        return;
+    }
+    // SAFETY: the stream passes a live sample buffer for the call
+    let Some(samples) = (unsafe { mono_samples(sample_buffer) }) else {
+        return;
+    };
+    if let Some(output) = OUTPUT.lock().unwrap().as_mut() {
+        (output.samples)(&samples, SAMPLE_RATE);
+    }
+}
+
+extern "C" fn did_stop_with_error(
+    _: &AnyObject,
+    _: Sel,
+    _stream: *mut AnyObject,
+    error: *mut NSError,
+) {
+    // SAFETY: the stream passes a live error, if any, for the call
+    let message = unsafe { describe(error) }.unwrap_or_else(|| "the capture stopped".to_string());
+    if let Some(output) = OUTPUT.lock().unwrap().as_ref() {
+        (output.failed)(message);
+    }
+}
+
+/// The output class, registered on first use, once ScreenCaptureKit is
+/// loaded so its protocols can be adopted.
+fn output_class() -> Option<&'static AnyClass> {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let Some(superclass) = AnyClass::get("NSObject") else {
+            return;
+        };
+        let Some(mut builder) = ClassBuilder::new(OUTPUT_CLASS, superclass) else {
+            return;
+        };
+        for name in ["SCStreamOutput", "SCStreamDelegate"] {
+            if let Some(protocol) = AnyProtocol::get(name) {
+                builder.add_protocol(protocol);
+            }
+        }
+        unsafe {
+            builder.add_method(
+                sel!(stream:didOutputSampleBuffer:ofType:),
+                did_output_sample_buffer
+                    as extern "C" fn(&AnyObject, Sel, *mut AnyObject, *mut c_void, isize),
+            );
+            builder.add_method(
+                sel!(stream:didStopWithError:),
+                did_stop_with_error as extern "C" fn(&AnyObject, Sel, *mut AnyObject, *mut NSError),
+            );
+        }
+        builder.register();
+    });
+    AnyClass::get(OUTPUT_CLASS)
+}
+
+/// The samples of an audio sample buffer, mixed down to mono.
+///
+/// # Safety
+///
+/// `sample_buffer` must be a live `CMSampleBuffer` of 32-bit float audio.
+unsafe fn mono_samples(sample_buffer: *mut c_void) -> Option<Vec<f32>> {
+    let mut list: AudioBufferList = std::mem::zeroed();
+    let mut block_buffer: *mut c_void = null_mut();
+    let status = CMSampleBufferGetAudioBufferListWithRetainedBlockBuffer(
+        sample_buffer,
+        null_mut(),
+        &mut list,
+        std::mem::size_of::<AudioBufferList>(),
+        null(),
+        null(),
+        ASSURE_16_BYTE_ALIGNMENT,
+        &mut block_buffer,
+    );
+    if status != 0 {
+        return None;
+    }
+
+    // One buffer per channel, or one with the channels interleaved
+    let count = (list.number_buffers as usize).min(MAX_BUFFERS);
+    let channels: Vec<Vec<f32>> = list.buffers[..count]
+        .iter()
+        .filter(|buffer| !buffer.data.is_null())
+        .map(|buffer| {
+            let samples = std::slice::from_raw_parts(
+                buffer.data.cast::<f32>(),
+                buffer.data_byte_size as usize / std::mem::size_of::<f32>(),
+            );
+            let width = buffer.number_channels.max(1) as usize;
+            samples
+                .chunks_exact(width)
+                .map(|frame| frame.iter().sum::<f32>() / width as f32)
+                .collect()
+        })
+        .collect();
+    if !block_buffer.is_null() {
+        CFRelease(block_buffer.cast_const());
+    }
+
+    let frames = channels.iter().map(Vec::len).min()?;
+    Some(
+        (0..frames)
+            .map(|i| channels.iter().map(|channel| channel[i]).sum::<f32>() / channels.len() as f32)
+            .collect(),
+    )
+}
+
+/// The description of `error`, if there is one.
+///
+/// # Safety
+///
+/// `error` must be null or a live `NSError`.
+unsafe fn describe(error: *mut NSError) -> Option<String> {
+    error
+        .as_ref()
+        .map(|error| error.localizedDescription().to_string())
+}
+
+fn class(name: &str) -> Result<&'static AnyClass, String> {
+    AnyClass::get(name).ok_or_else(|| format!("{} is unavailable", name))
+}
+
+fn wait<T>(rx: &mpsc::Receiver<T>) -> Result<T, String> {
+    rx.recv_timeout(TIMEOUT)
+        .map_err(|_| "ScreenCaptureKit didn't answer".to_string())
+}
+
+/// An object passed on from a completion handler, retained.
+struct Retained(*mut AnyObject);
+
+// SAFETY: ScreenCaptureKit's objects may be used from any thread
+unsafe impl Send for Retained {}
+
+/// What can be shared, with its displays.
+unsafe fn shareable_content() -> Result<Retained, String> {
+    let class = class("SCShareableContent")?;
+    let (tx, rx) = mpsc::channel();
+    let handler = RcBlock::new(move |content: *mut AnyObject, error: *mut NSError| {
+        let result = if content.is_null() {
+            Err(unsafe { describe(error) }
+                .unwrap_or_else(|| "there is nothing to capture".to_string()))
+        } else {
+            let _: *mut AnyObject = unsafe { msg_send![content, retain] };
+            Ok(Retained(content))
+        };
+        let _ = tx.send(result);
+    });
+    let _: () = msg_send![class, getShareableContentWithCompletionHandler: &*handler];
+    wait(&rx)?
+}
+
+/// A running ScreenCaptureKit stream and the objects it needs, released
+/// once it has stopped.
+struct Stream {
+    stream: *mut AnyObject,
+    filter: *mut AnyObject,
+    configuration: *mut AnyObject,
+    receiver: *mut AnyObject,
+}
+
+// SAFETY: an `SCStream` may be stopped from any thread
+unsafe impl Send for Stream {}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        for object in [self.stream, self.filter, self.configuration, self.receiver] {
+            if !object.is_null() {
+                let _: () = unsafe { msg_send![object, release] };
+            }
+        }
+    }
+}
+
+impl Capture for Stream {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn stop(self: Box<Self>) {
+        OUTPUT.lock().unwrap().take();
+        let stream = self.stream;
+        // Keeps the stream alive until it has stopped
+        let handler = RcBlock::new(move |_error: *mut NSError| {
+            let _ = &self;
+        });
+        unsafe {
+            let _: () = msg_send![stream, stopCaptureWithCompletionHandler: &*handler];
+        }
+    }
+}
+
+/// Starts capturing what plays on the main display, without the app's own
+/// sounds, into `output`.
+pub fn start(output: Output) -> Result<Box<dyn Capture>, String> {
+    support()?;
+    let path = CString::new(FRAMEWORK).unwrap();
+    // SAFETY: a system framework, loaded once and kept
+    if unsafe { dlopen(path.as_ptr(), RTLD_NOW) }.is_null() {
+        return Err("failed to load ScreenCaptureKit".to_string());
+    }
+    let receiver_class = output_class().ok_or("failed to register the audio output class")?;
+
+    // SAFETY: every message below goes to a live object of the class it's
+    // documented for; the objects are retained until the stream is dropped
+    unsafe {
+        let content = shareable_content()?;
+        let displays: *mut AnyObject = msg_send![content.0, displays];
+        let display: *mut AnyObject = msg_send![displays, firstObject];
+        if display.is_null() {
+            let _: () = msg_send![content.0, release];
+            return Err("there is no display to capture the audio of".to_string());
+        }
+
+        let mut stream = Stream {
+            stream: null_mut(),
+            filter: null_mut(),
+            configuration: null_mut(),
+            receiver: msg_send![receiver_class, new],
+        };
+        let no_windows: *mut AnyObject = msg_send![class("NSArray")?, array];
+        let filter: *mut AnyObject = msg_send![class("SCContentFilter")?, alloc];
+        stream.filter = msg_send![filter, initWithDisplay: display, excludingWindows: no_windows];
+        let _: () = msg_send![content.0, release];
+
+        let configuration: *mut AnyObject = msg_send![class("SCStreamConfiguration")?, new];
+        stream.configuration = configuration;
+        let _: () = msg_send![configuration, setCapturesAudio: true];
+        let _: () = msg_send![configuration, setExcludesCurrentProcessAudio: true];
+        let _: () = msg_send![configuration, setSampleRate: SAMPLE_RATE as isize];
+        let _: () = msg_send![configuration, setChannelCount: 1isize];
+        // The stream always captures video as well; keep it tiny
+        let _: () = msg_send![configuration, setWidth: 2usize];
+        let _: () = msg_send![configuration, setHeight: 2usize];
+
+        let allocated: *mut AnyObject = msg_send![class("SCStream")?, alloc];
+        stream.stream = msg_send![
+            allocated,
+            initWithFilter: stream.filter,
+            configuration: configuration,
+            delegate: stream.receiver
+        ];
+        if stream.stream.is_null() {
+            return Err("failed to create the capture stream".to_string());
+        }
+
+        *OUTPUT.lock().unwrap() = Some(output);
+        let label = CString::new("wispr.system-audio").unwrap();
+        let queue = dispatch_queue_create(label.as_ptr(), null_mut());
+        let mut error: *mut NSError = null_mut();
+        // `msg_send!` can't spell `type:`
+        let added: Bool = (&*stream.stream).send_message(
+            Sel::register("addStreamOutput:type:sampleHandlerQueue:error:"),
+            (
+                stream.receiver,
+                OUTPUT_TYPE_AUDIO,
+                queue,
+                &mut error as *mut *mut NSError,
+            ),
+        );
+        if !added.as_bool() {
+            OUTPUT.lock().unwrap().take();
+            return Err(describe(error).unwrap_or_else(|| "failed to receive audio".to_string()));
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let handler = RcBlock::new(move |error: *mut NSError| {
+            let _ = tx.send(unsafe { describe(error) });
+        });
+        let _: () = msg_send![stream.stream, startCaptureWithCompletionHandler: &*handler];
+        match wait(&rx) {
+            Ok(None) => Ok(Box::new(stream)),
+            Ok(Some(message)) | Err(message) => {
+                OUTPUT.lock().unwrap().take();
+                Err(message)
+            }
+        }
+    }
+}
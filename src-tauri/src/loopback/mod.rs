@@ -0,0 +1,295 @@
+//! System audio (loopback) capture for meetings.
+//!
+//! To transcribe what the other people on a call say, a meeting can record
+//! what the computer plays, instead of the microphone or along with it. It
+//! is opt-in (`meeting.input` set to `system_audio` or
+//! `microphone_and_system_audio`): whatever plays through the speakers
+//! ends up in the transcript, and on macOS it takes the Screen Recording
+//! permission, which the system asks for the first time.
+//!
+//! The capture is native; the samples, mono 32-bit float at
+//! [`SystemAudioStarted::sample_rate`], are streamed to the webview over
+//! the channel given to [`start_system_audio`], in packets of about
+//! [`PACKET`]. There they join the same audio graph as the microphone and
+//! are resampled and segmented with it (see `AudioService.pushSamples`).
+//! The capture stops with the meeting ([`stop`]).
+//!
+//! When it can't start, or stops on its own (the output device went away),
+//! `audio:system-audio-failed` ([`SystemAudioFailed`]) says why, and the
+//! meeting carries on from the microphone (`meeting:source-lost`).
+//!
+//! Recording the microphone too (`microphone_and_system_audio`), both are
+//! mixed in the webview. The capture runs on its own clock, a little off
+//! the webview's, so the frontend stretches or squeezes what it is sent by
+//! a fraction of a percent to keep the two in step.
+//!
+//! # Platform Support
+//!
+//! - macOS 13 and later: ScreenCaptureKit audio, the app's own sounds
+//!   excluded.
+//! - Windows: WASAPI loopback of the default output device.
+//! - Elsewhere, and on older macOS: unsupported, as reported by
+//!   [`get_system_audio_support`] and the health check.
+
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "windows")]
+mod wasapi;
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::ipc::{Channel, InvokeResponseBody};
+use tauri::{Emitter, Manager};
+
+use crate::capabilities::{Permission, PermissionStatus};
+use crate::meeting::{self, MeetingSource};
+use crate::settings::SettingsState;
+use crate::AppHandle;
+
+/// About how much audio goes in one packet to the webview.
+const PACKET: Duration = Duration::from_millis(50);
+
+/// Why system audio can't be captured on other platforms.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const UNSUPPORTED: &str = "system audio capture isn't available on this platform";
+
+/// Where captured samples go.
+pub struct Output {
+    /// Mono samples, in order, as they are captured, and their rate.
+    pub samples: Box<dyn FnMut(&[f32], u32) + Send>,
+    /// The capture stopped on its own, and why.
+    pub failed: Box<dyn Fn(String) + Send + Sync>,
+}
+
+/// A running capture.
+pub trait Capture: Send {
+    /// Rate of the samples delivered.
+    fn sample_rate(&self) -> u32;
+    fn stop(self: Box<Self>);
+}
+
+/// Whether system audio can be captured here, and if not, why.
+#[cfg(target_os = "macos")]
+pub fn support() -> Result<(), String> {
+    macos::support()
+}
+
+/// Whether system audio can be captured here: always on Windows.
+#[cfg(target_os = "windows")]
+pub fn support() -> Result<(), String> {
+    Ok(())
+}
+
+/// Whether system audio can be captured here: never.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub fn support() -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+/// Whether the Screen Recording permission capturing needs is granted.
+#[cfg(target_os = "macos")]
+pub fn permission_granted() -> Option<bool> {
+    Some(macos::screen_recording_granted())
+}
+
+/// `None`: capturing needs no permission here.
+#[cfg(not(target_os = "macos"))]
+pub fn permission_granted() -> Option<bool> {
+    None
+}
+
+/// Asks for the permission capturing needs, where there is one.
+fn request_permission() {
+    #[cfg(target_os = "macos")]
+    macos::request_screen_recording();
+}
+
+#[cfg(target_os = "macos")]
+fn open(output: Output) -> Result<Box<dyn Capture>, String> {
+    macos::start(output)
+}
+
+#[cfg(target_os = "windows")]
+fn open(output: Output) -> Result<Box<dyn Capture>, String> {
+    wasapi::start(output)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn open(_output: Output) -> Result<Box<dyn Capture>, String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+/// Managed state: the capture of the meeting in progress, if any.
+#[derive(Default)]
+pub struct LoopbackState {
+    capture: Mutex<Option<(String, Box<dyn Capture>)>>,
+}
+
+/// Result of [`get_system_audio_support`].
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemAudioSupport {
+    /// Whether system audio can be captured here.
+    pub supported: bool,
+    /// Whether meetings are set to record it (`meeting.input`).
+    pub selected: bool,
+    /// The permission it needs, where there is one.
+    pub permission: Option<PermissionStatus>,
+    /// Why it isn't supported, or what it takes, for the user.
+    pub detail: String,
+}
+
+/// Payload of `start_system_audio`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemAudioStarted {
+    pub sample_rate: u32,
+}
+
+/// Payload of `audio:system-audio-failed`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemAudioFailed {
+    pub meeting_id: String,
+    pub message: String,
+}
+
+/// Reports whether meetings can record system audio here.
+#[tauri::command]
+pub fn get_system_audio_support(app: AppHandle) -> SystemAudioSupport {
+    let selected = app
+        .state::<SettingsState>()
+        .get()
+        .meeting
+        .input
+        .records_system_audio();
+    let permission = permission_granted().map(|granted| PermissionStatus {
+        permission: Permission::ScreenRecording,
+        granted: Some(granted),
+    });
+    let (supported, detail) = match support() {
+        Ok(()) if permission.is_some() => (
+            true,
+            "Needs the Screen Recording permission; the app's own sounds aren't recorded"
+                .to_string(),
+        ),
+        Ok(()) => (
+            true,
+            "Records what plays on the default output device".to_string(),
+        ),
+        Err(reason) => (false, reason),
+    };
+    SystemAudioSupport {
+        supported,
+        selected,
+        permission,
+        detail,
+    }
+}
+
+/// Starts capturing system audio for the meeting `meeting_id`, streaming
+/// the samples to `channel` (see the module docs).
+///
+/// # Errors
+///
+/// Returns an error, also emitted as `audio:system-audio-failed`, if
+/// meetings aren't set to record system audio, it isn't supported here,
+/// the permission is missing (it is asked for), or the capture can't
+/// start.
+#[tauri::command]
+pub async fn start_system_audio(
+    app: AppHandle,
+    meeting_id: String,
+    channel: Channel<InvokeResponseBody>,
+) -> Result<SystemAudioStarted, String> {
+    let handle = app.clone();
+    let id = meeting_id.clone();
+    let started = tauri::async_runtime::spawn_blocking(move || start(&handle, id, channel))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result);
+    if let Err(message) = &started {
+        failed(&app, &meeting_id, message.clone());
+    }
+    started
+}
+
+fn start(
+    app: &AppHandle,
+    meeting_id: String,
+    channel: Channel<InvokeResponseBody>,
+) -> Result<SystemAudioStarted, String> {
+    if !app
+        .state::<SettingsState>()
+        .get()
+        .meeting
+        .input
+        .records_system_audio()
+    {
+        return Err("meetings are set to record the microphone only (meeting.input)".to_string());
+    }
+    support()?;
+    if permission_granted() == Some(false) {
+        request_permission();
+        return Err(
+            "system audio needs the Screen Recording permission: allow wispr-clone in \
+             System Settings › Privacy & Security › Screen Recording, then restart it"
+                .to_string(),
+        );
+    }
+
+    let mut packet: Vec<f32> = Vec::new();
+    let handle = app.clone();
+    let id = meeting_id.clone();
+    let output = Output {
+        samples: Box::new(move |samples, sample_rate| {
+            packet.extend_from_slice(samples);
+            if packet.len() as u128 >= u128::from(sample_rate) * PACKET.as_millis() / 1000 {
+                let bytes = packet.drain(..).flat_map(f32::to_le_bytes).collect();
+                let _ = channel.send(InvokeResponseBody::Raw(bytes));
+            }
+        }),
+        failed: Box::new(move |message| failed(&handle, &id, message)),
+    };
+    let capture = open(output)?;
+    let sample_rate = capture.sample_rate();
+
+    tracing::info!(%meeting_id, sample_rate, "System audio capture started");
+    let previous = app
+        .state::<LoopbackState>()
+        .capture
+        .lock()
+        .unwrap()
+        .replace((meeting_id, capture));
+    if let Some((_, previous)) = previous {
+        previous.stop();
+    }
+    Ok(SystemAudioStarted { sample_rate })
+}
+
+/// Stops the system audio capture of the meeting `meeting_id`, if any.
+pub fn stop(app: &AppHandle, meeting_id: &str) {
+    let capture = {
+        let mut current = app.state::<LoopbackState>().capture.lock().unwrap();
+        match current.as_ref() {
+            Some((id, _)) if id == meeting_id => current.take(),
+            _ => None,
+        }
+    };
+    if let Some((_, capture)) = capture {
+        capture.stop();
+        tracing::info!(%meeting_id, "System audio capture stopped");
+    }
+}
+
+/// Announces that system audio can't be recorded for `meeting_id`.
+fn failed(app: &AppHandle, meeting_id: &str, message: String) {
+    tracing::warn!(%meeting_id, "System audio capture failed: {}", message);
+    meeting::source_lost(app, meeting_id, MeetingSource::SystemAudio, &message);
+    let _ = app.emit(
+        "audio:system-audio-failed",
+        SystemAudioFailed {
+            meeting_id: meeting_id.to_string(),
+            message,
+        },
+    );
+}
@@ -0,0 +1,485 @@
+//! System audio capture with WASAPI loopback.
+//!
+//! A shared-mode stream on the default output device, opened with
+//! `AUDCLNT_STREAMFLAGS_LOOPBACK`, records what the device plays. The
+//! stream is polled on a thread of its own, which owns every COM object:
+//! the interfaces are called through their vtables directly, like the
+//! pill's Win32 calls, the few of them this takes not being worth a
+//! binding crate.
+
+use std::ffi::c_void;
+use std::ptr::{null, null_mut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::{Capture, Output};
+use crate::capture;
+
+/// How long the stream buffers, in 100ns units: 100ms.
+const BUFFER_DURATION: i64 = 1_000_000;
+/// How often the stream is read, well within [`BUFFER_DURATION`].
+const POLL: Duration = Duration::from_millis(10);
+
+const COINIT_MULTITHREADED: u32 = 0x0;
+const CLSCTX_ALL: u32 = 0x17;
+/// `eRender`: an output device.
+const E_RENDER: i32 = 0;
+/// `eConsole`.
+const E_CONSOLE: i32 = 0;
+const AUDCLNT_SHAREMODE_SHARED: i32 = 0;
+const AUDCLNT_STREAMFLAGS_LOOPBACK: u32 = 0x0002_0000;
+/// The packet is silence, whatever its data.
+const AUDCLNT_BUFFERFLAGS_SILENT: u32 = 0x2;
+/// The output device went away (unplugged, disabled).
+const AUDCLNT_E_DEVICE_INVALIDATED: i32 = 0x8889_0004_u32 as i32;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// Only ever read by Windows
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Guid {
+    data1: u32,
+    data2: u16,
+    data3: u16,
+    data4: [u8; 8],
+}
+
+const CLSID_MM_DEVICE_ENUMERATOR: Guid = Guid {
+    data1: 0xBCDE_0395,
+    data2: 0xE52F,
+    data3: 0x467C,
+    data4: [0x8E, 0x3D, 0xC4, 0x57, 0x92, 0x91, 0x69, 0x2E],
+};
+const IID_IMM_DEVICE_ENUMERATOR: Guid = Guid {
+    data1: 0xA956_64D2,
+    data2: 0x9614,
+    data3: 0x4F35,
+    data4: [0xA7, 0x46, 0xDE, 0x8D, 0xB6, 0x36, 0x17, 0xE6],
+};
+const IID_IAUDIO_CLIENT: Guid = Guid {
+    data1: 0x1CB9_AD4C,
+    data2: 0xDBFA,
+    data3: 0x4C32,
+    data4: [0xB1, 0x78, 0xC2, 0xF5, 0x68, 0xA7, 0x03, 0xB2],
+};
+const IID_IAUDIO_CAPTURE_CLIENT: Guid = Guid {
+    data1: 0xC8AD_BD64,
+    data2: 0xE71E,
+    data3: 0x48A0,
+    data4: [0xA4, 0xDE, 0x18, 0x5C, 0x39, 0x5C, 0xD3, 0x17],
+};
+
+#[repr(C, packed)]
+struct WaveFormatEx {
+    format_tag: u16,
+    channels: u16,
+    samples_per_sec: u32,
+    _avg_bytes_per_sec: u32,
+    _block_align: u16,
+    bits_per_sample: u16,
+    _size: u16,
+}
+
+#[repr(C, packed)]
+struct WaveFormatExtensible {
+    _format: WaveFormatEx,
+    _valid_bits_per_sample: u16,
+    _channel_mask: u32,
+    sub_format: Guid,
+}
+
+type HResult = i32;
+type This = *mut c_void;
+
+// The vtables, in declaration order; slots this doesn't call are left
+// untyped
+#[repr(C)]
+struct IUnknown {
+    _query_interface: usize,
+    _add_ref: usize,
+    release: unsafe extern "system" fn(This) -> u32,
+}
+
+#[repr(C)]
+struct IMMDeviceEnumerator {
+    _unknown: IUnknown,
+    _enum_audio_endpoints: usize,
+    get_default_audio_endpoint:
+        unsafe extern "system" fn(This, flow: i32, role: i32, device: *mut This) -> HResult,
+}
+
+#[repr(C)]
+struct IMMDevice {
+    _unknown: IUnknown,
+    activate: unsafe extern "system" fn(
+        This,
+        iid: *const Guid,
+        context: u32,
+        params: *mut c_void,
+        interface: *mut This,
+    ) -> HResult,
+}
+
+#[repr(C)]
+struct IAudioClient {
+    _unknown: IUnknown,
+    initialize: unsafe extern "system" fn(
+        This,
+        share_mode: i32,
+        flags: u32,
+        buffer_duration: i64,
+        periodicity: i64,
+        format: *const WaveFormatEx,
+        session: *const Guid,
+    ) -> HResult,
+    _get_buffer_size: usize,
+    _get_stream_latency: usize,
+    _get_current_padding: usize,
+    _is_format_supported: usize,
+    get_mix_format: unsafe extern "system" fn(This, format: *mut *mut WaveFormatEx) -> HResult,
+    _get_device_period: usize,
+    start: unsafe extern "system" fn(This) -> HResult,
+    stop: unsafe extern "system" fn(This) -> HResult,
+    _reset: usize,
+    _set_event_handle: usize,
+    get_service: unsafe extern "system" fn(This, iid: *const Guid, service: *mut This) -> HResult,
+}
+
+#[repr(C)]
+struct IAudioCaptureClient {
+    _unknown: IUnknown,
+    get_buffer: unsafe extern "system" fn(
+        This,
+        data: *mut *mut u8,
+        frames: *mut u32,
+        flags: *mut u32,
+        device_position: *mut u64,
+        counter_position: *mut u64,
+    ) -> HResult,
+    release_buffer: unsafe extern "system" fn(This, frames: u32) -> HResult,
+    get_next_packet_size: unsafe extern "system" fn(This, frames: *mut u32) -> HResult,
+}
+
+#[link(name = "ole32")]
+extern "system" {
+    fn CoInitializeEx(reserved: *mut c_void, flags: u32) -> HResult;
+    fn CoUninitialize();
+    fn CoCreateInstance(
+        class: *const Guid,
+        outer: *mut c_void,
+        context: u32,
+        iid: *const Guid,
+        interface: *mut This,
+    ) -> HResult;
+    fn CoTaskMemFree(memory: *mut c_void);
+}
+
+/// A COM interface with the vtable `V`, released on drop.
+struct Com<V>(This, std::marker::PhantomData<V>);
+
+impl<V> Com<V> {
+    /// # Safety
+    ///
+    /// `this` must be a live interface with the vtable `V`, whose reference
+    /// is handed over.
+    unsafe fn new(this: This) -> Self {
+        Self(this, std::marker::PhantomData)
+    }
+
+    unsafe fn vtable(&self) -> &V {
+        &**self.0.cast::<*const V>()
+    }
+}
+
+impl<V> Drop for Com<V> {
+    fn drop(&mut self) {
+        unsafe {
+            ((**self.0.cast::<*const IUnknown>()).release)(self.0);
+        }
+    }
+}
+
+fn check(result: HResult, what: &str) -> Result<(), String> {
+    match result {
+        AUDCLNT_E_DEVICE_INVALIDATED => Err("the output device went away".to_string()),
+        result if result < 0 => Err(format!("{} failed (0x{:08X})", what, result as u32)),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Encoding {
+    Float32,
+    Int16,
+}
+
+/// The shape of the samples the device delivers.
+#[derive(Clone, Copy)]
+struct Format {
+    channels: usize,
+    sample_rate: u32,
+    encoding: Encoding,
+}
+
+impl Format {
+    /// `None` for encodings other than 32-bit float and 16-bit integer:
+    /// the mix format is nearly always float.
+    ///
+    /// # Safety
+    ///
+    /// `format` must be a live format, extensible if its tag says so.
+    unsafe fn of(format: *const WaveFormatEx) -> Option<Self> {
+        let header = &*format;
+        let format_tag = header.format_tag;
+        let tag = match format_tag {
+            WAVE_FORMAT_EXTENSIBLE => {
+                let extensible = &*format.cast::<WaveFormatExtensible>();
+                // The subformat's first field is the plain format tag
+                let sub_format = extensible.sub_format;
+                sub_format.data1 as u16
+            }
+            tag => tag,
+        };
+        let bits_per_sample = header.bits_per_sample;
+        let encoding = match (tag, bits_per_sample) {
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Encoding::Float32,
+            (WAVE_FORMAT_PCM, 16) => Encoding::Int16,
+            _ => return None,
+        };
+        let channels = header.channels;
+        Some(Self {
+            channels: usize::from(channels.max(1)),
+            sample_rate: header.samples_per_sec,
+            encoding,
+        })
+    }
+
+    /// Appends `frames` frames of `data` to `mono`, mixed down.
+    ///
+    /// # Safety
+    ///
+    /// `data` must hold `frames` frames in this format.
+    unsafe fn mix_down(&self, data: *const u8, frames: usize, mono: &mut Vec<f32>) {
+        let len = frames * self.channels;
+        match self.encoding {
+            Encoding::Float32 => {
+                let samples = std::slice::from_raw_parts(data.cast::<f32>(), len);
+                capture::mix_channels(samples, self.channels, 1, mono);
+            }
+            Encoding::Int16 => {
+                let samples = std::slice::from_raw_parts(data.cast::<i16>(), len);
+                let samples: Vec<f32> = samples.iter().map(|&s| f32::from(s) / 32768.0).collect();
+                capture::mix_channels(&samples, self.channels, 1, mono);
+            }
+        }
+    }
+}
+
+/// A started loopback stream, stopped on drop.
+struct Stream {
+    format: Format,
+    capture: Com<IAudioCaptureClient>,
+    client: Com<IAudioClient>,
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe {
+            (self.client.vtable().stop)(self.client.0);
+        }
+    }
+}
+
+impl Stream {
+    /// Opens and starts a loopback stream on the default output device.
+    ///
+    /// # Safety
+    ///
+    /// COM must be initialized on the calling thread, which the stream
+    /// must stay on.
+    unsafe fn open() -> Result<Self, String> {
+        let mut enumerator = null_mut();
+        check(
+            CoCreateInstance(
+                &CLSID_MM_DEVICE_ENUMERATOR,
+                null_mut(),
+                CLSCTX_ALL,
+                &IID_IMM_DEVICE_ENUMERATOR,
+                &mut enumerator,
+            ),
+            "creating the device enumerator",
+        )?;
+        let enumerator = Com::<IMMDeviceEnumerator>::new(enumerator);
+
+        let mut device = null_mut();
+        check(
+            (enumerator.vtable().get_default_audio_endpoint)(
+                enumerator.0,
+                E_RENDER,
+                E_CONSOLE,
+                &mut device,
+            ),
+            "finding the default output device",
+        )?;
+        let device = Com::<IMMDevice>::new(device);
+
+        let mut client = null_mut();
+        check(
+            (device.vtable().activate)(
+                device.0,
+                &IID_IAUDIO_CLIENT,
+                CLSCTX_ALL,
+                null_mut(),
+                &mut client,
+            ),
+            "opening the output device",
+        )?;
+        let client = Com::<IAudioClient>::new(client);
+
+        let mut mix_format = null_mut();
+        check(
+            (client.vtable().get_mix_format)(client.0, &mut mix_format),
+            "reading the output format",
+        )?;
+        let format = Format::of(mix_format);
+        let initialized = (client.vtable().initialize)(
+            client.0,
+            AUDCLNT_SHAREMODE_SHARED,
+            AUDCLNT_STREAMFLAGS_LOOPBACK,
+            BUFFER_DURATION,
+            0,
+            mix_format,
+            null(),
+        );
+        CoTaskMemFree(mix_format.cast());
+        let format = format.ok_or("the output device's sample format isn't supported")?;
+        check(initialized, "opening the loopback stream")?;
+
+        let mut capture = null_mut();
+        check(
+            (client.vtable().get_service)(client.0, &IID_IAUDIO_CAPTURE_CLIENT, &mut capture),
+            "opening the loopback stream",
+        )?;
+        let capture = Com::<IAudioCaptureClient>::new(capture);
+        check(
+            (client.vtable().start)(client.0),
+            "starting the loopback stream",
+        )?;
+        Ok(Self {
+            format,
+            capture,
+            client,
+        })
+    }
+
+    /// Hands what the stream captures to `output` until `stop` is set.
+    ///
+    /// # Safety
+    ///
+    /// Must run on the thread the stream was opened on.
+    unsafe fn pump(&self, output: &mut Output, stop: &AtomicBool) -> Result<(), String> {
+        let capture = self.capture.vtable();
+        let mut mono = Vec::new();
+        while !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(POLL);
+            loop {
+                let mut packet = 0;
+                check(
+                    (capture.get_next_packet_size)(self.capture.0, &mut packet),
+                    "reading the loopback stream",
+                )?;
+                if packet == 0 {
+                    break;
+                }
+                let mut data = null_mut();
+                let mut frames = 0;
+                let mut flags = 0;
+                check(
+                    (capture.get_buffer)(
+                        self.capture.0,
+                        &mut data,
+                        &mut frames,
+                        &mut flags,
+                        null_mut(),
+                        null_mut(),
+                    ),
+                    "reading the loopback stream",
+                )?;
+                mono.clear();
+                if flags & AUDCLNT_BUFFERFLAGS_SILENT != 0 || data.is_null() {
+                    mono.resize(frames as usize, 0.0);
+                } else {
+                    self.format.mix_down(data, frames as usize, &mut mono);
+                }
+                (capture.release_buffer)(self.capture.0, frames);
+                (output.samples)(&mono, self.format.sample_rate);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The capture thread: reports the stream's rate, or why it couldn't be
+/// opened, to `ready`, then pumps it until `stop` is set.
+fn run(mut output: Output, stop: &AtomicBool, ready: mpsc::Sender<Result<u32, String>>) {
+    // SAFETY: the stream is opened, used and dropped on this thread, before
+    // COM is uninitialized
+    unsafe {
+        CoInitializeEx(null_mut(), COINIT_MULTITHREADED);
+        match Stream::open() {
+            Ok(stream) => {
+                let _ = ready.send(Ok(stream.format.sample_rate));
+                if let Err(message) = stream.pump(&mut output, stop) {
+                    (output.failed)(message);
+                }
+            }
+            Err(message) => {
+                let _ = ready.send(Err(message));
+            }
+        }
+        CoUninitialize();
+    }
+}
+
+struct Loopback {
+    sample_rate: u32,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Capture for Loopback {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn stop(self: Box<Self>) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+/// Starts capturing what the default output device plays into `output`.
+pub fn start(output: Output) -> Result<Box<dyn Capture>, String> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let (ready, started) = mpsc::channel();
+    let stopping = stop.clone();
+    let thread = std::thread::Builder::new()
+        .name("system-audio".into())
+        .spawn(move || run(output, &stopping, ready))
+        .map_err(|e| e.to_string())?;
+    match started.recv() {
+        Ok(Ok(sample_rate)) => Ok(Box::new(Loopback {
+            sample_rate,
+            stop,
+            thread,
+        })),
+        Ok(Err(message)) => Err(message),
+        Err(_) => Err("the capture thread exited".to_string()),
+    }
+}
@@ -0,0 +1,914 @@
+//! Meeting mode: long recordings transcribed as they go.
+//!
+//! A meeting is a session of its own, apart from the dictation state
+//! machine in [`crate::app_state`]: the hotkey flow works as usual while
+//! one runs. It is started and stopped from the tray
+//! (`external:toggle-meeting`, see [`crate::external`]) or by the frontend
+//! ([`start_meeting`], [`stop_meeting`]).
+//!
+//! Recording and transcription live in the webview, as for dictations. The
+//! frontend records the meeting in segments, each a file of its own, cut
+//! at a pause once it is [`MIN_SEGMENT`] long and in any case at
+//! [`MAX_SEGMENT`], and hands each to [`add_meeting_segment`]. Segments are
+//! written to `meetings/<id>/` in the app data directory as they arrive, so
+//! an hours-long meeting never sits in memory. The backend keeps the queue:
+//! the frontend asks for the next segment to transcribe
+//! ([`next_meeting_segment`]), reads it ([`read_meeting_segment`]) and
+//! reports the transcript ([`complete_meeting_segment`]) or the failure
+//! ([`fail_meeting_segment`]). A failed segment is retried after a growing
+//! delay, up to [`MAX_ATTEMPTS`] times, so a provider hiccup only holds
+//! that part of the transcript back.
+//!
+//! The transcript goes into one history entry, created with the first
+//! segment transcribed and updated with every one after it, one
+//! `[hh:mm:ss]` paragraph per segment. Once the meeting is stopped and the
+//! queue is drained, the entry is finalized with a heading and the length
+//! of the meeting, and the segments' audio deleted; that of segments that
+//! could never be transcribed is kept.
+//!
+//! A meeting records the microphone, what the computer plays (the other
+//! side of a call; see [`crate::loopback`]), or both, as `meeting.input`
+//! says. Both sources are mixed into one stream in the webview, each at its
+//! own gain (`meeting.microphone_gain_db`, `meeting.system_audio_gain_db`),
+//! and go through the same segments and queue. When one of two sources
+//! fails mid-meeting, the other carries on and `meeting:source-lost` says
+//! so.
+//!
+//! Events:
+//!
+//! - `meeting:started` ([`MeetingStarted`])
+//! - `meeting:segment` ([`SegmentTranscribed`]): a segment's transcript
+//! - `meeting:segment-failed` ([`SegmentFailed`]): a segment given up on
+//! - `meeting:source-lost` ([`SourceLost`]): the microphone or system audio
+//!   stopped; the meeting carries on from what is left
+//! - `meeting:limit-reached` (`{ "meeting_id": ... }`): the meeting ran for
+//!   `meeting.max_duration_minutes`; the frontend stops it
+//! - `meeting:finished` ([`MeetingFinished`])
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager, State};
+
+use crate::capture::CaptureQuality;
+use crate::history::speakers::{self, Paragraph, SpeakerTranscript};
+use crate::history::{HistoryChange, HistoryState, NewHistoryEntry};
+use crate::settings::{Settings, SettingsState};
+use crate::AppHandle;
+
+/// Directory under the app data dir holding the audio of meetings.
+const MEETINGS_DIR: &str = "meetings";
+
+/// Shortest segment the frontend cuts at a pause.
+pub const MIN_SEGMENT: Duration = Duration::from_secs(30);
+
+/// Longest segment; the frontend cuts here even without a pause.
+pub const MAX_SEGMENT: Duration = Duration::from_secs(60);
+
+/// How often a segment is sent for transcription before it is given up on.
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// Wait before each retry of a failed segment; the last one repeats.
+const RETRY_DELAYS: &[Duration] = &[
+    Duration::from_secs(5),
+    Duration::from_secs(15),
+    Duration::from_secs(30),
+    Duration::from_secs(60),
+];
+
+/// Bounds for `meeting.max_duration_minutes`.
+const MIN_MAX_DURATION_MINUTES: u32 = 1;
+const MAX_MAX_DURATION_MINUTES: u32 = 24 * 60;
+
+/// Range of `meeting.microphone_gain_db` and `meeting.system_audio_gain_db`.
+const MIN_GAIN_DB: i32 = -30;
+const MAX_GAIN_DB: i32 = 12;
+
+/// What a meeting records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeetingInput {
+    #[default]
+    Microphone,
+    /// What plays on the default output device, if it can be captured here;
+    /// the microphone otherwise.
+    SystemAudio,
+    /// Both, mixed; the microphone alone where system audio can't be
+    /// captured.
+    MicrophoneAndSystemAudio,
+}
+
+impl MeetingInput {
+    pub fn records_microphone(self) -> bool {
+        matches!(self, Self::Microphone | Self::MicrophoneAndSystemAudio)
+    }
+
+    pub fn records_system_audio(self) -> bool {
+        matches!(self, Self::SystemAudio | Self::MicrophoneAndSystemAudio)
+    }
+}
+
+/// A source a meeting records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeetingSource {
+    Microphone,
+    SystemAudio,
+}
+
+/// Meeting settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MeetingSettings {
+    /// Meetings are stopped after this long.
+    pub max_duration_minutes: u32,
+    pub input: MeetingInput,
+    /// Gain of the microphone in the mix, in dB.
+    pub microphone_gain_db: i32,
+    /// Gain of system audio in the mix, in dB.
+    pub system_audio_gain_db: i32,
+    /// Label who said what, where the provider can tell speakers apart.
+    pub diarize: bool,
+}
+
+impl Default for MeetingSettings {
+    fn default() -> Self {
+        Self {
+            max_duration_minutes: 240,
+            input: MeetingInput::Microphone,
+            microphone_gain_db: 0,
+            system_audio_gain_db: 0,
+            diarize: false,
+        }
+    }
+}
+
+impl MeetingSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_MAX_DURATION_MINUTES..=MAX_MAX_DURATION_MINUTES)
+            .contains(&self.max_duration_minutes)
+        {
+            return Err(format!(
+                "max_duration_minutes must be between {} and {}",
+                MIN_MAX_DURATION_MINUTES, MAX_MAX_DURATION_MINUTES
+            ));
+        }
+        for (name, gain) in [
+            ("microphone_gain_db", self.microphone_gain_db),
+            ("system_audio_gain_db", self.system_audio_gain_db),
+        ] {
+            if !(MIN_GAIN_DB..=MAX_GAIN_DB).contains(&gain) {
+                return Err(format!(
+                    "{} must be between {} and {}",
+                    name, MIN_GAIN_DB, MAX_GAIN_DB
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A gain in dB as a linear factor.
+fn linear_gain(db: i32) -> f32 {
+    10f32.powf(db as f32 / 20.0)
+}
+
+/// Where a segment is in the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SegmentStatus {
+    /// Waiting to be transcribed, or to be retried.
+    Pending,
+    /// Handed to the frontend for transcription.
+    Transcribing,
+    Done,
+    /// Failed [`MAX_ATTEMPTS`] times; its audio is kept.
+    Failed,
+}
+
+struct Segment {
+    index: u32,
+    /// Where the segment lies in the meeting.
+    start_ms: u64,
+    end_ms: u64,
+    status: SegmentStatus,
+    attempts: u32,
+    /// Not retried before this.
+    retry_at: Option<Instant>,
+    text: String,
+    /// The text by speaker, if the segment was diarized.
+    utterances: Vec<Utterance>,
+}
+
+/// What one speaker said in a segment, as the frontend reports it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Utterance {
+    /// Where it starts, from the start of the segment.
+    pub start_ms: u64,
+    /// The provider's number for the speaker, from 0.
+    pub speaker: u32,
+    pub text: String,
+}
+
+struct Meeting {
+    id: String,
+    dir: PathBuf,
+    /// Local time the meeting started, for the document's heading.
+    started_at: chrono::DateTime<Local>,
+    segments: Vec<Segment>,
+    /// The history entry holding the transcript, once there is one.
+    history_id: Option<i64>,
+    /// Stopped, and finalized once the queue is drained.
+    stopping: bool,
+}
+
+impl Meeting {
+    fn segment_path(&self, index: u32) -> PathBuf {
+        self.dir.join(format!("segment-{:05}.webm", index))
+    }
+
+    fn segment_mut(&mut self, index: u32) -> Result<&mut Segment, String> {
+        self.segments
+            .iter_mut()
+            .find(|segment| segment.index == index)
+            .ok_or_else(|| format!("no segment {} in this meeting", index))
+    }
+
+    /// Whether every segment is settled, transcribed or given up on.
+    fn drained(&self) -> bool {
+        self.segments
+            .iter()
+            .all(|segment| matches!(segment.status, SegmentStatus::Done | SegmentStatus::Failed))
+    }
+
+    /// Length of the meeting as recorded so far.
+    fn duration_ms(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| segment.end_ms)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// The transcript, one paragraph per segment in the order they were
+    /// recorded, or per turn in diarized segments; `finished` adds the
+    /// heading.
+    fn transcript(&self, finished: bool) -> SpeakerTranscript {
+        let mut segments: Vec<&Segment> = self
+            .segments
+            .iter()
+            .filter(|segment| match segment.status {
+                SegmentStatus::Done => !segment.text.trim().is_empty(),
+                SegmentStatus::Failed => finished,
+                _ => false,
+            })
+            .collect();
+        segments.sort_by_key(|segment| segment.start_ms);
+
+        let mut transcript = SpeakerTranscript {
+            heading: finished.then(|| {
+                format!(
+                    "Meeting on {} ({})",
+                    self.started_at.format("%Y-%m-%d %H:%M"),
+                    speakers::timestamp(self.duration_ms())
+                )
+            }),
+            ..SpeakerTranscript::default()
+        };
+        for segment in segments {
+            if segment.status != SegmentStatus::Done {
+                transcript.paragraphs.push(Paragraph {
+                    start_ms: segment.start_ms,
+                    speaker: None,
+                    text: "(not transcribed)".to_string(),
+                });
+                continue;
+            }
+            if segment.utterances.is_empty() {
+                transcript.paragraphs.push(Paragraph {
+                    start_ms: segment.start_ms,
+                    speaker: None,
+                    text: segment.text.trim().to_string(),
+                });
+                continue;
+            }
+            // One paragraph per turn, joining what a speaker said in a row
+            let mut turn: Option<Paragraph> = None;
+            for utterance in &segment.utterances {
+                let text = utterance.text.trim();
+                if text.is_empty() {
+                    continue;
+                }
+                let speaker = Some(utterance.speaker + 1);
+                match turn.as_mut() {
+                    Some(paragraph) if paragraph.speaker == speaker => {
+                        paragraph.text.push(' ');
+                        paragraph.text.push_str(text);
+                    }
+                    _ => {
+                        transcript.paragraphs.extend(turn.take());
+                        turn = Some(Paragraph {
+                            start_ms: segment.start_ms + utterance.start_ms,
+                            speaker,
+                            text: text.to_string(),
+                        });
+                    }
+                }
+            }
+            transcript.paragraphs.extend(turn);
+        }
+        transcript
+    }
+}
+
+/// Managed state: the meeting in progress, if any.
+pub struct MeetingState {
+    dir: PathBuf,
+    current: Mutex<Option<Meeting>>,
+}
+
+impl MeetingState {
+    /// Meeting audio goes under `data_dir`.
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            dir: data_dir.join(MEETINGS_DIR),
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Whether a meeting is recording: started and not yet stopped.
+    pub fn recording(&self) -> bool {
+        self.current
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|meeting| !meeting.stopping)
+    }
+
+    /// Runs `f` with the meeting `meeting_id`.
+    fn with_meeting<T>(
+        &self,
+        meeting_id: &str,
+        f: impl FnOnce(&mut Meeting) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut current = self.current.lock().unwrap();
+        match current.as_mut() {
+            Some(meeting) if meeting.id == meeting_id => f(meeting),
+            _ => Err(format!("meeting {} is not in progress", meeting_id)),
+        }
+    }
+}
+
+/// Payload of `start_meeting` and `meeting:started`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MeetingStarted {
+    pub meeting_id: String,
+    /// Segments are cut at a pause once they are this long...
+    pub min_segment_ms: u64,
+    /// ...and at this length in any case.
+    pub max_segment_ms: u64,
+    /// The meeting is stopped after this long.
+    pub max_duration_ms: u64,
+    /// Whether to record the microphone.
+    pub microphone: bool,
+    /// Whether to record system audio
+    /// ([`crate::loopback::start_system_audio`]).
+    pub system_audio: bool,
+    /// Linear gains of the sources in the mix.
+    pub microphone_gain: f32,
+    pub system_audio_gain: f32,
+    /// What to record in (see [`crate::capture`]).
+    pub capture: CaptureQuality,
+    /// Whether to label speakers (`meeting.diarize`).
+    pub diarize: bool,
+}
+
+/// Payload of `meeting:segment`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentTranscribed {
+    pub meeting_id: String,
+    pub index: u32,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+/// Payload of `meeting:segment-failed`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentFailed {
+    pub meeting_id: String,
+    pub index: u32,
+    pub start_ms: u64,
+    pub message: String,
+}
+
+/// Payload of `meeting:finished`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MeetingFinished {
+    pub meeting_id: String,
+    /// The history entry holding the transcript, if any was saved.
+    pub history_id: Option<i64>,
+    pub duration_ms: u64,
+    /// Segments that could never be transcribed.
+    pub failed_segments: u32,
+}
+
+/// Payload of `meeting:source-lost`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceLost {
+    pub meeting_id: String,
+    pub source: MeetingSource,
+    pub message: String,
+}
+
+/// Payload of `meeting:limit-reached`.
+#[derive(Debug, Clone, Serialize)]
+struct LimitReached {
+    meeting_id: String,
+}
+
+/// What [`next_meeting_segment`] hands out.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NextSegment {
+    /// Transcribe this segment.
+    Segment { index: u32 },
+    /// Segments are waiting to be retried; ask again after this long.
+    Wait { retry_in_ms: u64 },
+    /// Nothing to transcribe for now.
+    Idle,
+}
+
+/// Starts a meeting. Its segments are then added with
+/// [`add_meeting_segment`].
+///
+/// # Errors
+///
+/// Returns an error if a meeting is already in progress or its directory
+/// can't be created.
+#[tauri::command]
+pub fn start_meeting(
+    app: AppHandle,
+    state: State<'_, MeetingState>,
+) -> Result<MeetingStarted, String> {
+    let mut current = state.current.lock().unwrap();
+    if current.is_some() {
+        return Err("a meeting is already in progress".to_string());
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let dir = state.dir.join(&id);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    *current = Some(Meeting {
+        id: id.clone(),
+        dir,
+        started_at: Local::now(),
+        segments: Vec::new(),
+        history_id: None,
+        stopping: false,
+    });
+    drop(current);
+
+    let Settings {
+        meeting: settings,
+        capture,
+        ..
+    } = app.state::<SettingsState>().get();
+    let max_duration = Duration::from_secs(u64::from(settings.max_duration_minutes) * 60);
+    let system_audio = settings.input.records_system_audio() && crate::loopback::support().is_ok();
+    watch_duration(app.clone(), id.clone(), max_duration);
+
+    let started = MeetingStarted {
+        meeting_id: id,
+        min_segment_ms: MIN_SEGMENT.as_millis() as u64,
+        max_segment_ms: MAX_SEGMENT.as_millis() as u64,
+        max_duration_ms: max_duration.as_millis() as u64,
+        microphone: settings.input.records_microphone() || !system_audio,
+        system_audio,
+        microphone_gain: linear_gain(settings.microphone_gain_db),
+        system_audio_gain: linear_gain(settings.system_audio_gain_db),
+        diarize: settings.diarize,
+        capture: capture.meeting,
+    };
+    tracing::info!(meeting_id = %started.meeting_id, "Meeting started");
+    crate::indicator::sync(&app);
+    crate::focus::sync(&app);
+    let _ = app.emit("meeting:started", &started);
+    Ok(started)
+}
+
+/// Asks the frontend to stop the meeting `meeting_id` once it has run for
+/// `limit`, unless it was stopped before.
+fn watch_duration(app: AppHandle, meeting_id: String, limit: Duration) {
+    let spawned = std::thread::Builder::new()
+        .name("meeting-limit".to_string())
+        .spawn(move || {
+            std::thread::sleep(limit);
+            let running = app
+                .state::<MeetingState>()
+                .with_meeting(&meeting_id, |meeting| Ok(!meeting.stopping))
+                .unwrap_or(false);
+            if running {
+                tracing::info!(%meeting_id, "Meeting reached its maximum length");
+                let _ = app.emit("meeting:limit-reached", LimitReached { meeting_id });
+            }
+        });
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to watch the meeting's length: {}", e);
+    }
+}
+
+/// Adds a recorded segment of the meeting `meeting_id`, lying from
+/// `start_ms` to `end_ms` in it, to the transcription queue. Returns its
+/// index.
+///
+/// # Errors
+///
+/// Returns an error if the meeting isn't in progress or the audio can't be
+/// written.
+#[tauri::command]
+pub fn add_meeting_segment(
+    state: State<'_, MeetingState>,
+    meeting_id: String,
+    start_ms: u64,
+    end_ms: u64,
+    audio: Vec<u8>,
+) -> Result<u32, String> {
+    state.with_meeting(&meeting_id, |meeting| {
+        let index = meeting.segments.len() as u32;
+        let path = meeting.segment_path(index);
+        fs::write(&path, &audio)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        meeting.segments.push(Segment {
+            index,
+            start_ms,
+            end_ms: end_ms.max(start_ms),
+            status: SegmentStatus::Pending,
+            attempts: 0,
+            retry_at: None,
+            text: String::new(),
+            utterances: Vec::new(),
+        });
+        tracing::debug!(%meeting_id, index, bytes = audio.len(), "Meeting segment added");
+        Ok(index)
+    })
+}
+
+/// Hands out the next segment of the meeting `meeting_id` to transcribe,
+/// oldest first, skipping those waiting to be retried.
+///
+/// # Errors
+///
+/// Returns an error if the meeting isn't in progress.
+#[tauri::command]
+pub fn next_meeting_segment(
+    state: State<'_, MeetingState>,
+    meeting_id: String,
+) -> Result<NextSegment, String> {
+    state.with_meeting(&meeting_id, |meeting| {
+        let now = Instant::now();
+        let mut retry_in: Option<Duration> = None;
+        for segment in &mut meeting.segments {
+            if segment.status != SegmentStatus::Pending {
+                continue;
+            }
+            match segment.retry_at {
+                Some(at) if at > now => {
+                    let wait = at - now;
+                    retry_in = Some(retry_in.map_or(wait, |other| other.min(wait)));
+                }
+                _ => {
+                    segment.status = SegmentStatus::Transcribing;
+                    segment.attempts += 1;
+                    return Ok(NextSegment::Segment {
+                        index: segment.index,
+                    });
+                }
+            }
+        }
+        Ok(match retry_in {
+            Some(wait) => NextSegment::Wait {
+                retry_in_ms: wait.as_millis() as u64,
+            },
+            None => NextSegment::Idle,
+        })
+    })
+}
+
+/// Reads the audio of segment `index` of the meeting `meeting_id`, returned
+/// to the frontend as raw bytes (an `ArrayBuffer`).
+///
+/// # Errors
+///
+/// Returns an error if the meeting isn't in progress, has no such segment,
+/// or its audio can't be read.
+#[tauri::command]
+pub fn read_meeting_segment(
+    state: State<'_, MeetingState>,
+    meeting_id: String,
+    index: u32,
+) -> Result<tauri::ipc::Response, String> {
+    let path = state.with_meeting(&meeting_id, |meeting| {
+        meeting.segment_mut(index)?;
+        Ok(meeting.segment_path(index))
+    })?;
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(tauri::ipc::Response::new(bytes))
+}
+
+/// Records the transcript of segment `index` of the meeting `meeting_id`,
+/// deletes its audio, and brings the meeting's history entry up to date.
+/// `utterances` is the transcript by speaker, if the segment was diarized;
+/// without it, the segment is unlabeled.
+///
+/// # Errors
+///
+/// Returns an error if the meeting isn't in progress or has no such
+/// segment.
+#[tauri::command]
+pub fn complete_meeting_segment(
+    app: AppHandle,
+    meeting_id: String,
+    index: u32,
+    text: String,
+    utterances: Option<Vec<Utterance>>,
+) -> Result<(), String> {
+    let state = app.state::<MeetingState>();
+    let transcribed = state.with_meeting(&meeting_id, |meeting| {
+        let path = meeting.segment_path(index);
+        let segment = meeting.segment_mut(index)?;
+        segment.status = SegmentStatus::Done;
+        segment.retry_at = None;
+        segment.text = text;
+        segment.utterances = utterances.unwrap_or_default();
+        let transcribed = SegmentTranscribed {
+            meeting_id: meeting_id.clone(),
+            index,
+            start_ms: segment.start_ms,
+            end_ms: segment.end_ms,
+            text: segment.text.clone(),
+        };
+        if let Err(e) = fs::remove_file(&path) {
+            tracing::warn!("Failed to delete {}: {}", path.display(), e);
+        }
+        Ok(transcribed)
+    })?;
+
+    save_transcript(&app, &meeting_id);
+    let _ = app.emit("meeting:segment", transcribed);
+    finish_if_drained(&app, &meeting_id);
+    Ok(())
+}
+
+/// Records that segment `index` of the meeting `meeting_id` failed to
+/// transcribe with `message`. It is queued again after a delay, or given up
+/// on after [`MAX_ATTEMPTS`].
+///
+/// # Errors
+///
+/// Returns an error if the meeting isn't in progress or has no such
+/// segment.
+#[tauri::command]
+pub fn fail_meeting_segment(
+    app: AppHandle,
+    meeting_id: String,
+    index: u32,
+    message: String,
+) -> Result<(), String> {
+    let state = app.state::<MeetingState>();
+    let given_up = state.with_meeting(&meeting_id, |meeting| {
+        let segment = meeting.segment_mut(index)?;
+        if segment.attempts >= MAX_ATTEMPTS {
+            segment.status = SegmentStatus::Failed;
+            return Ok(Some(SegmentFailed {
+                meeting_id: meeting_id.clone(),
+                index,
+                start_ms: segment.start_ms,
+                message: message.clone(),
+            }));
+        }
+        let retry = segment.attempts.saturating_sub(1) as usize;
+        let delay = RETRY_DELAYS[retry.min(RETRY_DELAYS.len() - 1)];
+        segment.status = SegmentStatus::Pending;
+        segment.retry_at = Some(Instant::now() + delay);
+        tracing::warn!(
+            %meeting_id,
+            index,
+            attempts = segment.attempts,
+            retry_in_ms = delay.as_millis() as u64,
+            "Meeting segment failed to transcribe: {}",
+            message
+        );
+        Ok(None)
+    })?;
+
+    if let Some(failed) = given_up {
+        tracing::warn!(%meeting_id, index, "Gave up on a meeting segment: {}", message);
+        let _ = app.emit("meeting:segment-failed", failed);
+        finish_if_drained(&app, &meeting_id);
+    }
+    Ok(())
+}
+
+/// Announces that the meeting `meeting_id` lost `source`, for `message`.
+/// Ignored unless the meeting is in progress.
+pub fn source_lost(app: &AppHandle, meeting_id: &str, source: MeetingSource, message: &str) {
+    let running = app
+        .state::<MeetingState>()
+        .with_meeting(meeting_id, |meeting| Ok(!meeting.stopping))
+        .unwrap_or(false);
+    if !running {
+        return;
+    }
+    tracing::warn!(%meeting_id, ?source, "Meeting source lost: {}", message);
+    let _ = app.emit(
+        "meeting:source-lost",
+        SourceLost {
+            meeting_id: meeting_id.to_string(),
+            source,
+            message: message.to_string(),
+        },
+    );
+}
+
+/// Reports that the meeting `meeting_id` lost the microphone (or another
+/// `source` the frontend records) and carries on from what is left.
+#[tauri::command]
+pub fn report_meeting_source_lost(
+    app: AppHandle,
+    meeting_id: String,
+    source: MeetingSource,
+    message: String,
+) {
+    source_lost(&app, &meeting_id, source, &message);
+}
+
+/// Stops the meeting `meeting_id`, once the frontend added its last
+/// segment. It is finalized as soon as every segment is transcribed or
+/// given up on.
+///
+/// # Errors
+///
+/// Returns an error if the meeting isn't in progress.
+#[tauri::command]
+pub fn stop_meeting(app: AppHandle, meeting_id: String) -> Result<(), String> {
+    app.state::<MeetingState>()
+        .with_meeting(&meeting_id, |meeting| {
+            meeting.stopping = true;
+            Ok(())
+        })?;
+    crate::loopback::stop(&app, &meeting_id);
+    tracing::info!(%meeting_id, "Meeting stopped");
+    crate::indicator::sync(&app);
+    crate::focus::sync(&app);
+    finish_if_drained(&app, &meeting_id);
+    Ok(())
+}
+
+/// Writes the transcript so far to the meeting's history entry, creating
+/// it with the first text. Nothing is written when the storage policy
+/// keeps no transcripts (see [`crate::privacy`]); the usage counters are
+/// updated once it finishes.
+fn save_transcript(app: &AppHandle, meeting_id: &str) {
+    if !crate::privacy::policy().history {
+        return;
+    }
+
+    let state = app.state::<MeetingState>();
+    let history = app.state::<HistoryState>();
+    let saved = state.with_meeting(meeting_id, |meeting| {
+        let transcript = meeting.transcript(false);
+        if transcript.paragraphs.is_empty() {
+            return Ok(None);
+        }
+        let duration_ms = meeting.duration_ms();
+        let history_id = meeting.history_id;
+        match history_id {
+            Some(id) => history
+                .update_transcript(id, &transcript, Some(duration_ms))
+                .map(|()| Some((id, HistoryChange::Updated))),
+            None => {
+                let entry = new_entry(app, meeting, &transcript);
+                let id = insert(&history, entry, &transcript)?;
+                meeting.history_id = Some(id);
+                Ok(Some((id, HistoryChange::Added)))
+            }
+        }
+    });
+    match saved {
+        Ok(Some((id, change))) => crate::history::emit_changed(app, change, vec![id]),
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to save the meeting transcript: {}", e),
+    }
+}
+
+fn new_entry(
+    app: &AppHandle,
+    meeting: &Meeting,
+    transcript: &SpeakerTranscript,
+) -> NewHistoryEntry {
+    let settings = app.state::<SettingsState>().get();
+    let transcription = settings.transcription;
+    let text = transcript.render();
+    NewHistoryEntry {
+        raw_text: text.clone(),
+        processed_text: text,
+        target_app: None,
+        provider: Some(transcription.provider),
+        duration_ms: Some(meeting.duration_ms()),
+        language: Some(transcription.language),
+        audio_path: None,
+        session_id: Some(meeting.id.clone()),
+        peak_level: None,
+        profile: settings.profiles.active,
+    }
+}
+
+/// Inserts the meeting's history entry `entry`, with the speakers of
+/// `transcript` if it has any, returning its id.
+fn insert(
+    history: &HistoryState,
+    entry: NewHistoryEntry,
+    transcript: &SpeakerTranscript,
+) -> Result<i64, String> {
+    let id = history.insert(&entry)?;
+    if transcript.has_speakers() {
+        history.update_transcript(id, &transcript, entry.duration_ms)?;
+    }
+    Ok(id)
+}
+
+/// Finalizes the meeting `meeting_id` if it was stopped and nothing is
+/// left to transcribe: the history entry gets the whole document, the
+/// audio directory is removed unless segments failed, and the meeting
+/// ends.
+fn finish_if_drained(app: &AppHandle, meeting_id: &str) {
+    let state = app.state::<MeetingState>();
+    let meeting = {
+        let mut current = state.current.lock().unwrap();
+        match current.as_ref() {
+            Some(meeting) if meeting.id == meeting_id && meeting.stopping && meeting.drained() => {
+                current.take()
+            }
+            _ => None,
+        }
+    };
+    let Some(mut meeting) = meeting else {
+        return;
+    };
+
+    let failed_segments = meeting
+        .segments
+        .iter()
+        .filter(|segment| segment.status == SegmentStatus::Failed)
+        .count() as u32;
+    let transcript = meeting.transcript(true);
+    let history = app.state::<HistoryState>();
+    let policy = crate::privacy::policy();
+    let duration_ms = meeting.duration_ms();
+    let history_id = meeting.history_id;
+    let saved = match history_id {
+        Some(id) if policy.history => history
+            .update_transcript(id, &transcript, Some(duration_ms))
+            .map(|()| Some((id, HistoryChange::Updated))),
+        // Privacy mode was turned on since: the entry keeps what it had
+        Some(_) => Ok(None),
+        None if meeting.segments.is_empty() => Ok(None),
+        None if !policy.history => history
+            .record_usage_only(&new_entry(app, &meeting, &transcript))
+            .map(|()| None),
+        None => insert(&history, new_entry(app, &meeting, &transcript), &transcript).map(|id| {
+            meeting.history_id = Some(id);
+            Some((id, HistoryChange::Added))
+        }),
+    };
+    match saved {
+        Ok(Some((id, change))) => crate::history::emit_changed(app, change, vec![id]),
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to save the meeting transcript: {}", e),
+    }
+
+    if failed_segments == 0 || !policy.failed_meeting_audio {
+        if let Err(e) = fs::remove_dir_all(&meeting.dir) {
+            tracing::warn!("Failed to delete {}: {}", meeting.dir.display(), e);
+        }
+    }
+
+    let finished = MeetingFinished {
+        meeting_id: meeting.id,
+        history_id: meeting.history_id,
+        duration_ms,
+        failed_segments,
+    };
+    tracing::info!(
+        meeting_id = %finished.meeting_id,
+        duration_ms = finished.duration_ms,
+        failed_segments,
+        "Meeting finished"
+    );
+    crate::metrics::count(app, crate::metrics::Counter::MeetingsFinished);
+    let _ = app.emit("meeting:finished", finished);
+}
@@ -0,0 +1,382 @@
+//! Local usage counters.
+//!
+//! With `metrics.enabled` (off by default) the app counts what it does:
+//! dictations completed (in total and per day), failed pastes and
+//! transcriptions by error code, and how long transcription and whole
+//! dictations took, as histograms. They are for the user to understand
+//! their own usage and to help debug slowdowns, and never leave the
+//! machine: they are written to `metrics.json` in the app data directory
+//! every [`FLUSH_INTERVAL`] and on exit, and only read back by
+//! [`get_metrics`] (and diagnostics bundles). [`reset_metrics`] starts
+//! them over.
+//!
+//! # Architecture Decision
+//!
+//! Counting happens on the dictation path, so it is an atomic increment
+//! and nothing more; a background thread moves the increments into the
+//! stored totals and writes them. Every name counted comes from a fixed set
+//! ([`Counter`], [`ErrorCode`], [`Latency`]), never from a transcript or
+//! the app dictated into, so the counters hold nothing private, and are
+//! kept in privacy mode like the usage stats.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tauri::{Manager, State};
+
+use crate::errors::ErrorCode;
+use crate::AppHandle;
+
+/// File name of the counters inside the app data directory.
+const METRICS_FILE: &str = "metrics.json";
+
+/// How often counted increments are written.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Days of per-day counts kept.
+const KEPT_DAYS: usize = 90;
+
+/// Upper bounds of the latency buckets, in milliseconds; one more bucket
+/// holds everything slower.
+const BUCKETS_MS: [u64; 6] = [250, 500, 1_000, 2_000, 5_000, 10_000];
+
+const ERROR_CODES: usize = ErrorCode::ALL.len();
+
+/// Local metrics settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsSettings {
+    /// Count usage locally.
+    pub enabled: bool,
+}
+
+/// Something counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counter {
+    DictationsCompleted,
+    PastesFailed,
+    TranscriptionsFailed,
+    MeetingsFinished,
+}
+
+impl Counter {
+    const ALL: [Counter; 4] = [
+        Counter::DictationsCompleted,
+        Counter::PastesFailed,
+        Counter::TranscriptionsFailed,
+        Counter::MeetingsFinished,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::DictationsCompleted => "dictations_completed",
+            Self::PastesFailed => "pastes_failed",
+            Self::TranscriptionsFailed => "transcriptions_failed",
+            Self::MeetingsFinished => "meetings_finished",
+        }
+    }
+}
+
+/// Something timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latency {
+    /// From the end of the recording to the final transcript: the provider.
+    Transcription,
+    /// Everything after the recording, up to the paste.
+    Dictation,
+}
+
+impl Latency {
+    const ALL: [Latency; 2] = [Latency::Transcription, Latency::Dictation];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Transcription => "transcription",
+            Self::Dictation => "dictation",
+        }
+    }
+}
+
+/// The counters, as stored and returned.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metrics {
+    /// When counting started, or was last reset, Unix milliseconds.
+    pub since_ms: i64,
+    pub counters: BTreeMap<String, u64>,
+    /// Failed pastes and transcriptions by error code.
+    pub errors: BTreeMap<String, u64>,
+    /// Dictations completed per local day (`YYYY-MM-DD`), the last
+    /// [`KEPT_DAYS`].
+    pub dictations_per_day: BTreeMap<String, u64>,
+    pub latency: BTreeMap<String, Histogram>,
+}
+
+/// A latency histogram.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Histogram {
+    pub count: u64,
+    pub sum_ms: u64,
+    /// `None` until something was timed.
+    pub average_ms: Option<u64>,
+    /// Upper bounds of the buckets; the last bucket has none.
+    pub bounds_ms: Vec<u64>,
+    /// How many fell in each bucket.
+    pub buckets: Vec<u64>,
+}
+
+/// Increments not yet added to a [`Histogram`].
+#[derive(Default)]
+struct Pending {
+    buckets: [AtomicU64; BUCKETS_MS.len() + 1],
+    sum_ms: AtomicU64,
+}
+
+/// The stored counters, once loaded.
+struct Stored {
+    path: PathBuf,
+    metrics: Metrics,
+    /// Changed since last written.
+    dirty: bool,
+}
+
+/// Managed state: what was counted since the last flush, and the totals.
+#[derive(Default)]
+pub struct MetricsState {
+    enabled: AtomicBool,
+    counters: [AtomicU64; Counter::ALL.len()],
+    /// By index in [`ErrorCode::ALL`].
+    errors: [AtomicU64; ERROR_CODES],
+    latency: [Pending; Latency::ALL.len()],
+    stored: Mutex<Option<Stored>>,
+}
+
+impl MetricsState {
+    fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Adds everything counted since the last call to `metrics`, returning
+    /// whether there was anything.
+    fn drain(&self, metrics: &mut Metrics) -> bool {
+        let mut changed = false;
+        for (counter, pending) in Counter::ALL.iter().zip(&self.counters) {
+            let count = pending.swap(0, Ordering::Relaxed);
+            if count == 0 {
+                continue;
+            }
+            changed = true;
+            *metrics
+                .counters
+                .entry(counter.name().to_string())
+                .or_default() += count;
+            if *counter == Counter::DictationsCompleted {
+                let today = Local::now().format("%Y-%m-%d").to_string();
+                *metrics.dictations_per_day.entry(today).or_default() += count;
+                while metrics.dictations_per_day.len() > KEPT_DAYS {
+                    metrics.dictations_per_day.pop_first();
+                }
+            }
+        }
+        for (code, pending) in ErrorCode::ALL.iter().zip(&self.errors) {
+            let count = pending.swap(0, Ordering::Relaxed);
+            if count > 0 {
+                changed = true;
+                *metrics.errors.entry(code.as_str().to_string()).or_default() += count;
+            }
+        }
+        for (latency, pending) in Latency::ALL.iter().zip(&self.latency) {
+            let counts: Vec<u64> = pending
+                .buckets
+                .iter()
+                .map(|bucket| bucket.swap(0, Ordering::Relaxed))
+                .collect();
+            let added: u64 = counts.iter().sum();
+            if added == 0 {
+                continue;
+            }
+            changed = true;
+            let histogram = metrics
+                .latency
+                .entry(latency.name().to_string())
+                .or_default();
+            histogram.bounds_ms = BUCKETS_MS.to_vec();
+            histogram.buckets.resize(counts.len(), 0);
+            for (bucket, count) in histogram.buckets.iter_mut().zip(counts) {
+                *bucket += count;
+            }
+            histogram.count += added;
+            histogram.sum_ms += pending.sum_ms.swap(0, Ordering::Relaxed);
+            histogram.average_ms = Some(histogram.sum_ms / histogram.count);
+        }
+        changed
+    }
+}
+
+/// Result of [`get_metrics`].
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsReport {
+    /// Whether usage is being counted (`metrics.enabled`).
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub metrics: Metrics,
+}
+
+/// Counts one `counter`, if metrics are on.
+pub fn count(app: &AppHandle, counter: Counter) {
+    let state = app.state::<MetricsState>();
+    if state.enabled() {
+        state.counters[counter as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts one failure: `counter`, and `code`.
+pub fn failure(app: &AppHandle, counter: Counter, code: ErrorCode) {
+    let state = app.state::<MetricsState>();
+    if !state.enabled() {
+        return;
+    }
+    state.counters[counter as usize].fetch_add(1, Ordering::Relaxed);
+    if let Some(index) = ErrorCode::ALL.iter().position(|known| *known == code) {
+        state.errors[index].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Times one `latency` of `ms` milliseconds.
+pub fn time(app: &AppHandle, latency: Latency, ms: u64) {
+    let state = app.state::<MetricsState>();
+    if !state.enabled() {
+        return;
+    }
+    let pending = &state.latency[latency as usize];
+    let bucket = BUCKETS_MS
+        .iter()
+        .position(|bound| ms <= *bound)
+        .unwrap_or(BUCKETS_MS.len());
+    pending.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    pending.sum_ms.fetch_add(ms, Ordering::Relaxed);
+}
+
+/// Turns counting on or off.
+pub fn apply(app: &AppHandle, settings: &MetricsSettings) {
+    app.state::<MetricsState>()
+        .enabled
+        .store(settings.enabled, Ordering::Relaxed);
+}
+
+/// Loads the stored counters from `data_dir` and starts writing them every
+/// [`FLUSH_INTERVAL`]. Call from `setup`.
+pub fn open(app: &AppHandle, data_dir: &Path) {
+    let path = data_dir.join(METRICS_FILE);
+    let (metrics, dirty) = match fs::read(&path) {
+        Ok(json) => match serde_json::from_slice(&json) {
+            Ok(metrics) => (metrics, false),
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable {}: {}", path.display(), e);
+                (fresh(), true)
+            }
+        },
+        Err(_) => (fresh(), false),
+    };
+    *app.state::<MetricsState>().stored.lock().unwrap() = Some(Stored {
+        path,
+        metrics,
+        dirty,
+    });
+
+    let handle = app.clone();
+    let spawned = crate::crash::supervise(app, "metrics-flusher", move || loop {
+        std::thread::sleep(FLUSH_INTERVAL);
+        flush(&handle);
+    });
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to start the metrics flusher: {}", e);
+    }
+}
+
+/// Counters starting now.
+fn fresh() -> Metrics {
+    Metrics {
+        since_ms: now_ms(),
+        ..Metrics::default()
+    }
+}
+
+/// Writes what was counted since the last flush, if anything.
+pub fn flush(app: &AppHandle) {
+    let state = app.state::<MetricsState>();
+    let mut stored = state.stored.lock().unwrap();
+    let Some(stored) = stored.as_mut() else {
+        return;
+    };
+    stored.dirty |= state.drain(&mut stored.metrics);
+    if stored.dirty {
+        write(stored);
+    }
+}
+
+/// Writes the counters through a temporary file, so a crash never leaves
+/// a truncated one.
+fn write(stored: &mut Stored) {
+    let written = serde_json::to_vec_pretty(&stored.metrics)
+        .map_err(|e| e.to_string())
+        .and_then(|json| {
+            let temp = stored.path.with_extension("json.tmp");
+            fs::write(&temp, json)
+                .and_then(|()| fs::rename(&temp, &stored.path))
+                .map_err(|e| e.to_string())
+        });
+    match written {
+        Ok(()) => stored.dirty = false,
+        Err(e) => tracing::warn!("Failed to save metrics: {}", e),
+    }
+}
+
+/// The counters so far, including what wasn't written yet.
+pub fn snapshot(app: &AppHandle) -> Metrics {
+    let state = app.state::<MetricsState>();
+    let mut stored = state.stored.lock().unwrap();
+    match stored.as_mut() {
+        Some(stored) => {
+            stored.dirty |= state.drain(&mut stored.metrics);
+            stored.metrics.clone()
+        }
+        None => Metrics::default(),
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Returns the local usage counters, and whether they are being counted.
+#[tauri::command]
+pub fn get_metrics(app: AppHandle, state: State<'_, MetricsState>) -> MetricsReport {
+    MetricsReport {
+        enabled: state.enabled(),
+        metrics: snapshot(&app),
+    }
+}
+
+/// Starts every counter over, and writes them.
+#[tauri::command]
+pub fn reset_metrics(state: State<'_, MetricsState>) {
+    let mut stored = state.stored.lock().unwrap();
+    let Some(stored) = stored.as_mut() else {
+        return;
+    };
+    state.drain(&mut Metrics::default());
+    stored.metrics = fresh();
+    write(stored);
+    tracing::info!("Metrics reset");
+}
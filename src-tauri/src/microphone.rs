@@ -0,0 +1,118 @@
+//! What happens to the microphone during a recording.
+//!
+//! The microphone is the webview's, so the frontend reports what it sees
+//! and the backend announces it, like the audio it counts for
+//! [`crate::ticks`].
+//!
+//! When the default input changes mid-recording (a headset connecting or
+//! disconnecting), the frontend carries on recording from the new one in
+//! the same recording, crossfading between the two, and reports it
+//! ([`report_input_device_change`]). It is announced as
+//! `audio:device-changed`, for the pill to show a brief notice. If no
+//! microphone is left, the frontend ends the recording and pastes what was
+//! said up to then.
+
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::app_state;
+use crate::AppHandle;
+
+/// Payload of `audio:device-changed`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceChanged {
+    pub session_id: String,
+    /// Label of the microphone recorded until now, if known.
+    pub from: Option<String>,
+    /// Label of the microphone recorded from now on, if known.
+    pub to: Option<String>,
+}
+
+/// Announces that the recording `session_id` switched from the microphone
+/// `from` to `to`. Reports for any other dictation are ignored.
+#[tauri::command]
+pub fn report_input_device_change(
+    app: AppHandle,
+    session_id: String,
+    from: Option<String>,
+    to: Option<String>,
+) {
+    if app_state::check_session(&app, &session_id).is_err() {
+        return;
+    }
+    tracing::info!(
+        %session_id,
+        from = from.as_deref().unwrap_or("unknown"),
+        to = to.as_deref().unwrap_or("unknown"),
+        "Input device changed mid-recording"
+    );
+    let _ = app.emit(
+        "audio:device-changed",
+        DeviceChanged {
+            session_id,
+            from,
+            to,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::app_state::{self, Phase};
+    use crate::harness::Harness;
+
+    #[test]
+    fn a_switch_mid_recording_is_announced_and_the_recording_goes_on() {
+        let harness = Harness::new("microphone-switch");
+        let session_id = harness.press_hotkey();
+        let change = json!({
+            "sessionId": session_id,
+            "from": "MacBook Pro Microphone",
+            "to": "AirPods Pro",
+        });
+        harness
+            .invoke("report_input_device_change", change)
+            .unwrap();
+
+        assert_eq!(
+            harness.events("audio:device-changed"),
+            [json!({
+                "session_id": session_id,
+                "from": "MacBook Pro Microphone",
+                "to": "AirPods Pro",
+            })]
+        );
+        assert_eq!(app_state::phase(harness.handle()), Phase::Recording);
+        assert_eq!(app_state::session_id(harness.handle()), Some(session_id));
+    }
+
+    #[test]
+    fn unknown_labels_are_announced_as_such() {
+        let harness = Harness::new("microphone-unlabeled");
+        let session_id = harness.press_hotkey();
+        let change = json!({ "sessionId": session_id, "from": null, "to": null });
+        harness
+            .invoke("report_input_device_change", change)
+            .unwrap();
+        let changed = harness.events("audio:device-changed");
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0]["from"], json!(null));
+        assert_eq!(changed[0]["to"], json!(null));
+    }
+
+    #[test]
+    fn a_switch_reported_for_another_dictation_is_ignored() {
+        let harness = Harness::new("microphone-stale");
+        let session_id = harness.press_hotkey();
+        harness
+            .invoke("cancel_transcription", json!({ "sessionId": session_id }))
+            .unwrap();
+        let change = json!({ "sessionId": session_id, "from": "A", "to": "B" });
+        harness
+            .invoke("report_input_device_change", change)
+            .unwrap();
+        assert!(harness.events("audio:device-changed").is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+//! Native notifications for failures the user would otherwise miss.
+//!
+//! A paste that fails after the pill is hidden, or a transcription that
+//! fails while it isn't shown, leaves the user with nothing: the text just
+//! never appears. Those failures are emitted as `paste:failed` and
+//! `transcription:failed` ([`FailureEvent`]) and, with
+//! `notifications.enabled`, shown as a native notification with the code's
+//! message ([`errors::message_for`]), its suggested action and the code.
+//!
+//! Each code is notified at most once per [`MIN_INTERVAL`], so a loop of
+//! failures doesn't turn into a wall of notifications.
+//!
+//! # Recovery
+//!
+//! The text of a failed paste is kept for [`RECOVERABLE_FOR`]. Reopening the
+//! app in that time (on macOS, clicking the notification or the Dock icon)
+//! brings the pill back with it: `notification:recover` carries the text,
+//! which is also still on the clipboard. The notification plugin doesn't
+//! report clicks on Windows and Linux; there the clipboard and history are
+//! the way back.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::errors::{self, ErrorCode};
+use crate::metrics::{self, Counter};
+use crate::pill::{self, PillState};
+use crate::settings::SettingsState;
+use crate::AppHandle;
+
+/// Least time between two notifications of the same kind of failure.
+pub const MIN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long the text of a failed paste can be brought back.
+pub const RECOVERABLE_FOR: Duration = Duration::from_secs(2 * 60);
+
+/// Which notifications are shown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationSettings {
+    /// Notify failures that happen while the pill isn't shown.
+    pub enabled: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// A failure worth telling the user about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// The paste failed; `copied` if the text is on the clipboard anyway.
+    Paste {
+        copied: bool,
+    },
+    Transcription,
+}
+
+impl Failure {
+    /// The event the failure is emitted as.
+    fn event(self) -> &'static str {
+        match self {
+            Failure::Paste { .. } => "paste:failed",
+            Failure::Transcription => "transcription:failed",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Failure::Paste { copied: true } => "Couldn't paste — transcript copied to clipboard",
+            Failure::Paste { copied: false } => "Couldn't paste the transcript",
+            Failure::Transcription => "Transcription failed",
+        }
+    }
+}
+
+/// Payload of `paste:failed` and `transcription:failed`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureEvent {
+    pub session_id: Option<String>,
+    pub code: ErrorCode,
+    /// What the failing step reported, for logs and bug reports; show the
+    /// code's message to the user instead.
+    pub message: String,
+}
+
+/// Payload of `notification:recover`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Recovered {
+    pub text: String,
+}
+
+/// Notification state, managed for the life of the app.
+#[derive(Default)]
+pub struct NotificationState {
+    /// When each code was last notified.
+    last: Mutex<HashMap<ErrorCode, Instant>>,
+    /// The text of the last failed paste, and when it failed.
+    recoverable: Mutex<Option<(String, Instant)>>,
+}
+
+/// Emits `failure` with its `code` and, if the pill isn't shown
+/// (`pill_visible`), notifies it. `message` is what the failing step
+/// reported; `text` is what a failed paste was pasting, kept for recovery.
+pub fn failed(
+    app: &AppHandle,
+    failure: Failure,
+    code: ErrorCode,
+    session_id: Option<&str>,
+    message: &str,
+    text: Option<&str>,
+    pill_visible: bool,
+) {
+    let counter = match failure {
+        Failure::Paste { .. } => Counter::PastesFailed,
+        Failure::Transcription => Counter::TranscriptionsFailed,
+    };
+    metrics::failure(app, counter, code);
+    let _ = app.emit(
+        failure.event(),
+        FailureEvent {
+            session_id: session_id.map(str::to_string),
+            code,
+            message: message.to_string(),
+        },
+    );
+    if pill_visible || !app.state::<SettingsState>().get().notifications.enabled {
+        return;
+    }
+
+    let state = app.state::<NotificationState>();
+    if let Some(text) = text {
+        *state.recoverable.lock().unwrap() = Some((text.to_string(), Instant::now()));
+    }
+    {
+        let mut last = state.last.lock().unwrap();
+        let now = Instant::now();
+        if last
+            .get(&code)
+            .is_some_and(|at| now.duration_since(*at) < MIN_INTERVAL)
+        {
+            tracing::debug!(%code, "Not notifying a repeated failure");
+            return;
+        }
+        last.insert(code, now);
+    }
+
+    let user_message = errors::message_for(code);
+    let body = match user_message.action {
+        Some(action) => format!("{}. {}. ({})", user_message.message, action, code),
+        None => format!("{}. ({})", user_message.message, code),
+    };
+    let shown = app
+        .notification()
+        .builder()
+        .title(failure.title())
+        .body(body)
+        .show();
+    if let Err(e) = shown {
+        tracing::warn!(error = %e, "Failed to show a notification");
+    }
+}
+
+/// Brings the pill back with the text of a recent failed paste, if any.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn recover(app: &AppHandle) {
+    let recoverable = app
+        .state::<NotificationState>()
+        .recoverable
+        .lock()
+        .unwrap()
+        .take();
+    let Some((text, at)) = recoverable else {
+        return;
+    };
+    if at.elapsed() > RECOVERABLE_FOR {
+        return;
+    }
+    let _ = app.emit("notification:recover", Recovered { text });
+    pill::set_state(app, PillState::Transcript { estimated_lines: 2 });
+}
+
+/// Whether the pill window is on screen.
+pub fn pill_visible(app: &AppHandle) -> bool {
+    app.get_webview_window(pill::MAIN_WINDOW)
+        .and_then(|window| window.is_visible().ok())
+        .unwrap_or(false)
+}
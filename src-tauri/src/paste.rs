@@ -0,0 +1,1120 @@
+//! The paste itself, as an orchestration over a [`PasteBackend`].
+//!
+//! [`run`] is the sequence every paste goes through: handing focus back to
+//! the target app, a short delay, the clipboard write, the paste keystroke
+//! and caret placement. It only decides what happens in which
+//! order and what a failure stops; the platform work is behind
+//! [`PasteBackend`] and the delay behind [`Clock`], so the sequence can be
+//! driven without a GUI session. [`PlatformBackend`] is the real thing,
+//! unless [`PasteBackendState`] puts another in its place, as the tests do.
+//!
+//! The clipboard is left holding a dictation's text on purpose: it is the
+//! fallback when the keystroke can't be sent, so nothing restores the
+//! previous contents. Re-pasting an earlier transcript
+//! (`restore_clipboard`) puts the text the clipboard held back once it is
+//! pasted, since the user didn't ask for that one to be on it; contents
+//! other than text can't be read, and are lost as before.
+//!
+//! How the text is pasted depends on the app it goes into, looked up once
+//! focus is back there: its paste keystroke, and what happens to line
+//! breaks, so a terminal doesn't run a transcript line by line (see
+//! [`crate::app_paste`]).
+//!
+//! Text over `paste_chunking.threshold_chars` is pasted in chunks (see
+//! [`crate::chunking`]): clipboard write and keystroke once per chunk, with
+//! a wait between chunks. Before each chunk after the first, the app in
+//! front is checked to still be the one the paste started in, and the
+//! paste can be stopped ([`stop_paste`]); either way it stops there, and
+//! the error says how much was pasted ([`Progress`]).
+//!
+//! Where secure input is on (a password field has focus, or Secure
+//! Keyboard Entry in a terminal), macOS drops the keystroke without an
+//! error, so the paste would seem to succeed and paste nothing. It is
+//! checked before each chunk's clipboard write, and the paste stops there
+//! with [`ErrorCode::SecureInput`].
+//!
+//! Every clipboard write is read back before the keystroke is sent, since
+//! a clipboard that hasn't taken the text yet, or has changed it, pastes
+//! something else: emoji sequences and CJK text have arrived cut short. The
+//! two are compared grapheme by grapheme after NFC normalization and with
+//! `\r\n` and `\r` line breaks taken as `\n`, which clipboards may convert
+//! to without changing what is pasted ([`first_difference`]). A mismatch is
+//! polled a few more times, backing off, then written again, and after
+//! [`CLIPBOARD_ATTEMPTS`] fails the paste with
+//! [`ErrorCode::ClipboardMismatch`] and where the text first differed. A
+//! clipboard that can't be read back isn't checked.
+
+use std::fmt;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::Manager;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::accessibility;
+use crate::app_paste::{self, AppPasteSettings};
+use crate::chunking::{self, ChunkingSettings};
+use crate::clipboard_manager::{self, ClipboardManager};
+use crate::clock::Clock;
+use crate::errors::ErrorCode;
+use crate::frontmost;
+use crate::keystroke::{self, Chord};
+use crate::logging;
+use crate::pill;
+use crate::snippets::CursorPlacement;
+use crate::timings::Stage;
+use crate::AppHandle;
+
+/// Wait after the last keystroke before the clipboard's previous text is
+/// put back: the app reads the clipboard when it handles the keystroke,
+/// which may be after it was sent.
+const RESTORE_DELAY: Duration = Duration::from_millis(250);
+
+/// Times the clipboard is written before a mismatch fails the paste.
+pub const CLIPBOARD_ATTEMPTS: u32 = 3;
+
+/// Waits before each read of the clipboard after writing it, until it
+/// holds the text: at once, then backing off, 150ms in all.
+const READBACK_WAITS_MS: &[u64] = &[0, 10, 20, 40, 80];
+
+/// The platform work a paste needs.
+pub trait PasteBackend {
+    /// Puts `text` on the clipboard.
+    fn write_clipboard(&self, text: &str) -> Result<(), String>;
+    /// The text on the clipboard.
+    fn read_clipboard(&self) -> Result<String, String>;
+    /// Hands focus back to the app being dictated into, by getting our
+    /// windows out of the way.
+    fn activate_app(&self) -> Result<(), String>;
+    /// Sends `chord`, or the platform paste shortcut if `None`, to the
+    /// focused app.
+    fn send_paste_keystroke(&self, chord: Option<&Chord>) -> Result<(), String>;
+    /// Presses the Left arrow `count` times in the focused app.
+    fn send_left_arrows(&self, count: usize) -> Result<(), String>;
+    /// Presses `chords` one after the other in the focused app, for voice
+    /// commands (see [`crate::command_mode`]).
+    fn send_chords(&self, chords: &[Chord]) -> Result<(), String>;
+    /// Bundle id of the focused app, if known.
+    fn frontmost_app(&self) -> Option<String>;
+    /// Whether secure input is on, dropping the keystrokes sent now.
+    fn secure_input(&self) -> bool;
+}
+
+/// How a paste runs.
+#[derive(Debug, Clone)]
+pub struct PasteOptions {
+    /// Whether focus has to be handed back before the keystroke; not when
+    /// the pill is a non-activating panel and the target app kept it.
+    pub return_focus: bool,
+    /// Wait between handing focus back and the keystroke.
+    pub delay: Duration,
+    /// When and how to paste in chunks.
+    pub chunking: ChunkingSettings,
+    /// How to paste into which app.
+    pub apps: AppPasteSettings,
+    /// Write the text to the clipboard again once it is pasted, for a
+    /// clipboard manager that took the clipboard over meanwhile.
+    pub reassert_clipboard: bool,
+    /// Put back the text the clipboard held before, once the text is
+    /// pasted. A failed paste leaves the text on it, as the fallback.
+    pub restore_clipboard: bool,
+}
+
+/// How far a paste in chunks got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    /// Chunks pasted, of `chunks`.
+    pub pasted_chunks: usize,
+    pub chunks: usize,
+    /// Characters pasted, of `chars`.
+    pub pasted_chars: usize,
+    pub chars: usize,
+}
+
+/// Payload of `paste:progress`, emitted after each chunk.
+#[derive(Debug, Clone, Serialize)]
+pub struct PasteProgress {
+    /// The dictation pasted, if it is one.
+    pub session_id: Option<String>,
+    #[serde(flatten)]
+    pub progress: Progress,
+}
+
+/// A failed paste: the step that failed and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasteError {
+    pub stage: Stage,
+    pub message: String,
+    /// For a paste in chunks, what was pasted before it failed.
+    pub partial: Option<Progress>,
+    /// When the clipboard didn't hold the text, the grapheme it first
+    /// differed at (see [`first_difference`]).
+    pub mismatch_at: Option<usize>,
+    /// The text that wasn't pasted, as it would have been pasted into the
+    /// target app; `None` when it failed before the app was known.
+    pub unpasted: Option<String>,
+    /// Whether it stopped because secure input was on.
+    pub secure_input: bool,
+}
+
+impl PasteError {
+    pub fn new(stage: Stage, message: String) -> Self {
+        Self {
+            stage,
+            message,
+            partial: None,
+            mismatch_at: None,
+            unpasted: None,
+            secure_input: false,
+        }
+    }
+
+    /// The code of the failure, from the step that failed. A refused
+    /// keystroke on macOS without Accessibility access is a permission
+    /// problem rather than a keystroke one.
+    pub fn code(&self) -> ErrorCode {
+        match self.stage {
+            Stage::Clipboard if self.mismatch_at.is_some() => ErrorCode::ClipboardMismatch,
+            Stage::Clipboard => ErrorCode::Clipboard,
+            Stage::FocusWait => ErrorCode::FocusReturn,
+            Stage::Keystroke if self.secure_input => ErrorCode::SecureInput,
+            Stage::Keystroke if cfg!(target_os = "macos") && !accessibility::is_trusted() => {
+                ErrorCode::PermAccessibility
+            }
+            Stage::Keystroke => ErrorCode::PasteKeystroke,
+            Stage::ChunkWait => ErrorCode::PasteStopped,
+            Stage::Caret => ErrorCode::Caret,
+            // Not paste steps
+            Stage::Recording | Stage::Transcription | Stage::PostProcess | Stage::PasteQueue => {
+                ErrorCode::Internal
+            }
+        }
+    }
+}
+
+impl fmt::Display for PasteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Pastes `text` through `backend` and returns the text pasted (with any
+/// `{cursor}` marker removed, and its line breaks as the target app takes
+/// them). `record` is told how long each stage took,
+/// including one that failed. A paste in chunks tells `progress` after each
+/// chunk, and stops before the next one if it breaks.
+///
+/// # Errors
+///
+/// Returns the error of the first step that fails, with its stage; the
+/// steps after it don't run.
+pub fn run(
+    backend: &(impl PasteBackend + ?Sized),
+    clock: &impl Clock,
+    text: &str,
+    options: PasteOptions,
+    mut record: impl FnMut(Stage, Duration),
+    mut progress: impl FnMut(Progress) -> ControlFlow<()>,
+) -> Result<String, PasteError> {
+    // Steps 1 and 2 are skipped when the pill is a non-activating panel:
+    // the target app never lost focus, so there is nothing to wait for
+    if options.return_focus {
+        timed(clock, Stage::FocusWait, &mut record, || {
+            // Step 1: Return focus to the previous application. This is
+            // crucial - the paste keystroke must go to the app that was
+            // focused before our window appeared, not to our window
+            backend.activate_app()?;
+            // Step 2: Small delay to ensure the OS completes the focus
+            // shift. Without this, paste may fail
+            clock.sleep(options.delay);
+            Ok(())
+        })?;
+    }
+
+    // Step 3: The app pasted into decides how (see `app_paste`). Line
+    // breaks are handled before the `{cursor}` marker (if any) is stripped,
+    // so the caret presses count the text as pasted, and the clipboard only
+    // ever holds the text the user should see
+    let target_app = backend.frontmost_app();
+    let rule = app_paste::rule_for(&options.apps, target_app.as_deref());
+    let placement = CursorPlacement::from_body(&rule.newlines.apply(text));
+    let chunks = if options.chunking.applies_to(&placement.text) {
+        chunking::split(&placement.text, options.chunking.chunk_chars)
+    } else {
+        vec![placement.text.as_str()]
+    };
+    let chunked = chunks.len() > 1;
+    let mut done = Progress {
+        pasted_chunks: 0,
+        chunks: chunks.len(),
+        pasted_chars: 0,
+        chars: placement.text.chars().count(),
+    };
+    // Read before the first write; `None` also when it holds no text
+    let previous = options
+        .restore_clipboard
+        .then(|| backend.read_clipboard().ok())
+        .flatten();
+    let failed = |e: PasteError, done: Progress| PasteError {
+        partial: chunked.then_some(done),
+        unpasted: Some(chunks[done.pasted_chunks..].concat()),
+        ..e
+    };
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        if index > 0 {
+            timed(clock, Stage::ChunkWait, &mut record, || {
+                clock.sleep(Duration::from_millis(options.chunking.delay_ms));
+                // Pasting on into whatever took focus would put the rest of
+                // the text in the wrong place
+                match (&target_app, backend.frontmost_app()) {
+                    (Some(target), Some(front)) if *target != front => Err(format!(
+                        "focus moved to {} after {} of {} chunks",
+                        front, done.pasted_chunks, done.chunks
+                    )),
+                    _ => Ok(()),
+                }
+            })
+            .map_err(|e| failed(e, done))?;
+        }
+
+        // The keystroke wouldn't arrive, and nothing would say so
+        if backend.secure_input() {
+            let message = "secure input is on, so the paste keystroke would be dropped";
+            let e = PasteError {
+                secure_input: true,
+                ..PasteError::new(Stage::Keystroke, message.to_string())
+            };
+            return Err(failed(e, done));
+        }
+
+        // Step 4: Write to clipboard. Reading it back also makes sure the
+        // OS has it before the keystroke
+        write_clipboard(backend, clock, chunk, &mut record).map_err(|e| failed(e, done))?;
+
+        // Step 5: Simulate the paste keystroke
+        timed(clock, Stage::Keystroke, &mut record, || {
+            backend.send_paste_keystroke(rule.keystroke.as_ref())
+        })
+        .map_err(|e| failed(e, done))?;
+
+        if chunked {
+            done.pasted_chunks += 1;
+            done.pasted_chars += chunk.chars().count();
+            let more = index + 1 < chunks.len();
+            if progress(done).is_break() && more {
+                let message = format!(
+                    "stopped after {} of {} chunks",
+                    done.pasted_chunks, done.chunks
+                );
+                return Err(failed(PasteError::new(Stage::ChunkWait, message), done));
+            }
+        }
+    }
+
+    if let Some(previous) = &previous {
+        clock.sleep(RESTORE_DELAY);
+        if let Err(e) = backend.write_clipboard(previous) {
+            tracing::warn!("Failed to restore the clipboard after the paste: {}", e);
+        }
+    } else if options.reassert_clipboard {
+        // A clipboard manager may have taken the clipboard over meanwhile;
+        // leave it holding the text, as the fallback
+        if let Err(e) = backend.write_clipboard(&placement.text) {
+            tracing::warn!("Failed to write the clipboard again after the paste: {}", e);
+        }
+    }
+
+    // Step 6: Walk the caret back to the marker. Only reached when the paste
+    // succeeded, otherwise we'd be pressing arrows in unrelated text
+    if let Some(presses) = placement.left_presses {
+        if placement.is_unreliable_in(target_app.as_deref()) {
+            tracing::warn!(
+                "Skipping cursor positioning: text after marker spans lines in {}",
+                target_app.as_deref().unwrap_or("unknown app")
+            );
+        } else {
+            timed(clock, Stage::Caret, &mut record, || {
+                backend.send_left_arrows(presses)
+            })?;
+        }
+    }
+
+    Ok(placement.text)
+}
+
+/// Runs `step` as `stage`, in a `stage` span, and records how long it took.
+fn timed(
+    clock: &impl Clock,
+    stage: Stage,
+    record: &mut impl FnMut(Stage, Duration),
+    step: impl FnOnce() -> Result<(), String>,
+) -> Result<(), PasteError> {
+    let _span = tracing::info_span!("stage", ?stage).entered();
+    let started = clock.now();
+    let result = step();
+    record(stage, clock.now().saturating_duration_since(started));
+    result.map_err(|message| PasteError::new(stage, message))
+}
+
+/// Writes `text` to the clipboard and reads it back, writing it again
+/// while it doesn't match, as the [`Stage::Clipboard`] stage.
+fn write_clipboard(
+    backend: &(impl PasteBackend + ?Sized),
+    clock: &impl Clock,
+    text: &str,
+    record: &mut impl FnMut(Stage, Duration),
+) -> Result<(), PasteError> {
+    let mut mismatch_at = None;
+    let result = timed(clock, Stage::Clipboard, record, || {
+        for attempt in 1..=CLIPBOARD_ATTEMPTS {
+            if let Err(e) = backend.write_clipboard(text) {
+                // A failed write, whatever was read before
+                mismatch_at = None;
+                return Err(e);
+            }
+            let mut found = String::new();
+            for &wait in READBACK_WAITS_MS {
+                clock.sleep(Duration::from_millis(wait));
+                found = match backend.read_clipboard() {
+                    Ok(found) => found,
+                    Err(e) => {
+                        tracing::debug!("Can't read the clipboard back, not checking it: {}", e);
+                        return Ok(());
+                    }
+                };
+                mismatch_at = first_difference(text, &found);
+                if mismatch_at.is_none() {
+                    return Ok(());
+                }
+            }
+            // Lengths only, unless transcripts may be logged
+            tracing::warn!(
+                attempt,
+                offset = mismatch_at,
+                written = %logging::transcript(text),
+                found = %logging::transcript(&found),
+                "Clipboard doesn't hold the text written to it"
+            );
+        }
+        Err(format!(
+            "the clipboard didn't keep the text: it differs from character {} on",
+            mismatch_at.unwrap_or_default()
+        ))
+    });
+    result.map_err(|e| PasteError { mismatch_at, ..e })
+}
+
+/// Where `found` first differs from `expected`, counted in grapheme
+/// clusters, or `None` if they are the same text. Both are compared in NFC,
+/// with every line break as `\n`.
+pub fn first_difference(expected: &str, found: &str) -> Option<usize> {
+    let normalize = |text: &str| -> String {
+        text.replace("\r\n", "\n")
+            .replace('\r', "\n")
+            .nfc()
+            .collect()
+    };
+    let (expected, found) = (normalize(expected), normalize(found));
+    let (mut expected, mut found) = (expected.graphemes(true), found.graphemes(true));
+    let mut at = 0;
+    loop {
+        match (expected.next(), found.next()) {
+            (None, None) => return None,
+            (a, b) if a == b => at += 1,
+            _ => return Some(at),
+        }
+    }
+}
+
+/// Managed state: whether the paste in chunks under way should stop after
+/// its current chunk.
+#[derive(Default)]
+pub struct PasteStopState(AtomicBool);
+
+impl PasteStopState {
+    /// Clears a stop asked for an earlier paste.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    /// Asks the paste under way to stop.
+    pub fn request(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether a stop was asked for.
+    pub fn requested(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Stops the paste in chunks under way after its current chunk; the rest
+/// of the text is left on the clipboard. Does nothing for other pastes,
+/// which are a single keystroke.
+#[tauri::command]
+pub fn stop_paste(app: AppHandle) {
+    app.state::<PasteStopState>().request();
+}
+
+/// Leaves `text` on the clipboard after a failed paste, for the user to
+/// paste by hand. Returns whether it is there.
+pub fn copy_fallback(app: &AppHandle, text: &str) -> bool {
+    let platform = PlatformBackend { app, store: None };
+    app.state::<PasteBackendState>()
+        .or(&platform)
+        .write_clipboard(text)
+        .is_ok()
+}
+
+/// Managed state: the backend to paste with instead of the platform's, if
+/// any. The tests put a [`mock::MockPasteBackend`] here.
+#[derive(Default)]
+pub struct PasteBackendState(Option<Arc<dyn PasteBackend + Send + Sync>>);
+
+impl PasteBackendState {
+    #[cfg(test)]
+    pub fn new(backend: Arc<dyn PasteBackend + Send + Sync>) -> Self {
+        Self(Some(backend))
+    }
+
+    /// The backend to paste with: the one put in place, or `platform`.
+    pub fn or<'a>(&'a self, platform: &'a dyn PasteBackend) -> &'a dyn PasteBackend {
+        match &self.0 {
+            Some(backend) => backend.as_ref(),
+            None => platform,
+        }
+    }
+}
+
+/// Pastes for real: the clipboard plugin, our windows and
+/// [`keystroke`].
+pub struct PlatformBackend<'a> {
+    pub app: &'a AppHandle,
+    /// The clipboard manager to hand text to instead of writing the
+    /// clipboard (see [`clipboard_manager::store`]).
+    pub store: Option<ClipboardManager>,
+}
+
+impl PasteBackend for PlatformBackend<'_> {
+    fn write_clipboard(&self, text: &str) -> Result<(), String> {
+        if let Some(manager) = self.store {
+            match clipboard_manager::store(manager, text) {
+                Ok(()) => return Ok(()),
+                Err(e) => tracing::debug!(
+                    ?manager,
+                    "Clipboard manager didn't take the text, writing the clipboard: {}",
+                    e
+                ),
+            }
+        }
+        self.app
+            .clipboard()
+            .write_text(text.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    fn read_clipboard(&self) -> Result<String, String> {
+        self.app.clipboard().read_text().map_err(|e| e.to_string())
+    }
+
+    fn activate_app(&self) -> Result<(), String> {
+        // No fade-out here, it would hold up the focus change
+        pill::hide_now(self.app)?;
+        // With another of our windows open (e.g. settings), macOS would hand
+        // focus to it instead. Hiding the app returns focus to the previous
+        // one and keeps those windows for when the user comes back
+        #[cfg(target_os = "macos")]
+        if self
+            .app
+            .webview_windows()
+            .values()
+            .any(|window| window.is_visible().unwrap_or(false))
+        {
+            self.app.hide().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn send_paste_keystroke(&self, chord: Option<&Chord>) -> Result<(), String> {
+        match chord {
+            // Without an injector the user pastes by hand, whatever the chord
+            Some(chord) if keystroke::injector().is_some() => {
+                keystroke::send_chords(std::slice::from_ref(chord))
+            }
+            _ => keystroke::send_paste(),
+        }
+    }
+
+    fn send_left_arrows(&self, count: usize) -> Result<(), String> {
+        keystroke::send_left_arrows(count)
+    }
+
+    fn send_chords(&self, chords: &[Chord]) -> Result<(), String> {
+        keystroke::send_chords(chords)
+    }
+
+    fn frontmost_app(&self) -> Option<String> {
+        frontmost::frontmost_bundle_id()
+    }
+
+    fn secure_input(&self) -> bool {
+        keystroke::secure_input_enabled()
+    }
+}
+
+/// A [`PasteBackend`] for tests, without a GUI session.
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    use super::PasteBackend;
+    use crate::keystroke::Chord;
+
+    /// A call the backend got.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Call {
+        WriteClipboard(String),
+        ReadClipboard,
+        ActivateApp,
+        /// A paste keystroke, pasting what the clipboard held then.
+        PasteKeystroke {
+            pasted: String,
+        },
+        LeftArrows(usize),
+        /// Chords pressed for voice commands.
+        Chords(Vec<Chord>),
+    }
+
+    /// A call that can be made to fail.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Step {
+        WriteClipboard,
+        ActivateApp,
+        PasteKeystroke,
+        Chords,
+    }
+
+    /// Records every call, and keeps a clipboard and the app in front.
+    /// Calls of the steps made to fail ([`fail`](Self::fail)) are recorded,
+    /// then fail without doing anything.
+    #[derive(Debug, Default)]
+    pub struct MockPasteBackend {
+        clipboard: Mutex<String>,
+        /// What the clipboard makes of text written to it.
+        mangle: Mutex<Option<fn(&str) -> String>>,
+        /// Reads after a write that still find the clipboard as it was.
+        lag: Mutex<usize>,
+        /// Text written but not on the clipboard yet, and the reads left
+        /// before it is.
+        landing: Mutex<Option<(String, usize)>>,
+        frontmost: Mutex<Option<String>>,
+        calls: Mutex<Vec<Call>>,
+        failing: Mutex<Vec<Step>>,
+        secure_input: AtomicBool,
+    }
+
+    impl MockPasteBackend {
+        /// A backend whose clipboard holds `text`.
+        pub fn with_clipboard(text: &str) -> Self {
+            let backend = Self::default();
+            *backend.clipboard.lock().unwrap() = text.to_string();
+            backend
+        }
+
+        pub fn clipboard(&self) -> String {
+            self.clipboard.lock().unwrap().clone()
+        }
+
+        /// Every call so far, in order.
+        pub fn calls(&self) -> Vec<Call> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        /// What each paste keystroke pasted, in order.
+        pub fn pasted(&self) -> Vec<String> {
+            self.calls()
+                .into_iter()
+                .filter_map(|call| match call {
+                    Call::PasteKeystroke { pasted } => Some(pasted),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        /// Makes every later call of `step` fail.
+        pub fn fail(&self, step: Step) {
+            self.failing.lock().unwrap().push(step);
+        }
+
+        /// Makes the clipboard hold `mangle` of what is written to it, like
+        /// one that converts or cuts short some text.
+        pub fn mangle_clipboard(&self, mangle: fn(&str) -> String) {
+            *self.mangle.lock().unwrap() = Some(mangle);
+        }
+
+        /// Makes the clipboard keep what it held for `reads` reads after
+        /// each write, like one a clipboard manager takes over for a moment.
+        pub fn lag_clipboard(&self, reads: usize) {
+            *self.lag.lock().unwrap() = reads;
+        }
+
+        pub fn set_secure_input(&self, on: bool) {
+            self.secure_input.store(on, Ordering::SeqCst);
+        }
+
+        fn record(&self, call: Call) {
+            self.calls.lock().unwrap().push(call);
+        }
+
+        fn check(&self, step: Step) -> Result<(), String> {
+            if self.failing.lock().unwrap().contains(&step) {
+                return Err(format!("{:?} failed", step));
+            }
+            Ok(())
+        }
+    }
+
+    impl PasteBackend for MockPasteBackend {
+        fn write_clipboard(&self, text: &str) -> Result<(), String> {
+            self.record(Call::WriteClipboard(text.to_string()));
+            self.check(Step::WriteClipboard)?;
+            let held = match *self.mangle.lock().unwrap() {
+                Some(mangle) => mangle(text),
+                None => text.to_string(),
+            };
+            let lag = *self.lag.lock().unwrap();
+            if lag == 0 {
+                *self.clipboard.lock().unwrap() = held;
+            } else {
+                *self.landing.lock().unwrap() = Some((held, lag));
+            }
+            Ok(())
+        }
+
+        fn read_clipboard(&self) -> Result<String, String> {
+            self.record(Call::ReadClipboard);
+            let mut landing = self.landing.lock().unwrap();
+            match landing.as_mut() {
+                Some((_, 0)) => {
+                    let (held, _) = landing.take().unwrap();
+                    *self.clipboard.lock().unwrap() = held;
+                }
+                Some((_, left)) => *left -= 1,
+                None => {}
+            }
+            Ok(self.clipboard())
+        }
+
+        fn activate_app(&self) -> Result<(), String> {
+            self.record(Call::ActivateApp);
+            self.check(Step::ActivateApp)
+        }
+
+        fn send_paste_keystroke(&self, _chord: Option<&Chord>) -> Result<(), String> {
+            let pasted = self.clipboard();
+            self.record(Call::PasteKeystroke { pasted });
+            self.check(Step::PasteKeystroke)
+        }
+
+        fn send_left_arrows(&self, count: usize) -> Result<(), String> {
+            self.record(Call::LeftArrows(count));
+            Ok(())
+        }
+
+        fn send_chords(&self, chords: &[Chord]) -> Result<(), String> {
+            self.record(Call::Chords(chords.to_vec()));
+            self.check(Step::Chords)
+        }
+
+        fn frontmost_app(&self) -> Option<String> {
+            self.frontmost.lock().unwrap().clone()
+        }
+
+        fn secure_input(&self) -> bool {
+            self.secure_input.load(Ordering::SeqCst)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{Call, MockPasteBackend, Step};
+    use super::*;
+    use crate::clock::MockClock;
+
+    const TEXT: &str = "Hello {cursor}world";
+    const PASTED: &str = "Hello world";
+
+    fn options() -> PasteOptions {
+        PasteOptions {
+            return_focus: true,
+            delay: Duration::from_millis(150),
+            chunking: ChunkingSettings::default(),
+            apps: AppPasteSettings::default(),
+            reassert_clipboard: false,
+            restore_clipboard: false,
+        }
+    }
+
+    /// Pastes `text` through `backend`, returning the result and the stages
+    /// recorded.
+    fn paste(
+        backend: &MockPasteBackend,
+        clock: &MockClock,
+        text: &str,
+        options: PasteOptions,
+    ) -> (Result<String, PasteError>, Vec<Stage>) {
+        let mut stages = Vec::new();
+        let pasted = run(
+            backend,
+            clock,
+            text,
+            options,
+            |stage, _| stages.push(stage),
+            |_| ControlFlow::Continue(()),
+        );
+        (pasted, stages)
+    }
+
+    #[test]
+    fn a_paste_returns_focus_writes_checks_presses_and_places_the_caret() {
+        let backend = MockPasteBackend::with_clipboard("the user's own");
+        let clock = MockClock::default();
+        let started = clock.now();
+
+        let (pasted, stages) = paste(&backend, &clock, TEXT, options());
+
+        assert_eq!(pasted, Ok(PASTED.to_string()));
+        assert_eq!(
+            backend.calls(),
+            [
+                Call::ActivateApp,
+                Call::WriteClipboard(PASTED.to_string()),
+                Call::ReadClipboard,
+                Call::PasteKeystroke {
+                    pasted: PASTED.to_string()
+                },
+                Call::LeftArrows(5),
+            ]
+        );
+        assert_eq!(
+            stages,
+            [
+                Stage::FocusWait,
+                Stage::Clipboard,
+                Stage::Keystroke,
+                Stage::Caret
+            ]
+        );
+        // The paste delay, and the clipboard held the text at once
+        assert_eq!(clock.now() - started, Duration::from_millis(150));
+        // Left holding the text, as the fallback
+        assert_eq!(backend.clipboard(), PASTED);
+    }
+
+    #[test]
+    fn a_re_paste_puts_the_clipboard_back_afterwards() {
+        let backend = MockPasteBackend::with_clipboard("the user's own");
+        let clock = MockClock::default();
+        let options = PasteOptions {
+            restore_clipboard: true,
+            ..options()
+        };
+
+        let (pasted, _) = paste(&backend, &clock, PASTED, options);
+
+        assert_eq!(pasted, Ok(PASTED.to_string()));
+        assert_eq!(
+            backend.calls(),
+            [
+                Call::ActivateApp,
+                Call::ReadClipboard,
+                Call::WriteClipboard(PASTED.to_string()),
+                Call::ReadClipboard,
+                Call::PasteKeystroke {
+                    pasted: PASTED.to_string()
+                },
+                Call::WriteClipboard("the user's own".to_string()),
+            ]
+        );
+        assert_eq!(backend.clipboard(), "the user's own");
+    }
+
+    #[test]
+    fn a_failed_clipboard_write_sends_no_keystroke() {
+        let backend = MockPasteBackend::with_clipboard("the user's own");
+        backend.fail(Step::WriteClipboard);
+        let options = PasteOptions {
+            restore_clipboard: true,
+            ..options()
+        };
+
+        let (pasted, stages) = paste(&backend, &MockClock::default(), TEXT, options);
+
+        let e = pasted.unwrap_err();
+        assert_eq!(e.stage, Stage::Clipboard);
+        assert_eq!(e.code(), ErrorCode::Clipboard);
+        assert_eq!(e.mismatch_at, None);
+        assert_eq!(e.unpasted.as_deref(), Some(PASTED));
+        assert_eq!(e.partial, None);
+        assert_eq!(
+            backend.calls(),
+            [
+                Call::ActivateApp,
+                Call::ReadClipboard,
+                Call::WriteClipboard(PASTED.to_string()),
+            ]
+        );
+        assert_eq!(stages, [Stage::FocusWait, Stage::Clipboard]);
+    }
+
+    #[test]
+    fn a_failed_keystroke_after_the_window_is_hidden_leaves_the_text_on_the_clipboard() {
+        let backend = MockPasteBackend::with_clipboard("the user's own");
+        backend.fail(Step::PasteKeystroke);
+        let options = PasteOptions {
+            restore_clipboard: true,
+            ..options()
+        };
+
+        let (pasted, stages) = paste(&backend, &MockClock::default(), TEXT, options);
+
+        let e = pasted.unwrap_err();
+        assert_eq!(e.stage, Stage::Keystroke);
+        assert!(!e.secure_input);
+        if !cfg!(target_os = "macos") {
+            assert_eq!(e.code(), ErrorCode::PasteKeystroke);
+        }
+        assert_eq!(e.unpasted.as_deref(), Some(PASTED));
+        // Focus was handed back before; no caret presses and no restore after
+        assert_eq!(backend.calls()[0], Call::ActivateApp);
+        assert!(!backend
+            .calls()
+            .iter()
+            .any(|call| matches!(call, Call::LeftArrows(_))));
+        assert_eq!(backend.clipboard(), PASTED);
+        assert_eq!(
+            stages,
+            [Stage::FocusWait, Stage::Clipboard, Stage::Keystroke]
+        );
+    }
+
+    #[test]
+    fn secure_input_stops_the_paste_before_the_clipboard_is_touched() {
+        let backend = MockPasteBackend::with_clipboard("the user's own");
+        backend.set_secure_input(true);
+
+        let (pasted, stages) = paste(&backend, &MockClock::default(), TEXT, options());
+
+        let e = pasted.unwrap_err();
+        assert_eq!(e.stage, Stage::Keystroke);
+        assert!(e.secure_input);
+        assert_eq!(e.code(), ErrorCode::SecureInput);
+        assert_eq!(e.unpasted.as_deref(), Some(PASTED));
+        assert_eq!(backend.calls(), [Call::ActivateApp]);
+        assert_eq!(backend.clipboard(), "the user's own");
+        assert_eq!(stages, [Stage::FocusWait]);
+    }
+
+    #[test]
+    fn secure_input_turned_on_partway_stops_a_paste_in_chunks() {
+        let backend = MockPasteBackend::default();
+        let options = PasteOptions {
+            chunking: ChunkingSettings {
+                threshold_chars: 1_000,
+                chunk_chars: 500,
+                delay_ms: 150,
+            },
+            ..options()
+        };
+        let text = "word ".repeat(300);
+
+        let pasted = run(
+            &backend,
+            &MockClock::default(),
+            &text,
+            options,
+            |_, _| {},
+            |_| {
+                backend.set_secure_input(true);
+                ControlFlow::Continue(())
+            },
+        );
+
+        let e = pasted.unwrap_err();
+        assert_eq!(e.code(), ErrorCode::SecureInput);
+        assert_eq!(e.partial.map(|partial| partial.pasted_chunks), Some(1));
+        let pasted = backend.pasted();
+        assert_eq!(pasted.len(), 1);
+        assert_eq!(format!("{}{}", pasted[0], e.unpasted.unwrap()), text);
+    }
+
+    #[test]
+    fn texts_only_differing_in_normalization_or_line_breaks_are_the_same() {
+        // "é" composed, and as "e" with a combining acute
+        assert_eq!(first_difference("caf\u{e9}", "cafe\u{301}"), None);
+        assert_eq!(first_difference("cafe\u{301}", "caf\u{e9}"), None);
+        for (expected, found) in [
+            ("one\ntwo\n", "one\r\ntwo\r\n"),
+            ("one\ntwo\n", "one\rtwo\r"),
+            ("one\r\ntwo", "one\rtwo"),
+        ] {
+            assert_eq!(first_difference(expected, found), None, "{:?}", found);
+        }
+        // A line break is still one, not two
+        assert_eq!(first_difference("one\ntwo", "one\n\ntwo"), Some(4));
+    }
+
+    #[test]
+    fn a_difference_is_counted_in_graphemes() {
+        assert_eq!(first_difference("hello world", "hello wxrld"), Some(7));
+        assert_eq!(first_difference("hello", "hello world"), Some(5));
+        assert_eq!(first_difference("hello world", "hello"), Some(5));
+        assert_eq!(first_difference("hello", ""), Some(0));
+
+        // A family missing its last member is another grapheme, and
+        // graphemes count as one however many chars they are
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+        let cut = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let expected = format!("{} {} e\u{301}!", family, family);
+        assert_eq!(first_difference(&expected, &expected), None);
+        assert_eq!(
+            first_difference(&expected, &format!("{} {} e\u{301}!", family, cut)),
+            Some(2)
+        );
+        assert_eq!(
+            first_difference(&expected, &format!("{} {} e!", family, family)),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn a_clipboard_that_changes_the_text_fails_the_paste_after_every_attempt() {
+        let backend = MockPasteBackend::with_clipboard("the user's own");
+        // It turns curly quotes straight
+        backend.mangle_clipboard(|text| text.replace('\u{2019}', "'"));
+        let clock = MockClock::default();
+        let started = clock.now();
+        let text = "It\u{2019}s done";
+
+        let (pasted, stages) = paste(&backend, &clock, text, options());
+
+        let e = pasted.unwrap_err();
+        assert_eq!(e.stage, Stage::Clipboard);
+        assert_eq!(e.code(), ErrorCode::ClipboardMismatch);
+        assert_eq!(e.mismatch_at, Some(2));
+        assert_eq!(e.unpasted.as_deref(), Some(text));
+        assert!(e.message.contains("from character 2"), "{}", e.message);
+        let calls = backend.calls();
+        let written = calls
+            .iter()
+            .filter(|call| **call == Call::WriteClipboard(text.to_string()))
+            .count();
+        assert_eq!(written, CLIPBOARD_ATTEMPTS as usize);
+        let reads = calls
+            .iter()
+            .filter(|call| **call == Call::ReadClipboard)
+            .count();
+        assert_eq!(reads, CLIPBOARD_ATTEMPTS as usize * READBACK_WAITS_MS.len());
+        assert!(backend.pasted().is_empty());
+        assert_eq!(stages, [Stage::FocusWait, Stage::Clipboard]);
+        // The paste delay, then every wait of every attempt
+        let waited: u64 = READBACK_WAITS_MS.iter().sum();
+        assert_eq!(
+            clock.now() - started,
+            Duration::from_millis(150 + waited * u64::from(CLIPBOARD_ATTEMPTS))
+        );
+    }
+    #[test]
+    fn the_clipboard_is_polled_with_a_growing_wait_until_it_holds_the_text() {
+        for lag in 0..READBACK_WAITS_MS.len() {
+            let backend = MockPasteBackend::with_clipboard("the user's own");
+            backend.lag_clipboard(lag);
+            let clock = MockClock::default();
+            let started = clock.now();
+
+            let (pasted, _) = paste(&backend, &clock, PASTED, options());
+
+            assert_eq!(pasted, Ok(PASTED.to_string()));
+            let calls = backend.calls();
+            let written = calls
+                .iter()
+                .filter(|call| matches!(call, Call::WriteClipboard(_)))
+                .count();
+            let reads = calls
+                .iter()
+                .filter(|call| **call == Call::ReadClipboard)
+                .count();
+            assert_eq!((written, reads), (1, lag + 1));
+            assert_eq!(backend.pasted(), [PASTED]);
+            // The paste delay, then each wait up to the read that found it
+            let waited: u64 = READBACK_WAITS_MS[..=lag].iter().sum();
+            assert_eq!(
+                clock.now() - started,
+                Duration::from_millis(150 + waited),
+                "lag {}",
+                lag
+            );
+        }
+    }
+
+    #[test]
+    fn a_clipboard_that_never_holds_the_text_fails_the_paste_in_bounded_time() {
+        let backend = MockPasteBackend::with_clipboard("the user's own");
+        backend.lag_clipboard(usize::MAX);
+        let clock = MockClock::default();
+        let started = clock.now();
+
+        let (pasted, _) = paste(&backend, &clock, PASTED, options());
+
+        let e = pasted.unwrap_err();
+        assert_eq!(e.code(), ErrorCode::ClipboardMismatch);
+        assert_eq!(e.mismatch_at, Some(0));
+        let reads = backend
+            .calls()
+            .iter()
+            .filter(|call| **call == Call::ReadClipboard)
+            .count();
+        assert_eq!(reads, CLIPBOARD_ATTEMPTS as usize * READBACK_WAITS_MS.len());
+        assert!(backend.pasted().is_empty());
+        let waited: u64 = READBACK_WAITS_MS.iter().sum();
+        assert_eq!(
+            clock.now() - started,
+            Duration::from_millis(150 + waited * u64::from(CLIPBOARD_ATTEMPTS))
+        );
+    }
+
+    #[test]
+    fn with_a_clipboard_manager_the_text_is_written_once_more_after_the_paste() {
+        let backend = MockPasteBackend::with_clipboard("the user's own");
+        let clock = MockClock::default();
+        let options = PasteOptions {
+            reassert_clipboard: true,
+            ..options()
+        };
+
+        let (pasted, _) = paste(&backend, &clock, PASTED, options);
+
+        assert_eq!(pasted, Ok(PASTED.to_string()));
+        assert_eq!(
+            backend.calls(),
+            [
+                Call::ActivateApp,
+                Call::WriteClipboard(PASTED.to_string()),
+                Call::ReadClipboard,
+                Call::PasteKeystroke {
+                    pasted: PASTED.to_string()
+                },
+                Call::WriteClipboard(PASTED.to_string()),
+            ]
+        );
+        assert_eq!(backend.clipboard(), PASTED);
+    }
+}
@@ -0,0 +1,35 @@
+//! Easing for animated pill resizes and fades.
+//!
+//! The pill eases between the sizes of its states instead of jumping, with
+//! its position interpolated along with the size so the anchor (e.g. the top
+//! edge for a pill at the top of the screen) stays put throughout.
+
+use std::time::Duration;
+
+use super::placement::Rect;
+
+/// How long a resize takes.
+pub const DURATION: Duration = Duration::from_millis(150);
+
+/// How long fading the pill in or out takes.
+pub const FADE_DURATION: Duration = Duration::from_millis(120);
+
+/// Time between two frames, about 60 per second.
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Cubic ease-out: fast at first, settling gently.
+fn ease_out(t: f64) -> f64 {
+    1.0 - (1.0 - t).powi(3)
+}
+
+/// The frame `progress` (0 to 1) of the way from `from` to `to`.
+pub fn frame(from: Rect, to: Rect, progress: f64) -> Rect {
+    let t = ease_out(progress.clamp(0.0, 1.0));
+    let lerp = |a: f64, b: f64| a + (b - a) * t;
+    Rect {
+        x: lerp(from.x, to.x),
+        y: lerp(from.y, to.y),
+        width: lerp(from.width, to.width),
+        height: lerp(from.height, to.height),
+    }
+}
@@ -0,0 +1,188 @@
+//! The pill's translucent, blurred background.
+//!
+//! With `pill.vibrancy` the window behind the webview gets a native blur
+//! material and the webview draws no background of its own, so the
+//! frontend can paint a translucent pill over it (it asks with
+//! [`get_pill_appearance`](super::get_pill_appearance) and follows
+//! `pill:appearance-changed`). The effect view sits behind the webview, so
+//! clicks and drags still reach the page.
+//!
+//! The effect is applied whenever the window is created (including when it
+//! is recreated) and again when the setting changes; each window remembers
+//! what it has, so re-applying the same appearance is free.
+//!
+//! # Platform Support
+//!
+//! - macOS: an `NSVisualEffectView` with `pill.material` (HUD by default),
+//!   kept active while the app is inactive, which it nearly always is.
+//! - Windows: acrylic, or mica where acrylic isn't available (Windows 11
+//!   builds without it); `pill.material` has no effect.
+//! - Linux: not supported; the pill keeps its plain transparent background.
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use super::PillWindowState;
+
+use crate::{AppHandle, WebviewWindow};
+
+/// Corner radius of the blurred background, in logical pixels. The
+/// frontend rounds the pill to match.
+#[cfg(target_os = "macos")]
+const CORNER_RADIUS: f64 = 16.0;
+
+/// Tint of the Windows acrylic background (RGBA).
+#[cfg(target_os = "windows")]
+const ACRYLIC_TINT: (u8, u8, u8, u8) = (18, 18, 18, 125);
+
+/// Blur material behind the pill (macOS).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VibrancyMaterial {
+    /// The dark, heavily blurred look of HUD panels.
+    #[default]
+    Hud,
+    Popover,
+    Menu,
+    Sidebar,
+    UnderWindow,
+}
+
+/// The pill's background as applied, sent with `pill:appearance-changed`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PillAppearance {
+    /// Whether the background is blurred; `false` when vibrancy is off or
+    /// not available here.
+    pub vibrancy: bool,
+    pub material: VibrancyMaterial,
+}
+
+/// The appearance of the current window.
+#[derive(Default)]
+pub(super) struct AppliedAppearance {
+    /// What the window was last asked for, so asking again is free.
+    wanted: Mutex<Option<PillAppearance>>,
+    /// What it shows.
+    shown: Mutex<PillAppearance>,
+}
+
+impl AppliedAppearance {
+    pub(super) fn get(&self) -> PillAppearance {
+        *self.shown.lock().unwrap()
+    }
+
+    /// Forgets the appearance of a destroyed window.
+    pub(super) fn reset(&self) {
+        *self.wanted.lock().unwrap() = None;
+        *self.shown.lock().unwrap() = PillAppearance::default();
+    }
+}
+
+/// Gives `window` the background configured, unless it already has it.
+pub(super) fn apply(
+    app: &AppHandle,
+    window: &WebviewWindow,
+    vibrancy: bool,
+    material: VibrancyMaterial,
+) {
+    let handle = app.clone();
+    let window = window.clone();
+    // The effect views may only be changed on the main thread
+    let _ = app.run_on_main_thread(move || {
+        let applied = &handle.state::<PillWindowState>().appearance;
+        let mut asked = applied.wanted.lock().unwrap();
+        let wanted = PillAppearance { vibrancy, material };
+        if *asked == Some(wanted) {
+            return;
+        }
+        let previous = applied.get();
+
+        let active = if vibrancy {
+            let active = enable(&window, material, previous);
+            if !active {
+                tracing::warn!("Vibrancy isn't available for the pill; using a plain background");
+            }
+            active
+        } else {
+            if previous.vibrancy {
+                disable(&window);
+            }
+            false
+        };
+        let appearance = PillAppearance {
+            vibrancy: active,
+            material,
+        };
+        // A failed attempt counts as asked too, so it isn't retried on
+        // every settings change
+        *asked = Some(wanted);
+        *applied.shown.lock().unwrap() = appearance;
+        let _ = handle.emit("pill:appearance-changed", appearance);
+    });
+}
+
+#[cfg(target_os = "macos")]
+fn enable(window: &WebviewWindow, material: VibrancyMaterial, previous: PillAppearance) -> bool {
+    use window_vibrancy::{NSVisualEffectMaterial, NSVisualEffectState};
+
+    if previous.vibrancy {
+        let _ = window_vibrancy::clear_vibrancy(window);
+    }
+    let material = match material {
+        VibrancyMaterial::Hud => NSVisualEffectMaterial::HudWindow,
+        VibrancyMaterial::Popover => NSVisualEffectMaterial::Popover,
+        VibrancyMaterial::Menu => NSVisualEffectMaterial::Menu,
+        VibrancyMaterial::Sidebar => NSVisualEffectMaterial::Sidebar,
+        VibrancyMaterial::UnderWindow => NSVisualEffectMaterial::UnderWindowBackground,
+    };
+    if let Err(e) = window_vibrancy::apply_vibrancy(
+        window,
+        material,
+        Some(NSVisualEffectState::Active),
+        Some(CORNER_RADIUS),
+    ) {
+        tracing::warn!("Failed to add the pill's blur: {}", e);
+        return false;
+    }
+
+    // The blur is only seen through a window and webview that draw no
+    // background
+    let Ok(ns_window) = window.ns_window() else {
+        return true;
+    };
+    unsafe { super::macos::clear_window_background(ns_window) };
+    let _ = window.with_webview(|webview| unsafe {
+        super::macos::clear_webview_background(webview.inner());
+    });
+    true
+}
+
+#[cfg(target_os = "macos")]
+fn disable(window: &WebviewWindow) {
+    let _ = window_vibrancy::clear_vibrancy(window);
+}
+
+#[cfg(target_os = "windows")]
+fn enable(window: &WebviewWindow, _material: VibrancyMaterial, previous: PillAppearance) -> bool {
+    if previous.vibrancy {
+        return true;
+    }
+    window_vibrancy::apply_acrylic(window, Some(ACRYLIC_TINT)).is_ok()
+        || window_vibrancy::apply_mica(window, Some(true)).is_ok()
+}
+
+#[cfg(target_os = "windows")]
+fn disable(window: &WebviewWindow) {
+    let _ = window_vibrancy::clear_acrylic(window);
+    let _ = window_vibrancy::clear_mica(window);
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn enable(_window: &WebviewWindow, _material: VibrancyMaterial, _previous: PillAppearance) -> bool {
+    false
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn disable(_window: &WebviewWindow) {}
@@ -0,0 +1,215 @@
+//! Click-through for the transparent margins around the pill.
+//!
+//! The window is a rectangle, usually larger than the rounded pill drawn in
+//! it. The frontend reports where the pill actually is
+//! ([`set_pill_hit_region`](super::set_pill_hit_region)) and measures again
+//! whenever it resizes; clicks anywhere else in the window go to the app
+//! below. No region (the default) makes the whole window clickable.
+//!
+//! `debug_pill_hit_region` sends the active region to the frontend in
+//! `pill:hit-region-debug`, which outlines it, for troubleshooting.
+//!
+//! # Platform Support
+//!
+//! - macOS: windows can only ignore the mouse as a whole, so while the pill
+//!   is visible, mouse-moved monitors switch `setIgnoresMouseEvents:` on as
+//!   the mouse leaves the region and off as it enters.
+//! - Windows: the window is clipped to the region (`SetWindowRgn`), which
+//!   the system hit-tests itself.
+//! - Linux: not supported; the whole window stays clickable.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use super::{PillState, PillWindowState, MAIN_WINDOW};
+
+use crate::{AppHandle, WebviewWindow};
+
+/// A rounded rectangle of the pill, in logical pixels relative to the
+/// window's top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HitRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// Corner radius.
+    #[serde(default)]
+    pub radius: f64,
+}
+
+impl HitRect {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn contains(&self, x: f64, y: f64) -> bool {
+        if x < self.x || y < self.y || x >= self.x + self.width || y >= self.y + self.height {
+            return false;
+        }
+        // Outside the corner circles only in the corners themselves
+        let radius = self.radius.clamp(0.0, self.width.min(self.height) / 2.0);
+        let dx = (self.x + radius - x)
+            .max(x - (self.x + self.width - radius))
+            .max(0.0);
+        let dy = (self.y + radius - y)
+            .max(y - (self.y + self.height - radius))
+            .max(0.0);
+        dx * dx + dy * dy <= radius * radius
+    }
+}
+
+/// Payload of the `pill:hit-region-debug` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct HitRegionDebug {
+    /// Whether to outline the region.
+    pub visible: bool,
+    /// The active region; empty when the whole window is clickable.
+    pub rects: Vec<HitRect>,
+}
+
+#[derive(Default)]
+pub(super) struct HitRegion {
+    /// Where the pill is; empty for the whole window.
+    rects: Mutex<Vec<HitRect>>,
+    /// The mouse-moved monitors (macOS), as addresses.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    monitors: Mutex<Vec<usize>>,
+    /// Whether clicks currently pass through the window (macOS).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    passing: AtomicBool,
+    /// Whether the frontend outlines the region.
+    debug: AtomicBool,
+}
+
+impl HitRegion {
+    pub(super) fn rects(&self) -> Vec<HitRect> {
+        self.rects.lock().unwrap().clone()
+    }
+
+    /// Forgets the state of a destroyed window, which a new one doesn't
+    /// share.
+    pub(super) fn reset(&self) {
+        self.passing.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Replaces the region and applies it.
+pub(super) fn set(app: &AppHandle, rects: Vec<HitRect>) {
+    let pill = app.state::<PillWindowState>();
+    *pill.hit.rects.lock().unwrap() = rects;
+    let visible = *pill.state.lock().unwrap() != PillState::Hidden;
+    follow(app, visible);
+    if pill.hit.debug.load(Ordering::SeqCst) {
+        debug(app, true);
+    }
+}
+
+/// Outlines the region in the frontend, or stops.
+pub(super) fn debug(app: &AppHandle, visible: bool) -> Vec<HitRect> {
+    let pill = app.state::<PillWindowState>();
+    pill.hit.debug.store(visible, Ordering::SeqCst);
+    let rects = pill.hit.rects();
+    let _ = app.emit(
+        "pill:hit-region-debug",
+        HitRegionDebug {
+            visible,
+            rects: rects.clone(),
+        },
+    );
+    rects
+}
+
+/// Applies the region to the window, as it is shown or hidden.
+pub(super) fn follow(app: &AppHandle, visible: bool) {
+    let handle = app.clone();
+    let _ = app.run_on_main_thread(move || {
+        let Some(window) = handle.get_webview_window(MAIN_WINDOW) else {
+            return;
+        };
+        let rects = handle.state::<PillWindowState>().hit.rects();
+        apply(&handle, &window, &rects, visible);
+    });
+}
+
+#[cfg(target_os = "macos")]
+fn apply(app: &AppHandle, window: &WebviewWindow, rects: &[HitRect], visible: bool) {
+    let hit = &app.state::<PillWindowState>().hit;
+    let mut monitors = hit.monitors.lock().unwrap();
+    if visible && !rects.is_empty() {
+        if monitors.is_empty() {
+            let app = app.clone();
+            *monitors = unsafe { super::macos::add_mouse_moved_monitors(move || moved(&app)) }
+                .into_iter()
+                .map(|monitor| monitor.as_ptr() as usize)
+                .collect();
+            if monitors.is_empty() {
+                tracing::warn!("Failed to follow the mouse; the whole pill window stays clickable");
+            }
+        }
+        return;
+    }
+
+    for monitor in monitors.drain(..) {
+        if let Some(monitor) = std::ptr::NonNull::new(monitor as *mut objc2::runtime::AnyObject) {
+            unsafe { super::macos::remove_monitor(monitor) };
+        }
+    }
+    // Catch every click again, for the next time it shows
+    if hit.passing.swap(false, Ordering::SeqCst) {
+        if let Ok(ns_window) = window.ns_window() {
+            unsafe { super::macos::set_ignores_mouse_events(ns_window, false) };
+        }
+    }
+}
+
+/// Lets clicks through when the mouse is outside the region.
+#[cfg(target_os = "macos")]
+fn moved(app: &AppHandle) {
+    let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+        return;
+    };
+    let Ok(ns_window) = window.ns_window() else {
+        return;
+    };
+    let Some((x, y)) = (unsafe { super::macos::mouse_in_window(ns_window) }) else {
+        return;
+    };
+    let hit = &app.state::<PillWindowState>().hit;
+    let inside = hit
+        .rects
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|rect| rect.contains(x, y));
+    if hit.passing.swap(!inside, Ordering::SeqCst) != !inside {
+        unsafe { super::macos::set_ignores_mouse_events(ns_window, !inside) };
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn apply(_app: &AppHandle, window: &WebviewWindow, rects: &[HitRect], _visible: bool) {
+    let Ok(hwnd) = window.hwnd() else {
+        return;
+    };
+    let scale = window.scale_factor().unwrap_or(1.0);
+    let region: Vec<_> = rects
+        .iter()
+        .map(|rect| {
+            let px = |value: f64| (value * scale).round() as i32;
+            (
+                px(rect.x),
+                px(rect.y),
+                px(rect.x + rect.width),
+                px(rect.y + rect.height),
+                px(rect.radius),
+            )
+        })
+        .collect();
+    if !unsafe { super::win32::set_region(hwnd.0 as _, &region) } {
+        tracing::warn!("Failed to clip the pill window; the whole window stays clickable");
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn apply(_app: &AppHandle, _window: &WebviewWindow, _rects: &[HitRect], _visible: bool) {}
@@ -0,0 +1,352 @@
+//! Raw `NSWindow` access for the pill window and the recording indicator.
+//!
+//! Tauri has no API for panels, so the pill's window is switched to an
+//! `NSPanel` subclass registered at runtime, the same way native utilities
+//! built on other toolkits do it. Clicks outside the pill are watched with
+//! an `NSEvent` global monitor, app switches with `NSWorkspace`
+//! notifications. Click-through is `setIgnoresMouseEvents:`, toggled as
+//! the mouse moves in and out of the pill.
+
+use std::ffi::c_void;
+use std::ptr::NonNull;
+use std::sync::Once;
+
+use block2::RcBlock;
+use objc2::declare::ClassBuilder;
+use objc2::runtime::{AnyClass, AnyObject, Bool, Sel};
+use objc2::{msg_send, sel};
+use objc2_foundation::{NSPoint, NSRect, NSString};
+
+use super::WindowLevel;
+
+/// Name of the `NSPanel` subclass the pill window is switched to.
+const PANEL_CLASS: &str = "WisprPillPanel";
+
+/// `NSWindowStyleMaskNonactivatingPanel`.
+const NONACTIVATING_PANEL: usize = 1 << 7;
+/// `NSWindowCollectionBehaviorCanJoinAllSpaces`: shown on whichever Space
+/// is active instead of switching to the one the window is on.
+const CAN_JOIN_ALL_SPACES: usize = 1 << 0;
+/// `NSWindowCollectionBehaviorMoveToActiveSpace`, which excludes
+/// [`CAN_JOIN_ALL_SPACES`].
+const MOVE_TO_ACTIVE_SPACE: usize = 1 << 1;
+/// `NSWindowCollectionBehaviorIgnoresCycle`: kept out of Cmd+` cycling.
+const IGNORES_CYCLE: usize = 1 << 6;
+/// `NSWindowCollectionBehaviorFullScreenAuxiliary`: allowed on a fullscreen
+/// window's Space.
+const FULL_SCREEN_AUXILIARY: usize = 1 << 8;
+
+extern "C" fn no(_: &AnyObject, _: Sel) -> Bool {
+    Bool::NO
+}
+
+/// The panel class, registered on first use. Refusing to become the key or
+/// main window is what keeps keyboard focus in the target app.
+fn panel_class() -> Option<&'static AnyClass> {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let Some(superclass) = AnyClass::get("NSPanel") else {
+            return;
+        };
+        let Some(mut builder) = ClassBuilder::new(PANEL_CLASS, superclass) else {
+            return;
+        };
+        unsafe {
+            builder.add_method(
+                sel!(canBecomeKeyWindow),
+                no as extern "C" fn(&AnyObject, Sel) -> Bool,
+            );
+            builder.add_method(
+                sel!(canBecomeMainWindow),
+                no as extern "C" fn(&AnyObject, Sel) -> Bool,
+            );
+        }
+        builder.register();
+    });
+    AnyClass::get(PANEL_CLASS)
+}
+
+/// Turns `ns_window` into a non-activating floating panel, returning whether
+/// it worked.
+///
+/// # Safety
+///
+/// `ns_window` must be a live `NSWindow`, and this must run on the main
+/// thread.
+pub unsafe fn make_panel(ns_window: *mut c_void) -> bool {
+    let Some(class) = panel_class() else {
+        return false;
+    };
+    let window = ns_window.cast::<AnyObject>();
+    objc2::ffi::object_setClass(window.cast(), (class as *const AnyClass).cast());
+
+    let window = &*window;
+    let mask: usize = msg_send![window, styleMask];
+    let _: () = msg_send![window, setStyleMask: mask | NONACTIVATING_PANEL];
+    let _: () = msg_send![window, setFloatingPanel: true];
+    // Panels hide when their app deactivates by default, and ours is never
+    // active
+    let _: () = msg_send![window, setHidesOnDeactivate: false];
+    let behavior: usize = msg_send![window, collectionBehavior];
+    let _: () = msg_send![window, setCollectionBehavior: behavior | IGNORES_CYCLE];
+    true
+}
+
+/// Lets `ns_window` appear on every Space, including over fullscreen
+/// windows.
+///
+/// # Safety
+///
+/// `ns_window` must be a live `NSWindow`, and this must run on the main
+/// thread.
+pub unsafe fn float_over_fullscreen(ns_window: *mut c_void) {
+    let window = &*ns_window.cast::<AnyObject>();
+    let behavior: usize = msg_send![window, collectionBehavior];
+    let behavior = (behavior & !MOVE_TO_ACTIVE_SPACE) | CAN_JOIN_ALL_SPACES | FULL_SCREEN_AUXILIARY;
+    let _: () = msg_send![window, setCollectionBehavior: behavior];
+}
+
+/// Sets the window level of `ns_window`.
+///
+/// # Safety
+///
+/// `ns_window` must be a live `NSWindow`, and this must run on the main
+/// thread.
+pub unsafe fn set_level(ns_window: *mut c_void, level: WindowLevel) {
+    // NSNormalWindowLevel, NSFloatingWindowLevel, NSStatusWindowLevel and
+    // NSScreenSaverWindowLevel
+    let level: isize = match level {
+        WindowLevel::Normal => 0,
+        WindowLevel::Floating => 3,
+        WindowLevel::StatusBar => 25,
+        WindowLevel::ScreenSaver => 1000,
+    };
+    let window = &*ns_window.cast::<AnyObject>();
+    let _: () = msg_send![window, setLevel: level];
+}
+
+/// Brings `ns_window` to the front without making it key or activating the
+/// app.
+///
+/// # Safety
+///
+/// `ns_window` must be a live `NSWindow`, and this must run on the main
+/// thread.
+pub unsafe fn order_front(ns_window: *mut c_void) {
+    let window = &*ns_window.cast::<AnyObject>();
+    let _: () = msg_send![window, orderFrontRegardless];
+}
+
+/// Sets the opacity of `ns_window`, from 0 (invisible) to 1.
+///
+/// # Safety
+///
+/// `ns_window` must be a live `NSWindow`, and this must run on the main
+/// thread.
+pub unsafe fn set_alpha(ns_window: *mut c_void, alpha: f64) {
+    let window = &*ns_window.cast::<AnyObject>();
+    let _: () = msg_send![window, setAlphaValue: alpha];
+}
+
+/// `NSEventMaskLeftMouseDown`, `NSEventMaskRightMouseDown` and
+/// `NSEventMaskOtherMouseDown`.
+const MOUSE_DOWN_MASK: u64 = (1 << 1) | (1 << 3) | (1 << 25);
+
+/// Calls `handler` on every mouse-down in other apps, until the returned
+/// monitor is passed to [`remove_monitor`]. Clicks in our own windows are
+/// not reported. `None` if the monitor can't be installed.
+///
+/// # Safety
+///
+/// Must run on the main thread, where `handler` is called too.
+pub unsafe fn add_mouse_down_monitor(handler: impl Fn() + 'static) -> Option<NonNull<AnyObject>> {
+    let class = AnyClass::get("NSEvent")?;
+    let block = RcBlock::new(move |_event: *mut AnyObject| handler());
+    let monitor: *mut AnyObject = msg_send![
+        class,
+        addGlobalMonitorForEventsMatchingMask: MOUSE_DOWN_MASK,
+        handler: &*block
+    ];
+    let monitor = NonNull::new(monitor)?;
+    // Returned autoreleased; kept until it is removed
+    let _: *mut AnyObject = msg_send![monitor.as_ptr(), retain];
+    Some(monitor)
+}
+
+/// Removes a monitor added with [`add_mouse_down_monitor`].
+///
+/// # Safety
+///
+/// `monitor` must come from [`add_mouse_down_monitor`] and not have been
+/// removed yet, and this must run on the main thread.
+pub unsafe fn remove_monitor(monitor: NonNull<AnyObject>) {
+    if let Some(class) = AnyClass::get("NSEvent") {
+        let _: () = msg_send![class, removeMonitor: monitor.as_ptr()];
+    }
+    let _: () = msg_send![monitor.as_ptr(), release];
+}
+
+/// Calls `handler` with the bundle identifier of every app that becomes
+/// active (`None` for apps without one), for the life of the process.
+/// Returns whether the observer could be added.
+///
+/// # Safety
+///
+/// Must run on the main thread, where `handler` is called too.
+pub unsafe fn observe_app_activation(handler: impl Fn(Option<String>) + 'static) -> bool {
+    let Some(class) = AnyClass::get("NSWorkspace") else {
+        return false;
+    };
+    let workspace: *mut AnyObject = msg_send![class, sharedWorkspace];
+    let Some(workspace) = workspace.as_ref() else {
+        return false;
+    };
+    let center: *mut AnyObject = msg_send![workspace, notificationCenter];
+    let Some(center) = center.as_ref() else {
+        return false;
+    };
+
+    // The values of NSWorkspaceDidActivateApplicationNotification and
+    // NSWorkspaceApplicationKey
+    let name = NSString::from_str("NSWorkspaceDidActivateApplicationNotification");
+    let key = NSString::from_str("NSWorkspaceApplicationKey");
+    let block = RcBlock::new(move |notification: *mut AnyObject| {
+        handler(activated_bundle_id(notification, &key))
+    });
+    // No queue: the block runs on the posting thread, the main thread
+    let observer: *mut AnyObject = msg_send![
+        center,
+        addObserverForName: &*name,
+        object: std::ptr::null::<AnyObject>(),
+        queue: std::ptr::null::<AnyObject>(),
+        usingBlock: &*block
+    ];
+    if observer.is_null() {
+        return false;
+    }
+    // Never removed
+    let _: *mut AnyObject = msg_send![observer, retain];
+    true
+}
+
+/// Bundle identifier of the app an activation notification is about.
+unsafe fn activated_bundle_id(notification: *mut AnyObject, key: &NSString) -> Option<String> {
+    let notification = notification.as_ref()?;
+    let info: *mut AnyObject = msg_send![notification, userInfo];
+    let info = info.as_ref()?;
+    let app: *mut AnyObject = msg_send![info, objectForKey: key];
+    let app = app.as_ref()?;
+    let bundle_id: *mut NSString = msg_send![app, bundleIdentifier];
+    Some(bundle_id.as_ref()?.to_string())
+}
+
+/// Makes `ns_window` draw no background, so views behind its content (the
+/// blur) show through.
+///
+/// # Safety
+///
+/// `ns_window` must be a live `NSWindow`, and this must run on the main
+/// thread.
+pub unsafe fn clear_window_background(ns_window: *mut c_void) {
+    let window = &*ns_window.cast::<AnyObject>();
+    let _: () = msg_send![window, setOpaque: false];
+    if let Some(class) = AnyClass::get("NSColor") {
+        let clear: *mut AnyObject = msg_send![class, clearColor];
+        let _: () = msg_send![window, setBackgroundColor: clear];
+    }
+}
+
+/// Makes a `WKWebView` draw no background of its own. WebKit has no public
+/// API for it; `drawsBackground` is the key Tauri's transparency uses too.
+///
+/// # Safety
+///
+/// `webview` must be a live `WKWebView`, and this must run on the main
+/// thread.
+pub unsafe fn clear_webview_background(webview: *mut c_void) {
+    let Some(class) = AnyClass::get("NSNumber") else {
+        return;
+    };
+    let no: *mut AnyObject = msg_send![class, numberWithBool: false];
+    let key = NSString::from_str("drawsBackground");
+    let webview = &*webview.cast::<AnyObject>();
+    let _: () = msg_send![webview, setValue: no, forKey: &*key];
+}
+
+/// `NSEventMaskMouseMoved`, `NSEventMaskLeftMouseDragged` and
+/// `NSEventMaskMouseEntered`/`Exited`.
+const MOUSE_MOVED_MASK: u64 = (1 << 5) | (1 << 6) | (1 << 8) | (1 << 9);
+
+/// Calls `handler` whenever the mouse moves, over our windows or anyone
+/// else's, until the returned monitors are passed to [`remove_monitor`].
+/// Empty if they can't be installed.
+///
+/// A window that ignores mouse events gets none of its own, so the moves
+/// over it arrive through the global monitor, and the others through the
+/// local one.
+///
+/// # Safety
+///
+/// Must run on the main thread, where `handler` is called too.
+pub unsafe fn add_mouse_moved_monitors(
+    handler: impl Fn() + Clone + 'static,
+) -> Vec<NonNull<AnyObject>> {
+    let Some(class) = AnyClass::get("NSEvent") else {
+        return Vec::new();
+    };
+    let global = handler.clone();
+    let global = RcBlock::new(move |_event: *mut AnyObject| global());
+    let local = RcBlock::new(move |event: *mut AnyObject| -> *mut AnyObject {
+        handler();
+        // Passed on unchanged
+        event
+    });
+    let global: *mut AnyObject = msg_send![
+        class,
+        addGlobalMonitorForEventsMatchingMask: MOUSE_MOVED_MASK,
+        handler: &*global
+    ];
+    let local: *mut AnyObject = msg_send![
+        class,
+        addLocalMonitorForEventsMatchingMask: MOUSE_MOVED_MASK,
+        handler: &*local
+    ];
+    [global, local]
+        .into_iter()
+        .filter_map(NonNull::new)
+        .map(|monitor| {
+            let _: *mut AnyObject = msg_send![monitor.as_ptr(), retain];
+            monitor
+        })
+        .collect()
+}
+
+/// The mouse position relative to the top-left corner of `ns_window`, in
+/// points (logical pixels).
+///
+/// # Safety
+///
+/// `ns_window` must be a live `NSWindow`, and this must run on the main
+/// thread.
+pub unsafe fn mouse_in_window(ns_window: *mut c_void) -> Option<(f64, f64)> {
+    let class = AnyClass::get("NSEvent")?;
+    let mouse: NSPoint = msg_send![class, mouseLocation];
+    let window = &*ns_window.cast::<AnyObject>();
+    let frame: NSRect = msg_send![window, frame];
+    // Screen coordinates grow upwards from the bottom-left corner
+    Some((
+        mouse.x - frame.origin.x,
+        frame.origin.y + frame.size.height - mouse.y,
+    ))
+}
+
+/// Lets clicks on `ns_window` through to the windows below, or stops it.
+///
+/// # Safety
+///
+/// `ns_window` must be a live `NSWindow`, and this must run on the main
+/// thread.
+pub unsafe fn set_ignores_mouse_events(ns_window: *mut c_void, ignores: bool) {
+    let window = &*ns_window.cast::<AnyObject>();
+    let _: () = msg_send![window, setIgnoresMouseEvents: ignores];
+}
@@ -0,0 +1,1640 @@
+//! The recording pill window.
+//!
+//! The pill shows the recording state and live transcript. Normally it is a
+//! regular window that takes focus when shown, which is why pasting has to
+//! hide it and wait for focus to return to the target app first.
+//!
+//! # Architecture Decision
+//!
+//! The frontend only says which [`PillState`] the pill is in; this module
+//! owns the size, position and visibility for each state, so window
+//! behavior (placement, animation) lives in one place. Every change is
+//! announced as a `pill:state-changed` event carrying the new state.
+//!
+//! Callers never touch the window directly: [`set_state`] and friends queue
+//! the end state they want, and a single worker applies the changes in
+//! order (see `queue`), so rapid show/hide/resize requests can't interleave
+//! and the last one always wins.
+//!
+//! The pill appears at `pill.position` (top, bottom, center or custom
+//! percentages of the work area) on the monitor the user is looking at: the
+//! one with the mouse cursor, else the one with the focused window, else
+//! the primary one. `pill.monitor` pins it to one monitor by name instead (see
+//! `list_monitors`). [`get_monitors`] and [`get_cursor_position`] expose the
+//! same lookups to the frontend, for placing other things the same way.
+//! With `pill.near_caret` (and Accessibility access) the pill appears below
+//! the text caret instead, or above it near the bottom of the screen. The
+//! caret is looked up once, when the pill appears, so later state changes
+//! don't move it away from there.
+//!
+//! Between visible states the pill eases to its new size over 150ms
+//! (`pill.reduce_motion` snaps instead), from a timer thread that sets the
+//! frame about 60 times a second. A new state cancels the animation in
+//! flight and starts from wherever the window is. AppKit's animated
+//! `setFrame:display:animate:` is not used on macOS: it runs its own loop on
+//! the main thread and can't be retargeted halfway through.
+//!
+//! Showing and hiding fade the window's opacity over 120ms the same way
+//! (also off with `pill.reduce_motion`). Showing cancels a fade-out in
+//! progress and fades back in from where it was. Hiding before a paste
+//! that needs focus back skips the fade ([`hide_now`]).
+//!
+//! With `pill.vibrancy` the pill gets a native blurred background in
+//! `pill.material` (see `appearance`), set with `set_pill_appearance`.
+//!
+//! Clicks on the transparent margins around the pill go through to the app
+//! below, once the frontend reports where the pill is (see `hit`).
+//!
+//! Sizes are logical, and the pill is refitted when its scale factor
+//! changes (it moved to a monitor with different scaling), so it has the
+//! same size on a Retina display as on a 1x external monitor.
+//!
+//! Dragging the pill overrides `pill.position` on that monitor: where it was
+//! dropped is saved by monitor name (in the
+//! [`WindowStore`](crate::window_state::WindowStore)) and reused whenever
+//! it appears there, as long as the spot is still on screen.
+//! `reset_pill_position` forgets every saved spot.
+//!
+//! The pill is frameless, so the frontend starts a drag with
+//! `start_pill_drag` on mouse-down. The OS runs the drag itself and doesn't
+//! say when it ends, so a drag counts as finished once the window has
+//! stopped moving for a moment; then the spot is saved and `pill:moved`
+//! emitted. Until the pill places itself again, later moves (the user
+//! grabbing it again mid-drag) are saved the same way. Drags are refused
+//! while the pill is resizing or moving itself, and state changes during a
+//! drag resize the pill without moving it.
+//!
+//! # Platform Support
+//!
+//! - macOS: the pill joins every Space and may appear over fullscreen
+//!   windows, so the hotkey never switches to another Space to show it.
+//!   `pill.level` picks the window level: `normal`, `floating`,
+//!   `status_bar` (the default, above fullscreen apps and most floating
+//!   utilities such as Zoom's mini window or picture-in-picture video) or
+//!   `screen_saver`. Very high levels also cover system UI like the menu
+//!   bar, Dock and Notification Center.
+//! - macOS: with `pill.non_activating` the pill becomes a non-activating
+//!   panel. It floats above the target app without ever becoming the key
+//!   window, so the target keeps keyboard focus and the paste can be sent
+//!   while the pill is still visible. Mouse clicks (the cancel button, a
+//!   drag) still reach the webview, and dragging moves the panel without
+//!   activating the app. The window class can't be changed back, so the
+//!   setting takes effect the next time the app starts.
+//! - Windows and Linux: `pill.level` is `normal` or `floating` (topmost,
+//!   the default). On Windows the level is re-asserted every time the pill
+//!   is shown, so it goes over borderless fullscreen apps that made
+//!   themselves topmost since.
+//! - Windows and Linux: `pill.non_activating` has no effect; the pill is
+//!   always a regular window. On Linux it is sticky (on every workspace).
+//!
+//! # Submodules
+//!
+//! - `animation`: Easing for animated resizes and fades
+//! - `appearance`: The blurred, translucent background
+//! - `hit`: Click-through for the margins around the pill
+//! - `macos`: Raw `NSWindow` access (macOS only), also used by
+//!   [`crate::indicator`]
+//! - `outside`: Hiding the pill on clicks outside it
+//! - [`placement`]: Screen geometry for positioning the pill
+//! - `queue`: The ordered queue of window changes
+//! - `switch`: Hiding the pill when another app becomes active
+//! - `timeout`: Hiding the transcript and error pills after a while
+//! - `win32`: Raw window opacity (Windows only)
+
+mod animation;
+mod appearance;
+mod hit;
+#[cfg(target_os = "macos")]
+pub(crate) mod macos;
+mod outside;
+pub mod placement;
+mod queue;
+mod switch;
+mod timeout;
+#[cfg(target_os = "windows")]
+mod win32;
+
+pub use appearance::{PillAppearance, VibrancyMaterial};
+pub use hit::HitRect;
+use queue::{WindowOp, WindowQueue};
+pub use timeout::PillInteraction;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{
+    Emitter, LogicalSize, Manager, Monitor, PhysicalPosition, PhysicalSize, WebviewUrl,
+    WebviewWindowBuilder, WindowEvent,
+};
+
+use crate::accessibility;
+use crate::settings::{self, SettingsState};
+use crate::window_state::WindowStore;
+use crate::{AppHandle, WebviewWindow};
+use placement::{PillPosition, Rect};
+
+/// How high the pill floats above other windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowLevel {
+    /// Like any other window; may end up behind the target app.
+    Normal,
+    /// Above regular windows (always on top).
+    Floating,
+    /// Above floating utility windows and fullscreen apps (macOS).
+    StatusBar,
+    /// Above nearly everything, including the menu bar and Dock (macOS).
+    ScreenSaver,
+}
+
+impl Default for WindowLevel {
+    fn default() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::StatusBar
+        } else {
+            Self::Floating
+        }
+    }
+}
+
+impl WindowLevel {
+    /// Whether this platform has the level.
+    fn is_supported(self) -> bool {
+        cfg!(target_os = "macos") || matches!(self, Self::Normal | Self::Floating)
+    }
+}
+
+/// Pill window settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PillSettings {
+    /// Make the pill a non-activating panel that never takes focus (macOS,
+    /// applied at startup).
+    pub non_activating: bool,
+    pub level: WindowLevel,
+    /// Always show the pill on the monitor with this name, when connected,
+    /// instead of the one the user is looking at.
+    pub monitor: Option<String>,
+    /// Show the pill next to the text caret when its position is known.
+    pub near_caret: bool,
+    /// Where the pill appears otherwise.
+    pub position: PillPosition,
+    /// Snap between sizes instead of animating.
+    pub reduce_motion: bool,
+    /// Hide the pill (cancelling the recording) on a click outside it.
+    pub hide_on_click_outside: bool,
+    /// Also hide the recording pill when another app becomes active, not
+    /// just the transcript pill. The recording goes on.
+    pub auto_hide_while_recording: bool,
+    /// Blur what is behind the pill (macOS and Windows).
+    pub vibrancy: bool,
+    pub material: VibrancyMaterial,
+    /// Hide the transcript pill this long after it appeared. `0` never
+    /// does.
+    pub transcript_timeout_ms: u64,
+    /// Hide the error pill this long after it appeared, dismissing the
+    /// failed dictation. `0` never does.
+    pub error_timeout_ms: u64,
+}
+
+impl Default for PillSettings {
+    fn default() -> Self {
+        Self {
+            non_activating: false,
+            level: WindowLevel::default(),
+            monitor: None,
+            near_caret: true,
+            position: PillPosition::default(),
+            reduce_motion: false,
+            hide_on_click_outside: true,
+            auto_hide_while_recording: false,
+            vibrancy: false,
+            material: VibrancyMaterial::default(),
+            transcript_timeout_ms: 2_500,
+            error_timeout_ms: 6_000,
+        }
+    }
+}
+
+impl PillSettings {
+    /// Checks that the window level exists on this platform.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.level.is_supported() {
+            Ok(())
+        } else {
+            Err("only \"normal\" and \"floating\" are supported on this platform".to_string())
+        }
+    }
+}
+
+/// Label of the pill window.
+pub const MAIN_WINDOW: &str = "main";
+
+// Pill sizes are in logical pixels, so the pill looks the same on every
+// monitor whatever its scale factor.
+
+/// Size of the pill while recording, transcribing or showing an error.
+const COMPACT_SIZE: (f64, f64) = (400.0, 100.0);
+/// Width of the transcript pill, and the height of its chrome plus each
+/// line of text.
+const TRANSCRIPT_WIDTH: f64 = 600.0;
+const TRANSCRIPT_BASE_HEIGHT: f64 = 102.0;
+const TRANSCRIPT_LINE_HEIGHT: f64 = 24.0;
+/// Lines beyond this scroll inside the pill.
+const MAX_TRANSCRIPT_LINES: u32 = 6;
+/// Tallest the pill grows to fit its content before it scrolls.
+const MAX_CONTENT_HEIGHT: f64 =
+    TRANSCRIPT_BASE_HEIGHT + (MAX_TRANSCRIPT_LINES - 1) as f64 * TRANSCRIPT_LINE_HEIGHT;
+
+/// How long content heights must stop changing before the pill resizes to
+/// the last one, so streaming partial transcripts don't resize every word.
+const CONTENT_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// What the pill is showing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PillState {
+    #[default]
+    Hidden,
+    /// The mic is open.
+    Recording,
+    /// Speech ended; the transcript is being processed and pasted.
+    Transcribing,
+    /// Showing a transcript about `estimated_lines` lines long.
+    Transcript { estimated_lines: u32 },
+    /// A recording or paste failed.
+    Error,
+}
+
+impl PillState {
+    /// Logical window size for this state, or `None` when hidden.
+    fn size(self) -> Option<(f64, f64)> {
+        match self {
+            Self::Hidden => None,
+            Self::Recording | Self::Transcribing | Self::Error => Some(COMPACT_SIZE),
+            Self::Transcript { estimated_lines } => {
+                let lines = estimated_lines.clamp(1, MAX_TRANSCRIPT_LINES);
+                Some((
+                    TRANSCRIPT_WIDTH,
+                    TRANSCRIPT_BASE_HEIGHT + f64::from(lines - 1) * TRANSCRIPT_LINE_HEIGHT,
+                ))
+            }
+        }
+    }
+}
+
+/// Managed state for the pill window.
+#[derive(Default)]
+pub struct PillWindowState {
+    /// Whether the pill window was turned into a non-activating panel.
+    panel: AtomicBool,
+    state: Mutex<PillState>,
+    /// Caret bounds captured when the pill last appeared.
+    caret: Mutex<Option<Rect>>,
+    /// Set while the pill resizes and moves itself.
+    transitioning: AtomicBool,
+    drag: Mutex<Drag>,
+    /// Generation of the current resize animation; bumped to cancel it.
+    /// Held while a frame is applied, so a cancelled animation never
+    /// applies another one.
+    animation: Mutex<u64>,
+    /// Height the frontend measured for the current state's content.
+    content_height: Mutex<Option<f64>>,
+    /// Bumped on every `resize_pill_to_content` call, to debounce them.
+    content_generation: AtomicU64,
+    fade: Mutex<Fade>,
+    appearance: appearance::AppliedAppearance,
+    queue: WindowQueue,
+    outside: outside::OutsideClicks,
+    hit: hit::HitRegion,
+    switches: switch::AppSwitches,
+    auto_hide: timeout::AutoHide,
+    /// Held while the window is being created.
+    creating: Mutex<()>,
+    /// Whether the window was ever created, to tell recreations apart.
+    created: AtomicBool,
+    /// Bumped on every start or move of a drag.
+    drag_generation: AtomicU64,
+}
+
+impl PillWindowState {
+    /// Window size for `state`, with the measured content height when the
+    /// pill is already in that state.
+    fn size_for(&self, state: PillState) -> Option<(f64, f64)> {
+        let (width, height) = state.size()?;
+        let content = (*self.state.lock().unwrap() == state)
+            .then(|| *self.content_height.lock().unwrap())
+            .flatten();
+        Some((width, content.unwrap_or(height)))
+    }
+
+    /// Window size for the current state.
+    fn size(&self) -> Option<(f64, f64)> {
+        let state = *self.state.lock().unwrap();
+        self.size_for(state)
+    }
+}
+
+/// The pill window's opacity and the fade changing it.
+#[derive(Debug, Default)]
+struct Fade {
+    /// Bumped to cancel the fade in flight. Held while a step is applied,
+    /// so a cancelled fade never applies another one (or hides the pill).
+    generation: u64,
+    /// Kept at 0 while hidden, so showing never flashes the old opacity.
+    alpha: f64,
+}
+
+/// Whether the user is moving the pill.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Drag {
+    /// Moves are the pill's own.
+    #[default]
+    Idle,
+    /// The window moved (or the drag started) at this generation.
+    Moving(u64),
+    /// A drag finished; further moves are still the user's.
+    Settled,
+}
+
+/// Payload of the `pill:moved` event, in logical pixels.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PillMoved {
+    pub x: f64,
+    pub y: f64,
+    /// Name of the monitor the pill was dropped on.
+    pub monitor: Option<String>,
+}
+
+/// Returns the pill window, creating it (hidden) if it doesn't exist yet.
+///
+/// The window is created from code rather than `tauri.conf.json` so that
+/// startup decides when, if ever, it becomes visible. Its webview registers
+/// the global hotkeys and records audio, so it is created at startup even
+/// when nothing is shown.
+///
+/// When the window existed before and was destroyed since (closed from the
+/// Dock menu, or its webview crashed), it is rebuilt the same way with the
+/// panel, level and Space flags applied again, and `window:recreated` is
+/// emitted. Concurrent callers wait for one creation instead of racing.
+///
+/// # Errors
+///
+/// Returns an error if the window cannot be created.
+pub fn ensure_main_window(app: &AppHandle) -> Result<WebviewWindow, String> {
+    let pill = app.state::<PillWindowState>();
+    let _creating = pill.creating.lock().unwrap();
+    if let Some(window) = app.get_webview_window(MAIN_WINDOW) {
+        return Ok(window);
+    }
+    let recreated = pill.created.load(Ordering::SeqCst);
+    if recreated {
+        tracing::warn!("Pill window was destroyed; recreating it");
+        pill.panel.store(false, Ordering::SeqCst);
+        *pill.drag.lock().unwrap() = Drag::Idle;
+        pill.fade.lock().unwrap().alpha = 0.0;
+        pill.appearance.reset();
+        pill.hit.reset();
+    }
+
+    let builder = WebviewWindowBuilder::new(app, MAIN_WINDOW, WebviewUrl::default())
+        .title("wispr-clone")
+        .inner_size(200.0, 80.0)
+        .always_on_top(true)
+        .center()
+        .visible(false)
+        .decorations(false)
+        .resizable(false)
+        .skip_taskbar(true)
+        .visible_on_all_workspaces(true)
+        // A click on the inactive pill (the cancel button) must not be
+        // swallowed by window activation
+        .accept_first_mouse(true);
+    // Transparency needs the private API on macOS
+    #[cfg(not(target_os = "macos"))]
+    let builder = builder.transparent(true);
+
+    let window = builder.build().map_err(|e| e.to_string())?;
+    configure(app, &window);
+    let settings = app.state::<SettingsState>().get().pill;
+    appearance::apply(app, &window, settings.vibrancy, settings.material);
+    if fades(app) {
+        set_alpha(app, &window, 0.0);
+    }
+    let handle = app.clone();
+    window.on_window_event(move |event| match event {
+        WindowEvent::Moved(_) => dragged(&handle, true),
+        WindowEvent::ScaleFactorChanged { .. } => handle
+            .state::<PillWindowState>()
+            .queue
+            .push(&handle, WindowOp::Refit),
+        _ => {}
+    });
+
+    switch::observe(app);
+    pill.created.store(true, Ordering::SeqCst);
+    if recreated {
+        let _ = app.emit("window:recreated", MAIN_WINDOW);
+    }
+    Ok(window)
+}
+
+/// Applies the platform-specific window setup to a newly created pill
+/// window.
+fn configure(app: &AppHandle, window: &WebviewWindow) {
+    #[cfg(target_os = "macos")]
+    {
+        let settings = app.state::<SettingsState>().get().pill;
+        // NSWindow may only be changed on the main thread
+        let handle = app.clone();
+        let window = window.clone();
+        let _ = app.run_on_main_thread(move || {
+            let Ok(ns_window) = window.ns_window() else {
+                tracing::warn!("Pill window has no NSWindow; using defaults");
+                return;
+            };
+            if settings.non_activating {
+                if unsafe { macos::make_panel(ns_window) } {
+                    handle
+                        .state::<PillWindowState>()
+                        .panel
+                        .store(true, Ordering::SeqCst);
+                    tracing::info!("Pill window is a non-activating panel");
+                } else {
+                    tracing::warn!(
+                        "Failed to make the pill a panel; it will take focus when shown"
+                    );
+                }
+            }
+            unsafe { macos::float_over_fullscreen(ns_window) };
+            // Last, since becoming a floating panel resets the level
+            unsafe { macos::set_level(ns_window, settings.level) };
+        });
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let level = app.state::<SettingsState>().get().pill.level;
+        if let Err(e) = set_level(app, window, level) {
+            tracing::warn!("Failed to set the pill window level: {}", e);
+        }
+    }
+}
+
+/// A connected monitor, as listed by [`list_monitors`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MonitorInfo {
+    /// Name to use for `pill.monitor`.
+    pub name: Option<String>,
+    pub primary: bool,
+    /// Position and size in logical pixels.
+    pub bounds: Rect,
+    pub scale_factor: f64,
+}
+
+/// A connected monitor, as returned by [`get_monitors`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MonitorDetails {
+    /// Stable id: the monitor's name, which `pill.monitor` and the pill
+    /// positions saved per monitor also go by. `None` if the system doesn't
+    /// name it, in which case nothing can be saved for it.
+    pub id: Option<String>,
+    /// Name to show: the id, or "Display N" in the system's order.
+    pub name: String,
+    pub primary: bool,
+    /// Position and size in logical pixels, and the same without the menu
+    /// bar, Dock or taskbar.
+    pub bounds: Rect,
+    pub work_area: Rect,
+    pub scale_factor: f64,
+    /// Whether the mouse cursor, or the center of the focused window, is on
+    /// this monitor. The pill appears on the first of these.
+    pub has_cursor: bool,
+    pub has_focused_window: bool,
+}
+
+/// A point on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Where the mouse cursor is, as returned by [`get_cursor_position`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CursorPosition {
+    pub physical: Point,
+    /// Converted with the scale factor of the monitor under the cursor, like
+    /// monitor bounds; `None` if it is off every monitor.
+    pub logical: Option<Point>,
+    /// Id of the monitor under the cursor.
+    pub monitor: Option<String>,
+}
+
+/// Time budget for finding the focused window when the cursor position is
+/// unknown.
+const FOCUSED_WINDOW_BUDGET: Duration = Duration::from_millis(30);
+
+/// Time budget for finding the caret when the pill appears.
+const CARET_BUDGET: Duration = Duration::from_millis(30);
+
+/// Distance between the caret and the pill, in logical pixels.
+const CARET_GAP: f64 = 8.0;
+
+fn physical_rect(monitor: &Monitor) -> Rect {
+    let position = monitor.position();
+    let size = monitor.size();
+    Rect {
+        x: position.x as f64,
+        y: position.y as f64,
+        width: size.width as f64,
+        height: size.height as f64,
+    }
+}
+
+/// Bounds of `monitor` in logical pixels. Each monitor is converted with
+/// its own scale factor, which on macOS yields global screen points.
+fn logical_rect(monitor: &Monitor) -> Rect {
+    to_logical(physical_rect(monitor), monitor.scale_factor())
+}
+
+/// The part of `monitor` not covered by the menu bar, Dock or taskbar, in
+/// logical pixels.
+fn logical_work_area(monitor: &Monitor) -> Rect {
+    let area = monitor.work_area();
+    let physical = Rect {
+        x: area.position.x as f64,
+        y: area.position.y as f64,
+        width: area.size.width as f64,
+        height: area.size.height as f64,
+    };
+    to_logical(physical, monitor.scale_factor())
+}
+
+fn to_logical(physical: Rect, scale: f64) -> Rect {
+    Rect {
+        x: physical.x / scale,
+        y: physical.y / scale,
+        width: physical.width / scale,
+        height: physical.height / scale,
+    }
+}
+
+/// The physical position of the logical `point` on a monitor with logical
+/// `bounds`, whose top-left corner is at physical `origin`.
+fn to_physical(
+    point: (f64, f64),
+    bounds: Rect,
+    origin: PhysicalPosition<i32>,
+    scale: f64,
+) -> PhysicalPosition<i32> {
+    PhysicalPosition::new(
+        origin.x + ((point.0 - bounds.x) * scale).round() as i32,
+        origin.y + ((point.1 - bounds.y) * scale).round() as i32,
+    )
+}
+
+/// Logical offset of the physical `position` from the top-left corner of a
+/// monitor at physical `origin`.
+fn to_offset(
+    position: PhysicalPosition<i32>,
+    origin: PhysicalPosition<i32>,
+    scale: f64,
+) -> (f64, f64) {
+    (
+        f64::from(position.x - origin.x) / scale,
+        f64::from(position.y - origin.y) / scale,
+    )
+}
+
+/// Physical size of the logical `size`, in whole pixels.
+fn physical_size(size: (f64, f64), scale: f64) -> (f64, f64) {
+    ((size.0 * scale).round(), (size.1 * scale).round())
+}
+
+/// Picks the monitor to show the pill on: the pinned one, the one with the
+/// cursor, the one with the focused window, then the primary one.
+fn target_monitor(app: &AppHandle, pinned: Option<&str>) -> Option<Monitor> {
+    let monitors = app.available_monitors().ok()?;
+
+    if let Some(name) = pinned {
+        let pinned = monitors
+            .iter()
+            .find(|monitor| monitor.name().is_some_and(|n| n == name));
+        if let Some(monitor) = pinned {
+            return Some(monitor.clone());
+        }
+    }
+
+    let cursor = app.cursor_position().ok();
+    let index = cursor
+        .and_then(|cursor| monitor_with_cursor(&monitors, cursor))
+        .or_else(|| monitor_with_focused_window(&monitors));
+    if let Some(index) = index {
+        return Some(monitors[index].clone());
+    }
+
+    app.primary_monitor().ok().flatten()
+}
+
+/// Index of the monitor under `cursor`, in physical pixels.
+fn monitor_with_cursor(monitors: &[Monitor], cursor: PhysicalPosition<f64>) -> Option<usize> {
+    let bounds: Vec<Rect> = monitors.iter().map(physical_rect).collect();
+    placement::monitor_at(&bounds, cursor.x, cursor.y)
+}
+
+/// Index of the monitor with the center of the focused window, if
+/// Accessibility can tell where that is.
+fn monitor_with_focused_window(monitors: &[Monitor]) -> Option<usize> {
+    let frame = accessibility::focused_window_frame(FOCUSED_WINDOW_BUDGET)?;
+    let bounds: Vec<Rect> = monitors.iter().map(logical_rect).collect();
+    let (x, y) = frame.center();
+    placement::monitor_at(&bounds, x, y)
+}
+
+/// How long the window must stay put for a drag to count as finished.
+const DRAG_SETTLE: Duration = Duration::from_millis(400);
+
+/// Notes that a drag started, or that the window `moved` during one, and
+/// finishes the drag once it settles. Moves outside of a drag are the
+/// pill's own and ignored.
+fn dragged(app: &AppHandle, moved: bool) {
+    let pill = app.state::<PillWindowState>();
+    let generation = {
+        let mut drag = pill.drag.lock().unwrap();
+        if moved && *drag == Drag::Idle {
+            return;
+        }
+        let generation = pill.drag_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        *drag = Drag::Moving(generation);
+        generation
+    };
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(DRAG_SETTLE);
+        {
+            let mut drag = app.state::<PillWindowState>().drag.lock().unwrap();
+            if *drag != Drag::Moving(generation) {
+                return;
+            }
+            *drag = Drag::Settled;
+        }
+        // A click without moving isn't a new position
+        if moved {
+            finish_drag(&app);
+        }
+    });
+}
+
+/// Saves where the pill was dropped, as the spot for its monitor, and
+/// announces it as `pill:moved`.
+fn finish_drag(app: &AppHandle) {
+    let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+        return;
+    };
+    let Ok(position) = window.outer_position() else {
+        return;
+    };
+    let Ok(monitors) = app.available_monitors() else {
+        return;
+    };
+    let bounds: Vec<Rect> = monitors.iter().map(physical_rect).collect();
+    let Some(index) = placement::monitor_at(&bounds, position.x as f64, position.y as f64) else {
+        return;
+    };
+    let monitor = &monitors[index];
+    let offset = to_offset(position, monitor.position(), monitor.scale_factor());
+    // Unnamed monitors can't be recognized next time
+    if let Some(name) = monitor.name() {
+        app.state::<WindowStore>().set_pill_position(name, offset);
+    }
+
+    let bounds = logical_rect(monitor);
+    let _ = app.emit(
+        "pill:moved",
+        PillMoved {
+            x: bounds.x + offset.0,
+            y: bounds.y + offset.1,
+            monitor: monitor.name().cloned(),
+        },
+    );
+}
+
+/// Monitor whose logical bounds contain the center of `rect`.
+fn monitor_around(app: &AppHandle, rect: Rect) -> Option<Monitor> {
+    let monitors = app.available_monitors().ok()?;
+    let bounds: Vec<Rect> = monitors.iter().map(logical_rect).collect();
+    let (x, y) = rect.center();
+    placement::monitor_at(&bounds, x, y).map(|index| monitors[index].clone())
+}
+
+/// Where a pill window of logical `size` goes: next to `caret` when given,
+/// otherwise where it was dragged to on the target monitor, or else at the
+/// configured position. Returns the physical top-left corner, the
+/// monitor's scale factor and the size shrunk to fit the monitor's work
+/// area, or `None` when no monitor is known.
+///
+/// The math happens in the monitor's logical space and is converted back
+/// with its scale factor, so monitors with different scale factors each get
+/// a correctly placed pill.
+fn target_position(
+    app: &AppHandle,
+    size: (f64, f64),
+    caret: Option<Rect>,
+) -> Option<(PhysicalPosition<i32>, f64, (f64, f64))> {
+    let settings = app.state::<SettingsState>().get().pill;
+    let pinned = settings.monitor;
+    // A pinned monitor wins over the caret
+    let caret = caret.filter(|_| pinned.is_none());
+    let monitor = caret
+        .and_then(|caret| monitor_around(app, caret))
+        .or_else(|| target_monitor(app, pinned.as_deref()))?;
+
+    let scale = monitor.scale_factor();
+    let bounds = logical_rect(&monitor);
+    let work_area = logical_work_area(&monitor);
+    let size = placement::fit(size, work_area);
+    let (x, y) = match caret {
+        Some(caret) => placement::near_caret(caret, work_area, size, CARET_GAP),
+        None => monitor
+            .name()
+            .and_then(|name| app.state::<WindowStore>().pill_position(name))
+            .and_then(|offset| placement::restore(offset, bounds, work_area, size))
+            .unwrap_or_else(|| placement::anchored(work_area, size, settings.position)),
+    };
+
+    let position = to_physical((x, y), bounds, monitor.position(), scale);
+    Some((position, scale, size))
+}
+
+/// Whether the pill is a non-activating panel, i.e. the target app keeps
+/// focus while it is shown.
+pub fn is_panel(app: &AppHandle) -> bool {
+    app.state::<PillWindowState>().panel.load(Ordering::SeqCst)
+}
+
+/// Applies the configured window level, appearance and click-outside
+/// behavior to an existing pill window, and moves a visible pill to the
+/// configured position right away.
+pub fn apply(app: &AppHandle, settings: &PillSettings) {
+    let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+        return;
+    };
+    if let Err(e) = set_level(app, &window, settings.level) {
+        tracing::warn!("Failed to set the pill window level: {}", e);
+    }
+    appearance::apply(app, &window, settings.vibrancy, settings.material);
+
+    let pill = app.state::<PillWindowState>();
+    let visible = *pill.state.lock().unwrap() != PillState::Hidden;
+    if visible && settings.hide_on_click_outside {
+        outside::watch(app);
+    } else {
+        outside::unwatch(app);
+    }
+    pill.queue.push(app, WindowOp::Reposition);
+}
+
+/// Places a visible pill again, away from the caret, to preview a new
+/// position.
+fn reposition(app: &AppHandle) -> Result<(), String> {
+    let pill = app.state::<PillWindowState>();
+    let Some(size) = pill.size() else {
+        return Ok(());
+    };
+    let window = ensure_main_window(app)?;
+    *pill.caret.lock().unwrap() = None;
+    let reduce_motion = app.state::<SettingsState>().get().pill.reduce_motion;
+    transition(app, &window, size, None, !reduce_motion)
+}
+
+fn set_level(app: &AppHandle, window: &WebviewWindow, level: WindowLevel) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    {
+        let window = window.clone();
+        app.run_on_main_thread(move || {
+            if let Ok(ns_window) = window.ns_window() {
+                unsafe { macos::set_level(ns_window, level) };
+            }
+        })
+        .map_err(|e| e.to_string())
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = app;
+        window
+            .set_always_on_top(level != WindowLevel::Normal)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Shows the pill window, fading it in when it wasn't visible (or was
+/// fading out). A panel is ordered to the front without being made key, so
+/// it doesn't take focus.
+///
+/// The window level is set again first: macOS resets it on some
+/// operations, and on Windows fullscreen games and video players make
+/// themselves topmost too.
+fn show(app: &AppHandle, window: &WebviewWindow) -> Result<(), String> {
+    let level = app.state::<SettingsState>().get().pill.level;
+    set_level(app, window, level)?;
+
+    let visible = window.is_visible().unwrap_or(false);
+    let fade = fades(app);
+    {
+        let mut state = app.state::<PillWindowState>().fade.lock().unwrap();
+        // Cancels a fade-out, so it never hides the pill shown here
+        state.generation += 1;
+        let from = if visible { state.alpha } else { 0.0 };
+        if !fade || from >= 1.0 {
+            state.alpha = 1.0;
+            set_alpha(app, window, 1.0);
+        } else {
+            state.alpha = from;
+            set_alpha(app, window, from);
+            fade_to(app, state.generation, from, 1.0);
+        }
+    }
+    order_front(app, window)
+}
+
+fn order_front(app: &AppHandle, window: &WebviewWindow) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    if is_panel(app) {
+        let window = window.clone();
+        return app
+            .run_on_main_thread(move || {
+                if let Ok(ns_window) = window.ns_window() {
+                    unsafe { macos::order_front(ns_window) };
+                }
+            })
+            .map_err(|e| e.to_string());
+    }
+    #[cfg(not(target_os = "macos"))]
+    let _ = app;
+
+    window.show().map_err(|e| e.to_string())
+}
+
+/// Hides the pill window, after fading it out when `fade` allows.
+fn hide(app: &AppHandle, window: &WebviewWindow, fade: bool) -> Result<(), String> {
+    let visible = window.is_visible().unwrap_or(false);
+    let mut state = app.state::<PillWindowState>().fade.lock().unwrap();
+    state.generation += 1;
+    if fade && visible && fades(app) {
+        fade_to(app, state.generation, state.alpha, 0.0);
+        return Ok(());
+    }
+    window.hide().map_err(|e| e.to_string())?;
+    state.alpha = 0.0;
+    set_alpha(app, window, 0.0);
+    Ok(())
+}
+
+/// Whether show and hide fade: not with `pill.reduce_motion`, nor on
+/// platforms without window opacity.
+fn fades(app: &AppHandle) -> bool {
+    cfg!(any(target_os = "macos", target_os = "windows"))
+        && !app.state::<SettingsState>().get().pill.reduce_motion
+}
+
+/// Sets the pill window's opacity, on the main thread (which owns the
+/// window on every platform). Does nothing where opacity isn't supported.
+fn set_alpha(app: &AppHandle, window: &WebviewWindow, alpha: f64) {
+    #[cfg(target_os = "macos")]
+    {
+        let window = window.clone();
+        let _ = app.run_on_main_thread(move || {
+            if let Ok(ns_window) = window.ns_window() {
+                unsafe { macos::set_alpha(ns_window, alpha) };
+            }
+        });
+    }
+    #[cfg(target_os = "windows")]
+    {
+        let window = window.clone();
+        let _ = app.run_on_main_thread(move || {
+            if let Ok(hwnd) = window.hwnd() {
+                unsafe { win32::set_alpha(hwnd.0 as _, alpha) };
+            }
+        });
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let _ = (app, window, alpha);
+}
+
+/// Ramps the pill's opacity from `from` to `to` on a timer thread while
+/// `generation` is the current fade, hiding the window at the end of a
+/// fade-out.
+fn fade_to(app: &AppHandle, generation: u64, from: f64, to: f64) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let started = Instant::now();
+        loop {
+            let progress = started.elapsed().as_secs_f64() / animation::FADE_DURATION.as_secs_f64();
+            let pill = app.state::<PillWindowState>();
+            let mut state = pill.fade.lock().unwrap();
+            if state.generation != generation {
+                return;
+            }
+            let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+                return;
+            };
+            let progress = progress.min(1.0);
+            state.alpha = from + (to - from) * progress;
+            set_alpha(&app, &window, state.alpha);
+            if progress >= 1.0 {
+                if to == 0.0 {
+                    let _ = window.hide();
+                }
+                return;
+            }
+            drop(state);
+            std::thread::sleep(animation::FRAME_INTERVAL);
+        }
+    });
+}
+
+/// Puts the pill into `state`: sizes, places and shows the window for it,
+/// or hides it. Only queues the change; failures are logged by the queue.
+pub fn set_state(app: &AppHandle, state: PillState) {
+    let op = WindowOp::State { state, fade: true };
+    app.state::<PillWindowState>().queue.push(app, op);
+}
+
+/// Hides the pill at once, without fading out, and waits until it is
+/// hidden: for when hiding it is what hands keyboard focus back to the
+/// target app, which must not wait. Must not be called on the main thread.
+///
+/// # Errors
+///
+/// Returns an error if the window cannot be hidden.
+pub fn hide_now(app: &AppHandle) -> Result<(), String> {
+    let op = WindowOp::State {
+        state: PillState::Hidden,
+        fade: false,
+    };
+    app.state::<PillWindowState>().queue.push_and_wait(app, op)
+}
+
+/// Cancels the timer that hides the transcript or error pill (see
+/// `timeout`), for a dictation starting: the pill it shows must not be
+/// hidden under it.
+pub fn cancel_auto_hide(app: &AppHandle) {
+    timeout::cancel(app);
+}
+
+/// Applies a queued window operation; only ever called by the queue's
+/// worker.
+fn apply_op(app: &AppHandle, op: WindowOp) -> Result<(), String> {
+    match op {
+        WindowOp::State { state, fade } => change_state(app, state, fade),
+        WindowOp::Content { height } => fit_content(app, height),
+        WindowOp::Refit => refit(app),
+        WindowOp::Reposition => reposition(app),
+        WindowOp::Expire { generation } => timeout::expire(app, generation),
+        WindowOp::Rearm => timeout::rearm(app),
+    }
+}
+
+fn change_state(app: &AppHandle, state: PillState, fade: bool) -> Result<(), String> {
+    let pill = app.state::<PillWindowState>();
+    let size = pill.size_for(state);
+    if *pill.state.lock().unwrap() != state {
+        *pill.content_height.lock().unwrap() = None;
+    }
+    match size {
+        Some(size) => {
+            // Only while the target app still has focus is its caret ours
+            // to find
+            let appearing = *pill.state.lock().unwrap() == PillState::Hidden;
+            if appearing {
+                switch::capture_target(app);
+                let near_caret = app.state::<SettingsState>().get().pill.near_caret;
+                *pill.caret.lock().unwrap() = near_caret
+                    .then(|| accessibility::focused_caret_bounds(CARET_BUDGET))
+                    .flatten();
+            }
+            let caret = *pill.caret.lock().unwrap();
+
+            let window = ensure_main_window(app)?;
+            let reduce_motion = app.state::<SettingsState>().get().pill.reduce_motion;
+            transition(app, &window, size, caret, !appearing && !reduce_motion)?;
+        }
+        // Never created means never shown
+        None => {
+            if let Some(window) = app.get_webview_window(MAIN_WINDOW) {
+                finish_animation(app);
+                hide(app, &window, fade)?;
+            }
+        }
+    }
+
+    let hide_on_click_outside = app
+        .state::<SettingsState>()
+        .get()
+        .pill
+        .hide_on_click_outside;
+    if size.is_some() && hide_on_click_outside {
+        outside::watch(app);
+    } else {
+        outside::unwatch(app);
+    }
+
+    hit::follow(app, size.is_some());
+    timeout::arm(app, state);
+
+    let previous = std::mem::replace(&mut *pill.state.lock().unwrap(), state);
+    if previous != state {
+        let _ = app.emit("pill:state-changed", state);
+    }
+    Ok(())
+}
+
+/// Resizes and places a visible pill again after it moved to a monitor with
+/// a different scale factor (or the monitor's scaling changed).
+///
+/// The logical size was converted with the old scale factor at the time it
+/// was set, so the pill would otherwise come out larger or smaller than on
+/// the previous monitor, and off its anchor.
+fn refit(app: &AppHandle) -> Result<(), String> {
+    let pill = app.state::<PillWindowState>();
+    let (Some(size), Some(window)) = (pill.size(), app.get_webview_window(MAIN_WINDOW)) else {
+        return Ok(());
+    };
+    if pill.transitioning.load(Ordering::SeqCst) {
+        // The transition in flight sets the size with the new factor
+        return Ok(());
+    }
+    let caret = *pill.caret.lock().unwrap();
+    transition(app, &window, size, caret, false)
+}
+
+/// Fits the visible pill to content `height_px` tall, between the state's
+/// own height and the maximum.
+fn fit_content(app: &AppHandle, height_px: u32) -> Result<(), String> {
+    let pill = app.state::<PillWindowState>();
+    let state = *pill.state.lock().unwrap();
+    let Some((_, min_height)) = state.size() else {
+        return Ok(());
+    };
+    let height = f64::from(height_px).clamp(min_height, MAX_CONTENT_HEIGHT.max(min_height));
+    *pill.content_height.lock().unwrap() = Some(height);
+    let Some(size) = pill.size() else {
+        return Ok(());
+    };
+    let window = ensure_main_window(app)?;
+    let caret = *pill.caret.lock().unwrap();
+    let reduce_motion = app.state::<SettingsState>().get().pill.reduce_motion;
+    transition(app, &window, size, caret, !reduce_motion)
+}
+
+/// Resizes, places and shows the pill, leaving it where it is while the
+/// user drags it. With `animate` the visible pill eases from its current
+/// frame to the new one; otherwise it snaps there.
+///
+/// Starting a transition cancels the one in flight, which then never
+/// touches the window again, so the last state always wins with its exact
+/// size however fast states change. Everything that waits on the main
+/// thread (monitors, the current frame) is looked up before taking the
+/// animation lock, since the main thread may be waiting for that lock.
+fn transition(
+    app: &AppHandle,
+    window: &WebviewWindow,
+    size: (f64, f64),
+    caret: Option<Rect>,
+    animate: bool,
+) -> Result<(), String> {
+    let pill = app.state::<PillWindowState>();
+    let dragging = {
+        let mut drag = pill.drag.lock().unwrap();
+        let dragging = matches!(*drag, Drag::Moving(_));
+        if !dragging {
+            // From here on moves are the pill's own
+            *drag = Drag::Idle;
+        }
+        dragging
+    };
+    let target = if dragging {
+        None
+    } else {
+        target_position(app, size, caret)
+    };
+    let from = current_frame(window).filter(|_| animate);
+    let size = target.map_or(size, |(.., size)| size);
+
+    let mut generation = pill.animation.lock().unwrap();
+    *generation += 1;
+    pill.transitioning.store(true, Ordering::SeqCst);
+    match (target, from) {
+        // Mixed scale factors don't interpolate; those snap
+        (Some((position, scale, _)), Some((from, current_scale))) if scale == current_scale => {
+            let (width, height) = physical_size(size, scale);
+            let to = Rect {
+                x: position.x as f64,
+                y: position.y as f64,
+                width,
+                height,
+            };
+            animate_to(app, *generation, from, to, size);
+        }
+        _ => {
+            let snapped = snap(window, size, target.map(|(position, ..)| position));
+            pill.transitioning.store(false, Ordering::SeqCst);
+            snapped?;
+        }
+    }
+    drop(generation);
+
+    if target.is_none() && !dragging {
+        window.center().map_err(|e| e.to_string())?;
+    }
+    show(app, window)
+}
+
+/// Sets the final frame: the exact logical size, and `position` when known.
+fn snap(
+    window: &WebviewWindow,
+    size: (f64, f64),
+    position: Option<PhysicalPosition<i32>>,
+) -> Result<(), String> {
+    window
+        .set_size(LogicalSize::new(size.0, size.1))
+        .map_err(|e| e.to_string())?;
+    match position {
+        Some(position) => window.set_position(position).map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// The visible window's physical frame and scale factor.
+fn current_frame(window: &WebviewWindow) -> Option<(Rect, f64)> {
+    if !window.is_visible().ok()? {
+        return None;
+    }
+    let position = window.outer_position().ok()?;
+    let size = window.inner_size().ok()?;
+    let frame = Rect {
+        x: position.x as f64,
+        y: position.y as f64,
+        width: size.width as f64,
+        height: size.height as f64,
+    };
+    Some((frame, window.scale_factor().ok()?))
+}
+
+/// Eases the pill from the physical frame `from` to `to` on a timer thread.
+/// Each frame is only applied while `generation` is still the current
+/// animation; the last one sets the exact logical `size`.
+fn animate_to(app: &AppHandle, generation: u64, from: Rect, to: Rect, size: (f64, f64)) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let started = Instant::now();
+        loop {
+            let progress = started.elapsed().as_secs_f64() / animation::DURATION.as_secs_f64();
+            let pill = app.state::<PillWindowState>();
+            let current = pill.animation.lock().unwrap();
+            if *current != generation {
+                return;
+            }
+            let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+                return;
+            };
+            if progress >= 1.0 {
+                let position = PhysicalPosition::new(to.x as i32, to.y as i32);
+                if let Err(e) = snap(&window, size, Some(position)) {
+                    tracing::warn!("Failed to resize the pill: {}", e);
+                }
+                pill.transitioning.store(false, Ordering::SeqCst);
+                return;
+            }
+            let frame = animation::frame(from, to, progress);
+            let _ = window.set_size(PhysicalSize::new(
+                frame.width.round() as u32,
+                frame.height.round() as u32,
+            ));
+            let _ = window.set_position(PhysicalPosition::new(
+                frame.x.round() as i32,
+                frame.y.round() as i32,
+            ));
+            drop(current);
+            std::thread::sleep(animation::FRAME_INTERVAL);
+        }
+    });
+}
+
+/// Stops the animation in flight, e.g. when the pill hides.
+fn finish_animation(app: &AppHandle) {
+    let pill = app.state::<PillWindowState>();
+    *pill.animation.lock().unwrap() += 1;
+    pill.transitioning.store(false, Ordering::SeqCst);
+}
+
+/// Puts the pill into `state` (e.g. `{ "kind": "transcript",
+/// "estimated_lines": 3 }`); the backend picks the matching size, position
+/// and visibility.
+#[tauri::command]
+pub fn set_pill_state(app: AppHandle, state: PillState) {
+    set_state(&app, state);
+}
+
+/// Sets how high the pill floats above other windows, and saves it.
+///
+/// # Errors
+///
+/// Returns an error if the level doesn't exist on this platform, or if the
+/// settings cannot be saved.
+#[tauri::command]
+pub fn set_pill_window_level(app: AppHandle, level: WindowLevel) -> Result<(), String> {
+    settings::update(&app, |settings| {
+        settings.pill.level = level;
+        Ok(())
+    })
+    .map(|_| ())
+}
+
+/// Turns the pill's blurred background on or off, optionally with another
+/// `material`, and saves it.
+///
+/// # Errors
+///
+/// Returns an error if the settings cannot be saved.
+#[tauri::command]
+pub fn set_pill_appearance(
+    app: AppHandle,
+    vibrancy: bool,
+    material: Option<VibrancyMaterial>,
+) -> Result<(), String> {
+    settings::update(&app, |settings| {
+        settings.pill.vibrancy = vibrancy;
+        if let Some(material) = material {
+            settings.pill.material = material;
+        }
+        Ok(())
+    })
+    .map(|_| ())
+}
+
+/// The pill's background as it is now: whether the blur is actually on
+/// (it may be unavailable), for the frontend to style the pill to match.
+#[tauri::command]
+pub fn get_pill_appearance(app: AppHandle) -> PillAppearance {
+    app.state::<PillWindowState>().appearance.get()
+}
+
+/// Sets where the pill is drawn in its window; clicks anywhere else go to
+/// the app below. Call it again whenever the pill resizes. No `rects` make
+/// the whole window clickable.
+#[tauri::command]
+pub fn set_pill_hit_region(app: AppHandle, rects: Vec<HitRect>) {
+    hit::set(&app, rects);
+}
+
+/// Outlines the active hit region in the pill (`visible`), or stops, for
+/// troubleshooting click-through. Returns the region.
+#[tauri::command]
+pub fn debug_pill_hit_region(app: AppHandle, visible: bool) -> Vec<HitRect> {
+    hit::debug(&app, visible)
+}
+
+/// Lists the connected monitors, for choosing `pill.monitor`.
+///
+/// # Errors
+///
+/// Returns an error if the monitors cannot be queried.
+#[tauri::command]
+pub fn list_monitors(app: AppHandle) -> Result<Vec<MonitorInfo>, String> {
+    let primary = app
+        .primary_monitor()
+        .map_err(|e| e.to_string())?
+        .and_then(|monitor| monitor.name().cloned());
+    let monitors = app.available_monitors().map_err(|e| e.to_string())?;
+    Ok(monitors
+        .iter()
+        .map(|monitor| MonitorInfo {
+            name: monitor.name().cloned(),
+            primary: primary.is_some() && monitor.name() == primary.as_ref(),
+            bounds: logical_rect(monitor),
+            scale_factor: monitor.scale_factor(),
+        })
+        .collect())
+}
+
+/// Lists the connected monitors with their work areas and which one has
+/// the cursor and the focused window, for placing things from the frontend
+/// the way the pill is placed.
+///
+/// # Errors
+///
+/// Returns an error if the monitors cannot be queried.
+#[tauri::command]
+pub fn get_monitors(app: AppHandle) -> Result<Vec<MonitorDetails>, String> {
+    let primary = app
+        .primary_monitor()
+        .map_err(|e| e.to_string())?
+        .and_then(|monitor| monitor.name().cloned());
+    let monitors = app.available_monitors().map_err(|e| e.to_string())?;
+    let cursor = app
+        .cursor_position()
+        .ok()
+        .and_then(|cursor| monitor_with_cursor(&monitors, cursor));
+    let focused = monitor_with_focused_window(&monitors);
+    Ok(monitors
+        .iter()
+        .enumerate()
+        .map(|(index, monitor)| MonitorDetails {
+            id: monitor.name().cloned(),
+            name: monitor
+                .name()
+                .cloned()
+                .unwrap_or_else(|| format!("Display {}", index + 1)),
+            primary: primary.is_some() && monitor.name() == primary.as_ref(),
+            bounds: logical_rect(monitor),
+            work_area: logical_work_area(monitor),
+            scale_factor: monitor.scale_factor(),
+            has_cursor: cursor == Some(index),
+            has_focused_window: focused == Some(index),
+        })
+        .collect())
+}
+
+/// Returns the mouse cursor position in physical pixels, and in logical
+/// pixels by the scale factor of the monitor it is on.
+///
+/// # Errors
+///
+/// Returns an error if the cursor or monitors cannot be queried.
+#[tauri::command]
+pub fn get_cursor_position(app: AppHandle) -> Result<CursorPosition, String> {
+    let cursor = app.cursor_position().map_err(|e| e.to_string())?;
+    let monitors = app.available_monitors().map_err(|e| e.to_string())?;
+    let monitor = monitor_with_cursor(&monitors, cursor).map(|index| &monitors[index]);
+    Ok(CursorPosition {
+        physical: Point {
+            x: cursor.x,
+            y: cursor.y,
+        },
+        logical: monitor.map(|monitor| Point {
+            x: cursor.x / monitor.scale_factor(),
+            y: cursor.y / monitor.scale_factor(),
+        }),
+        monitor: monitor.and_then(|monitor| monitor.name().cloned()),
+    })
+}
+
+/// Sets where the pill appears, saves it and moves a visible pill there
+/// right away.
+///
+/// # Errors
+///
+/// Returns an error if custom percentages are out of range, or if the
+/// settings cannot be saved.
+#[tauri::command]
+pub fn set_pill_position(app: AppHandle, position: PillPosition) -> Result<(), String> {
+    settings::update(&app, |settings| {
+        settings.pill.position = position;
+        Ok(())
+    })
+    .map(|_| ())
+}
+
+/// Forgets every position the pill was dragged to, so it appears at
+/// `pill.position` again, and moves a visible pill there right away.
+#[tauri::command]
+pub fn reset_pill_position(app: AppHandle) {
+    app.state::<WindowStore>().clear_pill_positions();
+    app.state::<PillWindowState>()
+        .queue
+        .push(&app, WindowOp::Reposition);
+}
+
+/// Starts moving the pill with the mouse; call it on mouse-down in a drag
+/// handle. Returns `false` when the drag was refused because the pill is
+/// hidden or resizing and moving itself.
+///
+/// # Errors
+///
+/// Returns an error if the window cannot be dragged.
+#[tauri::command]
+pub fn start_pill_drag(app: AppHandle) -> Result<bool, String> {
+    let pill = app.state::<PillWindowState>();
+    let hidden = *pill.state.lock().unwrap() == PillState::Hidden;
+    if hidden || pill.transitioning.load(Ordering::SeqCst) {
+        return Ok(false);
+    }
+    let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+        return Ok(false);
+    };
+    dragged(&app, false);
+    window.start_dragging().map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// Resizes the pill to fit content the frontend measured `height_px`
+/// (logical) tall, e.g. a growing transcript. The height stays between the
+/// state's own height and a maximum beyond which the content scrolls, and
+/// within the screen. Calls are debounced: only the last of a burst is
+/// queued, and applied through the regular (animated) transition.
+#[tauri::command]
+pub fn resize_pill_to_content(app: AppHandle, height_px: u32) {
+    let generation = app
+        .state::<PillWindowState>()
+        .content_generation
+        .fetch_add(1, Ordering::SeqCst)
+        + 1;
+    std::thread::spawn(move || {
+        std::thread::sleep(CONTENT_DEBOUNCE);
+        let pill = app.state::<PillWindowState>();
+        if pill.content_generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        let op = WindowOp::Content { height: height_px };
+        pill.queue.push(&app, op);
+    });
+}
+
+/// Reports the mouse entering, leaving or clicking the pill. While the
+/// mouse is over it the transcript or error pill doesn't time out; leaving
+/// or clicking starts the timeout over.
+#[tauri::command]
+pub fn pill_interaction(app: AppHandle, interaction: PillInteraction) {
+    timeout::interact(&app, interaction);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// A monitor as the pill sees it: physical bounds and work area, and
+    /// the scale factor.
+    struct Screen {
+        bounds: Rect,
+        work_area: Rect,
+        scale: f64,
+    }
+
+    impl Screen {
+        fn origin(&self) -> PhysicalPosition<i32> {
+            PhysicalPosition::new(self.bounds.x as i32, self.bounds.y as i32)
+        }
+    }
+
+    /// A Retina laptop with a 1x monitor to its right. macOS gives each
+    /// monitor's origin in points scaled by its own factor.
+    fn mixed_layout() -> [Screen; 2] {
+        [
+            Screen {
+                bounds: rect(0.0, 0.0, 2880.0, 1800.0),
+                work_area: rect(0.0, 50.0, 2880.0, 1750.0),
+                scale: 2.0,
+            },
+            Screen {
+                bounds: rect(1440.0, 0.0, 1920.0, 1080.0),
+                work_area: rect(1440.0, 0.0, 1920.0, 1080.0),
+                scale: 1.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn monitors_of_any_scale_meet_in_logical_space() {
+        let [laptop, external] = mixed_layout();
+        assert_eq!(
+            to_logical(laptop.bounds, laptop.scale),
+            rect(0.0, 0.0, 1440.0, 900.0)
+        );
+        assert_eq!(
+            to_logical(laptop.work_area, laptop.scale),
+            rect(0.0, 25.0, 1440.0, 875.0)
+        );
+        assert_eq!(
+            to_logical(external.bounds, external.scale),
+            rect(1440.0, 0.0, 1920.0, 1080.0)
+        );
+    }
+
+    #[test]
+    fn logical_points_land_on_their_monitor() {
+        let [laptop, external] = mixed_layout();
+        let bounds = to_logical(laptop.bounds, laptop.scale);
+        assert_eq!(
+            to_physical((520.0, 400.0), bounds, laptop.origin(), laptop.scale),
+            PhysicalPosition::new(1040, 800)
+        );
+        let bounds = to_logical(external.bounds, external.scale);
+        assert_eq!(
+            to_physical((1960.0, 400.0), bounds, external.origin(), external.scale),
+            PhysicalPosition::new(1960, 400)
+        );
+    }
+
+    #[test]
+    fn offsets_survive_the_round_trip() {
+        for scale in [1.0, 1.25, 1.5, 2.0, 3.0] {
+            let origin = PhysicalPosition::new(-1920, 300);
+            let bounds = to_logical(rect(-1920.0, 300.0, 3840.0, 2160.0), scale);
+            for offset in [(0.0, 0.0), (100.0, 50.0), (333.3, 17.7)] {
+                let point = (bounds.x + offset.0, bounds.y + offset.1);
+                let position = to_physical(point, bounds, origin, scale);
+                let back = to_offset(position, origin, scale);
+                // Off by at most half a physical pixel
+                assert!(
+                    (back.0 - offset.0).abs() <= 0.5 / scale,
+                    "{scale}: {back:?}"
+                );
+                assert!(
+                    (back.1 - offset.1).abs() <= 0.5 / scale,
+                    "{scale}: {back:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn physical_sizes_follow_the_scale() {
+        assert_eq!(physical_size(COMPACT_SIZE, 1.0), (400.0, 100.0));
+        assert_eq!(physical_size(COMPACT_SIZE, 2.0), (800.0, 200.0));
+        assert_eq!(physical_size(COMPACT_SIZE, 1.5), (600.0, 150.0));
+        // Rounded to whole pixels
+        assert_eq!(physical_size((101.0, 33.0), 1.25), (126.0, 41.0));
+    }
+
+    #[test]
+    fn transcript_pill_grows_with_its_lines() {
+        let size = |estimated_lines| PillState::Transcript { estimated_lines }.size();
+        assert_eq!(size(0), Some((TRANSCRIPT_WIDTH, TRANSCRIPT_BASE_HEIGHT)));
+        assert_eq!(size(1), Some((600.0, 102.0)));
+        assert_eq!(size(3), Some((600.0, 150.0)));
+        assert_eq!(size(6), Some((600.0, MAX_CONTENT_HEIGHT)));
+        // Longer transcripts scroll
+        assert_eq!(size(40), size(6));
+    }
+
+    #[test]
+    fn other_states_are_compact() {
+        assert_eq!(PillState::Hidden.size(), None);
+        for state in [
+            PillState::Recording,
+            PillState::Transcribing,
+            PillState::Error,
+        ] {
+            assert_eq!(state.size(), Some(COMPACT_SIZE));
+        }
+    }
+
+    #[test]
+    fn every_pill_looks_the_same_on_mixed_monitors() {
+        let states = [
+            PillState::Recording,
+            PillState::Transcript { estimated_lines: 1 },
+            PillState::Transcript { estimated_lines: 6 },
+        ];
+        for state in states {
+            let size = state.size().unwrap();
+            let logical: Vec<(f64, f64)> = mixed_layout()
+                .iter()
+                .map(|screen| {
+                    let physical = physical_size(size, screen.scale);
+                    assert_eq!(physical, (size.0 * screen.scale, size.1 * screen.scale));
+                    (physical.0 / screen.scale, physical.1 / screen.scale)
+                })
+                .collect();
+            assert_eq!(logical, [size, size], "{state:?}");
+        }
+    }
+
+    #[test]
+    fn transcript_pill_fits_on_each_monitor() {
+        let size = PillState::Transcript { estimated_lines: 6 }.size().unwrap();
+        for screen in mixed_layout() {
+            let bounds = to_logical(screen.bounds, screen.scale);
+            let work_area = to_logical(screen.work_area, screen.scale);
+            let size = placement::fit(size, work_area);
+            for position in [
+                PillPosition::TopCenter,
+                PillPosition::BottomCenter,
+                PillPosition::Center,
+            ] {
+                let point = placement::anchored(work_area, size, position);
+                let top_left = to_physical(point, bounds, screen.origin(), screen.scale);
+                let (width, height) = physical_size(size, screen.scale);
+                let area = screen.work_area;
+                assert!(f64::from(top_left.x) >= area.x, "{position:?}");
+                assert!(f64::from(top_left.y) >= area.y, "{position:?}");
+                assert!(f64::from(top_left.x) + width <= area.x + area.width);
+                assert!(f64::from(top_left.y) + height <= area.y + area.height);
+            }
+        }
+    }
+}
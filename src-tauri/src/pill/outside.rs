@@ -0,0 +1,145 @@
+//! Hiding the pill when the user clicks somewhere else.
+//!
+//! While the pill is visible (and `pill.hide_on_click_outside` is on), a
+//! global mouse-down monitor watches for clicks. A click outside the pill's
+//! current frame, looked up at click time so it is right even mid-animation,
+//! hides the pill and emits `recording:cancelled` with the reason
+//! `click_outside`, which makes the frontend cancel the recording in
+//! progress. The monitor is removed as soon as the pill hides, so there is
+//! never a lingering event tap.
+//!
+//! # Platform Support
+//!
+//! - macOS: an `NSEvent` global monitor, which only sees clicks in other
+//!   apps' windows. No extra permission is needed.
+//! - Windows: a low-level mouse hook.
+//! - Linux: not supported; the pill stays until it is dismissed.
+
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+
+use super::placement::Rect;
+use super::{PillState, PillWindowState, MAIN_WINDOW};
+
+use crate::AppHandle;
+
+/// Payload of the `recording:cancelled` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingCancelled {
+    pub reason: &'static str,
+    /// The dictation to cancel, if one is under way.
+    pub session_id: Option<String>,
+}
+
+/// The installed monitor (an `NSEvent` monitor or a Windows hook), as an
+/// address so it can live in managed state.
+#[derive(Default)]
+pub(super) struct OutsideClicks {
+    monitor: Mutex<Option<usize>>,
+}
+
+/// Starts watching for clicks outside the pill, unless already watching.
+pub(super) fn watch(app: &AppHandle) {
+    let handle = app.clone();
+    // Installed and removed on the main thread, in the order requested
+    let _ = app.run_on_main_thread(move || {
+        let clicks = &handle.state::<PillWindowState>().outside;
+        let mut monitor = clicks.monitor.lock().unwrap();
+        if monitor.is_none() {
+            *monitor = install(&handle);
+        }
+    });
+}
+
+/// Stops watching for clicks outside the pill.
+pub(super) fn unwatch(app: &AppHandle) {
+    let handle = app.clone();
+    let _ = app.run_on_main_thread(move || {
+        let clicks = &handle.state::<PillWindowState>().outside;
+        if let Some(monitor) = clicks.monitor.lock().unwrap().take() {
+            remove(monitor);
+        }
+    });
+}
+
+#[cfg(target_os = "macos")]
+fn install(app: &AppHandle) -> Option<usize> {
+    let app = app.clone();
+    let monitor = unsafe { super::macos::add_mouse_down_monitor(move || clicked(&app)) };
+    if monitor.is_none() {
+        tracing::warn!("Failed to watch for clicks outside the pill");
+    }
+    monitor.map(|monitor| monitor.as_ptr() as usize)
+}
+
+#[cfg(target_os = "macos")]
+fn remove(monitor: usize) {
+    if let Some(monitor) = std::ptr::NonNull::new(monitor as *mut objc2::runtime::AnyObject) {
+        unsafe { super::macos::remove_monitor(monitor) };
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn install(app: &AppHandle) -> Option<usize> {
+    let app = app.clone();
+    let hook = unsafe {
+        super::win32::add_mouse_hook(move || {
+            // The hook must return right away
+            let app = app.clone();
+            std::thread::spawn(move || clicked(&app));
+        })
+    };
+    if hook.is_none() {
+        tracing::warn!("Failed to watch for clicks outside the pill");
+    }
+    hook.map(|hook| hook as usize)
+}
+
+#[cfg(target_os = "windows")]
+fn remove(hook: usize) {
+    unsafe { super::win32::remove_mouse_hook(hook as isize) };
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn install(_app: &AppHandle) -> Option<usize> {
+    None
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn remove(_monitor: usize) {}
+
+/// Hides the pill if the mouse went down outside its frame.
+#[cfg_attr(not(any(target_os = "macos", target_os = "windows")), allow(dead_code))]
+fn clicked(app: &AppHandle) {
+    let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+        return;
+    };
+    let (Ok(cursor), Ok(position), Ok(size)) = (
+        app.cursor_position(),
+        window.outer_position(),
+        window.outer_size(),
+    ) else {
+        return;
+    };
+    let frame = Rect {
+        x: position.x as f64,
+        y: position.y as f64,
+        width: size.width as f64,
+        height: size.height as f64,
+    };
+    if frame.contains(cursor.x, cursor.y) {
+        return;
+    }
+
+    tracing::info!("Click outside the pill; hiding it");
+    super::set_state(app, PillState::Hidden);
+    let _ = app.emit(
+        "recording:cancelled",
+        RecordingCancelled {
+            reason: "click_outside",
+            session_id: crate::app_state::session_id(app),
+        },
+    );
+}
@@ -0,0 +1,304 @@
+//! Where the pill goes on screen.
+//!
+//! Pure geometry over rectangles in logical pixels (points on macOS), kept
+//! apart from the window calls so the rules can be reasoned about on their
+//! own. Each monitor is handled in its own logical space: positions are
+//! computed relative to the monitor and only converted back to physical
+//! pixels with that monitor's scale factor, so mixed-DPI setups place the
+//! pill correctly.
+
+use serde::{Deserialize, Serialize};
+
+/// Distance kept between the pill and the edges of the work area, in
+/// logical pixels.
+const EDGE_MARGIN: f64 = 24.0;
+
+/// Where on the monitor the pill appears.
+///
+/// Each placement anchors the same point of the pill (e.g. its top edge for
+/// [`PillPosition::TopCenter`]), so the pill grows away from the anchor
+/// instead of jumping when it resizes between states.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PillPosition {
+    TopCenter,
+    BottomCenter,
+    #[default]
+    Center,
+    /// The point at `x_percent`/`y_percent` of the pill sits at the same
+    /// percentages of the work area: `0, 0` is the top left corner, `100,
+    /// 100` the bottom right one.
+    Custom {
+        x_percent: u8,
+        y_percent: u8,
+    },
+}
+
+impl PillPosition {
+    /// Checks that custom percentages are between 0 and 100.
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Self::Custom {
+                x_percent,
+                y_percent,
+            } if x_percent > 100 || y_percent > 100 => {
+                Err("custom percentages must be between 0 and 100".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The anchor as fractions of the width and height.
+    fn fractions(self) -> (f64, f64) {
+        match self {
+            Self::TopCenter => (0.5, 0.0),
+            Self::BottomCenter => (0.5, 1.0),
+            Self::Center => (0.5, 0.5),
+            Self::Custom {
+                x_percent,
+                y_percent,
+            } => (f64::from(x_percent) / 100.0, f64::from(y_percent) / 100.0),
+        }
+    }
+}
+
+/// An axis-aligned rectangle.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+}
+
+/// Index of the first of `monitors` containing the point.
+pub fn monitor_at(monitors: &[Rect], x: f64, y: f64) -> Option<usize> {
+    monitors.iter().position(|monitor| monitor.contains(x, y))
+}
+
+/// Top-left corner for a window of `size` at `position` in `work_area`
+/// (the monitor's area without the menu bar and Dock or taskbar).
+pub fn anchored(work_area: Rect, size: (f64, f64), position: PillPosition) -> (f64, f64) {
+    let area = Rect {
+        x: work_area.x + EDGE_MARGIN,
+        y: work_area.y + EDGE_MARGIN,
+        width: (work_area.width - 2.0 * EDGE_MARGIN).max(0.0),
+        height: (work_area.height - 2.0 * EDGE_MARGIN).max(0.0),
+    };
+    let (fx, fy) = position.fractions();
+    (
+        area.x + fx * (area.width - size.0),
+        area.y + fy * (area.height - size.1),
+    )
+}
+
+/// Top-left corner for a window of `size` at `offset` from the top-left
+/// corner of `bounds`, a position the user dragged the pill to on that
+/// monitor. `None` when the spot no longer lies in `work_area` (the
+/// resolution or arrangement changed); otherwise it is kept inside it, so a
+/// larger pill than the dragged one still fits.
+pub fn restore(
+    offset: (f64, f64),
+    bounds: Rect,
+    work_area: Rect,
+    size: (f64, f64),
+) -> Option<(f64, f64)> {
+    let (x, y) = (bounds.x + offset.0, bounds.y + offset.1);
+    work_area.contains(x, y).then(|| {
+        (
+            clamp_into(x, size.0, work_area.x, work_area.width),
+            clamp_into(y, size.1, work_area.y, work_area.height),
+        )
+    })
+}
+
+/// `size` shrunk to fit `work_area`, with the same margin as
+/// [`anchored`].
+pub fn fit(size: (f64, f64), work_area: Rect) -> (f64, f64) {
+    (
+        size.0.min(work_area.width - 2.0 * EDGE_MARGIN).max(1.0),
+        size.1.min(work_area.height - 2.0 * EDGE_MARGIN).max(1.0),
+    )
+}
+
+/// Top-left corner for a window of `size` next to the caret: centered under
+/// it, or above it when there is no room below, kept inside `visible` (the
+/// monitor's area without the menu bar and Dock or taskbar).
+pub fn near_caret(caret: Rect, visible: Rect, size: (f64, f64), gap: f64) -> (f64, f64) {
+    let (width, height) = size;
+    let below = caret.y + caret.height + gap;
+    let above = caret.y - gap - height;
+    let y = if below + height <= visible.y + visible.height || above < visible.y {
+        below
+    } else {
+        above
+    };
+    let x = caret.x + caret.width / 2.0 - width / 2.0;
+    (
+        clamp_into(x, width, visible.x, visible.width),
+        clamp_into(y, height, visible.y, visible.height),
+    )
+}
+
+/// Clamps the start of a span of `length` into `[start, start + extent]`,
+/// preferring the start when the span doesn't fit.
+fn clamp_into(value: f64, length: f64, start: f64, extent: f64) -> f64 {
+    value.min(start + extent - length).max(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PILL: (f64, f64) = (400.0, 100.0);
+    const GAP: f64 = 8.0;
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// A 1440x900 screen with a 25 point menu bar.
+    fn visible() -> Rect {
+        rect(0.0, 25.0, 1440.0, 875.0)
+    }
+
+    /// A one-line caret at `x, y`.
+    fn caret(x: f64, y: f64) -> Rect {
+        rect(x, y, 2.0, 18.0)
+    }
+
+    #[test]
+    fn pill_goes_centered_under_the_caret() {
+        let (x, y) = near_caret(caret(700.0, 300.0), visible(), PILL, GAP);
+        assert_eq!(x, 701.0 - 200.0);
+        assert_eq!(y, 300.0 + 18.0 + GAP);
+    }
+
+    #[test]
+    fn pill_flips_above_the_caret_near_the_bottom() {
+        let (_, y) = near_caret(caret(700.0, 850.0), visible(), PILL, GAP);
+        assert_eq!(y, 850.0 - GAP - 100.0);
+    }
+
+    #[test]
+    fn pill_fits_exactly_below_without_flipping() {
+        // The pill's bottom edge lands on the bottom of the screen
+        let top = 900.0 - 100.0 - GAP - 18.0;
+        let (_, y) = near_caret(caret(700.0, top), visible(), PILL, GAP);
+        assert_eq!(y, top + 18.0 + GAP);
+    }
+
+    #[test]
+    fn pill_stays_on_screen_when_there_is_room_neither_way() {
+        let short = rect(0.0, 0.0, 1440.0, 150.0);
+        let (_, y) = near_caret(caret(700.0, 60.0), short, PILL, GAP);
+        assert_eq!(y, 50.0);
+    }
+
+    #[test]
+    fn pill_is_clamped_to_the_left_and_right_edges() {
+        let (x, _) = near_caret(caret(10.0, 300.0), visible(), PILL, GAP);
+        assert_eq!(x, 0.0);
+        let (x, _) = near_caret(caret(1430.0, 300.0), visible(), PILL, GAP);
+        assert_eq!(x, 1440.0 - 400.0);
+    }
+
+    #[test]
+    fn pill_is_clamped_under_the_menu_bar() {
+        // A caret hidden under the menu bar still puts the pill below it
+        let (_, y) = near_caret(rect(700.0, -40.0, 2.0, 18.0), visible(), PILL, GAP);
+        assert_eq!(y, 25.0);
+    }
+
+    #[test]
+    fn pill_stays_on_a_monitor_left_of_the_primary() {
+        let left = rect(-1920.0, 0.0, 1920.0, 1080.0);
+        let (x, y) = near_caret(caret(-5.0, 1070.0), left, PILL, GAP);
+        assert_eq!(x, -400.0);
+        assert_eq!(y, 1070.0 - GAP - 100.0);
+    }
+
+    #[test]
+    fn tall_caret_flips_above_its_top() {
+        // A multi-line selection
+        let selection = rect(600.0, 500.0, 300.0, 380.0);
+        let (x, y) = near_caret(selection, visible(), PILL, GAP);
+        assert_eq!(x, 750.0 - 200.0);
+        assert_eq!(y, 500.0 - GAP - 100.0);
+    }
+
+    /// A laptop with a monitor to its right, higher up, and one to its left.
+    fn layout() -> Vec<Rect> {
+        vec![
+            rect(0.0, 0.0, 1440.0, 900.0),
+            rect(1440.0, -200.0, 1920.0, 1080.0),
+            rect(-1280.0, 0.0, 1280.0, 800.0),
+        ]
+    }
+
+    #[test]
+    fn points_are_found_on_their_monitor() {
+        let monitors = layout();
+        assert_eq!(monitor_at(&monitors, 700.0, 450.0), Some(0));
+        assert_eq!(monitor_at(&monitors, 1500.0, -100.0), Some(1));
+        assert_eq!(monitor_at(&monitors, -1.0, 10.0), Some(2));
+        // Edges belong to the monitor that starts there
+        assert_eq!(monitor_at(&monitors, 1440.0, 0.0), Some(1));
+        // Below the right monitor, which ends higher than the laptop
+        assert_eq!(monitor_at(&monitors, 2000.0, 1000.0), None);
+    }
+
+    #[test]
+    fn dragged_spot_is_restored_relative_to_its_monitor() {
+        let bounds = rect(1440.0, -200.0, 1920.0, 1080.0);
+        let work_area = rect(1440.0, -175.0, 1920.0, 1055.0);
+        let restored = restore((100.0, 50.0), bounds, work_area, PILL);
+        assert_eq!(restored, Some((1540.0, -150.0)));
+    }
+
+    #[test]
+    fn dragged_spot_follows_its_monitor_around_the_arrangement() {
+        // The same monitor, moved from the right of the laptop to its left
+        let offset = (100.0, 50.0);
+        let right = rect(1440.0, 0.0, 1920.0, 1080.0);
+        let left = rect(-1920.0, 0.0, 1920.0, 1080.0);
+        assert_eq!(restore(offset, right, right, PILL), Some((1540.0, 50.0)));
+        assert_eq!(restore(offset, left, left, PILL), Some((-1820.0, 50.0)));
+    }
+
+    #[test]
+    fn dragged_spot_off_a_smaller_resolution_is_dropped() {
+        // Dragged near the corner at 1920x1080, now at 1280x720
+        let smaller = rect(0.0, 0.0, 1280.0, 720.0);
+        assert_eq!(restore((1800.0, 900.0), smaller, smaller, PILL), None);
+    }
+
+    #[test]
+    fn dragged_spot_under_the_menu_bar_is_dropped() {
+        let bounds = rect(0.0, 0.0, 1440.0, 900.0);
+        assert_eq!(restore((100.0, 10.0), bounds, visible(), PILL), None);
+    }
+
+    #[test]
+    fn larger_pill_at_a_dragged_spot_is_kept_on_screen() {
+        let bounds = rect(0.0, 0.0, 1920.0, 1080.0);
+        let work_area = rect(0.0, 0.0, 1920.0, 1040.0);
+        let restored = restore((1800.0, 1000.0), bounds, work_area, (600.0, 200.0));
+        assert_eq!(restored, Some((1320.0, 840.0)));
+    }
+}
@@ -0,0 +1,353 @@
+//! The queue every pill window change goes through.
+//!
+//! Commands, window events and timers only enqueue the end state they want;
+//! one worker thread applies the operations in order. Before applying, the
+//! worker takes everything queued up and drops operations a later one makes
+//! redundant, so mashing the hotkey costs one window change instead of a
+//! dozen, and a late hide can never land after the show that followed it.
+//!
+//! Every state change bumps a generation. Operations for the state at the
+//! time they were queued (fitting content, refitting to a new scale
+//! factor, previewing a position) carry it and are dropped once the state
+//! has moved on.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+use tauri::Manager;
+
+use super::PillState;
+
+use crate::AppHandle;
+
+/// A change to the pill window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum WindowOp {
+    /// Put the pill into `state`; hiding fades out unless `fade` is off.
+    State { state: PillState, fade: bool },
+    /// Fit the visible pill to content `height` logical pixels tall.
+    Content { height: u32 },
+    /// Apply the size again after the scale factor changed.
+    Refit,
+    /// Place the visible pill again after the position settings changed.
+    Reposition,
+    /// Hide the pill if its timeout `generation` is still the current one.
+    Expire { generation: u64 },
+    /// Start the timeout over, after the user interacted with the pill.
+    Rearm,
+}
+
+impl WindowOp {
+    /// Whether applying `later` afterwards makes this operation pointless.
+    fn superseded_by(&self, later: &WindowOp) -> bool {
+        matches!(
+            (self, later),
+            // A state change sizes and places the pill from scratch
+            (_, Self::State { .. })
+                | (Self::Content { .. }, Self::Content { .. })
+                | (Self::Refit, Self::Refit)
+                | (Self::Reposition, Self::Reposition)
+                | (Self::Rearm, Self::Rearm)
+        )
+    }
+}
+
+struct Queued {
+    op: WindowOp,
+    /// State generation the operation was queued for.
+    generation: u64,
+    /// Told the outcome once the operation was applied or dropped.
+    done: Option<Sender<Result<(), String>>>,
+}
+
+/// Sending end of the queue, with the worker started on first use.
+#[derive(Default)]
+pub(super) struct WindowQueue {
+    sender: Mutex<Option<Sender<Queued>>>,
+    generation: AtomicU64,
+}
+
+impl WindowQueue {
+    /// Queues `op`, bumping the state generation for state changes.
+    pub(super) fn push(&self, app: &AppHandle, op: WindowOp) {
+        self.send(app, op, None);
+    }
+
+    /// Queues `op` and waits until it was applied. Must not be called on the
+    /// main thread, which the worker may need to finish.
+    pub(super) fn push_and_wait(&self, app: &AppHandle, op: WindowOp) -> Result<(), String> {
+        let (done, outcome) = mpsc::channel();
+        self.send(app, op, Some(done));
+        outcome
+            .recv()
+            .map_err(|_| "the pill window queue stopped".to_string())?
+    }
+
+    /// The state generation `op` is queued for, bumped for state changes.
+    fn stamp(&self, op: &WindowOp) -> u64 {
+        match op {
+            WindowOp::State { .. } => self.generation.fetch_add(1, Ordering::SeqCst) + 1,
+            _ => self.generation.load(Ordering::SeqCst),
+        }
+    }
+
+    fn send(&self, app: &AppHandle, op: WindowOp, done: Option<Sender<Result<(), String>>>) {
+        let queued = Queued {
+            op,
+            generation: self.stamp(&op),
+            done,
+        };
+        let mut sender = self.sender.lock().unwrap();
+        let sender = sender.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel();
+            let worker = app.clone();
+            // Restarted after a panic, so the pill keeps working
+            if let Err(e) =
+                crate::crash::supervise(app, "pill-queue", move || work(&worker, &receiver))
+            {
+                tracing::warn!("Failed to start the pill window queue: {}", e);
+            }
+            sender
+        });
+        if let Err(mpsc::SendError(queued)) = sender.send(queued) {
+            if let Some(done) = queued.done {
+                let _ = done.send(Err("the pill window queue stopped".to_string()));
+            }
+        }
+    }
+}
+
+/// Applies queued operations until the app exits.
+fn work(app: &AppHandle, receiver: &Receiver<Queued>) {
+    while let Ok(first) = receiver.recv() {
+        let mut batch = vec![first];
+        batch.extend(receiver.try_iter());
+
+        let current = app
+            .state::<super::PillWindowState>()
+            .queue
+            .generation
+            .load(Ordering::SeqCst);
+        let applied = to_apply(&batch, current);
+        for (queued, apply) in batch.iter().zip(applied) {
+            let outcome = if apply {
+                super::apply_op(app, queued.op)
+            } else {
+                Ok(())
+            };
+            if let Err(e) = &outcome {
+                tracing::warn!("Pill window operation {:?} failed: {}", queued.op, e);
+            }
+            if let Some(done) = &queued.done {
+                let _ = done.send(outcome);
+            }
+        }
+    }
+}
+
+/// Which operations of `batch` to apply while the state is at generation
+/// `current`: those queued for it that no later one makes redundant.
+fn to_apply(batch: &[Queued], current: u64) -> Vec<bool> {
+    batch
+        .iter()
+        .enumerate()
+        .map(|(index, queued)| {
+            let redundant = batch[index + 1..]
+                .iter()
+                .any(|later| queued.op.superseded_by(&later.op));
+            !redundant && queued.generation >= current
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the window ends up showing.
+    #[derive(Debug, Default, PartialEq)]
+    struct Window {
+        state: PillState,
+        height: Option<f64>,
+    }
+
+    impl Window {
+        fn apply(&mut self, op: WindowOp) {
+            match op {
+                WindowOp::State { state, .. } => {
+                    self.state = state;
+                    self.height = state.size().map(|(_, height)| height);
+                }
+                WindowOp::Content { height } => {
+                    if self.state != PillState::Hidden {
+                        self.height = Some(f64::from(height));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The queue with its worker played by hand: [`Harness::receive`] takes
+    /// what is queued like the worker's `recv`, and [`Harness::apply`]
+    /// applies it, so pushes can land between the two.
+    #[derive(Default)]
+    struct Harness {
+        queue: WindowQueue,
+        pending: Vec<Queued>,
+        batch: Vec<Queued>,
+        window: Window,
+        applied: Vec<WindowOp>,
+    }
+
+    impl Harness {
+        fn push(&mut self, op: WindowOp) {
+            let generation = self.queue.stamp(&op);
+            self.pending.push(Queued {
+                op,
+                generation,
+                done: None,
+            });
+        }
+
+        fn receive(&mut self) {
+            self.batch.append(&mut self.pending);
+        }
+
+        fn apply(&mut self) {
+            let current = self.queue.generation.load(Ordering::SeqCst);
+            let batch = std::mem::take(&mut self.batch);
+            for (queued, apply) in batch.iter().zip(to_apply(&batch, current)) {
+                if apply {
+                    self.window.apply(queued.op);
+                    self.applied.push(queued.op);
+                }
+            }
+        }
+
+        fn flush(&mut self) {
+            self.receive();
+            self.apply();
+        }
+    }
+
+    fn state(state: PillState) -> WindowOp {
+        WindowOp::State { state, fade: true }
+    }
+
+    const TRANSCRIPT: PillState = PillState::Transcript { estimated_lines: 2 };
+
+    #[test]
+    fn mashing_the_hotkey_applies_the_last_state_once() {
+        let mut harness = Harness::default();
+        for _ in 0..6 {
+            harness.push(state(PillState::Hidden));
+            harness.push(state(PillState::Recording));
+        }
+        harness.flush();
+        assert_eq!(harness.applied, [state(PillState::Recording)]);
+        assert_eq!(harness.window.state, PillState::Recording);
+    }
+
+    #[test]
+    fn late_hide_never_lands_after_a_newer_show() {
+        let mut harness = Harness::default();
+        harness.push(state(PillState::Hidden));
+        harness.receive();
+        // Shown again while the worker holds the hide
+        harness.push(state(PillState::Recording));
+        harness.apply();
+        harness.flush();
+        assert_eq!(harness.applied, [state(PillState::Recording)]);
+        assert_eq!(harness.window.state, PillState::Recording);
+    }
+
+    #[test]
+    fn content_for_an_old_state_is_dropped() {
+        let mut harness = Harness::default();
+        harness.push(state(TRANSCRIPT));
+        harness.push(WindowOp::Content { height: 300 });
+        harness.receive();
+        harness.push(state(PillState::Recording));
+        harness.apply();
+        harness.push(WindowOp::Refit);
+        harness.flush();
+        assert_eq!(
+            harness.applied,
+            [state(PillState::Recording), WindowOp::Refit]
+        );
+        assert_eq!(
+            harness.window,
+            Window {
+                state: PillState::Recording,
+                height: Some(100.0),
+            }
+        );
+    }
+
+    #[test]
+    fn interleaved_resizes_end_at_the_last_one() {
+        let mut harness = Harness::default();
+        harness.push(state(TRANSCRIPT));
+        harness.push(WindowOp::Content { height: 150 });
+        harness.receive();
+        harness.push(WindowOp::Content { height: 180 });
+        harness.push(WindowOp::Refit);
+        harness.apply();
+        harness.push(WindowOp::Content { height: 160 });
+        harness.push(WindowOp::Refit);
+        harness.flush();
+        assert_eq!(
+            harness.applied,
+            [
+                state(TRANSCRIPT),
+                WindowOp::Content { height: 150 },
+                WindowOp::Content { height: 160 },
+                WindowOp::Refit,
+            ]
+        );
+        assert_eq!(harness.window.height, Some(160.0));
+    }
+
+    #[test]
+    fn every_interleaving_ends_at_the_last_request() {
+        let ops = [
+            state(PillState::Hidden),
+            state(PillState::Recording),
+            state(PillState::Transcribing),
+            state(TRANSCRIPT),
+            state(PillState::Error),
+            WindowOp::Content { height: 130 },
+            WindowOp::Content { height: 170 },
+            WindowOp::Refit,
+            WindowOp::Reposition,
+            WindowOp::Rearm,
+        ];
+        // A fixed linear congruential sequence, so failures reproduce
+        let mut seed: u64 = 0x2545_f491;
+        let mut next = |bound: usize| {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 33) as usize % bound
+        };
+        for run in 0..500 {
+            let mut harness = Harness::default();
+            let mut expected = Window::default();
+            for _ in 0..next(40) {
+                match next(4) {
+                    0 => harness.receive(),
+                    1 => harness.apply(),
+                    _ => {
+                        let op = ops[next(ops.len())];
+                        expected.apply(op);
+                        harness.push(op);
+                    }
+                }
+            }
+            harness.flush();
+            assert_eq!(harness.window, expected, "run {}", run);
+        }
+    }
+}
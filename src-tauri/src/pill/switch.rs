@@ -0,0 +1,128 @@
+//! Hiding the pill when the user switches to another app.
+//!
+//! A transcript pill that lingers after a paste should get out of the way
+//! once the user Cmd+Tabs elsewhere. The pill remembers which app was
+//! active when it appeared (the paste target); when a different app becomes
+//! active while it shows a transcript, it hides (nothing is cancelled) and
+//! `pill:auto-hidden` is emitted. Switching apps mid-dictation is
+//! legitimate, so the recording pill stays unless
+//! `pill.auto_hide_while_recording` is on.
+//!
+//! Our own app becoming active (a regular pill window taking focus) never
+//! counts, and neither does anything while the app is `Pasting`: hiding
+//! before the paste hands focus back and forth on purpose.
+//!
+//! # Platform Support
+//!
+//! - macOS: `NSWorkspace` activation notifications.
+//! - Windows and Linux: not supported.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+
+use super::{PillState, PillWindowState};
+use crate::app_state::{self, Phase};
+use crate::settings::SettingsState;
+use crate::AppHandle;
+
+/// Payload of the `pill:auto-hidden` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct PillAutoHidden {
+    /// The state the pill was in.
+    pub state: PillState,
+    /// Bundle identifier of the app that became active, if known.
+    pub app: Option<String>,
+}
+
+#[derive(Default)]
+pub(super) struct AppSwitches {
+    observing: AtomicBool,
+    /// The app that is active now, as last reported.
+    active: Mutex<Option<String>>,
+    /// The app that was active when the pill appeared.
+    target: Mutex<Option<String>>,
+}
+
+/// Starts following the active app, once.
+pub(super) fn observe(app: &AppHandle) {
+    let switches = &app.state::<PillWindowState>().switches;
+    if switches.observing.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        // Until the first switch, ask which app is active now
+        let handle = app.clone();
+        std::thread::spawn(move || {
+            let id = crate::frontmost::frontmost_bundle_id().filter(|id| !is_ours(&handle, id));
+            if let Some(id) = id {
+                let switches = &handle.state::<PillWindowState>().switches;
+                switches.active.lock().unwrap().get_or_insert(id);
+            }
+        });
+
+        let handle = app.clone();
+        let _ = app.run_on_main_thread(move || {
+            let app = handle.clone();
+            let observed =
+                unsafe { super::macos::observe_app_activation(move |id| activated(&app, id)) };
+            if !observed {
+                tracing::warn!("Failed to follow the active app; the pill won't auto-hide");
+            }
+        });
+    }
+}
+
+/// Remembers the active app as the one the pill appeared over.
+pub(super) fn capture_target(app: &AppHandle) {
+    let switches = &app.state::<PillWindowState>().switches;
+    let active = switches.active.lock().unwrap().clone();
+    *switches.target.lock().unwrap() = active;
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn is_ours(app: &AppHandle, id: &str) -> bool {
+    id == app.config().identifier
+}
+
+/// Hides the pill if the app that just became active isn't the target.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn activated(app: &AppHandle, id: Option<String>) {
+    if id.as_deref().is_some_and(|id| is_ours(app, id)) {
+        return;
+    }
+    let pill = app.state::<PillWindowState>();
+    *pill.switches.active.lock().unwrap() = id.clone();
+    if app_state::phase(app) == Phase::Pasting {
+        return;
+    }
+    if id.is_some() && *pill.switches.target.lock().unwrap() == id {
+        return;
+    }
+
+    let state = *pill.state.lock().unwrap();
+    let applies = match state {
+        PillState::Transcript { .. } => true,
+        PillState::Recording => {
+            app.state::<SettingsState>()
+                .get()
+                .pill
+                .auto_hide_while_recording
+        }
+        _ => false,
+    };
+    if !applies {
+        return;
+    }
+
+    tracing::info!(
+        "{} became active; hiding the pill",
+        id.as_deref().unwrap_or("another app")
+    );
+    super::set_state(app, PillState::Hidden);
+    let _ = app.emit("pill:auto-hidden", PillAutoHidden { state, app: id });
+}
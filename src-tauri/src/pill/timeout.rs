@@ -0,0 +1,128 @@
+//! Hiding the transcript and error pills after a while.
+//!
+//! Entering the transcript state arms a timer that hides the pill after
+//! `pill.transcript_timeout_ms`, and entering the error state one for the
+//! longer `pill.error_timeout_ms`; `0` keeps the pill until it is
+//! dismissed. The backend owns the timer, so the pill goes away even when
+//! the webview is busy, and the hide is queued like any other state change
+//! (emitting `pill:state-changed`). An error pill timing out also dismisses
+//! the failed dictation, like `hide_recording_pill`. Every other state
+//! cancels the timer, and so does a dictation starting to record.
+//!
+//! The frontend reports the mouse with `pill_interaction`: while it is over
+//! the pill the timer is held, and leaving or clicking starts it over.
+//!
+//! A transcript waiting to be confirmed (see [`crate::confirm`]) never
+//! times out.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Deserialize;
+use tauri::Manager;
+
+use super::queue::WindowOp;
+use super::{PillState, PillWindowState};
+use crate::app_state::{self, Phase};
+use crate::settings::SettingsState;
+use crate::AppHandle;
+
+/// What the user did with the pill, as `pill_interaction` reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PillInteraction {
+    /// The mouse entered the pill.
+    Enter,
+    /// The mouse left it.
+    Leave,
+    Click,
+}
+
+#[derive(Default)]
+pub(super) struct AutoHide {
+    /// Bumped to cancel the timer in flight.
+    generation: AtomicU64,
+    /// Whether the mouse is over the pill.
+    hovering: AtomicBool,
+}
+
+/// How long the pill stays in `state`, or `None` if it stays.
+fn timeout(app: &AppHandle, state: PillState) -> Option<Duration> {
+    let settings = app.state::<SettingsState>().get().pill;
+    let ms = match state {
+        PillState::Transcript { .. } if app_state::phase(app) != Phase::AwaitingConfirmation => {
+            settings.transcript_timeout_ms
+        }
+        PillState::Error => settings.error_timeout_ms,
+        _ => 0,
+    };
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+/// Starts the timer for `state` over, or cancels it for a state that
+/// doesn't time out. Called for every state the pill is put into.
+pub(super) fn arm(app: &AppHandle, state: PillState) {
+    let auto_hide = &app.state::<PillWindowState>().auto_hide;
+    let generation = auto_hide.generation.fetch_add(1, Ordering::SeqCst) + 1;
+    if state == PillState::Hidden {
+        // The mouse can't still be over a pill that is gone
+        auto_hide.hovering.store(false, Ordering::SeqCst);
+    }
+    if auto_hide.hovering.load(Ordering::SeqCst) {
+        return;
+    }
+    let Some(delay) = timeout(app, state) else {
+        return;
+    };
+    let handle = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("pill-timeout".to_string())
+        .spawn(move || {
+            std::thread::sleep(delay);
+            let pill = handle.state::<PillWindowState>();
+            if pill.auto_hide.generation.load(Ordering::SeqCst) == generation {
+                pill.queue.push(&handle, WindowOp::Expire { generation });
+            }
+        });
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to start the pill timeout: {}", e);
+    }
+}
+
+/// Cancels the timer in flight, if any.
+pub(super) fn cancel(app: &AppHandle) {
+    let auto_hide = &app.state::<PillWindowState>().auto_hide;
+    auto_hide.generation.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Holds the timer or starts it over for `interaction`.
+pub(super) fn interact(app: &AppHandle, interaction: PillInteraction) {
+    let pill = app.state::<PillWindowState>();
+    let hovering = interaction == PillInteraction::Enter;
+    pill.auto_hide.hovering.store(hovering, Ordering::SeqCst);
+    // On the queue's worker, so it can't race a state change
+    pill.queue.push(app, WindowOp::Rearm);
+}
+
+/// Arms the timer again for the state the pill is in.
+pub(super) fn rearm(app: &AppHandle) -> Result<(), String> {
+    let state = *app.state::<PillWindowState>().state.lock().unwrap();
+    arm(app, state);
+    Ok(())
+}
+
+/// Hides the pill, unless the timer armed as `generation` was cancelled
+/// since: a state change queued before this has armed another one.
+pub(super) fn expire(app: &AppHandle, generation: u64) -> Result<(), String> {
+    let pill = app.state::<PillWindowState>();
+    if pill.auto_hide.generation.load(Ordering::SeqCst) != generation {
+        return Ok(());
+    }
+    let state = *pill.state.lock().unwrap();
+    tracing::debug!(?state, "Pill timed out; hiding it");
+    super::change_state(app, PillState::Hidden, true)?;
+    if state == PillState::Error && app_state::phase(app) == Phase::Error {
+        app_state::dismiss(app, "timed_out");
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Raw Win32 access for the pill window.
+//!
+//! Tauri has no opacity API, so the pill is made a layered window and its
+//! alpha set directly, like other toolkits fade their windows. Clicks
+//! outside the pill are watched with a low-level mouse hook, and the
+//! window is clipped to the pill with a window region for click-through.
+
+use std::ffi::c_void;
+use std::sync::OnceLock;
+
+/// `GWL_EXSTYLE`.
+const GWL_EXSTYLE: i32 = -20;
+/// `WS_EX_LAYERED`: the window may be partly transparent.
+const WS_EX_LAYERED: isize = 0x0008_0000;
+/// `LWA_ALPHA`: use the alpha argument of `SetLayeredWindowAttributes`.
+const LWA_ALPHA: u32 = 0x2;
+
+/// `WH_MOUSE_LL`.
+const WH_MOUSE_LL: i32 = 14;
+/// `WM_LBUTTONDOWN`, `WM_RBUTTONDOWN` and `WM_MBUTTONDOWN`.
+const MOUSE_DOWN_MESSAGES: [usize; 3] = [0x0201, 0x0204, 0x0207];
+
+type HookProc = unsafe extern "system" fn(i32, usize, isize) -> isize;
+
+#[link(name = "user32")]
+extern "system" {
+    fn SetWindowsHookExW(id: i32, hook: HookProc, module: *mut c_void, thread: u32) -> *mut c_void;
+    fn UnhookWindowsHookEx(hook: *mut c_void) -> i32;
+    fn CallNextHookEx(hook: *mut c_void, code: i32, wparam: usize, lparam: isize) -> isize;
+    fn GetWindowLongPtrW(hwnd: *mut c_void, index: i32) -> isize;
+    fn SetWindowLongPtrW(hwnd: *mut c_void, index: i32, value: isize) -> isize;
+    fn SetLayeredWindowAttributes(hwnd: *mut c_void, key: u32, alpha: u8, flags: u32) -> i32;
+    fn SetWindowRgn(hwnd: *mut c_void, region: *mut c_void, redraw: i32) -> i32;
+}
+
+/// Sets the opacity of `hwnd`, from 0 (invisible) to 1, making it a
+/// layered window first if needed.
+///
+/// # Safety
+///
+/// `hwnd` must be a live window, and this must run on the thread that owns
+/// it (the main thread).
+pub unsafe fn set_alpha(hwnd: *mut c_void, alpha: f64) {
+    let style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE);
+    if style & WS_EX_LAYERED == 0 {
+        SetWindowLongPtrW(hwnd, GWL_EXSTYLE, style | WS_EX_LAYERED);
+    }
+    let alpha = (alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+    SetLayeredWindowAttributes(hwnd, 0, alpha, LWA_ALPHA);
+}
+
+/// Called on every mouse-down while the hook is installed. There is one
+/// pill, so one handler for the life of the process.
+static MOUSE_DOWN: OnceLock<Box<dyn Fn() + Send + Sync>> = OnceLock::new();
+
+unsafe extern "system" fn mouse_hook(code: i32, wparam: usize, lparam: isize) -> isize {
+    if code >= 0 && MOUSE_DOWN_MESSAGES.contains(&wparam) {
+        if let Some(handler) = MOUSE_DOWN.get() {
+            handler();
+        }
+    }
+    CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam)
+}
+
+/// Calls `handler` on every mouse-down anywhere, until the returned hook is
+/// passed to [`remove_mouse_hook`]. Only the first handler ever passed is
+/// used. `None` if the hook can't be installed.
+///
+/// The handler runs inside the hook, which Windows gives very little time:
+/// it must hand the work off instead of doing it.
+///
+/// # Safety
+///
+/// Must run on a thread with a message loop (the main thread).
+pub unsafe fn add_mouse_hook(handler: impl Fn() + Send + Sync + 'static) -> Option<isize> {
+    let _ = MOUSE_DOWN.set(Box::new(handler));
+    let hook = SetWindowsHookExW(WH_MOUSE_LL, mouse_hook, std::ptr::null_mut(), 0);
+    (!hook.is_null()).then_some(hook as isize)
+}
+
+/// Removes a hook installed with [`add_mouse_hook`].
+///
+/// # Safety
+///
+/// `hook` must come from [`add_mouse_hook`] and not have been removed yet.
+pub unsafe fn remove_mouse_hook(hook: isize) {
+    UnhookWindowsHookEx(hook as *mut c_void);
+}
+
+/// `RGN_OR`.
+const RGN_OR: i32 = 2;
+
+#[link(name = "gdi32")]
+extern "system" {
+    fn CreateRectRgn(left: i32, top: i32, right: i32, bottom: i32) -> *mut c_void;
+    fn CreateRoundRectRgn(
+        left: i32,
+        top: i32,
+        right: i32,
+        bottom: i32,
+        width: i32,
+        height: i32,
+    ) -> *mut c_void;
+    fn CombineRgn(dest: *mut c_void, a: *mut c_void, b: *mut c_void, mode: i32) -> i32;
+    fn DeleteObject(object: *mut c_void) -> i32;
+}
+
+/// Limits `hwnd` to the union of `rects` (left, top, right, bottom and
+/// corner radius, in physical pixels relative to the window), so clicks
+/// everywhere else go to the windows below. Empty `rects` make the whole
+/// window clickable again. Returns whether it worked.
+///
+/// # Safety
+///
+/// `hwnd` must be a live window, and this must run on the thread that owns
+/// it (the main thread).
+pub unsafe fn set_region(hwnd: *mut c_void, rects: &[(i32, i32, i32, i32, i32)]) -> bool {
+    if rects.is_empty() {
+        return SetWindowRgn(hwnd, std::ptr::null_mut(), 1) != 0;
+    }
+    let region = CreateRectRgn(0, 0, 0, 0);
+    if region.is_null() {
+        return false;
+    }
+    for &(left, top, right, bottom, radius) in rects {
+        let rect = CreateRoundRectRgn(left, top, right + 1, bottom + 1, radius * 2, radius * 2);
+        if !rect.is_null() {
+            CombineRgn(region, region, rect, RGN_OR);
+            DeleteObject(rect);
+        }
+    }
+    // The window owns the region from here on
+    if SetWindowRgn(hwnd, region, 1) == 0 {
+        DeleteObject(region);
+        return false;
+    }
+    true
+}
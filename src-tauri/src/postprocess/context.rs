@@ -0,0 +1,174 @@
+//! Context-aware formatting stage.
+//!
+//! Adjusts the edges of a transcript so it fits into the text around the
+//! caret: capitalize at the start of a field or sentence, lowercase
+//! mid-sentence, and add or drop spaces so words don't run together.
+//!
+//! The provider formats every utterance as a standalone sentence ("Hello
+//! there."), which is right for an empty field but wrong after "I said ".
+//! Without context (no Accessibility access) the text is left as-is.
+
+use serde::Serialize;
+
+/// Text surrounding the caret in the target field.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TextContext {
+    /// Text immediately before the caret (or selection).
+    pub before: String,
+    /// Text immediately after the caret (or selection).
+    pub after: String,
+}
+
+/// Common words the provider only capitalizes because they start its
+/// sentence. Any other capitalized first word may be a name ("Paris",
+/// "Alice"), or "I", and keeps its capital mid-sentence.
+const SENTENCE_STARTERS: &[&str] = &[
+    "a", "about", "actually", "after", "again", "all", "also", "an", "and", "any", "are", "as",
+    "at", "be", "because", "before", "but", "by", "can", "could", "did", "do", "does", "for",
+    "from", "had", "has", "have", "he", "her", "here", "his", "how", "if", "in", "is", "it",
+    "it's", "its", "just", "let's", "maybe", "my", "no", "not", "now", "of", "ok", "okay", "on",
+    "or", "our", "please", "she", "should", "so", "some", "thank", "thanks", "that", "the",
+    "their", "then", "there", "these", "they", "this", "those", "to", "too", "was", "we", "we're",
+    "well", "were", "what", "when", "where", "which", "while", "who", "why", "will", "with",
+    "would", "yes", "you", "you're", "your",
+];
+
+/// Applies context-aware formatting, returning the adjusted text and whether
+/// anything changed.
+pub fn apply(text: &str, context: Option<&TextContext>) -> (String, bool) {
+    let Some(context) = context else {
+        return (text.to_string(), false);
+    };
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return (text.to_string(), false);
+    }
+
+    let mut output = if starts_sentence(&context.before) {
+        capitalize_first(trimmed)
+    } else {
+        lowercase_first_word(trimmed)
+    };
+
+    // Mid-sentence insertion: the provider's closing period would split the
+    // user's sentence in two ("I said hello. and left")
+    if context.after.starts_with(|c: char| c.is_lowercase()) && output.ends_with('.') {
+        output.pop();
+    }
+
+    if needs_leading_space(&context.before, &output) {
+        output.insert(0, ' ');
+    }
+    if needs_trailing_space(&output, &context.after) {
+        output.push(' ');
+    }
+
+    let changed = output != text;
+    (output, changed)
+}
+
+/// Whether text inserted after `before` begins a new sentence.
+fn starts_sentence(before: &str) -> bool {
+    if before.ends_with('\n') {
+        return true;
+    }
+    match before.trim_end().chars().last() {
+        None => true,
+        Some(c) => matches!(c, '.' | '!' | '?' | '…'),
+    }
+}
+
+fn capitalize_first(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Lowercases the first letter if the first word is one of
+/// [`SENTENCE_STARTERS`] written as the provider starts a sentence
+/// ("The"), leaving names, "I" and acronyms ("NASA", "IT") alone.
+fn lowercase_first_word(text: &str) -> String {
+    let first_word = text
+        .split(|c: char| c.is_whitespace() || matches!(c, ',' | '.' | '!' | '?'))
+        .next()
+        .unwrap_or_default();
+
+    let mut rest = first_word.chars().skip(1);
+    let sentence_cased =
+        first_word.starts_with(char::is_uppercase) && rest.all(|c| !c.is_uppercase());
+    let word = first_word.to_lowercase().replace('\u{2019}', "'");
+    if !sentence_cased || !SENTENCE_STARTERS.contains(&word.as_str()) {
+        return text.to_string();
+    }
+
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn needs_leading_space(before: &str, text: &str) -> bool {
+    let Some(prev) = before.chars().last() else {
+        return false;
+    };
+    let starts_with_punctuation =
+        text.starts_with(|c: char| matches!(c, ',' | '.' | '!' | '?' | ':' | ';'));
+    !prev.is_whitespace()
+        && !matches!(prev, '(' | '[' | '{' | '"' | '\'' | '“' | '‘')
+        && !starts_with_punctuation
+}
+
+fn needs_trailing_space(text: &str, after: &str) -> bool {
+    let Some(next) = after.chars().next() else {
+        return false;
+    };
+    !text.ends_with(char::is_whitespace)
+        && (next.is_alphanumeric() || matches!(next, '(' | '"' | '“'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fit(text: &str, before: &str) -> String {
+        let context = TextContext {
+            before: before.to_string(),
+            after: String::new(),
+        };
+        apply(text, Some(&context)).0
+    }
+
+    #[test]
+    fn common_words_are_lowercased_mid_sentence() {
+        assert_eq!(fit("The meeting moved.", "I said "), "the meeting moved.");
+        assert_eq!(fit("It\u{2019}s fine.", "and "), "it\u{2019}s fine.");
+    }
+
+    #[test]
+    fn names_keep_their_capital_mid_sentence() {
+        assert_eq!(fit("Paris is lovely.", "I said "), "Paris is lovely.");
+        assert_eq!(fit("Alice will call.", "and "), "Alice will call.");
+    }
+
+    #[test]
+    fn i_keeps_its_capital_mid_sentence() {
+        assert_eq!(fit("I think so.", "and "), "I think so.");
+        assert_eq!(fit("I'm late.", "and "), "I'm late.");
+        assert_eq!(fit("I\u{2019}ll go.", "so "), "I\u{2019}ll go.");
+    }
+
+    #[test]
+    fn acronyms_keep_their_capitals_mid_sentence() {
+        assert_eq!(fit("NASA called.", "and "), "NASA called.");
+        assert_eq!(fit("IT is down.", "and "), "IT is down.");
+    }
+
+    #[test]
+    fn sentence_starts_are_capitalized() {
+        assert_eq!(fit("hello there.", "Done. "), "Hello there.");
+        assert_eq!(fit("hello there.", ""), "Hello there.");
+    }
+}
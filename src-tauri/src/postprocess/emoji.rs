@@ -0,0 +1,273 @@
+//! Emoji trigger-word stage.
+//!
+//! Converts spoken emoji requests into Unicode emoji:
+//!
+//! - `"<name> emoji"` → the emoji with that name ("thumbs up emoji" → 👍,
+//!   "partying face emoji" → 🥳)
+//! - Configurable bare shortcuts ("smiley face" → 🙂) that need no keyword
+//!
+//! # Architecture Decision
+//!
+//! Matching is anchored to the trailing "emoji" keyword: we only look for a
+//! name in the few words right before it, preferring the longest match. This
+//! keeps ordinary sentences ("I gave it a thumbs up") untouched, and unknown
+//! names are left exactly as spoken rather than guessing at a near match.
+//!
+//! The name table is built once from the bundled emoji data: CLDR names
+//! ("face with tears of joy") plus GitHub-style shortcodes ("joy",
+//! "thumbsup"), including skin tone variants. User aliases take precedence.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Keyword that must follow an emoji name.
+const KEYWORD: &str = "emoji";
+
+/// Longest name, in words, considered before the keyword. CLDR names with
+/// skin tones run to about this length ("thumbs up medium dark skin tone").
+const MAX_NAME_WORDS: usize = 8;
+
+/// Configuration for the emoji stage.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmojiConfig {
+    pub enabled: bool,
+    /// User-defined names usable before the keyword ("ship it" → 🚀).
+    pub aliases: BTreeMap<String, String>,
+    /// Phrases converted without the keyword ("smiley face" → 🙂).
+    pub shortcuts: BTreeMap<String, String>,
+}
+
+impl Default for EmojiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            aliases: BTreeMap::new(),
+            shortcuts: [
+                ("smiley face", "🙂"),
+                ("sad face", "🙁"),
+                ("winky face", "😉"),
+            ]
+            .into_iter()
+            .map(|(phrase, emoji)| (phrase.to_string(), emoji.to_string()))
+            .collect(),
+        }
+    }
+}
+
+impl EmojiConfig {
+    /// Adds or replaces a user alias.
+    pub fn set_alias(&mut self, name: &str, emoji: &str) -> Result<(), String> {
+        let name = normalize_phrase(name).ok_or("alias name must not be empty")?;
+        if name == KEYWORD {
+            return Err(format!("\"{}\" cannot be used as an alias", KEYWORD));
+        }
+        self.aliases.insert(name, resolve_target(emoji)?);
+        Ok(())
+    }
+
+    /// Removes a user alias. Removing a missing alias is not an error.
+    pub fn remove_alias(&mut self, name: &str) {
+        if let Some(name) = normalize_phrase(name) {
+            self.aliases.remove(&name);
+        }
+    }
+
+    /// Adds or replaces a bare shortcut phrase.
+    pub fn set_shortcut(&mut self, phrase: &str, emoji: &str) -> Result<(), String> {
+        let phrase = normalize_phrase(phrase).ok_or("shortcut phrase must not be empty")?;
+        if phrase.split(' ').count() < 2 {
+            // Single-word shortcuts would fire on ordinary speech
+            return Err("shortcuts must be at least two words".to_string());
+        }
+        self.shortcuts.insert(phrase, resolve_target(emoji)?);
+        Ok(())
+    }
+
+    /// Removes a bare shortcut. Removing a missing shortcut is not an error.
+    pub fn remove_shortcut(&mut self, phrase: &str) {
+        if let Some(phrase) = normalize_phrase(phrase) {
+            self.shortcuts.remove(&phrase);
+        }
+    }
+
+    fn lookup_name(&self, name: &str) -> Option<&str> {
+        self.aliases
+            .get(name)
+            .map(String::as_str)
+            .or_else(|| name_table().get(name).copied())
+    }
+}
+
+/// Applies the stage, returning the converted text and whether any emoji
+/// were inserted.
+pub fn apply(text: &str, config: &EmojiConfig) -> (String, bool) {
+    if !config.enabled {
+        return (text.to_string(), false);
+    }
+
+    let (text, keyword_hits) = replace_keyword_phrases(text, config);
+    let (text, shortcut_hits) = replace_shortcuts(&text, config);
+    (text, keyword_hits || shortcut_hits)
+}
+
+/// Replaces `"<name> emoji"` phrases.
+fn replace_keyword_phrases(text: &str, config: &EmojiConfig) -> (String, bool) {
+    let words = word_ranges(text);
+    let mut replacements: Vec<(Range<usize>, &str)> = Vec::new();
+    // Words before this index already belong to a replacement
+    let mut first_free = 0;
+
+    for (keyword_index, keyword) in words.iter().enumerate() {
+        if !text[keyword.clone()].eq_ignore_ascii_case(KEYWORD) {
+            continue;
+        }
+
+        let longest = MAX_NAME_WORDS.min(keyword_index - first_free);
+        let found = (1..=longest).rev().find_map(|len| {
+            let first = keyword_index - len;
+            let name = normalize_phrase(&text[words[first].start..words[keyword_index - 1].end])?;
+            config.lookup_name(&name).map(|emoji| (first, emoji))
+        });
+
+        if let Some((first, emoji)) = found {
+            replacements.push((words[first].start..keyword.end, emoji));
+            first_free = keyword_index + 1;
+        }
+    }
+
+    splice(text, replacements)
+}
+
+/// Replaces bare shortcut phrases anywhere in the text.
+fn replace_shortcuts(text: &str, config: &EmojiConfig) -> (String, bool) {
+    if config.shortcuts.is_empty() {
+        return (text.to_string(), false);
+    }
+
+    let longest = config
+        .shortcuts
+        .keys()
+        .map(|phrase| phrase.split(' ').count())
+        .max()
+        .unwrap_or(0);
+    let words = word_ranges(text);
+    let mut replacements: Vec<(Range<usize>, &str)> = Vec::new();
+    let mut index = 0;
+
+    while index < words.len() {
+        let found = (1..=longest.min(words.len() - index))
+            .rev()
+            .find_map(|len| {
+                let last = index + len - 1;
+                let phrase = normalize_phrase(&text[words[index].start..words[last].end])?;
+                config
+                    .shortcuts
+                    .get(&phrase)
+                    .map(|emoji| (last, emoji.as_str()))
+            });
+
+        match found {
+            Some((last, emoji)) => {
+                replacements.push((words[index].start..words[last].end, emoji));
+                index = last + 1;
+            }
+            None => index += 1,
+        }
+    }
+
+    splice(text, replacements)
+}
+
+/// Byte ranges of the word tokens in `text`.
+fn word_ranges(text: &str) -> Vec<Range<usize>> {
+    text.split_word_bound_indices()
+        .filter(|(_, segment)| segment.starts_with(char::is_alphanumeric))
+        .map(|(start, segment)| start..start + segment.len())
+        .collect()
+}
+
+/// Applies non-overlapping, in-order replacements.
+fn splice(text: &str, replacements: Vec<(Range<usize>, &str)>) -> (String, bool) {
+    if replacements.is_empty() {
+        return (text.to_string(), false);
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (range, emoji) in replacements {
+        output.push_str(&text[cursor..range.start]);
+        output.push_str(emoji);
+        cursor = range.end;
+    }
+    output.push_str(&text[cursor..]);
+
+    (output, true)
+}
+
+/// Normalizes a spoken or configured name for lookup: lowercase, hyphens,
+/// underscores and colons as spaces, single spaces between words.
+///
+/// Returns `None` for empty input or text containing sentence punctuation,
+/// which means the candidate span crossed a clause boundary.
+fn normalize_phrase(phrase: &str) -> Option<String> {
+    if phrase.contains(['.', ',', '!', '?', ';']) {
+        return None;
+    }
+
+    let normalized = phrase
+        .to_lowercase()
+        .replace(['-', '_', ':'], " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    (!normalized.is_empty()).then_some(normalized)
+}
+
+/// Accepts either a literal emoji or a known emoji name as an alias target.
+fn resolve_target(target: &str) -> Result<String, String> {
+    let target = target.trim();
+    if target.is_empty() {
+        return Err("emoji must not be empty".to_string());
+    }
+    if emojis::get(target).is_some() {
+        return Ok(target.to_string());
+    }
+
+    normalize_phrase(target)
+        .and_then(|name| name_table().get(name.as_str()).copied())
+        .map(str::to_string)
+        .ok_or_else(|| format!("\"{}\" is not a known emoji", target))
+}
+
+/// Lowercase name/shortcode → emoji, built on first use.
+fn name_table() -> &'static HashMap<String, &'static str> {
+    static TABLE: OnceLock<HashMap<String, &'static str>> = OnceLock::new();
+
+    TABLE.get_or_init(|| {
+        let mut table = HashMap::new();
+        let mut add = |emoji: &'static emojis::Emoji| {
+            let names = std::iter::once(emoji.name()).chain(emoji.shortcodes());
+            for name in names {
+                if let Some(name) = normalize_phrase(name) {
+                    // First entry wins so base emoji beat later variants
+                    table.entry(name).or_insert(emoji.as_str());
+                }
+            }
+        };
+
+        for emoji in emojis::iter() {
+            add(emoji);
+            if let Some(tones) = emoji.skin_tones() {
+                tones.for_each(&mut add);
+            }
+        }
+
+        table
+    })
+}
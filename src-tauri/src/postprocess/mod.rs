@@ -0,0 +1,401 @@
+//! Transcript post-processing pipeline.
+//!
+//! Raw provider output goes through a sequence of stages before it is pasted.
+//! Each stage is a pure function over the text plus its own configuration, so
+//! stages can be reordered, toggled and reasoned about independently.
+//!
+//! # Stages
+//!
+//! - [`profanity`]: Optional masking/removal of profanity (opt-in)
+//! - [`emoji`]: Spoken emoji names ("thumbs up emoji") to Unicode emoji
+//! - [`context`]: Capitalization/spacing fitted to the text around the caret
+//!
+//! The frontend calls [`process_transcript`] with the final transcript and
+//! pastes the returned text; the returned metadata tells the pill which stages
+//! changed anything so it can show a subtle indicator.
+//!
+//! Voice commands (see [`crate::command_mode`]) skip every stage: they are
+//! matched against spoken phrases, which the stages would only get in the
+//! way of.
+
+pub mod context;
+pub mod emoji;
+pub mod profanity;
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager, State};
+
+use crate::app_state::{self, Phase, SessionError, SessionEvent};
+use crate::cancellation;
+use crate::command_mode;
+use crate::dictation::DictationState;
+use crate::logging;
+use crate::recovery::RecoveryState;
+use crate::settings;
+use crate::timings::{self, Stage};
+use crate::AppHandle;
+use context::TextContext;
+use emoji::EmojiConfig;
+use profanity::{ProfanityConfig, ProfanityMode};
+
+/// Configuration for every post-processing stage.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostProcessConfig {
+    pub profanity: ProfanityConfig,
+    pub emoji: EmojiConfig,
+}
+
+/// Managed state for the pipeline.
+#[derive(Default)]
+pub struct PostProcessState {
+    /// Live post-processing configuration.
+    pub config: Mutex<PostProcessConfig>,
+    /// Text around the caret of the target field, captured when the
+    /// recording pill was shown and consumed by the next transcript.
+    pub context: Mutex<Option<TextContext>>,
+    /// The dictation whose transcript was processed last, and the context
+    /// it was processed with, for [`reprocess`].
+    pub processed: Mutex<Option<(String, Option<TextContext>)>>,
+}
+
+/// Output of the pipeline: the text to paste plus per-stage metadata.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProcessedTranscript {
+    pub text: String,
+    /// Whether the profanity filter masked or removed anything.
+    pub profanity_filtered: bool,
+    /// Whether any spoken emoji names were converted.
+    pub emoji_inserted: bool,
+    /// Whether the text was adjusted to fit the surrounding field content.
+    pub context_applied: bool,
+    /// Whether the text is voice commands, run rather than pasted.
+    pub command: bool,
+}
+
+/// Runs `text` through every enabled stage in order.
+///
+/// `context` is the text around the caret when known; without it the
+/// context stage is skipped.
+pub fn run(
+    text: &str,
+    config: &PostProcessConfig,
+    context: Option<&TextContext>,
+) -> ProcessedTranscript {
+    let (text, profanity_filtered) = profanity::apply(text, &config.profanity);
+    let (text, emoji_inserted) = emoji::apply(&text, &config.emoji);
+    // Context formatting runs last so it sees the final leading/trailing words
+    let (text, context_applied) = context::apply(&text, context);
+
+    ProcessedTranscript {
+        text,
+        profanity_filtered,
+        emoji_inserted,
+        context_applied,
+        command: false,
+    }
+}
+
+/// Runs the post-processing pipeline over a final transcript.
+///
+/// Called by the frontend after speech ends and before `copy_and_paste_text`.
+/// Consumes the text context captured when the pill was shown, so a stale
+/// context never leaks into the next dictation. The raw transcript,
+/// `provider` and `language` are remembered for the history entry written
+/// after the paste.
+///
+/// A transcript starting with `command_mode.switch_word` switches the
+/// dictation to command mode; its commands, and those of a dictation
+/// already in command mode, come back unprocessed, with `command` set.
+///
+/// The processed text is saved for crash recovery until the paste completes.
+/// The mic is closed by now; a dictation still recording (a file
+/// transcription, which has no `stop_recording`) moves to `Transcribing`
+/// and announces `recording:stopped`.
+///
+/// # Errors
+///
+/// Returns [`SessionError::StaleSession`] if the dictation `session_id`
+/// isn't the current one, e.g. because it was cancelled, also while it was
+/// processed.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip(app, state, dictation, recovery, text))]
+pub fn process_transcript(
+    app: AppHandle,
+    state: State<'_, PostProcessState>,
+    dictation: State<'_, DictationState>,
+    recovery: State<'_, RecoveryState>,
+    text: String,
+    provider: Option<String>,
+    language: Option<String>,
+    session_id: String,
+) -> Result<ProcessedTranscript, SessionError> {
+    if app_state::phase(&app) == Phase::Recording {
+        app_state::transition_session(&app, &session_id, Phase::Transcribing, "speech_ended")?;
+        let _ = app.emit(
+            "recording:stopped",
+            SessionEvent {
+                session_id: Some(session_id.clone()),
+            },
+        );
+    } else {
+        app_state::check_session(&app, &session_id)?;
+    }
+    let cancel = cancellation::token(&app, &session_id)?;
+    // Timed from `stop_recording`; without one the recording ends now
+    let timed = dictation.0.lock().unwrap().as_mut().map(|dictation| {
+        let timed = match dictation.stopped_at {
+            Some(stopped_at) => (Stage::Transcription, stopped_at.elapsed()),
+            None => (Stage::Recording, dictation.stop_recording()),
+        };
+        dictation.finish_transcription(&text, provider, language);
+        timed
+    });
+    if let Some((stage, elapsed)) = timed {
+        timings::record(&app, Some(&session_id), stage, elapsed);
+    }
+
+    let context = state.context.lock().unwrap().take();
+    let switch_word = app
+        .state::<settings::SettingsState>()
+        .get()
+        .command_mode
+        .switch_word;
+    let commands = dictation.0.lock().unwrap().as_mut().and_then(|dictation| {
+        match command_mode::strip_switch_word(&text, &switch_word) {
+            Some(commands) => {
+                dictation.command_mode = true;
+                Some(commands)
+            }
+            None => dictation.command_mode.then(|| text.clone()),
+        }
+    });
+    if let Some(commands) = commands {
+        cancel.check(&app)?;
+        tracing::info!(commands = %logging::transcript(&commands), "Transcript is voice commands");
+        return Ok(ProcessedTranscript {
+            text: commands,
+            profanity_filtered: false,
+            emoji_inserted: false,
+            context_applied: false,
+            command: true,
+        });
+    }
+
+    *state.processed.lock().unwrap() = Some((session_id.clone(), context.clone()));
+    let config = state.config.lock().unwrap().clone();
+    let stage = timings::stage(&app, Some(&session_id), Stage::PostProcess);
+    let processed = run(&text, &config, context.as_ref());
+    drop(stage);
+    tracing::info!(
+        raw = %logging::transcript(&text),
+        processed = %logging::transcript(&processed.text),
+        processed.profanity_filtered,
+        processed.emoji_inserted,
+        processed.context_applied,
+        "Transcript processed"
+    );
+
+    // Not worth recovering once cancelled
+    cancel.check(&app)?;
+    if let Err(e) = recovery.save_pending(&processed.text) {
+        tracing::warn!("Failed to save transcript for recovery: {}", e);
+    }
+    Ok(processed)
+}
+
+/// Runs the rewrite of the dictation `session_id`'s processed transcript
+/// through the pipeline again, with the context the transcript had, so the
+/// model can't bring back what the stages filtered or converted. What comes
+/// out replaces the transcript saved for crash recovery.
+pub fn reprocess(app: &AppHandle, session_id: &str, text: &str) -> ProcessedTranscript {
+    let state = app.state::<PostProcessState>();
+    let context = context_of(&state.processed.lock().unwrap(), session_id);
+    let config = state.config.lock().unwrap().clone();
+    let processed = run(text, &config, context.as_ref());
+    tracing::info!(
+        rewritten = %logging::transcript(text),
+        processed = %logging::transcript(&processed.text),
+        "Rewrite processed"
+    );
+    if let Err(e) = app.state::<RecoveryState>().save_pending(&processed.text) {
+        tracing::warn!("Failed to save transcript for recovery: {}", e);
+    }
+    processed
+}
+
+/// The context `session_id`'s transcript was processed with, if `processed`
+/// is about it.
+fn context_of(
+    processed: &Option<(String, Option<TextContext>)>,
+    session_id: &str,
+) -> Option<TextContext> {
+    match processed {
+        Some((id, context)) if id == session_id => context.clone(),
+        _ => None,
+    }
+}
+
+/// Returns the current profanity filter configuration.
+#[tauri::command]
+pub fn get_profanity_filter(state: State<'_, PostProcessState>) -> ProfanityConfig {
+    state.config.lock().unwrap().profanity.clone()
+}
+
+/// Sets the profanity filter mode (`keep`, `mask` or `remove`).
+///
+/// # Errors
+///
+/// Returns an error if the settings cannot be saved.
+#[tauri::command]
+pub fn set_profanity_filter_mode(app: AppHandle, mode: ProfanityMode) -> Result<(), String> {
+    update_config(&app, |config| {
+        config.profanity.mode = mode;
+        Ok(())
+    })
+}
+
+/// Adds a word to the profanity filter.
+///
+/// # Errors
+///
+/// Returns an error if the word is empty or contains more than one word, or
+/// if the settings cannot be saved.
+#[tauri::command]
+pub fn add_profanity_word(app: AppHandle, word: String) -> Result<(), String> {
+    update_config(&app, |config| config.profanity.add_word(&word))
+}
+
+/// Removes a word from the profanity filter (built-in words are allow-listed).
+///
+/// # Errors
+///
+/// Returns an error if the word is empty or contains more than one word, or
+/// if the settings cannot be saved.
+#[tauri::command]
+pub fn remove_profanity_word(app: AppHandle, word: String) -> Result<(), String> {
+    update_config(&app, |config| config.profanity.remove_word(&word))
+}
+
+/// Returns the current emoji stage configuration.
+#[tauri::command]
+pub fn get_emoji_config(state: State<'_, PostProcessState>) -> EmojiConfig {
+    state.config.lock().unwrap().emoji.clone()
+}
+
+/// Enables or disables emoji trigger words.
+///
+/// # Errors
+///
+/// Returns an error if the settings cannot be saved.
+#[tauri::command]
+pub fn set_emoji_enabled(app: AppHandle, enabled: bool) -> Result<(), String> {
+    update_config(&app, |config| {
+        config.emoji.enabled = enabled;
+        Ok(())
+    })
+}
+
+/// Adds or replaces a user emoji alias, used as `"<name> emoji"`.
+///
+/// `emoji` may be a literal emoji or the name of a known one.
+///
+/// # Errors
+///
+/// Returns an error if the name is empty or the emoji is unknown, or if the
+/// settings cannot be saved.
+#[tauri::command]
+pub fn set_emoji_alias(app: AppHandle, name: String, emoji: String) -> Result<(), String> {
+    update_config(&app, |config| config.emoji.set_alias(&name, &emoji))
+}
+
+/// Removes a user emoji alias.
+///
+/// # Errors
+///
+/// Returns an error if the settings cannot be saved.
+#[tauri::command]
+pub fn remove_emoji_alias(app: AppHandle, name: String) -> Result<(), String> {
+    update_config(&app, |config| {
+        config.emoji.remove_alias(&name);
+        Ok(())
+    })
+}
+
+/// Adds or replaces a bare shortcut phrase converted without the keyword.
+///
+/// # Errors
+///
+/// Returns an error if the phrase is shorter than two words or the emoji is
+/// unknown, or if the settings cannot be saved.
+#[tauri::command]
+pub fn set_emoji_shortcut(app: AppHandle, phrase: String, emoji: String) -> Result<(), String> {
+    update_config(&app, |config| config.emoji.set_shortcut(&phrase, &emoji))
+}
+
+/// Removes a bare shortcut phrase.
+///
+/// # Errors
+///
+/// Returns an error if the settings cannot be saved.
+#[tauri::command]
+pub fn remove_emoji_shortcut(app: AppHandle, phrase: String) -> Result<(), String> {
+    update_config(&app, |config| {
+        config.emoji.remove_shortcut(&phrase);
+        Ok(())
+    })
+}
+
+/// Changes the post-processing configuration through the settings store, so
+/// the change is persisted.
+fn update_config(
+    app: &AppHandle,
+    f: impl FnOnce(&mut PostProcessConfig) -> Result<(), String>,
+) -> Result<(), String> {
+    settings::update(app, |settings| f(&mut settings.post_processing)).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masking() -> PostProcessConfig {
+        PostProcessConfig {
+            profanity: ProfanityConfig {
+                mode: ProfanityMode::Mask,
+                ..ProfanityConfig::default()
+            },
+            ..PostProcessConfig::default()
+        }
+    }
+
+    #[test]
+    fn rewritten_profanity_is_masked_again() {
+        // A rewrite bringing back a word the first pass masked
+        let processed = run("that was shit", &masking(), None);
+        assert_eq!(processed.text, "that was s***");
+        assert!(processed.profanity_filtered);
+    }
+
+    #[test]
+    fn processing_twice_changes_nothing() {
+        let config = masking();
+        let once = run("well shit, thumbs up emoji", &config, None);
+        let twice = run(&once.text, &config, None);
+        assert_eq!(twice.text, once.text);
+        assert!(!twice.profanity_filtered);
+        assert!(!twice.emoji_inserted);
+    }
+
+    #[test]
+    fn context_is_only_reused_for_its_dictation() {
+        let context = TextContext::default();
+        let processed = Some(("a".to_string(), Some(context.clone())));
+        assert_eq!(context_of(&processed, "a"), Some(context));
+        assert_eq!(context_of(&processed, "b"), None);
+        assert_eq!(context_of(&None, "a"), None);
+    }
+}
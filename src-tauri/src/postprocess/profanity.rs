@@ -0,0 +1,277 @@
+//! Profanity filter stage.
+//!
+//! Masks or removes profanity from a transcript before it is pasted, for
+//! users dictating into work chats.
+//!
+//! # Architecture Decision
+//!
+//! Matching is token-based: the text is split on Unicode word boundaries and
+//! each word is compared against the word list as a whole. Substring matching
+//! would mangle innocent words that happen to contain a listed word (the
+//! "Scunthorpe problem" — "Scunthorpe", "assume", "cocktail").
+//!
+//! A listed root also matches a small set of inflections (plurals, `-ing`,
+//! `-ed`, ...) and possessives (`'s`), so the list stays short while "fucking"
+//! or "bitch's" are still caught.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Built-in word list. Entries are lowercase roots; inflections are matched
+/// via [`INFLECTION_SUFFIXES`].
+const BUILT_IN_WORDS: &[&str] = &[
+    "asshole",
+    "bastard",
+    "bitch",
+    "bullshit",
+    "cock",
+    "crap",
+    "cunt",
+    "damn",
+    "dick",
+    "dickhead",
+    "douche",
+    "douchebag",
+    "fuck",
+    "fucker",
+    "goddamn",
+    "motherfucker",
+    "piss",
+    "prick",
+    "shit",
+    "shitty",
+    "slut",
+    "twat",
+    "wanker",
+    "whore",
+];
+
+/// Suffixes that turn a listed root into a variant that should also match.
+const INFLECTION_SUFFIXES: &[&str] = &["s", "es", "ed", "ing", "in"];
+
+/// Possessive endings stripped before matching (straight and curly apostrophe).
+const POSSESSIVE_SUFFIXES: &[&str] = &["'s", "\u{2019}s", "'", "\u{2019}"];
+
+/// What to do with a matched word.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfanityMode {
+    /// Leave the transcript untouched (filter disabled).
+    #[default]
+    Keep,
+    /// Keep the first letter and replace the rest with `*` ("f***").
+    Mask,
+    /// Drop the word entirely, tidying up the surrounding whitespace.
+    Remove,
+}
+
+/// User-facing configuration for the profanity filter.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfanityConfig {
+    pub mode: ProfanityMode,
+    /// Words the user added on top of the built-in list.
+    pub extra_words: BTreeSet<String>,
+    /// Built-in words the user wants to allow.
+    pub allowed_words: BTreeSet<String>,
+}
+
+impl ProfanityConfig {
+    /// Adds a word to the filter, undoing a previous removal if needed.
+    pub fn add_word(&mut self, word: &str) -> Result<(), String> {
+        let word = normalize_user_word(word)?;
+        self.allowed_words.remove(&word);
+        if !BUILT_IN_WORDS.contains(&word.as_str()) {
+            self.extra_words.insert(word);
+        }
+        Ok(())
+    }
+
+    /// Removes a word from the filter, whether built-in or user-added.
+    pub fn remove_word(&mut self, word: &str) -> Result<(), String> {
+        let word = normalize_user_word(word)?;
+        self.extra_words.remove(&word);
+        if BUILT_IN_WORDS.contains(&word.as_str()) {
+            self.allowed_words.insert(word);
+        }
+        Ok(())
+    }
+
+    fn is_listed(&self, root: &str) -> bool {
+        if self.allowed_words.contains(root) {
+            return false;
+        }
+        BUILT_IN_WORDS.contains(&root) || self.extra_words.contains(root)
+    }
+
+    /// Returns the byte length of the profane part of `word` (excluding any
+    /// possessive suffix), or `None` when the word is clean.
+    fn match_len(&self, word: &str) -> Option<usize> {
+        let lower = word.to_lowercase();
+        // Lowercasing can change byte lengths for some scripts; only trust the
+        // offsets when it didn't
+        if lower.len() != word.len() {
+            return None;
+        }
+
+        let core = POSSESSIVE_SUFFIXES
+            .iter()
+            .find_map(|suffix| lower.strip_suffix(suffix))
+            .unwrap_or(&lower);
+
+        let matches = self.is_listed(core)
+            || INFLECTION_SUFFIXES.iter().any(|suffix| {
+                core.strip_suffix(suffix)
+                    .is_some_and(|root| !root.is_empty() && self.is_listed(root))
+            });
+
+        matches.then_some(core.len())
+    }
+}
+
+/// Applies the filter to `text`, returning the filtered text and whether
+/// anything was changed.
+pub fn apply(text: &str, config: &ProfanityConfig) -> (String, bool) {
+    if config.mode == ProfanityMode::Keep {
+        return (text.to_string(), false);
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut modified = false;
+    let mut just_removed = false;
+
+    for segment in text.split_word_bounds() {
+        if just_removed {
+            just_removed = false;
+            if segment.chars().all(char::is_whitespace) {
+                // Collapse the gap left by the removed word
+                if output.is_empty() || output.ends_with(char::is_whitespace) {
+                    continue;
+                }
+            } else if !segment.starts_with(char::is_alphanumeric) {
+                // The removed word was followed by punctuation: don't leave a
+                // dangling space before it ("that's shit." → "that's.")
+                output.truncate(output.trim_end().len());
+            }
+        }
+
+        let Some(len) = config.match_len(segment) else {
+            output.push_str(segment);
+            continue;
+        };
+
+        modified = true;
+        match config.mode {
+            ProfanityMode::Mask => {
+                let (core, suffix) = segment.split_at(len);
+                let mut graphemes = core.graphemes(true);
+                if let Some(first) = graphemes.next() {
+                    output.push_str(first);
+                }
+                output.extend(graphemes.map(|_| '*'));
+                output.push_str(suffix);
+            }
+            ProfanityMode::Remove => just_removed = true,
+            ProfanityMode::Keep => unreachable!("handled by the early return"),
+        }
+    }
+
+    if just_removed {
+        output.truncate(output.trim_end().len());
+    }
+
+    (output, modified)
+}
+
+/// Validates and normalizes a word supplied through a settings command.
+fn normalize_user_word(word: &str) -> Result<String, String> {
+    let word = word.trim().to_lowercase();
+    if word.is_empty() {
+        return Err("word must not be empty".to_string());
+    }
+    if word.split_word_bounds().count() != 1 {
+        return Err(format!("\"{}\" must be a single word", word));
+    }
+    Ok(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: ProfanityMode) -> ProfanityConfig {
+        ProfanityConfig {
+            mode,
+            ..ProfanityConfig::default()
+        }
+    }
+
+    fn mask(text: &str) -> String {
+        apply(text, &config(ProfanityMode::Mask)).0
+    }
+
+    #[test]
+    fn words_containing_a_listed_word_are_left_alone() {
+        let text = "Scunthorpe, assume the cocktail in the cockpit; Dickens \
+                    had shiitake at the Hancock class in Essex, passing the scrap";
+        assert_eq!(
+            apply(text, &config(ProfanityMode::Mask)),
+            (text.to_string(), false)
+        );
+        assert_eq!(
+            apply(text, &config(ProfanityMode::Remove)),
+            (text.to_string(), false)
+        );
+    }
+
+    #[test]
+    fn inflections_are_masked() {
+        assert_eq!(mask("fucking"), "f******");
+        assert_eq!(mask("fucked fucks"), "f***** f****");
+        assert_eq!(mask("bitches"), "b******");
+        assert_eq!(mask("pissed"), "p*****");
+        assert_eq!(mask("damnin"), "d*****");
+    }
+
+    #[test]
+    fn possessives_keep_their_ending() {
+        assert_eq!(mask("the bitch's car"), "the b****'s car");
+        assert_eq!(mask("the bastard\u{2019}s car"), "the b******\u{2019}s car");
+    }
+
+    #[test]
+    fn matching_ignores_case_and_keeps_the_first_letter() {
+        assert_eq!(mask("Shit, FUCK."), "S***, F***.");
+    }
+
+    #[test]
+    fn removing_tidies_the_gap() {
+        let remove = |text| apply(text, &config(ProfanityMode::Remove)).0;
+        assert_eq!(remove("this is fucking great"), "this is great");
+        assert_eq!(remove("that's shit."), "that's.");
+        assert_eq!(remove("damn it"), "it");
+        assert_eq!(remove("oh shit"), "oh");
+    }
+
+    #[test]
+    fn keeping_changes_nothing() {
+        let (text, modified) = apply("fuck", &config(ProfanityMode::Keep));
+        assert_eq!(text, "fuck");
+        assert!(!modified);
+    }
+
+    #[test]
+    fn user_words_are_added_and_built_ins_allowed() {
+        let mut config = config(ProfanityMode::Mask);
+        config.add_word(" Frak ").unwrap();
+        config.remove_word("damn").unwrap();
+        assert_eq!(apply("frak's damn", &config).0, "f***'s damn");
+        assert!(config.add_word("two words").is_err());
+        assert!(config.add_word("  ").is_err());
+        // Adding it back undoes the removal
+        config.add_word("damn").unwrap();
+        assert_eq!(apply("damn", &config).0, "d***");
+    }
+}
@@ -0,0 +1,431 @@
+//! System sleep and wake.
+//!
+//! Closing the lid mid-dictation kills the webview's audio stream without
+//! an error, and the recording would otherwise stay up forever after wake.
+//! Instead, going to sleep while `Recording` ends the recording: the state
+//! machine goes back to `Idle` (reason `sleep`), the live session ends and
+//! `recording:stopped` is emitted, but the audio is kept, both in the page
+//! and in the crash-recovery checkpoint (see [`crate::recovery`]). On wake,
+//! `recording:interrupted` says which recording it was, and the pill offers
+//! to transcribe what was captured or discard it
+//! ([`discard_interrupted_recording`]).
+//!
+//! The dictation hotkey and the microphone occasionally break across sleep
+//! too. Every wake emits `power:woke` with whether the hotkey is still
+//! registered, for the page to register it again and look for its
+//! microphone.
+//!
+//! Notifications come from a [`PowerSource`]; [`handle`] reacts to them, so
+//! synthetic ones drive it the same way.
+//!
+//! # Platform Support
+//!
+//! - macOS: `NSWorkspace` will-sleep and did-wake notifications.
+//! - Windows and Linux: a thread that notices the wall clock jumping ahead
+//!   of its own sleep. Sleep is only noticed on wake, so both are handled
+//!   then, one after the other.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager, State};
+use tauri_plugin_global_shortcut::GlobalShortcut;
+
+use crate::api::live::LiveHub;
+use crate::app_state::{self, Phase, SessionEvent};
+use crate::dictation::DictationState;
+use crate::pill::{self, PillState};
+use crate::recovery::RecoveryState;
+use crate::settings::SettingsState;
+use crate::{AppHandle, Runtime};
+
+/// How often the wake detector looks at the clock.
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+const WAKE_POLL: Duration = Duration::from_secs(5);
+
+/// How far the wall clock has to jump past [`WAKE_POLL`] to count as a
+/// sleep, well above any scheduling delay or clock correction.
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+const WAKE_GAP: Duration = Duration::from_secs(30);
+
+/// Reason of the transitions that end a recording on sleep.
+pub(crate) const SLEEP: &str = "sleep";
+
+/// A power notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerEvent {
+    /// The system is about to sleep.
+    Sleep,
+    /// The system woke up.
+    Wake,
+}
+
+/// Where power notifications come from.
+pub trait PowerSource {
+    /// Starts calling `handler` with every notification, for the life of
+    /// the process. Returns whether it could.
+    fn watch(self, handler: Box<dyn Fn(PowerEvent) + Send + Sync>) -> bool;
+}
+
+/// Payload of `recording:interrupted`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingInterrupted {
+    pub session_id: String,
+    /// Audio captured before the sleep, as reported by the frontend.
+    pub audio_ms: u64,
+}
+
+/// Payload of `power:woke`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PowerWoke {
+    /// Whether the dictation hotkey is still registered.
+    pub hotkey_registered: bool,
+}
+
+/// Power state, managed for the life of the app.
+#[derive(Default)]
+pub struct PowerState {
+    /// The recording the last sleep ended, until the next wake.
+    interrupted: Mutex<Option<RecordingInterrupted>>,
+}
+
+/// Starts reacting to `source`'s notifications.
+pub fn watch(app: &AppHandle, source: impl PowerSource) {
+    let handle = app.clone();
+    if !source.watch(Box::new(move |event| self::handle(&handle, event))) {
+        tracing::warn!("Failed to follow system sleep; recordings won't stop on sleep");
+    }
+}
+
+/// Reacts to one power notification; see the module docs.
+pub fn handle(app: &AppHandle, event: PowerEvent) {
+    tracing::info!(?event, "Power notification");
+    match event {
+        PowerEvent::Sleep => going_to_sleep(app),
+        PowerEvent::Wake => woke(app),
+    }
+}
+
+/// Ends the recording in progress, if any, keeping its audio.
+fn going_to_sleep(app: &AppHandle) {
+    if app_state::phase(app) != Phase::Recording {
+        return;
+    }
+    let Some(session_id) = app_state::session_id(app) else {
+        return;
+    };
+    // Losing a race with a stop or a cancel leaves nothing to do
+    if app_state::transition_session(app, &session_id, Phase::Cancelled, SLEEP).is_err() {
+        return;
+    }
+    let _ = app_state::transition(app, Phase::Idle, SLEEP);
+
+    let audio_ms = app
+        .state::<DictationState>()
+        .0
+        .lock()
+        .unwrap()
+        .take()
+        .map(|dictation| dictation.audio_ms)
+        .unwrap_or_default();
+    app.state::<LiveHub>().end_session(app);
+    pill::set_state(app, PillState::Hidden);
+    let _ = app.emit(
+        "recording:stopped",
+        SessionEvent {
+            session_id: Some(session_id.clone()),
+        },
+    );
+    tracing::info!(%session_id, audio_ms, "Recording interrupted by sleep");
+    *app.state::<PowerState>().interrupted.lock().unwrap() = Some(RecordingInterrupted {
+        session_id,
+        audio_ms,
+    });
+}
+
+/// Offers the recording the sleep ended, if any, and checks the hotkey.
+fn woke(app: &AppHandle) {
+    let interrupted = app.state::<PowerState>().interrupted.lock().unwrap().take();
+    if let Some(interrupted) = interrupted {
+        // Unless a dictation started in the meantime
+        if app_state::phase(app) == Phase::Idle {
+            pill::set_state(app, PillState::Transcript { estimated_lines: 2 });
+            let _ = app.emit("recording:interrupted", interrupted);
+        }
+    }
+
+    let accelerator = app.state::<SettingsState>().get().hotkeys.dictation;
+    // Nothing is registered without the plugin, as on the mock runtime
+    let hotkey_registered = app
+        .try_state::<GlobalShortcut<Runtime>>()
+        .is_some_and(|shortcuts| shortcuts.is_registered(accelerator.as_str()));
+    if !hotkey_registered {
+        tracing::warn!(%accelerator, "The dictation hotkey was lost across sleep");
+    }
+    let _ = app.emit("power:woke", PowerWoke { hotkey_registered });
+}
+
+/// Drops the audio of a recording interrupted by sleep, once the user chose
+/// not to transcribe it, and hides the pill.
+#[tauri::command]
+pub fn discard_interrupted_recording(app: AppHandle, recovery: State<'_, RecoveryState>) {
+    recovery.clear_active();
+    if app_state::phase(&app) == Phase::Idle {
+        pill::set_state(&app, PillState::Hidden);
+    }
+}
+
+/// The system's own notifications.
+pub struct PlatformSource<'a> {
+    pub app: &'a AppHandle,
+}
+
+#[cfg(target_os = "macos")]
+impl PowerSource for PlatformSource<'_> {
+    fn watch(self, handler: Box<dyn Fn(PowerEvent) + Send + Sync>) -> bool {
+        let handler = std::sync::Arc::new(handler);
+        let scheduled = self.app.run_on_main_thread(move || {
+            let sleep = handler.clone();
+            let observed = unsafe {
+                macos::observe("NSWorkspaceWillSleepNotification", move || {
+                    sleep(PowerEvent::Sleep)
+                }) && macos::observe("NSWorkspaceDidWakeNotification", move || {
+                    handler(PowerEvent::Wake)
+                })
+            };
+            if !observed {
+                tracing::warn!("Failed to observe system sleep");
+            }
+        });
+        scheduled.is_ok()
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+impl PowerSource for PlatformSource<'_> {
+    fn watch(self, handler: Box<dyn Fn(PowerEvent) + Send + Sync>) -> bool {
+        let mut last = std::time::SystemTime::now();
+        let spawned = crate::crash::supervise(self.app, "wake-detector", move || loop {
+            std::thread::sleep(WAKE_POLL);
+            let now = std::time::SystemTime::now();
+            // A clock set back is no sleep
+            let gap = now.duration_since(last).unwrap_or_default();
+            last = now;
+            if gap > WAKE_POLL + WAKE_GAP {
+                tracing::debug!(gap_ms = gap.as_millis() as u64, "Clock jumped ahead");
+                handler(PowerEvent::Sleep);
+                handler(PowerEvent::Wake);
+            }
+        });
+        spawned.is_ok()
+    }
+}
+
+/// `NSWorkspace` power notifications.
+#[cfg(target_os = "macos")]
+mod macos {
+    use block2::RcBlock;
+    use objc2::msg_send;
+    use objc2::runtime::{AnyClass, AnyObject};
+    use objc2_foundation::NSString;
+
+    /// Calls `handler` whenever the workspace posts the notification
+    /// `name`, for the life of the process. Returns whether the observer
+    /// could be added.
+    ///
+    /// # Safety
+    ///
+    /// Must run on the main thread, where `handler` is called too.
+    pub unsafe fn observe(name: &str, handler: impl Fn() + 'static) -> bool {
+        let Some(class) = AnyClass::get("NSWorkspace") else {
+            return false;
+        };
+        let workspace: *mut AnyObject = msg_send![class, sharedWorkspace];
+        let Some(workspace) = workspace.as_ref() else {
+            return false;
+        };
+        let center: *mut AnyObject = msg_send![workspace, notificationCenter];
+        let Some(center) = center.as_ref() else {
+            return false;
+        };
+
+        let name = NSString::from_str(name);
+        let block = RcBlock::new(move |_notification: *mut AnyObject| handler());
+        // No queue: the block runs on the posting thread, the main thread
+        let observer: *mut AnyObject = msg_send![
+            center,
+            addObserverForName: &*name,
+            object: std::ptr::null::<AnyObject>(),
+            queue: std::ptr::null::<AnyObject>(),
+            usingBlock: &*block
+        ];
+        if observer.is_null() {
+            return false;
+        }
+        // Never removed
+        let _: *mut AnyObject = msg_send![observer, retain];
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::harness::Harness;
+    use crate::recovery::RecoveryConfig;
+
+    type Handler = Box<dyn Fn(PowerEvent) + Send + Sync>;
+
+    /// Notifications the test sends.
+    #[derive(Clone, Default)]
+    struct Synthetic(Arc<Mutex<Option<Handler>>>);
+
+    impl Synthetic {
+        fn send(&self, event: PowerEvent) {
+            let handler = self.0.lock().unwrap();
+            handler.as_ref().expect("not watched")(event);
+        }
+    }
+
+    impl PowerSource for Synthetic {
+        fn watch(self, handler: Handler) -> bool {
+            *self.0.lock().unwrap() = Some(handler);
+            true
+        }
+    }
+
+    /// `harness`'s app, following `power`.
+    fn watched(harness: &Harness) -> Synthetic {
+        let power = Synthetic::default();
+        watch(harness.handle(), power.clone());
+        power
+    }
+
+    /// Whether the next start would offer audio to recover.
+    fn audio_checkpointed(harness: &Harness) -> bool {
+        RecoveryState::open(harness.data_dir(), &RecoveryConfig::default())
+            .pending()
+            .is_some_and(|pending| pending.audio_path.is_some())
+    }
+
+    #[test]
+    fn sleep_ends_the_recording_and_wake_offers_it() {
+        let harness = Harness::new("power-sleep");
+        let app = harness.handle();
+        let power = watched(&harness);
+        let session_id = harness.press_hotkey();
+        let captured = json!({ "sessionId": session_id, "ms": 1500, "peak": 0.4 });
+        harness.invoke("report_captured_audio", captured).unwrap();
+        app.state::<RecoveryState>().append_audio(b"audio").unwrap();
+
+        power.send(PowerEvent::Sleep);
+        assert_eq!(app_state::phase(app), Phase::Idle);
+        assert_eq!(app_state::session_id(app), None);
+        assert_eq!(
+            harness.events("recording:stopped"),
+            [json!({ "session_id": session_id })]
+        );
+        // The webview's stop, once it runs again, is too late
+        let stop = json!({ "sessionId": session_id });
+        assert!(harness.invoke("stop_recording", stop).is_err());
+        assert!(harness.events("recording:interrupted").is_empty());
+
+        power.send(PowerEvent::Wake);
+        assert_eq!(
+            harness.events("recording:interrupted"),
+            [json!({ "session_id": session_id, "audio_ms": 1500 })]
+        );
+        // No plugin on the mock runtime, so no hotkey
+        assert_eq!(
+            harness.events("power:woke"),
+            [json!({ "hotkey_registered": false })]
+        );
+        assert!(audio_checkpointed(&harness));
+    }
+
+    #[test]
+    fn a_wake_offers_the_recording_once() {
+        let harness = Harness::new("power-wake-twice");
+        let power = watched(&harness);
+        harness.press_hotkey();
+        power.send(PowerEvent::Sleep);
+        power.send(PowerEvent::Wake);
+        power.send(PowerEvent::Wake);
+        assert_eq!(harness.events("recording:interrupted").len(), 1);
+        assert_eq!(harness.events("power:woke").len(), 2);
+    }
+
+    #[test]
+    fn sleep_while_idle_changes_nothing() {
+        let harness = Harness::new("power-idle");
+        let power = watched(&harness);
+        power.send(PowerEvent::Sleep);
+        power.send(PowerEvent::Wake);
+        assert_eq!(app_state::phase(harness.handle()), Phase::Idle);
+        assert!(harness.events("recording:stopped").is_empty());
+        assert!(harness.events("recording:interrupted").is_empty());
+        assert_eq!(harness.events("power:woke").len(), 1);
+    }
+
+    #[test]
+    fn sleep_while_transcribing_leaves_the_dictation_alone() {
+        let harness = Harness::new("power-transcribing");
+        let power = watched(&harness);
+        let session_id = harness.press_hotkey();
+        harness
+            .invoke("stop_recording", json!({ "sessionId": session_id }))
+            .unwrap();
+        power.send(PowerEvent::Sleep);
+        power.send(PowerEvent::Wake);
+        assert_eq!(app_state::phase(harness.handle()), Phase::Transcribing);
+        assert_eq!(app_state::session_id(harness.handle()), Some(session_id));
+        assert!(harness.events("recording:interrupted").is_empty());
+    }
+
+    #[test]
+    fn a_dictation_started_before_the_wake_isnt_interrupted() {
+        let harness = Harness::with_settings(
+            "power-new-dictation",
+            json!({ "hotkeys": { "debounce_ms": 0 } }),
+        );
+        let power = watched(&harness);
+        harness.press_hotkey();
+        power.send(PowerEvent::Sleep);
+        let session_id = harness.press_hotkey();
+        power.send(PowerEvent::Wake);
+        assert!(harness.events("recording:interrupted").is_empty());
+        assert_eq!(app_state::phase(harness.handle()), Phase::Recording);
+        assert_eq!(app_state::session_id(harness.handle()), Some(session_id));
+    }
+
+    #[test]
+    fn discarding_the_interrupted_recording_drops_its_audio() {
+        let harness = Harness::new("power-discard");
+        let app = harness.handle();
+        let power = watched(&harness);
+        harness.press_hotkey();
+        app.state::<RecoveryState>().append_audio(b"audio").unwrap();
+        power.send(PowerEvent::Sleep);
+        power.send(PowerEvent::Wake);
+
+        harness
+            .invoke("discard_interrupted_recording", json!({}))
+            .unwrap();
+        assert!(!audio_checkpointed(&harness));
+    }
+
+    #[test]
+    fn a_cancelled_recording_keeps_no_audio() {
+        let harness = Harness::new("power-cancelled");
+        let app = harness.handle();
+        let session_id = harness.press_hotkey();
+        app.state::<RecoveryState>().append_audio(b"audio").unwrap();
+        app_state::transition_session(app, &session_id, Phase::Cancelled, "escape").unwrap();
+        assert!(!audio_checkpointed(&harness));
+    }
+}
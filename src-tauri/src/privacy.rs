@@ -0,0 +1,428 @@
+//! Privacy mode: what the user says never touches the disk.
+//!
+//! With `privacy.enabled`, dictations and meetings are transcribed and
+//! pasted as usual, but nothing they contain is kept: no history entry is
+//! written (the day's usage counters are still updated), recordings are
+//! deleted as soon as they are transcribed whatever `retention.audio` says,
+//! logs only show the length of transcripts even with
+//! `logging.log_transcripts`, nothing is checkpointed for crash recovery,
+//! the audio of meeting segments that failed to transcribe isn't kept,
+//! nothing is appended to the journal or the dictation log, webhook
+//! deliveries waiting to be retried are only kept in memory, pasted text
+//! is never handed to a clipboard manager (its store takes the text on a
+//! command line, see [`crate::clipboard_manager`]), and diagnostics
+//! bundles leave out the last dictations.
+//!
+//! Entries and recordings stored before privacy mode was turned on stay
+//! where they are until deleted (`clear_history`) or pruned; retained
+//! recordings are expired by the next pruning run.
+//!
+//! # Architecture Decision
+//!
+//! What gets stored is decided in one place, the [`StoragePolicy`] derived
+//! from the settings whenever they change ([`apply`]). History, retention,
+//! logging, recovery and diagnostics ask [`policy`] instead of reading
+//! their own settings, so none of them can miss privacy mode, and
+//! [`get_privacy_status`] reports the policy they actually follow rather
+//! than what the settings say. Until the settings are first applied the
+//! policy is the strictest one.
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::history::audio::AudioRetention;
+use crate::history::HistoryState;
+use crate::settings::Settings;
+use crate::AppHandle;
+
+/// Privacy settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacySettings {
+    /// Never write transcripts or recordings to disk.
+    pub enabled: bool,
+}
+
+/// What may be written to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct StoragePolicy {
+    pub privacy_mode: bool,
+    /// Transcripts are saved in history; otherwise only the usage counters
+    /// are updated.
+    pub history: bool,
+    /// How long recordings are kept after transcription.
+    pub audio: AudioRetention,
+    /// Logs have the text of transcripts, not just their length.
+    pub log_transcripts: bool,
+    /// Unpasted transcripts and recordings in progress are checkpointed, to
+    /// be recovered after a crash.
+    pub recovery: bool,
+    /// Meeting segments that could never be transcribed keep their audio.
+    pub failed_meeting_audio: bool,
+    /// Diagnostics bundles may include the last dictations.
+    pub diagnostics_transcripts: bool,
+    /// Dictations are appended to the journal (see [`crate::journal`]).
+    pub journal: bool,
+    /// Dictations are appended to the dictation log (see
+    /// [`crate::dictation_log`]).
+    pub dictation_log: bool,
+    /// Undelivered webhook events are saved, to be retried after a restart.
+    pub webhook_queue: bool,
+    /// Pasted text may be handed to the clipboard manager, which keeps it
+    /// in its history, on a command line other processes can read.
+    pub clipboard_manager_store: bool,
+}
+
+impl StoragePolicy {
+    /// Nothing stored: privacy mode.
+    const PRIVATE: Self = Self {
+        privacy_mode: true,
+        history: false,
+        audio: AudioRetention::None,
+        log_transcripts: false,
+        recovery: false,
+        failed_meeting_audio: false,
+        diagnostics_transcripts: false,
+        journal: false,
+        dictation_log: false,
+        webhook_queue: false,
+        clipboard_manager_store: false,
+    };
+
+    /// The policy `settings` ask for.
+    pub fn new(settings: &Settings) -> Self {
+        if settings.privacy.enabled {
+            return Self::PRIVATE;
+        }
+        Self {
+            privacy_mode: false,
+            history: settings.retention.save_transcripts,
+            audio: settings.retention.audio,
+            log_transcripts: settings.logging.log_transcripts,
+            recovery: true,
+            failed_meeting_audio: true,
+            diagnostics_transcripts: true,
+            journal: true,
+            dictation_log: true,
+            webhook_queue: true,
+            clipboard_manager_store: settings.clipboard_manager.use_store,
+        }
+    }
+
+    /// What the policy keeps on disk, and what it doesn't, for the user.
+    fn describe(&self) -> (Vec<String>, Vec<String>) {
+        let mut stored =
+            vec!["Daily usage counters (dictations, words, speaking time)".to_string()];
+        let mut not_stored = Vec::new();
+        let mut add = |kept: bool, what: &str| {
+            if kept {
+                stored.push(what.to_string());
+            } else {
+                not_stored.push(what.to_string());
+            }
+        };
+        add(self.history, "Transcripts in history");
+        add(
+            self.audio != AudioRetention::None,
+            "Recordings after transcription",
+        );
+        add(self.log_transcripts, "Transcript text in the logs");
+        add(
+            self.recovery,
+            "Unpasted transcripts and recordings in progress, for crash recovery",
+        );
+        add(
+            self.failed_meeting_audio,
+            "Audio of meeting segments that failed to transcribe",
+        );
+        add(
+            self.diagnostics_transcripts,
+            "Transcripts in diagnostics bundles, when asked for",
+        );
+        add(self.journal, "Dictations appended to the journal");
+        add(
+            self.dictation_log,
+            "Dictations appended to the dictation log",
+        );
+        add(
+            self.webhook_queue,
+            "Dictations waiting to be sent to the webhook",
+        );
+        add(
+            self.clipboard_manager_store,
+            "Pasted text handed to the clipboard manager",
+        );
+        (stored, not_stored)
+    }
+}
+
+/// The policy in force.
+static POLICY: Mutex<StoragePolicy> = Mutex::new(StoragePolicy::PRIVATE);
+
+/// The storage policy in force.
+pub fn policy() -> StoragePolicy {
+    *POLICY.lock().unwrap()
+}
+
+/// Derives the storage policy from `settings`.
+pub fn apply(settings: &Settings) {
+    let policy = StoragePolicy::new(settings);
+    let previous = std::mem::replace(&mut *POLICY.lock().unwrap(), policy);
+    if previous.privacy_mode != policy.privacy_mode {
+        tracing::info!(privacy_mode = policy.privacy_mode, "Storage policy changed");
+    }
+}
+
+/// Result of [`get_privacy_status`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PrivacyStatus {
+    #[serde(flatten)]
+    pub policy: StoragePolicy,
+    /// What is written to disk, in words.
+    pub stored: Vec<String>,
+    /// What never is.
+    pub not_stored: Vec<String>,
+    /// Entries already in history, from before privacy mode or while it
+    /// was off; `None` if history is unavailable.
+    pub history_entries: Option<u64>,
+    /// How many of them still have their recording.
+    pub retained_recordings: Option<u64>,
+}
+
+/// Reports what is and isn't stored, as the storage policy in force
+/// decides, and what history already holds.
+#[tauri::command]
+pub fn get_privacy_status(app: AppHandle) -> PrivacyStatus {
+    let policy = policy();
+    let (stored, not_stored) = policy.describe();
+    let counts = app.state::<HistoryState>().counts().ok();
+    PrivacyStatus {
+        policy,
+        stored,
+        not_stored,
+        history_entries: counts.map(|(entries, _)| entries),
+        retained_recordings: counts.map(|(_, recordings)| recordings),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{self, Write};
+    use std::path::{Path, PathBuf};
+    use std::sync::Once;
+
+    use serde_json::{json, Value};
+    use tracing_subscriber::fmt::format::FmtSpan;
+    use tracing_subscriber::EnvFilter;
+
+    use super::*;
+    use crate::harness::{wait_for, Harness};
+    use crate::logging;
+
+    /// Everything logged while the tests run, as the log file has it.
+    static LOGGED: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+    struct Logged;
+
+    impl Write for Logged {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            LOGGED.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Sends everything the app logs, at every level, to [`LOGGED`], in
+    /// the log file's format.
+    fn capture_logs() {
+        static CAPTURING: Once = Once::new();
+        CAPTURING.call_once(|| {
+            let filter = format!("warn,{}=trace", env!("CARGO_CRATE_NAME"));
+            let _ = tracing_subscriber::fmt()
+                .with_writer(|| Logged)
+                .with_ansi(false)
+                .with_span_events(FmtSpan::CLOSE)
+                .with_env_filter(EnvFilter::new(filter))
+                .try_init();
+        });
+    }
+
+    fn logged() -> String {
+        String::from_utf8_lossy(&LOGGED.lock().unwrap()).into_owned()
+    }
+
+    /// A directory of its own for `test`'s journal and dictation log,
+    /// removed when dropped.
+    struct SinkDir(PathBuf);
+
+    impl SinkDir {
+        fn new(test: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("wispr-privacy-{}-{}", test, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for SinkDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Settings that store everything privacy mode keeps off the disk,
+    /// with privacy mode `enabled`, dictating `text`.
+    fn storing_everything(enabled: bool, text: &str, sinks: &SinkDir) -> Value {
+        json!({
+            "privacy": { "enabled": enabled },
+            "retention": {
+                "save_transcripts": true,
+                "audio": { "policy": "last_n", "value": 10 },
+            },
+            "logging": { "log_transcripts": true },
+            "transcription": { "mock": { "text": text } },
+            "journal": { "enabled": true, "directory": sinks.0.join("journal") },
+            "dictation_log": { "enabled": true, "path": sinks.0.join("dictations.log") },
+        })
+    }
+
+    /// Dictates and pastes with `harness`, waiting until the dictation was
+    /// counted. Returns what was pasted.
+    fn dictate(harness: &Harness) -> String {
+        crate::dictation_log::spawn_writer(harness.handle());
+        let session_id = harness.press_hotkey();
+        let text = harness.transcribe(&session_id).unwrap();
+        harness.paste(&session_id, &text).unwrap();
+        wait_for("the usage counters", || {
+            let stats = harness.invoke("get_usage_stats", json!({ "period": "today" }));
+            stats.unwrap()["total_dictations"] == 1
+        });
+        text
+    }
+
+    /// Every file under `dir`.
+    fn files(dir: &Path) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .flat_map(|entry| {
+                let path = entry.path();
+                if path.is_dir() {
+                    files(&path)
+                } else {
+                    vec![path]
+                }
+            })
+            .collect()
+    }
+
+    /// The files under `dirs` holding any of `texts`, leaving out the
+    /// settings, where the mock provider's text is.
+    fn holding(texts: &[&str], dirs: &[&Path]) -> Vec<PathBuf> {
+        dirs.iter()
+            .flat_map(|dir| files(dir))
+            .filter(|path| !path.ends_with("settings.json"))
+            .filter(|path| {
+                let bytes = fs::read(path).unwrap_or_default();
+                let contents = String::from_utf8_lossy(&bytes);
+                texts.iter().any(|text| contents.contains(text))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn privacy_mode_writes_no_transcript_to_disk_or_the_log() {
+        capture_logs();
+        let said = "Remind me to call the bank about the mortgage";
+        let sinks = SinkDir::new("on");
+        let harness = Harness::with_settings("privacy-on", storing_everything(true, said, &sinks));
+        let pasted = dictate(&harness);
+
+        assert!(harness.history().is_empty());
+        let status = harness.invoke("get_privacy_status", json!({})).unwrap();
+        assert_eq!(status["privacy_mode"], true);
+        assert_eq!(status["history_entries"], 0);
+        assert_eq!(status["retained_recordings"], 0);
+        // Nothing in the history database, the journal, the dictation log
+        // or any other file
+        let texts = [said, pasted.as_str()];
+        assert_eq!(
+            holding(&texts, &[harness.data_dir(), &sinks.0]),
+            Vec::<PathBuf>::new()
+        );
+        // Nor in the log, even with `logging.log_transcripts`
+        let logged = logged();
+        assert!(!logged.contains(said));
+        assert!(!logged.contains(&pasted));
+        assert_eq!(
+            logging::transcript(&pasted),
+            format!("<{} chars>", pasted.chars().count())
+        );
+    }
+
+    #[test]
+    fn without_privacy_mode_the_same_settings_store_the_transcript() {
+        capture_logs();
+        let said = "Pick up the dry cleaning before Friday";
+        let sinks = SinkDir::new("off");
+        let harness =
+            Harness::with_settings("privacy-off", storing_everything(false, said, &sinks));
+        let pasted = dictate(&harness);
+
+        // What the test above looks for is there to be found
+        wait_for("the history entry", || harness.history().len() == 1);
+        wait_for("the journal and the dictation log", || {
+            holding(&[pasted.as_str()], &[&sinks.0]).len() == 2
+        });
+        assert!(!holding(&[pasted.as_str()], &[harness.data_dir()]).is_empty());
+        assert!(logged().contains(&format!("{:?}", pasted)));
+        let status = harness.invoke("get_privacy_status", json!({})).unwrap();
+        assert_eq!(status["privacy_mode"], false);
+        assert_eq!(status["history_entries"], 1);
+    }
+
+    #[test]
+    fn the_status_lists_everything_once() {
+        let (stored, not_stored) = StoragePolicy::PRIVATE.describe();
+        // Only the usage counters
+        assert_eq!(stored.len(), 1);
+        let settings = Settings::default();
+        let (all_stored, none) = StoragePolicy {
+            log_transcripts: true,
+            audio: AudioRetention::LastN(10),
+            clipboard_manager_store: true,
+            ..StoragePolicy::new(&settings)
+        }
+        .describe();
+        assert_eq!(none, Vec::<String>::new());
+        assert_eq!(all_stored.len(), stored.len() + not_stored.len());
+        for what in &not_stored {
+            assert!(all_stored.contains(what), "{}", what);
+        }
+    }
+
+    #[test]
+    fn privacy_mode_overrides_every_setting() {
+        let mut settings = Settings::default();
+        settings.retention.audio = AudioRetention::Days(30);
+        settings.retention.save_transcripts = true;
+        settings.logging.log_transcripts = true;
+        settings.clipboard_manager.use_store = true;
+        settings.privacy.enabled = true;
+        assert_eq!(StoragePolicy::new(&settings), StoragePolicy::PRIVATE);
+        settings.privacy.enabled = false;
+        let policy = StoragePolicy::new(&settings);
+        assert!(policy.history && policy.log_transcripts);
+        assert!(policy.clipboard_manager_store);
+        assert_eq!(policy.audio, AudioRetention::Days(30));
+    }
+}
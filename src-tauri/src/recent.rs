@@ -0,0 +1,145 @@
+//! Recent transcripts: the last few dictations, to paste again quickly.
+//!
+//! The last [`MAX_RECENT`] pasted dictations are kept in memory, newest
+//! first, whether or not history is saved. Dictating the same text again
+//! moves it to the front instead of adding a copy; beyond that the oldest
+//! goes first. Nothing is kept in privacy mode, and turning it on forgets
+//! what was kept.
+//!
+//! `hotkeys.recent` opens the recent window, a small palette listing them
+//! (see [`crate::windows`]). Choosing one pastes it with
+//! [`paste_recent_transcript`] through the same path as history
+//! ([`crate::paste_text`]), into the app that was in front when the palette
+//! opened, or the app the dictation was pasted into if that isn't known.
+//! `recent:changed` is emitted whenever the list changes.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tauri::{Emitter, Manager, State};
+
+use crate::frontmost;
+use crate::privacy;
+use crate::windows::{self, RECENT_WINDOW};
+use crate::AppHandle;
+
+/// Transcripts kept.
+pub const MAX_RECENT: usize = 10;
+
+/// A recently pasted dictation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecentTranscript {
+    /// Only unique for this run of the app.
+    pub id: u64,
+    pub text: String,
+    /// Bundle id (or process name) of the app it was pasted into.
+    pub target_app: Option<String>,
+    pub created_at_ms: i64,
+}
+
+/// Managed state: the recent transcripts, newest first.
+#[derive(Default)]
+pub struct RecentState {
+    entries: Mutex<VecDeque<RecentTranscript>>,
+    next_id: AtomicU64,
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Adds the dictation `text` that was just pasted. Called once the paste
+/// has completed; finding the app it went to happens in the background.
+pub fn spawn_push(app: &AppHandle, text: &str) {
+    if privacy::policy().privacy_mode || text.trim().is_empty() {
+        return;
+    }
+    let app = app.clone();
+    let text = text.to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<RecentState>();
+        let entry = RecentTranscript {
+            id: state.next_id.fetch_add(1, Ordering::Relaxed),
+            text,
+            target_app: frontmost::frontmost_bundle_id(),
+            created_at_ms: now_ms(),
+        };
+        {
+            let mut entries = state.entries.lock().unwrap();
+            entries.retain(|existing| existing.text != entry.text);
+            entries.push_front(entry);
+            entries.truncate(MAX_RECENT);
+        }
+        let _ = app.emit("recent:changed", ());
+    });
+}
+
+/// Forgets every recent transcript.
+pub fn forget(app: &AppHandle) {
+    let state = app.state::<RecentState>();
+    let had_entries = {
+        let mut entries = state.entries.lock().unwrap();
+        let had_entries = !entries.is_empty();
+        entries.clear();
+        had_entries
+    };
+    if had_entries {
+        let _ = app.emit("recent:changed", ());
+    }
+}
+
+/// Returns the `n` most recent transcripts (all of them by default), newest
+/// first.
+#[tauri::command]
+pub fn get_recent_transcripts(
+    state: State<'_, RecentState>,
+    n: Option<usize>,
+) -> Vec<RecentTranscript> {
+    if privacy::policy().privacy_mode {
+        return Vec::new();
+    }
+    let entries = state.entries.lock().unwrap();
+    entries
+        .iter()
+        .take(n.unwrap_or(MAX_RECENT))
+        .cloned()
+        .collect()
+}
+
+/// Pastes the recent transcript `id`, hiding the recent window first and
+/// handing focus back to the app it was opened from (or, if that isn't
+/// known, the app the transcript was first pasted into).
+///
+/// # Errors
+///
+/// Returns an error if there is no such transcript or the paste fails.
+#[tauri::command]
+pub async fn paste_recent_transcript(app: AppHandle, id: u64) -> Result<(), String> {
+    let entry = app
+        .state::<RecentState>()
+        .entries
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|entry| entry.id == id)
+        .cloned()
+        .ok_or_else(|| format!("recent transcript {} not found", id))?;
+
+    if windows::leave_palette(&app, RECENT_WINDOW).is_none() {
+        if let Some(target_app) = &entry.target_app {
+            if !frontmost::activate(target_app) {
+                tracing::warn!("Failed to return focus to {}", target_app);
+            }
+        }
+    }
+    crate::paste_text(&app, &entry.text, None, crate::WhenBusy::Queue, true)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,339 @@
+//! Crash recovery for in-flight dictations.
+//!
+//! While recording, the frontend checkpoints audio every few seconds with
+//! [`checkpoint_recording`], appending to `recovery/active/recording.webm` in
+//! the app data directory. When a transcript is ready but not yet pasted it
+//! is saved as `recovery/active/pending.json`. A successful paste clears both,
+//! and so does cancelling the dictation. Neither is written in privacy mode
+//! (see [`crate::privacy`]), so a crash loses the dictation.
+//!
+//! Anything still in `active/` at startup belongs to a dictation that never
+//! finished. It is moved to `recovery/recovered/`; the frontend asks for it
+//! with [`get_pending_recovery`] once it is up, and the user can paste or
+//! transcribe it with [`recover_pending`] or drop it with
+//! [`discard_pending`]. Recoveries older
+//! than [`RecoveryConfig::max_age_hours`] are discarded automatically.
+//!
+//! # Architecture Decision
+//!
+//! The audio checkpoint is the MediaRecorder's WebM stream appended chunk by
+//! chunk. The stream is self-describing from its first chunk onwards, so a
+//! file cut short by a crash still decodes up to the last checkpoint, with no
+//! header to patch up afterwards.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::ipc::{InvokeBody, Request};
+use tauri::State;
+
+use crate::app_state;
+use crate::AppHandle;
+
+/// Directory under the app data dir holding recovery files.
+const RECOVERY_DIR: &str = "recovery";
+const ACTIVE_DIR: &str = "active";
+const RECOVERED_DIR: &str = "recovered";
+const RECORDING_FILE: &str = "recording.webm";
+const PENDING_FILE: &str = "pending.json";
+
+/// Recovery configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecoveryConfig {
+    /// Leftovers older than this are discarded at startup.
+    pub max_age_hours: u32,
+}
+
+impl Default for RecoveryConfig {
+    fn default() -> Self {
+        Self { max_age_hours: 24 }
+    }
+}
+
+/// A transcript that finished but was never pasted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingTranscript {
+    text: String,
+    created_at_ms: i64,
+}
+
+/// Content left behind by an unfinished dictation.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingRecovery {
+    /// Transcript that was ready to paste, if transcription completed.
+    pub transcript: Option<String>,
+    /// Audio checkpointed before the crash, if any.
+    pub audio_path: Option<PathBuf>,
+    /// When the leftovers were last written, Unix milliseconds.
+    pub created_at_ms: i64,
+}
+
+/// What [`recover_pending`] did.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecoveryOutcome {
+    /// The recovered transcript was pasted and the recovery cleared.
+    Pasted { text: String },
+    /// Only audio survived; it must be transcribed, after which the caller
+    /// should call [`discard_pending`].
+    NeedsTranscription { audio_path: PathBuf },
+    /// There was nothing to recover.
+    Nothing,
+}
+
+/// Managed state for crash recovery.
+pub struct RecoveryState {
+    dir: PathBuf,
+    recovered: Mutex<Option<PendingRecovery>>,
+}
+
+impl RecoveryState {
+    /// Moves leftovers of the previous run aside and loads them, discarding
+    /// ones older than `config.max_age_hours`.
+    pub fn open(data_dir: &Path, config: &RecoveryConfig) -> Self {
+        let dir = data_dir.join(RECOVERY_DIR);
+        let active = dir.join(ACTIVE_DIR);
+        let recovered = dir.join(RECOVERED_DIR);
+
+        // A newer unfinished dictation replaces an older unclaimed recovery
+        if has_content(&active) {
+            let _ = fs::remove_dir_all(&recovered);
+            if let Err(e) = fs::rename(&active, &recovered) {
+                tracing::warn!("Failed to set aside unfinished dictation: {}", e);
+            }
+        }
+
+        let max_age = Duration::from_secs(u64::from(config.max_age_hours) * 60 * 60);
+        let pending = load_recovery(&recovered);
+        let pending = match pending {
+            Some(pending) if age(pending.created_at_ms) > max_age => {
+                tracing::info!("Discarding stale dictation recovery");
+                let _ = fs::remove_dir_all(&recovered);
+                None
+            }
+            pending => pending,
+        };
+
+        if let Err(e) = fs::create_dir_all(&active) {
+            tracing::warn!("Crash recovery unavailable: {}", e);
+        }
+
+        Self {
+            dir,
+            recovered: Mutex::new(pending),
+        }
+    }
+
+    /// Leftovers from the previous run, if any.
+    pub fn pending(&self) -> Option<PendingRecovery> {
+        self.recovered.lock().unwrap().clone()
+    }
+
+    fn active_dir(&self) -> PathBuf {
+        self.dir.join(ACTIVE_DIR)
+    }
+
+    /// Clears the checkpoint of the previous dictation so a new one starts
+    /// from an empty file.
+    pub fn begin_dictation(&self) {
+        self.clear_active();
+    }
+
+    /// Appends recorded audio to the active checkpoint, unless the storage
+    /// policy forbids it.
+    pub fn append_audio(&self, audio: &[u8]) -> Result<(), String> {
+        if !crate::privacy::policy().recovery {
+            return Ok(());
+        }
+        let dir = self.active_dir();
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(RECORDING_FILE))
+            .map_err(|e| e.to_string())?;
+        file.write_all(audio).map_err(|e| e.to_string())
+    }
+
+    /// Saves a transcript that is about to be pasted, unless the storage
+    /// policy forbids it.
+    ///
+    /// Written to a temporary file and renamed so a crash mid-write never
+    /// leaves a truncated transcript.
+    pub fn save_pending(&self, text: &str) -> Result<(), String> {
+        if !crate::privacy::policy().recovery {
+            return Ok(());
+        }
+        let dir = self.active_dir();
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let pending = PendingTranscript {
+            text: text.to_string(),
+            created_at_ms: now_ms(),
+        };
+        let json = serde_json::to_vec(&pending).map_err(|e| e.to_string())?;
+
+        let temp = dir.join(format!("{}.tmp", PENDING_FILE));
+        fs::write(&temp, json)
+            .and_then(|()| fs::rename(&temp, dir.join(PENDING_FILE)))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Removes the active checkpoint after the dictation was pasted or
+    /// cancelled.
+    pub fn clear_active(&self) {
+        let dir = self.active_dir();
+        for file in [RECORDING_FILE, PENDING_FILE] {
+            match fs::remove_file(dir.join(file)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => tracing::warn!("Failed to clear recovery file {}: {}", file, e),
+            }
+        }
+    }
+
+    /// Drops the recovered leftovers.
+    pub fn discard(&self) -> Result<(), String> {
+        *self.recovered.lock().unwrap() = None;
+        match fs::remove_dir_all(self.dir.join(RECOVERED_DIR)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// Whether `dir` exists and contains any files.
+fn has_content(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_some())
+}
+
+/// Reads the leftovers in `dir`, if there are any.
+fn load_recovery(dir: &Path) -> Option<PendingRecovery> {
+    let transcript = fs::read(dir.join(PENDING_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_slice::<PendingTranscript>(&json).ok());
+
+    let audio_path = dir.join(RECORDING_FILE);
+    let audio_modified = fs::metadata(&audio_path)
+        .ok()
+        .filter(|metadata| metadata.len() > 0)
+        .and_then(|metadata| metadata.modified().ok())
+        .map(|modified| {
+            modified
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or_default()
+        });
+
+    let created_at_ms = transcript
+        .as_ref()
+        .map(|pending| pending.created_at_ms)
+        .into_iter()
+        .chain(audio_modified)
+        .max()?;
+
+    Some(PendingRecovery {
+        transcript: transcript.map(|pending| pending.text),
+        audio_path: audio_modified.map(|_| audio_path),
+        created_at_ms,
+    })
+}
+
+/// Time elapsed since `at_ms`.
+fn age(at_ms: i64) -> Duration {
+    Duration::from_millis(now_ms().saturating_sub(at_ms).max(0) as u64)
+}
+
+/// Current time as Unix milliseconds.
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Appends a chunk of the recording in progress to the crash checkpoint.
+///
+/// The body is the chunk, as raw bytes, and the `x-session-id` header names
+/// the dictation it was recorded for. A chunk arriving after its dictation
+/// ended, cancelled or not, is dropped.
+///
+/// # Errors
+///
+/// Returns an error if the body isn't raw bytes or the checkpoint file
+/// cannot be written.
+#[tauri::command]
+pub fn checkpoint_recording(
+    app: AppHandle,
+    state: State<'_, RecoveryState>,
+    request: Request<'_>,
+) -> Result<(), String> {
+    let InvokeBody::Raw(audio) = request.body() else {
+        return Err("the recording must be sent as raw bytes".to_string());
+    };
+    let session_id = request
+        .headers()
+        .get("x-session-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if let Err(e) = app_state::check_session(&app, session_id) {
+        tracing::debug!("Dropping a checkpoint: {}", e);
+        return Ok(());
+    }
+    state.append_audio(audio)
+}
+
+/// Returns what was left behind by a dictation interrupted in the previous
+/// run, if anything.
+#[tauri::command]
+pub fn get_pending_recovery(state: State<'_, RecoveryState>) -> Option<PendingRecovery> {
+    state.pending()
+}
+
+/// Recovers an interrupted dictation.
+///
+/// A recovered transcript is pasted into the current app (through the same
+/// path as a fresh dictation) and the recovery cleared. If only audio
+/// survived, its path is returned for transcription and the recovery is kept
+/// until [`discard_pending`] is called.
+///
+/// # Errors
+///
+/// Returns an error if pasting fails; the recovery is kept in that case.
+#[tauri::command]
+pub async fn recover_pending(
+    app: AppHandle,
+    state: State<'_, RecoveryState>,
+) -> Result<RecoveryOutcome, String> {
+    let Some(pending) = state.pending() else {
+        return Ok(RecoveryOutcome::Nothing);
+    };
+
+    if let Some(text) = pending.transcript {
+        let pasted = crate::paste_text(&app, &text, None, crate::WhenBusy::Queue, false)
+            .await
+            .map_err(|e| e.to_string())?;
+        state.discard()?;
+        return Ok(RecoveryOutcome::Pasted { text: pasted });
+    }
+
+    Ok(match pending.audio_path {
+        Some(audio_path) => RecoveryOutcome::NeedsTranscription { audio_path },
+        None => RecoveryOutcome::Nothing,
+    })
+}
+
+/// Discards an interrupted dictation's recovered audio and transcript.
+///
+/// # Errors
+///
+/// Returns an error if the recovery files cannot be deleted.
+#[tauri::command]
+pub fn discard_pending(state: State<'_, RecoveryState>) -> Result<(), String> {
+    state.discard()
+}
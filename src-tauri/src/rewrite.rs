@@ -0,0 +1,309 @@
+//! LLM rewrite of transcripts with tone presets.
+//!
+//! Sends a transcript to an OpenAI-compatible chat-completion endpoint with a
+//! preset-specific system prompt (formal, casual, concise, bullet points) and
+//! returns the rewritten text, streaming partial output to the frontend as
+//! `rewrite:partial` events.
+//!
+//! # Architecture Decision
+//!
+//! Rewriting is an enhancement, never a dependency: every failure (missing
+//! key, timeout, HTTP error, empty response) falls back to the original
+//! transcript so dictation keeps working when the LLM is down. The command
+//! therefore always succeeds and reports whether the rewrite actually
+//! happened in [`RewriteResult`].
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, State};
+
+use crate::cancellation;
+use crate::postprocess;
+use crate::secrets;
+use crate::settings;
+use crate::AppHandle;
+
+/// Bounds for the request timeout, in milliseconds.
+const MIN_TIMEOUT_MS: u64 = 1_000;
+const MAX_TIMEOUT_MS: u64 = 60_000;
+
+/// Configuration for the rewrite endpoint and its prompt presets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RewriteConfig {
+    /// Full URL of the chat-completion endpoint.
+    pub endpoint: String,
+    pub model: String,
+    /// Hard limit for the whole request, including the streamed body.
+    pub timeout_ms: u64,
+    /// System prompt per preset name. User-editable.
+    pub presets: BTreeMap<String, String>,
+}
+
+impl Default for RewriteConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://api.openai.com/v1/chat/completions".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            timeout_ms: 8_000,
+            presets: default_presets(),
+        }
+    }
+}
+
+impl RewriteConfig {
+    /// Validates a configuration supplied by the frontend.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.endpoint.starts_with("https://") && !self.endpoint.starts_with("http://") {
+            return Err("endpoint must be an http(s) URL".to_string());
+        }
+        if self.model.trim().is_empty() {
+            return Err("model must not be empty".to_string());
+        }
+        if !(MIN_TIMEOUT_MS..=MAX_TIMEOUT_MS).contains(&self.timeout_ms) {
+            return Err(format!(
+                "timeout_ms must be between {} and {}",
+                MIN_TIMEOUT_MS, MAX_TIMEOUT_MS
+            ));
+        }
+        if let Some((name, _)) = self.presets.iter().find(|(_, p)| p.trim().is_empty()) {
+            return Err(format!("preset \"{}\" has an empty prompt", name));
+        }
+        Ok(())
+    }
+}
+
+/// Built-in presets, used until the user edits them.
+pub fn default_presets() -> BTreeMap<String, String> {
+    const INSTRUCTIONS: &str = "Rewrite the user's dictated text. Preserve its meaning and language. \
+        Reply with the rewritten text only, without quotes or commentary.";
+
+    [
+        ("formal", "Use a professional, formal tone suitable for work email."),
+        ("casual", "Use a relaxed, friendly tone suitable for chat."),
+        ("concise", "Make it as short as possible without losing information."),
+        ("bullet-points", "Restructure it as a list of short bullet points starting with \"- \"."),
+    ]
+    .into_iter()
+    .map(|(name, style)| (name.to_string(), format!("{} {}", INSTRUCTIONS, style)))
+    .collect()
+}
+
+/// Managed state holding the live rewrite configuration.
+#[derive(Default)]
+pub struct RewriteState(pub Mutex<RewriteConfig>);
+
+/// Payload of the `rewrite:partial` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct RewritePartial {
+    pub preset: String,
+    /// Rewritten text accumulated so far.
+    pub text: String,
+}
+
+/// Result of [`rewrite_text`].
+#[derive(Debug, Clone, Serialize)]
+pub struct RewriteResult {
+    /// Rewritten text, or the original text if the rewrite failed.
+    pub text: String,
+    pub rewritten: bool,
+    /// Why the rewrite fell back to the original text, if it did.
+    pub error: Option<String>,
+}
+
+/// Rewrites `text` using the given preset.
+///
+/// Partial output is emitted as `rewrite:partial` events while the response
+/// streams in.
+///
+/// # Architecture Decision
+///
+/// This command never fails: on any error the original text is returned with
+/// `rewritten: false` so callers in the paste path don't need a fallback of
+/// their own.
+///
+/// The rewrite of a dictation's transcript (`session_id`) is aborted once
+/// the dictation is cancelled (see [`crate::cancellation`]), and returns the
+/// original text the same way. Its rewritten text goes through
+/// post-processing again (see [`postprocess::reprocess`]), like the
+/// transcript it came from.
+#[tauri::command]
+pub async fn rewrite_text(
+    app: AppHandle,
+    state: State<'_, RewriteState>,
+    text: String,
+    preset: String,
+    session_id: Option<String>,
+) -> Result<RewriteResult, String> {
+    let config = state.0.lock().unwrap().clone();
+
+    let requested = request_rewrite(&app, &config, &text, &preset);
+    let rewritten = match session_id.clone().map(|id| cancellation::token(&app, &id)) {
+        Some(Ok(cancel)) => cancel.run(requested).await,
+        Some(Err(_)) => None,
+        None => Some(requested.await),
+    };
+    let Some(rewritten) = rewritten else {
+        tracing::info!("Rewrite stopped: the dictation was cancelled");
+        return Ok(RewriteResult {
+            text,
+            rewritten: false,
+            error: Some("the dictation was cancelled".to_string()),
+        });
+    };
+    match rewritten {
+        Ok(rewritten) => Ok(RewriteResult {
+            text: match session_id {
+                Some(id) => postprocess::reprocess(&app, &id, &rewritten).text,
+                None => rewritten,
+            },
+            rewritten: true,
+            error: None,
+        }),
+        Err(e) => {
+            tracing::warn!("Rewrite failed, using original text: {}", e);
+            Ok(RewriteResult {
+                text,
+                rewritten: false,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+/// Returns the current rewrite configuration.
+#[tauri::command]
+pub fn get_rewrite_config(state: State<'_, RewriteState>) -> RewriteConfig {
+    state.0.lock().unwrap().clone()
+}
+
+/// Replaces the rewrite configuration (endpoint, model, timeout, presets).
+///
+/// # Errors
+///
+/// Returns an error if the configuration fails validation or the settings
+/// cannot be saved.
+#[tauri::command]
+pub fn set_rewrite_config(app: AppHandle, config: RewriteConfig) -> Result<(), String> {
+    settings::update(&app, |settings| {
+        settings.rewrite = config;
+        Ok(())
+    })
+    .map(|_| ())
+}
+
+/// Stores the rewrite API key in the OS keychain. An empty key deletes it.
+///
+/// # Errors
+///
+/// Returns an error if the keychain rejects the operation.
+#[tauri::command]
+pub fn set_rewrite_api_key(key: String) -> Result<(), String> {
+    if key.trim().is_empty() {
+        secrets::delete(secrets::REWRITE_API_KEY)
+    } else {
+        secrets::set(secrets::REWRITE_API_KEY, key.trim())
+    }
+}
+
+/// Performs the streaming chat-completion request.
+async fn request_rewrite(
+    app: &AppHandle,
+    config: &RewriteConfig,
+    text: &str,
+    preset: &str,
+) -> Result<String, String> {
+    let system_prompt = config
+        .presets
+        .get(preset)
+        .ok_or_else(|| format!("unknown rewrite preset \"{}\"", preset))?;
+    let api_key = secrets::get(secrets::REWRITE_API_KEY)?
+        .ok_or_else(|| "no rewrite API key configured".to_string())?;
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let body = serde_json::json!({
+        "model": config.model,
+        "stream": true,
+        "messages": [
+            { "role": "system", "content": system_prompt },
+            { "role": "user", "content": text },
+        ],
+    });
+
+    let mut response = client
+        .post(&config.endpoint)
+        .bearer_auth(api_key)
+        .json(&body)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?;
+
+    // The body is a server-sent event stream: one `data: {...}` line per
+    // delta, terminated by `data: [DONE]`. Chunks can split lines (and UTF-8
+    // sequences), so buffer raw bytes until a full line is available
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut rewritten = String::new();
+
+    'stream: while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(newline) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            match parse_sse_line(&String::from_utf8_lossy(&line)) {
+                SseLine::Delta(delta) => {
+                    rewritten.push_str(&delta);
+                    let _ = app.emit(
+                        "rewrite:partial",
+                        RewritePartial {
+                            preset: preset.to_string(),
+                            text: rewritten.clone(),
+                        },
+                    );
+                }
+                SseLine::Done => break 'stream,
+                SseLine::Ignore => {}
+            }
+        }
+    }
+
+    let rewritten = rewritten.trim().to_string();
+    if rewritten.is_empty() {
+        return Err("rewrite returned no text".to_string());
+    }
+
+    Ok(rewritten)
+}
+
+/// A parsed line from a chat-completion event stream.
+enum SseLine {
+    Delta(String),
+    Done,
+    Ignore,
+}
+
+fn parse_sse_line(line: &str) -> SseLine {
+    let Some(data) = line.trim().strip_prefix("data:") else {
+        return SseLine::Ignore;
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return SseLine::Done;
+    }
+
+    serde_json::from_str::<serde_json::Value>(data)
+        .ok()
+        .and_then(|event| {
+            event["choices"][0]["delta"]["content"]
+                .as_str()
+                .map(str::to_string)
+        })
+        .map_or(SseLine::Ignore, SseLine::Delta)
+}
@@ -0,0 +1,61 @@
+//! OS keychain access for secrets (API keys, tokens).
+//!
+//! Secrets never live in settings files or managed state; they are read from
+//! the platform credential store (Keychain on macOS, Credential Manager on
+//! Windows, Secret Service on Linux) at the moment they are needed.
+
+use keyring::Entry;
+
+/// Service name under which all of the app's credentials are stored.
+const SERVICE: &str = "com.apurvp.wispr-clone";
+
+/// Keychain account for the chat-completion API key used by text rewriting.
+pub const REWRITE_API_KEY: &str = "rewrite-api-key";
+
+/// Keychain account for the history database encryption key.
+pub const HISTORY_DATABASE_KEY: &str = "history-database-key";
+
+/// Keychain account for the bearer token sent to the webhook.
+pub const WEBHOOK_TOKEN: &str = "webhook-token";
+
+/// Keychain account for the token the local API requires.
+pub const API_TOKEN: &str = "api-token";
+
+/// Reads a secret, returning `None` when no entry exists.
+///
+/// # Errors
+///
+/// Returns an error if the credential store is unavailable or the entry
+/// cannot be decoded.
+pub fn get(account: &str) -> Result<Option<String>, String> {
+    let entry = Entry::new(SERVICE, account).map_err(|e| e.to_string())?;
+    match entry.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Stores a secret, replacing any existing value.
+///
+/// # Errors
+///
+/// Returns an error if the credential store rejects the write.
+pub fn set(account: &str, secret: &str) -> Result<(), String> {
+    Entry::new(SERVICE, account)
+        .and_then(|entry| entry.set_password(secret))
+        .map_err(|e| e.to_string())
+}
+
+/// Deletes a secret. Deleting a missing entry is not an error.
+///
+/// # Errors
+///
+/// Returns an error if the credential store rejects the deletion.
+pub fn delete(account: &str) -> Result<(), String> {
+    let entry = Entry::new(SERVICE, account).map_err(|e| e.to_string())?;
+    match entry.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,222 @@
+//! Settings bundles for moving a setup to another machine.
+//!
+//! A bundle is a single JSON file holding every user setting (hotkeys,
+//! post-processing rules such as emoji aliases and profanity words, rewrite
+//! presets, retention...) under a versioned envelope:
+//!
+//! ```json
+//! {
+//!   "format": "wispr-clone-settings-bundle",
+//!   "bundle_version": 1,
+//!   "exported_at_ms": 1767225600000,
+//!   "app_version": "0.1.0",
+//!   "settings": { "schema_version": 3, "...": "..." }
+//! }
+//! ```
+//!
+//! # Architecture Decision
+//!
+//! Secrets are never part of a bundle. API keys and tokens, the local API's
+//! included, live only in the system keychain and are not fields of
+//! [`Settings`], so exporting the settings document cannot leak them; each
+//! machine stores its own keys.
+//!
+//! The bundled settings carry their own schema version and go through the
+//! same migration chain as `settings.json`. Unlike a settings file written by
+//! a newer build, a bundle from a newer schema is rejected rather than
+//! loaded best-effort: an import applies completely or not at all.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tauri::Manager;
+
+use super::migrate::{self, SCHEMA_VERSION};
+use super::{changed_keys, merge_patch, now_ms, update, Settings, SettingsState};
+
+use crate::AppHandle;
+
+/// Marker identifying a settings bundle file.
+const BUNDLE_FORMAT: &str = "wispr-clone-settings-bundle";
+
+/// Current version of the bundle envelope.
+const BUNDLE_VERSION: u32 = 1;
+
+/// What an import changed (or would change, for a dry run).
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleImport {
+    /// Dotted paths of the settings that differ from the current ones.
+    pub changed: Vec<String>,
+    /// Whether the changes were applied (`false` for dry runs).
+    pub applied: bool,
+    /// Schema version the bundled settings were migrated from, if older.
+    pub migrated_from: Option<u32>,
+}
+
+/// Settings read out of a bundle, migrated to [`SCHEMA_VERSION`].
+struct BundleSettings {
+    settings: Map<String, Value>,
+    migrated_from: Option<u32>,
+}
+
+/// Writes the current settings to `path` as a bundle.
+fn export(app: &AppHandle, path: &Path) -> Result<(), String> {
+    let settings = app.state::<SettingsState>().get();
+    let mut settings = serde_json::to_value(settings).map_err(|e| e.to_string())?;
+    if let Value::Object(object) = &mut settings {
+        object.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+    }
+
+    let bundle = serde_json::json!({
+        "format": BUNDLE_FORMAT,
+        "bundle_version": BUNDLE_VERSION,
+        "exported_at_ms": now_ms(),
+        "app_version": app.package_info().version.to_string(),
+        "settings": settings,
+    });
+    let json = serde_json::to_vec_pretty(&bundle).map_err(|e| e.to_string())?;
+
+    // Same temporary file and rename as settings.json, so an existing bundle
+    // is never left truncated
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, json)
+        .and_then(|()| fs::rename(&temp, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            format!("failed to write settings bundle: {}", e)
+        })
+}
+
+/// Reads and checks a bundle file, returning its migrated settings.
+fn read(path: &Path) -> Result<BundleSettings, String> {
+    let json = fs::read(path).map_err(|e| format!("failed to read settings bundle: {}", e))?;
+    let bundle: Value =
+        serde_json::from_slice(&json).map_err(|e| format!("invalid settings bundle: {}", e))?;
+
+    if bundle.get("format").and_then(Value::as_str) != Some(BUNDLE_FORMAT) {
+        return Err("not a settings bundle".to_string());
+    }
+    let bundle_version = bundle
+        .get("bundle_version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| "settings bundle has no valid bundle_version".to_string())?;
+    if bundle_version > u64::from(BUNDLE_VERSION) {
+        return Err(format!(
+            "settings bundle version {} is newer than this build supports ({})",
+            bundle_version, BUNDLE_VERSION
+        ));
+    }
+
+    let Some(Value::Object(mut settings)) = bundle.get("settings").cloned() else {
+        return Err("settings bundle has no settings object".to_string());
+    };
+    let version = match settings.remove("schema_version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|&version| version >= 1)
+            .ok_or_else(|| "schema_version: must be a positive integer".to_string())?,
+    };
+    settings.remove("last_migration");
+
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "bundle settings use schema {}, this build reads up to {}",
+            version, SCHEMA_VERSION
+        ));
+    }
+    migrate::migrate(&mut settings, version);
+
+    Ok(BundleSettings {
+        settings,
+        migrated_from: (version < SCHEMA_VERSION).then_some(version),
+    })
+}
+
+/// Builds the settings an import results in. With `merge`, settings present
+/// in the bundle override `current` and everything else is kept; otherwise
+/// the bundle replaces the settings entirely and missing ones use defaults.
+fn resolve(
+    current: &Settings,
+    bundle: Map<String, Value>,
+    merge: bool,
+) -> Result<Settings, String> {
+    let resolved = if merge {
+        let mut merged = serde_json::to_value(current).map_err(|e| e.to_string())?;
+        merge_patch(&mut merged, Value::Object(bundle));
+        merged
+    } else {
+        Value::Object(bundle)
+    };
+
+    let settings: Settings = serde_json::from_value(resolved)
+        .map_err(|e| format!("invalid settings in bundle: {}", e))?;
+    settings.validate()?;
+    Ok(settings)
+}
+
+/// Exports every setting to a bundle file at `path`. Keychain secrets (API
+/// keys) are never included.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written.
+#[tauri::command]
+pub fn export_settings_bundle(app: AppHandle, path: PathBuf) -> Result<(), String> {
+    export(&app, &path)
+}
+
+/// Imports a settings bundle written by [`export_settings_bundle`].
+///
+/// With `merge`, only the settings in the bundle change; otherwise they
+/// replace the current settings. With `dry_run`, nothing is applied and the
+/// result lists what would change, for a confirmation step.
+///
+/// The import is all-or-nothing: the bundle is fully migrated and validated
+/// before anything is written.
+///
+/// # Errors
+///
+/// Returns an error if the file is missing or corrupt, was written by a
+/// newer bundle or settings schema, contains any invalid setting, or if the
+/// settings cannot be saved. Nothing is changed in that case.
+#[tauri::command]
+pub fn import_settings_bundle(
+    app: AppHandle,
+    path: PathBuf,
+    merge: bool,
+    dry_run: Option<bool>,
+) -> Result<BundleImport, String> {
+    let BundleSettings {
+        settings: bundle,
+        migrated_from,
+    } = read(&path)?;
+
+    if dry_run.unwrap_or(false) {
+        let current = app.state::<SettingsState>().get();
+        let imported = resolve(&current, bundle, merge)?;
+        return Ok(BundleImport {
+            changed: changed_keys(&current, &imported),
+            applied: false,
+            migrated_from,
+        });
+    }
+
+    let mut changed = Vec::new();
+    update(&app, |settings| {
+        let imported = resolve(settings, bundle, merge)?;
+        changed = changed_keys(settings, &imported);
+        *settings = imported;
+        Ok(())
+    })?;
+
+    tracing::info!("Imported settings bundle ({} changed)", changed.len());
+    Ok(BundleImport {
+        changed,
+        applied: true,
+        migrated_from,
+    })
+}
@@ -0,0 +1,257 @@
+//! Settings schema versions and migrations.
+//!
+//! The settings file records the `schema_version` it was written with. Older
+//! files are upgraded at load time by running every migration step between
+//! their version and [`SCHEMA_VERSION`], in order.
+//!
+//! Steps operate on the raw JSON object rather than on [`Settings`], so they
+//! keep working after the struct moves on: each one only knows the shape of
+//! the version it upgrades from. Never edit a released step; append a new
+//! one and bump [`SCHEMA_VERSION`].
+//!
+//! Saved profiles (see [`super::profiles`]) hold settings in the shape of
+//! the same version, so every step upgrades each of them as well.
+//!
+//! A file from a newer schema is never downgraded: the top-level settings
+//! this build doesn't understand are kept as they were ([`NewerFile`]) and
+//! written back, along with the newer version, on every save.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::Settings;
+
+/// Schema version written by this build.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Migration steps. Entry `i` upgrades a file from version `i + 1` to
+/// `i + 2`.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[v1_to_v2, v2_to_v3];
+
+/// A migration applied to the settings file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationRecord {
+    pub from_version: u32,
+    pub to_version: u32,
+    /// Unix milliseconds.
+    pub migrated_at_ms: i64,
+    /// Copy of the file as it was before the migration.
+    pub backup_path: std::path::PathBuf,
+}
+
+/// Upgrades `settings` from schema `from` to [`SCHEMA_VERSION`].
+pub(super) fn migrate(settings: &mut Map<String, Value>, from: u32) {
+    let first = from.saturating_sub(1) as usize;
+    for step in MIGRATIONS.iter().skip(first) {
+        step(settings);
+        let saved = settings
+            .get_mut("profiles")
+            .and_then(|profiles| profiles.get_mut("saved"))
+            .and_then(Value::as_object_mut);
+        for profile in saved.into_iter().flat_map(|saved| saved.values_mut()) {
+            if let Value::Object(profile) = profile {
+                step(profile);
+            }
+        }
+    }
+}
+
+/// v2: `provider` and `language` moved into a `transcription` group.
+fn v1_to_v2(settings: &mut Map<String, Value>) {
+    let mut transcription = Map::new();
+    for key in ["provider", "language"] {
+        if let Some(value) = settings.remove(key) {
+            transcription.insert(key.to_string(), value);
+        }
+    }
+    if !transcription.is_empty() {
+        settings.insert("transcription".to_string(), Value::Object(transcription));
+    }
+}
+
+/// What a file from a newer schema held that this build keeps as is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct NewerFile {
+    /// The schema version it was written with.
+    pub version: u32,
+    /// The top-level settings this build ignored, as they were in the file.
+    pub ignored: Map<String, Value>,
+}
+
+impl NewerFile {
+    /// Puts what was kept back into `settings`, the JSON object about to be
+    /// written.
+    pub fn restore(&self, settings: &mut Map<String, Value>) {
+        for (key, value) in &self.ignored {
+            settings.insert(key.clone(), value.clone());
+        }
+        settings.insert("schema_version".to_string(), self.version.into());
+    }
+}
+
+/// v3: the local API token moved to the keychain. It can't be carried over
+/// from here, so a new one is generated the next time the API starts.
+fn v2_to_v3(settings: &mut Map<String, Value>) {
+    if let Some(Value::Object(api)) = settings.get_mut("api") {
+        api.remove("token");
+    }
+}
+
+/// Loads a file written by a newer schema as far as this build understands
+/// it, returning the settings and the top-level settings that had to be
+/// ignored.
+///
+/// Each top-level setting is taken from the file only if it still parses and
+/// validates on its own; anything else keeps its default.
+pub(super) fn best_effort(file: Value) -> (Settings, Map<String, Value>) {
+    let mut accepted = serde_json::to_value(Settings::default()).unwrap_or_default();
+    let mut ignored = Map::new();
+
+    if let Value::Object(file) = file {
+        for (key, value) in file {
+            let mut candidate = accepted.clone();
+            let Some(slot) = candidate.get_mut(&key) else {
+                ignored.insert(key, value);
+                continue;
+            };
+            *slot = value.clone();
+
+            let parses = serde_json::from_value::<Settings>(candidate.clone())
+                .is_ok_and(|settings| settings.validate().is_ok());
+            if parses {
+                accepted = candidate;
+            } else {
+                ignored.insert(key, value);
+            }
+        }
+    }
+
+    let settings = serde_json::from_value(accepted).unwrap_or_default();
+    (settings, ignored)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(object) => object,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn every_step_is_listed() {
+        assert_eq!(MIGRATIONS.len() as u32, SCHEMA_VERSION - 1);
+    }
+
+    #[test]
+    fn v1_to_v2_moves_provider_and_language() {
+        let mut settings = object(json!({
+            "provider": "deepgram",
+            "language": "fr-FR",
+            "paste_delay_ms": 200,
+        }));
+        v1_to_v2(&mut settings);
+        assert_eq!(
+            Value::Object(settings),
+            json!({
+                "transcription": { "provider": "deepgram", "language": "fr-FR" },
+                "paste_delay_ms": 200,
+            })
+        );
+    }
+
+    #[test]
+    fn v1_to_v2_leaves_a_file_without_them_alone() {
+        let mut settings = object(json!({ "paste_delay_ms": 200 }));
+        v1_to_v2(&mut settings);
+        assert_eq!(Value::Object(settings), json!({ "paste_delay_ms": 200 }));
+    }
+
+    #[test]
+    fn v2_to_v3_drops_the_api_token() {
+        let mut settings = object(json!({
+            "api": { "enabled": true, "port": 47823, "token": "secret" },
+        }));
+        v2_to_v3(&mut settings);
+        assert_eq!(
+            Value::Object(settings),
+            json!({ "api": { "enabled": true, "port": 47823 } })
+        );
+    }
+
+    #[test]
+    fn migrated_v2_file_parses() {
+        let mut settings = object(json!({ "api": { "enabled": true, "token": "secret" } }));
+        migrate(&mut settings, 2);
+        let settings: Settings = serde_json::from_value(Value::Object(settings)).unwrap();
+        assert!(settings.api.enabled);
+    }
+
+    #[test]
+    fn migrated_v1_file_parses() {
+        let mut settings = object(json!({ "provider": "deepgram", "language": "de-DE" }));
+        migrate(&mut settings, 1);
+        let settings: Settings = serde_json::from_value(Value::Object(settings)).unwrap();
+        assert_eq!(settings.transcription.language, "de-DE");
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn migrate_upgrades_saved_profiles() {
+        let mut settings = object(json!({
+            "language": "en-GB",
+            "profiles": { "active": null, "saved": { "work": { "language": "es-ES" } } },
+        }));
+        migrate(&mut settings, 1);
+        assert_eq!(
+            settings["profiles"]["saved"]["work"],
+            json!({ "transcription": { "language": "es-ES" } })
+        );
+        assert_eq!(settings["transcription"], json!({ "language": "en-GB" }));
+    }
+
+    #[test]
+    fn migrate_from_the_current_version_does_nothing() {
+        let original = object(json!({ "provider": "deepgram" }));
+        let mut settings = original.clone();
+        migrate(&mut settings, SCHEMA_VERSION);
+        assert_eq!(settings, original);
+    }
+
+    #[test]
+    fn best_effort_keeps_what_it_ignores() {
+        let file = json!({
+            "paste_delay_ms": 200,
+            "from_the_future": { "enabled": true },
+            "transcription": { "provider": "deepgram", "engine": "v9" },
+        });
+        let (settings, ignored) = best_effort(file);
+        assert_eq!(settings.paste_delay_ms, 200);
+        assert_eq!(settings.transcription, Settings::default().transcription);
+        assert_eq!(
+            Value::Object(ignored),
+            json!({
+                "from_the_future": { "enabled": true },
+                "transcription": { "provider": "deepgram", "engine": "v9" },
+            })
+        );
+    }
+
+    #[test]
+    fn newer_file_is_restored_with_its_version() {
+        let newer = NewerFile {
+            version: SCHEMA_VERSION + 1,
+            ignored: object(json!({ "from_the_future": 1 })),
+        };
+        let mut settings = object(json!({ "paste_delay_ms": 200, "schema_version": 2 }));
+        newer.restore(&mut settings);
+        assert_eq!(settings["from_the_future"], json!(1));
+        assert_eq!(settings["schema_version"], json!(SCHEMA_VERSION + 1));
+        assert_eq!(settings["paste_delay_ms"], json!(200));
+    }
+}
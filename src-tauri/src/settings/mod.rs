@@ -0,0 +1,1056 @@
+//! Persistent application settings.
+//!
+//! All user-configurable behavior lives in one serde-defined [`Settings`]
+//! struct, stored as `settings.json` in the app data directory and held in
+//! managed state. Subsystems keep their own live configuration (e.g.
+//! [`PostProcessState`]); every settings change is pushed into them and
+//! broadcast as a `settings:changed` event so long-lived subsystems (hotkeys,
+//! audio) can react.
+//!
+//! # Architecture Decision
+//!
+//! Updates are partial objects merged into the current settings, which are
+//! then validated as a whole, field by field. A patch either applies
+//! completely or not at all: invalid values, unknown fields and unknown
+//! provider names are rejected with every problem listed, and nothing is
+//! written. Writes go to a temporary file that is renamed over the old one,
+//! so a crash never leaves a truncated settings file.
+//!
+//! A missing file yields the defaults; an unreadable or invalid one is logged
+//! and also yields the defaults, without overwriting the file until the user
+//! next changes a setting.
+//!
+//! # Submodules
+//!
+//! - [`bundle`]: Import/export of all settings as a portable file
+//! - [`migrate`]: Schema versions and the migration chain run at load time
+//! - [`profiles`]: Named sets of settings to switch between
+//! - [`watch`]: Hot reload when the file is edited outside the app
+
+pub mod bundle;
+pub mod migrate;
+pub mod profiles;
+pub mod watch;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{Emitter, Manager, State};
+
+use crate::api::{self, ApiSettings};
+use crate::app_paste::AppPasteSettings;
+use crate::capture::CaptureSettings;
+use crate::chunking::ChunkingSettings;
+use crate::clipboard_manager::ClipboardManagerSettings;
+use crate::command_mode::CommandModeSettings;
+use crate::confirm::ConfirmSettings;
+use crate::dictation_log::DictationLogSettings;
+use crate::dock::{self, DockSettings};
+use crate::external::AutomationSettings;
+use crate::focus::{self, FocusSettings};
+use crate::history::prune::{RetentionConfig, RetentionState};
+use crate::history::stats::{StatsConfig, StatsState};
+use crate::indicator::{self, IndicatorSettings};
+use crate::journal::JournalSettings;
+use crate::logging::{self, LoggingSettings};
+use crate::meeting::MeetingSettings;
+use crate::metrics::{self, MetricsSettings};
+use crate::notifications::NotificationSettings;
+use crate::pill::{self, PillSettings};
+use crate::postprocess::{PostProcessConfig, PostProcessState};
+use crate::privacy::{self, PrivacySettings};
+use crate::recent;
+use crate::recovery::RecoveryConfig;
+use crate::rewrite::{RewriteConfig, RewriteState};
+use crate::ticks::RecordingSettings;
+use crate::transcription::{MockSettings, MOCK_PROVIDER};
+use crate::tray::TraySettings;
+use crate::updater::{self, UpdateSettings};
+use crate::webhook::WebhookSettings;
+use crate::windows;
+use crate::AppHandle;
+use migrate::{MigrationRecord, NewerFile, SCHEMA_VERSION};
+use profiles::ProfileSettings;
+
+/// File name of the settings inside the app data directory.
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Transcription providers the app knows how to use.
+pub const KNOWN_PROVIDERS: &[&str] = &["deepgram"];
+
+/// Bounds for the delay between hiding our window and pasting.
+const MIN_PASTE_DELAY_MS: u64 = 50;
+const MAX_PASTE_DELAY_MS: u64 = 2_000;
+
+/// Longest debounce of the dictation hotkey.
+const MAX_HOTKEY_DEBOUNCE_MS: u64 = 2_000;
+
+/// Which provider transcribes dictations, and in what language.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranscriptionSettings {
+    /// Transcription provider, one of [`KNOWN_PROVIDERS`] or, where it is
+    /// available, `mock`.
+    pub provider: String,
+    /// Language code dictations are transcribed in.
+    pub language: String,
+    /// Languages for dictations into particular apps, by bundle id (process
+    /// name off macOS), instead of `language` (see [`crate::transcription`]).
+    pub app_languages: BTreeMap<String, String>,
+    /// The mock provider, see [`crate::transcription`].
+    pub mock: MockSettings,
+}
+
+impl Default for TranscriptionSettings {
+    fn default() -> Self {
+        Self {
+            provider: "deepgram".to_string(),
+            language: "en-US".to_string(),
+            app_languages: BTreeMap::new(),
+            mock: MockSettings::default(),
+        }
+    }
+}
+
+/// Global hotkeys, in the accelerator syntax of the global-shortcut plugin,
+/// and how presses of the dictation ones are handled (see
+/// [`app_state::press`](crate::app_state::press)).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotkeySettings {
+    /// Starts a dictation.
+    pub dictation: String,
+    /// Starts a dictation that is rewritten before pasting.
+    pub rewrite: String,
+    /// Starts a dictation in command mode.
+    pub command: String,
+    /// Opens the history window.
+    pub history: String,
+    /// Opens the recent transcripts window.
+    pub recent: String,
+    /// Presses this soon after the previous one are ignored.
+    pub debounce_ms: u64,
+    /// A press while the last dictation is being transcribed cancels it and
+    /// starts a new one, instead of being ignored.
+    pub restart_while_transcribing: bool,
+}
+
+impl Default for HotkeySettings {
+    fn default() -> Self {
+        Self {
+            dictation: "Alt+Space".to_string(),
+            rewrite: "Alt+Ctrl+Space".to_string(),
+            command: "Alt+Shift+C".to_string(),
+            history: "Alt+Shift+H".to_string(),
+            recent: "Alt+Shift+V".to_string(),
+            debounce_ms: 250,
+            restart_while_transcribing: false,
+        }
+    }
+}
+
+/// How the app starts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StartupSettings {
+    /// Launch the app when the user logs in. Mirrors the OS login item,
+    /// which is the source of truth (see [`crate::autostart`]).
+    pub launch_at_login: bool,
+    /// Start in the background without showing any window.
+    pub start_hidden: bool,
+}
+
+/// Every user-configurable setting.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub transcription: TranscriptionSettings,
+    /// Delay between hiding our window and sending the paste keystroke.
+    pub paste_delay_ms: u64,
+    pub paste_chunking: ChunkingSettings,
+    pub paste_apps: AppPasteSettings,
+    pub clipboard_manager: ClipboardManagerSettings,
+    pub confirm_before_paste: ConfirmSettings,
+    pub command_mode: CommandModeSettings,
+    pub hotkeys: HotkeySettings,
+    pub recording: RecordingSettings,
+    pub capture: CaptureSettings,
+    pub meeting: MeetingSettings,
+    pub post_processing: PostProcessConfig,
+    pub rewrite: RewriteConfig,
+    pub retention: RetentionConfig,
+    pub privacy: PrivacySettings,
+    pub stats: StatsConfig,
+    pub metrics: MetricsSettings,
+    pub recovery: RecoveryConfig,
+    pub startup: StartupSettings,
+    pub updates: UpdateSettings,
+    pub api: ApiSettings,
+    pub webhook: WebhookSettings,
+    pub journal: JournalSettings,
+    pub dictation_log: DictationLogSettings,
+    pub automation: AutomationSettings,
+    pub tray: TraySettings,
+    pub dock: DockSettings,
+    pub pill: PillSettings,
+    pub indicator: IndicatorSettings,
+    pub focus: FocusSettings,
+    pub logging: LoggingSettings,
+    pub notifications: NotificationSettings,
+    pub profiles: ProfileSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            transcription: TranscriptionSettings::default(),
+            paste_delay_ms: 150,
+            paste_chunking: ChunkingSettings::default(),
+            paste_apps: AppPasteSettings::default(),
+            clipboard_manager: ClipboardManagerSettings::default(),
+            confirm_before_paste: ConfirmSettings::default(),
+            command_mode: CommandModeSettings::default(),
+            hotkeys: HotkeySettings::default(),
+            recording: RecordingSettings::default(),
+            capture: CaptureSettings::default(),
+            meeting: MeetingSettings::default(),
+            post_processing: PostProcessConfig::default(),
+            rewrite: RewriteConfig::default(),
+            retention: RetentionConfig::default(),
+            privacy: PrivacySettings::default(),
+            stats: StatsConfig::default(),
+            metrics: MetricsSettings::default(),
+            recovery: RecoveryConfig::default(),
+            startup: StartupSettings::default(),
+            updates: UpdateSettings::default(),
+            api: ApiSettings::default(),
+            webhook: WebhookSettings::default(),
+            journal: JournalSettings::default(),
+            dictation_log: DictationLogSettings::default(),
+            automation: AutomationSettings::default(),
+            tray: TraySettings::default(),
+            dock: DockSettings::default(),
+            pill: PillSettings::default(),
+            indicator: IndicatorSettings::default(),
+            focus: FocusSettings::default(),
+            logging: LoggingSettings::default(),
+            notifications: NotificationSettings::default(),
+            profiles: ProfileSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Checks every field, returning all problems found as one error.
+    pub fn validate(&self) -> Result<(), String> {
+        let errors = self.problems();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(describe(&errors))
+        }
+    }
+
+    /// Checks every field, returning each problem with the field it is in.
+    pub fn problems(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        let transcription = &self.transcription;
+        let mock = transcription.provider == MOCK_PROVIDER && transcription.mock.available();
+        if !mock && !KNOWN_PROVIDERS.contains(&transcription.provider.as_str()) {
+            errors.push(FieldError::new(
+                "transcription.provider",
+                format!(
+                    "unknown provider \"{}\" (expected one of: {})",
+                    transcription.provider,
+                    KNOWN_PROVIDERS.join(", ")
+                ),
+            ));
+        }
+        if transcription.language.trim().is_empty() {
+            errors.push(FieldError::new(
+                "transcription.language",
+                "must not be empty",
+            ));
+        }
+        for (app, language) in &transcription.app_languages {
+            if app.trim().is_empty() || language.trim().is_empty() {
+                errors.push(FieldError::new(
+                    "transcription.app_languages",
+                    "apps and languages must not be empty",
+                ));
+                break;
+            }
+        }
+        if let Err(e) = transcription.mock.validate() {
+            errors.push(FieldError::new("transcription.mock.delay_ms", e));
+        }
+        if !(MIN_PASTE_DELAY_MS..=MAX_PASTE_DELAY_MS).contains(&self.paste_delay_ms) {
+            errors.push(FieldError::new(
+                "paste_delay_ms",
+                format!(
+                    "must be between {} and {}",
+                    MIN_PASTE_DELAY_MS, MAX_PASTE_DELAY_MS
+                ),
+            ));
+        }
+        if let Err(e) = self.paste_chunking.validate() {
+            errors.push(FieldError::new("paste_chunking", e));
+        }
+        if let Err(e) = self.paste_apps.validate() {
+            errors.push(FieldError::new("paste_apps", e));
+        }
+        if let Err(e) = self.focus.validate() {
+            errors.push(FieldError::new("focus", e));
+        }
+        if let Err(e) = self.confirm_before_paste.validate() {
+            errors.push(FieldError::new("confirm_before_paste", e));
+        }
+        if let Err(e) = self.command_mode.validate() {
+            errors.push(FieldError::new("command_mode", e));
+        }
+        if self.hotkeys.dictation.trim().is_empty() {
+            errors.push(FieldError::new("hotkeys.dictation", "must not be empty"));
+        }
+        if self.hotkeys.rewrite.trim().is_empty() {
+            errors.push(FieldError::new("hotkeys.rewrite", "must not be empty"));
+        }
+        if self.hotkeys.command.trim().is_empty() {
+            errors.push(FieldError::new("hotkeys.command", "must not be empty"));
+        }
+        if self.hotkeys.history.trim().is_empty() {
+            errors.push(FieldError::new("hotkeys.history", "must not be empty"));
+        }
+        if self.hotkeys.recent.trim().is_empty() {
+            errors.push(FieldError::new("hotkeys.recent", "must not be empty"));
+        }
+        if self.hotkeys.debounce_ms > MAX_HOTKEY_DEBOUNCE_MS {
+            errors.push(FieldError::new(
+                "hotkeys.debounce_ms",
+                format!("must be at most {}", MAX_HOTKEY_DEBOUNCE_MS),
+            ));
+        }
+        if let Err(e) = self.recording.validate() {
+            errors.push(FieldError::new("recording", e));
+        }
+        for (field, quality) in [
+            ("capture.dictation", &self.capture.dictation),
+            ("capture.meeting", &self.capture.meeting),
+        ] {
+            if let Err(e) = quality.validate() {
+                errors.push(FieldError::new(field, e));
+            }
+        }
+        if let Err(e) = self.meeting.validate() {
+            errors.push(FieldError::new("meeting", e));
+        }
+        if let Err(e) = self.rewrite.validate() {
+            errors.push(FieldError::new("rewrite", e));
+        }
+        if let Err(e) = self.pill.validate() {
+            errors.push(FieldError::new("pill.level", e));
+        }
+        if let Err(e) = self.pill.position.validate() {
+            errors.push(FieldError::new("pill.position", e));
+        }
+        if let Err(e) = self.retention.validate() {
+            errors.push(FieldError::new("retention", e));
+        }
+        if let Err(e) = self.api.validate() {
+            errors.push(FieldError::new("api.port", e));
+        }
+        if let Err(e) = self.webhook.validate() {
+            errors.push(FieldError::new("webhook.url", e));
+        }
+        if let Err(e) = self.journal.validate() {
+            errors.push(FieldError::new("journal", e));
+        }
+        if let Err(e) = self.dictation_log.validate() {
+            errors.push(FieldError::new("dictation_log", e));
+        }
+        if let Err(e) = self.automation.validate() {
+            errors.push(FieldError::new("automation.callback_schemes", e));
+        }
+        if self.stats.baseline_wpm == 0 {
+            errors.push(FieldError::new("stats.baseline_wpm", "must be at least 1"));
+        }
+        if let Some(Err(e)) = self.stats.weekly_summary.as_ref().map(|s| s.validate()) {
+            errors.push(FieldError::new("stats.weekly_summary.hour", e));
+        }
+        if self.recovery.max_age_hours == 0 {
+            errors.push(FieldError::new(
+                "recovery.max_age_hours",
+                "must be at least 1",
+            ));
+        }
+        errors.extend(self.profiles.problems(self));
+
+        errors
+    }
+}
+
+/// A problem with one setting.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// Dotted path of the offending setting, when known.
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: Some(field.to_string()),
+            message: message.into(),
+        }
+    }
+
+    /// An error that can't be attributed to a single field.
+    fn general(message: impl Into<String>) -> Self {
+        Self {
+            field: None,
+            message: message.into(),
+        }
+    }
+
+    /// Converts a deserialization error, naming the field when serde does
+    /// ("unknown field `foo`").
+    fn from_serde(error: &serde_json::Error) -> Self {
+        let message = error.to_string();
+        let field = message
+            .strip_prefix("unknown field `")
+            .and_then(|rest| rest.split('`').next())
+            .map(str::to_string);
+        Self { field, message }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}: {}", field, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Joins errors into one message.
+fn describe(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Where the loaded settings came from, for the diagnostics screen.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SettingsMeta {
+    /// Schema version this build reads and writes.
+    pub schema_version: u32,
+    /// Schema version found in the file at startup (`None` without a file).
+    pub file_version: Option<u32>,
+    /// The most recent migration applied to the file.
+    pub last_migration: Option<MigrationRecord>,
+    /// Problems found while loading (e.g. a file from a newer version).
+    pub warnings: Vec<String>,
+    /// What a file from a newer version held that is written back as is.
+    #[serde(skip)]
+    newer: Option<NewerFile>,
+}
+
+/// Managed state holding the current settings.
+pub struct SettingsState {
+    path: PathBuf,
+    settings: Mutex<Settings>,
+    meta: Mutex<SettingsMeta>,
+    /// Contents of our own last write, so the file watcher can tell our
+    /// writes apart from external edits.
+    last_written: Mutex<Option<Vec<u8>>>,
+}
+
+impl SettingsState {
+    /// Loads the settings from `data_dir`, migrating older files and falling
+    /// back to the defaults when the file is missing, unreadable or invalid.
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(SETTINGS_FILE);
+        let mut meta = SettingsMeta {
+            schema_version: SCHEMA_VERSION,
+            ..SettingsMeta::default()
+        };
+
+        // Whatever is on disk now (including a migrated file) is in sync
+        let (settings, last_written) = match fs::read(&path) {
+            Ok(json) => match load_file(&path, &json, &mut meta) {
+                Ok(loaded) => (loaded.settings, Some(loaded.on_disk)),
+                Err(errors) => {
+                    let e = describe(&errors);
+                    tracing::warn!("Ignoring settings file {}: {}", path.display(), e);
+                    meta.warnings.push(format!("settings file ignored: {}", e));
+                    (Settings::default(), Some(json))
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (Settings::default(), None),
+            Err(e) => {
+                tracing::warn!("Failed to read settings {}: {}", path.display(), e);
+                (Settings::default(), None)
+            }
+        };
+
+        Self {
+            path,
+            settings: Mutex::new(settings),
+            meta: Mutex::new(meta),
+            last_written: Mutex::new(last_written),
+        }
+    }
+
+    /// Returns a copy of the current settings.
+    pub fn get(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Returns a copy of the load and migration metadata.
+    pub fn meta(&self) -> SettingsMeta {
+        self.meta.lock().unwrap().clone()
+    }
+
+    /// Writes `settings` to disk atomically, keeping what a file from a
+    /// newer version held that this build doesn't understand.
+    fn persist(&self, settings: &Settings) -> Result<(), String> {
+        let meta = self.meta();
+        let written = write_file(
+            &self.path,
+            settings,
+            meta.last_migration.as_ref(),
+            meta.newer.as_ref(),
+        )?;
+        *self.last_written.lock().unwrap() = Some(written);
+        Ok(())
+    }
+}
+
+/// A settings file [`load_file`] read.
+struct LoadedFile {
+    settings: Settings,
+    /// The file as it is on disk now: as read, or as written back after a
+    /// migration.
+    on_disk: Vec<u8>,
+}
+
+/// Parses a settings file, migrating it to [`SCHEMA_VERSION`] if it is older
+/// and loading it best-effort if it is newer.
+fn load_file(
+    path: &Path,
+    json: &[u8],
+    meta: &mut SettingsMeta,
+) -> Result<LoadedFile, Vec<FieldError>> {
+    let mut value: Value =
+        serde_json::from_slice(json).map_err(|e| vec![FieldError::from_serde(&e)])?;
+    let object = value.as_object_mut().ok_or_else(|| {
+        vec![FieldError::general(
+            "settings file must contain a JSON object",
+        )]
+    })?;
+
+    // Files written before versioning existed are version 1
+    let version = match object.remove("schema_version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|&version| version >= 1)
+            .ok_or_else(|| {
+                vec![FieldError::new(
+                    "schema_version",
+                    "must be a positive integer",
+                )]
+            })?,
+    };
+    meta.file_version = Some(version);
+    meta.last_migration = object
+        .remove("last_migration")
+        .and_then(|record| serde_json::from_value(record).ok());
+
+    if version > SCHEMA_VERSION {
+        // Written by a newer build (the user downgraded). Keep a copy before
+        // we ever write over it, load whatever this build understands, and
+        // keep the rest to write back
+        backup(path, json, version).map_err(|e| vec![FieldError::general(e)])?;
+        let (settings, ignored) = migrate::best_effort(value);
+        meta.warnings.push(format!(
+            "settings were written by a newer version of the app (schema {}, this build reads {}); \
+             unrecognized settings use their defaults, and are kept for that version",
+            version, SCHEMA_VERSION
+        ));
+        meta.warnings.extend(
+            ignored
+                .keys()
+                .map(|key| format!("{}: not understood, using defaults", key)),
+        );
+        meta.newer = Some(NewerFile { version, ignored });
+        return Ok(LoadedFile {
+            settings,
+            on_disk: json.to_vec(),
+        });
+    }
+
+    let migrated = version < SCHEMA_VERSION;
+    if migrated {
+        let backup_path = backup(path, json, version).map_err(|e| vec![FieldError::general(e)])?;
+        migrate::migrate(object, version);
+        tracing::info!(
+            "Migrated settings from schema {} to {}",
+            version,
+            SCHEMA_VERSION
+        );
+        meta.last_migration = Some(MigrationRecord {
+            from_version: version,
+            to_version: SCHEMA_VERSION,
+            migrated_at_ms: now_ms(),
+            backup_path,
+        });
+    }
+
+    let settings: Settings =
+        serde_json::from_value(value).map_err(|e| vec![FieldError::from_serde(&e)])?;
+    let problems = settings.problems();
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+
+    meta.newer = None;
+    // Save the migrated file so the migration runs only once
+    let on_disk = if migrated {
+        write_file(path, &settings, meta.last_migration.as_ref(), None)
+            .map_err(|e| vec![FieldError::general(e)])?
+    } else {
+        json.to_vec()
+    };
+    Ok(LoadedFile { settings, on_disk })
+}
+
+/// Writes `settings` with its schema version, via a temporary file and
+/// rename so a crash never leaves a truncated file. What `newer` kept of a
+/// file from a newer version goes back in, with that version. Returns the
+/// bytes written.
+fn write_file(
+    path: &Path,
+    settings: &Settings,
+    last_migration: Option<&MigrationRecord>,
+    newer: Option<&NewerFile>,
+) -> Result<Vec<u8>, String> {
+    let mut value = serde_json::to_value(settings).map_err(|e| e.to_string())?;
+    if let Value::Object(object) = &mut value {
+        object.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+        if let Some(record) = last_migration {
+            let record = serde_json::to_value(record).map_err(|e| e.to_string())?;
+            object.insert("last_migration".to_string(), record);
+        }
+        if let Some(newer) = newer {
+            newer.restore(object);
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_vec_pretty(&value).map_err(|e| e.to_string())?;
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, &json)
+        .and_then(|()| fs::rename(&temp, path))
+        .map_err(|e| format!("failed to save settings: {}", e))?;
+    Ok(json)
+}
+
+/// Copies the original file to `settings.v<version>.backup.json` next to it.
+/// Written once per version: an existing backup is never overwritten.
+fn backup(path: &Path, json: &[u8], version: u32) -> Result<PathBuf, String> {
+    let backup_path = path.with_file_name(format!("settings.v{}.backup.json", version));
+    if !backup_path.exists() {
+        fs::write(&backup_path, json)
+            .map_err(|e| format!("failed to back up settings before migration: {}", e))?;
+    }
+    Ok(backup_path)
+}
+
+/// Current time as Unix milliseconds.
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Applies `f` to a copy of the current settings, then validates, saves,
+/// applies and broadcasts the result. Nothing changes if any step fails.
+///
+/// Every settings mutation goes through here, including the per-feature
+/// commands, so the file on disk always matches the live configuration. A
+/// change to the profile settings is saved in the active profile too (see
+/// [`profiles`]).
+pub fn update(
+    app: &AppHandle,
+    f: impl FnOnce(&mut Settings) -> Result<(), String>,
+) -> Result<Settings, String> {
+    let state = app.state::<SettingsState>();
+    let mut current = state.settings.lock().unwrap();
+
+    let mut updated = current.clone();
+    f(&mut updated)?;
+    profiles::sync_active(&mut updated);
+    updated.validate()?;
+    if updated == *current {
+        return Ok(updated);
+    }
+
+    state.persist(&updated)?;
+    let previous = std::mem::replace(&mut *current, updated.clone());
+    drop(current);
+
+    broadcast(app, &previous, &updated);
+    Ok(updated)
+}
+
+/// Payload of the `settings:changed` event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingsChanged {
+    pub settings: Settings,
+    /// Dotted paths of the settings that changed (e.g. `hotkeys.dictation`).
+    pub changed: Vec<String>,
+}
+
+/// Pushes a change into the subsystems it affects and emits
+/// `settings:changed` listing the changed keys.
+fn broadcast(app: &AppHandle, previous: &Settings, updated: &Settings) {
+    let changed = changed_keys(previous, updated);
+    apply_changed(app, updated, &changed);
+    let _ = app.emit(
+        "settings:changed",
+        SettingsChanged {
+            settings: updated.clone(),
+            changed,
+        },
+    );
+}
+
+/// Dotted paths of every leaf setting that differs between `old` and `new`.
+fn changed_keys(old: &Settings, new: &Settings) -> Vec<String> {
+    fn diff(path: &str, old: &Value, new: &Value, changed: &mut Vec<String>) {
+        match (old, new) {
+            (Value::Object(old), Value::Object(new)) => {
+                let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+                for key in keys {
+                    let path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    let old = old.get(key).unwrap_or(&Value::Null);
+                    let new = new.get(key).unwrap_or(&Value::Null);
+                    diff(&path, old, new, changed);
+                }
+            }
+            _ if old != new => changed.push(path.to_string()),
+            _ => {}
+        }
+    }
+
+    let mut changed = Vec::new();
+    if let (Ok(old), Ok(new)) = (serde_json::to_value(old), serde_json::to_value(new)) {
+        diff("", &old, &new, &mut changed);
+    }
+    changed
+}
+
+/// Whether any of `changed` is `group` or lies inside it.
+pub(crate) fn touches(changed: &[String], group: &str) -> bool {
+    changed.iter().any(|key| {
+        key == group
+            || key
+                .strip_prefix(group)
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// Pushes `settings` into the subsystems whose keys are in `changed`, so
+/// unrelated subsystems aren't reconfigured.
+fn apply_changed(app: &AppHandle, settings: &Settings, changed: &[String]) {
+    if ["privacy", "retention", "logging"]
+        .iter()
+        .any(|group| touches(changed, group))
+    {
+        privacy::apply(settings);
+    }
+    if touches(changed, "privacy.enabled") && settings.privacy.enabled {
+        recent::forget(app);
+    }
+    if touches(changed, "post_processing") {
+        app.state::<PostProcessState>()
+            .config
+            .lock()
+            .unwrap()
+            .clone_from(&settings.post_processing);
+    }
+    if touches(changed, "rewrite") {
+        app.state::<RewriteState>()
+            .0
+            .lock()
+            .unwrap()
+            .clone_from(&settings.rewrite);
+    }
+    if touches(changed, "retention") {
+        app.state::<RetentionState>()
+            .0
+            .lock()
+            .unwrap()
+            .clone_from(&settings.retention);
+    }
+    if touches(changed, "stats") {
+        app.state::<StatsState>()
+            .0
+            .lock()
+            .unwrap()
+            .clone_from(&settings.stats);
+    }
+    if touches(changed, "metrics") {
+        metrics::apply(app, &settings.metrics);
+    }
+    if touches(changed, "updates.channel") {
+        updater::channel_changed(app);
+    }
+    if touches(changed, "api") {
+        api::apply(app, &settings.api);
+    }
+    if touches(changed, "dock") {
+        dock::apply(app, &settings.dock);
+    }
+    if touches(changed, "pill") {
+        pill::apply(app, &settings.pill);
+    }
+    if touches(changed, "indicator") {
+        indicator::sync(app);
+    }
+    if touches(changed, "focus") {
+        focus::sync(app);
+    }
+    if touches(changed, "logging") {
+        logging::apply(&settings.logging);
+    }
+    if touches(changed, "hotkeys.history") {
+        windows::register_history_hotkey(app, &settings.hotkeys.history);
+    }
+    if touches(changed, "hotkeys.recent") {
+        windows::register_recent_hotkey(app, &settings.hotkeys.recent);
+    }
+}
+
+/// Pushes `settings` into the live configuration of every subsystem.
+pub fn apply(app: &AppHandle, settings: &Settings) {
+    privacy::apply(settings);
+    app.state::<PostProcessState>()
+        .config
+        .lock()
+        .unwrap()
+        .clone_from(&settings.post_processing);
+    app.state::<RewriteState>()
+        .0
+        .lock()
+        .unwrap()
+        .clone_from(&settings.rewrite);
+    app.state::<RetentionState>()
+        .0
+        .lock()
+        .unwrap()
+        .clone_from(&settings.retention);
+    app.state::<StatsState>()
+        .0
+        .lock()
+        .unwrap()
+        .clone_from(&settings.stats);
+    metrics::apply(app, &settings.metrics);
+    api::apply(app, &settings.api);
+    dock::apply(app, &settings.dock);
+    windows::register_history_hotkey(app, &settings.hotkeys.history);
+    windows::register_recent_hotkey(app, &settings.hotkeys.recent);
+    pill::apply(app, &settings.pill);
+    logging::apply(&settings.logging);
+}
+
+/// Merges `patch` into `target`: objects merge recursively and any other
+/// value, including `null`, replaces the existing one.
+///
+/// Unlike RFC 7386, `null` is a value rather than a deletion, because
+/// optional settings use it to mean "disabled".
+fn merge_patch(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge_patch(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+/// Returns the schema version and migration history of the settings file,
+/// and the problems found loading it at startup.
+#[tauri::command]
+pub fn get_settings_meta(state: State<'_, SettingsState>) -> SettingsMeta {
+    state.meta()
+}
+
+/// Returns the current settings.
+#[tauri::command]
+pub fn get_settings(state: State<'_, SettingsState>) -> Settings {
+    state.get()
+}
+
+/// Updates settings with a partial object and returns the resulting
+/// settings.
+///
+/// Only the fields present in `patch` change; nested objects are merged, so
+/// `{"rewrite": {"model": "x"}}` leaves the other rewrite fields alone.
+///
+/// # Errors
+///
+/// Returns an error listing every invalid field (unknown fields or provider
+/// names, out-of-range values) or if the settings cannot be saved. Nothing is
+/// changed in that case.
+#[tauri::command]
+pub fn update_settings(app: AppHandle, patch: Value) -> Result<Settings, String> {
+    if !patch.is_object() {
+        return Err("settings patch must be an object".to_string());
+    }
+
+    update(&app, |settings| {
+        let mut merged = serde_json::to_value(&*settings).map_err(|e| e.to_string())?;
+        merge_patch(&mut merged, patch);
+        *settings = serde_json::from_value(merged).map_err(|e| e.to_string())?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A directory of its own for `test`, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "wispr-settings-{}-{}",
+                test,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, settings: Value) {
+            fs::write(self.0.join(SETTINGS_FILE), settings.to_string()).unwrap();
+        }
+
+        fn read(&self) -> Value {
+            serde_json::from_slice(&fs::read(self.0.join(SETTINGS_FILE)).unwrap()).unwrap()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn v1_file_is_migrated_once_and_backed_up() {
+        let dir = TempDir::new("v1");
+        dir.write(json!({ "provider": "deepgram", "language": "de-DE" }));
+
+        let state = SettingsState::load(&dir.0);
+        assert_eq!(state.get().transcription.language, "de-DE");
+        let meta = state.meta();
+        assert_eq!(meta.file_version, Some(1));
+        let migration = meta.last_migration.unwrap();
+        assert_eq!(
+            (migration.from_version, migration.to_version),
+            (1, SCHEMA_VERSION)
+        );
+        assert!(dir.0.join("settings.v1.backup.json").exists());
+
+        let saved = dir.read();
+        assert_eq!(saved["schema_version"], json!(SCHEMA_VERSION));
+        assert_eq!(saved["transcription"]["language"], json!("de-DE"));
+        assert!(saved.get("language").is_none());
+
+        // Loading the saved file again migrates nothing
+        let state = SettingsState::load(&dir.0);
+        assert_eq!(state.meta().file_version, Some(SCHEMA_VERSION));
+        assert_eq!(state.get().transcription.language, "de-DE");
+    }
+
+    #[test]
+    fn migrated_file_is_what_load_reports_on_disk() {
+        let dir = TempDir::new("on-disk");
+        let path = dir.0.join(SETTINGS_FILE);
+        let original = json!({ "language": "it-IT" }).to_string().into_bytes();
+        fs::write(&path, &original).unwrap();
+
+        let mut meta = SettingsMeta::default();
+        let loaded = load_file(&path, &original, &mut meta).unwrap();
+        assert_ne!(loaded.on_disk, original);
+        assert_eq!(loaded.on_disk, fs::read(&path).unwrap());
+        let state = SettingsState::load(&dir.0);
+        let last_written = state.last_written.lock().unwrap().clone();
+        assert_eq!(last_written, Some(loaded.on_disk));
+    }
+
+    #[test]
+    fn newer_file_is_not_downgraded_on_save() {
+        let dir = TempDir::new("newer");
+        let newer = SCHEMA_VERSION + 1;
+        dir.write(json!({
+            "schema_version": newer,
+            "paste_delay_ms": 200,
+            "from_the_future": { "enabled": true },
+        }));
+
+        let state = SettingsState::load(&dir.0);
+        assert_eq!(state.get().paste_delay_ms, 200);
+        assert!(!state.meta().warnings.is_empty());
+
+        let mut updated = state.get();
+        updated.paste_delay_ms = 300;
+        state.persist(&updated).unwrap();
+
+        let saved = dir.read();
+        assert_eq!(saved["schema_version"], json!(newer));
+        assert_eq!(saved["from_the_future"], json!({ "enabled": true }));
+        assert_eq!(saved["paste_delay_ms"], json!(300));
+    }
+
+    #[test]
+    fn current_file_drops_nothing_it_did_not_have() {
+        let dir = TempDir::new("current");
+        dir.write(json!({ "schema_version": SCHEMA_VERSION, "paste_delay_ms": 200 }));
+
+        let state = SettingsState::load(&dir.0);
+        assert!(state.meta().warnings.is_empty());
+        state.persist(&state.get()).unwrap();
+
+        let saved = dir.read();
+        assert_eq!(saved["schema_version"], json!(SCHEMA_VERSION));
+        assert_eq!(saved["paste_delay_ms"], json!(200));
+    }
+}
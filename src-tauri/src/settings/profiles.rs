@@ -0,0 +1,289 @@
+//! Named profiles: sets of settings to switch between, like "work" and
+//! "personal".
+//!
+//! A [`Profile`] holds the settings that depend on what the app is used
+//! for: the transcription provider and language, post-processing (emoji
+//! aliases, profanity words), rewrite presets, command mode, confirming
+//! before pasting, hotkeys, and the webhook and journal outputs. Everything
+//! else belongs to the machine (audio device, privacy, the pill, startup,
+//! logging...) and is the same in every profile. The webhook token is a
+//! secret kept in the keychain, so it is shared too.
+//!
+//! # Architecture Decision
+//!
+//! The profiles are stored in `settings.json` itself, under `profiles`, so
+//! they are saved, migrated, exported and hot-reloaded with the rest of the
+//! settings. The live [`Settings`] are always the active profile's: every
+//! settings update copies the profile settings back into the active
+//! profile, so changing a setting changes it in the profile in use, and
+//! switching is one settings update that loads the other profile's values.
+//! It applies completely or not at all, and only reconfigures what differs
+//! (hotkeys are registered again only if they changed).
+//!
+//! Without any profile the settings are used as they are;
+//! [`create_profile`] saves them as the first one.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, State};
+
+use super::{update, FieldError, HotkeySettings, Settings, SettingsState, TranscriptionSettings};
+use crate::command_mode::CommandModeSettings;
+use crate::confirm::ConfirmSettings;
+use crate::journal::JournalSettings;
+use crate::postprocess::PostProcessConfig;
+use crate::rewrite::RewriteConfig;
+use crate::webhook::WebhookSettings;
+use crate::AppHandle;
+
+/// Longest profile name, in characters.
+const MAX_NAME_CHARS: usize = 64;
+
+/// The settings a profile holds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub transcription: TranscriptionSettings,
+    pub post_processing: PostProcessConfig,
+    pub rewrite: RewriteConfig,
+    pub command_mode: CommandModeSettings,
+    pub confirm_before_paste: ConfirmSettings,
+    pub hotkeys: HotkeySettings,
+    pub webhook: WebhookSettings,
+    pub journal: JournalSettings,
+}
+
+impl Profile {
+    /// The profile settings of `settings`.
+    fn of(settings: &Settings) -> Self {
+        Self {
+            transcription: settings.transcription.clone(),
+            post_processing: settings.post_processing.clone(),
+            rewrite: settings.rewrite.clone(),
+            command_mode: settings.command_mode.clone(),
+            confirm_before_paste: settings.confirm_before_paste.clone(),
+            hotkeys: settings.hotkeys.clone(),
+            webhook: settings.webhook.clone(),
+            journal: settings.journal.clone(),
+        }
+    }
+
+    /// Replaces the profile settings of `settings` with this profile's.
+    fn apply_to(&self, settings: &mut Settings) {
+        let Profile {
+            transcription,
+            post_processing,
+            rewrite,
+            command_mode,
+            confirm_before_paste,
+            hotkeys,
+            webhook,
+            journal,
+        } = self.clone();
+        settings.transcription = transcription;
+        settings.post_processing = post_processing;
+        settings.rewrite = rewrite;
+        settings.command_mode = command_mode;
+        settings.confirm_before_paste = confirm_before_paste;
+        settings.hotkeys = hotkeys;
+        settings.webhook = webhook;
+        settings.journal = journal;
+    }
+}
+
+/// The saved profiles and which one is in use.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileSettings {
+    /// The profile in use; `None` until one is created.
+    pub active: Option<String>,
+    /// Profiles by name. The active one is kept equal to the live settings.
+    pub saved: BTreeMap<String, Profile>,
+}
+
+impl ProfileSettings {
+    /// Checks the names, and every saved profile on top of `settings`.
+    pub(super) fn problems(&self, settings: &Settings) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if let Some(active) = &self.active {
+            if !self.saved.contains_key(active) {
+                errors.push(FieldError::new(
+                    "profiles.active",
+                    format!("no profile is named \"{}\"", active),
+                ));
+            }
+        }
+        for (name, profile) in &self.saved {
+            if let Err(e) = validate_name(name) {
+                errors.push(FieldError::new("profiles.saved", e));
+            }
+            // The live settings, checked already
+            if self.active.as_ref() == Some(name) {
+                continue;
+            }
+            let mut candidate = Settings {
+                profiles: ProfileSettings::default(),
+                ..settings.clone()
+            };
+            profile.apply_to(&mut candidate);
+            errors.extend(candidate.problems().into_iter().map(|error| FieldError {
+                field: Some(format!(
+                    "profiles.saved.{}.{}",
+                    name,
+                    error.field.as_deref().unwrap_or_default()
+                )),
+                message: error.message,
+            }));
+        }
+        errors
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() || name.trim() != name {
+        return Err("a profile name must not be empty or start or end with spaces".to_string());
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!(
+            "a profile name must be at most {} characters",
+            MAX_NAME_CHARS
+        ));
+    }
+    Ok(())
+}
+
+/// Copies the profile settings of `settings` into its active profile.
+/// Called on every settings update.
+pub(super) fn sync_active(settings: &mut Settings) {
+    let profile = Profile::of(settings);
+    if let Some(active) = settings.profiles.active.clone() {
+        settings.profiles.saved.insert(active, profile);
+    }
+}
+
+/// A saved profile, as listed by [`list_profiles`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileInfo {
+    pub name: String,
+    pub active: bool,
+}
+
+/// Payload of `profile:switched`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileSwitched {
+    pub from: Option<String>,
+    pub to: String,
+}
+
+/// Switches to the profile `name`, loading its settings, and emits
+/// `profile:switched`. Switching to the active profile does nothing.
+///
+/// # Errors
+///
+/// Returns an error if there is no such profile, its settings are invalid
+/// or they can't be saved; nothing changes then.
+pub fn switch(app: &AppHandle, name: &str) -> Result<(), String> {
+    let mut from = None;
+    update(app, |settings| {
+        let profile = settings
+            .profiles
+            .saved
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("no profile is named \"{}\"", name))?;
+        from = settings.profiles.active.replace(name.to_string());
+        profile.apply_to(settings);
+        Ok(())
+    })?;
+    switched(app, from, name);
+    Ok(())
+}
+
+/// Emits `profile:switched` if the active profile changed.
+fn switched(app: &AppHandle, from: Option<String>, to: &str) {
+    if from.as_deref() == Some(to) {
+        return;
+    }
+    tracing::info!(from = ?from, to, "Switched profile");
+    let _ = app.emit(
+        "profile:switched",
+        ProfileSwitched {
+            from,
+            to: to.to_string(),
+        },
+    );
+}
+
+/// Lists the saved profiles by name.
+#[tauri::command]
+pub fn list_profiles(state: State<'_, SettingsState>) -> Vec<ProfileInfo> {
+    let profiles = state.get().profiles;
+    profiles
+        .saved
+        .into_keys()
+        .map(|name| ProfileInfo {
+            active: profiles.active.as_deref() == Some(name.as_str()),
+            name,
+        })
+        .collect()
+}
+
+/// Saves the current profile settings as a new profile `name`, and
+/// switches to it.
+///
+/// # Errors
+///
+/// Returns an error if the name is invalid or taken, or the settings can't
+/// be saved.
+#[tauri::command]
+pub fn create_profile(app: AppHandle, name: String) -> Result<(), String> {
+    validate_name(&name)?;
+    let mut from = None;
+    update(&app, |settings| {
+        if settings.profiles.saved.contains_key(&name) {
+            return Err(format!("a profile named \"{}\" already exists", name));
+        }
+        let profile = Profile::of(settings);
+        settings.profiles.saved.insert(name.clone(), profile);
+        from = settings.profiles.active.replace(name.clone());
+        Ok(())
+    })?;
+    switched(&app, from, &name);
+    Ok(())
+}
+
+/// Switches to the profile `name` (see [`switch`]).
+///
+/// # Errors
+///
+/// Returns an error if there is no such profile or it can't be applied.
+#[tauri::command]
+pub fn switch_profile(app: AppHandle, name: String) -> Result<(), String> {
+    switch(&app, &name)
+}
+
+/// Deletes the profile `name`, which must not be the active one.
+///
+/// # Errors
+///
+/// Returns an error if there is no such profile, it is active, or the
+/// settings can't be saved.
+#[tauri::command]
+pub fn delete_profile(app: AppHandle, name: String) -> Result<(), String> {
+    update(&app, |settings| {
+        if settings.profiles.active.as_deref() == Some(name.as_str()) {
+            return Err(format!(
+                "\"{}\" is the active profile; switch to another one first",
+                name
+            ));
+        }
+        settings
+            .profiles
+            .saved
+            .remove(&name)
+            .map(|_| ())
+            .ok_or_else(|| format!("no profile is named \"{}\"", name))
+    })
+    .map(|_| ())
+}
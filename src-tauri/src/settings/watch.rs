@@ -0,0 +1,139 @@
+//! Hot reload of the settings file when it is edited outside the app.
+//!
+//! A watcher on the app data directory picks up changes to `settings.json`,
+//! waits for writes to settle, then parses, migrates and validates the file
+//! exactly as at startup. Valid edits replace the in-memory settings and are
+//! broadcast like any other change; invalid ones emit `settings:invalid`
+//! naming the offending fields and keep the previous values.
+//!
+//! # Architecture Decision
+//!
+//! The directory is watched rather than the file because our own saves (and
+//! many editors) replace the file by renaming a temporary one over it, which
+//! would orphan a watch on the old file. Our own writes are recognized by
+//! comparing the file's contents with what we last wrote, so the watcher
+//! never reacts to (or fights with) the app's atomic saves.
+
+use std::fs;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+
+use super::{broadcast, describe, load_file, FieldError, SettingsState};
+
+use crate::AppHandle;
+
+/// Quiet period after the last change before the file is re-read. Editors
+/// often write a file several times in quick succession.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Payload of the `settings:invalid` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingsInvalid {
+    pub errors: Vec<FieldError>,
+}
+
+/// Starts watching the settings file for external edits.
+///
+/// Failures are logged; the app works without hot reload.
+pub fn spawn_watcher(app: AppHandle) {
+    let path = app.state::<SettingsState>().path.clone();
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return;
+    };
+    let file_name = file_name.to_os_string();
+
+    let (sender, receiver) = mpsc::channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let touches_settings = event.is_ok_and(|event| {
+            event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == Some(file_name.as_os_str()))
+        });
+        if touches_settings {
+            let _ = sender.send(());
+        }
+    });
+
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            tracing::warn!("Settings hot reload unavailable: {}", e);
+            return;
+        }
+    };
+    let watched = fs::create_dir_all(dir)
+        .map_err(|e| e.to_string())
+        .and_then(|()| {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = watched {
+        tracing::warn!("Settings hot reload unavailable: {}", e);
+        return;
+    }
+
+    // Restarted after a panic (see `crate::crash::supervise`)
+    let spawned = crate::crash::supervise(&app.clone(), "settings-watcher", move || {
+        // The watcher stops when dropped, so it lives as long as the thread
+        let _watcher = &watcher;
+        while receiver.recv().is_ok() {
+            while receiver.recv_timeout(DEBOUNCE).is_ok() {}
+            reload(&app);
+        }
+    });
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to start settings watcher: {}", e);
+    }
+}
+
+/// Re-reads the settings file and applies it if it changed and is valid.
+fn reload(app: &AppHandle) {
+    let state = app.state::<SettingsState>();
+    let json = match fs::read(&state.path) {
+        Ok(json) => json,
+        // Deleted or mid-replace; keep the in-memory settings
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            tracing::warn!("Failed to re-read settings: {}", e);
+            return;
+        }
+    };
+
+    // Our own atomic save
+    if state.last_written.lock().unwrap().as_deref() == Some(json.as_slice()) {
+        return;
+    }
+
+    let mut meta = state.meta();
+    meta.warnings.clear();
+    let loaded = match load_file(&state.path, &json, &mut meta) {
+        Ok(loaded) => loaded,
+        Err(errors) => {
+            tracing::warn!("Ignoring invalid settings edit: {}", describe(&errors));
+            let _ = app.emit("settings:invalid", SettingsInvalid { errors });
+            return;
+        }
+    };
+
+    // What is on disk after a migration wrote the edit back, so that write
+    // isn't taken for another edit
+    *state.last_written.lock().unwrap() = Some(loaded.on_disk);
+    *state.meta.lock().unwrap() = meta;
+
+    let loaded = loaded.settings;
+    let mut current = state.settings.lock().unwrap();
+    if *current == loaded {
+        return;
+    }
+    let previous = std::mem::replace(&mut *current, loaded.clone());
+    drop(current);
+
+    tracing::info!("Settings reloaded from disk");
+    broadcast(app, &previous, &loaded);
+}
@@ -0,0 +1,415 @@
+//! Recording a shortcut by pressing it.
+//!
+//! [`capture_next_shortcut`] waits for the next key chord pressed in the
+//! window that called it and returns it as an accelerator (`Alt+Shift+K`,
+//! the form the `hotkeys` settings take) and as a label to show (`⌥⇧K` on
+//! macOS). The app has no system-wide key hook, so the keys come from that
+//! window: while the command waits, it listens for key presses and reports
+//! each with [`report_shortcut_key`].
+//!
+//! Presses of modifiers alone, and of keys without a modifier, are ignored
+//! (except function keys); Escape on its own cancels. A chord the OS keeps
+//! for itself, such as Cmd+Q or Alt+Tab, is refused
+//! ([`CaptureError::Reserved`]). The capture also ends after its timeout,
+//! with [`cancel_shortcut_capture`], and when the window reloads or closes
+//! ([`abandon`]) so it never outlives the page listening for it.
+//!
+//! The accelerator names the physical key, which is what global shortcuts
+//! match; the label names the character it types in the user's keyboard
+//! layout, as the window reports it.
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::json;
+use tauri::{Manager, State};
+use tokio::sync::oneshot;
+
+use crate::errors::{self, ErrorCode};
+use crate::{AppHandle, WebviewWindow};
+
+/// How long a capture waits when the caller doesn't say.
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+
+/// Longest a capture may wait.
+const MAX_TIMEOUT_MS: u64 = 60_000;
+
+/// Chords the OS handles itself, so a shortcut on them would never fire.
+#[cfg(target_os = "macos")]
+const RESERVED: &[&str] = &[
+    "CmdOrCtrl+Q",
+    "CmdOrCtrl+Tab",
+    "CmdOrCtrl+Shift+Tab",
+    "CmdOrCtrl+Space",
+    "CmdOrCtrl+Alt+Escape",
+    "CmdOrCtrl+Shift+3",
+    "CmdOrCtrl+Shift+4",
+    "CmdOrCtrl+Shift+5",
+    "CmdOrCtrl+Ctrl+Q",
+];
+#[cfg(not(target_os = "macos"))]
+const RESERVED: &[&str] = &[
+    "Alt+Tab",
+    "Alt+Shift+Tab",
+    "Alt+F4",
+    "CmdOrCtrl+Alt+Delete",
+    "CmdOrCtrl+Shift+Escape",
+    "Super+L",
+    "Super+D",
+    "Super+Tab",
+];
+
+/// Why no shortcut was captured. Serialized with its `kind`, its
+/// [`code`](CaptureError::code) and its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureError {
+    /// Another capture is waiting.
+    Busy,
+    /// Nothing was pressed in time.
+    TimedOut,
+    /// Escape was pressed, the capture was cancelled, or its window went
+    /// away.
+    Cancelled,
+    /// The chord is the system's.
+    Reserved { accelerator: String },
+    /// The key can't be used in a shortcut.
+    Unsupported { code: String },
+}
+
+impl CaptureError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Reserved { .. } => ErrorCode::ShortcutReserved,
+            Self::Busy | Self::TimedOut | Self::Cancelled | Self::Unsupported { .. } => {
+                ErrorCode::ShortcutCapture
+            }
+        }
+    }
+}
+
+impl Serialize for CaptureError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (kind, fields) = match self {
+            Self::Busy => ("busy", json!({})),
+            Self::TimedOut => ("timed_out", json!({})),
+            Self::Cancelled => ("cancelled", json!({})),
+            Self::Reserved { accelerator } => ("reserved", json!({ "accelerator": accelerator })),
+            Self::Unsupported { code } => ("unsupported", json!({ "code": code })),
+        };
+        errors::serialize_error(serializer, kind, self.code(), fields)
+    }
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Busy => f.write_str("a shortcut is already being recorded"),
+            Self::TimedOut => f.write_str("no shortcut was pressed"),
+            Self::Cancelled => f.write_str("recording the shortcut was cancelled"),
+            Self::Reserved { accelerator } => {
+                write!(f, "{} is reserved by the system", accelerator)
+            }
+            Self::Unsupported { code } => write!(f, "{} can't be used in a shortcut", code),
+        }
+    }
+}
+
+/// A key press in the capturing window, from its `keydown` event.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyPress {
+    /// The physical key (`KeyboardEvent.code`), e.g. `KeyK`.
+    pub code: String,
+    /// What it types (`KeyboardEvent.key`), with the modifiers applied.
+    pub key: String,
+    /// What it types without modifiers in the current layout, where the
+    /// webview can tell (`navigator.keyboard.getLayoutMap`).
+    pub layout_key: Option<String>,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+/// The result of [`capture_next_shortcut`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CapturedShortcut {
+    /// For the `hotkeys` settings, e.g. `CmdOrCtrl+Shift+K`.
+    pub accelerator: String,
+    /// For the user to read, e.g. `⌘⇧K` or `Ctrl+Shift+K`.
+    pub label: String,
+}
+
+/// Managed state: the capture waiting for a key, if any.
+#[derive(Default)]
+pub struct CaptureState(Mutex<Option<Capture>>);
+
+struct Capture {
+    /// Label of the window the keys come from.
+    window: String,
+    done: oneshot::Sender<Result<CapturedShortcut, CaptureError>>,
+}
+
+impl CaptureState {
+    /// Ends the capture from `window` with `result`, if there is one.
+    fn finish(&self, window: &str, result: Result<CapturedShortcut, CaptureError>) -> bool {
+        let mut capture = self.0.lock().unwrap();
+        if capture
+            .as_ref()
+            .is_some_and(|capture| capture.window == window)
+        {
+            if let Some(capture) = capture.take() {
+                let _ = capture.done.send(result);
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// What a press means for the capture: nothing yet, or its end.
+fn interpret(press: &KeyPress) -> Option<Result<CapturedShortcut, CaptureError>> {
+    if is_modifier(&press.code) {
+        return None;
+    }
+    let any_modifier = press.ctrl || press.alt || press.shift || press.meta;
+    if press.code == "Escape" && !any_modifier {
+        return Some(Err(CaptureError::Cancelled));
+    }
+    let key = accelerator_key(&press.code);
+    let function_key = key.as_deref().is_some_and(is_function_key);
+    if !any_modifier && !function_key {
+        return None;
+    }
+    let Some(key) = key else {
+        return Some(Err(CaptureError::Unsupported {
+            code: press.code.clone(),
+        }));
+    };
+
+    // The platform's command modifier is written CmdOrCtrl
+    let (primary, other_ctrl, superkey) = if cfg!(target_os = "macos") {
+        (press.meta, press.ctrl, false)
+    } else {
+        (press.ctrl, false, press.meta)
+    };
+    let mut parts = Vec::new();
+    if primary {
+        parts.push("CmdOrCtrl");
+    }
+    if other_ctrl {
+        parts.push("Ctrl");
+    }
+    if superkey {
+        parts.push("Super");
+    }
+    if press.alt {
+        parts.push("Alt");
+    }
+    if press.shift {
+        parts.push("Shift");
+    }
+    parts.push(&key);
+    let accelerator = parts.join("+");
+    if RESERVED.contains(&accelerator.as_str()) {
+        return Some(Err(CaptureError::Reserved { accelerator }));
+    }
+
+    Some(Ok(CapturedShortcut {
+        label: label(press, &key),
+        accelerator,
+    }))
+}
+
+fn is_modifier(code: &str) -> bool {
+    matches!(
+        code,
+        "ControlLeft"
+            | "ControlRight"
+            | "AltLeft"
+            | "AltRight"
+            | "ShiftLeft"
+            | "ShiftRight"
+            | "MetaLeft"
+            | "MetaRight"
+            | "OSLeft"
+            | "OSRight"
+            | "CapsLock"
+            | "Fn"
+    )
+}
+
+/// The accelerator's name of the physical key `code`, if shortcuts can use
+/// it.
+fn accelerator_key(code: &str) -> Option<String> {
+    if let Some(letter) = code.strip_prefix("Key").filter(|rest| rest.len() == 1) {
+        return Some(letter.to_string());
+    }
+    if let Some(digit) = code.strip_prefix("Digit").filter(|rest| rest.len() == 1) {
+        return Some(digit.to_string());
+    }
+    let named = matches!(
+        code,
+        "Space"
+            | "Enter"
+            | "Tab"
+            | "Backspace"
+            | "Delete"
+            | "Insert"
+            | "Home"
+            | "End"
+            | "PageUp"
+            | "PageDown"
+            | "ArrowUp"
+            | "ArrowDown"
+            | "ArrowLeft"
+            | "ArrowRight"
+            | "Escape"
+            | "Minus"
+            | "Equal"
+            | "BracketLeft"
+            | "BracketRight"
+            | "Backslash"
+            | "Semicolon"
+            | "Quote"
+            | "Comma"
+            | "Period"
+            | "Slash"
+            | "Backquote"
+    ) || code.starts_with("Numpad");
+    (is_function_key(code) || named).then(|| code.to_string())
+}
+
+/// Whether `code` is F1 to F24.
+fn is_function_key(code: &str) -> bool {
+    code.strip_prefix('F')
+        .and_then(|number| number.parse::<u8>().ok())
+        .is_some_and(|number| (1..=24).contains(&number))
+}
+
+/// How the chord reads: the modifiers in the platform's style, then the
+/// character the key types in the user's layout, or its name.
+fn label(press: &KeyPress, key: &str) -> String {
+    let typed = press
+        .layout_key
+        .clone()
+        // `key` has Shift and Alt applied, so it only names the key without them
+        .or_else(|| (!press.shift && !press.alt).then(|| press.key.clone()))
+        .filter(|typed| typed.chars().count() == 1 && typed != " ")
+        .map(|typed| typed.to_uppercase());
+    let key_label = typed.unwrap_or_else(|| match key {
+        "ArrowUp" => "↑".to_string(),
+        "ArrowDown" => "↓".to_string(),
+        "ArrowLeft" => "←".to_string(),
+        "ArrowRight" => "→".to_string(),
+        "Escape" => "Esc".to_string(),
+        other => other.to_string(),
+    });
+
+    if cfg!(target_os = "macos") {
+        let mut label = String::new();
+        for (held, symbol) in [
+            (press.ctrl, "⌃"),
+            (press.alt, "⌥"),
+            (press.shift, "⇧"),
+            (press.meta, "⌘"),
+        ] {
+            if held {
+                label.push_str(symbol);
+            }
+        }
+        label + &key_label
+    } else {
+        let superkey = if cfg!(target_os = "windows") {
+            "Win"
+        } else {
+            "Super"
+        };
+        let mut parts: Vec<&str> = [
+            (press.ctrl, "Ctrl"),
+            (press.meta, superkey),
+            (press.alt, "Alt"),
+            (press.shift, "Shift"),
+        ]
+        .into_iter()
+        .filter_map(|(held, name)| held.then_some(name))
+        .collect();
+        parts.push(&key_label);
+        parts.join("+")
+    }
+}
+
+/// Ends the capture from the window labeled `window`, which reloaded or
+/// closed, as cancelled.
+pub fn abandon(app: &AppHandle, window: &str) {
+    if app
+        .state::<CaptureState>()
+        .finish(window, Err(CaptureError::Cancelled))
+    {
+        tracing::debug!(window, "Shortcut capture abandoned");
+    }
+}
+
+/// Waits up to `timeout_ms` (10 seconds by default, at most a minute) for a
+/// key chord, reported by this window with [`report_shortcut_key`].
+///
+/// # Errors
+///
+/// Returns a [`CaptureError`]: `busy` if another capture is waiting,
+/// `timed_out`, `cancelled` (Escape, [`cancel_shortcut_capture`], or the
+/// window going away), `reserved` for a chord the OS keeps and
+/// `unsupported` for a key shortcuts can't use.
+#[tauri::command]
+pub async fn capture_next_shortcut(
+    window: WebviewWindow,
+    timeout_ms: Option<u64>,
+) -> Result<CapturedShortcut, CaptureError> {
+    let label = window.label().to_string();
+    let (done, captured) = oneshot::channel();
+    {
+        let state = window.state::<CaptureState>();
+        let mut capture = state.0.lock().unwrap();
+        if capture.is_some() {
+            return Err(CaptureError::Busy);
+        }
+        *capture = Some(Capture {
+            window: label.clone(),
+            done,
+        });
+    }
+
+    let timeout =
+        Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS).min(MAX_TIMEOUT_MS));
+    match tokio::time::timeout(timeout, captured).await {
+        Ok(Ok(result)) => {
+            tracing::debug!(ok = result.is_ok(), "Shortcut capture ended");
+            result
+        }
+        // The sender went away without an answer
+        Ok(Err(_)) => Err(CaptureError::Cancelled),
+        Err(_) => {
+            window
+                .state::<CaptureState>()
+                .finish(&label, Err(CaptureError::TimedOut));
+            Err(CaptureError::TimedOut)
+        }
+    }
+}
+
+/// Reports a key pressed in this window to its capture. Presses that don't
+/// make a chord are ignored; the rest end the capture.
+#[tauri::command]
+pub fn report_shortcut_key(window: WebviewWindow, state: State<'_, CaptureState>, press: KeyPress) {
+    if let Some(result) = interpret(&press) {
+        state.finish(window.label(), result);
+    }
+}
+
+/// Cancels this window's capture, if it has one.
+#[tauri::command]
+pub fn cancel_shortcut_capture(window: WebviewWindow, state: State<'_, CaptureState>) {
+    state.finish(window.label(), Err(CaptureError::Cancelled));
+}
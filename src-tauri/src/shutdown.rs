@@ -0,0 +1,158 @@
+//! Quitting without losing a dictation.
+//!
+//! Every request to exit (the tray's Quit, Cmd+Q) comes through
+//! [`exit_requested`] first, which holds it off while work is in flight:
+//!
+//! - While recording, the exit is cancelled and `shutdown:confirm` emitted,
+//!   for the pill to ask whether to stop and transcribe, discard the
+//!   recording, or keep going. Its answer is [`shutdown_now`].
+//! - While a transcript is processed or pasted, or a history write is
+//!   pending ([`PendingWork`]), the app waits for it to finish, at most
+//!   [`FLUSH_TIMEOUT`], then exits. Whatever is still unfinished then is
+//!   left to crash recovery, which has the recording and the transcript
+//!   about to be pasted on disk.
+//!
+//! Before exiting, background work that holds resources is stopped
+//! explicitly ([`stop_background`]): the local API server and its live
+//! clients; the window store is flushed. The remaining background threads
+//! only sleep between runs and end with the process.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{Emitter, ExitRequestApi, Manager};
+
+use crate::api::{self, live::LiveHub};
+use crate::app_state::{self, Phase};
+use crate::dictation::DictationState;
+use crate::window_state::WindowStore;
+use crate::AppHandle;
+
+/// Longest wait for in-flight work before exiting anyway.
+pub const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the wait checks whether the work is done.
+const FLUSH_POLL: Duration = Duration::from_millis(50);
+
+/// Shutdown state, managed for the life of the app.
+#[derive(Default)]
+pub struct ShutdownState {
+    /// Set once everything is flushed; the next exit request goes through.
+    allowed: AtomicBool,
+    /// Set while waiting to exit, so a second Quit doesn't wait twice.
+    exiting: AtomicBool,
+    /// Writes that must not be cut off, e.g. recording a history entry.
+    pending: AtomicUsize,
+}
+
+/// Work the exit waits for, for as long as the guard lives.
+pub struct PendingWork(AppHandle);
+
+impl PendingWork {
+    pub fn start(app: &AppHandle) -> Self {
+        app.state::<ShutdownState>()
+            .pending
+            .fetch_add(1, Ordering::SeqCst);
+        Self(app.clone())
+    }
+}
+
+impl Drop for PendingWork {
+    fn drop(&mut self) {
+        self.0
+            .state::<ShutdownState>()
+            .pending
+            .fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Payload of `shutdown:confirm`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfirmQuit {
+    pub session_id: Option<String>,
+}
+
+/// Decides whether a request to exit goes through now; see the module
+/// docs. Call from `RunEvent::ExitRequested`.
+pub fn exit_requested(app: &AppHandle, api: &ExitRequestApi) {
+    if app.state::<ShutdownState>().allowed.load(Ordering::SeqCst) {
+        return;
+    }
+    api.prevent_exit();
+    if app_state::phase(app) == Phase::Recording {
+        tracing::info!("Asking before quitting during a recording");
+        let _ = app.emit(
+            "shutdown:confirm",
+            ConfirmQuit {
+                session_id: app_state::session_id(app),
+            },
+        );
+        return;
+    }
+    exit_when_flushed(app);
+}
+
+/// Whether anything the exit should wait for is still going on.
+fn busy(app: &AppHandle) -> bool {
+    let phase = app_state::phase(app);
+    matches!(
+        phase,
+        Phase::Recording | Phase::Transcribing | Phase::Pasting
+    ) || app.state::<ShutdownState>().pending.load(Ordering::SeqCst) > 0
+}
+
+/// Exits once nothing is [`busy`], or after [`FLUSH_TIMEOUT`].
+fn exit_when_flushed(app: &AppHandle) {
+    let state = app.state::<ShutdownState>();
+    if state.exiting.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        let waited = Instant::now();
+        while busy(&app) && Instant::now() < deadline {
+            std::thread::sleep(FLUSH_POLL);
+        }
+        if busy(&app) {
+            tracing::warn!(phase = ?app_state::phase(&app), "Quitting with work in flight");
+        } else {
+            tracing::info!(waited_ms = waited.elapsed().as_millis() as u64, "Quitting");
+        }
+        stop_background(&app);
+        app.state::<ShutdownState>()
+            .allowed
+            .store(true, Ordering::SeqCst);
+        app.exit(0);
+    });
+}
+
+/// Stops background work that holds resources, before exiting.
+fn stop_background(app: &AppHandle) {
+    api::stop(app);
+    app.state::<LiveHub>().end_session(app);
+    app.state::<WindowStore>().flush();
+}
+
+/// Quits, answering `shutdown:confirm`: with `force`, the dictation in
+/// progress is discarded and the app exits right away; otherwise it exits
+/// once the dictation (which the caller has stopped to transcribe it) has
+/// been pasted.
+#[tauri::command]
+pub fn shutdown_now(app: AppHandle, force: bool) {
+    if force {
+        tracing::info!("Quitting, discarding the dictation in progress");
+        app_state::dismiss(&app, "quit");
+        if let Some(mut dictation) = app.state::<DictationState>().0.lock().unwrap().take() {
+            dictation.discard_audio();
+        }
+        stop_background(&app);
+        app.state::<ShutdownState>()
+            .allowed
+            .store(true, Ordering::SeqCst);
+        app.exit(0);
+        return;
+    }
+    exit_when_flushed(&app);
+}
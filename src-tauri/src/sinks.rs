@@ -0,0 +1,41 @@
+//! Output sinks: where a finished dictation goes besides history.
+//!
+//! Once a dictation has been pasted, `copy_and_paste_text` hands it to
+//! every sink: the webhook ([`crate::webhook`]), the journal
+//! ([`crate::journal`]) and the dictation log ([`crate::dictation_log`]).
+//! Each one reads its own settings to decide whether it wants it.
+//!
+//! # Architecture Decision
+//!
+//! A sink can never hold up or break a paste. [`OutputSink::accept`] runs
+//! on the paste path, after the paste has completed, so it only takes a
+//! copy of what it needs; finding the target app, the disk and the network
+//! happen in the background, and a failure there is only logged. What
+//! privacy mode allows differs between sinks (the webhook still sends, the
+//! journal writes nothing), so each one asks [`crate::privacy::policy`]
+//! itself.
+
+use crate::dictation::Dictation;
+use crate::dictation_log::DictationLog;
+use crate::journal::Journal;
+use crate::webhook::Webhook;
+use crate::AppHandle;
+
+/// Something that receives every pasted dictation.
+pub trait OutputSink: Sync {
+    /// Takes the pasted `dictation`, whose text was `pasted`. Must return
+    /// quickly and never fail the dictation.
+    fn accept(&self, app: &AppHandle, dictation: &Dictation, pasted: &str);
+}
+
+/// Every sink, in the order they are handed a dictation.
+const SINKS: &[&dyn OutputSink] = &[&Webhook, &Journal, &DictationLog];
+
+/// Hands the pasted `dictation` to every sink. Called once the paste has
+/// completed, before the recording is handed to history retention, which
+/// may move or delete it.
+pub fn deliver(app: &AppHandle, dictation: &Dictation, pasted: &str) {
+    for sink in SINKS {
+        sink.accept(app, dictation, pasted);
+    }
+}
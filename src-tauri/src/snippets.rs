@@ -0,0 +1,153 @@
+//! Snippet body handling.
+//!
+//! Snippets (email templates, signatures, canned replies) may contain a
+//! `{cursor}` marker indicating where the caret should end up after the
+//! snippet is inserted, e.g. `"Hi {cursor}, thanks for reaching out!"`.
+//!
+//! # Architecture Decision
+//!
+//! There is no cross-platform way to "paste and put the caret here", so we
+//! paste the whole body with the marker removed and then walk the caret back
+//! with Left-arrow presses. One Left press moves over one user-perceived
+//! character, so the offset is counted in grapheme clusters rather than bytes
+//! or `char`s — an emoji with a skin tone modifier or a ZWJ family is several
+//! code points but a single press.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Marker placed in a snippet body to indicate the final caret position.
+pub const CURSOR_MARKER: &str = "{cursor}";
+
+/// Bundle identifiers of apps where moving the caret across a line break with
+/// Left-arrow presses does not land where the text offset suggests (terminals
+/// treat every line as a separate prompt, chat apps may send on newline).
+const UNRELIABLE_VERTICAL_NAVIGATION_APPS: &[&str] = &[
+    "com.apple.Terminal",
+    "com.googlecode.iterm2",
+    "dev.warp.Warp-Stable",
+    "com.tinyspeck.slackmacgap",
+    "com.hnc.Discord",
+];
+
+/// The result of stripping the cursor marker from a snippet body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorPlacement {
+    /// Text to paste, with the marker removed.
+    pub text: String,
+    /// Number of Left-arrow presses needed after pasting, or `None` when the
+    /// body contained no marker (caret stays at the end).
+    pub left_presses: Option<usize>,
+    /// Whether the text after the marker spans multiple lines.
+    pub crosses_newline: bool,
+}
+
+impl CursorPlacement {
+    /// Splits `body` at the first `{cursor}` marker.
+    ///
+    /// Only the first marker positions the caret; any further markers are
+    /// removed so they never end up in the pasted text.
+    pub fn from_body(body: &str) -> Self {
+        let Some(index) = body.find(CURSOR_MARKER) else {
+            return Self {
+                text: body.to_string(),
+                left_presses: None,
+                crosses_newline: false,
+            };
+        };
+
+        let before = &body[..index];
+        let after = body[index + CURSOR_MARKER.len()..].replace(CURSOR_MARKER, "");
+
+        Self {
+            text: format!("{}{}", before, after),
+            left_presses: Some(after.graphemes(true).count()),
+            crosses_newline: after.contains(['\n', '\r']),
+        }
+    }
+
+    /// Returns whether caret positioning should be skipped for the given
+    /// target app because the presses would land on the wrong line.
+    pub fn is_unreliable_in(&self, bundle_id: Option<&str>) -> bool {
+        self.crosses_newline
+            && bundle_id.is_some_and(|id| UNRELIABLE_VERTICAL_NAVIGATION_APPS.contains(&id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presses(body: &str) -> Option<usize> {
+        CursorPlacement::from_body(body).left_presses
+    }
+
+    #[test]
+    fn no_marker_leaves_the_caret_at_the_end() {
+        let placement = CursorPlacement::from_body("Thanks for reaching out!");
+        assert_eq!(placement.text, "Thanks for reaching out!");
+        assert_eq!(placement.left_presses, None);
+        assert!(!placement.crosses_newline);
+    }
+
+    #[test]
+    fn marker_is_removed_and_counted_from_the_end() {
+        let placement = CursorPlacement::from_body("Hi {cursor}, thanks!");
+        assert_eq!(placement.text, "Hi , thanks!");
+        assert_eq!(placement.left_presses, Some(", thanks!".len()));
+    }
+
+    #[test]
+    fn marker_at_either_end() {
+        assert_eq!(presses("{cursor}abc"), Some(3));
+        assert_eq!(presses("abc{cursor}"), Some(0));
+    }
+
+    #[test]
+    fn later_markers_are_dropped() {
+        let placement = CursorPlacement::from_body("a{cursor}b{cursor}c");
+        assert_eq!(placement.text, "abc");
+        assert_eq!(placement.left_presses, Some(2));
+    }
+
+    #[test]
+    fn emoji_count_as_one_press_each() {
+        // Skin tone modifier, ZWJ family and a flag: one cluster each
+        assert_eq!(presses("{cursor}👍🏽"), Some(1));
+        assert_eq!(presses("{cursor}👨‍👩‍👧‍👦"), Some(1));
+        assert_eq!(presses("{cursor}🇯🇵!"), Some(2));
+        assert_eq!(presses("Hi {cursor} 😀 there"), Some(8));
+    }
+
+    #[test]
+    fn combining_marks_stay_with_their_letter() {
+        // "e" + combining acute accent
+        assert_eq!(presses("{cursor}cafe\u{301}"), Some(4));
+    }
+
+    #[test]
+    fn cjk_counts_characters_not_bytes() {
+        let placement = CursorPlacement::from_body("你好{cursor}世界");
+        assert_eq!(placement.text, "你好世界");
+        assert_eq!(placement.left_presses, Some(2));
+        assert_eq!(presses("{cursor}こんにちは"), Some(5));
+        assert_eq!(presses("{cursor}한국어"), Some(3));
+    }
+
+    #[test]
+    fn crlf_is_one_press() {
+        let placement = CursorPlacement::from_body("a{cursor}b\r\nc");
+        assert_eq!(placement.left_presses, Some(3));
+        assert!(placement.crosses_newline);
+    }
+
+    #[test]
+    fn newlines_are_only_unreliable_in_some_apps() {
+        let multiline = CursorPlacement::from_body("Hi {cursor}\nBest");
+        assert!(multiline.is_unreliable_in(Some("com.apple.Terminal")));
+        assert!(!multiline.is_unreliable_in(Some("com.apple.mail")));
+        assert!(!multiline.is_unreliable_in(None));
+
+        let single = CursorPlacement::from_body("Hi {cursor}, Best");
+        assert!(!single.is_unreliable_in(Some("com.apple.Terminal")));
+    }
+}
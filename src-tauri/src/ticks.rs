@@ -0,0 +1,317 @@
+//! Elapsed-time ticks while recording.
+//!
+//! Once a second while the app is `Recording`, `recording:tick` tells the
+//! frontend how long the dictation has been going, so the pill's timer
+//! follows the backend instead of the page's own clock:
+//!
+//! - `elapsed_ms`: wall-clock time since the recording started.
+//! - `audio_ms`: audio actually captured, as reported by the frontend
+//!   ([`report_captured_audio`]) for every chunk the recorder delivers. Mic
+//!   stalls deliver nothing, so they count towards `elapsed_ms` only.
+//!
+//! Ticks are only sent in the `Recording` phase of their own session: the
+//! ticker checks both before every tick and stops for good once either
+//! changes, so no tick follows a stop, a cancel or a new recording. A
+//! paused recording would get no ticks at all (it wouldn't be `Recording`),
+//! rather than ticks with a paused flag.
+//!
+//! Limits on a recording's length, such as a max-duration warning, belong
+//! in [`tick`], which sees every tick.
+//!
+//! # No speech
+//!
+//! A recording started by accident would keep the mic open indefinitely.
+//! The frontend reports the first sign of speech ([`report_speech`]: the
+//! mic's level staying up, or a partial transcript); a recording that hasn't
+//! had any after `recording.no_speech_timeout_ms` is cancelled, its audio
+//! discarded, and `recording:cancelled` emitted with `reason: "no_speech"`.
+//! The pill shows why for a moment before hiding. Once speech was heard the
+//! timeout no longer applies; ending a recording after speech is the
+//! provider's speech-end detection. Being tick-driven, it never fires
+//! outside `Recording`.
+//!
+//! # Silent input
+//!
+//! A muted or broken microphone delivers digital silence, which would only
+//! show as an empty transcript after a wasted provider call. The frontend
+//! reports the peak level of every chunk it captures; when the first
+//! `recording.silent_input_check_ms` of audio never got above
+//! `recording.silent_input_dbfs`, `audio:input-silent` is emitted once, for
+//! the pill to ask the user to check the mic. A recording that stays that
+//! quiet to the end is rejected when it stops (see [`is_silent`]), and the
+//! peak level of every other one goes into its history entry. Interfaces
+//! with a high noise floor may need a higher threshold.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager, State};
+
+use crate::api::live::LiveHub;
+use crate::app_state::{self, Phase, SessionEvent};
+use crate::dictation::{Dictation, DictationState};
+use crate::pill::{self, PillState};
+use crate::recovery::RecoveryState;
+use crate::settings::SettingsState;
+use crate::AppHandle;
+
+/// Time between ticks.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the pill shows why a recording was cancelled.
+const CANCEL_NOTICE: Duration = Duration::from_secs(2);
+
+/// Longest `no_speech_timeout_ms`.
+const MAX_NO_SPEECH_TIMEOUT_MS: u64 = 10 * 60 * 1000;
+
+/// Longest `silent_input_check_ms`.
+const MAX_SILENT_INPUT_CHECK_MS: u64 = 60 * 1000;
+
+/// Range of `silent_input_dbfs`.
+const MIN_SILENT_INPUT_DBFS: i32 = -120;
+const MAX_SILENT_INPUT_DBFS: i32 = 0;
+
+/// Limits on recordings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordingSettings {
+    /// Cancel a recording that heard no speech for this long; 0 never does.
+    pub no_speech_timeout_ms: u64,
+    /// Peak level, in dBFS, the input has to get above not to count as
+    /// silent.
+    pub silent_input_dbfs: i32,
+    /// Audio captured before silent input is reported; 0 never reports it.
+    pub silent_input_check_ms: u64,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            no_speech_timeout_ms: 15_000,
+            silent_input_dbfs: -70,
+            silent_input_check_ms: 3_000,
+        }
+    }
+}
+
+impl RecordingSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.no_speech_timeout_ms > MAX_NO_SPEECH_TIMEOUT_MS {
+            return Err(format!(
+                "no_speech_timeout_ms must be at most {} (0 disables it)",
+                MAX_NO_SPEECH_TIMEOUT_MS
+            ));
+        }
+        if !(MIN_SILENT_INPUT_DBFS..=MAX_SILENT_INPUT_DBFS).contains(&self.silent_input_dbfs) {
+            return Err(format!(
+                "silent_input_dbfs must be between {} and {}",
+                MIN_SILENT_INPUT_DBFS, MAX_SILENT_INPUT_DBFS
+            ));
+        }
+        if self.silent_input_check_ms > MAX_SILENT_INPUT_CHECK_MS {
+            return Err(format!(
+                "silent_input_check_ms must be at most {} (0 disables it)",
+                MAX_SILENT_INPUT_CHECK_MS
+            ));
+        }
+        Ok(())
+    }
+
+    /// `silent_input_dbfs` as a linear peak level, 0 to 1.
+    pub fn silent_input_level(&self) -> f32 {
+        10f32.powf(self.silent_input_dbfs as f32 / 20.0)
+    }
+}
+
+/// Payload of the `recording:tick` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingTick {
+    pub session_id: String,
+    pub elapsed_ms: u64,
+    pub audio_ms: u64,
+}
+
+/// Payload of the `audio:input-silent` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputSilent {
+    pub session_id: String,
+    /// Highest level captured so far, 0 to 1.
+    pub peak_level: f32,
+}
+
+/// Payload of the `recording:cancelled` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingCancelled {
+    pub session_id: String,
+    pub reason: &'static str,
+}
+
+/// Starts ticking for the recording `session_id`, until it stops.
+pub fn start(app: &AppHandle, session_id: String) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(TICK_INTERVAL);
+        if !tick(&app, &session_id) {
+            break;
+        }
+    });
+}
+
+/// Sends one tick for `session_id`; `false` once it is no longer recording.
+fn tick(app: &AppHandle, session_id: &str) -> bool {
+    if app_state::phase(app) != Phase::Recording
+        || app_state::session_id(app).as_deref() != Some(session_id)
+    {
+        return false;
+    }
+    let settings = app.state::<SettingsState>().get().recording;
+    let (tick, speech_detected, silent) = {
+        let dictation = app.state::<DictationState>();
+        let mut dictation = dictation.0.lock().unwrap();
+        let Some(dictation) = dictation.as_mut().filter(|d| d.session_id == session_id) else {
+            return false;
+        };
+        let tick = RecordingTick {
+            session_id: session_id.to_string(),
+            elapsed_ms: dictation.started_at.elapsed().as_millis() as u64,
+            audio_ms: dictation.audio_ms,
+        };
+        // Reported once per recording
+        let silent = settings.silent_input_check_ms > 0
+            && !dictation.silent_input_reported
+            && dictation.audio_ms >= settings.silent_input_check_ms
+            && dictation.peak_level < settings.silent_input_level();
+        if silent {
+            dictation.silent_input_reported = true;
+        }
+        (
+            tick,
+            dictation.speech_detected,
+            silent.then_some(dictation.peak_level),
+        )
+    };
+    let timeout_ms = settings.no_speech_timeout_ms;
+    if !speech_detected && timeout_ms > 0 && tick.elapsed_ms >= timeout_ms {
+        cancel_without_speech(app, session_id);
+        return false;
+    }
+    if let Some(peak_level) = silent {
+        tracing::warn!(session_id, peak_level, "The microphone is silent");
+        let _ = app.emit(
+            "audio:input-silent",
+            InputSilent {
+                session_id: session_id.to_string(),
+                peak_level,
+            },
+        );
+    }
+    let _ = app.emit("recording:tick", tick);
+    true
+}
+
+/// `level` (0 to 1) in dBFS, down to the quietest `silent_input_dbfs`.
+pub fn dbfs(level: f32) -> i32 {
+    if level <= 0.0 {
+        return MIN_SILENT_INPUT_DBFS;
+    }
+    ((20.0 * level.log10()).round() as i32).clamp(MIN_SILENT_INPUT_DBFS, MAX_SILENT_INPUT_DBFS)
+}
+
+/// Whether `dictation` captured audio that never got above `settings`'
+/// silent input level. Without captured audio (the mock provider records
+/// nothing) there is nothing to judge.
+pub fn is_silent(dictation: &Dictation, settings: &RecordingSettings) -> bool {
+    dictation.audio_ms > 0 && dictation.peak_level < settings.silent_input_level()
+}
+
+/// Cancels the recording `session_id`, which heard no speech.
+fn cancel_without_speech(app: &AppHandle, session_id: &str) {
+    // Losing a race with a stop or a cancel leaves nothing to do
+    if app_state::transition_session(app, session_id, Phase::Cancelled, "no_speech").is_err() {
+        return;
+    }
+    let _ = app_state::transition(app, Phase::Idle, "no_speech");
+    tracing::info!(session_id, "Cancelled a recording that heard no speech");
+
+    {
+        let dictation = app.state::<DictationState>();
+        let mut dictation = dictation.0.lock().unwrap();
+        if dictation
+            .as_ref()
+            .is_some_and(|d| d.session_id == session_id)
+        {
+            if let Some(mut dictation) = dictation.take() {
+                dictation.discard_audio();
+            }
+        }
+    }
+    app.state::<RecoveryState>().clear_active();
+    app.state::<LiveHub>().end_session(app);
+    let _ = app.emit(
+        "recording:stopped",
+        SessionEvent {
+            session_id: Some(session_id.to_string()),
+        },
+    );
+    let _ = app.emit(
+        "recording:cancelled",
+        RecordingCancelled {
+            session_id: session_id.to_string(),
+            reason: "no_speech",
+        },
+    );
+
+    // The page shows why in the pill, then it goes away unless a new
+    // dictation took it over
+    pill::set_state(app, PillState::Error);
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(CANCEL_NOTICE);
+        if app_state::phase(&app) == Phase::Idle {
+            pill::set_state(&app, PillState::Hidden);
+        }
+    });
+}
+
+/// Adds `ms` of captured audio, whose highest level was `peak` (0 to 1),
+/// to the recording `session_id`. Reports for any other dictation are
+/// ignored.
+#[tauri::command]
+pub fn report_captured_audio(
+    dictation: State<'_, DictationState>,
+    session_id: String,
+    ms: u64,
+    peak: Option<f32>,
+) {
+    if let Some(dictation) = dictation
+        .0
+        .lock()
+        .unwrap()
+        .as_mut()
+        .filter(|d| d.session_id == session_id)
+    {
+        dictation.audio_ms += ms;
+        if let Some(peak) = peak {
+            dictation.peak_level = dictation.peak_level.max(peak);
+        }
+    }
+}
+
+/// Notes that the recording `session_id` has heard speech, which keeps it
+/// from being cancelled for silence. Reports for any other dictation are
+/// ignored.
+#[tauri::command]
+pub fn report_speech(dictation: State<'_, DictationState>, session_id: String) {
+    if let Some(dictation) = dictation
+        .0
+        .lock()
+        .unwrap()
+        .as_mut()
+        .filter(|d| d.session_id == session_id)
+    {
+        if !dictation.speech_detected {
+            tracing::debug!(session_id, "Speech detected");
+        }
+        dictation.speech_detected = true;
+    }
+}
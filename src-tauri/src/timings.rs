@@ -0,0 +1,185 @@
+//! Where the time of a dictation goes.
+//!
+//! Every stage between the end of a recording and the paste keystroke is
+//! timed, for the most recent [`KEPT_DICTATIONS`] dictations (and pastes of
+//! history entries, which only have the paste stages):
+//!
+//! - `recording`: the recording itself, for reference
+//! - `transcription`: from the end of the recording to the final transcript.
+//!   Audio is encoded and streamed to the provider while recording, so this
+//!   is the provider finishing up
+//! - `post_process`: the post-processing pipeline
+//! - `paste_queue`: waiting for another paste to finish
+//! - `clipboard`: writing the clipboard
+//! - `focus_wait`: hiding our windows and waiting for focus to return
+//! - `keystroke`: the paste keystroke
+//! - `chunk_wait`: for a paste in chunks, the waits between them
+//! - `caret`: moving the caret to a `{cursor}` marker
+//!
+//! Each stage is a `stage` tracing span, so the timings are in the logs as
+//! well. `get_last_timings` returns them, and `transcription:complete`
+//! announces them once a dictation is pasted.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tauri::{Emitter, Manager, State};
+
+use crate::metrics::{self, Counter, Latency};
+use crate::AppHandle;
+
+/// Dictations whose timings are kept.
+const KEPT_DICTATIONS: usize = 20;
+
+/// A timed stage of a dictation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Recording,
+    Transcription,
+    PostProcess,
+    PasteQueue,
+    Clipboard,
+    FocusWait,
+    Keystroke,
+    ChunkWait,
+    Caret,
+}
+
+/// How long one stage took.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageTiming {
+    pub stage: Stage,
+    pub ms: u64,
+}
+
+/// The timings of one dictation, also the payload of
+/// `transcription:complete`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DictationTimings {
+    /// `None` for pastes that aren't dictations.
+    pub session_id: Option<String>,
+    /// When the dictation started, Unix milliseconds.
+    pub started_at_ms: i64,
+    /// In the order they ran.
+    pub stages: Vec<StageTiming>,
+    /// Every stage but the recording: the wait the user feels.
+    pub latency_ms: u64,
+}
+
+/// The most recent timings, newest last.
+#[derive(Default)]
+pub struct TimingsState(Mutex<VecDeque<DictationTimings>>);
+
+/// Starts timing a dictation (or another paste, for `None`).
+pub fn begin(app: &AppHandle, session_id: Option<&str>) {
+    let started_at_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+    let mut recent = app.state::<TimingsState>().0.lock().unwrap();
+    if recent.len() == KEPT_DICTATIONS {
+        recent.pop_front();
+    }
+    recent.push_back(DictationTimings {
+        session_id: session_id.map(str::to_string),
+        started_at_ms,
+        stages: Vec::new(),
+        latency_ms: 0,
+    });
+}
+
+/// Records that `stage` of the dictation `session_id` (the latest paste for
+/// `None`) took `elapsed`.
+pub fn record(app: &AppHandle, session_id: Option<&str>, stage: Stage, elapsed: Duration) {
+    let ms = elapsed.as_millis() as u64;
+    tracing::debug!(?stage, session_id, ms, "Stage finished");
+    let mut recent = app.state::<TimingsState>().0.lock().unwrap();
+    let Some(timings) = recent
+        .iter_mut()
+        .rev()
+        .find(|timings| timings.session_id.as_deref() == session_id)
+    else {
+        return;
+    };
+    timings.stages.push(StageTiming { stage, ms });
+    if stage != Stage::Recording {
+        timings.latency_ms += ms;
+    }
+}
+
+/// Times `stage` until the returned guard is dropped, in a `stage` span.
+pub fn stage(app: &AppHandle, session_id: Option<&str>, stage: Stage) -> StageGuard {
+    StageGuard {
+        app: app.clone(),
+        session_id: session_id.map(str::to_string),
+        stage,
+        started: Instant::now(),
+        _span: tracing::info_span!("stage", ?stage, session_id).entered(),
+    }
+}
+
+/// A stage being timed, see [`stage`].
+pub struct StageGuard {
+    app: AppHandle,
+    session_id: Option<String>,
+    stage: Stage,
+    started: Instant,
+    _span: tracing::span::EnteredSpan,
+}
+
+impl Drop for StageGuard {
+    fn drop(&mut self) {
+        record(
+            &self.app,
+            self.session_id.as_deref(),
+            self.stage,
+            self.started.elapsed(),
+        );
+    }
+}
+
+/// The timings of the dictation `session_id`, if still kept.
+pub fn of(app: &AppHandle, session_id: Option<&str>) -> Option<DictationTimings> {
+    let recent = app.state::<TimingsState>().0.lock().unwrap();
+    recent
+        .iter()
+        .rev()
+        .find(|timings| timings.session_id.as_deref() == session_id)
+        .cloned()
+}
+
+/// The timings kept, newest first.
+pub fn recent(app: &AppHandle) -> Vec<DictationTimings> {
+    let recent = app.state::<TimingsState>().0.lock().unwrap();
+    recent.iter().rev().cloned().collect()
+}
+
+/// Announces the timings of the dictation `session_id`, once it is pasted.
+pub fn complete(app: &AppHandle, session_id: &str) {
+    if let Some(timings) = of(app, Some(session_id)) {
+        tracing::info!(
+            session_id,
+            latency_ms = timings.latency_ms,
+            "Dictation complete"
+        );
+        metrics::count(app, Counter::DictationsCompleted);
+        metrics::time(app, Latency::Dictation, timings.latency_ms);
+        let transcription = timings
+            .stages
+            .iter()
+            .find(|timing| timing.stage == Stage::Transcription);
+        if let Some(timing) = transcription {
+            metrics::time(app, Latency::Transcription, timing.ms);
+        }
+        let _ = app.emit("transcription:complete", timings);
+    }
+}
+
+/// Returns the stage timings of the most recent dictations, newest first.
+#[tauri::command]
+pub fn get_last_timings(state: State<'_, TimingsState>) -> Vec<DictationTimings> {
+    state.0.lock().unwrap().iter().rev().cloned().collect()
+}
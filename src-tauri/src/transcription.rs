@@ -0,0 +1,251 @@
+//! Transcription providers.
+//!
+//! Dictations are transcribed in the webview, which streams the audio to
+//! the provider; the backend says which provider that is
+//! ([`get_transcription_config`]) and which ones can be picked
+//! ([`list_providers`]).
+//!
+//! Besides the real providers there is `mock`, for frontend development,
+//! demos and end-to-end tests without an API key or a microphone: it
+//! ignores the audio and, after `transcription.mock.delay_ms`, returns
+//! `transcription.mock.text`, optionally streamed word by word as partials,
+//! or fails with `transcription.mock.fail`. It is only offered in debug
+//! builds or with `transcription.mock.enabled`, and the
+//! `--mock-transcription` flag uses it for the run whatever the settings
+//! say.
+//!
+//! A dictation is transcribed in `transcription.language`, unless the app in
+//! front when it starts is one of `transcription.app_languages`: then it is
+//! transcribed in that app's language. The language is picked once, at
+//! recording start, so switching apps mid-dictation doesn't change it, and
+//! it is what history and the webhook record. There are no vocabulary sets
+//! to pick per app, only the language.
+
+use serde::{Deserialize, Serialize};
+use tauri::{Manager, State};
+
+use crate::capture::CaptureQuality;
+use crate::dictation::DictationState;
+use crate::frontmost;
+use crate::history::audio::AudioRetention;
+use crate::settings::{self, SettingsState, TranscriptionSettings, KNOWN_PROVIDERS};
+use crate::AppHandle;
+
+/// Name of the mock provider.
+pub const MOCK_PROVIDER: &str = "mock";
+
+/// Command-line flag that transcribes with the mock provider.
+const MOCK_FLAG: &str = "--mock-transcription";
+
+/// Longest artificial delay of the mock provider.
+const MAX_MOCK_DELAY_MS: u64 = 30_000;
+
+/// How the mock provider behaves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockSettings {
+    /// Offer the mock provider in release builds.
+    pub enabled: bool,
+    /// The transcript every dictation gets.
+    pub text: String,
+    /// Time from the start of a dictation to its final transcript.
+    pub delay_ms: u64,
+    /// Emit the transcript word by word as partials first.
+    pub stream_partials: bool,
+    /// Fail every dictation with a provider error instead.
+    pub fail: bool,
+}
+
+impl Default for MockSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            text: "This is a mock transcription.".to_string(),
+            delay_ms: 1_000,
+            stream_partials: true,
+            fail: false,
+        }
+    }
+}
+
+impl MockSettings {
+    /// Rejects delays long enough to look like a hang.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.delay_ms > MAX_MOCK_DELAY_MS {
+            return Err(format!("delay_ms must be at most {}", MAX_MOCK_DELAY_MS));
+        }
+        Ok(())
+    }
+
+    /// Whether the mock provider may be picked.
+    pub fn available(&self) -> bool {
+        cfg!(debug_assertions) || self.enabled || forced()
+    }
+}
+
+/// Whether `--mock-transcription` was passed.
+fn forced() -> bool {
+    std::env::args().any(|arg| arg == MOCK_FLAG)
+}
+
+/// A provider that can be picked in `transcription.provider`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderInfo {
+    pub id: &'static str,
+    pub name: &'static str,
+    /// Whether it streams partial transcripts while recording.
+    pub streaming: bool,
+    /// Whether it needs the network.
+    pub cloud: bool,
+}
+
+fn info(id: &'static str) -> ProviderInfo {
+    match id {
+        "deepgram" => ProviderInfo {
+            id,
+            name: "Deepgram",
+            streaming: true,
+            cloud: true,
+        },
+        MOCK_PROVIDER => ProviderInfo {
+            id,
+            name: "Mock (development)",
+            streaming: true,
+            cloud: false,
+        },
+        _ => ProviderInfo {
+            id,
+            name: id,
+            streaming: false,
+            cloud: true,
+        },
+    }
+}
+
+/// What the webview transcribes with, the payload of
+/// [`get_transcription_config`].
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptionConfig {
+    /// The provider in effect: `mock` with `--mock-transcription`,
+    /// `transcription.provider` otherwise.
+    pub provider: String,
+    /// The dictation's language (see [`language_for_dictation`]).
+    pub language: String,
+    pub mock: MockSettings,
+    /// Peak level (0 to 1) below which the microphone counts as silent;
+    /// audio isn't streamed to the provider until it gets above it.
+    pub silent_input_level: f32,
+    /// What to record the dictation in (see [`crate::capture`]).
+    pub capture: CaptureQuality,
+    /// Whether the storage policy keeps recordings, which are only staged
+    /// then (see [`crate::history::audio`]).
+    pub keep_audio: bool,
+}
+
+/// The provider dictations are transcribed with.
+pub fn active_provider(app: &AppHandle) -> String {
+    if forced() {
+        return MOCK_PROVIDER.to_string();
+    }
+    app.state::<SettingsState>().get().transcription.provider
+}
+
+/// The language to transcribe a dictation into the app `bundle_id` in.
+pub fn language_for(settings: &TranscriptionSettings, bundle_id: Option<&str>) -> String {
+    bundle_id
+        .and_then(|id| settings.app_languages.get(id))
+        .unwrap_or(&settings.language)
+        .clone()
+}
+
+/// The language to transcribe a dictation starting now in. Only looks up
+/// the app in front when that could change the answer, so it must be called
+/// before our own windows take focus.
+pub fn language_for_dictation(app: &AppHandle) -> String {
+    let settings = app.state::<SettingsState>().get().transcription;
+    if settings.app_languages.is_empty() {
+        return settings.language;
+    }
+    let bundle_id = frontmost::frontmost_bundle_id();
+    let language = language_for(&settings, bundle_id.as_deref());
+    if language != settings.language {
+        tracing::info!(app = ?bundle_id, %language, "Using the app's language");
+    }
+    language
+}
+
+/// Returns the providers that can be picked: the real ones, and the mock
+/// one in debug builds or when enabled.
+#[tauri::command]
+pub fn list_providers(app: AppHandle) -> Vec<ProviderInfo> {
+    let settings = app.state::<SettingsState>().get();
+    let mut providers: Vec<_> = KNOWN_PROVIDERS.iter().map(|&id| info(id)).collect();
+    if settings.transcription.mock.available() {
+        providers.push(info(MOCK_PROVIDER));
+    }
+    providers
+}
+
+/// Returns the provider to transcribe the next dictation with, its language,
+/// how the mock provider behaves, when the microphone counts as silent and
+/// what to record in.
+/// The language is the one picked when the dictation in flight started, or
+/// `transcription.language` without one.
+#[tauri::command]
+pub fn get_transcription_config(
+    app: AppHandle,
+    dictation: State<'_, DictationState>,
+) -> TranscriptionConfig {
+    let settings = app.state::<SettingsState>().get();
+    let language = dictation
+        .0
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|dictation| dictation.language.clone());
+    TranscriptionConfig {
+        provider: active_provider(&app),
+        language: language.unwrap_or(settings.transcription.language),
+        mock: settings.transcription.mock,
+        silent_input_level: settings.recording.silent_input_level(),
+        capture: settings.capture.dictation,
+        keep_audio: crate::privacy::policy().audio != AudioRetention::None,
+    }
+}
+
+/// Transcribes dictations into the app `bundle_id` in `language`.
+///
+/// # Errors
+///
+/// Returns an error if either is empty or the settings can't be saved.
+#[tauri::command]
+pub fn set_app_language(app: AppHandle, bundle_id: String, language: String) -> Result<(), String> {
+    settings::update(&app, |settings| {
+        settings
+            .transcription
+            .app_languages
+            .insert(bundle_id.trim().to_string(), language.trim().to_string());
+        Ok(())
+    })
+    .map(|_| ())
+}
+
+/// Transcribes dictations into the app `bundle_id` in
+/// `transcription.language` again.
+///
+/// # Errors
+///
+/// Returns an error if the app has no language of its own or the settings
+/// can't be saved.
+#[tauri::command]
+pub fn remove_app_language(app: AppHandle, bundle_id: String) -> Result<(), String> {
+    settings::update(&app, |settings| {
+        settings
+            .transcription
+            .app_languages
+            .remove(&bundle_id)
+            .map(|_| ())
+            .ok_or_else(|| format!("{} has no language of its own", bundle_id))
+    })
+    .map(|_| ())
+}
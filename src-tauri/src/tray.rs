@@ -0,0 +1,438 @@
+//! System tray (menu bar) icon and its status menu.
+//!
+//! The tray is the app's only permanent presence: the pill window is hidden
+//! between dictations. The menu offers the core actions and reflects live
+//! state, updated from the `recording:started` / `recording:stopped`,
+//! `meeting:started` / `meeting:finished` and `settings:changed` events
+//! rather than by the code that changes it.
+//!
+//! The Profile submenu lists the settings profiles (see
+//! [`crate::settings::profiles`]), the active one checked, and switches
+//! between them.
+//!
+//! While the mic is open the icon switches to an animated "recording"
+//! variant, so there is a glanceable indicator even when the pill is on
+//! another display.
+//!
+//! # Architecture Decision
+//!
+//! Icons are drawn in code as monochrome glyphs. On macOS they are template
+//! images, which the menu bar tints for light and dark appearances. The
+//! animation runs on its own thread only while recording. Every start and
+//! stop bumps a generation counter, and each frame is drawn on the main
+//! thread only if its animation is still current, after any icon change
+//! queued before it: a superseded animation can never draw over the idle
+//! icon, and exits on its next tick.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::image::Image;
+use tauri::menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{Listener, Manager};
+
+use crate::autostart;
+use crate::external::{self, ExternalAction};
+use crate::settings::profiles::{self, ProfileSettings};
+use crate::settings::{self, SettingsChanged, SettingsState};
+use crate::windows;
+use crate::{AppHandle, Runtime};
+
+/// Identifier of the app's tray icon.
+const TRAY_ID: &str = "main";
+
+/// Menu ids of the profile items are this followed by the profile name.
+const PROFILE_ITEM_PREFIX: &str = "profile:";
+
+/// Side of the generated icons, in pixels.
+const ICON_SIZE: u32 = 32;
+
+/// Frames of the recording animation, and how long each is shown.
+const RECORDING_FRAMES: usize = 3;
+const FRAME_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Glyph colors. Template images only use alpha, so macOS gets black.
+#[cfg(target_os = "macos")]
+const GLYPH_COLOR: [u8; 3] = [0, 0, 0];
+#[cfg(not(target_os = "macos"))]
+const GLYPH_COLOR: [u8; 3] = [230, 57, 70];
+
+/// What a left click on the tray icon does. Right clicks always open the
+/// menu.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrayClick {
+    /// Open the menu.
+    #[default]
+    Menu,
+    /// Start a dictation, or stop the one in progress.
+    ToggleRecording,
+}
+
+/// Tray icon settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraySettings {
+    pub left_click: TrayClick,
+}
+
+/// Menu items whose state changes while the app runs.
+#[derive(Clone)]
+struct LiveItems {
+    toggle: MenuItem<Runtime>,
+    meeting: MenuItem<Runtime>,
+    profiles: Submenu<Runtime>,
+    launch_at_login: CheckMenuItem<Runtime>,
+}
+
+/// Managed state for the tray once it exists.
+#[derive(Default)]
+pub struct TrayState {
+    items: Mutex<Option<LiveItems>>,
+    /// Incremented whenever recording starts or stops; an animation stops
+    /// as soon as it is no longer current.
+    animation: AtomicU64,
+}
+
+/// Creates the tray icon and keeps its menu in sync with app state.
+///
+/// # Errors
+///
+/// Returns an error if the menu or icon cannot be created.
+pub fn create(app: &AppHandle) -> tauri::Result<()> {
+    let current = app.state::<SettingsState>().get();
+
+    let toggle = MenuItem::with_id(app, "toggle", "Start Dictation", true, None::<&str>)?;
+    let meeting = MenuItem::with_id(
+        app,
+        "meeting",
+        "Start Meeting Recording",
+        true,
+        None::<&str>,
+    )?;
+    let paste_last = MenuItem::with_id(
+        app,
+        "paste_last",
+        "Paste Last Transcript",
+        true,
+        None::<&str>,
+    )?;
+    let profiles = Submenu::with_id(app, "profiles", "Profile", true)?;
+    fill_profiles(app, &profiles, &current.profiles)?;
+    let open_history = MenuItem::with_id(app, "open_history", "Open History…", true, None::<&str>)?;
+    let open_settings =
+        MenuItem::with_id(app, "open_settings", "Open Settings…", true, None::<&str>)?;
+    let launch_at_login = CheckMenuItem::with_id(
+        app,
+        "launch_at_login",
+        "Launch at Login",
+        true,
+        current.startup.launch_at_login,
+        None::<&str>,
+    )?;
+    let quit = MenuItem::with_id(app, "quit", "Quit", true, Some("CmdOrCtrl+Q"))?;
+
+    let menu = Menu::with_items(
+        app,
+        &[
+            &toggle,
+            &meeting,
+            &paste_last,
+            &PredefinedMenuItem::separator(app)?,
+            &profiles,
+            &open_history,
+            &open_settings,
+            &launch_at_login,
+            &PredefinedMenuItem::separator(app)?,
+            &quit,
+        ],
+    )?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("wispr-clone")
+        .menu(&menu)
+        .show_menu_on_left_click(current.tray.left_click == TrayClick::Menu)
+        .on_menu_event(|app, event| handle_menu(app, event.id().as_ref()))
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                let app = tray.app_handle();
+                let left_click = app.state::<SettingsState>().get().tray.left_click;
+                if left_click == TrayClick::ToggleRecording {
+                    external::dispatch(app, ExternalAction::Toggle);
+                }
+            }
+        });
+    if let Some(icon) = idle_icon(app) {
+        builder = builder.icon(icon);
+    }
+    #[cfg(target_os = "macos")]
+    {
+        builder = builder.icon_as_template(true);
+    }
+    builder.build(app)?;
+
+    *app.state::<TrayState>().items.lock().unwrap() = Some(LiveItems {
+        toggle,
+        meeting,
+        profiles,
+        launch_at_login,
+    });
+
+    let handle = app.clone();
+    app.listen("recording:started", move |_| set_recording(&handle, true));
+    let handle = app.clone();
+    app.listen("recording:stopped", move |_| set_recording(&handle, false));
+    let handle = app.clone();
+    app.listen("meeting:started", move |_| set_meeting(&handle, true));
+    let handle = app.clone();
+    app.listen("meeting:finished", move |_| set_meeting(&handle, false));
+    let handle = app.clone();
+    app.listen("settings:changed", move |event| {
+        if let Ok(changed) = serde_json::from_str::<SettingsChanged>(event.payload()) {
+            apply_settings(&handle, &changed);
+        }
+    });
+    Ok(())
+}
+
+fn handle_menu(app: &AppHandle, id: &str) {
+    match id {
+        "toggle" => external::dispatch(app, ExternalAction::Toggle),
+        "meeting" => external::dispatch(app, ExternalAction::ToggleMeeting),
+        "paste_last" => external::dispatch(app, ExternalAction::PasteLast),
+        "open_history" => {
+            if let Err(e) = windows::open_history_window(app.clone()) {
+                tracing::warn!("Failed to open history: {}", e);
+            }
+        }
+        "open_settings" => {
+            if let Err(e) = windows::open_settings_window(app.clone()) {
+                tracing::warn!("Failed to open settings: {}", e);
+            }
+        }
+        "launch_at_login" => toggle_launch_at_login(app),
+        "quit" => app.exit(0),
+        _ => {
+            if let Some(name) = id.strip_prefix(PROFILE_ITEM_PREFIX) {
+                switch_profile(app, name);
+            }
+        }
+    }
+}
+
+/// Lists `profiles` in `submenu`, replacing what it listed before.
+fn fill_profiles(
+    app: &AppHandle,
+    submenu: &Submenu<Runtime>,
+    profiles: &ProfileSettings,
+) -> tauri::Result<()> {
+    for item in submenu.items()? {
+        submenu.remove(&item)?;
+    }
+    if profiles.saved.is_empty() {
+        let none = MenuItem::with_id(app, "no_profiles", "No Profiles", false, None::<&str>)?;
+        return submenu.append(&none);
+    }
+    for name in profiles.saved.keys() {
+        let item = CheckMenuItem::with_id(
+            app,
+            format!("{}{}", PROFILE_ITEM_PREFIX, name),
+            name,
+            true,
+            profiles.active.as_ref() == Some(name),
+            None::<&str>,
+        )?;
+        submenu.append(&item)?;
+    }
+    Ok(())
+}
+
+/// Switches to the profile `name`. The item toggles itself on click, so the
+/// list is drawn again afterwards, whatever happened.
+fn switch_profile(app: &AppHandle, name: &str) {
+    if let Err(e) = profiles::switch(app, name) {
+        tracing::warn!("Failed to switch to profile {}: {}", name, e);
+    }
+    let current = app.state::<SettingsState>().get().profiles;
+    if let Some(items) = live_items(app) {
+        let _ = fill_profiles(app, &items.profiles, &current);
+    }
+}
+
+/// Flips launch at login. The checkbox toggles itself on click, so it is
+/// reset to the saved state afterwards; on success that is the new state.
+fn toggle_launch_at_login(app: &AppHandle) {
+    let startup = app.state::<SettingsState>().get().startup;
+    if let Err(e) =
+        autostart::set_autostart(app.clone(), !startup.launch_at_login, startup.start_hidden)
+    {
+        tracing::warn!("Failed to change launch at login: {}", e);
+    }
+
+    let launch_at_login = app.state::<SettingsState>().get().startup.launch_at_login;
+    if let Some(items) = live_items(app) {
+        let _ = items.launch_at_login.set_checked(launch_at_login);
+    }
+}
+
+/// Copies of the live menu items. Updating an item waits for the main
+/// thread, so it must never happen with the lock held.
+fn live_items(app: &AppHandle) -> Option<LiveItems> {
+    app.state::<TrayState>().items.lock().unwrap().clone()
+}
+
+fn set_recording(app: &AppHandle, recording: bool) {
+    if let Some(items) = live_items(app) {
+        let label = if recording {
+            "Stop Dictation"
+        } else {
+            "Start Dictation"
+        };
+        let _ = items.toggle.set_text(label);
+    }
+
+    let generation = app
+        .state::<TrayState>()
+        .animation
+        .fetch_add(1, Ordering::SeqCst)
+        + 1;
+    if recording {
+        animate(app.clone(), generation);
+    } else {
+        set_icon(app, generation, idle_icon(app));
+    }
+}
+
+fn set_meeting(app: &AppHandle, recording: bool) {
+    if let Some(items) = live_items(app) {
+        let label = if recording {
+            "Stop Meeting Recording"
+        } else {
+            "Start Meeting Recording"
+        };
+        let _ = items.meeting.set_text(label);
+    }
+}
+
+/// Cycles the recording frames until `generation` is superseded.
+fn animate(app: AppHandle, generation: u64) {
+    let spawned = std::thread::Builder::new()
+        .name("tray-animation".to_string())
+        .spawn(move || {
+            let frames: Vec<Image<'static>> = (0..RECORDING_FRAMES).map(recording_icon).collect();
+            for frame in frames.iter().cycle() {
+                if !is_current(&app, generation) {
+                    return;
+                }
+                set_icon(&app, generation, Some(frame.clone()));
+                std::thread::sleep(FRAME_INTERVAL);
+            }
+        });
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to animate tray icon: {}", e);
+    }
+}
+
+fn is_current(app: &AppHandle, generation: u64) -> bool {
+    app.state::<TrayState>().animation.load(Ordering::SeqCst) == generation
+}
+
+/// Sets the tray icon on the main thread, unless `generation` has been
+/// superseded by the time it runs.
+fn set_icon(app: &AppHandle, generation: u64, icon: Option<Image<'static>>) {
+    let handle = app.clone();
+    let _ = app.run_on_main_thread(move || {
+        if !is_current(&handle, generation) {
+            return;
+        }
+        if let Some(tray) = handle.tray_by_id(TRAY_ID) {
+            let _ = tray.set_icon(icon);
+            #[cfg(target_os = "macos")]
+            let _ = tray.set_icon_as_template(true);
+        }
+    });
+}
+
+/// Whether the tray icon exists.
+pub fn is_present(app: &AppHandle) -> bool {
+    app.tray_by_id(TRAY_ID).is_some()
+}
+
+/// Icon shown while not recording: a ring glyph on macOS, the app icon
+/// elsewhere.
+fn idle_icon(app: &AppHandle) -> Option<Image<'static>> {
+    #[cfg(target_os = "macos")]
+    {
+        let _ = app;
+        Some(draw(|distance| ring(distance, 10.0, 2.5)))
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        app.default_window_icon().cloned()
+    }
+}
+
+/// One frame of the recording animation: a solid dot with a ring pulsing
+/// outwards and fading.
+fn recording_icon(frame: usize) -> Image<'static> {
+    let progress = frame as f32 / (RECORDING_FRAMES - 1) as f32;
+    let radius = 9.0 + 5.0 * progress;
+    let strength = 1.0 - 0.6 * progress;
+    draw(|distance| dot(distance, 6.5).max(ring(distance, radius, 2.0) * strength))
+}
+
+/// Draws a square glyph from the coverage (0 to 1) at each distance from
+/// the center.
+fn draw(coverage: impl Fn(f32) -> f32) -> Image<'static> {
+    let center = ICON_SIZE as f32 / 2.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let dx = x as f32 + 0.5 - center;
+            let dy = y as f32 + 0.5 - center;
+            let alpha = coverage((dx * dx + dy * dy).sqrt()).clamp(0.0, 1.0);
+            rgba.extend_from_slice(&GLYPH_COLOR);
+            rgba.push((alpha * 255.0).round() as u8);
+        }
+    }
+    Image::new_owned(rgba, ICON_SIZE, ICON_SIZE)
+}
+
+/// Anti-aliased coverage of a filled circle.
+fn dot(distance: f32, radius: f32) -> f32 {
+    (radius - distance + 0.5).clamp(0.0, 1.0)
+}
+
+/// Anti-aliased coverage of a circle outline.
+fn ring(distance: f32, radius: f32, width: f32) -> f32 {
+    (width / 2.0 - (distance - radius).abs() + 0.5).clamp(0.0, 1.0)
+}
+
+fn apply_settings(app: &AppHandle, changed: &SettingsChanged) {
+    let settings = &changed.settings;
+    if settings::touches(&changed.changed, "startup") {
+        if let Some(items) = live_items(app) {
+            let _ = items
+                .launch_at_login
+                .set_checked(settings.startup.launch_at_login);
+        }
+    }
+    if settings::touches(&changed.changed, "profiles") {
+        if let Some(items) = live_items(app) {
+            let _ = fill_profiles(app, &items.profiles, &settings.profiles);
+        }
+    }
+    if settings::touches(&changed.changed, "tray") {
+        if let Some(tray) = app.tray_by_id(TRAY_ID) {
+            let _ = tray.set_show_menu_on_left_click(settings.tray.left_click == TrayClick::Menu);
+        }
+    }
+}
@@ -0,0 +1,525 @@
+//! Updates: finding, downloading and installing new versions.
+//!
+//! Updates come from the GitHub releases feed of `updates.channel` through
+//! tauri-plugin-updater: `stable`, the latest release, or `beta`, the
+//! release tagged `beta`. [`check_for_updates`] compares the version running
+//! with the channel's, and [`download_and_install_update`] downloads
+//! it (emitting `update:progress`) and installs it. With
+//! `updates.auto_check` (the default) the feed is also checked once a day in
+//! the background, and `update:available` is emitted when there is a new
+//! version.
+//!
+//! Every update is verified against the public key the build was made with,
+//! `WISPR_UPDATER_PUBKEY` at compile time, before it is installed. A build
+//! without one, such as one made from source, never checks or installs
+//! anything ([`UpdateError::NotConfigured`]).
+//!
+//! Switching channels ([`set_update_channel`]) checks the new one right
+//! away. Nothing is ever downgraded: on a beta build newer than stable,
+//! switching to stable reports stable's version as `behind_version` and
+//! keeps the build until stable catches up with it.
+//!
+//! An update never interrupts a recording: one downloaded while a dictation
+//! or meeting is under way waits (`update:deferred`) until the app is idle,
+//! then is installed and the app restarts.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::json;
+use tauri::{Emitter, Manager, Runtime, Url};
+use tauri_plugin_updater::{Update, UpdaterExt};
+
+use crate::app_state::{self, Phase};
+use crate::errors::{self, ErrorCode};
+use crate::meeting::MeetingState;
+use crate::settings::{self, SettingsState};
+use crate::AppHandle;
+
+/// Public key update signatures are verified with, set when building a
+/// release.
+const PUBKEY: Option<&str> = option_env!("WISPR_UPDATER_PUBKEY");
+
+/// Feeds of the release channels.
+const STABLE_FEED: &str =
+    "https://github.com/ApurvP13/wispr-clone/releases/latest/download/latest.json";
+const BETA_FEED: &str =
+    "https://github.com/ApurvP13/wispr-clone/releases/download/beta/latest.json";
+
+/// How often the feed is checked in the background.
+const CHECK_INTERVAL_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// How often the background checker wakes up to see whether a check is
+/// due.
+const CHECKER_POLL: Duration = Duration::from_secs(60 * 60);
+
+/// How often a deferred update looks whether the app is idle yet.
+const IDLE_POLL: Duration = Duration::from_secs(2);
+
+/// Which releases to update to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    /// Pre-releases, for trying new features early.
+    Beta,
+}
+
+impl UpdateChannel {
+    fn feed(self) -> &'static str {
+        match self {
+            Self::Stable => STABLE_FEED,
+            Self::Beta => BETA_FEED,
+        }
+    }
+}
+
+/// Update settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateSettings {
+    /// Check for a new version once a day.
+    pub auto_check: bool,
+    pub channel: UpdateChannel,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        Self {
+            auto_check: true,
+            channel: UpdateChannel::default(),
+        }
+    }
+}
+
+/// Why checking for or installing an update failed. Serialized with its
+/// `kind`, its [`code`](UpdateError::code) and its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateError {
+    /// This build has no key to verify updates with.
+    NotConfigured,
+    /// There is no newer version to install.
+    NoUpdate,
+    /// The feed or the download couldn't be reached.
+    Network { message: String },
+    /// The update's signature is missing or doesn't match the key.
+    Signature { message: String },
+    /// The update couldn't be written or unpacked.
+    Disk { message: String },
+    /// Any other failure.
+    Failed { message: String },
+}
+
+impl UpdateError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Network { .. } => ErrorCode::UpdateNetwork,
+            Self::Signature { .. } => ErrorCode::UpdateSignature,
+            Self::Disk { .. } => ErrorCode::UpdateDisk,
+            Self::NotConfigured | Self::NoUpdate | Self::Failed { .. } => ErrorCode::Update,
+        }
+    }
+}
+
+impl Serialize for UpdateError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (kind, fields) = match self {
+            Self::NotConfigured => ("not_configured", json!({})),
+            Self::NoUpdate => ("no_update", json!({})),
+            Self::Network { message } => ("network", json!({ "message": message })),
+            Self::Signature { message } => ("signature", json!({ "message": message })),
+            Self::Disk { message } => ("disk", json!({ "message": message })),
+            Self::Failed { message } => ("failed", json!({ "message": message })),
+        };
+        errors::serialize_error(serializer, kind, self.code(), fields)
+    }
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotConfigured => f.write_str("this build can't verify updates"),
+            Self::NoUpdate => f.write_str("no update is available"),
+            Self::Network { message } => write!(f, "couldn't reach the update server: {}", message),
+            Self::Signature { message } => {
+                write!(f, "the update's signature is invalid: {}", message)
+            }
+            Self::Disk { message } => write!(f, "couldn't write the update: {}", message),
+            Self::Failed { message } => f.write_str(message),
+        }
+    }
+}
+
+impl From<tauri_plugin_updater::Error> for UpdateError {
+    fn from(e: tauri_plugin_updater::Error) -> Self {
+        use tauri_plugin_updater::Error;
+
+        let message = e.to_string();
+        match e {
+            Error::Reqwest(_) | Error::Network(_) => Self::Network { message },
+            Error::Minisign(_) | Error::Base64(_) | Error::SignatureUtf8(_) => {
+                Self::Signature { message }
+            }
+            Error::Io(_) => Self::Disk { message },
+            _ => Self::Failed { message },
+        }
+    }
+}
+
+/// The result of [`check_for_updates`], and the payload of
+/// `update:available`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UpdateInfo {
+    pub channel: UpdateChannel,
+    pub current_version: String,
+    /// The newer version, if there is one.
+    pub available_version: Option<String>,
+    pub release_notes: Option<String>,
+    /// When it was published.
+    pub release_date: Option<String>,
+    /// The channel's version when it is older than this build (a beta build
+    /// after switching to stable); it isn't installed.
+    pub behind_version: Option<String>,
+}
+
+/// What a channel's feed has, compared with this build.
+enum Release {
+    Newer(Update),
+    Older(String),
+    Same,
+}
+
+impl UpdateInfo {
+    fn new(app: &AppHandle, channel: UpdateChannel, release: &Release) -> Self {
+        let update = match release {
+            Release::Newer(update) => Some(update),
+            _ => None,
+        };
+        Self {
+            channel,
+            current_version: app.package_info().version.to_string(),
+            available_version: update.map(|update| update.version.clone()),
+            release_notes: update.and_then(|update| update.body.clone()),
+            release_date: update.and_then(|update| update.date.map(|date| date.to_string())),
+            behind_version: match release {
+                Release::Older(version) => Some(version.clone()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// The last check, for the health report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LastCheck {
+    pub at_ms: i64,
+    pub channel: UpdateChannel,
+    pub available_version: Option<String>,
+    pub behind_version: Option<String>,
+    pub error: Option<UpdateError>,
+}
+
+/// Payload of `update:progress`.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateProgress {
+    pub downloaded_bytes: u64,
+    /// `None` if the server didn't say.
+    pub total_bytes: Option<u64>,
+}
+
+/// What [`download_and_install_update`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallStatus {
+    /// Installing now; the app restarts when done.
+    Installing,
+    /// Waiting for the dictation or meeting under way to end.
+    Deferred,
+}
+
+/// Managed state: the update found last, and when the feed was checked.
+#[derive(Default)]
+pub struct UpdaterState {
+    update: Mutex<Option<Update>>,
+    last_check: Mutex<Option<LastCheck>>,
+    /// Whether an update is being downloaded or installed.
+    installing: AtomicBool,
+}
+
+/// The updater plugin, verifying updates with this build's key.
+pub fn plugin<R: Runtime>() -> tauri::plugin::TauriPlugin<R, tauri_plugin_updater::Config> {
+    let builder = tauri_plugin_updater::Builder::new();
+    match PUBKEY {
+        Some(pubkey) => builder.pubkey(pubkey).build(),
+        None => builder.build(),
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Whether a dictation or meeting is recording or being transcribed.
+fn busy(app: &AppHandle) -> bool {
+    let dictating = matches!(
+        app_state::phase(app),
+        Phase::Recording | Phase::Transcribing | Phase::AwaitingConfirmation | Phase::Pasting
+    );
+    dictating || app.state::<MeetingState>().recording()
+}
+
+/// When the feed was last checked, and what came of it.
+pub fn last_check(app: &AppHandle) -> Option<LastCheck> {
+    app.state::<UpdaterState>()
+        .last_check
+        .lock()
+        .unwrap()
+        .clone()
+}
+
+/// Asks `channel`'s feed for its version.
+async fn fetch(app: &AppHandle, channel: UpdateChannel) -> Result<Release, UpdateError> {
+    if PUBKEY.is_none() {
+        return Err(UpdateError::NotConfigured);
+    }
+    let feed = Url::parse(channel.feed()).map_err(|e| UpdateError::Failed {
+        message: e.to_string(),
+    })?;
+    // Any other version is returned, so an older one can be reported
+    let updater = app
+        .updater_builder()
+        .endpoints(vec![feed])?
+        .version_comparator(|current, release| release.version != current)
+        .build()?;
+    let Some(update) = updater.check().await? else {
+        return Ok(Release::Same);
+    };
+
+    let version = semver::Version::parse(&update.version).map_err(|e| UpdateError::Failed {
+        message: format!("invalid version \"{}\": {}", update.version, e),
+    })?;
+    if version > app.package_info().version {
+        Ok(Release::Newer(update))
+    } else {
+        Ok(Release::Older(update.version))
+    }
+}
+
+/// Asks the channel in the settings for a newer version and remembers the
+/// answer.
+async fn check(app: &AppHandle) -> Result<UpdateInfo, UpdateError> {
+    let channel = app.state::<SettingsState>().get().updates.channel;
+    let found = fetch(app, channel).await;
+    let info = found
+        .as_ref()
+        .map(|release| UpdateInfo::new(app, channel, release));
+
+    let state = app.state::<UpdaterState>();
+    *state.last_check.lock().unwrap() = Some(LastCheck {
+        at_ms: now_ms(),
+        channel,
+        available_version: info
+            .as_ref()
+            .ok()
+            .and_then(|info| info.available_version.clone()),
+        behind_version: info
+            .as_ref()
+            .ok()
+            .and_then(|info| info.behind_version.clone()),
+        error: info.as_ref().err().cloned(),
+    });
+    match &found {
+        Ok(Release::Newer(update)) => {
+            tracing::info!(?channel, version = %update.version, "Update available")
+        }
+        Ok(Release::Older(version)) => {
+            tracing::info!(?channel, %version, "Channel is behind this build; staying on it")
+        }
+        Ok(Release::Same) => tracing::debug!(?channel, "No update available"),
+        Err(e) => tracing::warn!("Update check failed: {}", e),
+    }
+    if let Ok(release) = found {
+        *state.update.lock().unwrap() = match release {
+            Release::Newer(update) => Some(update),
+            _ => None,
+        };
+    }
+    info
+}
+
+/// Checks in the background, announcing a newer version with
+/// `update:available`.
+fn check_and_announce(app: &AppHandle) {
+    if let Ok(info) = tauri::async_runtime::block_on(check(app)) {
+        if info.available_version.is_some() {
+            let _ = app.emit("update:available", info);
+        }
+    }
+}
+
+/// Re-checks in the background after the channel changed, dropping what the
+/// previous one offered.
+pub fn channel_changed(app: &AppHandle) {
+    *app.state::<UpdaterState>().update.lock().unwrap() = None;
+    let handle = app.clone();
+    std::thread::spawn(move || check_and_announce(&handle));
+}
+
+/// Starts the background thread checking the feed once a day while
+/// `updates.auto_check` is on. A panic restarts it (see
+/// [`crate::crash::supervise`]).
+pub fn spawn_checker(app: AppHandle) {
+    let handle = app.clone();
+    let spawned = crate::crash::supervise(&app, "update-checker", move || loop {
+        let due =
+            last_check(&handle).map_or(true, |last| now_ms() - last.at_ms >= CHECK_INTERVAL_MS);
+        if due && handle.state::<SettingsState>().get().updates.auto_check {
+            check_and_announce(&handle);
+        }
+        std::thread::sleep(CHECKER_POLL);
+    });
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to start the update checker: {}", e);
+    }
+}
+
+/// Installs a downloaded update once the app is idle, then restarts.
+fn install_when_idle(app: AppHandle, update: Update, bytes: Vec<u8>) {
+    let spawned = std::thread::Builder::new()
+        .name("update-installer".to_string())
+        .spawn(move || {
+            while busy(&app) {
+                std::thread::sleep(IDLE_POLL);
+            }
+            tracing::info!(version = %update.version, "Installing update");
+            match update.install(bytes) {
+                Ok(()) => {
+                    let _ = app.emit("update:installed", &update.version);
+                    app.restart();
+                }
+                Err(e) => {
+                    let error = UpdateError::from(e);
+                    tracing::warn!("Update install failed: {}", error);
+                    app.state::<UpdaterState>()
+                        .installing
+                        .store(false, Ordering::SeqCst);
+                    let _ = app.emit("update:failed", error);
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to start the update installer: {}", e);
+    }
+}
+
+/// Checks the channel's feed for a version newer than this one.
+///
+/// # Errors
+///
+/// Returns an [`UpdateError`]: `not_configured` in builds that can't verify
+/// updates, `network` if the feed can't be reached.
+#[tauri::command]
+pub async fn check_for_updates(app: AppHandle) -> Result<UpdateInfo, UpdateError> {
+    check(&app).await
+}
+
+/// Switches to the `stable` or `beta` channel and checks it right away,
+/// emitting `update:available` if it has a newer version.
+///
+/// # Errors
+///
+/// Returns an error if `channel` is neither or the settings cannot be
+/// saved.
+#[tauri::command]
+pub fn set_update_channel(app: AppHandle, channel: String) -> Result<(), String> {
+    let channel = match channel.as_str() {
+        "stable" => UpdateChannel::Stable,
+        "beta" => UpdateChannel::Beta,
+        _ => {
+            return Err(format!(
+                "unknown update channel \"{}\" (expected stable or beta)",
+                channel
+            ))
+        }
+    };
+    settings::update(&app, |settings| {
+        settings.updates.channel = channel;
+        Ok(())
+    })
+    .map(|_| ())
+}
+
+/// Downloads the newer version, emitting `update:progress`, and installs
+/// it: right away if the app is idle, otherwise once the dictation or
+/// meeting under way ends (`update:deferred`). The app restarts after
+/// installing.
+///
+/// # Errors
+///
+/// Returns an [`UpdateError`]: `no_update` if there is nothing newer,
+/// `network` if the download fails, `signature` if it doesn't verify and
+/// `disk` if it can't be written.
+#[tauri::command]
+pub async fn download_and_install_update(app: AppHandle) -> Result<InstallStatus, UpdateError> {
+    let state = app.state::<UpdaterState>();
+    if state.installing.swap(true, Ordering::SeqCst) {
+        return Err(UpdateError::Failed {
+            message: "an update is already being installed".to_string(),
+        });
+    }
+
+    let downloaded = async {
+        let known = state.update.lock().unwrap().clone();
+        let update = match known {
+            Some(update) => update,
+            None => {
+                check(&app).await?;
+                let found = state.update.lock().unwrap().clone();
+                found.ok_or(UpdateError::NoUpdate)?
+            }
+        };
+        tracing::info!(version = %update.version, "Downloading update");
+        let mut downloaded_bytes = 0u64;
+        let bytes = update
+            .download(
+                |chunk, total_bytes| {
+                    downloaded_bytes += chunk as u64;
+                    let _ = app.emit(
+                        "update:progress",
+                        UpdateProgress {
+                            downloaded_bytes,
+                            total_bytes,
+                        },
+                    );
+                },
+                || {},
+            )
+            .await?;
+        Ok::<_, UpdateError>((update, bytes))
+    }
+    .await;
+    let (update, bytes) = match downloaded {
+        Ok(downloaded) => downloaded,
+        Err(e) => {
+            state.installing.store(false, Ordering::SeqCst);
+            return Err(e);
+        }
+    };
+
+    let status = if busy(&app) {
+        tracing::info!("Update deferred until the recording ends");
+        let _ = app.emit("update:deferred", &update.version);
+        InstallStatus::Deferred
+    } else {
+        InstallStatus::Installing
+    };
+    install_when_idle(app.clone(), update, bytes);
+    Ok(status)
+}
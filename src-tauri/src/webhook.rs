@@ -0,0 +1,466 @@
+//! Webhook: posts every finished dictation to a URL of the user's choosing.
+//!
+//! With `webhook.url` set, each dictation that was pasted is sent there as
+//! a JSON [`WebhookEvent`]: the text, when it started and finished, the app
+//! it was pasted into, how long it took, its session id and, with
+//! `webhook.include_audio`, the recording as base64. The token stored with
+//! [`set_webhook_token`] is sent as a bearer token, if there is one.
+//!
+//! # Architecture Decision
+//!
+//! The webhook can never hold up or break a paste: a dictation is queued
+//! only once its paste has completed, and a background thread delivers the
+//! queue in order. Failed deliveries are retried with exponential backoff
+//! (2s doubling up to 10 minutes, 20 attempts); the queue is saved as
+//! `webhook-queue.json` in the app data directory, so an outage or a
+//! restart doesn't lose events. In privacy mode it stays in memory. A
+//! failure is only ever logged and counted in [`get_webhook_status`], which
+//! diagnostics bundles include, never shown to the user.
+//!
+//! An event whose response got lost is sent again, so every event has an
+//! `id` the receiver can use to ignore duplicates. Responses other than
+//! 408 and 429 in the 4xx range mean the receiver will never accept the
+//! event, which is then dropped instead of retried.
+//!
+//! The URL must be https, except to `localhost` and loopback addresses;
+//! `webhook.allow_insecure_http` allows plain http to any host.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tauri::{Manager, State};
+
+use crate::capture::CaptureFormat;
+use crate::dictation::Dictation;
+use crate::frontmost;
+use crate::privacy;
+use crate::secrets;
+use crate::settings::{Settings, SettingsState};
+use crate::sinks::OutputSink;
+use crate::AppHandle;
+
+/// File under the app data directory holding undelivered events.
+const QUEUE_FILE: &str = "webhook-queue.json";
+
+/// Events kept waiting; the oldest are dropped beyond this.
+const MAX_QUEUED: usize = 500;
+
+/// Deliveries of one event before it is given up.
+const MAX_ATTEMPTS: u32 = 20;
+
+/// Wait before the first retry, doubled for each one after it up to
+/// `MAX_BACKOFF`.
+const FIRST_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// Limit for one delivery, including the upload.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Longest the dispatcher sleeps without checking the queue.
+const IDLE_POLL: Duration = Duration::from_secs(60);
+
+/// Webhook settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookSettings {
+    /// Where dictations are posted. `None` turns the webhook off.
+    pub url: Option<String>,
+    /// Send the recording along with the text.
+    pub include_audio: bool,
+    /// Allow plain http to hosts other than this machine.
+    pub allow_insecure_http: bool,
+}
+
+impl WebhookSettings {
+    /// Checks that the URL is one dictations may be sent to.
+    pub fn validate(&self) -> Result<(), String> {
+        let Some(url) = &self.url else {
+            return Ok(());
+        };
+        let url = reqwest::Url::parse(url.trim()).map_err(|e| format!("invalid URL: {}", e))?;
+        match url.scheme() {
+            "https" => Ok(()),
+            "http" if self.allow_insecure_http || is_local(&url) => Ok(()),
+            "http" => Err(
+                "plain http is only allowed to localhost unless allow_insecure_http is set"
+                    .to_string(),
+            ),
+            _ => Err("must be an http(s) URL".to_string()),
+        }
+    }
+}
+
+/// Whether `url` points at this machine.
+fn is_local(url: &reqwest::Url) -> bool {
+    let host = url.host_str().unwrap_or_default();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// What is posted for one dictation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    /// Unique per event, for the receiver to recognize repeats.
+    pub id: String,
+    pub session_id: String,
+    /// The text as pasted, and as the provider returned it.
+    pub text: String,
+    pub raw_text: String,
+    /// When the recording started and the paste completed.
+    pub started_at_ms: i64,
+    pub finished_at_ms: i64,
+    /// From recording start until the final transcript arrived.
+    pub duration_ms: Option<u64>,
+    /// Bundle id (or process name) of the app pasted into, when known.
+    pub target_app: Option<String>,
+    pub provider: Option<String>,
+    pub language: Option<String>,
+    /// The active settings profile, if any.
+    pub profile: Option<String>,
+    /// What it was recorded in, for dictations recorded by the frontend.
+    pub capture: Option<CaptureFormat>,
+    /// Only with `webhook.include_audio`, for dictations recorded by the
+    /// frontend.
+    pub audio: Option<WebhookAudio>,
+}
+
+/// A recording sent with an event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookAudio {
+    /// Container format, such as `webm`.
+    pub format: String,
+    pub base64: String,
+}
+
+/// An event waiting to be delivered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Pending {
+    event: WebhookEvent,
+    attempts: u32,
+    next_attempt_ms: i64,
+}
+
+/// How deliveries have gone since launch, as returned by
+/// [`get_webhook_status`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WebhookStatus {
+    /// Whether `webhook.url` is set.
+    pub configured: bool,
+    /// Events waiting to be delivered.
+    pub queued: usize,
+    pub delivered: u64,
+    /// Deliveries that failed, counting each retry.
+    pub failures: u64,
+    /// Events given up on: refused, out of attempts, or pushed out of a
+    /// full queue.
+    pub dropped: u64,
+    pub last_error: Option<String>,
+    pub last_delivered_at_ms: Option<i64>,
+}
+
+/// Managed state: the queue, and how deliveries went.
+pub struct WebhookState {
+    path: PathBuf,
+    queue: Mutex<VecDeque<Pending>>,
+    /// Signalled when an event is queued.
+    queued: Condvar,
+    status: Mutex<WebhookStatus>,
+}
+
+impl WebhookState {
+    /// Events left undelivered by the last run are loaded from `data_dir`.
+    pub fn new(data_dir: &Path) -> Self {
+        let path = data_dir.join(QUEUE_FILE);
+        let queue = match fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable {}: {}", path.display(), e);
+                VecDeque::new()
+            }),
+            Err(_) => VecDeque::new(),
+        };
+        Self {
+            path,
+            queue: Mutex::new(queue),
+            queued: Condvar::new(),
+            status: Mutex::new(WebhookStatus::default()),
+        }
+    }
+
+    /// Saves `queue`, or in privacy mode makes sure nothing is on disk.
+    fn save(&self, queue: &VecDeque<Pending>) {
+        let result = if !privacy::policy().webhook_queue || queue.is_empty() {
+            match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+                _ => Ok(()),
+            }
+        } else {
+            serde_json::to_vec(queue)
+                .map_err(|e| e.to_string())
+                .and_then(|json| fs::write(&self.path, json).map_err(|e| e.to_string()))
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to save the webhook queue: {}", e);
+        }
+    }
+
+    /// Notes a failed delivery.
+    fn failed(&self, error: String, dropped: bool) {
+        let mut status = self.status.lock().unwrap();
+        status.failures += 1;
+        if dropped {
+            status.dropped += 1;
+        }
+        status.last_error = Some(error);
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// How long to wait after the `attempts`th failed delivery.
+fn backoff(attempts: u32) -> Duration {
+    FIRST_BACKOFF
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF)
+}
+
+/// The webhook as an output sink: queues each pasted dictation for
+/// delivery, if the webhook is on.
+pub struct Webhook;
+
+impl OutputSink for Webhook {
+    fn accept(&self, app: &AppHandle, dictation: &Dictation, pasted: &str) {
+        enqueue(app, dictation, pasted);
+    }
+}
+
+/// Queues the pasted `dictation` for delivery, if the webhook is on.
+fn enqueue(app: &AppHandle, dictation: &Dictation, pasted: &str) {
+    let Settings {
+        webhook: settings,
+        profiles,
+        ..
+    } = app.state::<SettingsState>().get();
+    if settings.url.is_none() {
+        return;
+    }
+    let Some(raw_text) = dictation.raw_text.clone() else {
+        return;
+    };
+    let audio = match &dictation.audio_path {
+        Some(path) if settings.include_audio => match fs::read(path) {
+            Ok(bytes) => Some(WebhookAudio {
+                format: path
+                    .extension()
+                    .map(|extension| extension.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                base64: base64::engine::general_purpose::STANDARD.encode(bytes),
+            }),
+            Err(e) => {
+                tracing::warn!("Sending the webhook without the recording: {}", e);
+                None
+            }
+        },
+        _ => None,
+    };
+    let finished_at_ms = now_ms();
+    let mut event = WebhookEvent {
+        id: uuid::Uuid::new_v4().to_string(),
+        session_id: dictation.session_id.clone(),
+        text: pasted.to_string(),
+        raw_text,
+        started_at_ms: finished_at_ms - dictation.started_at.elapsed().as_millis() as i64,
+        finished_at_ms,
+        duration_ms: dictation.duration_ms,
+        target_app: None,
+        provider: dictation.provider.clone(),
+        language: dictation.language.clone(),
+        profile: profiles.active,
+        capture: dictation.capture.clone(),
+        audio,
+    };
+
+    // Finding the target app can take a while
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        event.target_app = frontmost::frontmost_bundle_id();
+        let state = app.state::<WebhookState>();
+        let mut queue = state.queue.lock().unwrap();
+        queue.push_back(Pending {
+            event,
+            attempts: 0,
+            next_attempt_ms: finished_at_ms,
+        });
+        let excess = queue.len().saturating_sub(MAX_QUEUED);
+        if excess > 0 {
+            queue.drain(..excess);
+            tracing::warn!(
+                dropped = excess,
+                "Webhook queue full, dropping the oldest events"
+            );
+            state.status.lock().unwrap().dropped += excess as u64;
+        }
+        state.save(&queue);
+        state.queued.notify_one();
+    });
+}
+
+/// Starts the thread that delivers the queue.
+pub fn spawn_dispatcher(app: AppHandle) {
+    let handle = app.clone();
+    let spawned = crate::crash::supervise(&app, "webhook", move || loop {
+        deliver_next(&handle);
+    });
+    if let Err(e) = spawned {
+        tracing::warn!("Failed to start the webhook dispatcher: {}", e);
+    }
+}
+
+/// Delivers the event at the front of the queue, or waits until it is due
+/// or another one is queued.
+fn deliver_next(app: &AppHandle) {
+    let state = app.state::<WebhookState>();
+    let url = app.state::<SettingsState>().get().webhook.url;
+    let pending = {
+        let queue = state.queue.lock().unwrap();
+        let wait = match (&url, queue.front()) {
+            (Some(_), Some(front)) => {
+                Duration::from_millis((front.next_attempt_ms - now_ms()).max(0) as u64)
+            }
+            _ => IDLE_POLL,
+        };
+        if !wait.is_zero() {
+            let _ = state
+                .queued
+                .wait_timeout(queue, wait.min(IDLE_POLL))
+                .unwrap();
+            return;
+        }
+        queue.front().cloned()
+    };
+    let (Some(url), Some(pending)) = (url, pending) else {
+        return;
+    };
+
+    let token = secrets::get(secrets::WEBHOOK_TOKEN).unwrap_or_else(|e| {
+        tracing::warn!("Sending the webhook without a token: {}", e);
+        None
+    });
+    let result = tauri::async_runtime::block_on(send(url.trim(), token, &pending.event));
+
+    let mut queue = state.queue.lock().unwrap();
+    let Some(index) = queue.iter().position(|p| p.event.id == pending.event.id) else {
+        return;
+    };
+    match result {
+        Ok(()) => {
+            queue.remove(index);
+            let mut status = state.status.lock().unwrap();
+            status.delivered += 1;
+            status.last_delivered_at_ms = Some(now_ms());
+        }
+        Err(failure) => {
+            let attempts = pending.attempts + 1;
+            let dropped = !failure.retry || attempts >= MAX_ATTEMPTS;
+            tracing::warn!(
+                session_id = %pending.event.session_id,
+                attempts,
+                dropped,
+                "Webhook delivery failed: {}",
+                failure.error
+            );
+            if dropped {
+                queue.remove(index);
+            } else {
+                queue[index].attempts = attempts;
+                queue[index].next_attempt_ms = now_ms() + backoff(attempts).as_millis() as i64;
+            }
+            state.failed(failure.error, dropped);
+        }
+    }
+    state.save(&queue);
+}
+
+/// Why a delivery failed, and whether trying again could help.
+struct Failure {
+    error: String,
+    retry: bool,
+}
+
+/// Posts `event` to `url`.
+async fn send(url: &str, token: Option<String>, event: &WebhookEvent) -> Result<(), Failure> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| Failure {
+            error: e.to_string(),
+            retry: true,
+        })?;
+    let mut request = client.post(url).json(event);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.map_err(|e| Failure {
+        error: e.to_string(),
+        retry: true,
+    })?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    Err(Failure {
+        error: format!("the webhook responded {}", status),
+        retry: !status.is_client_error()
+            || status == reqwest::StatusCode::REQUEST_TIMEOUT
+            || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+    })
+}
+
+/// How deliveries have gone since launch.
+pub fn status(app: &AppHandle) -> WebhookStatus {
+    let state = app.state::<WebhookState>();
+    let mut status = state.status.lock().unwrap().clone();
+    status.configured = app.state::<SettingsState>().get().webhook.url.is_some();
+    status.queued = state.queue.lock().unwrap().len();
+    status
+}
+
+/// Returns how webhook deliveries have gone since launch.
+#[tauri::command]
+pub fn get_webhook_status(app: AppHandle) -> WebhookStatus {
+    status(&app)
+}
+
+/// Stores the bearer token sent to the webhook in the OS keychain. An empty
+/// token deletes it.
+///
+/// # Errors
+///
+/// Returns an error if the keychain rejects the operation.
+#[tauri::command]
+pub fn set_webhook_token(token: String) -> Result<(), String> {
+    if token.trim().is_empty() {
+        secrets::delete(secrets::WEBHOOK_TOKEN)
+    } else {
+        secrets::set(secrets::WEBHOOK_TOKEN, token.trim())
+    }
+}
+
+/// Drops every event waiting to be delivered.
+#[tauri::command]
+pub fn clear_webhook_queue(state: State<'_, WebhookState>) {
+    let mut queue = state.queue.lock().unwrap();
+    queue.clear();
+    state.save(&queue);
+}
@@ -0,0 +1,500 @@
+//! Window geometry that survives restarts.
+//!
+//! One store, `window-state.json` in the app data dir, holds where every
+//! window was left: the position and size of each secondary window, by
+//! label, and the positions the pill was dragged to, by monitor name (see
+//! [`crate::pill`]). It is kept out of the settings because it changes on
+//! every drag, and settings changes are broadcast to every subsystem.
+//!
+//! Changes are tracked in memory and written at most once per
+//! [`SAVE_INTERVAL`] while a window moves, and always when it closes or the
+//! app exits ([`WindowStore::flush`]). Writes go through a temporary file,
+//! so a crash never leaves a truncated file.
+//!
+//! Saved geometry is checked against the monitors connected when it is
+//! restored ([`fit_on_screen`]): a window whose monitor was unplugged, or
+//! whose resolution shrank, is moved and shrunk onto the nearest one
+//! instead of opening off screen.
+//!
+//! # Migration
+//!
+//! No file means nothing is saved yet. Older versions kept the window
+//! geometry as a bare map by label in the same file, and the pill positions
+//! in `pill-position.json`; both are read into the store, which is then
+//! written in the current format and the old pill file removed.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::pill::placement::Rect;
+
+/// File name of the store inside the app data directory.
+const WINDOW_STATE_FILE: &str = "window-state.json";
+
+/// File the pill positions were kept in before they joined the store.
+const LEGACY_PILL_POSITIONS_FILE: &str = "pill-position.json";
+
+/// Version of the file format.
+const STORE_VERSION: u32 = 1;
+
+/// Least time between two writes while windows move.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Position and inner size of a window, in logical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Geometry {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// The file's contents.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Stored {
+    version: u32,
+    /// Geometry of each secondary window, by label.
+    windows: HashMap<String, Geometry>,
+    /// Where the pill was dragged to on each monitor, by name: its top-left
+    /// corner relative to the monitor's, in that monitor's logical pixels,
+    /// so it survives the monitor moving around in the display arrangement.
+    pill_positions: HashMap<String, (f64, f64)>,
+}
+
+struct Inner {
+    stored: Stored,
+    /// Whether `stored` has changes that aren't written yet.
+    dirty: bool,
+    last_save: Option<Instant>,
+}
+
+/// Managed state: the saved geometry of every window.
+pub struct WindowStore {
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+impl WindowStore {
+    /// Loads the store from `data_dir`, migrating older files. A missing or
+    /// unreadable file just means every window starts at its default place.
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(WINDOW_STATE_FILE);
+        let bytes = fs::read(&path).ok();
+        let (mut stored, mut migrated) = match bytes.as_deref().map(parse) {
+            Some(Some(parsed)) => parsed,
+            Some(None) => {
+                tracing::warn!("Ignoring unreadable {}", WINDOW_STATE_FILE);
+                (Stored::default(), false)
+            }
+            None => (Stored::default(), false),
+        };
+
+        let legacy = data_dir.join(LEGACY_PILL_POSITIONS_FILE);
+        let legacy_positions = fs::read(&legacy)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<HashMap<String, (f64, f64)>>(&bytes).ok());
+        if let Some(positions) = legacy_positions {
+            for (monitor, offset) in positions {
+                stored.pill_positions.entry(monitor).or_insert(offset);
+            }
+            migrated = true;
+        }
+
+        stored.version = STORE_VERSION;
+        let store = Self {
+            path,
+            inner: Mutex::new(Inner {
+                stored,
+                dirty: migrated,
+                last_save: None,
+            }),
+        };
+        if migrated {
+            tracing::info!("Migrated the saved window state");
+            if store.flush() {
+                let _ = fs::remove_file(legacy);
+            }
+        }
+        store
+    }
+
+    /// The saved geometry of the window labeled `label`.
+    pub fn geometry(&self, label: &str) -> Option<Geometry> {
+        self.inner
+            .lock()
+            .unwrap()
+            .stored
+            .windows
+            .get(label)
+            .copied()
+    }
+
+    /// Remembers the geometry of the window labeled `label`, writing it if
+    /// the last write was long enough ago.
+    pub fn set_geometry(&self, label: &str, geometry: Geometry) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.stored.windows.get(label) == Some(&geometry) {
+            return;
+        }
+        inner.stored.windows.insert(label.to_string(), geometry);
+        inner.dirty = true;
+        if !inner
+            .last_save
+            .is_some_and(|at| at.elapsed() < SAVE_INTERVAL)
+        {
+            self.write(&mut inner);
+        }
+    }
+
+    /// Where the pill was dragged to on the monitor named `monitor`.
+    pub fn pill_position(&self, monitor: &str) -> Option<(f64, f64)> {
+        self.inner
+            .lock()
+            .unwrap()
+            .stored
+            .pill_positions
+            .get(monitor)
+            .copied()
+    }
+
+    /// Remembers where the pill was dragged to on `monitor` and writes it.
+    pub fn set_pill_position(&self, monitor: &str, offset: (f64, f64)) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .stored
+            .pill_positions
+            .insert(monitor.to_string(), offset);
+        inner.dirty = true;
+        self.write(&mut inner);
+    }
+
+    /// Forgets every position the pill was dragged to and writes it.
+    pub fn clear_pill_positions(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.stored.pill_positions.clear();
+        inner.dirty = true;
+        self.write(&mut inner);
+    }
+
+    /// Writes any changes not written yet. Returns whether the file is up
+    /// to date.
+    pub fn flush(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        !inner.dirty || self.write(&mut inner)
+    }
+
+    fn write(&self, inner: &mut Inner) -> bool {
+        let json = match serde_json::to_vec_pretty(&inner.stored) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::warn!("Failed to serialize window state: {}", e);
+                return false;
+            }
+        };
+        let tmp = self.path.with_extension("json.tmp");
+        let written = fs::write(&tmp, json).and_then(|()| fs::rename(&tmp, &self.path));
+        inner.last_save = Some(Instant::now());
+        match written {
+            Ok(()) => {
+                inner.dirty = false;
+                true
+            }
+            Err(e) => {
+                tracing::warn!("Failed to save window state: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Reads the file in the current format or the legacy one (a bare map of
+/// geometry by label). The flag says whether it was the legacy one.
+fn parse(bytes: &[u8]) -> Option<(Stored, bool)> {
+    let value: serde_json::Value = serde_json::from_slice(bytes).ok()?;
+    if value.get("version").is_some() {
+        return serde_json::from_value(value)
+            .ok()
+            .map(|stored| (stored, false));
+    }
+    let windows = serde_json::from_value(value).ok()?;
+    Some((
+        Stored {
+            windows,
+            ..Stored::default()
+        },
+        true,
+    ))
+}
+
+/// Where a window saved at `geometry` goes among `monitors` (their logical
+/// bounds): unchanged if it lies within one, otherwise shrunk to fit and
+/// moved inside the monitor it overlaps most, or the one nearest to it when
+/// it is on none. `None` without monitors.
+pub fn fit_on_screen(geometry: Geometry, monitors: &[Rect]) -> Option<Geometry> {
+    let window = Rect {
+        x: geometry.x,
+        y: geometry.y,
+        width: geometry.width,
+        height: geometry.height,
+    };
+    let monitor = monitors
+        .iter()
+        .copied()
+        .max_by(|a, b| overlap(window, *a).total_cmp(&overlap(window, *b)))
+        .filter(|monitor| overlap(window, *monitor) > 0.0)
+        .or_else(|| {
+            monitors
+                .iter()
+                .copied()
+                .min_by(|a, b| distance(window, *a).total_cmp(&distance(window, *b)))
+        })?;
+
+    let width = geometry.width.min(monitor.width);
+    let height = geometry.height.min(monitor.height);
+    Some(Geometry {
+        x: geometry
+            .x
+            .min(monitor.x + monitor.width - width)
+            .max(monitor.x),
+        y: geometry
+            .y
+            .min(monitor.y + monitor.height - height)
+            .max(monitor.y),
+        width,
+        height,
+    })
+}
+
+/// Area of the intersection of `a` and `b`.
+fn overlap(a: Rect, b: Rect) -> f64 {
+    let width = (a.x + a.width).min(b.x + b.width) - a.x.max(b.x);
+    let height = (a.y + a.height).min(b.y + b.height) - a.y.max(b.y);
+    width.max(0.0) * height.max(0.0)
+}
+
+/// Distance between the centers of `a` and `b`.
+fn distance(a: Rect, b: Rect) -> f64 {
+    let (ax, ay) = a.center();
+    let (bx, by) = b.center();
+    (ax - bx).hypot(ay - by)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own for `test`, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "wispr-window-state-{}-{}",
+                test,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn pill_positions_are_kept_per_monitor() {
+        let dir = TempDir::new("per-monitor");
+        let store = WindowStore::open(&dir.0);
+        store.set_pill_position("Built-in Retina Display", (30.0, 40.0));
+        store.set_pill_position("DELL U2720Q", (10.0, 20.0));
+        assert_eq!(store.pill_position("DELL U2720Q"), Some((10.0, 20.0)));
+        assert_eq!(store.pill_position("LG HDR 4K"), None);
+
+        // Written right away, and read back by name
+        let store = WindowStore::open(&dir.0);
+        assert_eq!(
+            store.pill_position("Built-in Retina Display"),
+            Some((30.0, 40.0))
+        );
+        assert_eq!(store.pill_position("DELL U2720Q"), Some((10.0, 20.0)));
+    }
+
+    #[test]
+    fn resetting_forgets_every_monitor() {
+        let dir = TempDir::new("reset");
+        let store = WindowStore::open(&dir.0);
+        store.set_pill_position("DELL U2720Q", (10.0, 20.0));
+        store.clear_pill_positions();
+        assert_eq!(store.pill_position("DELL U2720Q"), None);
+        assert_eq!(WindowStore::open(&dir.0).pill_position("DELL U2720Q"), None);
+    }
+
+    #[test]
+    fn legacy_files_are_migrated() {
+        let dir = TempDir::new("legacy");
+        let geometry = Geometry {
+            x: 100.0,
+            y: 80.0,
+            width: 720.0,
+            height: 520.0,
+        };
+        let windows = HashMap::from([("history".to_string(), geometry)]);
+        fs::write(
+            dir.0.join(WINDOW_STATE_FILE),
+            serde_json::to_vec(&windows).unwrap(),
+        )
+        .unwrap();
+        let positions = HashMap::from([("DELL U2720Q".to_string(), (10.0, 20.0))]);
+        let legacy = dir.0.join(LEGACY_PILL_POSITIONS_FILE);
+        fs::write(&legacy, serde_json::to_vec(&positions).unwrap()).unwrap();
+
+        let store = WindowStore::open(&dir.0);
+        assert_eq!(store.geometry("history"), Some(geometry));
+        assert_eq!(store.pill_position("DELL U2720Q"), Some((10.0, 20.0)));
+        assert!(!legacy.exists());
+
+        let (stored, was_legacy) =
+            parse(&fs::read(dir.0.join(WINDOW_STATE_FILE)).unwrap()).unwrap();
+        assert!(!was_legacy);
+        assert_eq!(stored.version, STORE_VERSION);
+    }
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn geometry(x: f64, y: f64, width: f64, height: f64) -> Geometry {
+        Geometry {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// A 1440x900 laptop display with a 1920x1080 monitor to its left.
+    fn monitors() -> [Rect; 2] {
+        [
+            rect(0.0, 0.0, 1440.0, 900.0),
+            rect(-1920.0, 0.0, 1920.0, 1080.0),
+        ]
+    }
+
+    #[test]
+    fn a_window_on_screen_is_left_alone() {
+        for saved in [
+            geometry(100.0, 80.0, 720.0, 520.0),
+            geometry(-1800.0, 300.0, 800.0, 600.0),
+            // Exactly filling the display
+            geometry(0.0, 0.0, 1440.0, 900.0),
+        ] {
+            assert_eq!(fit_on_screen(saved, &monitors()), Some(saved));
+        }
+    }
+
+    #[test]
+    fn a_window_partly_off_screen_is_moved_on() {
+        let monitors = monitors();
+        // Off the bottom right corner of the display
+        assert_eq!(
+            fit_on_screen(geometry(1300.0, 800.0, 400.0, 300.0), &monitors),
+            Some(geometry(1040.0, 600.0, 400.0, 300.0))
+        );
+        // Above the top of the display
+        assert_eq!(
+            fit_on_screen(geometry(200.0, -150.0, 400.0, 300.0), &monitors),
+            Some(geometry(200.0, 0.0, 400.0, 300.0))
+        );
+        // Across both, mostly on the left monitor: into that one
+        assert_eq!(
+            fit_on_screen(geometry(-500.0, 100.0, 800.0, 600.0), &monitors),
+            Some(geometry(-800.0, 100.0, 800.0, 600.0))
+        );
+    }
+
+    #[test]
+    fn a_window_on_an_unplugged_monitor_goes_to_the_nearest() {
+        // Saved on a monitor to the right of the display, now gone
+        let saved = geometry(2000.0, 100.0, 800.0, 600.0);
+        assert_eq!(
+            fit_on_screen(saved, &monitors()),
+            Some(geometry(640.0, 100.0, 800.0, 600.0))
+        );
+        // Far below the left monitor
+        let saved = geometry(-1500.0, 3000.0, 800.0, 600.0);
+        assert_eq!(
+            fit_on_screen(saved, &monitors()),
+            Some(geometry(-1500.0, 480.0, 800.0, 600.0))
+        );
+    }
+
+    #[test]
+    fn a_window_larger_than_its_monitor_is_shrunk() {
+        // Saved on the left monitor, whose resolution then shrank to the
+        // display's
+        let monitors = [rect(0.0, 0.0, 1440.0, 900.0)];
+        assert_eq!(
+            fit_on_screen(geometry(0.0, 0.0, 1920.0, 1080.0), &monitors),
+            Some(geometry(0.0, 0.0, 1440.0, 900.0))
+        );
+        assert_eq!(
+            fit_on_screen(geometry(300.0, 200.0, 1000.0, 1080.0), &monitors),
+            Some(geometry(300.0, 0.0, 1000.0, 900.0))
+        );
+    }
+
+    #[test]
+    fn nothing_fits_without_monitors() {
+        assert_eq!(
+            fit_on_screen(geometry(100.0, 80.0, 720.0, 520.0), &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn no_file_means_nothing_saved_yet() {
+        let dir = TempDir::new("no-file");
+        let store = WindowStore::open(&dir.0);
+        assert_eq!(store.geometry("history"), None);
+        assert_eq!(store.pill_position("DELL U2720Q"), None);
+        // Nothing to migrate, so nothing is written
+        assert!(!dir.0.join(WINDOW_STATE_FILE).exists());
+
+        let saved = geometry(100.0, 80.0, 720.0, 520.0);
+        store.set_geometry("history", saved);
+        assert!(store.flush());
+        assert_eq!(WindowStore::open(&dir.0).geometry("history"), Some(saved));
+    }
+
+    #[test]
+    fn an_unreadable_file_is_ignored() {
+        let dir = TempDir::new("unreadable");
+        fs::write(
+            dir.0.join(WINDOW_STATE_FILE),
+            b"{\"version\": 1, \"windows\"",
+        )
+        .unwrap();
+        let store = WindowStore::open(&dir.0);
+        assert_eq!(store.geometry("history"), None);
+        store.set_pill_position("DELL U2720Q", (10.0, 20.0));
+        assert_eq!(
+            WindowStore::open(&dir.0).pill_position("DELL U2720Q"),
+            Some((10.0, 20.0))
+        );
+    }
+}
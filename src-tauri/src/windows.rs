@@ -0,0 +1,427 @@
+//! Secondary windows, opened on demand next to the pill.
+//!
+//! Each window is a separate `WebviewWindow` showing one route of the
+//! frontend (`index.html#/<route>`). Windows are created lazily, at most
+//! once: opening one that already exists just brings it to the front.
+//!
+//! The history and recent windows are quick-paste palettes: each remembers
+//! the app that was in front when it opened, hands focus back to that app
+//! when it is dismissed (Escape, or pasting an entry), and is opened by a
+//! global shortcut (`hotkeys.history`, `hotkeys.recent`). Escape is a global
+//! shortcut registered from here while a palette has focus, so it works
+//! before the webview has loaded.
+//!
+//! # Architecture Decision
+//!
+//! Window geometry is remembered in the [`WindowStore`] as the window
+//! moves, resizes and closes, and restored when it is created again, kept on
+//! the monitors connected then.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use tauri::webview::PageLoadEvent;
+use tauri::{Manager, WebviewUrl, WebviewWindowBuilder, WindowEvent};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+
+use crate::errors::{self, ErrorCode};
+use crate::frontmost;
+use crate::pill::placement::Rect;
+use crate::shortcut_capture;
+use crate::window_state::{self, Geometry, WindowStore};
+use crate::{AppHandle, WebviewWindow};
+
+/// Label of the settings window.
+pub const SETTINGS_WINDOW: &str = "settings";
+
+/// Label of the history window.
+pub const HISTORY_WINDOW: &str = "history";
+
+/// Label of the recent transcripts window.
+pub const RECENT_WINDOW: &str = "recent";
+
+/// Closes the focused palette.
+const ESCAPE: &str = "Escape";
+
+/// Why a window could not be opened or closed, serialized with its `kind`,
+/// its [`code`](WindowError::code) and its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowError {
+    /// The window could not be created.
+    CreateFailed { message: String },
+    /// The window exists but could not be shown, focused or closed.
+    OperationFailed { message: String },
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateFailed { message } => write!(f, "Failed to create window: {}", message),
+            Self::OperationFailed { message } => write!(f, "Window operation failed: {}", message),
+        }
+    }
+}
+
+impl Serialize for WindowError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (kind, message) = match self {
+            Self::CreateFailed { message } => ("create_failed", message),
+            Self::OperationFailed { message } => ("operation_failed", message),
+        };
+        errors::serialize_error(serializer, kind, self.code(), json!({ "message": message }))
+    }
+}
+
+impl WindowError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::CreateFailed { .. } | Self::OperationFailed { .. } => ErrorCode::Window,
+        }
+    }
+
+    fn operation(e: tauri::Error) -> Self {
+        Self::OperationFailed {
+            message: e.to_string(),
+        }
+    }
+}
+
+/// A secondary window and how it is first created.
+struct WindowSpec {
+    label: &'static str,
+    title: &'static str,
+    /// Frontend route shown in the window.
+    route: &'static str,
+    /// Inner size used until the user resizes the window.
+    default_size: (f64, f64),
+    min_size: (f64, f64),
+    /// Dismiss the window with Escape while it has focus.
+    close_on_escape: bool,
+}
+
+static SETTINGS: WindowSpec = WindowSpec {
+    label: SETTINGS_WINDOW,
+    title: "wispr-clone Settings",
+    route: "settings",
+    default_size: (800.0, 600.0),
+    min_size: (560.0, 400.0),
+    close_on_escape: false,
+};
+
+static HISTORY: WindowSpec = WindowSpec {
+    label: HISTORY_WINDOW,
+    title: "wispr-clone History",
+    route: "history",
+    default_size: (560.0, 480.0),
+    min_size: (400.0, 300.0),
+    close_on_escape: true,
+};
+
+static RECENT: WindowSpec = WindowSpec {
+    label: RECENT_WINDOW,
+    title: "wispr-clone Recent",
+    route: "recent",
+    default_size: (420.0, 320.0),
+    min_size: (320.0, 200.0),
+    close_on_escape: true,
+};
+
+/// Managed state: what the palettes need to hand focus back.
+#[derive(Default)]
+pub struct WindowState {
+    /// Bundle id of the app in front when each palette opened, by label.
+    previous_apps: Mutex<HashMap<&'static str, String>>,
+    /// Accelerators currently registered for the palettes.
+    history_hotkey: Mutex<Option<String>>,
+    recent_hotkey: Mutex<Option<String>>,
+}
+
+fn geometry_of(window: &WebviewWindow) -> Option<Geometry> {
+    let scale = window.scale_factor().ok()?;
+    let position = window.outer_position().ok()?.to_logical::<f64>(scale);
+    let size = window.inner_size().ok()?.to_logical::<f64>(scale);
+    Some(Geometry {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+    })
+}
+
+/// Remembers where `window` is now.
+fn remember(window: &WebviewWindow) {
+    if let Some(geometry) = geometry_of(window) {
+        window
+            .state::<WindowStore>()
+            .set_geometry(window.label(), geometry);
+    }
+}
+
+/// The logical bounds of every connected monitor.
+fn monitor_bounds(app: &AppHandle) -> Vec<Rect> {
+    app.available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .map(|monitor| {
+            let scale = monitor.scale_factor();
+            let position = monitor.position().to_logical::<f64>(scale);
+            let size = monitor.size().to_logical::<f64>(scale);
+            Rect {
+                x: position.x,
+                y: position.y,
+                width: size.width,
+                height: size.height,
+            }
+        })
+        .collect()
+}
+
+/// Shows and focuses the window described by `spec`, creating it first if it
+/// isn't open.
+fn open(app: &AppHandle, spec: &'static WindowSpec) -> Result<WebviewWindow, WindowError> {
+    if let Some(window) = app.get_webview_window(spec.label) {
+        window.unminimize().map_err(WindowError::operation)?;
+        window.show().map_err(WindowError::operation)?;
+        window.set_focus().map_err(WindowError::operation)?;
+        return Ok(window);
+    }
+
+    let url = WebviewUrl::App(format!("index.html#/{}", spec.route).into());
+    let mut builder = WebviewWindowBuilder::new(app, spec.label, url)
+        .title(spec.title)
+        .min_inner_size(spec.min_size.0, spec.min_size.1)
+        .resizable(true)
+        .focused(true)
+        // A reload drops the page's key listener, so its capture ends too
+        .on_page_load(|window, payload| {
+            if payload.event() == PageLoadEvent::Started {
+                shortcut_capture::abandon(window.app_handle(), window.label());
+            }
+        });
+    let saved = app
+        .state::<WindowStore>()
+        .geometry(spec.label)
+        .and_then(|geometry| window_state::fit_on_screen(geometry, &monitor_bounds(app)));
+    match saved {
+        Some(geometry) => {
+            builder = builder
+                .inner_size(
+                    geometry.width.max(spec.min_size.0),
+                    geometry.height.max(spec.min_size.1),
+                )
+                .position(geometry.x, geometry.y);
+        }
+        None => {
+            builder = builder
+                .inner_size(spec.default_size.0, spec.default_size.1)
+                .center();
+        }
+    }
+    let window = builder.build().map_err(|e| WindowError::CreateFailed {
+        message: e.to_string(),
+    })?;
+
+    let handle = window.clone();
+    window.on_window_event(move |event| match event {
+        WindowEvent::Destroyed => {
+            shortcut_capture::abandon(handle.app_handle(), handle.label());
+            if spec.close_on_escape {
+                unregister_escape(&handle);
+            }
+        }
+        WindowEvent::Moved(_) | WindowEvent::Resized(_) => remember(&handle),
+        WindowEvent::CloseRequested { .. } => {
+            remember(&handle);
+            handle.state::<WindowStore>().flush();
+        }
+        WindowEvent::Focused(true) if spec.close_on_escape => {
+            register_escape(handle.app_handle(), spec.label);
+        }
+        WindowEvent::Focused(false) if spec.close_on_escape => {
+            unregister_escape(&handle);
+        }
+        _ => {}
+    });
+
+    // Accessory apps aren't activated by creating a window
+    #[cfg(target_os = "macos")]
+    let _ = app.show();
+    window.set_focus().map_err(WindowError::operation)?;
+    Ok(window)
+}
+
+/// Closes the window labeled `label` if it is open.
+fn close(app: &AppHandle, label: &str) -> Result<(), WindowError> {
+    let Some(window) = app.get_webview_window(label) else {
+        return Ok(());
+    };
+    window.close().map_err(WindowError::operation)
+}
+
+/// Opens the settings window, or focuses it if it is already open.
+///
+/// # Errors
+///
+/// Returns a [`WindowError`] if the window cannot be created or shown.
+#[tauri::command]
+pub fn open_settings_window(app: AppHandle) -> Result<(), WindowError> {
+    open(&app, &SETTINGS).map(|_| ())
+}
+
+/// Closes the settings window. Does nothing if it isn't open.
+///
+/// # Errors
+///
+/// Returns a [`WindowError`] if the window cannot be closed.
+#[tauri::command]
+pub fn close_settings_window(app: AppHandle) -> Result<(), WindowError> {
+    close(&app, SETTINGS_WINDOW)
+}
+
+/// Opens the palette described by `spec`, remembering the app in front
+/// right now so that dismissing it returns focus there.
+fn open_palette(app: &AppHandle, spec: &'static WindowSpec) -> Result<(), WindowError> {
+    // Reopening from one of our own windows keeps the app remembered last
+    let previous = frontmost::frontmost_bundle_id().filter(|id| *id != app.config().identifier);
+    if let Some(previous) = previous {
+        app.state::<WindowState>()
+            .previous_apps
+            .lock()
+            .unwrap()
+            .insert(spec.label, previous);
+    }
+    open(app, spec).map(|_| ())
+}
+
+/// Opens the history window, or focuses it if it is already open.
+///
+/// The app in front right now is remembered first, so pasting an entry or
+/// pressing Escape returns focus to it.
+///
+/// # Errors
+///
+/// Returns a [`WindowError`] if the window cannot be created or shown.
+#[tauri::command]
+pub fn open_history_window(app: AppHandle) -> Result<(), WindowError> {
+    open_palette(&app, &HISTORY)
+}
+
+/// Opens the recent transcripts window, or focuses it if it is already
+/// open. Like the history window, it hands focus back when dismissed.
+///
+/// # Errors
+///
+/// Returns a [`WindowError`] if the window cannot be created or shown.
+#[tauri::command]
+pub fn open_recent_window(app: AppHandle) -> Result<(), WindowError> {
+    open_palette(&app, &RECENT)
+}
+
+/// Hides the history window, if it is showing, and gives focus back to the
+/// app that was in front when it opened.
+///
+/// Called before pasting a history entry, so the paste lands in that app.
+pub(crate) fn leave_history_window(app: &AppHandle) {
+    leave_palette(app, HISTORY_WINDOW);
+}
+
+/// Hides the palette labeled `label`, if it is showing, and gives focus back
+/// to the app that was in front when it opened. Returns that app's bundle
+/// id, or `None` if it isn't known or the palette wasn't showing.
+pub(crate) fn leave_palette(app: &AppHandle, label: &str) -> Option<String> {
+    let window = app.get_webview_window(label)?;
+    if !window.is_visible().unwrap_or(false) {
+        return None;
+    }
+
+    unregister_escape(app);
+    remember(&window);
+    app.state::<WindowStore>().flush();
+    if let Err(e) = window.hide() {
+        tracing::warn!("Failed to hide the {} window: {}", label, e);
+    }
+
+    let previous = app
+        .state::<WindowState>()
+        .previous_apps
+        .lock()
+        .unwrap()
+        .remove(label);
+    if let Some(bundle_id) = &previous {
+        if !frontmost::activate(bundle_id) {
+            tracing::warn!("Failed to return focus to {}", bundle_id);
+        }
+    }
+    previous
+}
+
+fn register_escape(app: &AppHandle, label: &'static str) {
+    let registered = app
+        .global_shortcut()
+        .on_shortcut(ESCAPE, move |app, _, event| {
+            if event.state == ShortcutState::Pressed {
+                leave_palette(app, label);
+            }
+        });
+    if let Err(e) = registered {
+        tracing::warn!("Failed to register Escape for the {} window: {}", label, e);
+    }
+}
+
+fn unregister_escape(app: &AppHandle) {
+    if app.global_shortcut().is_registered(ESCAPE) {
+        let _ = app.global_shortcut().unregister(ESCAPE);
+    }
+}
+
+/// Registers `accelerator` as the global shortcut that opens the history
+/// window, replacing the previous one.
+pub fn register_history_hotkey(app: &AppHandle, accelerator: &str) {
+    let state = app.state::<WindowState>();
+    register_hotkey(app, &state.history_hotkey, accelerator, open_history_window);
+}
+
+/// Registers `accelerator` as the global shortcut that opens the recent
+/// transcripts window, replacing the previous one.
+pub fn register_recent_hotkey(app: &AppHandle, accelerator: &str) {
+    let state = app.state::<WindowState>();
+    register_hotkey(app, &state.recent_hotkey, accelerator, open_recent_window);
+}
+
+/// Registers `accelerator` to run `open`, replacing the accelerator kept in
+/// `current`.
+///
+/// The lock is never held while (un)registering, which may wait for the main
+/// thread.
+fn register_hotkey(
+    app: &AppHandle,
+    current: &Mutex<Option<String>>,
+    accelerator: &str,
+    open: fn(AppHandle) -> Result<(), WindowError>,
+) {
+    let previous = current.lock().unwrap().take();
+    if let Some(previous) = previous {
+        if previous == accelerator {
+            *current.lock().unwrap() = Some(previous);
+            return;
+        }
+        let _ = app.global_shortcut().unregister(previous.as_str());
+    }
+
+    let registered = app
+        .global_shortcut()
+        .on_shortcut(accelerator, move |app, _, event| {
+            if event.state == ShortcutState::Pressed {
+                if let Err(e) = open(app.clone()) {
+                    tracing::warn!("Failed to open a window: {}", e);
+                }
+            }
+        });
+    match registered {
+        Ok(()) => *current.lock().unwrap() = Some(accelerator.to_string()),
+        Err(e) => tracing::warn!("Failed to register hotkey {}: {}", accelerator, e),
+    }
+}
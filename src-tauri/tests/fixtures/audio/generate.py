@@ -0,0 +1,339 @@
+#!/usr/bin/env python3
+"""Writes the audio files the `audio_file` tests probe and decode.
+
+Each is a few seconds of silence, built by hand so no encoder is needed and
+the files stay a few kilobytes:
+
+- silence.wav: 8 kHz stereo PCM, 0.5 s
+- silence.flac: 8 kHz mono, 3 s of CONSTANT subframes
+- truncated.flac: silence.flac cut after half of its frames
+- silence.mp3: MPEG-1 layer III, 48 kHz mono, 1.2 s, with an Info tag
+- silence.m4a: AAC-LC in MP4, 16 kHz mono, 3.2 s
+- protected.m4a: silence.m4a with its track encrypted ('enca' and 'sinf')
+- silence.ogg: Vorbis, 16 kHz mono, 3 s, with an unused floor per packet
+- silence.opus.ogg: Opus, mono, 3 s of 20 ms packets
+
+Run it from this directory: python3 generate.py
+"""
+
+import struct
+import zlib
+
+
+def crc8(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x07) & 0xFF if crc & 0x80 else (crc << 1) & 0xFF
+    return crc
+
+
+def crc16(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte << 8
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x8005) & 0xFFFF if crc & 0x8000 else (crc << 1) & 0xFFFF
+    return crc
+
+
+def wav():
+    rate, channels, frames = 8000, 2, 4000
+    data = bytes(frames * channels * 2)
+    fmt = struct.pack("<HHIIHH", 1, channels, rate, rate * channels * 2, channels * 2, 16)
+    return (
+        b"RIFF"
+        + struct.pack("<I", 4 + 8 + len(fmt) + 8 + len(data))
+        + b"WAVE"
+        + b"fmt "
+        + struct.pack("<I", len(fmt))
+        + fmt
+        + b"data"
+        + struct.pack("<I", len(data))
+        + data
+    )
+
+
+FLAC_BLOCK = 1000
+FLAC_FRAMES = 24
+
+
+def flac(frames=FLAC_FRAMES):
+    rate, samples = 8000, FLAC_BLOCK * FLAC_FRAMES
+    info = struct.pack(">HH", FLAC_BLOCK, FLAC_BLOCK) + bytes(6)
+    # Rate (20 bits), channels - 1 (3), bits per sample - 1 (5), samples (36)
+    packed = (rate << 44) | (0 << 41) | (15 << 36) | samples
+    info += packed.to_bytes(8, "big") + bytes(16)
+    out = b"fLaC" + bytes([0x80]) + len(info).to_bytes(3, "big") + info
+    for number in range(frames):
+        # Fixed blocking, size in 16 bits at the end, 8 kHz, mono, 16 bits
+        header = bytes([0xFF, 0xF8, 0x74, 0x08, number]) + struct.pack(">H", FLAC_BLOCK - 1)
+        header += bytes([crc8(header)])
+        # CONSTANT subframe of 0
+        frame = header + bytes([0x00]) + struct.pack(">h", 0)
+        out += frame + struct.pack(">H", crc16(frame))
+    return out
+
+
+MP3_FRAMES = 50
+
+
+def mp3(frames=MP3_FRAMES):
+    # MPEG-1 layer III, no CRC, 32 kbps, 48 kHz, mono: 96 bytes, 1152 samples
+    header = bytes([0xFF, 0xFB, 0x14, 0xC0])
+    size = 96
+    side_info = 17
+    info = bytearray(header + bytes(size - 4))
+    tag = b"Info" + struct.pack(">II", 0x1, frames)
+    info[4 + side_info : 4 + side_info + len(tag)] = tag
+    # Zero side info: every granule is silent
+    return bytes(info) + (header + bytes(size - 4)) * frames
+
+
+def box(kind, payload):
+    return struct.pack(">I", 8 + len(payload)) + kind + payload
+
+
+def full_box(kind, version_flags, payload):
+    return box(kind, struct.pack(">I", version_flags) + payload)
+
+
+def descriptor(tag, payload):
+    return bytes([tag, len(payload)]) + payload
+
+
+AAC_FRAMES = 50
+# A single channel element with max_sfb 0 and global gain 160, then END:
+# a long window with no spectral data
+AAC_SILENT_FRAME = bytes([0x01, 0x40, 0x00, 0x07])
+
+
+def m4a(protected=False):
+    rate, frames = 16000, AAC_FRAMES
+    matrix = struct.pack(">9I", 0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x40000000)
+    duration = frames * 1024
+
+    esds = full_box(
+        b"esds",
+        0,
+        descriptor(
+            0x03,
+            struct.pack(">HB", 1, 0)
+            + descriptor(
+                0x04,
+                bytes([0x40, 0x15]) + bytes(3) + struct.pack(">II", 0, 0)
+                # AAC-LC, 16 kHz, mono
+                + descriptor(0x05, bytes([0x14, 0x08])),
+            )
+            + descriptor(0x06, bytes([0x02])),
+        ),
+    )
+    entry = bytes(6) + struct.pack(">H", 1) + bytes(8) + struct.pack(">HHHHI", 1, 16, 0, 0, rate << 16)
+    if protected:
+        sinf = box(
+            b"sinf",
+            box(b"frma", b"mp4a")
+            + full_box(b"schm", 0, b"cenc" + struct.pack(">I", 0x10000))
+            + box(b"schi", full_box(b"tenc", 0, bytes(4) + bytes(16))),
+        )
+        sample_entry = box(b"enca", entry + esds + sinf)
+    else:
+        sample_entry = box(b"mp4a", entry + esds)
+
+    def moov(offset):
+        stbl = box(
+            b"stbl",
+            full_box(b"stsd", 0, struct.pack(">I", 1) + sample_entry)
+            + full_box(b"stts", 0, struct.pack(">III", 1, frames, 1024))
+            + full_box(b"stsc", 0, struct.pack(">IIII", 1, 1, frames, 1))
+            + full_box(b"stsz", 0, struct.pack(">II", len(AAC_SILENT_FRAME), frames))
+            + full_box(b"stco", 0, struct.pack(">II", 1, offset)),
+        )
+        minf = box(
+            b"minf",
+            full_box(b"smhd", 0, bytes(4))
+            + box(b"dinf", full_box(b"dref", 0, struct.pack(">I", 1) + full_box(b"url ", 1, b"")))
+            + stbl,
+        )
+        mdia = box(
+            b"mdia",
+            full_box(b"mdhd", 0, struct.pack(">IIIIHH", 0, 0, rate, duration, 0x55C4, 0))
+            + full_box(b"hdlr", 0, bytes(4) + b"soun" + bytes(12) + b"SoundHandler\0")
+            + minf,
+        )
+        tkhd = full_box(
+            b"tkhd",
+            3,
+            struct.pack(">IIIII", 0, 0, 1, 0, duration * 1000 // rate)
+            + bytes(8)
+            + struct.pack(">HHHH", 0, 0, 0x100, 0)
+            + matrix
+            + struct.pack(">II", 0, 0),
+        )
+        mvhd = full_box(
+            b"mvhd",
+            0,
+            struct.pack(">IIII", 0, 0, 1000, duration * 1000 // rate)
+            + struct.pack(">IH", 0x10000, 0x100)
+            + bytes(10)
+            + matrix
+            + bytes(24)
+            + struct.pack(">I", 2),
+        )
+        return box(b"moov", mvhd + box(b"trak", tkhd + mdia))
+
+    ftyp = box(b"ftyp", b"M4A " + struct.pack(">I", 0) + b"M4A mp42isom")
+    # The samples start after the mdat header, whatever the offset's digits
+    offset = len(ftyp) + len(moov(0)) + 8
+    return ftyp + moov(offset) + box(b"mdat", AAC_SILENT_FRAME * frames)
+
+
+def ogg_crc(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte << 24
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x04C11DB7) & 0xFFFFFFFF if crc & 0x80000000 else (crc << 1) & 0xFFFFFFFF
+    return crc
+
+
+def ogg_page(serial, sequence, granule, packets, flags=0):
+    lacing = b""
+    for packet in packets:
+        length = len(packet)
+        lacing += bytes([255]) * (length // 255) + bytes([length % 255])
+    header = b"OggS" + bytes([0, flags]) + struct.pack("<qIII", granule, serial, sequence, 0)
+    header += bytes([len(lacing)]) + lacing
+    page = bytearray(header + b"".join(packets))
+    page[22:26] = struct.pack("<I", ogg_crc(bytes(page)))
+    return bytes(page)
+
+
+def ogg(headers, packets, samples_per_packet, packets_per_page, first_samples=None):
+    """An Ogg stream of `headers` (a page each) then `packets`, whose first
+    one decodes to `first_samples` and every other to `samples_per_packet`."""
+    serial = 0x57495350
+    pages = []
+    for i, header in enumerate(headers):
+        pages.append(ogg_page(serial, i, 0, [header], flags=0x02 if i == 0 else 0))
+    granule = 0
+    for start in range(0, len(packets), packets_per_page):
+        page = packets[start : start + packets_per_page]
+        for i in range(start, start + len(page)):
+            granule += first_samples if i == 0 and first_samples is not None else samples_per_packet
+        last = start + packets_per_page >= len(packets)
+        pages.append(ogg_page(serial, len(pages), granule, page, flags=0x04 if last else 0))
+    return b"".join(pages)
+
+
+OPUS_PACKETS = 150
+
+
+def opus():
+    head = b"OpusHead" + struct.pack("<BBHIhB", 1, 1, 0, 16000, 0, 0)
+    vendor = b"wispr-clone"
+    tags = b"OpusTags" + struct.pack("<I", len(vendor)) + vendor + struct.pack("<I", 0)
+    # CELT fullband 20 ms, mono, one frame with no data
+    packet = bytes([31 << 3])
+    return ogg([head, tags], [packet] * OPUS_PACKETS, 960, 50)
+
+
+class Bits:
+    """Packs bits the Vorbis way: least significant first."""
+
+    def __init__(self):
+        self.value = 0
+        self.length = 0
+
+    def put(self, value, bits):
+        self.value |= (value & ((1 << bits) - 1)) << self.length
+        self.length += bits
+
+    def bytes(self):
+        return self.value.to_bytes((self.length + 7) // 8, "little")
+
+
+VORBIS_PACKETS = 376
+
+
+def vorbis():
+    rate = 16000
+    ident = b"\x01vorbis" + struct.pack("<IBIiiiBB", 0, 1, rate, 0, 0, 0, 0xB8, 1)
+    vendor = b"wispr-clone"
+    comment = b"\x03vorbis" + struct.pack("<I", len(vendor)) + vendor + struct.pack("<IB", 0, 1)
+
+    setup = Bits()
+    # One codebook: 1 dimension, 2 entries of 1 bit, no lookup
+    setup.put(0, 8)
+    setup.put(0x564342, 24)
+    setup.put(1, 16)
+    setup.put(2, 24)
+    setup.put(0, 1)
+    setup.put(0, 1)
+    setup.put(0, 5)
+    setup.put(0, 5)
+    setup.put(0, 4)
+    # One time domain transform, as always 0
+    setup.put(0, 6)
+    setup.put(0, 16)
+    # One floor 1 with no partitions
+    setup.put(0, 6)
+    setup.put(1, 16)
+    setup.put(0, 5)
+    setup.put(1, 2)
+    setup.put(7, 4)
+    # One residue 0 covering nothing
+    setup.put(0, 6)
+    setup.put(0, 16)
+    setup.put(0, 24)
+    setup.put(0, 24)
+    setup.put(0, 24)
+    setup.put(0, 6)
+    setup.put(0, 8)
+    setup.put(0, 3)
+    setup.put(0, 1)
+    # One mapping: one submap, no coupling
+    setup.put(0, 6)
+    setup.put(0, 16)
+    setup.put(0, 1)
+    setup.put(0, 1)
+    setup.put(0, 2)
+    setup.put(0, 8)
+    setup.put(0, 8)
+    setup.put(0, 8)
+    # One mode: short blocks, mapping 0
+    setup.put(0, 6)
+    setup.put(0, 1)
+    setup.put(0, 16)
+    setup.put(0, 16)
+    setup.put(0, 8)
+    # Framing
+    setup.put(1, 1)
+    setup = b"\x05vorbis" + setup.bytes()
+
+    # An audio packet in mode 0 whose floor is unused: silence
+    packet = bytes([0x00])
+    # Short blocks of 256 give 128 samples each, but the first gives none
+    return ogg([ident, comment, setup], [packet] * VORBIS_PACKETS, 128, 64, first_samples=0)
+
+
+def main():
+    files = {
+        "silence.wav": wav(),
+        "silence.flac": flac(),
+        "truncated.flac": flac(FLAC_FRAMES // 2),
+        "silence.mp3": mp3(),
+        "silence.m4a": m4a(),
+        "protected.m4a": m4a(protected=True),
+        "silence.ogg": vorbis(),
+        "silence.opus.ogg": opus(),
+    }
+    for name, data in files.items():
+        with open(name, "wb") as file:
+            file.write(data)
+        print(f"{name}: {len(data)} bytes, crc {zlib.crc32(data):08x}")
+
+
+if __name__ == "__main__":
+    main()
@@ -7,7 +7,7 @@
  *
  * # Features
  *
- * - Global hotkey registration (Alt+Space to start recording)
+ * - Global hotkey registration (`hotkeys.dictation`, Alt+Space by default)
  * - Visual feedback during recording (animation + transcript)
  * - Processing state indicator
  * - Error handling and display
@@ -21,26 +21,95 @@
  *
  * # Hotkeys
  *
- * - **Alt+Space**: Start recording (shows window and begins transcription)
+ * - **Alt+Space** (`hotkeys.dictation`): Start recording (shows window and
+ *   begins transcription)
+ * - **Alt+Ctrl+Space**: Start recording and rewrite concisely before pasting
  * - **Alt+Shift+Space**: Test transcript UI (development only)
  * - **Escape**: Cancel recording and hide window
  *
  * @module App
  */
 
-import { useEffect } from "react";
+import { useEffect, useRef, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import Lottie from "lottie-react";
 import recordingAnimation from "./assets/recordingAnimation.json";
 import "./App.css";
 import { LoaderCircle } from "lucide-react";
-import { register, unregister } from "@tauri-apps/plugin-global-shortcut";
+import {
+  register,
+  unregister,
+  ShortcutEvent,
+} from "@tauri-apps/plugin-global-shortcut";
 import { useVoiceRecording } from "./hooks/useVoiceRecording";
+import { useMeetingRecording } from "./hooks/useMeetingRecording";
+import { BenchmarkRequest, runBenchmark } from "./services/benchmark";
+import { log } from "./services/log";
 
 // Deepgram API key from environment variables
 // Must be set in .env file: VITE_DEEPGRAM_API_KEY=your_key_here
 const DEEPGRAM_API_KEY = import.meta.env.VITE_DEEPGRAM_API_KEY as string;
 
+/** The pill window's background, as applied by the backend. */
+interface PillAppearance {
+  vibrancy: boolean;
+  material: string;
+}
+
+/** Part of the pill, in window coordinates, for click-through. */
+interface HitRect {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+  radius: number;
+}
+
+/** What a press of a dictation hotkey did (`press_dictation_hotkey`). */
+type HotkeyOutcome =
+  | { action: "started"; session_id: string; replaced: string | null }
+  | { action: "ignored"; reason: string };
+
+/** What a dictation interrupted in the previous run left behind. */
+interface PendingRecovery {
+  transcript: string | null;
+  audio_path: string | null;
+  created_at_ms: number;
+}
+
+/** What `recover_pending` did. */
+type RecoveryOutcome =
+  | { kind: "pasted"; text: string }
+  | { kind: "needs_transcription"; audio_path: string }
+  | { kind: "nothing" };
+
+/** The dictation hotkeys of the backend's `hotkeys` settings. */
+interface DictationHotkeys {
+  dictation: string;
+  rewrite: string;
+  command: string;
+}
+
+/** The settings whose change registers the hotkeys again. */
+const HOTKEY_SETTINGS = [
+  "hotkeys.dictation",
+  "hotkeys.rewrite",
+  "hotkeys.command",
+];
+
+/** Formats a duration as m:ss. */
+const formatDuration = (ms: number) => {
+  const seconds = Math.floor(ms / 1000);
+  return `${Math.floor(seconds / 60)}:${String(seconds % 60).padStart(2, "0")}`;
+};
+
+/** An external event that arrived before the listeners were registered. */
+interface PendingEvent {
+  event: string;
+  payload: any;
+}
+
 /**
  * Main application component.
  *
@@ -60,20 +129,310 @@ function App() {
     transcript,
     isProcessing,
     error,
+    recordedMs,
+    inputSilent,
+    interrupted,
     startRecording,
-    stopRecording,
+    cancelRecording,
+    finishRecording,
+    transcribeFile,
+    transcribeInterrupted,
+    discardInterrupted,
   } = useVoiceRecording(DEEPGRAM_API_KEY);
 
+  // Meetings are recorded apart from dictations; their transcript goes to
+  // history as it grows
+  const { toggleMeeting } = useMeetingRecording(DEEPGRAM_API_KEY);
+
+  // Latest state and actions for listeners that are registered only once
+  const latest = useRef({
+    isRecording,
+    startRecording,
+    cancelRecording,
+    finishRecording,
+    transcribeFile,
+    toggleMeeting,
+  });
+  latest.current = {
+    isRecording,
+    startRecording,
+    cancelRecording,
+    finishRecording,
+    transcribeFile,
+    toggleMeeting,
+  };
+
+  // The recording pill, measured to fit the window to the transcript
+  const pillRef = useRef<HTMLDivElement>(null);
+
+  // Whether the window behind the pill is blurred (`pill.vibrancy`)
+  const [vibrant, setVibrant] = useState(false);
+
+  // The window's content, whose children are the pill as drawn
+  const rootRef = useRef<HTMLDivElement>(null);
+
+  // Text of a failed paste, brought back from its notification
+  const [recovered, setRecovered] = useState<string | null>(null);
+
+  // What a dictation interrupted in the previous run left behind
+  const [pendingRecovery, setPendingRecovery] =
+    useState<PendingRecovery | null>(null);
+
+  // The transcript waiting for the paste to be confirmed
+  // (`confirm_before_paste`)
+  const [confirming, setConfirming] = useState<{
+    session_id: string;
+    kind: "paste" | "commands";
+    text: string;
+  } | null>(null);
+
+  // Set while asking whether to quit in the middle of a recording
+  const [quitPrompt, setQuitPrompt] = useState(false);
+
+  // Briefly set when a hotkey press was ignored, to flash the pill
+  const [flash, setFlash] = useState(false);
+
+  // Briefly set to the new microphone when the input changed mid-recording
+  const [inputNotice, setInputNotice] = useState<string | null>(null);
+
+  // The hit region outlined for debugging (`debug_pill_hit_region`)
+  const [hitRegionDebug, setHitRegionDebug] = useState<HitRect[] | null>(
+    null
+  );
+
+  /**
+   * Effect hook: Follow the pill's background, so the pill is translucent
+   * over a blurred window and opaque otherwise.
+   */
+  useEffect(() => {
+    invoke<PillAppearance>("get_pill_appearance")
+      .then((appearance) => setVibrant(appearance.vibrancy))
+      .catch(console.warn);
+    const unlisten = listen<PillAppearance>("pill:appearance-changed", (e) =>
+      setVibrant(e.payload.vibrancy)
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  /**
+   * Effect hook: Show the text of a failed paste again when its
+   * notification brings the app back; a new dictation replaces it.
+   */
+  useEffect(() => {
+    const unlisten = listen<{ text: string }>("notification:recover", (e) =>
+      setRecovered(e.payload.text)
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+  useEffect(() => {
+    if (isRecording) setRecovered(null);
+  }, [isRecording]);
+
+  /**
+   * Effect hook: Offer what an interrupted dictation left behind. Asked
+   * for once the page is up, since the backend finds it at startup, before
+   * anything here listens; a new dictation puts the offer away.
+   */
+  useEffect(() => {
+    invoke<PendingRecovery | null>("get_pending_recovery")
+      .then((pending) => {
+        if (!pending) return;
+        setPendingRecovery(pending);
+        return invoke("set_pill_state", {
+          state: { kind: "transcript", estimated_lines: 2 },
+        });
+      })
+      .catch(console.warn);
+  }, []);
+  useEffect(() => {
+    if (isRecording) setPendingRecovery(null);
+  }, [isRecording]);
+
+  // Pastes the recovered transcript, or transcribes the recovered audio
+  // and pastes that
+  const recoverPending = async () => {
+    setPendingRecovery(null);
+    try {
+      const outcome = await invoke<RecoveryOutcome>("recover_pending");
+      if (outcome.kind === "needs_transcription") {
+        await transcribeFile(outcome.audio_path);
+        await invoke("discard_pending");
+      }
+    } catch (err) {
+      console.warn("Failed to recover the interrupted dictation:", err);
+    }
+  };
+
+  const discardPending = async () => {
+    setPendingRecovery(null);
+    await invoke("discard_pending");
+    await invoke("hide_recording_pill");
+  };
+
+  /**
+   * Effect hook: Show the transcript waiting for confirmation
+   * (`paste:awaiting-confirmation`) until it is answered, cancelled or
+   * times out, which moves the app out of `awaiting_confirmation`.
+   */
+  useEffect(() => {
+    const unlistenAwaiting = listen<{
+      session_id: string;
+      kind: "paste" | "commands";
+      text: string;
+    }>("paste:awaiting-confirmation", (e) => setConfirming(e.payload));
+    const unlistenState = listen<{ from: string }>("state:changed", (e) => {
+      if (e.payload.from === "awaiting_confirmation") setConfirming(null);
+    });
+    return () => {
+      unlistenAwaiting.then((fn) => fn());
+      unlistenState.then((fn) => fn());
+    };
+  }, []);
+
+  /**
+   * Effect hook: Say which microphone the recording carries on from when
+   * the default input changed (`audio:device-changed`).
+   */
+  useEffect(() => {
+    let timer: number | undefined;
+    const unlisten = listen<{ from: string | null; to: string | null }>(
+      "audio:device-changed",
+      (e) => {
+        setInputNotice(e.payload.to ?? "another microphone");
+        window.clearTimeout(timer);
+        timer = window.setTimeout(() => setInputNotice(null), 3000);
+      }
+    );
+    return () => {
+      window.clearTimeout(timer);
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  /**
+   * Effect hook: Flash the pill when a hotkey press is ignored because a
+   * dictation is already under way (`hotkey:ignored`).
+   */
+  useEffect(() => {
+    let timer: number | undefined;
+    const unlisten = listen<{ reason: string }>("hotkey:ignored", (e) => {
+      log.debug(`Hotkey ignored while ${e.payload.reason}`);
+      setFlash(true);
+      window.clearTimeout(timer);
+      timer = window.setTimeout(() => setFlash(false), 400);
+    });
+    return () => {
+      window.clearTimeout(timer);
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  /**
+   * Effect hook: Ask what to do with the recording when the app is told to
+   * quit in the middle of one (`shutdown:confirm`); the answer goes to
+   * `shutdown_now`.
+   */
+  useEffect(() => {
+    const unlisten = listen("shutdown:confirm", () => setQuitPrompt(true));
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+  useEffect(() => {
+    if (!isRecording) setQuitPrompt(false);
+  }, [isRecording]);
+
+  /**
+   * Effect hook: Grow the pill with the live transcript.
+   *
+   * The backend debounces these, keeps the height within limits and the
+   * screen, and animates the resize; past the maximum the pill scrolls.
+   */
+  useEffect(() => {
+    if (!isRecording || !transcript || !pillRef.current) return;
+    invoke("resize_pill_to_content", {
+      heightPx: pillRef.current.scrollHeight,
+    }).catch(console.warn);
+  }, [isRecording, transcript]);
+
+  /**
+   * Effect hook: Report where the pill is drawn, so clicks on the
+   * transparent margins around it go through to the app below.
+   *
+   * Measured again whenever the window or the visible pill resizes.
+   */
+  useEffect(() => {
+    const root = rootRef.current;
+    if (!root) return;
+    const parts = Array.from(root.children).filter(
+      (el) => !el.hasAttribute("data-hit-debug")
+    );
+    const report = () => {
+      const rects: HitRect[] = parts.map((el) => {
+        const rect = el.getBoundingClientRect();
+        return {
+          x: rect.left,
+          y: rect.top,
+          width: rect.width,
+          height: rect.height,
+          // The backend caps it, e.g. for `rounded-full`
+          radius: parseFloat(getComputedStyle(el).borderTopLeftRadius) || 0,
+        };
+      });
+      invoke("set_pill_hit_region", { rects }).catch(console.warn);
+    };
+    const observer = new ResizeObserver(report);
+    observer.observe(root);
+    parts.forEach((el) => observer.observe(el));
+    return () => observer.disconnect();
+  }, [
+    isRecording,
+    isProcessing,
+    error,
+    vibrant,
+    quitPrompt,
+    interrupted,
+    confirming,
+  ]);
+
+  /**
+   * Effect hook: Outline the hit region while the backend asks for it.
+   */
+  useEffect(() => {
+    const unlisten = listen<{ visible: boolean; rects: HitRect[] }>(
+      "pill:hit-region-debug",
+      (e) => setHitRegionDebug(e.payload.visible ? e.payload.rects : null)
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
   /**
    * Effect hook: Register global hotkeys on component mount.
    *
-   * Sets up three global hotkeys:
-   * 1. Alt+Space: Primary recording trigger
-   * 2. Alt+Shift+Space: Test transcript UI (dev only)
-   * 3. Escape: Cancel recording
+   * Sets up four global hotkeys, the first three from the settings:
+   * 1. `hotkeys.dictation` (Alt+Space): Primary recording trigger
+   * 2. `hotkeys.rewrite` (Alt+Ctrl+Space): Recording with concise LLM
+   *    rewrite before paste
+   * 3. `hotkeys.command` (Alt+Shift+C): Recording in command mode, whose
+   *    voice commands press keys instead of typing
+   * 4. Alt+Shift+Space: Test transcript UI (dev only)
+   *
+   * Escape is registered separately, only while recording (see below).
+   *
+   * The handlers run on press only, and leave debouncing and what a press
+   * does in the current state to the backend (`press_dictation_hotkey`).
    *
    * Hotkeys are registered asynchronously and unregistered on unmount
-   * to prevent memory leaks and ensure clean teardown.
+   * to prevent memory leaks and ensure clean teardown. They are registered
+   * again when one of them is changed in the settings (`settings:changed`),
+   * and after the system wakes if the backend found the dictation hotkey
+   * gone (`power:woke`).
    *
    * # Architecture Decision
    *
@@ -85,63 +444,247 @@ function App() {
    * Empty dependency array ensures this runs only once on mount.
    */
   useEffect(() => {
+    // What `setup` registered, for `teardown` to unregister
+    let registered: string[] = [];
+
     const setup = async () => {
-      console.log("=== SETUP STARTING ===");
+      log.debug("Registering hotkeys");
 
-      try {
-        // Register Alt+Space hotkey for recording
-        await register("Alt+Space", async () => {
-          console.log("🔥 Alt+Space PRESSED! 🔥");
+      // A press of a dictation hotkey. The plugin also reports releases,
+      // which would count as a second press
+      const onDictationHotkey =
+        (rewritePreset?: string, command = false) =>
+        async (event: ShortcutEvent) => {
+          if (event.state !== "Pressed") return;
+          log.debug(`${event.shortcut} pressed`);
           try {
-            // Show window
-            await invoke("show_recording_pill");
+            // Shows the window when a dictation starts; its session id
+            // comes back
+            const outcome = await invoke<HotkeyOutcome>("press_dictation_hotkey", {
+              action: command
+                ? "command"
+                : rewritePreset
+                  ? "rewrite"
+                  : "dictation",
+            });
+            if (outcome.action === "ignored") return;
 
             // Start recording
-            await startRecording();
+            await startRecording({
+              sessionId: outcome.session_id,
+              rewritePreset,
+              replacing: outcome.replaced !== null,
+            });
           } catch (e) {
             console.error("Failed:", e);
           }
-        });
+        };
+
+      try {
+        const { hotkeys } = await invoke<{ hotkeys: DictationHotkeys }>(
+          "get_settings"
+        );
+
+        // Register the dictation hotkey for recording
+        await register(hotkeys.dictation, onDictationHotkey());
+        registered.push(hotkeys.dictation);
+
+        // The rewrite hotkey: same flow, but rewrite the transcript before
+        // pasting
+        await register(hotkeys.rewrite, onDictationHotkey("concise"));
+        registered.push(hotkeys.rewrite);
+
+        // The command hotkey: same flow, but the transcript is voice commands
+        await register(hotkeys.command, onDictationHotkey(undefined, true));
+        registered.push(hotkeys.command);
 
         // Development hotkey: Alt+Shift+Space to test transcript UI
         // Useful for testing the transcript pill appearance without recording
         await register("Alt+Shift+Space", async () => {
-          console.log("🔥 Alt+Shift+Space PRESSED (Test) 🔥");
+          log.debug("Alt+Shift+Space pressed (test)");
           try {
             await invoke("show_transcript_pill");
           } catch (e) {
             console.error("Failed:", e);
           }
         });
+        registered.push("Alt+Shift+Space");
 
-        // Cancel hotkey: Escape to stop recording and hide window
-        // Only active when recording is in progress
-        await register("Escape", async () => {
-          if (isRecording) {
-            console.log("🚫 Recording cancelled by user");
-            await stopRecording();
-            await invoke("hide_recording_pill");
-          }
-        });
-
-        console.log("✅ Hotkeys registered successfully!");
+        log.info("Hotkeys registered");
       } catch (error) {
         console.error("❌ Setup failed:", error);
       }
     };
 
-    setup();
+    const teardown = () => {
+      const shortcuts = registered;
+      registered = [];
+      return Promise.all(
+        shortcuts.map((shortcut) => unregister(shortcut).catch(console.error))
+      );
+    };
+
+    // Registers again, one change at a time
+    let pending = setup();
+    const reregister = () => {
+      pending = pending.then(teardown).then(setup);
+      return pending;
+    };
+
+    const unlisten = listen<{ hotkey_registered: boolean }>(
+      "power:woke",
+      async (e) => {
+        if (e.payload.hotkey_registered) return;
+        log.info("Registering hotkeys again after sleep");
+        await reregister();
+      }
+    );
+    const unlistenSettings = listen<{ changed: string[] }>(
+      "settings:changed",
+      async (e) => {
+        if (!e.payload.changed.some((key) => HOTKEY_SETTINGS.includes(key))) {
+          return;
+        }
+        log.info("Registering the changed hotkeys");
+        await reregister();
+      }
+    );
 
     // Cleanup: Unregister all hotkeys when component unmounts
     // This prevents hotkeys from persisting after app closes
     return () => {
-      console.log("Cleanup: unregistering hotkeys...");
-      unregister("Alt+Space").catch(console.error);
-      unregister("Alt+Shift+Space").catch(console.error);
-      unregister("Escape").catch(console.error);
+      log.debug("Unregistering hotkeys");
+      unlisten.then((fn) => fn());
+      unlistenSettings.then((fn) => fn());
+      pending.then(teardown);
     };
   }, []); // Empty dependency array - run once on mount
 
+  /**
+   * Effect hook: Escape cancels the dictation in progress, while recording
+   * or once stopped, while it is transcribed.
+   *
+   * A global Escape would swallow the key in every other app, so it is only
+   * registered during a dictation. The history window registers its own
+   * Escape (from the backend) while it has focus.
+   */
+  useEffect(() => {
+    if (!isRecording && !isProcessing) return;
+
+    register("Escape", async () => {
+      log.info("Dictation cancelled by the user");
+      await latest.current.cancelRecording();
+    }).catch(console.error);
+
+    return () => {
+      unregister("Escape").catch(console.error);
+    };
+  }, [isRecording, isProcessing]);
+
+  /**
+   * Effect hook: Resync with the backend's dictation state on load.
+   *
+   * After a reload (e.g. in development) the backend may still think a
+   * dictation is under way that this page knows nothing about; it is
+   * cancelled so the next hotkey press can start a new one.
+   */
+  useEffect(() => {
+    invoke<{ phase: string }>("get_app_state")
+      .then(async ({ phase }) => {
+        if (
+          (phase === "recording" || phase === "transcribing") &&
+          !latest.current.isRecording
+        ) {
+          log.info(`Dropping a ${phase} dictation from before the reload`);
+          await invoke("hide_recording_pill");
+        }
+      })
+      .catch(console.warn);
+  }, []);
+
+  /**
+   * Effect hook: Cancel the recording when the backend dismissed the pill
+   * (e.g. a click outside it).
+   */
+  useEffect(() => {
+    const unlisten = listen<{ reason: string; session_id: string | null }>(
+      "recording:cancelled",
+      async (e) => {
+        log.info(`Recording cancelled: ${e.payload.reason}`);
+        await latest.current.cancelRecording();
+      }
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  /**
+   * Effect hook: Handle requests from outside the app.
+   *
+   * Launching the app again while it runs (optionally with
+   * `--transcribe <file>`), `wispr://` links and the local HTTP API are
+   * forwarded here by the backend as `external:*` / `deeplink:*` events. Requests that arrived before this effect ran
+   * (arguments of the first launch) are fetched once and replayed.
+   */
+  useEffect(() => {
+    const start = async () => {
+      if (!latest.current.isRecording) {
+        const sessionId = await invoke<string>("show_recording_pill");
+        await latest.current.startRecording({ sessionId });
+      }
+    };
+    const stop = async () => {
+      if (latest.current.isRecording) {
+        await latest.current.finishRecording();
+      }
+    };
+
+    const handlers: Record<string, (payload: any) => Promise<void>> = {
+      "external:toggle": async () => {
+        await (latest.current.isRecording ? stop() : start());
+      },
+      "external:start": start,
+      "external:stop": stop,
+      "external:toggle-meeting": async () => {
+        await latest.current.toggleMeeting();
+      },
+      "external:transcribe": async (payload) => {
+        await latest.current.transcribeFile(payload.path);
+      },
+      "benchmark:run": async (payload: BenchmarkRequest) => {
+        await runBenchmark(payload, DEEPGRAM_API_KEY);
+      },
+      "external:error": async (payload) => {
+        console.warn("⚠️ External request ignored:", payload.message);
+      },
+      "deeplink:error": async (payload) => {
+        console.warn(`⚠️ Link ${payload.url} ignored:`, payload.message);
+      },
+    };
+
+    const unlisteners = Promise.all(
+      Object.entries(handlers).map(([event, handler]) =>
+        listen(event, (e) => {
+          handler(e.payload).catch(console.error);
+        })
+      )
+    );
+
+    unlisteners
+      .then(() => invoke<PendingEvent[]>("take_pending_external_events"))
+      .then(async (pending) => {
+        for (const { event, payload } of pending) {
+          await handlers[event]?.(payload);
+        }
+      })
+      .catch(console.error);
+
+    return () => {
+      unlisteners.then((fns) => fns.forEach((unlisten) => unlisten()));
+    };
+  }, []);
+
   /**
    * Render the application UI based on current state.
    *
@@ -155,10 +698,85 @@ function App() {
    * and only shows content when recording or processing.
    */
   return (
-    <div className="w-screen bg-transparent flex h-screen text-white items-center justify-center">
+    <div
+      ref={rootRef}
+      className={`w-screen bg-transparent flex h-screen text-white items-center justify-center ${
+        flash ? "animate-pulse" : ""
+      }`}
+      // The pill is frameless: the backend moves it, and remembers where
+      onMouseDown={(e) => {
+        invoke("pill_interaction", { interaction: "click" }).catch(console.warn);
+        if (e.button === 0) {
+          invoke("start_pill_drag").catch(console.warn);
+        }
+      }}
+      // Holds the backend's auto-hide timer while the mouse is over the pill
+      onMouseEnter={() =>
+        invoke("pill_interaction", { interaction: "enter" }).catch(console.warn)
+      }
+      onMouseLeave={() =>
+        invoke("pill_interaction", { interaction: "leave" }).catch(console.warn)
+      }
+    >
+      {/* Quit prompt: the app was told to quit while recording */}
+      {isRecording && quitPrompt && (
+        <div
+          className="flex flex-col gap-3 w-full bg-neutral-900 shadow-2xl rounded-2xl px-8 py-5"
+          onMouseDown={(e) => e.stopPropagation()}
+        >
+          <span className="text-sm font-semibold">Quit while recording?</span>
+          <div className="flex gap-3 justify-end text-xs">
+            <button
+              className="text-neutral-400 underline"
+              onClick={() => setQuitPrompt(false)}
+            >
+              Keep recording
+            </button>
+            <button
+              className="text-neutral-400 underline"
+              onClick={() =>
+                invoke("shutdown_now", { force: true }).catch(console.warn)
+              }
+            >
+              Discard and quit
+            </button>
+            <button
+              className="underline"
+              onClick={async () => {
+                setQuitPrompt(false);
+                await latest.current.finishRecording();
+                invoke("shutdown_now", { force: false }).catch(console.warn);
+              }}
+            >
+              Stop, paste and quit
+            </button>
+          </div>
+        </div>
+      )}
+
       {/* Recording State: Shows animation or live transcript */}
-      {isRecording && (
-        <div className="flex flex-col items-center gap-3 w-full bg-neutral-900 px-8 py-5 shadow-2xl">
+      {isRecording && !quitPrompt && (
+        <div
+          ref={pillRef}
+          className={`flex flex-col items-center gap-3 w-full max-h-screen overflow-y-auto px-8 py-5 ${
+            // Rounded like the blurred background behind it
+            vibrant ? "bg-neutral-900/40 rounded-2xl" : "bg-neutral-900 shadow-2xl"
+          }`}
+        >
+          {/* Recording timer, from the audio the backend has counted */}
+          <span className="text-xs tabular-nums text-neutral-400">
+            {formatDuration(recordedMs)}
+          </span>
+          {inputSilent && (
+            <span className="text-xs text-amber-400">
+              We're not hearing anything — check your mic
+            </span>
+          )}
+          {inputNotice && (
+            <span className="text-xs text-neutral-400">
+              Now recording from {inputNotice}
+            </span>
+          )}
           {/* Show animation only when no transcript is available yet */}
           {!transcript && (
             <div className="flex items-center gap-3">
@@ -179,7 +797,7 @@ function App() {
       )}
 
       {/* Processing State: Shown after speech ends, before paste */}
-      {isProcessing && (
+      {isProcessing && !confirming && (
         <div className="flex items-center gap-3 bg-blue-500 rounded-full px-8 py-5 shadow-2xl">
           <div className="animate-spin">
             <LoaderCircle className="size-6" />
@@ -188,6 +806,38 @@ function App() {
         </div>
       )}
 
+      {/* Confirming State: the transcript, pasted once confirmed */}
+      {confirming && (
+        <div
+          className="flex flex-col gap-2 w-full bg-neutral-900 shadow-2xl rounded-2xl px-8 py-5"
+          onMouseDown={(e) => e.stopPropagation()}
+        >
+          <p className="text-sm select-text">{confirming.text}</p>
+          <div className="flex gap-3 justify-end text-xs">
+            <button
+              className="text-neutral-400 underline"
+              onClick={() =>
+                invoke("cancel_paste", {
+                  sessionId: confirming.session_id,
+                }).catch(console.warn)
+              }
+            >
+              Cancel (Esc)
+            </button>
+            <button
+              className="underline"
+              onClick={() =>
+                invoke("confirm_paste", {
+                  sessionId: confirming.session_id,
+                }).catch(console.warn)
+              }
+            >
+              {confirming.kind === "commands" ? "Run" : "Paste"} (Enter)
+            </button>
+          </div>
+        </div>
+      )}
+
       {/* Error State: Shows error message */}
       {error && (
         <div className="bg-red-500 text-white rounded-full px-8 py-5 shadow-2xl">
@@ -195,12 +845,107 @@ function App() {
         </div>
       )}
 
-      {/* Idle State: Instruction text (typically hidden when window is hidden) */}
-      {!isRecording && !isProcessing && !error && (
-        <div className="bg-white text-gray-800 rounded-full px-8 py-5 shadow-2xl">
-          <span className="font-semibold">Press Option+Space to speak</span>
+      {/* Interrupted State: the system slept mid-recording */}
+      {interrupted && !isRecording && !isProcessing && (
+        <div
+          className="flex flex-col gap-3 w-full bg-neutral-900 shadow-2xl rounded-2xl px-8 py-5"
+          onMouseDown={(e) => e.stopPropagation()}
+        >
+          <span className="text-sm font-semibold">
+            The computer went to sleep after{" "}
+            {formatDuration(interrupted.audio_ms)} of recording
+          </span>
+          <div className="flex gap-3 justify-end text-xs">
+            <button
+              className="text-neutral-400 underline"
+              onClick={() => discardInterrupted().catch(console.warn)}
+            >
+              Discard
+            </button>
+            <button
+              className="underline"
+              onClick={() => transcribeInterrupted()}
+            >
+              Transcribe and paste
+            </button>
+          </div>
         </div>
       )}
+
+      {/* Pending Recovery State: left behind by a crash or a quit */}
+      {pendingRecovery && !isRecording && !isProcessing && (
+        <div
+          className="flex flex-col gap-3 w-full bg-neutral-900 shadow-2xl rounded-2xl px-8 py-5"
+          onMouseDown={(e) => e.stopPropagation()}
+        >
+          <span className="text-sm font-semibold">
+            {pendingRecovery.transcript
+              ? "A dictation was never pasted"
+              : "A recording was never transcribed"}
+          </span>
+          <div className="flex gap-3 justify-end text-xs">
+            <button
+              className="text-neutral-400 underline"
+              onClick={() => discardPending().catch(console.warn)}
+            >
+              Discard
+            </button>
+            <button className="underline" onClick={() => recoverPending()}>
+              {pendingRecovery.transcript ? "Paste it" : "Transcribe and paste"}
+            </button>
+          </div>
+        </div>
+      )}
+
+      {/* Recovered State: a failed paste's text, to copy by hand */}
+      {recovered && !isRecording && !isProcessing && (
+        <div
+          className="flex flex-col gap-2 w-full bg-neutral-900 shadow-2xl rounded-2xl px-8 py-5"
+          onMouseDown={(e) => e.stopPropagation()}
+        >
+          <span className="text-xs text-neutral-400">
+            Couldn't paste this; it is on the clipboard
+          </span>
+          <p className="text-sm select-text">{recovered}</p>
+          <button
+            className="self-end text-xs text-neutral-400 underline"
+            onClick={() => {
+              setRecovered(null);
+              invoke("hide_recording_pill").catch(console.warn);
+            }}
+          >
+            Dismiss
+          </button>
+        </div>
+      )}
+
+      {/* Idle State: Instruction text (typically hidden when window is hidden) */}
+      {!isRecording &&
+        !isProcessing &&
+        !error &&
+        !recovered &&
+        !pendingRecovery &&
+        !interrupted && (
+          <div className="bg-white text-gray-800 rounded-full px-8 py-5 shadow-2xl">
+            <span className="font-semibold">Press Option+Space to speak</span>
+          </div>
+        )}
+
+      {/* Debug outline of the click-through hit region */}
+      {hitRegionDebug?.map((rect, i) => (
+        <div
+          key={i}
+          data-hit-debug
+          className="fixed pointer-events-none border-2 border-dashed border-fuchsia-500"
+          style={{
+            left: rect.x,
+            top: rect.y,
+            width: rect.width,
+            height: rect.height,
+            borderRadius: rect.radius,
+          }}
+        />
+      ))}
     </div>
   );
 }
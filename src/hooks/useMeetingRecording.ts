@@ -0,0 +1,425 @@
+import { useState, useRef, useEffect } from "react";
+import { Channel, invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import {
+  transcribeFile,
+  transcribeFileWithSpeakers,
+  Utterance,
+} from "../services/deepgram";
+import {
+  AudioService,
+  CaptureQuality,
+  SPEECH_LEVEL,
+} from "../services/audio";
+import { TranscriptionConfig } from "../services/transcription";
+import { log } from "../services/log";
+
+/** Payload of `start_meeting`. */
+interface MeetingStarted {
+  meeting_id: string;
+  min_segment_ms: number;
+  max_segment_ms: number;
+  max_duration_ms: number;
+  /** Whether to record the mic... */
+  microphone: boolean;
+  /** ...and system audio (`start_system_audio`). */
+  system_audio: boolean;
+  /** Linear gains of the two in the mix. */
+  microphone_gain: number;
+  system_audio_gain: number;
+  /** Whether to label speakers (`meeting.diarize`). */
+  diarize: boolean;
+  /** What to record in (`capture.meeting`). */
+  capture: CaptureQuality;
+}
+
+/** Payload of the backend's `audio:system-audio-failed`. */
+interface SystemAudioFailed {
+  meeting_id: string;
+  message: string;
+}
+
+/** What `next_meeting_segment` hands out. */
+type NextSegment =
+  | { kind: "segment"; index: number }
+  | { kind: "wait"; retry_in_ms: number }
+  | { kind: "idle" };
+
+/** Payload of the backend's `meeting:segment`. */
+export interface SegmentTranscribed {
+  meeting_id: string;
+  index: number;
+  start_ms: number;
+  end_ms: number;
+  text: string;
+}
+
+/** Payload of the backend's `meeting:finished`. */
+interface MeetingFinished {
+  meeting_id: string;
+  history_id: number | null;
+  duration_ms: number;
+  failed_segments: number;
+}
+
+/** How long a pause has to be for a segment to be cut at it. */
+const PAUSE_MS = 500;
+
+/**
+ * Records a meeting: the mic, the system audio the backend captures and
+ * streams, or both mixed (`meeting.input`), in segments cut at pauses, each
+ * handed to the
+ * backend (`add_meeting_segment`), which keeps it on disk and queues it.
+ * Segments are transcribed one at a time, oldest first, as the queue hands
+ * them out; a failure is reported back, and the backend says when to try
+ * again. Quick dictations are recorded and transcribed separately, so they
+ * work as usual while a meeting runs. If system audio can't be captured,
+ * or stops, the meeting carries on from the mic; if the mic is lost while
+ * system audio is recorded, from system audio.
+ *
+ * @param apiKey - Deepgram key the segments are transcribed with
+ */
+export function useMeetingRecording(apiKey: string) {
+  const [meetingId, setMeetingId] = useState<string | null>(null);
+  // Stopped, while the last segments are transcribed
+  const [isFinishing, setIsFinishing] = useState(false);
+  // The transcript so far, a segment at a time
+  const [segments, setSegments] = useState<SegmentTranscribed[]>([]);
+  const [error, setError] = useState<string | null>(null);
+
+  const audioServiceRef = useRef<AudioService>(new AudioService());
+  const meetingIdRef = useRef<string | null>(null);
+  // Whether the mic is being recorded, as opposed to stopped or finishing
+  const recordingRef = useRef(false);
+  // Which sources are being recorded
+  const microphoneRef = useRef(false);
+  const systemAudioRef = useRef(false);
+  const pumpingRef = useRef(false);
+  const retryTimerRef = useRef<number | null>(null);
+  const diarizeRef = useRef(false);
+  // When the segment being recorded started, and since when it is quiet
+  const segmentStartRef = useRef(0);
+  const quietSinceRef = useRef<number | null>(null);
+  const cuttingRef = useRef(false);
+
+  // Transcribes a segment in the configured language, labeling speakers if
+  // asked to. Labeling is best-effort: if it fails, the segment is
+  // transcribed without it
+  const transcribe = async (
+    audio: ArrayBuffer
+  ): Promise<{ text: string; utterances: Utterance[] | null }> => {
+    const { language } = await invoke<TranscriptionConfig>(
+      "get_transcription_config"
+    );
+    if (diarizeRef.current) {
+      try {
+        return await transcribeFileWithSpeakers(apiKey, audio, language);
+      } catch (err) {
+        console.warn("Failed to label speakers:", err);
+      }
+    }
+    return {
+      text: await transcribeFile(apiKey, audio, language),
+      utterances: null,
+    };
+  };
+
+  // Transcribes queued segments one at a time until the queue has nothing
+  // to hand out, or asks to wait for a retry
+  const pump = async () => {
+    const id = meetingIdRef.current;
+    if (!id || pumpingRef.current) return;
+    pumpingRef.current = true;
+    try {
+      while (meetingIdRef.current === id) {
+        const next = await invoke<NextSegment>("next_meeting_segment", {
+          meetingId: id,
+        });
+        if (next.kind === "idle") break;
+        if (next.kind === "wait") {
+          if (retryTimerRef.current !== null) {
+            window.clearTimeout(retryTimerRef.current);
+          }
+          retryTimerRef.current = window.setTimeout(() => {
+            retryTimerRef.current = null;
+            pump();
+          }, next.retry_in_ms);
+          break;
+        }
+
+        const { index } = next;
+        try {
+          const audio = await invoke<ArrayBuffer>("read_meeting_segment", {
+            meetingId: id,
+            index,
+          });
+          const { text, utterances } = await transcribe(audio);
+          await invoke("complete_meeting_segment", {
+            meetingId: id,
+            index,
+            text,
+            utterances,
+          });
+        } catch (err) {
+          console.warn(`Meeting segment ${index} failed:`, err);
+          await invoke("fail_meeting_segment", {
+            meetingId: id,
+            index,
+            message: err instanceof Error ? err.message : String(err),
+          }).catch(console.warn);
+        }
+      }
+    } catch (err) {
+      // The meeting is over
+      log.debug("Meeting queue closed", err);
+    } finally {
+      pumpingRef.current = false;
+    }
+  };
+
+  // Cuts a segment at the first pause once it is long enough, and at the
+  // longest length in any case
+  const watchLevel =
+    (started: MeetingStarted) =>
+    (level: number) => {
+      if (cuttingRef.current || !recordingRef.current) return;
+      const now = performance.now();
+      if (level >= SPEECH_LEVEL) {
+        quietSinceRef.current = null;
+      } else if (quietSinceRef.current === null) {
+        quietSinceRef.current = now;
+      }
+      const length = now - segmentStartRef.current;
+      const paused =
+        quietSinceRef.current !== null &&
+        now - quietSinceRef.current >= PAUSE_MS;
+      if (
+        length >= started.max_segment_ms ||
+        (length >= started.min_segment_ms && paused)
+      ) {
+        cuttingRef.current = true;
+        segmentStartRef.current = now;
+        quietSinceRef.current = null;
+        audioServiceRef.current
+          .nextSegment()
+          .catch(console.warn)
+          .finally(() => {
+            cuttingRef.current = false;
+          });
+      }
+    };
+
+  // Streams the system audio the backend captures into the recording. If
+  // it can't start, the backend reports it (`audio:system-audio-failed`)
+  const startSystemAudio = async (id: string) => {
+    // Packets arriving before the rate is known are dropped; there is at
+    // most one
+    let sampleRate: number | null = null;
+    const channel = new Channel<ArrayBuffer>();
+    channel.onmessage = (packet) => {
+      if (sampleRate === null || meetingIdRef.current !== id) return;
+      const samples = new Float32Array(packet);
+      audioServiceRef.current.pushSamples(samples, sampleRate);
+    };
+    try {
+      const started = await invoke<{ sample_rate: number }>(
+        "start_system_audio",
+        { meetingId: id, channel }
+      );
+      sampleRate = started.sample_rate;
+      systemAudioRef.current = true;
+      log.info("Recording system audio");
+    } catch (err) {
+      console.warn("Failed to record system audio:", err);
+    }
+  };
+
+  const startMeeting = async () => {
+    if (meetingIdRef.current) {
+      log.info("A meeting is already in progress");
+      return;
+    }
+    setError(null);
+    setSegments([]);
+    setIsFinishing(false);
+
+    let started: MeetingStarted;
+    try {
+      started = await invoke<MeetingStarted>("start_meeting");
+    } catch (err) {
+      console.error("Failed to start the meeting:", err);
+      setError(String(err));
+      return;
+    }
+    const id = started.meeting_id;
+    diarizeRef.current = started.diarize;
+    meetingIdRef.current = id;
+    setMeetingId(id);
+
+    try {
+      segmentStartRef.current = performance.now();
+      quietSinceRef.current = null;
+      recordingRef.current = true;
+      microphoneRef.current = started.microphone;
+      systemAudioRef.current = false;
+      await audioServiceRef.current.startRecording(
+        {
+          onSegment: async (audio, startMs, endMs) => {
+            await invoke("add_meeting_segment", {
+              meetingId: id,
+              startMs,
+              endMs,
+              audio: Array.from(new Uint8Array(audio)),
+            });
+            pump();
+          },
+          onLevel: watchLevel(started),
+          onInputLost: (err) => {
+            microphoneRef.current = false;
+            if (systemAudioRef.current) {
+              const message = err instanceof Error ? err.message : String(err);
+              setError("Lost the microphone; recording system audio only");
+              invoke("report_meeting_source_lost", {
+                meetingId: id,
+                source: "microphone",
+                message,
+              }).catch(console.warn);
+              return;
+            }
+            // Keep what was recorded up to then
+            stopMeeting().catch(console.warn);
+          },
+        },
+        {
+          segmented: true,
+          microphone: started.microphone,
+          microphoneGain: started.microphone_gain,
+          pushedGain: started.system_audio_gain,
+          quality: started.capture,
+        }
+      );
+      const format = audioServiceRef.current.captureFormat();
+      if (format) {
+        invoke("report_capture_format", { id, format }).catch(console.warn);
+      }
+      if (started.system_audio) await startSystemAudio(id);
+      log.info("Meeting recording started");
+    } catch (err) {
+      console.error("Failed to record the meeting:", err);
+      setError("Failed to start recording the meeting");
+      await stopMeeting();
+    }
+  };
+
+  // Stops recording; the meeting is finalized by the backend once the
+  // last segments are transcribed (`meeting:finished`)
+  const stopMeeting = async () => {
+    const id = meetingIdRef.current;
+    if (!id || !recordingRef.current) return;
+    recordingRef.current = false;
+    setIsFinishing(true);
+    await audioServiceRef.current.endSegments();
+    try {
+      await invoke("stop_meeting", { meetingId: id });
+    } catch (err) {
+      console.warn("Failed to stop the meeting:", err);
+    }
+    log.info("Meeting recording stopped");
+  };
+
+  const toggleMeeting = () =>
+    recordingRef.current ? stopMeeting() : startMeeting();
+
+  useEffect(() => {
+    const unlisten = listen<SegmentTranscribed>("meeting:segment", (e) => {
+      if (e.payload.meeting_id !== meetingIdRef.current) return;
+      setSegments((segments) =>
+        [...segments, e.payload].sort((a, b) => a.start_ms - b.start_ms)
+      );
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // System audio couldn't be recorded, or stopped: carry on from the mic,
+  // opening it if it isn't recorded already
+  useEffect(() => {
+    const unlisten = listen<SystemAudioFailed>(
+      "audio:system-audio-failed",
+      (e) => {
+        const { meeting_id, message } = e.payload;
+        if (meeting_id !== meetingIdRef.current || !recordingRef.current) {
+          return;
+        }
+        systemAudioRef.current = false;
+        if (microphoneRef.current) {
+          setError("Lost system audio; recording the microphone only");
+          return;
+        }
+        setError(
+          `Recording the microphone instead of system audio: ${message}`
+        );
+        audioServiceRef.current
+          .addMicrophone()
+          .then(() => {
+            microphoneRef.current = true;
+          })
+          .catch((err) => {
+            console.error("Failed to record the microphone:", err);
+            stopMeeting().catch(console.warn);
+          });
+      }
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // The meeting ran for `meeting.max_duration_minutes`
+  useEffect(() => {
+    const unlisten = listen<{ meeting_id: string }>(
+      "meeting:limit-reached",
+      (e) => {
+        if (e.payload.meeting_id !== meetingIdRef.current) return;
+        log.info("Meeting reached its maximum length");
+        stopMeeting().catch(console.warn);
+      }
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  useEffect(() => {
+    const unlisten = listen<MeetingFinished>("meeting:finished", (e) => {
+      if (e.payload.meeting_id !== meetingIdRef.current) return;
+      log.info(`Meeting ${e.payload.meeting_id} transcribed`);
+      if (retryTimerRef.current !== null) {
+        window.clearTimeout(retryTimerRef.current);
+        retryTimerRef.current = null;
+      }
+      meetingIdRef.current = null;
+      setMeetingId(null);
+      setIsFinishing(false);
+      if (e.payload.failed_segments > 0) {
+        setError(
+          `${e.payload.failed_segments} part(s) of the meeting couldn't be transcribed`
+        );
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  return {
+    meetingId,
+    isFinishing,
+    segments,
+    error,
+    startMeeting,
+    stopMeeting,
+    toggleMeeting,
+  };
+}
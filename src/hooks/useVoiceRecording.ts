@@ -1,79 +1,323 @@
-import { useState, useRef } from "react";
+import { useState, useRef, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import { DeepgramService, TranscriptionCallbacks } from "../services/deepgram";
-import { AudioService } from "../services/audio";
+import { listen } from "@tauri-apps/api/event";
+import {
+  DeepgramService,
+  transcribeFile as transcribeAudioFile,
+} from "../services/deepgram";
+import { describeError, errorCode } from "../services/errors";
+import { MockTranscriptionService } from "../services/mock";
+import {
+  TranscriptionCallbacks,
+  TranscriptionConfig,
+  TranscriptionProvider,
+} from "../services/transcription";
+import { AudioService, CHUNK_MS } from "../services/audio";
+import { readAudioFile } from "../services/audioFile";
+import { log } from "../services/log";
+
+/** Result of the backend `rewrite_text` command. */
+interface RewriteResult {
+  text: string;
+  rewritten: boolean;
+  error: string | null;
+}
+
+/** Options for a single dictation. */
+export interface RecordingOptions {
+  /** Session id returned by `show_recording_pill`. */
+  sessionId?: string;
+  /** Rewrite preset to apply before pasting (e.g. "concise"). */
+  rewritePreset?: string;
+  /**
+   * The backend cancelled the dictation being processed to start this one
+   * (`hotkeys.restart_while_transcribing`), so it starts even though that
+   * one hasn't finished.
+   */
+  replacing?: boolean;
+}
+
+/** Payload of the backend's once-a-second `recording:tick`. */
+interface RecordingTick {
+  session_id: string;
+  elapsed_ms: number;
+  audio_ms: number;
+}
+
+/** Payload of the backend's `recording:cancelled`. */
+interface RecordingCancelled {
+  session_id: string;
+  reason: string;
+}
+
+/** Payload of the backend's `recording:interrupted`. */
+export interface RecordingInterrupted {
+  session_id: string;
+  audio_ms: number;
+}
+
+/** Code of the error of a recording that stayed silent throughout. */
+const EMPTY_RECORDING = "E_EMPTY_RECORDING";
+
+/** Whether a command failed because its dictation is no longer current. */
+const isStaleSession = (err: unknown) =>
+  (err as { kind?: string } | null)?.kind === "stale_session";
+
+/** Whether a paste waiting for confirmation was cancelled or timed out. */
+const isNotConfirmed = (err: unknown) =>
+  (err as { kind?: string } | null)?.kind === "not_confirmed";
+
+/** How often recorded audio is checkpointed for crash recovery. */
+const CHECKPOINT_INTERVAL_MS = 3000;
+
+/** Result of the backend post-processing pipeline. */
+interface ProcessedTranscript {
+  text: string;
+  profanity_filtered: boolean;
+  /** Voice commands, which the backend runs instead of pasting */
+  command: boolean;
+}
 
 export function useVoiceRecording(apiKey: string) {
   const [isRecording, setIsRecording] = useState(false);
   const [transcript, setTranscript] = useState("");
   const [isProcessing, setIsProcessing] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  const [profanityFiltered, setProfanityFiltered] = useState(false);
+  // Audio captured so far, from the backend's ticks
+  const [recordedMs, setRecordedMs] = useState(0);
+  // Set while the microphone delivers nothing (`audio:input-silent`)
+  const [inputSilent, setInputSilent] = useState(false);
+  // A recording the system's sleep ended, until transcribed or discarded
+  const [interrupted, setInterrupted] = useState<RecordingInterrupted | null>(
+    null
+  );
 
-  const deepgramRef = useRef<DeepgramService | null>(null);
+  const providerRef = useRef<TranscriptionProvider | null>(null);
   const audioServiceRef = useRef<AudioService>(new AudioService());
   const hasEndedRef = useRef(false);
   const isStartingRef = useRef(false); // Prevent double-start
+  const rewritePresetRef = useRef<string | undefined>(undefined);
+  // Backend session id of the dictation in progress
+  const sessionIdRef = useRef<string | undefined>(undefined);
+  const audioChunksRef = useRef<ArrayBuffer[]>([]);
+  const uncheckpointedRef = useRef<ArrayBuffer[]>([]);
+  const checkpointTimerRef = useRef<number | null>(null);
+  // Whether the backend was told this dictation has heard speech
+  const speechReportedRef = useRef(false);
+  // Highest level of the recording so far, and the level below which it
+  // counts as silent
+  const peakRef = useRef(0);
+  const silentLevelRef = useRef(0);
+  // Audio held back from the provider while the recording is silent
+  const heldRef = useRef<ArrayBuffer[]>([]);
+
+  useEffect(() => {
+    const unlisten = listen<RecordingTick>("recording:tick", (e) => {
+      if (e.payload.session_id === sessionIdRef.current) {
+        setRecordedMs(e.payload.audio_ms);
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
 
-  const startRecording = async () => {
+  // Tells the backend the dictation heard speech, once, so it isn't
+  // cancelled for silence (`recording.no_speech_timeout_ms`)
+  const reportSpeech = (sessionId: string | undefined) => {
+    if (!sessionId || speechReportedRef.current) return;
+    speechReportedRef.current = true;
+    invoke("report_speech", { sessionId }).catch(console.warn);
+  };
+
+  // Appends audio recorded since the last checkpoint to the backend's
+  // crash-recovery file
+  const flushCheckpoint = async () => {
+    const chunks = uncheckpointedRef.current;
+    if (chunks.length === 0) return;
+    uncheckpointedRef.current = [];
+    try {
+      const audio = await new Blob(chunks).arrayBuffer();
+      await invoke("checkpoint_recording", audio, {
+        headers: { "x-session-id": sessionIdRef.current ?? "" },
+      });
+    } catch (err) {
+      console.warn("Failed to checkpoint recording:", err);
+    }
+  };
+
+  const stopCheckpoints = () => {
+    if (checkpointTimerRef.current !== null) {
+      window.clearInterval(checkpointTimerRef.current);
+      checkpointTimerRef.current = null;
+    }
+    uncheckpointedRef.current = [];
+  };
+
+  const startRecording = async (options: RecordingOptions = {}) => {
     // Prevent starting if already recording or starting
-    if (isStartingRef.current || isRecording) {
-      console.log("⚠️ Already recording or starting, ignoring");
+    if (!options.replacing && (isStartingRef.current || isRecording)) {
+      log.debug("Already recording or starting, ignoring");
       return;
     }
+    if (options.replacing) {
+      setIsProcessing(false);
+    }
 
     isStartingRef.current = true;
 
     try {
       // Clean up any existing connections first
-      if (deepgramRef.current) {
-        console.log("🧹 Cleaning up old connection");
-        await deepgramRef.current.stop();
-        deepgramRef.current = null;
+      if (providerRef.current) {
+        log.debug("Cleaning up the previous connection");
+        await providerRef.current.stop();
+        providerRef.current = null;
       }
 
       setError(null);
       setTranscript("");
+      setProfanityFiltered(false);
+      setRecordedMs(0);
+      setInterrupted(null);
+      setInputSilent(false);
+      peakRef.current = 0;
+      heldRef.current = [];
+      rewritePresetRef.current = options.rewritePreset;
+      sessionIdRef.current = options.sessionId;
+      const sessionId = options.sessionId;
+      speechReportedRef.current = false;
+      audioChunksRef.current = [];
+      stopCheckpoints();
       setIsRecording(true);
       hasEndedRef.current = false;
 
+      // The provider can change between dictations (settings, or the
+      // backend's `--mock-transcription`)
+      const config = await invoke<TranscriptionConfig>(
+        "get_transcription_config"
+      );
+      silentLevelRef.current = config.silent_input_level;
+
       const callbacks: TranscriptionCallbacks = {
         onTranscript: (text, isFinal) => {
+          if (text.trim()) reportSpeech(sessionId);
           setTranscript(text);
-          console.log("Transcript:", text, "Final:", isFinal);
+          // Mirror to live caption clients of the local API
+          invoke("publish_live_transcript", {
+            sessionId,
+            text,
+            stable: isFinal,
+          }).catch(console.warn);
         },
 
         onSpeechEnd: async (finalText) => {
           if (hasEndedRef.current) {
-            console.log("⚠️ Speech end already handled, ignoring");
+            log.debug("Speech end already handled, ignoring");
             return;
           }
 
           hasEndedRef.current = true;
-          console.log("🎯 Speech ended, final text:", finalText);
+          log.info(`Speech ended (${finalText.length} chars)`);
 
           // Stop recording
           audioServiceRef.current.stopRecording();
-          await deepgramRef.current?.stop();
-          deepgramRef.current = null; // Clear reference
+          stopCheckpoints();
+          await providerRef.current?.stop();
+          providerRef.current = null; // Clear reference
 
           setIsRecording(false);
+          try {
+            await invoke("stop_recording", { sessionId });
+          } catch (err) {
+            isStartingRef.current = false;
+            if (errorCode(err) === EMPTY_RECORDING) {
+              // Nothing but silence; the backend dropped it
+              log.info("Silent recording, not transcribing");
+              setError(await describeError(err, "The microphone is silent"));
+              return;
+            }
+            // Cancelled (e.g. a click outside the pill) as speech ended
+            log.info("Dictation is no longer current", err);
+            return;
+          }
           setIsProcessing(true);
 
-          // Copy to clipboard and paste
+          // Hand the recording to the backend when the audio retention
+          // policy keeps it, as raw bytes. Not awaited: the paste never
+          // waits for it, and the backend keeps it for the dictation's
+          // history entry whichever comes first
+          const chunks = audioChunksRef.current;
+          audioChunksRef.current = [];
+          if (config.keep_audio) {
+            new Blob(chunks)
+              .arrayBuffer()
+              .then((audio) =>
+                invoke("stage_dictation_audio", audio, {
+                  headers: {
+                    "x-session-id": sessionId,
+                    "x-audio-extension": "webm",
+                  },
+                })
+              )
+              .catch((err) => console.warn("Failed to stage recording:", err));
+          }
+
+          // Post-process, then copy to clipboard and paste
           try {
-            await invoke("copy_and_paste_text", { text: finalText });
-            console.log("✅ Text copied and pasted!");
+            const processed = await invoke<ProcessedTranscript>(
+              "process_transcript",
+              {
+                text: finalText,
+                provider: config.provider,
+                language: config.language,
+                sessionId,
+              }
+            );
+            setProfanityFiltered(processed.profanity_filtered);
+
+            // Optional LLM rewrite; the backend falls back to the original
+            // text on any failure, so this never blocks the paste
+            let textToPaste = processed.text;
+            if (rewritePresetRef.current && !processed.command) {
+              const rewrite = await invoke<RewriteResult>("rewrite_text", {
+                text: processed.text,
+                preset: rewritePresetRef.current,
+                sessionId,
+              });
+              textToPaste = rewrite.text;
+            }
+
+            await invoke("copy_and_paste_text", {
+              text: textToPaste,
+              sessionId,
+            });
+            log.info("Text copied and pasted");
 
-            // Wait a bit then hide window and reset
-            setTimeout(async () => {
-              await invoke("hide_recording_pill");
+            // The paste put the pill away; the backend hides whatever it
+            // shows next on its own
+            setIsProcessing(false);
+            setTranscript("");
+            isStartingRef.current = false; // Allow next recording
+          } catch (err) {
+            if (isStaleSession(err) || isNotConfirmed(err)) {
+              // Cancelled while processing or not confirmed; nothing was
+              // pasted, and the backend already hid the pill
+              log.info("Dictation cancelled before the paste");
+              if (sessionIdRef.current !== sessionId) {
+                // Replaced by a new dictation, which owns the state now
+                return;
+              }
               setIsProcessing(false);
               setTranscript("");
-              isStartingRef.current = false; // Allow next recording
-            }, 1000);
-          } catch (err) {
+              isStartingRef.current = false;
+              return;
+            }
             console.error("Failed to copy/paste:", err);
-            setError("Failed to paste text");
+            invoke("set_pill_state", { state: { kind: "error" } }).catch(
+              console.warn
+            );
+            setError(await describeError(err, "Failed to paste text"));
             setIsProcessing(false);
             isStartingRef.current = false;
           }
@@ -81,22 +325,87 @@ export function useVoiceRecording(apiKey: string) {
 
         onError: (err) => {
           console.error("Recording error:", err);
+          invoke("report_recording_error", {
+            message: err.message,
+            sessionId,
+          }).catch(console.warn);
+          stopCheckpoints();
           setError(err.message);
           setIsRecording(false);
           isStartingRef.current = false;
         },
       };
 
-      deepgramRef.current = new DeepgramService(apiKey, callbacks);
-      await deepgramRef.current.start();
+      const provider =
+        config.provider === "mock"
+          ? new MockTranscriptionService(config.mock, callbacks)
+          : new DeepgramService(apiKey, callbacks, config.language);
+      providerRef.current = provider;
+      await provider.start();
 
-      await audioServiceRef.current.startRecording((audioData) => {
-        deepgramRef.current?.sendAudio(audioData);
-      });
+      // The mock provider makes do without a microphone
+      if (provider.needsAudio) {
+        await audioServiceRef.current.startRecording(
+          {
+            onAudioData: (audioData, peak) => {
+              audioChunksRef.current.push(audioData);
+              uncheckpointedRef.current.push(audioData);
+              peakRef.current = Math.max(peakRef.current, peak);
+              // Nothing but silence isn't worth a provider round trip; once
+              // the mic picks something up, what was held back goes first
+              if (peakRef.current < silentLevelRef.current) {
+                heldRef.current.push(audioData);
+              } else {
+                for (const held of heldRef.current) {
+                  providerRef.current?.sendAudio(held);
+                }
+                heldRef.current = [];
+                providerRef.current?.sendAudio(audioData);
+                setInputSilent(false);
+              }
+              // Counts towards the backend's `recording:tick` audio time and
+              // its silent input check
+              invoke("report_captured_audio", {
+                sessionId,
+                ms: CHUNK_MS,
+                peak,
+              }).catch(console.warn);
+            },
+            onSpeech: () => reportSpeech(sessionId),
+            // The backend announces it as `audio:device-changed`
+            onDeviceChange: (from, to) => {
+              invoke("report_input_device_change", {
+                sessionId,
+                from,
+                to,
+              }).catch(console.warn);
+            },
+            // Paste what was said before the mic went away
+            onInputLost: () => {
+              finishRecording().catch(console.warn);
+            },
+          },
+          { quality: config.capture }
+        );
+        const format = audioServiceRef.current.captureFormat();
+        if (format) {
+          invoke("report_capture_format", { id: sessionId, format }).catch(
+            console.warn
+          );
+        }
+        checkpointTimerRef.current = window.setInterval(
+          flushCheckpoint,
+          CHECKPOINT_INTERVAL_MS
+        );
+      }
 
       isStartingRef.current = false;
     } catch (err) {
       console.error("Failed to start recording:", err);
+      invoke("report_recording_error", {
+        message: String(err),
+        sessionId: options.sessionId,
+      }).catch(console.warn);
       setError("Failed to start recording");
       setIsRecording(false);
       isStartingRef.current = false;
@@ -104,21 +413,244 @@ export function useVoiceRecording(apiKey: string) {
   };
 
   const stopRecording = async () => {
-    console.log("🛑 Manually stopping recording");
+    log.info("Stopping the recording by hand");
     audioServiceRef.current.stopRecording();
-    await deepgramRef.current?.stop();
-    deepgramRef.current = null;
+    stopCheckpoints();
+    await providerRef.current?.stop();
+    providerRef.current = null;
     setIsRecording(false);
     hasEndedRef.current = false;
     isStartingRef.current = false;
   };
 
+  // The backend cancelled a recording that heard no speech; close the mic
+  // and say why in the pill for the moment it stays up
+  useEffect(() => {
+    const unlisten = listen<RecordingCancelled>("recording:cancelled", (e) => {
+      if (e.payload.session_id !== sessionIdRef.current) return;
+      log.info(`Recording cancelled: ${e.payload.reason}`);
+      stopRecording();
+      if (e.payload.reason === "no_speech") {
+        setError("No speech heard, recording cancelled");
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // The dictation was cancelled after its recording stopped; the backend
+  // pastes nothing, so stop the provider and drop whatever it still returns
+  useEffect(() => {
+    const unlisten = listen<{ session_id: string | null }>(
+      "transcription:cancelled",
+      (e) => {
+        if (e.payload.session_id !== sessionIdRef.current) return;
+        log.info("Transcription cancelled");
+        providerRef.current?.stop();
+        providerRef.current = null;
+        setIsProcessing(false);
+        setTranscript("");
+        isStartingRef.current = false;
+      }
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // The backend heard nothing from the mic for a few seconds; the pill
+  // asks the user to check it until it picks something up
+  useEffect(() => {
+    const unlisten = listen<{ session_id: string }>(
+      "audio:input-silent",
+      (e) => {
+        if (e.payload.session_id !== sessionIdRef.current) return;
+        log.info("The microphone is silent");
+        setInputSilent(true);
+      }
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // The system slept mid-recording and the backend ended it. The stream is
+  // dead; close it, but keep what was recorded for the user to transcribe
+  useEffect(() => {
+    const unlisten = listen<RecordingInterrupted>(
+      "recording:interrupted",
+      (e) => {
+        if (e.payload.session_id !== sessionIdRef.current) return;
+        log.info("Recording interrupted by sleep");
+        stopRecording();
+        setInterrupted(e.payload);
+      }
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // Microphones come and go across sleep (a dock, a headset); say so
+  // before the next dictation finds out
+  useEffect(() => {
+    const unlisten = listen("power:woke", async () => {
+      try {
+        const devices = await navigator.mediaDevices.enumerateDevices();
+        if (!devices.some((device) => device.kind === "audioinput")) {
+          console.warn("🎤 No microphone after waking");
+        }
+      } catch (err) {
+        console.warn("Failed to list microphones:", err);
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // Stops the dictation in progress without pasting anything, and hides
+  // the pill; after the recording, its transcription is stopped too
+  const cancelRecording = async () => {
+    const recording = isRecording;
+    await stopRecording();
+    const sessionId = sessionIdRef.current;
+    if (!sessionId) {
+      await invoke("hide_recording_pill");
+      return;
+    }
+    try {
+      await invoke(recording ? "cancel_recording" : "cancel_transcription", {
+        sessionId,
+      });
+    } catch (err) {
+      // Already over (pasted or cancelled elsewhere); nothing to cancel
+      log.debug("Nothing to cancel", err);
+    }
+  };
+
+  // Ends the dictation in progress and pastes what was said so far (from
+  // external triggers); without any final transcript it is cancelled
+  const finishRecording = async () => {
+    if (providerRef.current?.finishNow()) {
+      return;
+    }
+    await cancelRecording();
+  };
+
+  // Transcribes recorded audio from `load` and pastes the result like a
+  // dictation; `failure` is the error shown if that fails
+  const transcribeAudio = async (
+    load: () => Promise<ArrayBuffer>,
+    failure: string
+  ) => {
+    if (isStartingRef.current || isRecording || isProcessing) {
+      log.debug("Busy, ignoring transcription");
+      return;
+    }
+    isStartingRef.current = true;
+
+    try {
+      const sessionId = await invoke<string>("show_recording_pill");
+      sessionIdRef.current = sessionId;
+      await invoke("set_pill_state", { state: { kind: "transcribing" } });
+      setError(null);
+      setIsProcessing(true);
+
+      const config = await invoke<TranscriptionConfig>(
+        "get_transcription_config"
+      );
+      const audio = await load();
+      const text = await transcribeAudioFile(apiKey, audio, config.language);
+      if (!text) {
+        throw new Error("No speech found in the audio");
+      }
+
+      const processed = await invoke<ProcessedTranscript>(
+        "process_transcript",
+        {
+          text,
+          provider: config.provider,
+          language: config.language,
+          sessionId,
+        }
+      );
+      await invoke("copy_and_paste_text", { text: processed.text, sessionId });
+      log.info("Audio transcribed and pasted");
+      await invoke("hide_recording_pill");
+    } catch (err) {
+      if (isStaleSession(err)) {
+        // Cancelled while it was transcribed; nothing was pasted
+        log.info("Transcription cancelled");
+        return;
+      }
+      console.error("Transcription failed:", err);
+      invoke("set_pill_state", { state: { kind: "error" } }).catch(
+        console.warn
+      );
+      // Files that can't be transcribed say why (AudioFileError)
+      setError(await describeError(err, failure));
+    } finally {
+      setIsProcessing(false);
+      isStartingRef.current = false;
+    }
+  };
+
+  // Transcribes an audio file (from `--transcribe` or another external
+  // trigger) and pastes the result like a dictation
+  const transcribeFile = (path: string) =>
+    transcribeAudio(
+      () => readAudioFile(path),
+      "Failed to transcribe file"
+    );
+
+  // Transcribes and pastes what was recorded before the system slept
+  const transcribeInterrupted = async () => {
+    const chunks = audioChunksRef.current;
+    audioChunksRef.current = [];
+    setInterrupted(null);
+    if (peakRef.current < silentLevelRef.current) {
+      // Nothing but silence; don't send it to the provider
+      setError(
+        await describeError(
+          { code: EMPTY_RECORDING },
+          "The microphone is silent"
+        )
+      );
+      invoke("set_pill_state", { state: { kind: "error" } }).catch(
+        console.warn
+      );
+      return;
+    }
+    return transcribeAudio(
+      () => new Blob(chunks).arrayBuffer(),
+      "Failed to transcribe the interrupted recording"
+    );
+  };
+
+  // Drops what was recorded before the system slept
+  const discardInterrupted = async () => {
+    audioChunksRef.current = [];
+    setInterrupted(null);
+    await invoke("discard_interrupted_recording");
+  };
+
   return {
     isRecording,
     transcript,
     isProcessing,
     error,
+    profanityFiltered,
+    recordedMs,
+    inputSilent,
+    interrupted,
     startRecording,
     stopRecording,
+    cancelRecording,
+    finishRecording,
+    transcribeFile,
+    transcribeInterrupted,
+    discardInterrupted,
   };
 }
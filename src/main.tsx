@@ -1,9 +1,26 @@
 import React from "react";
 import ReactDOM from "react-dom/client";
 import App from "./App";
+import HistoryWindow from "./windows/HistoryWindow";
+import IndicatorWindow from "./windows/IndicatorWindow";
+import RecentWindow from "./windows/RecentWindow";
+import SettingsWindow from "./windows/SettingsWindow";
+
+/**
+ * Every window loads this bundle; the backend opens secondary windows on
+ * `index.html#/<route>`. Only the pill window (no route) mounts `App`, which
+ * owns the hotkeys and the microphone.
+ */
+const routes: Record<string, React.ComponentType> = {
+  "#/settings": SettingsWindow,
+  "#/history": HistoryWindow,
+  "#/indicator": IndicatorWindow,
+  "#/recent": RecentWindow,
+};
+const Root = routes[window.location.hash] ?? App;
 
 ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
   <React.StrictMode>
-    <App />
+    <Root />
   </React.StrictMode>,
 );
@@ -1,47 +1,514 @@
+import { log } from "./log";
+import { isSameInput, schedulePacket } from "./mixing";
+
+/** Length of each audio chunk handed to `onAudioData`. */
+export const CHUNK_MS = 250;
+
+/** Mic level (RMS of the samples, 0 to 1) taken as someone speaking. */
+export const SPEECH_LEVEL = 0.02;
+/** How long the level has to stay up before it counts as speech. */
+const SPEECH_MIN_MS = 300;
+/** How often the level is measured. */
+const LEVEL_INTERVAL_MS = 50;
+/** How long the old and new microphone overlap when the input changes. */
+const CROSSFADE_MS = 30;
+
+/**
+ * What to record in (`capture.dictation` or `capture.meeting` in the
+ * settings). Recordings are WebM with Opus.
+ */
+export interface CaptureQuality {
+  sample_rate: number;
+  channels: number;
+  bitrate_kbps: number;
+}
+
+/** What a recording is made in, for `report_capture_format`. */
+export interface CaptureFormat {
+  /** The recording's: what the audio graph runs at and mixes to. */
+  sample_rate: number;
+  channels: number;
+  /** What the microphone was opened with, when the browser says. */
+  device_sample_rate: number | null;
+  device_channels: number | null;
+}
+
+/** Deepgram works well with 16kHz mono, which dictations need at most. */
+const DEFAULT_QUALITY: CaptureQuality = {
+  sample_rate: 16000,
+  channels: 1,
+  bitrate_kbps: 32,
+};
+
+// Asks for `quality`; the browser picks the device's nearest configuration
+const micConstraints = (quality: CaptureQuality): MediaStreamConstraints => ({
+  audio: {
+    echoCancellation: true,
+    noiseSuppression: true,
+    sampleRate: { ideal: quality.sample_rate },
+    channelCount: { ideal: quality.channels },
+  },
+});
+
+/** What a recording reports as it goes. */
+export interface RecordingCallbacks {
+  /**
+   * A chunk of `CHUNK_MS` of the recording, and the highest level (absolute
+   * sample value, 0 to 1) measured while it was recorded. Not called for
+   * segmented recordings.
+   */
+  onAudioData?: (data: ArrayBuffer, peak: number) => void;
+  /**
+   * A finished segment of a segmented recording, a file of its own, and
+   * where it lies in the recording. Awaited before `nextSegment` and
+   * `endSegments` resolve.
+   */
+  onSegment?: (
+    audio: ArrayBuffer,
+    startMs: number,
+    endMs: number
+  ) => void | Promise<void>;
+  /** The mic's level (RMS of the samples, 0 to 1), as it is measured. */
+  onLevel?: (level: number) => void;
+  /** Called once, when the mic's level first stays above speech level. */
+  onSpeech?: () => void;
+  /**
+   * The default microphone changed (e.g. a headset connected) and the
+   * recording carries on from the new one; the labels of both, if known.
+   */
+  onDeviceChange?: (from: string | null, to: string | null) => void;
+  /** The microphone went away and no other could be opened. */
+  onInputLost?: (error: unknown) => void;
+}
+
+/** Options for a recording. */
+export interface RecordingOptions {
+  /**
+   * Record in segments, each a file of its own, instead of one stream of
+   * chunks: `nextSegment` starts a new one. For recordings too long to
+   * hold in memory (meetings).
+   */
+  segmented?: boolean;
+  /**
+   * Record the microphone (the default). Without it, the recording is only
+   * what `pushSamples` adds, until `addMicrophone`.
+   */
+  microphone?: boolean;
+  /** Linear gain of the microphone in the mix; 1 by default. */
+  microphoneGain?: number;
+  /** Linear gain of pushed samples in the mix; 1 by default. */
+  pushedGain?: number;
+  /** What to record in; 16kHz mono by default. */
+  quality?: CaptureQuality;
+}
+
+/** The segment being recorded in a segmented recording. */
+interface Segment {
+  recorder: MediaRecorder;
+  startMs: number;
+  /** Set when the segment is cut. */
+  endMs?: number;
+  /** Resolves once the segment was handed to `onSegment`. */
+  delivered: Promise<void>;
+}
+
+/**
+ * Records the default microphone, and samples pushed to it.
+ *
+ * The recorder doesn't record the microphone directly: the mic goes
+ * through an audio graph into a stream that lives as long as the
+ * recording. When the default input changes mid-recording, the new mic is
+ * connected to the same graph and crossfaded in over `CROSSFADE_MS` while
+ * the old one fades out, so the recording is one continuous stream. The
+ * graph runs at a single sample rate and resamples every mic to it, so
+ * mics with different rates can't corrupt the recording. That rate, and the
+ * channels the mix is downmixed to, are the recording's `quality`, whatever
+ * the mic was opened with.
+ *
+ * A segmented recording cuts that stream into files: each segment is
+ * recorded by a recorder of its own, the next one started before the last
+ * one stops, so no audio falls between two segments. A WebM file only
+ * plays from its start, which is why a segment can't be a slice of a
+ * single recorder's chunks.
+ *
+ * Audio captured elsewhere (system audio, captured natively) is pushed into
+ * the same mix, each packet scheduled right after the one before, so it is
+ * resampled, measured and segmented with the mic. The mix is never played
+ * out loud. The capture's clock runs a little faster or slower than the
+ * audio context's, which would make the backlog of pushed samples grow or
+ * run dry over a long recording; each packet is played a fraction of a
+ * percent faster or slower, by how far the backlog is from its target, so
+ * it stays put. The mic and pushed samples each go through a gain of their
+ * own.
+ */
 export class AudioService {
   private mediaRecorder: MediaRecorder | null = null;
   private audioStream: MediaStream | null = null;
+  private audioContext: AudioContext | null = null;
+  // Gain of the current mic, faded when switching mics
+  private input: GainNode | null = null;
+  // Every mic is mixed into this, which feeds the recorder
+  private mix: GainNode | null = null;
+  // Gains of all mics, and of pushed samples, on their way to the mix
+  private microphoneBus: GainNode | null = null;
+  private pushedBus: GainNode | null = null;
+  private analyser: AnalyserNode | null = null;
+  private levelTimer: number | null = null;
+  // Highest level since the last chunk was delivered
+  private chunkPeak = 0;
+  private callbacks: RecordingCallbacks | null = null;
+  private switching = false;
+  private destination: MediaStreamAudioDestinationNode | null = null;
+  private segment: Segment | null = null;
+  // When the recording started, on the `performance.now()` clock
+  private startedAt = 0;
+  // When the samples pushed so far end, on the audio context's clock
+  private pushedUntil = 0;
+  private quality: CaptureQuality = DEFAULT_QUALITY;
 
+  /** Starts recording, reporting to `callbacks`. */
   async startRecording(
-    onAudioData: (data: ArrayBuffer) => void
+    callbacks: RecordingCallbacks,
+    options: RecordingOptions = {}
   ): Promise<void> {
     try {
+      this.callbacks = callbacks;
+      this.quality = options.quality ?? DEFAULT_QUALITY;
+
       // Get microphone access
-      this.audioStream = await navigator.mediaDevices.getUserMedia({
-        audio: {
-          echoCancellation: true,
-          noiseSuppression: true,
-          sampleRate: 16000, // Deepgram works well with 16kHz
-        },
-      });
+      if (options.microphone ?? true) {
+        this.audioStream = await navigator.mediaDevices.getUserMedia(
+          micConstraints(this.quality)
+        );
+      }
 
-      // Create MediaRecorder
-      this.mediaRecorder = new MediaRecorder(this.audioStream, {
-        mimeType: "audio/webm",
+      this.audioContext = new AudioContext({
+        sampleRate: this.quality.sample_rate,
       });
+      await this.audioContext.resume();
+      this.mix = this.audioContext.createGain();
+      this.microphoneBus = this.bus(options.microphoneGain ?? 1);
+      this.pushedBus = this.bus(options.pushedGain ?? 1);
+      const destination = this.audioContext.createMediaStreamDestination();
+      destination.channelCount = this.quality.channels;
+      destination.channelCountMode = "explicit";
+      this.destination = destination;
+      this.mix.connect(destination);
+      if (this.audioStream) {
+        this.input = this.connect(this.audioStream, 1);
+      }
+      this.startedAt = performance.now();
 
-      // Send audio chunks as they become available
-      this.mediaRecorder.addEventListener("dataavailable", async (event) => {
-        if (event.data.size > 0) {
-          const arrayBuffer = await event.data.arrayBuffer();
-          onAudioData(arrayBuffer);
-        }
-      });
+      if (options.segmented) {
+        this.startSegment(callbacks, 0);
+      } else {
+        // Create MediaRecorder
+        this.mediaRecorder = new MediaRecorder(
+          destination.stream,
+          this.recorderOptions()
+        );
+
+        // Send audio chunks as they become available
+        this.mediaRecorder.addEventListener("dataavailable", async (event) => {
+          if (event.data.size > 0) {
+            const peak = this.chunkPeak;
+            this.chunkPeak = 0;
+            const arrayBuffer = await event.data.arrayBuffer();
+            callbacks.onAudioData?.(arrayBuffer, peak);
+          }
+        });
 
-      // Start recording in chunks (250ms intervals)
-      this.mediaRecorder.start(250);
+        // Start recording in chunks (250ms intervals)
+        this.mediaRecorder.start(CHUNK_MS);
+      }
 
-      console.log("🎤 Recording started");
+      if (this.audioStream) {
+        this.watchInput(this.audioStream);
+        navigator.mediaDevices.addEventListener(
+          "devicechange",
+          this.onDeviceChange
+        );
+      }
+
+      this.measureLevel(callbacks.onSpeech, callbacks.onLevel);
+
+      log.debug("Microphone recording started");
     } catch (error) {
       console.error("Failed to start recording:", error);
+      this.stopRecording();
       throw error;
     }
   }
 
+  // Starts recording a segment from `startMs` into the recording, with a
+  // recorder of its own that keeps the whole segment until it stops
+  private startSegment(callbacks: RecordingCallbacks, startMs: number) {
+    const recorder = new MediaRecorder(
+      this.destination!.stream,
+      this.recorderOptions()
+    );
+    const parts: Blob[] = [];
+    recorder.addEventListener("dataavailable", (event) => {
+      if (event.data.size > 0) parts.push(event.data);
+    });
+    let segment: Segment;
+    const delivered = new Promise<void>((resolve) => {
+      recorder.addEventListener("stop", async () => {
+        try {
+          if (parts.length > 0) {
+            const audio = await new Blob(parts).arrayBuffer();
+            const endMs = segment.endMs ?? this.elapsedMs();
+            await callbacks.onSegment?.(audio, startMs, endMs);
+          }
+        } catch (error) {
+          console.error("Failed to deliver a segment:", error);
+        } finally {
+          resolve();
+        }
+      });
+    });
+    segment = { recorder, startMs, delivered };
+    recorder.start();
+    this.mediaRecorder = recorder;
+    this.segment = segment;
+  }
+
+  private recorderOptions(): MediaRecorderOptions {
+    return {
+      mimeType: "audio/webm",
+      audioBitsPerSecond: this.quality.bitrate_kbps * 1000,
+    };
+  }
+
+  /** What the recording in progress is made in, if there is one. */
+  captureFormat(): CaptureFormat | null {
+    if (!this.audioContext || !this.destination) return null;
+    const device = this.audioStream?.getAudioTracks()[0]?.getSettings();
+    return {
+      sample_rate: this.audioContext.sampleRate,
+      channels: this.destination.channelCount,
+      device_sample_rate: device?.sampleRate ?? null,
+      device_channels: device?.channelCount ?? null,
+    };
+  }
+
+  private elapsedMs() {
+    return Math.round(performance.now() - this.startedAt);
+  }
+
+  /**
+   * Ends the segment being recorded and starts the next, with no gap
+   * between the two. Resolves once the finished one was delivered.
+   */
+  async nextSegment(): Promise<void> {
+    const previous = this.segment;
+    if (!previous || !this.callbacks) return;
+    previous.endMs = this.elapsedMs();
+    this.startSegment(this.callbacks, previous.endMs);
+    previous.recorder.stop();
+    await previous.delivered;
+  }
+
+  /**
+   * Stops a segmented recording. Resolves once its last segment was
+   * delivered.
+   */
+  async endSegments(): Promise<void> {
+    const last = this.segment;
+    if (last) last.endMs = this.elapsedMs();
+    this.stopRecording();
+    await last?.delivered;
+  }
+
+  /**
+   * Adds the default microphone to a recording started without it, e.g.
+   * when the system audio recorded instead stops, or to one whose mic was
+   * lost. Does nothing if a mic is recorded already.
+   */
+  async addMicrophone(): Promise<void> {
+    const live = this.audioStream
+      ?.getAudioTracks()
+      .some((track) => track.readyState === "live");
+    if (live || !this.audioContext) return;
+    const stream = await navigator.mediaDevices.getUserMedia(
+      micConstraints(this.quality)
+    );
+    // Stopped in the meantime
+    if (!this.audioContext) {
+      stream.getTracks().forEach((t) => t.stop());
+      return;
+    }
+    this.input?.disconnect();
+    this.audioStream?.getTracks().forEach((t) => t.stop());
+    this.audioStream = stream;
+    this.input = this.connect(stream, 1);
+    this.watchInput(stream);
+    navigator.mediaDevices.addEventListener(
+      "devicechange",
+      this.onDeviceChange
+    );
+    log.debug("Microphone added to the recording");
+  }
+
+  /**
+   * Adds mono `samples` at `sampleRate` to the recording, right after the
+   * ones pushed before (or, after a gap, a little ahead of now), correcting
+   * for drift.
+   */
+  pushSamples(samples: Float32Array, sampleRate: number) {
+    const context = this.audioContext;
+    if (!context || !this.pushedBus || samples.length === 0) return;
+    const buffer = context.createBuffer(1, samples.length, sampleRate);
+    buffer.copyToChannel(samples, 0);
+    const source = context.createBufferSource();
+    source.buffer = buffer;
+    const { start, rate, end } = schedulePacket(
+      this.pushedUntil,
+      context.currentTime,
+      samples.length,
+      sampleRate
+    );
+    source.playbackRate.value = rate;
+    source.connect(this.pushedBus);
+    source.start(start);
+    this.pushedUntil = end;
+  }
+
+  // A gain on the way to the mix
+  private bus(gain: number): GainNode {
+    const node = this.audioContext!.createGain();
+    node.gain.value = gain;
+    node.connect(this.mix!);
+    return node;
+  }
+
+  // Connects `stream` to the mic bus through a gain of its own
+  private connect(stream: MediaStream, gain: number): GainNode {
+    const context = this.audioContext!;
+    const node = context.createGain();
+    node.gain.value = gain;
+    context.createMediaStreamSource(stream).connect(node);
+    node.connect(this.microphoneBus!);
+    return node;
+  }
+
+  // A mic that is unplugged ends its track; look for the new default
+  private watchInput(stream: MediaStream) {
+    stream.getAudioTracks().forEach((track) =>
+      track.addEventListener("ended", () => this.switchInput())
+    );
+  }
+
+  private onDeviceChange = () => {
+    this.switchInput();
+  };
+
+  // Opens the default mic and, if it isn't the one being recorded,
+  // crossfades to it. If none can be opened and the current one is gone,
+  // the recording can't go on
+  private async switchInput() {
+    if (this.switching || !this.audioContext || !this.audioStream) return;
+    this.switching = true;
+    const previousStream = this.audioStream;
+    const previousTrack = previousStream.getAudioTracks()[0];
+    try {
+      const stream = await navigator.mediaDevices.getUserMedia(
+        micConstraints(this.quality)
+      );
+      const track = stream.getAudioTracks()[0];
+      const context = this.audioContext;
+      // Stopped in the meantime, or the same mic
+      if (!context || isSameInput(previousTrack, track)) {
+        stream.getTracks().forEach((t) => t.stop());
+        return;
+      }
+
+      const now = context.currentTime;
+      const end = now + CROSSFADE_MS / 1000;
+      const input = this.connect(stream, 0);
+      input.gain.setValueAtTime(0, now);
+      input.gain.linearRampToValueAtTime(1, end);
+      const previousInput = this.input;
+      previousInput?.gain.setValueAtTime(1, now);
+      previousInput?.gain.linearRampToValueAtTime(0, end);
+      window.setTimeout(() => {
+        previousInput?.disconnect();
+        previousStream.getTracks().forEach((t) => t.stop());
+      }, CROSSFADE_MS * 2);
+
+      this.audioStream = stream;
+      this.input = input;
+      this.watchInput(stream);
+      log.info(`Input changed: ${previousTrack?.label} → ${track?.label}`);
+      this.callbacks?.onDeviceChange?.(
+        previousTrack?.label || null,
+        track?.label || null
+      );
+    } catch (error) {
+      // Keep recording from the old mic while it lasts
+      if (previousTrack?.readyState !== "ended") return;
+      console.error("Lost the microphone:", error);
+      this.callbacks?.onInputLost?.(error);
+    } finally {
+      this.switching = false;
+    }
+  }
+
+  // Measures the level of the mix for the whole recording: its peak for
+  // each chunk, the level for `onLevel`, and, until it stays above speech
+  // level for `SPEECH_MIN_MS`, whether someone speaks, which calls
+  // `onSpeech` once
+  private measureLevel(
+    onSpeech?: () => void,
+    onLevel?: (level: number) => void
+  ) {
+    const analyser = this.audioContext!.createAnalyser();
+    analyser.fftSize = 2048;
+    this.mix!.connect(analyser);
+    this.analyser = analyser;
+    const samples = new Float32Array(analyser.fftSize);
+    let loudMs = 0;
+    let speechHeard = !onSpeech;
+    this.levelTimer = window.setInterval(() => {
+      analyser.getFloatTimeDomainData(samples);
+      let sum = 0;
+      let peak = 0;
+      for (const sample of samples) {
+        sum += sample * sample;
+        peak = Math.max(peak, Math.abs(sample));
+      }
+      this.chunkPeak = Math.max(this.chunkPeak, peak);
+      const level = Math.sqrt(sum / samples.length);
+      onLevel?.(level);
+      if (speechHeard) return;
+      loudMs = level >= SPEECH_LEVEL ? loudMs + LEVEL_INTERVAL_MS : 0;
+      if (loudMs >= SPEECH_MIN_MS) {
+        speechHeard = true;
+        onSpeech?.();
+      }
+    }, LEVEL_INTERVAL_MS);
+  }
+
+  private stopMeasuringLevel() {
+    if (this.levelTimer !== null) {
+      window.clearInterval(this.levelTimer);
+      this.levelTimer = null;
+    }
+    this.analyser?.disconnect();
+    this.analyser = null;
+  }
+
   stopRecording() {
+    this.stopMeasuringLevel();
+    navigator.mediaDevices.removeEventListener(
+      "devicechange",
+      this.onDeviceChange
+    );
+    this.callbacks = null;
+
     if (this.mediaRecorder && this.mediaRecorder.state !== "inactive") {
       this.mediaRecorder.stop();
-      console.log("🎤 Recording stopped");
+      log.debug("Microphone recording stopped");
     }
 
     if (this.audioStream) {
@@ -49,6 +516,15 @@ export class AudioService {
       this.audioStream = null;
     }
 
+    this.audioContext?.close().catch(console.warn);
+    this.audioContext = null;
+    this.input = null;
+    this.mix = null;
+    this.microphoneBus = null;
+    this.pushedBus = null;
+    this.destination = null;
+    this.segment = null;
+    this.pushedUntil = 0;
     this.mediaRecorder = null;
   }
 }
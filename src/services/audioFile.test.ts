@@ -0,0 +1,71 @@
+import { describe, expect, it } from "vitest";
+
+import {
+  DecodedAudio,
+  joinAudio,
+  WAV_HEADER_BYTES,
+  wavHeader,
+} from "./audioFile";
+
+function text(bytes: Uint8Array, offset: number, length: number): string {
+  return String.fromCharCode(...bytes.subarray(offset, offset + length));
+}
+
+describe("wavHeader", () => {
+  it("describes 16-bit PCM at the rate and channels given", () => {
+    const header = wavHeader(16000, 1, 32000);
+    const view = new DataView(header.buffer);
+    expect(header.length).toBe(WAV_HEADER_BYTES);
+    expect(text(header, 0, 4)).toBe("RIFF");
+    expect(view.getUint32(4, true)).toBe(36 + 32000);
+    expect(text(header, 8, 8)).toBe("WAVEfmt ");
+    expect(view.getUint16(20, true)).toBe(1);
+    expect(view.getUint16(22, true)).toBe(1);
+    expect(view.getUint32(24, true)).toBe(16000);
+    expect(view.getUint32(28, true)).toBe(32000);
+    expect(view.getUint16(32, true)).toBe(2);
+    expect(view.getUint16(34, true)).toBe(16);
+    expect(text(header, 36, 4)).toBe("data");
+    expect(view.getUint32(40, true)).toBe(32000);
+  });
+
+  it("counts every channel in the byte rate and block", () => {
+    const view = new DataView(wavHeader(48000, 2, 0).buffer);
+    expect(view.getUint32(28, true)).toBe(48000 * 4);
+    expect(view.getUint16(32, true)).toBe(4);
+  });
+});
+
+describe("joinAudio", () => {
+  const chunks = [
+    new Uint8Array([1, 2]).buffer,
+    new Uint8Array([3, 4]).buffer,
+  ];
+
+  it("puts PCM chunks in order after a WAV header", () => {
+    const decoded: DecodedAudio = {
+      encoding: "pcm",
+      sample_rate: 16000,
+      channels: 1,
+      bytes: 4,
+      chunks: 2,
+    };
+    const audio = new Uint8Array(joinAudio(decoded, chunks));
+    expect(audio.length).toBe(WAV_HEADER_BYTES + 4);
+    expect(text(audio, 0, 4)).toBe("RIFF");
+    expect([...audio.subarray(WAV_HEADER_BYTES)]).toEqual([1, 2, 3, 4]);
+  });
+
+  it("leaves a file streamed as it is alone", () => {
+    const decoded: DecodedAudio = {
+      encoding: "file",
+      sample_rate: 16000,
+      channels: 1,
+      bytes: 4,
+      chunks: 2,
+    };
+    expect([...new Uint8Array(joinAudio(decoded, chunks))]).toEqual([
+      1, 2, 3, 4,
+    ]);
+  });
+});
@@ -0,0 +1,105 @@
+/**
+ * Audio files given to transcribe, as the backend decodes them (see
+ * `read_audio_file`): PCM streamed in chunks, put back together as a WAV
+ * file for the provider.
+ *
+ * @module audioFile
+ */
+
+import { Channel, invoke } from "@tauri-apps/api/core";
+
+/** What `read_audio_file` sent. */
+export interface DecodedAudio {
+  /** `pcm` (16-bit little-endian) or `file` (the file as it is). */
+  encoding: "pcm" | "file";
+  sample_rate: number;
+  channels: number;
+  bytes: number;
+  chunks: number;
+}
+
+/** Bytes of the header `wavHeader` builds. */
+export const WAV_HEADER_BYTES = 44;
+
+/**
+ * The header of a WAV file holding `dataBytes` of 16-bit PCM at
+ * `sampleRate` with `channels` channels.
+ */
+export function wavHeader(
+  sampleRate: number,
+  channels: number,
+  dataBytes: number
+): Uint8Array {
+  const header = new Uint8Array(WAV_HEADER_BYTES);
+  const view = new DataView(header.buffer);
+  const text = (offset: number, value: string) => {
+    for (let i = 0; i < value.length; i++) {
+      view.setUint8(offset + i, value.charCodeAt(i));
+    }
+  };
+  const blockAlign = channels * 2;
+  text(0, "RIFF");
+  view.setUint32(4, WAV_HEADER_BYTES - 8 + dataBytes, true);
+  text(8, "WAVE");
+  text(12, "fmt ");
+  view.setUint32(16, 16, true);
+  // PCM
+  view.setUint16(20, 1, true);
+  view.setUint16(22, channels, true);
+  view.setUint32(24, sampleRate, true);
+  view.setUint32(28, sampleRate * blockAlign, true);
+  view.setUint16(32, blockAlign, true);
+  view.setUint16(34, 16, true);
+  text(36, "data");
+  view.setUint32(40, dataBytes, true);
+  return header;
+}
+
+/**
+ * The audio of `chunks` in one file: a WAV file for PCM, the chunks one
+ * after the other otherwise.
+ */
+export function joinAudio(
+  decoded: DecodedAudio,
+  chunks: ArrayBuffer[]
+): ArrayBuffer {
+  const header =
+    decoded.encoding === "pcm"
+      ? wavHeader(decoded.sample_rate, decoded.channels, decoded.bytes)
+      : new Uint8Array(0);
+  const audio = new Uint8Array(header.length + decoded.bytes);
+  audio.set(header);
+  let offset = header.length;
+  for (const chunk of chunks) {
+    audio.set(new Uint8Array(chunk), offset);
+    offset += chunk.byteLength;
+  }
+  return audio.buffer;
+}
+
+/**
+ * Reads the audio file at `path` for transcription. Chunks may still be
+ * arriving when `read_audio_file` returns, so this waits for as many as it
+ * says it sent.
+ */
+export async function readAudioFile(path: string): Promise<ArrayBuffer> {
+  const chunks: ArrayBuffer[] = [];
+  let received: (() => void) | null = null;
+  let expected: number | null = null;
+  const channel = new Channel<ArrayBuffer>();
+  channel.onmessage = (chunk) => {
+    chunks.push(chunk);
+    if (chunks.length === expected) received?.();
+  };
+  const decoded = await invoke<DecodedAudio>("read_audio_file", {
+    path,
+    channel,
+  });
+  if (chunks.length < decoded.chunks) {
+    await new Promise<void>((resolve) => {
+      received = resolve;
+      expected = decoded.chunks;
+    });
+  }
+  return joinAudio(decoded, chunks);
+}
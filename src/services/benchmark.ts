@@ -0,0 +1,63 @@
+import { invoke } from "@tauri-apps/api/core";
+import { readAudioFile } from "./audioFile";
+import { transcribeFile } from "./deepgram";
+import { MockTranscriptionService } from "./mock";
+import { TranscriptionConfig } from "./transcription";
+
+/** Payload of `benchmark:run`: a clip to transcribe `runs` times. */
+export interface BenchmarkRequest {
+  id: string;
+  provider: string;
+  /** The provider's default model when null. */
+  model: string | null;
+  path: string;
+  runs: number;
+}
+
+/** Waits out one mock transcription, as configured; it ignores the audio. */
+function transcribeMock(config: TranscriptionConfig): Promise<string> {
+  return new Promise((resolve, reject) => {
+    const mock = new MockTranscriptionService(config.mock, {
+      onTranscript: () => {},
+      onSpeechEnd: resolve,
+      onError: reject,
+    });
+    mock.start().catch(reject);
+  });
+}
+
+/**
+ * Runs a benchmark the backend started (`run_benchmark`): transcribes the
+ * clip once per run, one after the other, and reports how long each took
+ * with `report_benchmark_run`.
+ */
+export async function runBenchmark(request: BenchmarkRequest, apiKey: string) {
+  const audio = await readAudioFile(request.path);
+  const config = await invoke<TranscriptionConfig>("get_transcription_config");
+
+  for (let run = 0; run < request.runs; run++) {
+    const started = performance.now();
+    let latencyMs: number | null = null;
+    let error: string | null = null;
+    try {
+      if (request.provider === "mock") {
+        await transcribeMock(config);
+      } else {
+        await transcribeFile(
+          apiKey,
+          audio,
+          config.language,
+          request.model ?? undefined
+        );
+      }
+      latencyMs = Math.round(performance.now() - started);
+    } catch (err) {
+      error = err instanceof Error ? err.message : String(err);
+    }
+    await invoke("report_benchmark_run", {
+      id: request.id,
+      latencyMs,
+      error,
+    });
+  }
+}
@@ -1,22 +1,25 @@
 import { createClient, LiveTranscriptionEvents } from "@deepgram/sdk";
+import { TranscriptionCallbacks, TranscriptionProvider } from "./transcription";
+import { log } from "./log";
 
-export interface TranscriptionCallbacks {
-  onTranscript: (text: string, isFinal: boolean) => void;
-  onSpeechEnd: (finalText: string) => void;
-  onError: (error: Error) => void;
-}
-
-export class DeepgramService {
+export class DeepgramService implements TranscriptionProvider {
+  readonly needsAudio = true;
   private connection: any = null;
   private apiKey: string;
   private finalTranscript: string = "";
   private interimTranscript: string = "";
   private callbacks: TranscriptionCallbacks;
   private hasSpeechFinal: boolean = false;
+  private language: string;
 
-  constructor(apiKey: string, callbacks: TranscriptionCallbacks) {
+  constructor(
+    apiKey: string,
+    callbacks: TranscriptionCallbacks,
+    language = "en-US"
+  ) {
     this.apiKey = apiKey;
     this.callbacks = callbacks;
+    this.language = language;
   }
 
   async start() {
@@ -31,12 +34,12 @@ export class DeepgramService {
         endpointing: 1000, // 300ms pause for speech_final
         utterance_end_ms: 1500, // 1 second gap for UtteranceEnd
         punctuate: true,
-        language: "en-US",
+        language: this.language,
       });
 
       // Connection opened
       this.connection.on(LiveTranscriptionEvents.Open, () => {
-        console.log("✅ Deepgram connection opened");
+        log.debug("Deepgram connection opened");
       });
 
       // Receiving transcripts
@@ -66,7 +69,7 @@ export class DeepgramService {
 
         // 3. speech_final is just our "Trigger" to finish the UX flow
         if (speechFinal && !this.hasSpeechFinal) {
-          console.log("🎯 Sentence complete, finishing up...");
+          log.debug("Sentence complete, finishing up");
           this.hasSpeechFinal = true;
 
           // Crucial: Use the current state of finalTranscript
@@ -77,7 +80,7 @@ export class DeepgramService {
 
       // UtteranceEnd event - backup for speech_final
       this.connection.on(LiveTranscriptionEvents.UtteranceEnd, () => {
-        console.log("🔚 UtteranceEnd detected");
+        log.debug("UtteranceEnd detected");
 
         // Only use UtteranceEnd if we didn't get speech_final
         if (!this.hasSpeechFinal && this.finalTranscript) {
@@ -93,7 +96,7 @@ export class DeepgramService {
 
       // Connection closed
       this.connection.on(LiveTranscriptionEvents.Close, () => {
-        console.log("❌ Deepgram connection closed");
+        log.debug("Deepgram connection closed");
       });
     } catch (error) {
       console.error("Failed to start Deepgram:", error);
@@ -108,6 +111,19 @@ export class DeepgramService {
     }
   }
 
+  // Finish now with whatever has been transcribed, as if speech had ended.
+  // Returns false when nothing final was transcribed yet
+  finishNow(): boolean {
+    if (this.hasSpeechFinal || !this.finalTranscript) {
+      return false;
+    }
+
+    this.hasSpeechFinal = true;
+    this.callbacks.onSpeechEnd(this.finalTranscript);
+    this.stop();
+    return true;
+  }
+
   // Stop recording and close connection
   async stop() {
     if (this.connection && this.connection.getReadyState() === 1) {
@@ -132,3 +148,82 @@ export class DeepgramService {
     this.hasSpeechFinal = false;
   }
 }
+
+/**
+ * Transcribes a whole audio file with Deepgram's pre-recorded API, using the
+ * same model (unless `model` says otherwise) and formatting as live
+ * dictation.
+ *
+ * @returns The transcript (empty when no speech was found)
+ */
+export async function transcribeFile(
+  apiKey: string,
+  audio: ArrayBuffer,
+  language: string,
+  model = "nova-2"
+): Promise<string> {
+  const deepgram = createClient(apiKey);
+  // The SDK types file sources as Node buffers; any byte array works
+  const { result, error } = await deepgram.listen.prerecorded.transcribeFile(
+    new Uint8Array(audio) as any,
+    {
+      model,
+      smart_format: true,
+      punctuate: true,
+      language,
+    }
+  );
+  if (error) {
+    throw new Error(error.message || "Deepgram error");
+  }
+
+  return result?.results?.channels[0]?.alternatives[0]?.transcript ?? "";
+}
+
+/** What one speaker said in a recording. */
+export interface Utterance {
+  /** Where it starts, from the start of the recording. */
+  start_ms: number;
+  /** Deepgram's number for the speaker, from 0. */
+  speaker: number;
+  text: string;
+}
+
+/**
+ * Transcribes a whole audio file like `transcribeFile`, also telling
+ * speakers apart (Deepgram's `diarize`). Speaker labels are best-effort:
+ * `utterances` is empty when Deepgram labels none.
+ *
+ * @returns The transcript, and what each speaker said in it
+ */
+export async function transcribeFileWithSpeakers(
+  apiKey: string,
+  audio: ArrayBuffer,
+  language: string
+): Promise<{ text: string; utterances: Utterance[] }> {
+  const deepgram = createClient(apiKey);
+  const { result, error } = await deepgram.listen.prerecorded.transcribeFile(
+    new Uint8Array(audio) as any,
+    {
+      model: "nova-2",
+      smart_format: true,
+      punctuate: true,
+      language,
+      diarize: true,
+      utterances: true,
+    }
+  );
+  if (error) {
+    throw new Error(error.message || "Deepgram error");
+  }
+
+  const text = result?.results?.channels[0]?.alternatives[0]?.transcript ?? "";
+  const utterances = (result?.results?.utterances ?? [])
+    .filter((u: any) => typeof u.speaker === "number" && u.transcript)
+    .map((u: any) => ({
+      start_ms: Math.round(u.start * 1000),
+      speaker: u.speaker,
+      text: u.transcript,
+    }));
+  return { text, utterances };
+}
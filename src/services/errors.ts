@@ -0,0 +1,51 @@
+/**
+ * Stable error codes from the backend and their default messages
+ * (`get_error_messages`).
+ *
+ * @module errors
+ */
+
+import { invoke } from "@tauri-apps/api/core";
+
+/** Default message and suggested action of an error code. */
+export interface UserMessage {
+  code: string;
+  message: string;
+  action: string | null;
+}
+
+let messages: Promise<Map<string, UserMessage>> | null = null;
+
+/** The backend's messages by code, fetched once. */
+function errorMessages(): Promise<Map<string, UserMessage>> {
+  messages ??= invoke<UserMessage[]>("get_error_messages")
+    .then((list) => new Map(list.map((entry) => [entry.code, entry])))
+    .catch((err) => {
+      messages = null;
+      throw err;
+    });
+  return messages;
+}
+
+/** The `code` of a typed command error, if it has one. */
+export const errorCode = (err: unknown): string | undefined =>
+  (err as { code?: string } | null)?.code;
+
+/**
+ * What to tell the user about `err`: its code's message and action, or
+ * `fallback` when it has no known code.
+ */
+export async function describeError(
+  err: unknown,
+  fallback: string
+): Promise<string> {
+  const code = errorCode(err);
+  if (!code) return fallback;
+  try {
+    const entry = (await errorMessages()).get(code);
+    if (!entry) return fallback;
+    return entry.action ? `${entry.message}. ${entry.action}.` : entry.message;
+  } catch {
+    return fallback;
+  }
+}
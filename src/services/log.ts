@@ -0,0 +1,38 @@
+/**
+ * Logging into the app's log file, next to the backend's own messages
+ * (`log_frontend`), at the level `logging.level` lets through.
+ *
+ * Messages end up on disk: never put transcript text in them, only its
+ * length.
+ *
+ * @module log
+ */
+
+import { invoke } from "@tauri-apps/api/core";
+
+type Level = "debug" | "info" | "warn" | "error";
+
+/** `detail` as text: an error's message, or anything else as JSON. */
+function describe(detail: unknown): string {
+  if (detail instanceof Error) return detail.message;
+  if (typeof detail === "string") return detail;
+  try {
+    return JSON.stringify(detail);
+  } catch {
+    return String(detail);
+  }
+}
+
+function send(level: Level, message: string, detail?: unknown) {
+  const text =
+    detail === undefined ? message : `${message}: ${describe(detail)}`;
+  // Nowhere left to report a failure to
+  invoke("log_frontend", { level, message: text }).catch(() => {});
+}
+
+export const log = {
+  debug: (message: string, detail?: unknown) => send("debug", message, detail),
+  info: (message: string, detail?: unknown) => send("info", message, detail),
+  warn: (message: string, detail?: unknown) => send("warn", message, detail),
+  error: (message: string, detail?: unknown) => send("error", message, detail),
+};
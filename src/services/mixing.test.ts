@@ -0,0 +1,141 @@
+import { describe, expect, it } from "vitest";
+
+import {
+  InputTrack,
+  isSameInput,
+  MAX_DRIFT_CORRECTION,
+  PacketSchedule,
+  PUSH_LATENCY_MS,
+  schedulePacket,
+} from "./mixing";
+
+function track(
+  label: string,
+  deviceId: string,
+  readyState: MediaStreamTrackState = "live"
+): InputTrack {
+  return { label, readyState, getSettings: () => ({ deviceId }) };
+}
+
+describe("isSameInput", () => {
+  const builtIn = track("MacBook Pro Microphone", "built-in");
+
+  it("takes the mic being recorded for itself", () => {
+    expect(
+      isSameInput(builtIn, track("MacBook Pro Microphone", "built-in"))
+    ).toBe(true);
+  });
+
+  it("switches to another mic", () => {
+    expect(isSameInput(builtIn, track("AirPods Pro", "airpods"))).toBe(false);
+    // Two of the same model
+    expect(
+      isSameInput(builtIn, track("MacBook Pro Microphone", "other"))
+    ).toBe(false);
+  });
+
+  it("switches once the mic being recorded ended, even back to it", () => {
+    const ended = track("AirPods Pro", "airpods", "ended");
+    expect(isSameInput(ended, track("AirPods Pro", "airpods"))).toBe(false);
+    expect(isSameInput(ended, builtIn)).toBe(false);
+  });
+
+  it("switches without a mic being recorded", () => {
+    expect(isSameInput(undefined, builtIn)).toBe(false);
+  });
+});
+
+/** A packet as `push` scheduled it. */
+interface Pushed {
+  schedule: PacketSchedule;
+  /** What was left to play when it was pushed, in seconds. */
+  backlog: number;
+}
+
+/**
+ * Pushes `minutes` of 10ms packets captured at `sampleRate`, on a clock
+ * running `drift` (a fraction) faster than the audio context's, each as it
+ * arrives.
+ */
+function push(minutes: number, drift: number, sampleRate = 48_000): Pushed[] {
+  const frames = sampleRate / 100;
+  const count = (minutes * 60 * sampleRate) / frames;
+  const pushed: Pushed[] = [];
+  let pushedUntil = 0;
+  for (let i = 0; i < count; i++) {
+    const now = (i * frames) / sampleRate / (1 + drift);
+    const schedule = schedulePacket(pushedUntil, now, frames, sampleRate);
+    pushed.push({ schedule, backlog: pushedUntil - now });
+    pushedUntil = schedule.end;
+  }
+  return pushed;
+}
+
+/** How many packets after the first didn't follow on from the one before. */
+function gaps(pushed: Pushed[]): number {
+  return pushed
+    .slice(1)
+    .filter(({ schedule }, i) => schedule.start !== pushed[i].schedule.end)
+    .length;
+}
+
+describe("schedulePacket", () => {
+  const target = PUSH_LATENCY_MS / 1000;
+
+  it("schedules the first packet ahead of now", () => {
+    expect(schedulePacket(0, 12, 480, 48_000)).toEqual({
+      start: 12 + target,
+      rate: 1,
+      end: 12 + target + 0.01,
+    });
+  });
+
+  it("starts over ahead of now once the backlog ran dry", () => {
+    const { start, rate } = schedulePacket(11.5, 12, 480, 48_000);
+    expect(start).toBeCloseTo(12 + target);
+    expect(rate).toBe(1);
+  });
+
+  it("plays packets as long as they were captured, at any rate", () => {
+    for (const sampleRate of [16_000, 44_100, 48_000]) {
+      const pushed = push(1, 0, sampleRate);
+      expect(gaps(pushed)).toBe(0);
+      for (const { schedule } of pushed) {
+        expect(schedule.rate).toBeCloseTo(1, 9);
+        expect(schedule.end - schedule.start).toBeCloseTo(0.01, 9);
+      }
+      // A minute captured is a minute played
+      const last = pushed[pushed.length - 1].schedule;
+      expect(last.end - pushed[0].schedule.start).toBeCloseTo(60, 6);
+    }
+  });
+
+  it("holds the backlog steady across clock drift", () => {
+    for (const drift of [0.002, -0.002, 0.004, -0.004]) {
+      // Ten minutes, over which the drift alone would move the backlog by
+      // more than a second
+      const pushed = push(10, drift);
+      expect(gaps(pushed)).toBe(0);
+      // Settled where the correction matches the drift
+      const backlog = pushed[pushed.length - 1].backlog;
+      expect(backlog).toBeCloseTo(
+        target * (1 + drift / MAX_DRIFT_CORRECTION),
+        3
+      );
+      for (const { schedule } of pushed) {
+        expect(Math.abs(schedule.rate - 1)).toBeLessThanOrEqual(
+          MAX_DRIFT_CORRECTION
+        );
+      }
+    }
+  });
+
+  it("never overlaps packets when the drift is past correcting", () => {
+    const pushed = push(10, -2 * MAX_DRIFT_CORRECTION);
+    // The capture falls behind: the backlog runs dry now and then
+    expect(gaps(pushed)).toBeGreaterThan(0);
+    for (const [i, { schedule }] of pushed.slice(1).entries()) {
+      expect(schedule.start).toBeGreaterThanOrEqual(pushed[i].schedule.end);
+    }
+  });
+});
@@ -0,0 +1,75 @@
+/**
+ * The decisions behind the recording's audio graph (see `AudioService`),
+ * kept apart from Web Audio so they can be tested without it.
+ *
+ * @module mixing
+ */
+
+/** What `isSameInput` looks at in a microphone's track. */
+export type InputTrack = Pick<
+  MediaStreamTrack,
+  "readyState" | "label" | "getSettings"
+>;
+
+/**
+ * Whether `next`, just opened as the default input, is the microphone
+ * `previous` records from, leaving nothing to switch to. A `previous` that
+ * ended never is: the device is gone, even if it is back under the same
+ * name, and the recording has to go on from `next`.
+ */
+export function isSameInput(
+  previous: InputTrack | undefined,
+  next: InputTrack | undefined
+): boolean {
+  return (
+    previous?.readyState === "live" &&
+    next?.label === previous.label &&
+    next?.getSettings().deviceId === previous.getSettings().deviceId
+  );
+}
+
+/** How far ahead pushed samples are scheduled, for packets arriving late. */
+export const PUSH_LATENCY_MS = 100;
+/**
+ * Most pushed samples are sped up or slowed down by to keep their backlog
+ * at `PUSH_LATENCY_MS`, as a fraction of their rate.
+ */
+export const MAX_DRIFT_CORRECTION = 0.005;
+
+/** When a packet of pushed samples plays, on the audio context's clock. */
+export interface PacketSchedule {
+  /** When it starts, in seconds. */
+  start: number;
+  /** How fast it plays; 1 as captured. */
+  rate: number;
+  /** When it ends, and the next packet starts. */
+  end: number;
+}
+
+/**
+ * Schedules `frames` pushed samples at `sampleRate`, on the audio context's
+ * clock at `now`: right after the ones pushed before, which play until
+ * `pushedUntil`, or `PUSH_LATENCY_MS` ahead of now once those ran dry. The
+ * rate is the packet's own, so it lasts as long as it was captured
+ * whatever the context's. A backlog longer than `PUSH_LATENCY_MS` plays
+ * the packet a little faster, a shorter one slower, which makes up for the
+ * capture's clock drifting from the context's.
+ */
+export function schedulePacket(
+  pushedUntil: number,
+  now: number,
+  frames: number,
+  sampleRate: number
+): PacketSchedule {
+  const target = PUSH_LATENCY_MS / 1000;
+  const duration = frames / sampleRate;
+  const backlog = pushedUntil - now;
+  if (backlog <= 0) {
+    // Ran dry, or the first packet
+    const start = now + target;
+    return { start, rate: 1, end: start + duration };
+  }
+  const off = Math.max(-1, Math.min(1, (backlog - target) / target));
+  const rate = 1 + off * MAX_DRIFT_CORRECTION;
+  return { start: pushedUntil, rate, end: pushedUntil + duration / rate };
+}
@@ -0,0 +1,73 @@
+import {
+  MockSettings,
+  TranscriptionCallbacks,
+  TranscriptionProvider,
+} from "./transcription";
+
+/**
+ * The `mock` provider: no API key, no microphone. After `delay_ms` it
+ * returns the canned `text`, streamed word by word as partials first with
+ * `stream_partials`, or fails with `fail`.
+ */
+export class MockTranscriptionService implements TranscriptionProvider {
+  readonly needsAudio = false;
+  private timers: number[] = [];
+  private finished = false;
+
+  constructor(
+    private settings: MockSettings,
+    private callbacks: TranscriptionCallbacks
+  ) {}
+
+  async start() {
+    this.finished = false;
+    const { text, delay_ms, stream_partials, fail } = this.settings;
+
+    if (fail) {
+      this.schedule(delay_ms, () => {
+        this.finished = true;
+        this.callbacks.onError(new Error("Mock provider error"));
+      });
+      return;
+    }
+
+    // Partials spread over the delay, the final transcript at its end
+    const words = text.split(/\s+/).filter(Boolean);
+    if (stream_partials && words.length > 1) {
+      const step = delay_ms / words.length;
+      words.slice(0, -1).forEach((_, i) => {
+        this.schedule(step * (i + 1), () =>
+          this.callbacks.onTranscript(words.slice(0, i + 1).join(" "), false)
+        );
+      });
+    }
+    this.schedule(delay_ms, () => this.finishNow());
+  }
+
+  sendAudio(_audioData: ArrayBuffer) {}
+
+  finishNow(): boolean {
+    if (this.finished || this.settings.fail) {
+      return false;
+    }
+    this.finished = true;
+    this.clearTimers();
+    this.callbacks.onTranscript(this.settings.text, true);
+    this.callbacks.onSpeechEnd(this.settings.text);
+    return true;
+  }
+
+  async stop() {
+    this.finished = true;
+    this.clearTimers();
+  }
+
+  private schedule(ms: number, run: () => void) {
+    this.timers.push(window.setTimeout(run, ms));
+  }
+
+  private clearTimers() {
+    this.timers.forEach((timer) => window.clearTimeout(timer));
+    this.timers = [];
+  }
+}
@@ -0,0 +1,60 @@
+import { invoke } from "@tauri-apps/api/core";
+
+/** A shortcut recorded with `captureShortcut`. */
+export interface CapturedShortcut {
+  /** For the `hotkeys` settings, e.g. `CmdOrCtrl+Shift+K`. */
+  accelerator: string;
+  /** For the user to read, e.g. `⌘⇧K`. */
+  label: string;
+}
+
+/** `navigator.keyboard`, where the webview has it (not in WebKit). */
+interface KeyboardLayoutMap {
+  get(code: string): string | undefined;
+}
+type LayoutKeyboard = { getLayoutMap(): Promise<KeyboardLayoutMap> };
+
+/**
+ * Records the next key chord pressed in this window: listens for key
+ * presses, reports them to `capture_next_shortcut` and stops listening when
+ * it answers. Rejects with its typed error on timeout, Escape, a reserved
+ * chord or `cancelShortcutCapture`.
+ */
+export async function captureShortcut(
+  timeoutMs?: number
+): Promise<CapturedShortcut> {
+  const keyboard = (navigator as any).keyboard as LayoutKeyboard | undefined;
+  const layout = await keyboard?.getLayoutMap().catch(() => undefined);
+
+  const onKeyDown = (event: KeyboardEvent) => {
+    // The keys are for the shortcut, not the page
+    event.preventDefault();
+    event.stopPropagation();
+    if (event.repeat) return;
+    invoke("report_shortcut_key", {
+      press: {
+        code: event.code,
+        key: event.key,
+        layoutKey: layout?.get(event.code) ?? null,
+        ctrl: event.ctrlKey,
+        alt: event.altKey,
+        shift: event.shiftKey,
+        meta: event.metaKey,
+      },
+    }).catch(console.warn);
+  };
+
+  window.addEventListener("keydown", onKeyDown, true);
+  try {
+    return await invoke<CapturedShortcut>("capture_next_shortcut", {
+      timeoutMs,
+    });
+  } finally {
+    window.removeEventListener("keydown", onKeyDown, true);
+  }
+}
+
+/** Stops a `captureShortcut` of this window, e.g. when its page unmounts. */
+export function cancelShortcutCapture(): Promise<void> {
+  return invoke("cancel_shortcut_capture");
+}
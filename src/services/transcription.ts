@@ -0,0 +1,53 @@
+/**
+ * What the recording hook needs from a transcription provider, and the
+ * backend's choice of provider (`get_transcription_config`).
+ *
+ * @module transcription
+ */
+
+import { CaptureQuality } from "./audio";
+
+export interface TranscriptionCallbacks {
+  onTranscript: (text: string, isFinal: boolean) => void;
+  onSpeechEnd: (finalText: string) => void;
+  onError: (error: Error) => void;
+}
+
+/** A live transcription of one dictation. */
+export interface TranscriptionProvider {
+  /** Opens the transcription; results arrive through the callbacks. */
+  start(): Promise<void>;
+  /** Whether it needs the microphone's audio (`sendAudio`). */
+  readonly needsAudio: boolean;
+  sendAudio(audioData: ArrayBuffer): void;
+  /**
+   * Finishes now with whatever has been transcribed, as if speech had
+   * ended. Returns false when nothing final was transcribed yet.
+   */
+  finishNow(): boolean;
+  stop(): Promise<void>;
+}
+
+/** How the backend's `mock` provider behaves (`transcription.mock`). */
+export interface MockSettings {
+  text: string;
+  delay_ms: number;
+  stream_partials: boolean;
+  fail: boolean;
+}
+
+/** Payload of `get_transcription_config`. */
+export interface TranscriptionConfig {
+  provider: string;
+  language: string;
+  mock: MockSettings;
+  /**
+   * Peak level (0 to 1) below which the microphone counts as silent; audio
+   * isn't streamed to the provider until it gets above it.
+   */
+  silent_input_level: number;
+  /** What to record the dictation in (`capture.dictation`). */
+  capture: CaptureQuality;
+  /** Whether recordings are kept, and so worth staging after a dictation. */
+  keep_audio: boolean;
+}
@@ -0,0 +1,105 @@
+/**
+ * History window: a keyboard-driven list of past transcripts.
+ *
+ * Up/Down select an entry and Enter pastes it into the app that was in front
+ * when the window opened (the backend hides this window and hands focus
+ * back first). Escape is handled by the backend. The list reloads on every
+ * `history:changed` event, so new dictations show up while it is open.
+ *
+ * @module HistoryWindow
+ */
+
+import { useEffect, useRef, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+/** The fields of a backend `HistoryItem` this window shows. */
+interface HistoryItem {
+  id: number;
+  created_at_ms: number;
+  processed_text: string;
+  pinned: boolean;
+}
+
+export default function HistoryWindow() {
+  const [items, setItems] = useState<HistoryItem[]>([]);
+  const [selected, setSelected] = useState(0);
+  const [error, setError] = useState<string | null>(null);
+  const list = useRef<HTMLUListElement>(null);
+
+  useEffect(() => {
+    const load = () =>
+      invoke<HistoryItem[]>("list_history", { limit: 100 })
+        .then(setItems)
+        .catch((e) => setError(String(e?.message ?? e)));
+    load();
+    const unlisten = listen("history:changed", load);
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // Keep the selection on an existing entry as the list changes
+  useEffect(() => {
+    setSelected((index) => Math.min(index, Math.max(items.length - 1, 0)));
+  }, [items]);
+
+  useEffect(() => {
+    list.current?.children[selected]?.scrollIntoView({ block: "nearest" });
+  }, [selected]);
+
+  const paste = async (item: HistoryItem | undefined) => {
+    if (!item) return;
+    try {
+      setError(null);
+      await invoke("paste_history_item", { id: item.id });
+    } catch (e: any) {
+      setError(e?.message ?? String(e));
+    }
+  };
+
+  useEffect(() => {
+    const onKey = (event: KeyboardEvent) => {
+      if (event.key === "ArrowDown") {
+        event.preventDefault();
+        setSelected((index) => Math.min(index + 1, items.length - 1));
+      } else if (event.key === "ArrowUp") {
+        event.preventDefault();
+        setSelected((index) => Math.max(index - 1, 0));
+      } else if (event.key === "Enter") {
+        event.preventDefault();
+        paste(items[selected]);
+      }
+    };
+    window.addEventListener("keydown", onKey);
+    return () => window.removeEventListener("keydown", onKey);
+  }, [items, selected]);
+
+  return (
+    <div className="flex h-screen flex-col bg-white text-sm text-gray-900">
+      {error && <p className="border-b px-4 py-2 text-red-600">{error}</p>}
+      {items.length === 0 ? (
+        <p className="p-6 text-gray-500">No transcripts yet.</p>
+      ) : (
+        <ul ref={list} className="flex-1 overflow-auto">
+          {items.map((item, index) => (
+            <li
+              key={item.id}
+              className={`cursor-default px-4 py-2 ${index === selected ? "bg-blue-100" : ""}`}
+              onMouseEnter={() => setSelected(index)}
+              onDoubleClick={() => paste(item)}
+            >
+              <div className="truncate">
+                {item.pinned && "📌 "}
+                {item.processed_text}
+              </div>
+              <div className="text-xs text-gray-500">
+                {new Date(item.created_at_ms).toLocaleString()}
+              </div>
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+}
@@ -0,0 +1,15 @@
+/**
+ * Recording indicator: a red dot in a corner of every display while the
+ * microphone is recording.
+ *
+ * The backend opens, places and closes these windows, and they ignore the
+ * mouse, so this page only draws the dot.
+ *
+ * @module IndicatorWindow
+ */
+
+export default function IndicatorWindow() {
+  return (
+    <div className="h-screen w-screen rounded-full border-2 border-white/80 bg-red-600" />
+  );
+}
@@ -0,0 +1,93 @@
+/**
+ * Recent window: the last few transcripts, for pasting one again quickly.
+ *
+ * Works like the history window: Up/Down select a transcript and Enter (or
+ * a click) pastes it into the app that was in front when the window opened.
+ * Escape is handled by the backend. The list reloads on `recent:changed`.
+ *
+ * @module RecentWindow
+ */
+
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+/** A backend `RecentTranscript`. */
+interface RecentTranscript {
+  id: number;
+  text: string;
+  target_app: string | null;
+  created_at_ms: number;
+}
+
+export default function RecentWindow() {
+  const [items, setItems] = useState<RecentTranscript[]>([]);
+  const [selected, setSelected] = useState(0);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    const load = () =>
+      invoke<RecentTranscript[]>("get_recent_transcripts")
+        .then(setItems)
+        .catch((e) => setError(String(e?.message ?? e)));
+    load();
+    const unlisten = listen("recent:changed", load);
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // Keep the selection on an existing transcript as the list changes
+  useEffect(() => {
+    setSelected((index) => Math.min(index, Math.max(items.length - 1, 0)));
+  }, [items]);
+
+  const paste = async (item: RecentTranscript | undefined) => {
+    if (!item) return;
+    try {
+      setError(null);
+      await invoke("paste_recent_transcript", { id: item.id });
+    } catch (e: any) {
+      setError(e?.message ?? String(e));
+    }
+  };
+
+  useEffect(() => {
+    const onKey = (event: KeyboardEvent) => {
+      if (event.key === "ArrowDown") {
+        event.preventDefault();
+        setSelected((index) => Math.min(index + 1, items.length - 1));
+      } else if (event.key === "ArrowUp") {
+        event.preventDefault();
+        setSelected((index) => Math.max(index - 1, 0));
+      } else if (event.key === "Enter") {
+        event.preventDefault();
+        paste(items[selected]);
+      }
+    };
+    window.addEventListener("keydown", onKey);
+    return () => window.removeEventListener("keydown", onKey);
+  }, [items, selected]);
+
+  return (
+    <div className="flex h-screen flex-col bg-white text-sm text-gray-900">
+      {error && <p className="border-b px-3 py-2 text-red-600">{error}</p>}
+      {items.length === 0 ? (
+        <p className="p-4 text-gray-500">Nothing dictated yet.</p>
+      ) : (
+        <ul className="flex-1 overflow-auto">
+          {items.map((item, index) => (
+            <li
+              key={item.id}
+              className={`cursor-default truncate px-3 py-1.5 ${index === selected ? "bg-blue-100" : ""}`}
+              onMouseEnter={() => setSelected(index)}
+              onClick={() => paste(item)}
+            >
+              {item.text}
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+}
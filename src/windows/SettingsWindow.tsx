@@ -0,0 +1,265 @@
+/**
+ * Settings window, opened from the tray menu.
+ *
+ * Reads the settings from the backend and writes each change back as a
+ * partial patch through `update_settings`. External changes (the settings
+ * file edited by hand, the tray's Launch at Login item) arrive as
+ * `settings:changed` events, so the window always shows the saved state.
+ *
+ * @module SettingsWindow
+ */
+
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { downloadDir, join } from "@tauri-apps/api/path";
+import { listen } from "@tauri-apps/api/event";
+import { revealItemInDir } from "@tauri-apps/plugin-opener";
+
+/** The subset of the backend `Settings` this window edits. */
+interface Settings {
+  startup: { launch_at_login: boolean; start_hidden: boolean };
+  dock: { visible: boolean };
+  paste_delay_ms: number;
+  notifications: { enabled: boolean };
+}
+
+/** One subsystem of the backend `HealthReport`. */
+interface HealthItem {
+  subsystem: string;
+  status: "ok" | "warn" | "error";
+  code: string;
+  detail: string;
+}
+
+/** What the webview knows about the microphone, for `health_check`. */
+async function microphoneReport() {
+  const devices = await navigator.mediaDevices.enumerateDevices();
+  const input = devices.find((device) => device.kind === "audioinput");
+  let permission: string | null = null;
+  try {
+    const status = await navigator.permissions.query({ name: "microphone" as PermissionName });
+    permission = status.state;
+  } catch {
+    // Not every webview can query the microphone permission
+  }
+  // Labels are empty until access is granted
+  return { device: input ? input.label || "Default microphone" : null, permission };
+}
+
+export default function SettingsWindow() {
+  const [settings, setSettings] = useState<Settings | null>(null);
+  const [error, setError] = useState<string | null>(null);
+  // Where the backend writes its logs; null when it logs to stdout only
+  const [logDirectory, setLogDirectory] = useState<string | null>(null);
+  // The crash of the previous run, offered for the diagnostics export
+  const [crash, setCrash] = useState<{ message: string } | null>(null);
+  // Problems found loading the settings file at startup
+  const [warnings, setWarnings] = useState<string[]>([]);
+  // The last diagnostics bundle exported
+  const [bundle, setBundle] = useState<{ path: string; size_bytes: number } | null>(null);
+  const [health, setHealth] = useState<HealthItem[] | null>(null);
+
+  const checkHealth = async () => {
+    const report = await invoke<{ items: HealthItem[] }>("health_check", {
+      microphone: await microphoneReport().catch(() => null),
+    });
+    setHealth(report.items);
+  };
+
+  useEffect(() => {
+    invoke<Settings>("get_settings").then(setSettings).catch((e) => setError(String(e)));
+    invoke<string>("get_log_directory").then(setLogDirectory).catch(() => setLogDirectory(null));
+    invoke<{ message: string } | null>("get_last_crash").then(setCrash).catch(console.warn);
+    invoke<{ warnings: string[] }>("get_settings_meta")
+      .then((meta) => setWarnings(meta.warnings))
+      .catch(console.warn);
+    checkHealth().catch(console.warn);
+    const unlisten = listen<{ settings: Settings }>("settings:changed", (event) => {
+      setSettings(event.payload.settings);
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  /** Runs a backend change, showing its error if it fails. */
+  const save = async (change: () => Promise<unknown>) => {
+    try {
+      setError(null);
+      await change();
+    } catch (e: any) {
+      setError(typeof e === "string" ? e : e?.message ?? JSON.stringify(e));
+    }
+  };
+
+  if (!settings) {
+    return <div className="p-6 text-sm text-gray-500">Loading settings…</div>;
+  }
+
+  return (
+    <div className="h-screen overflow-auto bg-white p-6 text-sm text-gray-900">
+      <h1 className="mb-4 text-lg font-semibold">Settings</h1>
+
+      {crash && (
+        <div className="mb-4 rounded border border-amber-300 bg-amber-50 p-3">
+          <p>Wispr Clone quit unexpectedly last time ({crash.message}).</p>
+          <p>Exporting diagnostics below helps us find out why.</p>
+          <button
+            className="mt-2 rounded border px-2"
+            onClick={() =>
+              save(async () => {
+                await invoke("dismiss_crash");
+                setCrash(null);
+              })
+            }
+          >
+            Dismiss
+          </button>
+        </div>
+      )}
+
+      {warnings.length > 0 && (
+        <div className="mb-4 rounded border border-amber-300 bg-amber-50 p-3">
+          <p>Some settings couldn't be loaded:</p>
+          <ul className="list-disc pl-5">
+            {warnings.map((warning) => (
+              <li key={warning}>{warning}</li>
+            ))}
+          </ul>
+        </div>
+      )}
+
+      <section className="space-y-3">
+        <label className="flex items-center gap-2">
+          <input
+            type="checkbox"
+            checked={settings.startup.launch_at_login}
+            onChange={(e) =>
+              save(() =>
+                invoke("set_autostart", {
+                  enabled: e.target.checked,
+                  startHidden: settings.startup.start_hidden,
+                }),
+              )
+            }
+          />
+          Launch at login
+        </label>
+        <label className="flex items-center gap-2">
+          <input
+            type="checkbox"
+            checked={settings.startup.start_hidden}
+            onChange={(e) =>
+              save(() =>
+                invoke("update_settings", { patch: { startup: { start_hidden: e.target.checked } } }),
+              )
+            }
+          />
+          Start in the background
+        </label>
+        <label className="flex items-center gap-2">
+          <input
+            type="checkbox"
+            checked={settings.dock.visible}
+            onChange={(e) =>
+              save(() => invoke("set_dock_icon_visible", { visible: e.target.checked }))
+            }
+          />
+          Show Dock icon (macOS)
+        </label>
+        <label className="flex items-center gap-2">
+          <input
+            type="checkbox"
+            checked={settings.notifications.enabled}
+            onChange={(e) =>
+              save(() =>
+                invoke("update_settings", {
+                  patch: { notifications: { enabled: e.target.checked } },
+                }),
+              )
+            }
+          />
+          Notify when a paste or transcription fails in the background
+        </label>
+        <label className="flex items-center gap-2">
+          Paste delay
+          <input
+            type="number"
+            min={0}
+            className="w-20 rounded border px-1"
+            value={settings.paste_delay_ms}
+            onChange={(e) =>
+              save(() =>
+                invoke("update_settings", { patch: { paste_delay_ms: Number(e.target.value) } }),
+              )
+            }
+          />
+          ms
+        </label>
+        <p className="flex items-center gap-2">
+          Diagnostics
+          <button
+            className="rounded border px-2"
+            onClick={() =>
+              save(async () => {
+                const path = await join(await downloadDir(), "wispr-clone-diagnostics.zip");
+                setBundle(await invoke("export_diagnostics", { path }));
+              })
+            }
+          >
+            Export for support
+          </button>
+          {bundle && (
+            <button
+              className="text-blue-600 underline"
+              onClick={() => save(() => revealItemInDir(bundle.path))}
+            >
+              Show ({Math.ceil(bundle.size_bytes / 1024)} KB)
+            </button>
+          )}
+        </p>
+        {logDirectory && (
+          <p className="flex items-center gap-2">
+            Logs
+            <button
+              className="rounded border px-2"
+              onClick={() => save(() => revealItemInDir(logDirectory))}
+            >
+              Show in folder
+            </button>
+          </p>
+        )}
+      </section>
+
+      {health && (
+        <section className="mt-6 space-y-1">
+          <h2 className="flex items-center gap-2 font-semibold">
+            Health
+            <button className="rounded border px-2 font-normal" onClick={() => save(checkHealth)}>
+              Check again
+            </button>
+          </h2>
+          {health.map((item) => (
+            <p key={item.subsystem} className="flex gap-2">
+              <span
+                className={
+                  item.status === "ok"
+                    ? "text-green-600"
+                    : item.status === "warn"
+                      ? "text-amber-600"
+                      : "text-red-600"
+                }
+              >
+                {item.status === "ok" ? "✓" : "!"}
+              </span>
+              <span className="w-24 capitalize">{item.subsystem}</span>
+              <span className="text-gray-600">{item.detail}</span>
+            </p>
+          ))}
+        </section>
+      )}
+
+      {error && <p className="mt-4 text-red-600">{error}</p>}
+    </div>
+  );
+}